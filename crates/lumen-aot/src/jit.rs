@@ -0,0 +1,863 @@
+//! Ejecución por niveles: especializa funciones calientes según los tipos de
+//! sus argumentos y las compila a código nativo con Cranelift.
+//!
+//! El código generado usa la ABI `extern "C" fn(*const i64, *mut JitStatus) -> i64`:
+//! los argumentos llegan empaquetados en 64 bits (decimales como bits de `f64`,
+//! booleanos como 0/1) y los errores de ejecución se informan en `JitStatus`
+//! para que la VM los convierta en su `VmError` equivalente.
+
+use crate::JitEngine;
+use cranelift::codegen::ir::{BlockArg, FuncRef};
+use cranelift::prelude::*;
+use cranelift_module::{FuncId, Linkage, Module};
+use lumen_ir::ir::{Func as LumenFunc, Instr, Op};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+/// Tipo de un valor dentro del código JIT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JitType {
+    Int,
+    Float,
+    Bool,
+}
+
+impl JitType {
+    fn cl(self) -> Type {
+        match self {
+            JitType::Float => types::F64,
+            JitType::Int | JitType::Bool => types::I64,
+        }
+    }
+}
+
+pub const JIT_OK: i64 = 0;
+pub const JIT_DIV_ZERO: i64 = 1;
+pub const JIT_SHIFT_RANGE: i64 = 2;
+pub const JIT_STACK_OVERFLOW: i64 = 3;
+
+/// Estado compartido con el código nativo: código de error, dato asociado
+/// (p. ej. el desplazamiento fuera de rango) y profundidad de llamadas.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JitStatus {
+    pub code: i64,
+    pub payload: i64,
+    pub depth: i64,
+}
+
+const STATUS_CODE: i32 = 0;
+const STATUS_PAYLOAD: i32 = 8;
+const STATUS_DEPTH: i32 = 16;
+
+/// Función especializada y finalizada, lista para invocarse desde la VM.
+#[derive(Debug, Clone)]
+pub struct JitFunction {
+    ptr: *const u8,
+    arity: usize,
+    pub ret: JitType,
+}
+
+// SAFETY: `ptr` apunta a código inmutable del `JITModule`, que vive mientras
+// viva el `JitEngine` que lo generó.
+unsafe impl Send for JitFunction {}
+
+impl JitFunction {
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Ejecuta la función. `args` lleva un valor codificado por parámetro.
+    pub fn call(&self, args: &[i64], status: &mut JitStatus) -> i64 {
+        assert_eq!(args.len(), self.arity, "aridad incorrecta en llamada JIT");
+        // SAFETY: `ptr` se obtuvo de `finalize_definitions` para una función
+        // declarada con exactamente esta firma.
+        let f = unsafe {
+            std::mem::transmute::<*const u8, extern "C" fn(*const i64, *mut JitStatus) -> i64>(
+                self.ptr,
+            )
+        };
+        f(args.as_ptr(), status)
+    }
+}
+
+/// Motivo por el que una especialización no se compiló.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JitError {
+    /// El cuerpo usa algo que el JIT no compila; no se vuelve a intentar.
+    Unsupported(String),
+    /// `lookup` aún no entrega una función llamada; puede reintentarse.
+    MissingCallee(String),
+}
+
+impl std::fmt::Display for JitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JitError::Unsupported(msg) => write!(f, "{}", msg),
+            JitError::MissingCallee(name) => write!(f, "'{}' aún no está disponible", name),
+        }
+    }
+}
+
+pub(crate) type SpecKey = (String, Vec<JitType>);
+
+/// Especialización de una función para unos tipos de argumento concretos.
+pub(crate) struct Spec {
+    id: FuncId,
+    func: LumenFunc,
+    params: Vec<JitType>,
+    ret: Option<JitType>,
+    /// Tipo de retorno supuesto por una llamada recursiva durante el análisis.
+    assumed_ret: Option<JitType>,
+    analysis: Option<Analysis>,
+    ready: bool,
+}
+
+struct Analysis {
+    vars: HashMap<String, JitType>,
+    /// Inicio de bloque (índice de instrucción) → tipos de la pila de operandos.
+    blocks: HashMap<usize, Vec<JitType>>,
+    calls: HashMap<usize, SpecKey>,
+}
+
+#[derive(Clone)]
+struct FlowState {
+    defined: BTreeSet<String>,
+    stack: Vec<JitType>,
+}
+
+fn binary_type(op: &Op, a: JitType, b: JitType) -> Result<JitType, String> {
+    use JitType::*;
+    let numeric = |a, b| match (a, b) {
+        (Int, Int) => Ok(Int),
+        (Int | Float, Int | Float) => Ok(Float),
+        _ => Err(format!("{:?} requiere números", op)),
+    };
+    match op {
+        Op::Add | Op::Sub | Op::Mul | Op::Div => numeric(a, b),
+        Op::Mod | Op::BitOr | Op::BitAnd | Op::BitXor | Op::ShiftLeft | Op::ShiftRight => {
+            match (a, b) {
+                (Int, Int) => Ok(Int),
+                _ => Err(format!("{:?} solo se compila con enteros", op)),
+            }
+        }
+        Op::Equal | Op::NotEqual | Op::And | Op::Or => Ok(Bool),
+        Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual => numeric(a, b).map(|_| Bool),
+        _ => Err(format!("operador {:?} no soportado por el JIT", op)),
+    }
+}
+
+fn unary_type(op: &Op, a: JitType) -> Result<JitType, String> {
+    match (op, a) {
+        (Op::Negate, JitType::Int | JitType::Float) => Ok(a),
+        (Op::Not, _) => Ok(JitType::Bool),
+        (Op::BitNot, JitType::Int) => Ok(JitType::Int),
+        _ => Err(format!(
+            "operador unario {:?} no soportado para {:?}",
+            op, a
+        )),
+    }
+}
+
+fn pop(st: &mut FlowState) -> Result<JitType, String> {
+    st.stack
+        .pop()
+        .ok_or_else(|| "pila de operandos vacía".to_string())
+}
+
+fn merge_state(
+    entries: &mut HashMap<usize, FlowState>,
+    work: &mut VecDeque<usize>,
+    at: usize,
+    state: FlowState,
+) -> Result<(), String> {
+    match entries.get_mut(&at) {
+        Some(prev) => {
+            if prev.stack != state.stack {
+                return Err("pila de operandos desbalanceada entre ramas".to_string());
+            }
+            let defined: BTreeSet<String> =
+                prev.defined.intersection(&state.defined).cloned().collect();
+            if defined != prev.defined {
+                prev.defined = defined;
+                work.push_back(at);
+            }
+        }
+        None => {
+            entries.insert(at, state);
+            work.push_back(at);
+        }
+    }
+    Ok(())
+}
+
+impl JitEngine {
+    /// Compila (o recupera de la caché) la especialización de `name` para los
+    /// tipos de argumento dados. `lookup` entrega el IR de cualquier función
+    /// de usuario que el cuerpo llame. Devuelve error si algo del cuerpo no es
+    /// compilable; la VM entonces sigue interpretando.
+    pub fn compile_specialized(
+        &mut self,
+        name: &str,
+        arg_types: &[JitType],
+        lookup: &mut dyn FnMut(&str) -> Option<LumenFunc>,
+    ) -> Result<JitFunction, JitError> {
+        let key: SpecKey = (name.to_string(), arg_types.to_vec());
+        if self.failed.contains(&key) {
+            return Err(JitError::Unsupported(format!(
+                "'{}' ya fue rechazada por el JIT",
+                name
+            )));
+        }
+        if !self.specs.get(&key).is_some_and(|s| s.ready) {
+            let before: Vec<SpecKey> = self.specs.keys().cloned().collect();
+            self.missing_callee = None;
+            if let Err(e) = self
+                .analyze(&key, lookup)
+                .and_then(|_| self.define_pending())
+            {
+                self.specs.retain(|k, _| before.contains(k));
+                if let Some(callee) = self.missing_callee.take() {
+                    return Err(JitError::MissingCallee(callee));
+                }
+                self.failed.insert(key);
+                return Err(JitError::Unsupported(e));
+            }
+        }
+        let spec = &self.specs[&key];
+        Ok(JitFunction {
+            ptr: self.module.get_finalized_function(spec.id),
+            arity: spec.params.len(),
+            ret: spec.ret.unwrap_or(JitType::Int),
+        })
+    }
+
+    /// Analiza tipos de una especialización y, recursivamente, de sus llamadas.
+    fn analyze(
+        &mut self,
+        key: &SpecKey,
+        lookup: &mut dyn FnMut(&str) -> Option<LumenFunc>,
+    ) -> Result<JitType, String> {
+        if self.failed.contains(key) {
+            return Err(format!("'{}' ya fue rechazada por el JIT", key.0));
+        }
+        if let Some(spec) = self.specs.get_mut(key) {
+            if let Some(ret) = spec.ret {
+                return Ok(ret);
+            }
+            // Llamada recursiva en pleno análisis: se supone un tipo y se
+            // verifica al terminar.
+            return Ok(*spec.assumed_ret.get_or_insert(JitType::Int));
+        }
+        let Some(func) = lookup(&key.0) else {
+            self.missing_callee = Some(key.0.clone());
+            return Err(format!("'{}' no es una función JIT", key.0));
+        };
+        if func.params.len() != key.1.len() {
+            return Err(format!("aridad distinta al llamar a '{}'", key.0));
+        }
+
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(types::I64));
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I64));
+        self.next_symbol += 1;
+        let symbol = format!("__jit_{}_{}", key.0, self.next_symbol);
+        let id = self
+            .module
+            .declare_function(&symbol, Linkage::Local, &sig)
+            .map_err(|e| e.to_string())?;
+        self.specs.insert(
+            key.clone(),
+            Spec {
+                id,
+                func: func.clone(),
+                params: key.1.clone(),
+                ret: None,
+                assumed_ret: None,
+                analysis: None,
+                ready: false,
+            },
+        );
+
+        // Si una llamada recursiva supuso mal el tipo de retorno, se repite el
+        // análisis con otro candidato y se descarta lo analizado mientras tanto.
+        let mut tried = vec![JitType::Int];
+        loop {
+            let before: Vec<SpecKey> = self.specs.keys().cloned().collect();
+            let result = self.infer(&func, &key.1, lookup);
+            let spec = self.specs.get_mut(key).expect("especialización registrada");
+            let retry = match (&result, spec.assumed_ret) {
+                (Ok((_, ret)), Some(assumed)) if assumed != *ret => Some(*ret),
+                (Err(_), Some(_)) => [JitType::Bool, JitType::Float]
+                    .into_iter()
+                    .find(|t| !tried.contains(t)),
+                _ => None,
+            };
+            match retry {
+                Some(next) if !tried.contains(&next) => {
+                    tried.push(next);
+                    spec.assumed_ret = Some(next);
+                    self.specs.retain(|k, _| before.contains(k));
+                }
+                Some(_) => return Err(format!("tipo de retorno inestable en '{}'", key.0)),
+                None => {
+                    let (analysis, ret) = result?;
+                    spec.ret = Some(ret);
+                    spec.analysis = Some(analysis);
+                    return Ok(ret);
+                }
+            }
+        }
+    }
+
+    fn infer(
+        &mut self,
+        func: &LumenFunc,
+        params: &[JitType],
+        lookup: &mut dyn FnMut(&str) -> Option<LumenFunc>,
+    ) -> Result<(Analysis, JitType), String> {
+        let labels: HashMap<usize, usize> = func
+            .instrs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, ins)| match ins {
+                Instr::Label(l) => Some((*l, i)),
+                _ => None,
+            })
+            .collect();
+        let target = |l: &usize| {
+            labels
+                .get(l)
+                .copied()
+                .ok_or_else(|| format!("etiqueta {} inexistente", l))
+        };
+        let mut vars: HashMap<String, JitType> = func
+            .params
+            .iter()
+            .cloned()
+            .zip(params.iter().copied())
+            .collect();
+        let mut entries: HashMap<usize, FlowState> = HashMap::new();
+        let mut work = VecDeque::new();
+        let mut calls = HashMap::new();
+        let mut ret: Option<JitType> = None;
+        let start = FlowState {
+            defined: func.params.iter().cloned().collect(),
+            stack: Vec::new(),
+        };
+        merge_state(&mut entries, &mut work, 0, start)?;
+
+        while let Some(block) = work.pop_front() {
+            let mut st = entries[&block].clone();
+            let mut i = block;
+            loop {
+                let ins = func
+                    .instrs
+                    .get(i)
+                    .ok_or_else(|| "la función termina sin retornar".to_string())?;
+                match ins {
+                    Instr::Label(_) if i != block => {
+                        merge_state(&mut entries, &mut work, i, st)?;
+                        break;
+                    }
+                    Instr::Label(_)
+                    | Instr::Nop
+                    | Instr::Loc(..)
+                    | Instr::Bind(_)
+                    | Instr::ScopeBegin
+                    | Instr::ScopeEnd => {}
+                    Instr::ConstInt(_) => st.stack.push(JitType::Int),
+                    Instr::ConstFloat(_) => st.stack.push(JitType::Float),
+                    Instr::ConstBool(_) => st.stack.push(JitType::Bool),
+                    Instr::Load(n) => {
+                        if !st.defined.contains(n) {
+                            return Err(format!("'{}' no está definida localmente", n));
+                        }
+                        st.stack.push(vars[n]);
+                    }
+                    Instr::Store(n) => {
+                        let t = pop(&mut st)?;
+                        if *vars.entry(n.clone()).or_insert(t) != t {
+                            return Err(format!("'{}' cambia de tipo", n));
+                        }
+                        st.defined.insert(n.clone());
+                    }
+                    Instr::Binary(op) => {
+                        let b = pop(&mut st)?;
+                        let a = pop(&mut st)?;
+                        st.stack.push(binary_type(op, a, b)?);
+                    }
+                    Instr::Unary(op) => {
+                        let a = pop(&mut st)?;
+                        st.stack.push(unary_type(op, a)?);
+                    }
+                    Instr::Jmp(l) => {
+                        merge_state(&mut entries, &mut work, target(l)?, st)?;
+                        break;
+                    }
+                    Instr::JmpIf(l) => {
+                        pop(&mut st)?;
+                        merge_state(&mut entries, &mut work, target(l)?, st.clone())?;
+                    }
+                    Instr::Return => {
+                        let t = pop(&mut st)?;
+                        if ret.is_some_and(|r| r != t) {
+                            return Err("tipos de retorno distintos".to_string());
+                        }
+                        ret = Some(t);
+                        break;
+                    }
+                    Instr::Call(name, argc) => {
+                        if st.stack.len() < *argc {
+                            return Err("pila de operandos vacía".to_string());
+                        }
+                        let args = st.stack.split_off(st.stack.len() - argc);
+                        let callee = (name.clone(), args);
+                        let r = self.analyze(&callee, lookup)?;
+                        calls.insert(i, callee);
+                        st.stack.push(r);
+                    }
+                    other => return Err(format!("instrucción no soportada: {:?}", other)),
+                }
+                i += 1;
+            }
+        }
+
+        let ret = ret.ok_or_else(|| "la función nunca retorna".to_string())?;
+        let blocks = entries.into_iter().map(|(i, s)| (i, s.stack)).collect();
+        Ok((
+            Analysis {
+                vars,
+                blocks,
+                calls,
+            },
+            ret,
+        ))
+    }
+
+    fn define_pending(&mut self) -> Result<(), String> {
+        let pending: Vec<SpecKey> = self
+            .specs
+            .iter()
+            .filter(|(_, s)| !s.ready)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in &pending {
+            self.define(key)?;
+        }
+        self.module
+            .finalize_definitions()
+            .map_err(|e| e.to_string())?;
+        for key in &pending {
+            if let Some(spec) = self.specs.get_mut(key) {
+                spec.ready = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, key: &SpecKey) -> Result<(), String> {
+        let JitEngine {
+            builder_context,
+            ctx,
+            module,
+            specs,
+            max_call_depth,
+            ..
+        } = self;
+        let spec = &specs[key];
+        let analysis = spec
+            .analysis
+            .as_ref()
+            .ok_or_else(|| format!("'{}' sin analizar", key.0))?;
+        let func = &spec.func;
+
+        let mut sig = module.make_signature();
+        sig.params.push(AbiParam::new(types::I64));
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I64));
+        ctx.func.signature = sig;
+        ctx.func.name = cranelift::codegen::ir::UserFuncName::user(0, spec.id.as_u32());
+
+        {
+            let mut b = FunctionBuilder::new(&mut ctx.func, builder_context);
+            let entry = b.create_block();
+            b.append_block_params_for_function_params(entry);
+            b.switch_to_block(entry);
+            let args_ptr = b.block_params(entry)[0];
+            let status = b.block_params(entry)[1];
+            let mem = MemFlags::trusted();
+
+            let mut vars = HashMap::new();
+            for (name, ty) in &analysis.vars {
+                let var = b.declare_var(ty.cl());
+                let zero = match ty {
+                    JitType::Float => b.ins().f64const(0.0),
+                    _ => b.ins().iconst(types::I64, 0),
+                };
+                b.def_var(var, zero);
+                vars.insert(name.clone(), var);
+            }
+            for (i, (name, ty)) in func.params.iter().zip(&spec.params).enumerate() {
+                let raw = b.ins().load(types::I64, mem, args_ptr, (i * 8) as i32);
+                let v = match ty {
+                    JitType::Float => b.ins().bitcast(types::F64, MemFlags::new(), raw),
+                    _ => raw,
+                };
+                b.def_var(vars[name], v);
+            }
+
+            let depth = b.ins().load(types::I64, mem, status, STATUS_DEPTH);
+            let over = b
+                .ins()
+                .icmp_imm(IntCC::SignedGreaterThanOrEqual, depth, *max_call_depth);
+            raise_if(&mut b, status, over, JIT_STACK_OVERFLOW, depth);
+            let next = b.ins().iadd_imm(depth, 1);
+            b.ins().store(mem, next, status, STATUS_DEPTH);
+
+            let mut blocks = HashMap::new();
+            for (&at, tys) in &analysis.blocks {
+                let block = b.create_block();
+                for ty in tys {
+                    b.append_block_param(block, ty.cl());
+                }
+                blocks.insert(at, block);
+            }
+            b.ins().jump(blocks[&0], &[]);
+
+            let mut funcs: HashMap<FuncId, FuncRef> = HashMap::new();
+            let mut stack: Vec<(Value, JitType)> = Vec::new();
+            let mut live = false;
+            for (i, ins) in func.instrs.iter().enumerate() {
+                if let Some(&block) = blocks.get(&i) {
+                    if live {
+                        jump_with(&mut b, block, &stack);
+                    }
+                    b.switch_to_block(block);
+                    let params = b.block_params(block).to_vec();
+                    stack = params
+                        .into_iter()
+                        .zip(analysis.blocks[&i].iter().copied())
+                        .collect();
+                    live = true;
+                }
+                if !live {
+                    continue;
+                }
+                match ins {
+                    Instr::Label(_)
+                    | Instr::Nop
+                    | Instr::Loc(..)
+                    | Instr::Bind(_)
+                    | Instr::ScopeBegin
+                    | Instr::ScopeEnd => {}
+                    Instr::ConstInt(n) => {
+                        stack.push((b.ins().iconst(types::I64, *n), JitType::Int))
+                    }
+                    Instr::ConstFloat(f) => stack.push((b.ins().f64const(*f), JitType::Float)),
+                    Instr::ConstBool(v) => {
+                        stack.push((b.ins().iconst(types::I64, *v as i64), JitType::Bool))
+                    }
+                    Instr::Load(n) => stack.push((b.use_var(vars[n]), analysis.vars[n])),
+                    Instr::Store(n) => {
+                        let (v, _) = stack.pop().expect("pila verificada");
+                        b.def_var(vars[n], v);
+                    }
+                    Instr::Binary(op) => {
+                        let rhs = stack.pop().expect("pila verificada");
+                        let lhs = stack.pop().expect("pila verificada");
+                        stack.push(emit_binary(&mut b, status, op, lhs, rhs));
+                    }
+                    Instr::Unary(op) => {
+                        let (v, ty) = stack.pop().expect("pila verificada");
+                        let r = match (op, ty) {
+                            (Op::Negate, JitType::Float) => b.ins().fneg(v),
+                            (Op::Negate, _) => b.ins().ineg(v),
+                            (Op::BitNot, _) => b.ins().bnot(v),
+                            _ => {
+                                let t = truthy(&mut b, v, ty);
+                                let f = b.ins().icmp_imm(IntCC::Equal, t, 0);
+                                b.ins().uextend(types::I64, f)
+                            }
+                        };
+                        stack.push((r, unary_type(op, ty).expect("tipo verificado")));
+                    }
+                    Instr::Jmp(l) => {
+                        let at = label_index(func, *l);
+                        jump_with(&mut b, blocks[&at], &stack);
+                        live = false;
+                    }
+                    Instr::JmpIf(l) => {
+                        let (v, ty) = stack.pop().expect("pila verificada");
+                        let cond = truthy(&mut b, v, ty);
+                        let at = label_index(func, *l);
+                        let cont = b.create_block();
+                        let args: Vec<BlockArg> =
+                            stack.iter().map(|(v, _)| BlockArg::Value(*v)).collect();
+                        b.ins().brif(cond, cont, &[], blocks[&at], &args);
+                        b.switch_to_block(cont);
+                    }
+                    Instr::Return => {
+                        let (v, ty) = stack.pop().expect("pila verificada");
+                        let raw = to_raw(&mut b, v, ty);
+                        b.ins().store(mem, depth, status, STATUS_DEPTH);
+                        b.ins().return_(&[raw]);
+                        live = false;
+                    }
+                    Instr::Call(_, argc) => {
+                        let callee = &specs[&analysis.calls[&i]];
+                        let fref = *funcs
+                            .entry(callee.id)
+                            .or_insert_with(|| module.declare_func_in_func(callee.id, b.func));
+                        let args = stack.split_off(stack.len() - argc);
+                        let addr = if args.is_empty() {
+                            b.ins().iconst(types::I64, 0)
+                        } else {
+                            let slot = b.create_sized_stack_slot(StackSlotData::new(
+                                StackSlotKind::ExplicitSlot,
+                                (args.len() * 8) as u32,
+                                3,
+                            ));
+                            for (k, (v, ty)) in args.iter().enumerate() {
+                                let raw = to_raw(&mut b, *v, *ty);
+                                b.ins().stack_store(raw, slot, (k * 8) as i32);
+                            }
+                            b.ins().stack_addr(types::I64, slot, 0)
+                        };
+                        let call = b.ins().call(fref, &[addr, status]);
+                        let raw = b.inst_results(call)[0];
+                        // Un error en la llamada se propaga tal cual hasta la VM.
+                        let code = b.ins().load(types::I64, mem, status, STATUS_CODE);
+                        let failed = b.ins().icmp_imm(IntCC::NotEqual, code, JIT_OK);
+                        let bail = b.create_block();
+                        let cont = b.create_block();
+                        b.ins().brif(failed, bail, &[], cont, &[]);
+                        b.switch_to_block(bail);
+                        let zero = b.ins().iconst(types::I64, 0);
+                        b.ins().return_(&[zero]);
+                        b.switch_to_block(cont);
+                        let ret = callee.ret.expect("especialización analizada");
+                        let v = match ret {
+                            JitType::Float => b.ins().bitcast(types::F64, MemFlags::new(), raw),
+                            _ => raw,
+                        };
+                        stack.push((v, ret));
+                    }
+                    other => unreachable!("instrucción no verificada: {:?}", other),
+                }
+            }
+            if live {
+                let zero = b.ins().iconst(types::I64, 0);
+                b.ins().return_(&[zero]);
+            }
+            b.seal_all_blocks();
+            b.finalize();
+        }
+
+        let id = spec.id;
+        let res = module
+            .define_function(id, ctx)
+            .map_err(|e| format!("{:?}", e));
+        module.clear_context(ctx);
+        res
+    }
+}
+
+fn label_index(func: &LumenFunc, label: usize) -> usize {
+    func.instrs
+        .iter()
+        .position(|i| *i == Instr::Label(label))
+        .expect("etiqueta verificada")
+}
+
+fn jump_with(b: &mut FunctionBuilder, block: Block, stack: &[(Value, JitType)]) {
+    let args: Vec<BlockArg> = stack.iter().map(|(v, _)| BlockArg::Value(*v)).collect();
+    b.ins().jump(block, &args);
+}
+
+fn to_raw(b: &mut FunctionBuilder, v: Value, ty: JitType) -> Value {
+    match ty {
+        JitType::Float => b.ins().bitcast(types::I64, MemFlags::new(), v),
+        _ => v,
+    }
+}
+
+fn to_float(b: &mut FunctionBuilder, v: Value, ty: JitType) -> Value {
+    match ty {
+        JitType::Float => v,
+        _ => b.ins().fcvt_from_sint(types::F64, v),
+    }
+}
+
+/// Valor de verdad como entero de 8 bits (mismas reglas que `is_truthy`).
+fn truthy(b: &mut FunctionBuilder, v: Value, ty: JitType) -> Value {
+    match ty {
+        JitType::Float => {
+            let zero = b.ins().f64const(0.0);
+            b.ins().fcmp(FloatCC::NotEqual, v, zero)
+        }
+        _ => b.ins().icmp_imm(IntCC::NotEqual, v, 0),
+    }
+}
+
+/// Si `cond` se cumple, registra el error en `JitStatus` y retorna.
+fn raise_if(b: &mut FunctionBuilder, status: Value, cond: Value, code: i64, payload: Value) {
+    let fail = b.create_block();
+    let cont = b.create_block();
+    b.ins().brif(cond, fail, &[], cont, &[]);
+    b.switch_to_block(fail);
+    let mem = MemFlags::trusted();
+    let code = b.ins().iconst(types::I64, code);
+    b.ins().store(mem, code, status, STATUS_CODE);
+    b.ins().store(mem, payload, status, STATUS_PAYLOAD);
+    let zero = b.ins().iconst(types::I64, 0);
+    b.ins().return_(&[zero]);
+    b.switch_to_block(cont);
+}
+
+fn emit_binary(
+    b: &mut FunctionBuilder,
+    status: Value,
+    op: &Op,
+    (x, xt): (Value, JitType),
+    (y, yt): (Value, JitType),
+) -> (Value, JitType) {
+    let ty = binary_type(op, xt, yt).expect("tipo verificado");
+    let bool_of =
+        |b: &mut FunctionBuilder, c: Value| (b.ins().uextend(types::I64, c), JitType::Bool);
+    let ints = xt == JitType::Int && yt == JitType::Int;
+    let nums = xt != JitType::Bool && yt != JitType::Bool;
+    match op {
+        Op::Add | Op::Sub | Op::Mul if ints => {
+            let r = match op {
+                Op::Add => b.ins().iadd(x, y),
+                Op::Sub => b.ins().isub(x, y),
+                _ => b.ins().imul(x, y),
+            };
+            (r, ty)
+        }
+        Op::Div | Op::Mod if ints => {
+            let zero = b.ins().icmp_imm(IntCC::Equal, y, 0);
+            raise_if(b, status, zero, JIT_DIV_ZERO, y);
+            // `x / -1` se resuelve aparte: `sdiv` atrapa i64::MIN / -1.
+            let minus_one = b.ins().icmp_imm(IntCC::Equal, y, -1);
+            let special = b.create_block();
+            let general = b.create_block();
+            let done = b.create_block();
+            b.append_block_param(done, types::I64);
+            b.ins().brif(minus_one, special, &[], general, &[]);
+            b.switch_to_block(special);
+            let r = match op {
+                Op::Div => b.ins().ineg(x),
+                _ => b.ins().iconst(types::I64, 0),
+            };
+            b.ins().jump(done, &[BlockArg::Value(r)]);
+            b.switch_to_block(general);
+            let r = match op {
+                Op::Div => b.ins().sdiv(x, y),
+                _ => {
+                    // rem_euclid: el resto truncado más |y| si quedó negativo.
+                    let r = b.ins().srem(x, y);
+                    let abs = b.ins().iabs(y);
+                    let neg = b.ins().icmp_imm(IntCC::SignedLessThan, r, 0);
+                    let adj = b.ins().iadd(r, abs);
+                    b.ins().select(neg, adj, r)
+                }
+            };
+            b.ins().jump(done, &[BlockArg::Value(r)]);
+            b.switch_to_block(done);
+            (b.block_params(done)[0], ty)
+        }
+        Op::Add | Op::Sub | Op::Mul | Op::Div => {
+            let x = to_float(b, x, xt);
+            let y = to_float(b, y, yt);
+            let r = match op {
+                Op::Add => b.ins().fadd(x, y),
+                Op::Sub => b.ins().fsub(x, y),
+                Op::Mul => b.ins().fmul(x, y),
+                _ => {
+                    let zero = b.ins().f64const(0.0);
+                    let is_zero = b.ins().fcmp(FloatCC::Equal, y, zero);
+                    let payload = b.ins().iconst(types::I64, 0);
+                    raise_if(b, status, is_zero, JIT_DIV_ZERO, payload);
+                    b.ins().fdiv(x, y)
+                }
+            };
+            (r, ty)
+        }
+        Op::Equal | Op::NotEqual if ints || (xt == JitType::Bool && yt == JitType::Bool) => {
+            let cc = if *op == Op::Equal {
+                IntCC::Equal
+            } else {
+                IntCC::NotEqual
+            };
+            let c = b.ins().icmp(cc, x, y);
+            bool_of(b, c)
+        }
+        Op::Equal | Op::NotEqual if nums => {
+            let x = to_float(b, x, xt);
+            let y = to_float(b, y, yt);
+            let diff = b.ins().fsub(x, y);
+            let diff = b.ins().fabs(diff);
+            let eps = b.ins().f64const(f64::EPSILON);
+            let cc = if *op == Op::Equal {
+                FloatCC::LessThan
+            } else {
+                FloatCC::GreaterThanOrEqual
+            };
+            let c = b.ins().fcmp(cc, diff, eps);
+            bool_of(b, c)
+        }
+        Op::Equal | Op::NotEqual => {
+            // Booleano frente a número: nunca son iguales.
+            let v = b.ins().iconst(types::I64, (*op == Op::NotEqual) as i64);
+            (v, JitType::Bool)
+        }
+        Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual => {
+            let c = if ints {
+                let cc = match op {
+                    Op::Less => IntCC::SignedLessThan,
+                    Op::LessEqual => IntCC::SignedLessThanOrEqual,
+                    Op::Greater => IntCC::SignedGreaterThan,
+                    _ => IntCC::SignedGreaterThanOrEqual,
+                };
+                b.ins().icmp(cc, x, y)
+            } else {
+                let x = to_float(b, x, xt);
+                let y = to_float(b, y, yt);
+                let cc = match op {
+                    Op::Less => FloatCC::LessThan,
+                    Op::LessEqual => FloatCC::LessThanOrEqual,
+                    Op::Greater => FloatCC::GreaterThan,
+                    _ => FloatCC::GreaterThanOrEqual,
+                };
+                b.ins().fcmp(cc, x, y)
+            };
+            bool_of(b, c)
+        }
+        Op::And | Op::Or => {
+            let l = truthy(b, x, xt);
+            let r = truthy(b, y, yt);
+            let c = if *op == Op::And {
+                b.ins().band(l, r)
+            } else {
+                b.ins().bor(l, r)
+            };
+            bool_of(b, c)
+        }
+        Op::BitOr => (b.ins().bor(x, y), ty),
+        Op::BitAnd => (b.ins().band(x, y), ty),
+        Op::BitXor => (b.ins().bxor(x, y), ty),
+        Op::ShiftLeft | Op::ShiftRight => {
+            let bad = b.ins().icmp_imm(IntCC::UnsignedGreaterThan, y, 63);
+            raise_if(b, status, bad, JIT_SHIFT_RANGE, y);
+            let r = if *op == Op::ShiftLeft {
+                b.ins().ishl(x, y)
+            } else {
+                b.ins().sshr(x, y)
+            };
+            (r, ty)
+        }
+        _ => unreachable!("operador verificado: {:?}", op),
+    }
+}
@@ -6,6 +6,12 @@ use lumen_ir::ir::{Func as LumenFunc, Instr, Op, Program};
 use std::collections::{BTreeMap, HashMap};
 use std::sync::Mutex;
 
+mod jit;
+pub use jit::{
+    JitError, JitFunction, JitStatus, JitType, JIT_DIV_ZERO, JIT_OK, JIT_SHIFT_RANGE,
+    JIT_STACK_OVERFLOW,
+};
+
 static UNSUPPORTED_BUILTINS: Mutex<Vec<String>> = Mutex::new(Vec::new());
 
 /// Registra un builtin no soportado por el backend AOT
@@ -248,6 +254,66 @@ impl AotCompiler {
             builder.def_var(v, val);
         }
 
+        // ── posponer: pila de etiquetas pendientes (dfs) y de sitios DeferRun
+        // a los que vuelve cada bloque (drs), en slots explícitos del frame ──
+        let defer_labels: Vec<usize> = instrs
+            .iter()
+            .filter_map(|i| match i {
+                Instr::DeferPush(l) => Some(*l),
+                _ => None,
+            })
+            .collect();
+        let run_blocks: Vec<Block> = instrs
+            .iter()
+            .filter(|i| matches!(i, Instr::DeferRun(_)))
+            .map(|_| builder.create_block())
+            .collect();
+        let defer_state = if defer_labels.is_empty() {
+            None
+        } else {
+            let dfs = builder.create_sized_stack_slot(StackSlotData::new(
+                StackSlotKind::ExplicitSlot,
+                (defer_labels.len() * 8) as u32,
+                3,
+            ));
+            let drs = builder.create_sized_stack_slot(StackSlotData::new(
+                StackSlotKind::ExplicitSlot,
+                (run_blocks.len().max(1) * 8) as u32,
+                3,
+            ));
+            let dfn = builder.declare_var(i64);
+            let drn = builder.declare_var(i64);
+            builder.def_var(dfn, zero);
+            builder.def_var(drn, zero);
+            Some((dfs, drs, dfn, drn))
+        };
+        // Salta al bloque de `targets` cuyo id coincide con `key`, o a `fallback`.
+        fn dispatch(
+            builder: &mut FunctionBuilder,
+            key: Value,
+            targets: &[(i64, Block)],
+            fallback: Block,
+        ) {
+            for &(id, target) in targets {
+                let hit = builder.ins().icmp_imm(IntCC::Equal, key, id);
+                let next = builder.create_block();
+                builder.ins().brif(hit, target, &[], next, &[]);
+                builder.switch_to_block(next);
+            }
+            builder.ins().jump(fallback, &[]);
+        }
+        fn slot_addr(
+            builder: &mut FunctionBuilder,
+            slot: cranelift::codegen::ir::StackSlot,
+            index: Value,
+        ) -> Value {
+            let base = builder.ins().stack_addr(types::I64, slot, 0);
+            let off = builder.ins().imul_imm(index, 8);
+            builder.ins().iadd(base, off)
+        }
+        let mut run_site = 0usize;
+        let mut defer_saved: Vec<(Vec<Value>, Vec<bool>)> = Vec::new();
+
         // ── Emisión lineal ──
         let mut cur = entry_block;
         let mut stack: Vec<Value> = Vec::new();
@@ -270,7 +336,9 @@ impl AotCompiler {
                 terminated = false;
                 continue;
             }
-            if terminated {
+            // DeferRun abre un bloque propio (destino de DeferEnd) aunque el
+            // código que lo precede sea inalcanzable.
+            if terminated && !matches!(ins, Instr::DeferRun(_)) {
                 continue;
             }
             match ins {
@@ -575,7 +643,7 @@ impl AotCompiler {
                     stack.push(builder.ins().iconst(i64, 0));
                     kinds.push(false);
                 }
-                Instr::FuncRef(_) => {
+                Instr::FuncRef(_) | Instr::MakeClosure(..) => {
                     stack.push(builder.ins().iconst(i64, 0));
                     kinds.push(false);
                 }
@@ -583,7 +651,96 @@ impl AotCompiler {
                     stack.push(builder.ins().iconst(i64, 0));
                     kinds.push(false);
                 }
-                Instr::Phi(_, _) | Instr::Nop => {}
+                Instr::DeferPush(label) => {
+                    if let Some((dfs, _, dfn, _)) = defer_state {
+                        let n = builder.use_var(dfn);
+                        let addr = slot_addr(&mut builder, dfs, n);
+                        let l = builder.ins().iconst(i64, *label as i64);
+                        builder.ins().store(MemFlags::new(), l, addr, 0);
+                        let n1 = builder.ins().iadd_imm(n, 1);
+                        builder.def_var(dfn, n1);
+                    }
+                    // la pila de operandos sigue intacta tras el bloque pospuesto
+                    defer_saved.push((stack.clone(), kinds.clone()));
+                }
+                Instr::DeferRun(depth) => {
+                    let run_block = run_blocks[run_site];
+                    let site = run_site as i64;
+                    run_site += 1;
+                    // DeferRun está siempre entre sentencias: lo que quede en la
+                    // pila son resultados descartados, que además pueden venir de
+                    // bloques que no dominan al de despacho.
+                    stack.clear();
+                    kinds.clear();
+                    if !terminated {
+                        builder.ins().jump(run_block, &[]);
+                    }
+                    builder.switch_to_block(run_block);
+                    let next_block = builder.create_block();
+                    if let Some((dfs, drs, dfn, drn)) = defer_state {
+                        let n = builder.use_var(dfn);
+                        let more =
+                            builder
+                                .ins()
+                                .icmp_imm(IntCC::SignedGreaterThan, n, *depth as i64);
+                        let pop_block = builder.create_block();
+                        builder.ins().brif(more, pop_block, &[], next_block, &[]);
+                        builder.switch_to_block(pop_block);
+                        let n1 = builder.ins().iadd_imm(n, -1);
+                        builder.def_var(dfn, n1);
+                        let addr = slot_addr(&mut builder, dfs, n1);
+                        let label = builder.ins().load(i64, MemFlags::new(), addr, 0);
+                        let r = builder.use_var(drn);
+                        let raddr = slot_addr(&mut builder, drs, r);
+                        let site_v = builder.ins().iconst(i64, site);
+                        builder.ins().store(MemFlags::new(), site_v, raddr, 0);
+                        let r1 = builder.ins().iadd_imm(r, 1);
+                        builder.def_var(drn, r1);
+                        let targets: Vec<(i64, Block)> = defer_labels
+                            .iter()
+                            .filter_map(|l| label_block.get(l).map(|b| (*l as i64, *b)))
+                            .collect();
+                        dispatch(&mut builder, label, &targets, next_block);
+                    } else {
+                        builder.ins().jump(next_block, &[]);
+                    }
+                    builder.switch_to_block(next_block);
+                    builder.ensure_inserted_block();
+                    cur = next_block;
+                    terminated = false;
+                }
+                Instr::DeferEnd => {
+                    match (defer_state, run_blocks.split_last()) {
+                        (Some((_, drs, _, drn)), Some((&last, rest))) => {
+                            let r = builder.use_var(drn);
+                            let r1 = builder.ins().iadd_imm(r, -1);
+                            builder.def_var(drn, r1);
+                            let addr = slot_addr(&mut builder, drs, r1);
+                            let site = builder.ins().load(i64, MemFlags::new(), addr, 0);
+                            let targets: Vec<(i64, Block)> = rest
+                                .iter()
+                                .enumerate()
+                                .map(|(k, b)| (k as i64, *b))
+                                .collect();
+                            dispatch(&mut builder, site, &targets, last);
+                        }
+                        _ => {
+                            let zero = builder.ins().iconst(i64, 0);
+                            builder.ins().return_(&[zero]);
+                        }
+                    }
+                    terminated = true;
+                    if let Some((saved_stack, saved_kinds)) = defer_saved.pop() {
+                        stack = saved_stack;
+                        kinds = saved_kinds;
+                    }
+                }
+                Instr::Phi(_, _)
+                | Instr::Nop
+                | Instr::Loc(..)
+                | Instr::Bind(_)
+                | Instr::ScopeBegin
+                | Instr::ScopeEnd => {}
                 _ => {}
             }
         }
@@ -642,6 +799,12 @@ pub struct JitEngine {
     builder_context: FunctionBuilderContext,
     ctx: cranelift::codegen::Context,
     module: JITModule,
+    specs: HashMap<jit::SpecKey, jit::Spec>,
+    failed: std::collections::HashSet<jit::SpecKey>,
+    next_symbol: usize,
+    missing_callee: Option<String>,
+    /// Profundidad máxima de llamadas nativas anidadas (igual que la VM).
+    pub max_call_depth: i64,
 }
 
 impl JitEngine {
@@ -667,6 +830,11 @@ impl JitEngine {
             builder_context: FunctionBuilderContext::new(),
             ctx,
             module,
+            specs: HashMap::new(),
+            failed: std::collections::HashSet::new(),
+            next_symbol: 0,
+            missing_callee: None,
+            max_call_depth: 10_000,
         })
     }
 
@@ -988,7 +1156,9 @@ pub fn compile_to_c(program: &Program) -> String {
             add_name(p);
         }
         for ins in &func.instrs {
-            if let Instr::Load(n) | Instr::Store(n) | Instr::FuncRef(n) = ins {
+            if let Instr::Load(n) | Instr::Store(n) | Instr::FuncRef(n) | Instr::MakeClosure(n, _) =
+                ins
+            {
                 add_name(n);
             }
             if let Instr::Call(n, _) = ins {
@@ -1143,6 +1313,30 @@ fn emit_func(
     let mut s = String::new();
     s.push_str(&format!("static Val _f_{}(void) {{\n", mangle(name)));
 
+    // `posponer`: _dfs guarda las etiquetas de los bloques pendientes y _drs
+    // el sitio DeferRun al que vuelve cada bloque al terminar.
+    let defer_labels: Vec<usize> = func
+        .instrs
+        .iter()
+        .filter_map(|i| match i {
+            Instr::DeferPush(l) => Some(*l),
+            _ => None,
+        })
+        .collect();
+    let defer_runs = func
+        .instrs
+        .iter()
+        .filter(|i| matches!(i, Instr::DeferRun(_)))
+        .count();
+    if !defer_labels.is_empty() {
+        s.push_str(&format!(
+            "  int _dfs[{}]; int _dfn = 0; int _drs[{}]; int _drn = 0;\n",
+            defer_labels.len(),
+            defer_runs.max(1)
+        ));
+    }
+    let mut run_site = 0usize;
+
     for instr in &func.instrs {
         match instr {
             Instr::ConstInt(n) => s.push_str(&format!("  PUSH(_v_int({}));\n", n)),
@@ -1424,7 +1618,9 @@ fn emit_func(
                     s.push_str(&format!("  {{ Val _r = _f_{}(); PUSH(_r); }}\n", mangle(n)));
                 }
             }
-            Instr::FuncRef(n) => {
+            // En C todas las variables son globales por nombre: la lambda ya
+            // ve (y modifica) las que captura.
+            Instr::FuncRef(n) | Instr::MakeClosure(n, _) => {
                 s.push_str(&format!(
                     "  PUSH(_vfref(\"{}\", &_f_{}));\n",
                     esc(n),
@@ -1541,7 +1737,33 @@ fn emit_func(
             Instr::Jmp(t) => s.push_str(&format!("  goto L_{};\n", t)),
             Instr::JmpIf(t) => s.push_str(&format!("  if (!_truthy(POP())) goto L_{};\n", t)),
             Instr::Label(t) => s.push_str(&format!("  L_{}:;\n", t)),
-            Instr::Phi(..) | Instr::Nop | Instr::Halt => {
+            Instr::DeferPush(t) => s.push_str(&format!("  _dfs[_dfn++] = {};\n", t)),
+            Instr::DeferRun(n) => {
+                if defer_labels.is_empty() {
+                    continue;
+                }
+                s.push_str(&format!(
+                    "  D_R{k}: if (_dfn > {n}) {{ _drs[_drn++] = {k}; switch (_dfs[--_dfn]) {{",
+                    k = run_site,
+                    n = n
+                ));
+                for l in &defer_labels {
+                    s.push_str(&format!(" case {l}: goto L_{l};", l = l));
+                }
+                s.push_str(" } }\n");
+                run_site += 1;
+            }
+            Instr::DeferEnd => {
+                s.push_str("  switch (_drs[--_drn]) {");
+                for k in 0..defer_runs {
+                    s.push_str(&format!(" case {k}: goto D_R{k};", k = k));
+                }
+                s.push_str(" }\n");
+            }
+            // El runtime C aborta ante errores: no hay manejadores que instalar.
+            Instr::TryBegin(_) | Instr::TryEnd => {}
+            Instr::ScopeBegin | Instr::ScopeEnd => {}
+            Instr::Phi(..) | Instr::Nop | Instr::Loc(..) | Instr::Bind(_) | Instr::Halt => {
                 if let Instr::Halt = instr {
                     s.push_str("  return _v_void();\n");
                 }
@@ -1552,8 +1774,22 @@ fn emit_func(
     s
 }
 
+/// Nombre de LÚMEN como símbolo de C o de LLVM. Lo ASCII que no vale pasa a
+/// `_`; cada carácter no ASCII (`año`, `canción`) se escribe como `_u`, su
+/// código en hexadecimal y `_`, porque ni los compiladores de C ni el IR de
+/// LLVM sin comillas aceptan identificadores UTF-8 en todas partes.
 fn mangle(name: &str) -> String {
-    name.replace(|c: char| !c.is_alphanumeric() && c != '_', "_")
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            out.push(c);
+        } else if c.is_ascii() {
+            out.push('_');
+        } else {
+            out.push_str(&format!("_u{:x}_", c as u32));
+        }
+    }
+    out
 }
 
 #[cfg(test)]
@@ -1573,6 +1809,7 @@ mod tests {
                 defaults: vec![],
                 entry: 0,
                 instrs: vec![Instr::ConstInt(42), Instr::Return],
+                declared: vec![],
             },
         );
         let compiler = AotCompiler::new();
@@ -1591,6 +1828,7 @@ mod tests {
                 defaults: vec![],
                 entry: 0,
                 instrs: vec![Instr::ConstInt(1), Instr::Return],
+                declared: vec![],
             },
         );
         program.funcs.insert(
@@ -1601,6 +1839,7 @@ mod tests {
                 defaults: vec![],
                 entry: 0,
                 instrs: vec![Instr::ConstInt(0), Instr::Return],
+                declared: vec![],
             },
         );
         // AOT only compiles entry-reachable functions
@@ -1626,6 +1865,7 @@ mod tests {
                     Instr::Print,
                     Instr::Halt,
                 ],
+                declared: vec![],
             },
         );
         program
@@ -1643,24 +1883,36 @@ mod tests {
     }
 
     #[test]
-    fn test_c_backend_gcc_runtime() {
+    fn test_nombres_no_ascii() {
+        assert_eq!(mangle("año"), "a_uf1_o");
+        assert_eq!(mangle("Punto.x"), "Punto_x");
+        let program = sample_program("canción");
+        let c = compile_to_c(&program);
+        assert!(c.contains("static Val _f_canci_uf3_n(void)"));
+        assert!(!c.contains("_f_canción"));
+        let llvm = compile_to_llvm_ir(&program);
+        assert!(llvm.contains("define i64 @canci_uf3_n("));
+    }
+
+    /// Compila `program` a C con gcc, lo ejecuta y devuelve su salida.
+    /// `None` si no hay un gcc con el que probar el runtime C.
+    fn run_c(program: &Program, name: &str) -> Option<String> {
         // Skip on Windows: el runtime C usa POSIX (opendir, regex) no disponible nativamente
         if cfg!(windows) {
-            return;
+            return None;
         }
         if std::process::Command::new("gcc")
             .arg("--version")
             .output()
             .is_err()
         {
-            return;
+            return None;
         }
         if std::env::var_os("MSYSTEM").is_some() {
-            return;
+            return None;
         }
-        let program = sample_program("main");
-        let c = compile_to_c(&program);
-        let dir = std::env::temp_dir().join(format!("lumen_aot_test_{}", std::process::id()));
+        let c = compile_to_c(program);
+        let dir = std::env::temp_dir().join(format!("lumen_aot_{}_{}", name, std::process::id()));
         std::fs::create_dir_all(&dir).unwrap();
         let c_path = dir.join("test.c");
         let exe_path = dir.join("test.exe");
@@ -1677,9 +1929,59 @@ mod tests {
             );
         }
         let out = std::process::Command::new(&exe_path).output().unwrap();
-        let test_out = String::from_utf8_lossy(&out.stdout).replace("\r\n", "\n");
-        assert_eq!(test_out, "42\n");
         let _ = std::fs::remove_dir_all(&dir);
+        Some(String::from_utf8_lossy(&out.stdout).replace("\r\n", "\n"))
+    }
+
+    #[test]
+    fn test_c_backend_gcc_runtime() {
+        if let Some(out) = run_c(&sample_program("main"), "test") {
+            assert_eq!(out, "42\n");
+        }
+    }
+
+    fn defer_program() -> Program {
+        let mut program = Program::new();
+        program.entry = "inicio".to_string();
+        program.funcs.insert(
+            "inicio".to_string(),
+            Func {
+                name: "inicio".to_string(),
+                params: vec![],
+                defaults: vec![],
+                entry: 0,
+                instrs: vec![
+                    Instr::DeferPush(0),
+                    Instr::Jmp(1),
+                    Instr::Label(0),
+                    Instr::ConstInt(1),
+                    Instr::Print,
+                    Instr::DeferEnd,
+                    Instr::Label(1),
+                    Instr::ConstInt(2),
+                    Instr::Print,
+                    Instr::DeferRun(0),
+                    Instr::Halt,
+                ],
+                declared: vec![],
+            },
+        );
+        program
+    }
+
+    #[test]
+    fn test_defer_backends() {
+        let program = defer_program();
+        let c = compile_to_c(&program);
+        assert!(c.contains("_dfs[_dfn++] = 0;"));
+        assert!(c.contains("D_R0: if (_dfn > 0)"));
+        assert!(c.contains("case 0: goto D_R0;"));
+        // El bloque pospuesto corre al salir, después del resto.
+        if let Some(out) = run_c(&program, "defer") {
+            assert_eq!(out, "2\n1\n");
+        }
+        let product = AotCompiler::new().compile(&program);
+        assert!(product.object.write().is_ok());
     }
 
     #[test]
@@ -1696,6 +1998,7 @@ mod tests {
                 Instr::Binary(Op::Add),
                 Instr::Return,
             ],
+            declared: vec![],
         };
         let code_ptr = jit
             .compile_function("jit_add", &func)
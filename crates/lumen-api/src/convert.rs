@@ -0,0 +1,261 @@
+// Conversions between Rust values and LÚMEN `Value`s, and the adapter that
+// turns typed Rust closures into host functions.
+
+use crate::LumenError;
+use lumen_vm::{HostFn, Value, VmError};
+use std::sync::Arc;
+
+/// Converts a LÚMEN value into a Rust value.
+pub trait FromLumen: Sized {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError>;
+}
+
+/// Converts a Rust value into a LÚMEN value.
+pub trait IntoLumen {
+    fn into_lumen(self) -> Value;
+}
+
+fn mismatch<T>(expected: &'static str, value: &Value) -> Result<T, LumenError> {
+    Err(LumenError::Conversion {
+        expected,
+        found: value.type_name(),
+    })
+}
+
+impl FromLumen for Value {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError> {
+        Ok(value.clone())
+    }
+}
+
+impl IntoLumen for Value {
+    fn into_lumen(self) -> Value {
+        self
+    }
+}
+
+impl FromLumen for i64 {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError> {
+        match value {
+            Value::Int(n) => Ok(*n),
+            other => mismatch("entero", other),
+        }
+    }
+}
+
+impl IntoLumen for i64 {
+    fn into_lumen(self) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl FromLumen for i32 {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError> {
+        let n = i64::from_lumen(value)?;
+        i32::try_from(n).map_err(|_| LumenError::Conversion {
+            expected: "entero de 32 bits",
+            found: "entero",
+        })
+    }
+}
+
+impl IntoLumen for i32 {
+    fn into_lumen(self) -> Value {
+        Value::Int(self as i64)
+    }
+}
+
+/// Accepts `entero` too, like LÚMEN does when a `decimal` is expected.
+impl FromLumen for f64 {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError> {
+        match value {
+            Value::Float(x) => Ok(*x),
+            Value::Int(n) => Ok(*n as f64),
+            other => mismatch("decimal", other),
+        }
+    }
+}
+
+impl IntoLumen for f64 {
+    fn into_lumen(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl FromLumen for bool {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            other => mismatch("booleano", other),
+        }
+    }
+}
+
+impl IntoLumen for bool {
+    fn into_lumen(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl FromLumen for String {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError> {
+        match value {
+            Value::Str(s) => Ok(s.to_string()),
+            other => mismatch("texto", other),
+        }
+    }
+}
+
+impl IntoLumen for String {
+    fn into_lumen(self) -> Value {
+        Value::str(self)
+    }
+}
+
+impl IntoLumen for &str {
+    fn into_lumen(self) -> Value {
+        Value::str(self)
+    }
+}
+
+impl FromLumen for () {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError> {
+        match value {
+            Value::Void => Ok(()),
+            other => mismatch("nulo", other),
+        }
+    }
+}
+
+impl IntoLumen for () {
+    fn into_lumen(self) -> Value {
+        Value::Void
+    }
+}
+
+impl<T: FromLumen> FromLumen for Vec<T> {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError> {
+        match value {
+            Value::Array(items) => items.iter().map(T::from_lumen).collect(),
+            other => mismatch("lista", other),
+        }
+    }
+}
+
+impl<T: IntoLumen> IntoLumen for Vec<T> {
+    fn into_lumen(self) -> Value {
+        Value::Array(Arc::new(self.into_iter().map(T::into_lumen).collect()))
+    }
+}
+
+impl<T: FromLumen> FromLumen for Option<T> {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError> {
+        match value {
+            Value::Opcion(Some(v)) => Ok(Some(T::from_lumen(v)?)),
+            Value::Opcion(None) => Ok(None),
+            other => mismatch("opcion", other),
+        }
+    }
+}
+
+impl<T: IntoLumen> IntoLumen for Option<T> {
+    fn into_lumen(self) -> Value {
+        Value::Opcion(self.map(|v| Box::new(v.into_lumen())))
+    }
+}
+
+/// `resultado<T, E>`: `exito(..)` is `Ok` and `error(..)` is `Err`.
+impl<T: FromLumen, E: FromLumen> FromLumen for Result<T, E> {
+    fn from_lumen(value: &Value) -> Result<Self, LumenError> {
+        match value {
+            Value::Exito(v) => Ok(Ok(T::from_lumen(v)?)),
+            Value::Error(e) => Ok(Err(E::from_lumen(e)?)),
+            other => mismatch("resultado", other),
+        }
+    }
+}
+
+impl<T: IntoLumen, E: IntoLumen> IntoLumen for Result<T, E> {
+    fn into_lumen(self) -> Value {
+        match self {
+            Ok(v) => Value::Exito(Box::new(v.into_lumen())),
+            Err(e) => Value::Error(Box::new(e.into_lumen())),
+        }
+    }
+}
+
+/// Arguments for `LumenEngine::call`: a tuple of `IntoLumen` values or a
+/// ready-made `Vec<Value>`.
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<Value>;
+}
+
+impl IntoArgs for Vec<Value> {
+    fn into_args(self) -> Vec<Value> {
+        self
+    }
+}
+
+/// A Rust closure callable from LÚMEN. Implemented for `Fn`s of up to six
+/// `FromLumen` arguments returning an `IntoLumen` value; `Args` is the tuple
+/// of argument types and only exists to tell the implementations apart.
+pub trait HostFunction<Args>: Send + Sync + 'static {
+    fn arity(&self) -> usize;
+    fn call(&self, args: &[Value]) -> Result<Value, LumenError>;
+}
+
+/// Wraps a typed host function into the VM's untyped `HostFn`. Conversion
+/// errors become runtime errors that name the function.
+pub(crate) fn into_host_fn<Args, F: HostFunction<Args>>(name: &str, f: F) -> HostFn {
+    let name = name.to_string();
+    Arc::new(move |args: &[Value]| {
+        f.call(args)
+            .map_err(|e| VmError::Runtime(format!("'{}': {}", name, e)))
+    })
+}
+
+macro_rules! impl_arity {
+    ($($arg:ident),*) => {
+        impl<$($arg: IntoLumen),*> IntoArgs for ($($arg,)*) {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Vec<Value> {
+                let ($($arg,)*) = self;
+                vec![$($arg.into_lumen()),*]
+            }
+        }
+
+        impl<Func, Ret, $($arg),*> HostFunction<($($arg,)*)> for Func
+        where
+            Func: Fn($($arg),*) -> Ret + Send + Sync + 'static,
+            Ret: IntoLumen,
+            $($arg: FromLumen,)*
+        {
+            fn arity(&self) -> usize {
+                <[&str]>::len(&[$(stringify!($arg)),*])
+            }
+
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn call(&self, args: &[Value]) -> Result<Value, LumenError> {
+                let mut args = args.iter();
+                $(
+                    let $arg = match args.next() {
+                        Some(v) => $arg::from_lumen(v)?,
+                        None => return Err(LumenError::Conversion {
+                            expected: "argumento",
+                            found: "nada",
+                        }),
+                    };
+                )*
+                Ok(self($($arg),*).into_lumen())
+            }
+        }
+    };
+}
+
+impl_arity!();
+impl_arity!(A);
+impl_arity!(A, B);
+impl_arity!(A, B, C);
+impl_arity!(A, B, C, D);
+impl_arity!(A, B, C, D, E);
+impl_arity!(A, B, C, D, E, G);
@@ -0,0 +1,45 @@
+// Errors returned by `LumenEngine`.
+
+use lumen_lexer::diagnostic::Diagnostic;
+use lumen_vm::VmError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum LumenError {
+    /// The program did not compile: lexer, parser, import or semantic errors.
+    Compile(Vec<Diagnostic>),
+    /// The VM failed while running; `stack` lists the active LÚMEN
+    /// functions, innermost last.
+    Runtime { error: VmError, stack: Vec<String> },
+    /// A value did not have the type the Rust side asked for.
+    Conversion {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// `call` was used before `load`/`run`.
+    NotLoaded,
+}
+
+impl fmt::Display for LumenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LumenError::Compile(diagnostics) => {
+                let lines: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            LumenError::Runtime { error, stack } => {
+                write!(f, "{}", error)?;
+                if !stack.is_empty() {
+                    write!(f, " (en {})", stack.join(" → "))?;
+                }
+                Ok(())
+            }
+            LumenError::Conversion { expected, found } => {
+                write!(f, "se esperaba un valor de tipo {}, no {}", expected, found)
+            }
+            LumenError::NotLoaded => write!(f, "no hay ningún programa cargado"),
+        }
+    }
+}
+
+impl std::error::Error for LumenError {}
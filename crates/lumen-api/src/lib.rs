@@ -1,42 +1,80 @@
 // LÚMEN Compiler API — Embedded scripting engine for Rust
-// Provides compile, check, and run functionality for LÚMEN source code.
+// Provides compile, check, and run functionality for LÚMEN source code,
+// host functions written in Rust and typed calls into loaded programs.
 
-use lumen_codegen::Codegen;
+mod convert;
+mod error;
+
+use lumen_codegen::{Bytecode, Codegen};
 use lumen_ir::IRBuilder;
-use lumen_lexer::Lexer;
-use lumen_parser::Parser;
-use lumen_sema::SemanticAnalyzer;
-use lumen_vm::VM;
-use std::path::PathBuf;
+use lumen_sema::{loader::canonical_path, ModuleLoader, SemanticAnalyzer};
+use lumen_vm::{HostFn, VM};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub use convert::{FromLumen, HostFunction, IntoArgs, IntoLumen};
+pub use error::LumenError;
+pub use lumen_lexer::diagnostic::{Diagnostic, Severity};
+pub use lumen_vm::{
+    Capabilities, Capability, PathAccess, SandboxViolation, Value, VmError, VmLimits,
+};
 
 /// Result type for LumenEngine operations.
-pub type LumenResult<T> = Result<T, String>;
+pub type LumenResult<T> = Result<T, LumenError>;
+
+/// Path given to the module loader for source passed as a string. It does
+/// not exist, so relative imports resolve from the current directory and the
+/// loader's parse cache (keyed by path and mtime) is never used for it.
+const INLINE_SOURCE: &str = "<lumen-engine>";
+
+struct HostEntry {
+    name: String,
+    arity: Option<usize>,
+    func: HostFn,
+}
 
 /// The LÚMEN engine: compiles and runs LÚMEN source code from Rust.
 ///
+/// A program stays loaded after `load`/`run`, so its functions can be called
+/// any number of times with `call`. Rust closures registered with
+/// `register_fn` are callable from LÚMEN like any other function.
+///
 /// # Example
 ///
-/// ```ignore
+/// ```
 /// use lumen_api::LumenEngine;
 ///
 /// let mut engine = LumenEngine::new();
-/// let result = engine.run("imprimir(\"Hola desde Rust!\");").unwrap();
-/// println!("{}", result);
+/// engine.register_fn("doble", |n: i64| n * 2);
+/// engine
+///     .load("funcion entero siguiente(entero n) { retornar doble(n) + 1; }")
+///     .unwrap();
+/// let n: i64 = engine.call("siguiente", (20,)).unwrap();
+/// assert_eq!(n, 41);
 /// ```
-#[allow(dead_code)]
 pub struct LumenEngine {
     lib_dirs: Vec<PathBuf>,
+    host_fns: Vec<HostEntry>,
+    limits: VmLimits,
+    vm: Option<VM>,
 }
 
 impl LumenEngine {
     /// Creates a new LÚMEN engine instance.
     pub fn new() -> Self {
-        Self { lib_dirs: vec![] }
+        Self::with_lib_dirs(vec![])
     }
 
-    /// Creates a new LÚMEN engine with custom library search paths.
+    /// Creates a new LÚMEN engine with custom library search paths, used to
+    /// resolve `importar`.
     pub fn with_lib_dirs(lib_dirs: Vec<PathBuf>) -> Self {
-        Self { lib_dirs }
+        Self {
+            lib_dirs,
+            host_fns: Vec::new(),
+            limits: VmLimits::default(),
+            vm: None,
+        }
     }
 
     /// Returns the LÚMEN version string.
@@ -44,79 +82,195 @@ impl LumenEngine {
         env!("CARGO_PKG_VERSION")
     }
 
-    /// Checks LÚMEN source code for syntax and semantic errors.
-    ///
-    /// Returns `Ok(())` if the code is valid, or `Err(errors)` with a list of error messages.
-    pub fn check(&self, source: &str) -> Result<(), Vec<String>> {
-        let lexer = Lexer::new(source);
-        let (tokens, lex_errors) = lexer.tokenize();
-
-        if !lex_errors.is_empty() {
-            return Err(lex_errors.iter().map(|e| format!("{:?}", e)).collect());
-        }
+    /// Registers a Rust closure as a LÚMEN function. Arguments are converted
+    /// with `FromLumen` and the result with `IntoLumen`; a call with the wrong
+    /// number or type of arguments is a LÚMEN runtime error.
+    pub fn register_fn<Args, F: HostFunction<Args>>(&mut self, name: &str, f: F) -> &mut Self {
+        let arity = f.arity();
+        let func = convert::into_host_fn(name, f);
+        self.add_host_fn(name, Some(arity), func)
+    }
 
-        let parser = Parser::new(tokens);
-        let (mut program, parse_errors) = parser.parse();
+    /// Registers a host function that works on raw values. With `arity: None`
+    /// it accepts any number of arguments. An `Err` is raised in LÚMEN as a
+    /// runtime error that `intentar`/`atrapar` can catch.
+    pub fn register_raw<F>(&mut self, name: &str, arity: Option<usize>, f: F) -> &mut Self
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        let func: HostFn = Arc::new(move |args: &[Value]| f(args).map_err(VmError::Runtime));
+        self.add_host_fn(name, arity, func)
+    }
 
-        if !parse_errors.is_empty() {
-            return Err(parse_errors.iter().map(|e| format!("{:?}", e)).collect());
+    /// Sets the limits for code run by this engine: instruction fuel,
+    /// wall-clock timeout, live memory, printed lines and which builtins may
+    /// touch files, the network, FFI, the environment or threads. Each
+    /// `load`/`run` and each `call` gets a fresh budget. A violation fails
+    /// with `LumenError::Runtime` holding `VmError::Sandbox`, which LÚMEN code
+    /// cannot catch. Host functions are never restricted.
+    ///
+    /// ```
+    /// use lumen_api::{LumenEngine, LumenError, VmError, VmLimits};
+    ///
+    /// let mut engine = LumenEngine::new();
+    /// engine.set_limits(VmLimits::sandboxed());
+    /// let err = engine.run("mientras verdadero { }").unwrap_err();
+    /// assert!(matches!(
+    ///     err,
+    ///     LumenError::Runtime { error: VmError::Sandbox(_), .. }
+    /// ));
+    /// ```
+    pub fn set_limits(&mut self, limits: VmLimits) -> &mut Self {
+        if let Some(vm) = self.vm.as_mut() {
+            vm.set_limits(limits.clone());
         }
+        self.limits = limits;
+        self
+    }
 
-        let sema = SemanticAnalyzer::new();
-        let sem_errors = sema.analyze(&mut program);
-
-        if !sem_errors.is_empty() {
-            return Err(sem_errors.iter().map(|e| format!("{:?}", e)).collect());
+    fn add_host_fn(&mut self, name: &str, arity: Option<usize>, func: HostFn) -> &mut Self {
+        if let Some(vm) = self.vm.as_mut() {
+            vm.register_host_fn(&[name], arity, "", func.clone());
         }
+        self.host_fns.retain(|h| h.name != name);
+        self.host_fns.push(HostEntry {
+            name: name.to_string(),
+            arity,
+            func,
+        });
+        self
+    }
 
-        Ok(())
+    /// Checks LÚMEN source code for import, syntax and semantic errors.
+    ///
+    /// Returns `Ok(())` if the code is valid, or `Err(diagnostics)` with the
+    /// errors of the first stage that failed.
+    pub fn check(&self, source: &str) -> Result<(), Vec<Diagnostic>> {
+        self.build(source, Path::new(INLINE_SOURCE)).map(|_| ())
     }
 
     /// Compiles LÚMEN source code to bytecode.
     ///
     /// Returns the compiled bytecode as a `Vec<u8>`.
-    pub fn compile(&self, source: &str) -> Result<Vec<u8>, String> {
-        let lexer = Lexer::new(source);
-        let (tokens, lex_errors) = lexer.tokenize();
+    pub fn compile(&self, source: &str) -> LumenResult<Vec<u8>> {
+        let bytecode = self
+            .build(source, Path::new(INLINE_SOURCE))
+            .map_err(LumenError::Compile)?;
+        Ok(bytecode.encode())
+    }
 
-        if !lex_errors.is_empty() {
-            return Err(format!("{:?}", lex_errors[0]));
-        }
+    /// Compiles `source` and runs its top-level code. The program stays
+    /// loaded for `call` until the next `load`.
+    pub fn load(&mut self, source: &str) -> LumenResult<()> {
+        let bytecode = self
+            .build(source, Path::new(INLINE_SOURCE))
+            .map_err(LumenError::Compile)?;
+        self.start(bytecode)
+    }
 
-        let parser = Parser::new(tokens);
-        let (mut program, parse_errors) = parser.parse();
+    /// Like `load`, reading the program from a file. Imports resolve from
+    /// the file's directory first.
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> LumenResult<()> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| {
+            LumenError::Compile(
+                lumen_sema::ModuleError::Io {
+                    path: path.to_path_buf(),
+                    message: e.to_string(),
+                }
+                .to_diagnostics(),
+            )
+        })?;
+        let bytecode = self.build(&source, path).map_err(LumenError::Compile)?;
+        self.start(bytecode)
+    }
 
-        if !parse_errors.is_empty() {
-            return Err(format!("{:?}", parse_errors[0]));
-        }
+    /// Runs LÚMEN source code and returns the lines it printed. The program
+    /// stays loaded, as with `load`.
+    pub fn run(&mut self, source: &str) -> LumenResult<Vec<String>> {
+        self.load(source)?;
+        Ok(self.take_output())
+    }
 
-        let sema = SemanticAnalyzer::new();
-        let sem_errors = sema.analyze(&mut program);
+    /// Calls a function of the loaded program. `args` is a tuple of Rust
+    /// values (or a `Vec<Value>`) and the result is converted to `R`.
+    pub fn call<R: FromLumen>(&mut self, name: &str, args: impl IntoArgs) -> LumenResult<R> {
+        let vm = self.vm.as_mut().ok_or(LumenError::NotLoaded)?;
+        vm.reset_budget();
+        let value = match vm.run_function(name, args.into_args()) {
+            Ok(value) => value,
+            Err(error) => {
+                let stack = vm.error_stack().to_vec();
+                return Err(LumenError::Runtime { error, stack });
+            }
+        };
+        R::from_lumen(&value)
+    }
+
+    /// Lines printed by the loaded program since the last `take_output`.
+    pub fn output(&self) -> &[String] {
+        self.vm.as_ref().map_or(&[], |vm| vm.output())
+    }
 
+    /// Returns the printed lines and clears them.
+    pub fn take_output(&mut self) -> Vec<String> {
+        self.vm.as_mut().map(VM::take_output).unwrap_or_default()
+    }
+
+    fn build(&self, source: &str, path: &Path) -> Result<Bytecode, Vec<Diagnostic>> {
+        let mut loader = ModuleLoader::new(self.lib_dirs.clone());
+        let (mut program, origins) =
+            loader
+                .resolve_imports_with_origins(source, path)
+                .map_err(|e| {
+                    // Las posiciones de la fuente en línea no tienen archivo.
+                    e.to_diagnostics()
+                        .into_iter()
+                        .map(|mut d| {
+                            if d.file.as_deref() == Some(INLINE_SOURCE) {
+                                d.file = None;
+                            }
+                            d
+                        })
+                        .collect::<Vec<_>>()
+                })?;
+
+        let mut sema = SemanticAnalyzer::new();
+        for host in &self.host_fns {
+            sema.declare_external(&host.name, host.arity);
+        }
+        let sem_errors = sema.analyze(&mut program);
         if !sem_errors.is_empty() {
-            return Err(format!("{:?}", sem_errors[0]));
+            return Err(sem_errors.iter().map(Diagnostic::from).collect());
         }
 
-        let ir = IRBuilder::new().build(&program);
+        let mut builder = IRBuilder::new();
+        builder.set_origins(&canonical_path(path), &origins);
+        for host in &self.host_fns {
+            builder.declare_external(&host.name);
+        }
+        let ir = builder.build(&program);
         let (bytecode, _) = Codegen::new().generate(&ir);
-
-        Ok(bytecode.encode())
+        Ok(bytecode)
     }
 
-    /// Runs LÚMEN source code and captures the output.
-    ///
-    /// Returns the captured output as a `String`, or an error description.
-    pub fn run(&mut self, source: &str) -> Result<String, String> {
-        let bytecode_data = self.compile(source)?;
-
-        let (bytecode, _) = lumen_codegen::Bytecode::decode(&bytecode_data)
-            .map_err(|e| format!("Decode error: {}", e))?;
-
+    fn start(&mut self, bytecode: Bytecode) -> LumenResult<()> {
+        self.vm = None;
         let mut vm = VM::new(bytecode);
-        vm.run().map_err(|e| format!("Runtime error: {:?}", e))?;
-
-        let output = vm.stack_top().map(|v| format!("{}", v)).unwrap_or_default();
-        Ok(output)
+        for host in &self.host_fns {
+            vm.register_host_fn(&[&host.name], host.arity, "", host.func.clone());
+        }
+        vm.set_limits(self.limits.clone());
+        if let Err(error) = vm.run() {
+            let stack = vm
+                .call_stack()
+                .iter()
+                .map(|f| f.func_name.clone())
+                .collect();
+            return Err(LumenError::Runtime { error, stack });
+        }
+        self.vm = Some(vm);
+        Ok(())
     }
 }
 
@@ -150,6 +304,16 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_check_diagnostics() {
+        let engine = LumenEngine::new();
+        let diags = engine.check("entero x = 1;\nimprimir(y);").unwrap_err();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].code, "E033");
+        assert_eq!(diags[0].severity, Severity::Error);
+        assert_eq!(diags[0].span().start.line, 2);
+    }
+
     #[test]
     fn test_compile() {
         let engine = LumenEngine::new();
@@ -167,4 +331,272 @@ mod tests {
         let result = engine.run("imprimir(\"hello\");");
         assert!(result.is_ok(), "Expected Ok, got: {:?}", result);
     }
+
+    #[test]
+    fn test_run_devuelve_salida() {
+        let mut engine = LumenEngine::new();
+        let lines = engine.run("imprimir(\"hola\");\nimprimir(1 + 2);").unwrap();
+        assert_eq!(lines, vec!["hola", "3"]);
+    }
+
+    #[test]
+    fn test_funcion_del_anfitrion() {
+        let mut engine = LumenEngine::new();
+        engine
+            .register_fn("sumar", |a: i64, b: i64| a + b)
+            .register_fn("saludo", |nombre: String| format!("hola {}", nombre));
+        let lines = engine
+            .run("imprimir(sumar(2, 3));\nimprimir(saludo(\"mundo\"));")
+            .unwrap();
+        assert_eq!(lines, vec!["5", "hola mundo"]);
+    }
+
+    #[test]
+    fn test_llamadas_repetidas() {
+        let mut engine = LumenEngine::new();
+        engine
+            .load("funcion entero cuadrado(entero n) { retornar n * n; }")
+            .unwrap();
+        for n in 0..5i64 {
+            let r: i64 = engine.call("cuadrado", (n,)).unwrap();
+            assert_eq!(r, n * n);
+        }
+    }
+
+    #[test]
+    fn test_llamada_tras_error() {
+        let mut engine = LumenEngine::new();
+        engine
+            .load("funcion entero dividir(entero a, entero b) { retornar a / b; }")
+            .unwrap();
+        let err = engine.call::<i64>("dividir", (1, 0)).unwrap_err();
+        assert!(matches!(err, LumenError::Runtime { .. }), "{:?}", err);
+        let r: i64 = engine.call("dividir", (9, 3)).unwrap();
+        assert_eq!(r, 3);
+    }
+
+    #[test]
+    fn test_error_de_llamada_lleva_la_pila() {
+        let mut engine = LumenEngine::new();
+        engine
+            .load(
+                "funcion entero interna(entero b) { retornar 1 / b; }
+funcion entero externa(entero b) { retornar interna(b) + 1; }",
+            )
+            .unwrap();
+        match engine.call::<i64>("externa", (0,)).unwrap_err() {
+            LumenError::Runtime { stack, .. } => assert_eq!(stack, vec!["externa", "interna"]),
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+        // Una función que no existe no hereda la pila del fallo anterior.
+        match engine.call::<i64>("no_existe", ()).unwrap_err() {
+            LumenError::Runtime { stack, .. } => assert!(stack.is_empty(), "{:?}", stack),
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_error_de_conversion() {
+        let mut engine = LumenEngine::new();
+        engine
+            .load("funcion texto nombre() { retornar \"ana\"; }")
+            .unwrap();
+        let err = engine.call::<i64>("nombre", ()).unwrap_err();
+        assert!(matches!(
+            err,
+            LumenError::Conversion {
+                expected: "entero",
+                found: "texto"
+            }
+        ));
+        let s: String = engine.call("nombre", ()).unwrap();
+        assert_eq!(s, "ana");
+    }
+
+    #[test]
+    fn test_sin_programa_cargado() {
+        let mut engine = LumenEngine::new();
+        let err = engine.call::<i64>("f", ()).unwrap_err();
+        assert!(matches!(err, LumenError::NotLoaded));
+    }
+
+    #[test]
+    fn test_error_de_compilacion() {
+        let mut engine = LumenEngine::new();
+        match engine.load("imprimir(y);") {
+            Err(LumenError::Compile(diags)) => assert_eq!(diags[0].code, "E033"),
+            other => panic!("se esperaba error de compilación: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_valores_compuestos() {
+        let mut engine = LumenEngine::new();
+        engine.register_fn("total", |xs: Vec<i64>| xs.iter().sum::<i64>());
+        engine
+            .load("funcion entero f(entero x) { retornar x; }\nfuncion entero suma(entero xs) { retornar total(xs); }")
+            .unwrap();
+        let v: Vec<i64> = engine.call("f", (vec![1i64, 2, 3],)).unwrap();
+        assert_eq!(v, vec![1, 2, 3]);
+        let o: Option<i64> = engine.call("f", (Some(4i64),)).unwrap();
+        assert_eq!(o, Some(4));
+        let r: Result<i64, String> = engine
+            .call("f", (Err::<i64, String>("mal".to_string()),))
+            .unwrap();
+        assert_eq!(r, Err("mal".to_string()));
+        let n: i64 = engine.call("suma", (vec![1i64, 2, 3],)).unwrap();
+        assert_eq!(n, 6);
+    }
+
+    #[test]
+    fn test_importar_desde_lib_dirs() {
+        let dir = std::env::temp_dir().join(format!("lumen_api_lib_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("util_api.nv"),
+            "funcion entero triple(entero n) { retornar n * 3; }",
+        )
+        .unwrap();
+        let mut engine = LumenEngine::with_lib_dirs(vec![dir.clone()]);
+        let lines = engine
+            .run("importar \"util_api.nv\";\nimprimir(util_api_triple(4));")
+            .unwrap();
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(lines, vec!["12"]);
+    }
+
+    fn sandbox_violation(err: LumenError) -> SandboxViolation {
+        match err {
+            LumenError::Runtime {
+                error: VmError::Sandbox(v),
+                ..
+            } => v,
+            other => panic!("expected a sandbox violation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_limite_de_instrucciones_por_llamada() {
+        let mut engine = LumenEngine::new();
+        engine.set_limits(VmLimits {
+            max_instructions: Some(5_000),
+            ..Default::default()
+        });
+        engine
+            .load(
+                "funcion entero contar(entero n) {\n    entero i = 0;\n    mientras i < n { i = i + 1; }\n    retornar i;\n}",
+            )
+            .unwrap();
+        // Cada llamada empieza con el combustible entero.
+        for _ in 0..3 {
+            let n: i64 = engine.call("contar", (100,)).unwrap();
+            assert_eq!(n, 100);
+        }
+        let err = engine.call::<i64>("contar", (1_000_000,)).unwrap_err();
+        assert_eq!(
+            sandbox_violation(err),
+            SandboxViolation::Instructions(5_000)
+        );
+    }
+
+    #[test]
+    fn test_intentar_no_captura_el_sandbox() {
+        let mut engine = LumenEngine::new();
+        engine.set_limits(VmLimits {
+            max_output_lines: Some(3),
+            ..Default::default()
+        });
+        let err = engine
+            .run("intentar {\n    mientras verdadero { imprimir(\"x\"); }\n} atrapar (e) {\n    imprimir(e);\n}")
+            .unwrap_err();
+        assert_eq!(sandbox_violation(err), SandboxViolation::Output(3));
+    }
+
+    #[test]
+    fn test_permisos_de_archivos() {
+        let dir = std::env::temp_dir().join(format!("lumen_api_sandbox_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let inside = dir.join("permitido.txt");
+        let outside = std::env::temp_dir().join("lumen_api_sandbox_fuera.txt");
+        let mut engine = LumenEngine::new();
+        engine.register_fn("anfitrion", |n: i64| n + 1);
+        engine.set_limits(VmLimits {
+            capabilities: Capabilities::none().allow_write(&dir),
+            ..Default::default()
+        });
+        let lines = engine
+            .run(&format!(
+                "__escribir_archivo(\"{}\", \"hola\");\nimprimir(anfitrion(1));",
+                inside.display()
+            ))
+            .unwrap();
+        assert_eq!(lines, vec!["2"]);
+        assert_eq!(fs::read_to_string(&inside).unwrap(), "hola");
+
+        let err = engine
+            .run(&format!(
+                "__escribir_archivo(\"{}\", \"hola\");",
+                outside.display()
+            ))
+            .unwrap_err();
+        match sandbox_violation(err) {
+            SandboxViolation::Denied { capability, .. } => {
+                assert_eq!(capability, Capability::FsWrite)
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(!outside.exists());
+        let err = engine
+            .run("__ffi_rust_eval(\"fn main() {}\");")
+            .unwrap_err();
+        assert!(matches!(
+            sandbox_violation(err),
+            SandboxViolation::Denied {
+                capability: Capability::Ffi,
+                ..
+            }
+        ));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_reservas_grandes_chocan_con_el_tope_de_memoria() {
+        let mut engine = LumenEngine::new();
+        engine.set_limits(VmLimits::sandboxed());
+        // Un largo imposible es un error del script, no una reserva.
+        let lines = engine
+            .run("imprimir(__bytes_aleatorios(9000000000000000000));")
+            .unwrap();
+        assert!(
+            lines[0].starts_with("error(bytes_aleatorios"),
+            "{:?}",
+            lines
+        );
+        let err = engine
+            .run("imprimir(__str_pad_start(\"a\", 9000000000000000000, \"x\"));")
+            .unwrap_err();
+        assert_eq!(
+            sandbox_violation(err),
+            SandboxViolation::Memory(64 * 1024 * 1024)
+        );
+
+        let dir = std::env::temp_dir().join(format!("lumen_api_memoria_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let big = dir.join("grande.txt");
+        fs::write(&big, vec![b'x'; 2 << 20]).unwrap();
+        engine.set_limits(VmLimits {
+            max_memory: Some(1 << 20),
+            capabilities: Capabilities::none().allow_read(&dir),
+            ..Default::default()
+        });
+        let err = engine
+            .run("imprimir(__bytes_aleatorios(1000000));")
+            .unwrap_err();
+        assert_eq!(sandbox_violation(err), SandboxViolation::Memory(1 << 20));
+        let err = engine
+            .run(&format!("imprimir(__leer_archivo(\"{}\"));", big.display()))
+            .unwrap_err();
+        assert_eq!(sandbox_violation(err), SandboxViolation::Memory(1 << 20));
+        fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -1,13 +1,15 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
+// Las cargas están en `workloads/` porque la prueba diferencial del JIT
+// (`lumen-vm/tests/jit_differential.rs`) ejecuta las mismas.
+const FIB: &str = include_str!("../workloads/fib.nv");
+const FALLTHROUGH: &str = include_str!("../workloads/fallthrough.nv");
+const DEFAULTS: &str = include_str!("../workloads/defaults.nv");
+const POTENCIA: &str = include_str!("../workloads/potencia.nv");
+const HEADLESS: &str = include_str!("../workloads/headless.nv");
+
 fn lexer_bench(c: &mut Criterion) {
-    let source = r#"
-funcion entero fib(entero n) {
-    si n <= 1 { retornar n; }
-    retornar fib(n-1) + fib(n-2);
-}
-imprimir(fib(20));
-"#;
+    let source = FIB;
 
     c.bench_function("lexer_tokenize", |b| {
         b.iter(|| {
@@ -19,13 +21,7 @@ imprimir(fib(20));
 }
 
 fn parser_bench(c: &mut Criterion) {
-    let source = r#"
-funcion entero fib(entero n) {
-    si n <= 1 { retornar n; }
-    retornar fib(n-1) + fib(n-2);
-}
-imprimir(fib(20));
-"#;
+    let source = FIB;
     let (tokens, _) = lumen_lexer::Lexer::new(source).tokenize();
 
     c.bench_function("parser_parse", |b| {
@@ -38,13 +34,7 @@ imprimir(fib(20));
 }
 
 fn pipeline_bench(c: &mut Criterion) {
-    let source = r#"
-funcion entero fib(entero n) {
-    si n <= 1 { retornar n; }
-    retornar fib(n-1) + fib(n-2);
-}
-imprimir(fib(20));
-"#;
+    let source = FIB;
 
     c.bench_function("pipeline_full", |b| {
         b.iter(|| {
@@ -62,13 +52,7 @@ imprimir(fib(20));
 }
 
 fn vm_exec_bench(c: &mut Criterion) {
-    let source = r#"
-funcion entero fib(entero n) {
-    si n <= 1 { retornar n; }
-    retornar fib(n-1) + fib(n-2);
-}
-imprimir(fib(20));
-"#;
+    let source = FIB;
 
     c.bench_function("vm_fib_20", |b| {
         b.iter(|| {
@@ -92,11 +76,7 @@ imprimir(fib(20));
 // ============================================================
 
 fn prod_fallthrough_bench(c: &mut Criterion) {
-    let source = r#"
-        funcion void foo(entero r, entero g, entero b){ si r==0{retornar;} imprimir(r); }
-        funcion void bar(entero r, entero g, entero b, entero a){ imprimir(a); }
-        foo(0,0,0); foo(1,2,3); bar(1,2,3,99);
-    "#;
+    let source = FALLTHROUGH;
     c.bench_function("prod_fallthrough_early_return", |b| {
         b.iter(|| {
             let (tokens, _) = lumen_lexer::Lexer::new(black_box(source)).tokenize();
@@ -112,8 +92,7 @@ fn prod_fallthrough_bench(c: &mut Criterion) {
 }
 
 fn prod_defaults_bench(c: &mut Criterion) {
-    let source =
-        r#"cualquiera f = funcion(entero a, entero b=10){ retornar a+b; }; f(5); f(5,20);"#;
+    let source = DEFAULTS;
     c.bench_function("prod_defaults_callvalue", |b| {
         b.iter(|| {
             let (tokens, _) = lumen_lexer::Lexer::new(black_box(source)).tokenize();
@@ -129,13 +108,7 @@ fn prod_defaults_bench(c: &mut Criterion) {
 }
 
 fn prod_matematicas_bench(c: &mut Criterion) {
-    let source = r#"
-        funcion numero potencia(numero base, entero exp){
-            si(exp==0){retornar 1;} numero res=1; entero i=0;
-            mientras(i<exp){res=res*base;i=i+1;} retornar res;
-        }
-        potencia(2,10); potencia(3,7); potencia(5,5);
-    "#;
+    let source = POTENCIA;
     c.bench_function("prod_matematicas_potencia", |b| {
         b.iter(|| {
             let (tokens, _) = lumen_lexer::Lexer::new(black_box(source)).tokenize();
@@ -152,10 +125,7 @@ fn prod_matematicas_bench(c: &mut Criterion) {
 
 fn prod_headless_bench(c: &mut Criterion) {
     // Valida el path headless centralizado es_headless() sin tocar SDL (solo pipeline)
-    let source = r#"
-        funcion booleano es_headless(){ si 1==1 { retornar falso; } retornar verdadero; }
-        es_headless();
-    "#;
+    let source = HEADLESS;
     c.bench_function("prod_graficos_headless", |b| {
         b.iter(|| {
             let (tokens, _) = lumen_lexer::Lexer::new(black_box(source)).tokenize();
@@ -0,0 +1,970 @@
+//! `lumen dap`: servidor del Debug Adapter Protocol sobre stdio.
+//!
+//! Traduce las peticiones DAP del editor a la API de depuración de la VM:
+//! breakpoints por archivo y línea (del principal o de sus módulos), pasos
+//! por sentencia, pila de llamadas, variables del frame actual y `stepBack`
+//! sobre los snapshots del time-travel.
+//! Solo hay un hilo (`id` 1) y las líneas y columnas empiezan en 1.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use lumen_codegen::{Bytecode, Codegen};
+use lumen_ir::IRBuilder;
+use lumen_lexer::{ident, Diagnostic};
+use lumen_sema::{loader::canonical_path, ModuleLoader, SemanticAnalyzer};
+use lumen_vm::{StepKind, Value, VM};
+use serde_json::{json, Value as Json};
+
+const THREAD_ID: i64 = 1;
+/// Referencia de variables del ámbito del frame actual; las de valores
+/// compuestos se numeran a partir de la siguiente.
+const LOCALS_REF: i64 = 1;
+
+/// Arranca el adaptador sobre stdin/stdout hasta `disconnect` o fin de entrada.
+pub fn run_dap(lib_dirs: &[PathBuf]) {
+    let stdin = io::stdin();
+    let mut adapter = DebugAdapter::new(lib_dirs.to_vec(), io::stdout());
+    adapter.serve(stdin.lock());
+}
+
+/// Lee un mensaje con cabecera `Content-Length`. `None` al terminar la entrada.
+fn read_message(reader: &mut impl BufRead) -> Option<Json> {
+    loop {
+        let mut content_length = 0usize;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).ok()? == 0 {
+                return None;
+            }
+            let trimmed = header.trim();
+            if trimmed.is_empty() {
+                break;
+            }
+            if let Some(len) = trimmed.strip_prefix("Content-Length:") {
+                content_length = len.trim().parse().unwrap_or(0);
+            }
+        }
+        if content_length == 0 {
+            continue;
+        }
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body).ok()?;
+        if let Ok(message) = serde_json::from_slice(&body) {
+            return Some(message);
+        }
+    }
+}
+
+struct Session {
+    vm: VM,
+    /// Ruta del programa tal como la dio `launch`.
+    path: String,
+    /// Líneas con breakpoint por archivo del chunk, para poder reemplazarlas
+    /// en `setBreakpoints`.
+    breakpoint_lines: HashMap<u32, Vec<u32>>,
+    stop_on_entry: bool,
+    /// Líneas de salida ya enviadas como eventos `output`.
+    printed: usize,
+    /// La ejecución falló; el siguiente paso o `continue` la termina.
+    faulted: bool,
+}
+
+impl Session {
+    /// Índice en la tabla de archivos del chunk de una ruta de
+    /// `setBreakpoints`; el programa principal es siempre el 0.
+    fn file_index(&self, path: &str) -> Option<u32> {
+        if normalize(path) == normalize(&self.path) {
+            return Some(0);
+        }
+        self.vm.source_file_index(Path::new(path))
+    }
+
+    /// Reemplaza los breakpoints de `file` por los de `lines`. Devuelve las
+    /// líneas que quedaron puestas.
+    fn replace_breakpoints(&mut self, file: u32, lines: &[u32]) -> Vec<u32> {
+        for line in self.breakpoint_lines.remove(&file).unwrap_or_default() {
+            self.vm.clear_file_line_breakpoint(file, line);
+        }
+        let verified: Vec<u32> = lines
+            .iter()
+            .copied()
+            .filter(|&line| self.vm.set_file_line_breakpoint(file, line))
+            .collect();
+        self.breakpoint_lines.insert(file, verified.clone());
+        verified
+    }
+
+    /// `Source` de DAP del archivo `file`: el principal con la ruta de
+    /// `launch`; un módulo importado, con su ruta canónica.
+    fn source(&self, file: Option<u32>) -> Json {
+        let path = match file {
+            Some(f) if f != 0 => self.vm.source_file(f).unwrap_or(&self.path),
+            _ => &self.path,
+        };
+        json!({
+            "name": Path::new(path).file_name().map(|n| n.to_string_lossy()),
+            "path": path,
+        })
+    }
+}
+
+pub struct DebugAdapter<W: Write> {
+    out: W,
+    seq: i64,
+    lib_dirs: Vec<PathBuf>,
+    session: Option<Session>,
+    /// Breakpoints recibidos antes de `launch`, por ruta.
+    pending_breakpoints: HashMap<String, Vec<u32>>,
+    /// Valores compuestos expandibles en la parada actual, por referencia.
+    handles: Vec<Value>,
+}
+
+impl<W: Write> DebugAdapter<W> {
+    pub fn new(lib_dirs: Vec<PathBuf>, out: W) -> Self {
+        Self {
+            out,
+            seq: 0,
+            lib_dirs,
+            session: None,
+            pending_breakpoints: HashMap::new(),
+            handles: Vec::new(),
+        }
+    }
+
+    /// Atiende mensajes hasta `disconnect`/`terminate` o el fin de la entrada.
+    pub fn serve(&mut self, mut reader: impl BufRead) {
+        while let Some(message) = read_message(&mut reader) {
+            if !self.handle(&message) {
+                break;
+            }
+        }
+    }
+
+    /// Atiende una petición. Devuelve `false` cuando la sesión termina.
+    pub fn handle(&mut self, request: &Json) -> bool {
+        let command = request["command"].as_str().unwrap_or("").to_string();
+        let args = &request["arguments"];
+        let result = match command.as_str() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsStepBack": true,
+                "supportsEvaluateForHovers": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "configurationDone" | "threads" | "stackTrace" | "scopes" | "variables"
+            | "evaluate" | "continue" | "next" | "stepIn" | "stepOut" | "stepBack"
+            | "reverseContinue" | "pause"
+                if self.session.is_none() =>
+            {
+                Err("No hay ningún programa en depuración".to_string())
+            }
+            "configurationDone" => Ok(Json::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "principal" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(self.scopes(args)),
+            "variables" => self.variables(args),
+            "evaluate" => self.evaluate(args),
+            "continue" => Ok(json!({ "allThreadsContinued": true })),
+            "next" | "stepIn" | "stepOut" | "stepBack" | "reverseContinue" | "pause" => {
+                Ok(Json::Null)
+            }
+            "disconnect" | "terminate" => Ok(Json::Null),
+            other => Err(format!("Comando DAP no soportado: {}", other)),
+        };
+        match result {
+            Ok(body) => self.respond(request, true, body, None),
+            Err(message) => self.respond(request, false, Json::Null, Some(message)),
+        }
+
+        // Los eventos que provoca cada petición van después de su respuesta.
+        match command.as_str() {
+            "initialize" => {}
+            "launch" if self.session.is_some() => self.send_event("initialized", Json::Null),
+            "configurationDone" if self.session.is_some() => {
+                if self.session.as_ref().is_some_and(|s| s.stop_on_entry) {
+                    self.stopped("entry", None);
+                } else {
+                    self.resume(None);
+                }
+            }
+            "continue" if self.session.is_some() => self.resume(None),
+            "next" if self.session.is_some() => self.resume(Some(StepKind::Over)),
+            "stepIn" if self.session.is_some() => self.resume(Some(StepKind::Into)),
+            "stepOut" if self.session.is_some() => self.resume(Some(StepKind::Out)),
+            "stepBack" | "reverseContinue" if self.session.is_some() => self.step_back(),
+            "pause" if self.session.is_some() => self.stopped("pause", None),
+            "disconnect" | "terminate" => return false,
+            _ => {}
+        }
+        true
+    }
+
+    fn launch(&mut self, args: &Json) -> Result<Json, String> {
+        let path = args["program"]
+            .as_str()
+            .ok_or("falta el argumento 'program'")?
+            .to_string();
+        let bytecode = compile(&path, &self.lib_dirs)?;
+        let mut vm = VM::new(bytecode);
+        vm.debug = true;
+        vm.set_source_name(&path);
+        let mut session = Session {
+            vm,
+            path: path.clone(),
+            breakpoint_lines: HashMap::new(),
+            stop_on_entry: args["stopOnEntry"].as_bool().unwrap_or(false),
+            printed: 0,
+            faulted: false,
+        };
+        // Los de archivos que el programa no importa se descartan.
+        for (path, lines) in self.pending_breakpoints.drain() {
+            if let Some(file) = session.file_index(&path) {
+                session.replace_breakpoints(file, &lines);
+            }
+        }
+        self.session = Some(session);
+        Ok(Json::Null)
+    }
+
+    fn set_breakpoints(&mut self, args: &Json) -> Json {
+        let path = args["source"]["path"].as_str().unwrap_or("");
+        let lines: Vec<u32> = match args["breakpoints"].as_array() {
+            Some(bps) => bps
+                .iter()
+                .filter_map(|bp| bp["line"].as_u64())
+                .map(|l| l as u32)
+                .collect(),
+            None => args["lines"]
+                .as_array()
+                .map(|ls| ls.iter().filter_map(|l| l.as_u64()).map(|l| l as u32))
+                .into_iter()
+                .flatten()
+                .collect(),
+        };
+        let Some(session) = self.session.as_mut() else {
+            // Se aplican en `launch`; hasta entonces no están verificados.
+            self.pending_breakpoints
+                .insert(normalize(path), lines.clone());
+            let bps: Vec<Json> = lines
+                .iter()
+                .map(|l| json!({ "verified": false, "line": l }))
+                .collect();
+            return json!({ "breakpoints": bps });
+        };
+        let Some(file) = session.file_index(path) else {
+            let bps: Vec<Json> = lines
+                .iter()
+                .map(|l| {
+                    json!({
+                        "verified": false,
+                        "line": l,
+                        "message": "El archivo no forma parte del programa",
+                    })
+                })
+                .collect();
+            return json!({ "breakpoints": bps });
+        };
+        let verified = session.replace_breakpoints(file, &lines);
+        let bps: Vec<Json> = lines
+            .iter()
+            .map(|line| {
+                let ok = verified.contains(line);
+                let mut bp = json!({ "verified": ok, "line": line });
+                if !ok {
+                    bp["message"] = json!("No hay ninguna sentencia en esta línea");
+                }
+                bp
+            })
+            .collect();
+        json!({ "breakpoints": bps })
+    }
+
+    fn stack_trace(&self) -> Json {
+        let Some(session) = self.session.as_ref() else {
+            return json!({ "stackFrames": [], "totalFrames": 0 });
+        };
+        let vm = &session.vm;
+        // El frame más interno está en la próxima instrucción; cada llamador,
+        // en la llamada a su sucesor.
+        let mut frames = Vec::new();
+        let (mut position, mut file) = if session.faulted {
+            (vm.error_position(), vm.error_file())
+        } else {
+            (vm.current_position(), vm.current_file())
+        };
+        for (id, frame) in vm.call_stack().iter().rev().enumerate() {
+            frames.push(stack_frame(
+                id,
+                &frame.func_name,
+                position,
+                &session.source(file),
+            ));
+            let caller = frame.return_ip.checked_sub(1);
+            position = caller.and_then(|ip| vm.position_of(ip));
+            file = caller.and_then(|ip| vm.file_of(ip));
+        }
+        frames.push(stack_frame(
+            frames.len(),
+            "(nivel superior)",
+            position,
+            &session.source(file),
+        ));
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    /// Solo el frame actual tiene variables: la VM expone las de ese frame.
+    fn scopes(&self, args: &Json) -> Json {
+        if args["frameId"].as_i64().unwrap_or(0) != 0 {
+            return json!({ "scopes": [] });
+        }
+        let in_function = self
+            .session
+            .as_ref()
+            .is_some_and(|s| !s.vm.call_stack().is_empty());
+        let name = if in_function { "Locales" } else { "Globales" };
+        json!({
+            "scopes": [{
+                "name": name,
+                "variablesReference": LOCALS_REF,
+                "expensive": false,
+            }]
+        })
+    }
+
+    fn variables(&mut self, args: &Json) -> Result<Json, String> {
+        let reference = args["variablesReference"].as_i64().unwrap_or(0);
+        let children = if reference == LOCALS_REF {
+            let mut locals: Vec<(String, Value)> = self
+                .current_locals()
+                .into_iter()
+                .filter(|(name, _)| !name.starts_with("__"))
+                .collect();
+            locals.sort_by(|a, b| a.0.cmp(&b.0));
+            locals
+        } else {
+            let value = usize::try_from(reference - LOCALS_REF - 1)
+                .ok()
+                .and_then(|i| self.handles.get(i))
+                .ok_or("Referencia de variables inválida")?;
+            children(value)
+        };
+        let vars: Vec<Json> = children
+            .into_iter()
+            .map(|(name, value)| {
+                let mut var = self.describe(&value);
+                var["name"] = json!(name);
+                var
+            })
+            .collect();
+        Ok(json!({ "variables": vars }))
+    }
+
+    /// Evalúa un nombre del frame actual seguido de accesos `.campo`, `.0`,
+    /// `[índice]` o `["clave"]`.
+    fn evaluate(&mut self, args: &Json) -> Result<Json, String> {
+        let expression = args["expression"].as_str().unwrap_or("").trim();
+        let (name, accessors) = parse_path(expression)
+            .ok_or_else(|| format!("Solo se pueden evaluar variables: '{}'", expression))?;
+        let mut value = self
+            .current_locals()
+            .into_iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+            .ok_or_else(|| format!("Variable '{}' no definida", name))?;
+        for accessor in accessors {
+            value = children(&value)
+                .into_iter()
+                .find(|(n, _)| *n == accessor)
+                .map(|(_, v)| v)
+                .ok_or_else(|| format!("'{}' no tiene '{}'", expression, accessor))?;
+        }
+        let var = self.describe(&value);
+        Ok(json!({
+            "result": var["value"],
+            "type": var["type"],
+            "variablesReference": var["variablesReference"],
+        }))
+    }
+
+    fn current_locals(&self) -> Vec<(String, Value)> {
+        self.session
+            .as_ref()
+            .and_then(|s| s.vm.current_locals())
+            .map(|vars| vars.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Variable DAP de un valor; los compuestos reciben una referencia para
+    /// expandirse.
+    fn describe(&mut self, value: &Value) -> Json {
+        let reference = if children(value).is_empty() {
+            0
+        } else {
+            self.handles.push(value.clone());
+            LOCALS_REF + self.handles.len() as i64
+        };
+        json!({
+            "value": value.to_string(),
+            "type": value.type_name(),
+            "variablesReference": reference,
+        })
+    }
+
+    /// Ejecuta hasta la siguiente parada (`step` o breakpoint) o el final.
+    fn resume(&mut self, step: Option<StepKind>) {
+        let Some(session) = self.session.as_mut() else {
+            return;
+        };
+        if session.faulted {
+            self.finish(1);
+            return;
+        }
+        let result = match step {
+            Some(kind) => session.vm.step_source(kind),
+            None => session.vm.run(),
+        };
+        self.flush_output();
+        let Some(session) = self.session.as_mut() else {
+            return;
+        };
+        match result {
+            Err(e) => {
+                session.faulted = true;
+                let message = session.vm.describe_error(e);
+                self.send_event(
+                    "output",
+                    json!({ "category": "stderr", "output": format!("{}\n", message) }),
+                );
+                self.stopped("exception", Some(message));
+            }
+            Ok(()) if session.vm.is_finished() => self.finish(0),
+            Ok(()) if session.vm.is_paused() => self.stopped("breakpoint", None),
+            Ok(()) => self.stopped("step", None),
+        }
+    }
+
+    fn step_back(&mut self) {
+        if let Some(session) = self.session.as_mut() {
+            if session.vm.step_back().unwrap_or(false) {
+                session.faulted = false;
+                session.printed = session.printed.min(session.vm.output().len());
+            }
+        }
+        self.stopped("step", None);
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) {
+        self.handles.clear();
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.send_event("stopped", body);
+    }
+
+    fn finish(&mut self, exit_code: i32) {
+        self.send_event("exited", json!({ "exitCode": exit_code }));
+        self.send_event("terminated", Json::Null);
+    }
+
+    /// Envía como eventos `output` las líneas impresas desde la última vez.
+    fn flush_output(&mut self) {
+        let Some(session) = self.session.as_mut() else {
+            return;
+        };
+        let lines: Vec<String> = session.vm.output()[session.printed..].to_vec();
+        session.printed += lines.len();
+        for line in lines {
+            self.send_event(
+                "output",
+                json!({ "category": "stdout", "output": format!("{}\n", line) }),
+            );
+        }
+    }
+
+    fn respond(&mut self, request: &Json, success: bool, body: Json, message: Option<String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": success,
+            "command": request["command"],
+        });
+        if !body.is_null() {
+            response["body"] = body;
+        }
+        if let Some(message) = message {
+            response["message"] = json!(message);
+        }
+        self.send(response);
+    }
+
+    fn send_event(&mut self, event: &str, body: Json) {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message);
+    }
+
+    fn send(&mut self, mut message: Json) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        let _ = write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = self.out.flush();
+    }
+}
+
+/// Compila el programa; los diagnósticos se devuelven ya renderizados, sin
+/// colores, para el mensaje de error de `launch`.
+fn compile(path: &str, lib_dirs: &[PathBuf]) -> Result<Bytecode, String> {
+    let source =
+        fs::read_to_string(path).map_err(|e| format!("Error al leer '{}': {}", path, e))?;
+    let render = |diags: Vec<Diagnostic>| {
+        diags
+            .iter()
+            .map(|d| {
+                let other = d
+                    .file
+                    .as_ref()
+                    .map(|f| fs::read_to_string(f).unwrap_or_default());
+                d.render(other.as_deref().unwrap_or(&source), path, false)
+            })
+            .collect::<String>()
+    };
+    let mut loader = ModuleLoader::new(lib_dirs.to_vec());
+    let (mut program, origins) = loader
+        .resolve_imports_with_origins(&source, Path::new(path))
+        .map_err(|e| render(e.to_diagnostics()))?;
+    let errors = SemanticAnalyzer::new().analyze(&mut program);
+    if !errors.is_empty() {
+        return Err(render(errors.iter().map(Diagnostic::from).collect()));
+    }
+    let mut builder = IRBuilder::new();
+    builder.set_origins(&canonical_path(Path::new(path)), &origins);
+    let ir = builder.build(&program);
+    Ok(Codegen::new().generate(&ir).0)
+}
+
+/// Las rutas de `launch` y `setBreakpoints` pueden llegar escritas distinto.
+fn normalize(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+fn stack_frame(id: usize, name: &str, position: Option<(u32, u32)>, source: &Json) -> Json {
+    let (line, column) = position.unwrap_or((0, 0));
+    json!({
+        "id": id,
+        "name": name,
+        "source": source,
+        "line": line,
+        "column": column,
+    })
+}
+
+/// Elementos de un valor compuesto con el nombre con el que se accede a cada
+/// uno.
+fn children(value: &Value) -> Vec<(String, Value)> {
+    let indexed = |items: &[Value]| {
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (format!("[{}]", i), v.clone()))
+            .collect()
+    };
+    match value {
+        Value::Array(items) => indexed(items),
+        Value::Enum { fields, .. } => indexed(fields),
+        Value::Tuple(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v.clone()))
+            .collect(),
+        Value::Struct { fields, .. } => fields.clone(),
+        Value::Map(map) => {
+            let mut entries: Vec<(String, Value)> = map
+                .iter()
+                .map(|(k, v)| (format!("[{:?}]", k.to_string()), v.clone()))
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            entries
+        }
+        Value::Exito(inner) | Value::Error(inner) => vec![("valor".to_string(), (**inner).clone())],
+        Value::Opcion(Some(inner)) => vec![("valor".to_string(), (**inner).clone())],
+        _ => Vec::new(),
+    }
+}
+
+/// Separa `a.b[0]["k"]` en el nombre y los accesos, con la forma que les da
+/// `children`.
+fn parse_path(expression: &str) -> Option<(String, Vec<String>)> {
+    let end = expression
+        .find(|c: char| !ident::is_ident_continue(c))
+        .unwrap_or(expression.len());
+    let name = ident::normalize(&expression[..end]);
+    if !ident::is_identifier(&name) {
+        return None;
+    }
+    let mut rest = &expression[end..];
+    let mut accessors = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after
+                .find(|c: char| !ident::is_ident_continue(c))
+                .unwrap_or(after.len());
+            if end == 0 {
+                return None;
+            }
+            accessors.push(ident::normalize(&after[..end]));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let close = after.find(']')?;
+            let inner = after[..close].trim();
+            let accessor = match inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                Some(key) => format!("[{:?}]", key),
+                None => format!("[{}]", inner.parse::<usize>().ok()?),
+            };
+            accessors.push(accessor);
+            rest = &after[close + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some((name, accessors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const PROGRAMA: &str = "estructura Punto { x: entero, y: entero }
+funcion entero doble(entero n) {
+    entero r = n * 2;
+    retornar r;
+}
+entero a = doble(3);
+imprimir(a);
+Punto p = Punto { x: 1, y: 2 };
+lista<entero> xs = [1, 2, 3];
+imprimir(doble(a));
+";
+
+    fn write_program(name: &str, source: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("lumen_dap_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("prog.nv");
+        fs::write(&path, source).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn frame(message: Json) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    /// Pasa los mensajes por el servidor como llegarían por stdio y devuelve
+    /// todo lo que respondió.
+    fn drive(requests: &[(&str, Json)]) -> Vec<Json> {
+        let input: String = requests
+            .iter()
+            .enumerate()
+            .map(|(i, (command, arguments))| {
+                frame(json!({
+                    "seq": i + 1,
+                    "type": "request",
+                    "command": command,
+                    "arguments": arguments,
+                }))
+            })
+            .collect();
+        let mut out = Vec::new();
+        DebugAdapter::new(Vec::new(), &mut out).serve(Cursor::new(input.into_bytes()));
+        let mut reader = Cursor::new(out);
+        std::iter::from_fn(|| read_message(&mut reader)).collect()
+    }
+
+    fn response(messages: &[Json], request_seq: usize) -> &Json {
+        messages
+            .iter()
+            .find(|m| m["type"] == "response" && m["request_seq"] == request_seq)
+            .unwrap_or_else(|| panic!("sin respuesta a {}: {:#?}", request_seq, messages))
+    }
+
+    /// Eventos `event` enviados entre la respuesta a `request_seq` y la
+    /// siguiente respuesta.
+    fn events_after<'a>(messages: &'a [Json], request_seq: usize, event: &str) -> Vec<&'a Json> {
+        messages
+            .iter()
+            .skip_while(|m| !(m["type"] == "response" && m["request_seq"] == request_seq))
+            .skip(1)
+            .take_while(|m| m["type"] == "event")
+            .filter(|m| m["event"] == event)
+            .collect()
+    }
+
+    fn top_line(messages: &[Json], request_seq: usize) -> i64 {
+        response(messages, request_seq)["body"]["stackFrames"][0]["line"]
+            .as_i64()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sesion_con_breakpoints_y_pasos() {
+        let path = write_program("pasos", PROGRAMA);
+        let messages = drive(&[
+            ("initialize", json!({ "adapterID": "lumen" })),
+            ("launch", json!({ "program": path })),
+            (
+                "setBreakpoints",
+                json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }, { "line": 5 }] }),
+            ),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("next", json!({ "threadId": 1 })),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("stepOut", json!({ "threadId": 1 })),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("stepBack", json!({ "threadId": 1 })),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("continue", json!({ "threadId": 1 })),
+            ("continue", json!({ "threadId": 1 })),
+            ("disconnect", json!({})),
+        ]);
+        assert_eq!(response(&messages, 1)["body"]["supportsStepBack"], true);
+        assert!(response(&messages, 2)["success"].as_bool().unwrap());
+        assert_eq!(events_after(&messages, 2, "initialized").len(), 1);
+
+        let bps = &response(&messages, 3)["body"]["breakpoints"];
+        assert_eq!(bps[0]["verified"], true);
+        assert_eq!(bps[1]["verified"], false);
+
+        let stopped = events_after(&messages, 4, "stopped");
+        assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+        let frames = &response(&messages, 5)["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "doble");
+        assert_eq!(frames[0]["line"], 3);
+        assert_eq!(frames[1]["name"], "(nivel superior)");
+        assert_eq!(frames[1]["line"], 6);
+
+        assert_eq!(top_line(&messages, 7), 4);
+        assert_eq!(top_line(&messages, 9), 7);
+        assert_eq!(top_line(&messages, 11), 4);
+
+        // El breakpoint de la línea 3 vuelve a parar en la segunda llamada.
+        let output = events_after(&messages, 12, "output");
+        assert_eq!(output[0]["body"]["output"], "6\n");
+        assert_eq!(
+            events_after(&messages, 12, "stopped")[0]["body"]["reason"],
+            "breakpoint"
+        );
+        assert_eq!(
+            events_after(&messages, 13, "output")[0]["body"]["output"],
+            "12\n"
+        );
+        assert_eq!(events_after(&messages, 13, "terminated").len(), 1);
+        assert!(response(&messages, 14)["success"].as_bool().unwrap());
+    }
+
+    #[test]
+    fn test_breakpoints_en_modulo_importado() {
+        let path = write_program(
+            "modulos",
+            "importar \"lib.nv\";\nentero r = lib_dividir(4, 2);\nimprimir(r);\n",
+        );
+        let lib = Path::new(&path).with_file_name("lib.nv");
+        fs::write(
+            &lib,
+            "funcion entero dividir(entero a, entero b) {\n    entero c = a + b;\n    imprimir(c);\n    retornar a / b;\n}\n",
+        )
+        .unwrap();
+        let lib = lib.to_string_lossy().into_owned();
+        let otro = Path::new(&path).with_file_name("otro.nv");
+        let messages = drive(&[
+            ("initialize", json!({ "adapterID": "lumen" })),
+            (
+                "setBreakpoints",
+                json!({ "source": { "path": lib }, "breakpoints": [{ "line": 3 }] }),
+            ),
+            ("launch", json!({ "program": path })),
+            (
+                "setBreakpoints",
+                json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }] }),
+            ),
+            (
+                "setBreakpoints",
+                json!({ "source": { "path": otro }, "breakpoints": [{ "line": 1 }] }),
+            ),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("continue", json!({ "threadId": 1 })),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("disconnect", json!({})),
+        ]);
+        assert_eq!(
+            response(&messages, 4)["body"]["breakpoints"][0]["verified"],
+            true
+        );
+        assert_eq!(
+            response(&messages, 5)["body"]["breakpoints"][0]["verified"],
+            false
+        );
+
+        // La línea 3 del módulo para antes que la 3 del principal.
+        assert_eq!(
+            events_after(&messages, 6, "stopped")[0]["body"]["reason"],
+            "breakpoint"
+        );
+        let frames = &response(&messages, 7)["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "lib_dividir");
+        assert_eq!(frames[0]["line"], 3);
+        assert_eq!(frames[0]["source"]["name"], "lib.nv");
+        assert_eq!(
+            frames[0]["source"]["path"].as_str(),
+            Some(normalize(&lib).as_str())
+        );
+        assert_eq!(frames[1]["name"], "(nivel superior)");
+        assert_eq!(frames[1]["line"], 2);
+        assert_eq!(frames[1]["source"]["path"], path);
+
+        assert_eq!(
+            events_after(&messages, 8, "output")[0]["body"]["output"],
+            "6\n"
+        );
+        let frames = &response(&messages, 9)["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "(nivel superior)");
+        assert_eq!(frames[0]["line"], 3);
+        assert_eq!(frames[0]["source"]["name"], "prog.nv");
+    }
+
+    #[test]
+    fn test_variables_y_evaluate() {
+        let path = write_program("vars", PROGRAMA);
+        let messages = drive(&[
+            ("initialize", json!({})),
+            ("launch", json!({ "program": path })),
+            (
+                "setBreakpoints",
+                json!({ "source": { "path": path }, "breakpoints": [{ "line": 10 }] }),
+            ),
+            ("configurationDone", json!({})),
+            ("scopes", json!({ "frameId": 0 })),
+            ("variables", json!({ "variablesReference": 1 })),
+            ("evaluate", json!({ "expression": "p.y", "frameId": 0 })),
+            ("evaluate", json!({ "expression": "xs[2]", "frameId": 0 })),
+            ("evaluate", json!({ "expression": "xs", "frameId": 0 })),
+            // La referencia de `xs` que dio la petición de variables.
+            ("variables", json!({ "variablesReference": 3 })),
+            ("evaluate", json!({ "expression": "a +", "frameId": 0 })),
+        ]);
+        assert_eq!(
+            response(&messages, 5)["body"]["scopes"][0]["name"],
+            "Globales"
+        );
+        let vars = response(&messages, 6)["body"]["variables"]
+            .as_array()
+            .unwrap();
+        let names: Vec<&str> = vars.iter().map(|v| v["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["a", "p", "xs"]);
+        assert_eq!(vars[0]["value"], "6");
+        assert_eq!(vars[0]["type"], "entero");
+        assert!(vars[1]["variablesReference"].as_i64().unwrap() > 1);
+
+        assert_eq!(response(&messages, 7)["body"]["result"], "2");
+        assert_eq!(response(&messages, 8)["body"]["result"], "3");
+        assert!(response(&messages, 9)["body"]["variablesReference"].as_i64() > Some(0));
+        let items = response(&messages, 10)["body"]["variables"]
+            .as_array()
+            .unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1]["name"], "[1]");
+        assert!(!response(&messages, 11)["success"].as_bool().unwrap());
+    }
+
+    #[test]
+    fn test_error_de_ejecucion_detiene_con_excepcion() {
+        let path = write_program(
+            "error",
+            "funcion entero f(entero a) {\n    retornar 1 / a;\n}\nimprimir(f(0));\n",
+        );
+        let messages = drive(&[
+            ("initialize", json!({})),
+            ("launch", json!({ "program": path })),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("continue", json!({ "threadId": 1 })),
+        ]);
+        let stopped = events_after(&messages, 3, "stopped");
+        assert_eq!(stopped[0]["body"]["reason"], "exception");
+        let stderr = events_after(&messages, 3, "output");
+        assert!(stderr[0]["body"]["output"]
+            .as_str()
+            .unwrap()
+            .contains("prog.nv:2:5"));
+        assert_eq!(top_line(&messages, 4), 2);
+        let exited = events_after(&messages, 5, "exited");
+        assert_eq!(exited[0]["body"]["exitCode"], 1);
+    }
+
+    #[test]
+    fn test_launch_con_error_de_compilacion() {
+        let path = write_program("compila", "imprimir(y);\n");
+        let messages = drive(&[
+            ("initialize", json!({})),
+            ("launch", json!({ "program": path })),
+            ("stackTrace", json!({ "threadId": 1 })),
+        ]);
+        let launch = response(&messages, 2);
+        assert_eq!(launch["success"], false);
+        assert!(launch["message"].as_str().unwrap().contains("E033"));
+        assert!(events_after(&messages, 2, "initialized").is_empty());
+        assert_eq!(response(&messages, 3)["success"], false);
+    }
+
+    #[test]
+    fn test_stop_on_entry() {
+        let path = write_program("entrada", PROGRAMA);
+        let messages = drive(&[
+            ("initialize", json!({})),
+            ("launch", json!({ "program": path, "stopOnEntry": true })),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("stepIn", json!({ "threadId": 1 })),
+            ("stackTrace", json!({ "threadId": 1 })),
+        ]);
+        assert_eq!(
+            events_after(&messages, 3, "stopped")[0]["body"]["reason"],
+            "entry"
+        );
+        assert_eq!(top_line(&messages, 4), 6);
+        assert_eq!(top_line(&messages, 6), 3);
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("p.x[0][\"k\"]"),
+            Some((
+                "p".to_string(),
+                vec!["x".to_string(), "[0]".to_string(), "[\"k\"]".to_string()]
+            ))
+        );
+        assert_eq!(
+            parse_path("t.0"),
+            Some(("t".to_string(), vec!["0".to_string()]))
+        );
+        assert_eq!(parse_path("a + 1"), None);
+        assert_eq!(parse_path("1x"), None);
+    }
+}
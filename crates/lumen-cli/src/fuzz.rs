@@ -0,0 +1,1130 @@
+//! `lumen fuzz`: fuzzing guiado por cobertura de las funciones `fuzz_*`.
+//!
+//! Cada función objetivo recibe entradas generadas a partir de los tipos de
+//! sus parámetros, o mutaciones de entradas del corpus. Una entrada entra al
+//! corpus si hace tomar a algún `JmpIf` un desenlace que no se había visto.
+//! Los fallos se minimizan y se guardan como un `.nv` que los reproduce.
+//!
+//! El corpus vive en disco junto al archivo (`<nombre>.fuzz/corpus/<función>/`),
+//! una llamada LÚMEN por archivo, y se retoma en la siguiente ejecución.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+
+use lumen_codegen::{Codegen, Instruction, Opcode};
+use lumen_ir::IRBuilder;
+use lumen_lexer::Lexer;
+use lumen_parser::ast::{BinOp, Decl, DeclOrStmt, Expr, Stmt, Type, UnOp};
+use lumen_parser::Parser;
+use lumen_sema::{ModuleLoader, SemanticAnalyzer};
+use lumen_vm::{SandboxViolation, Value, VmError, VmLimits, VM};
+
+/// Iteraciones por función si no se da `--iteraciones` ni `--tiempo`.
+const DEFAULT_ITERATIONS: u64 = 10_000;
+/// Instrucciones por ejecución antes de considerar la entrada un cuelgue.
+const INSTRUCTION_BUDGET: usize = 1_000_000;
+/// Ejecuciones máximas dedicadas a minimizar cada fallo.
+const MINIMIZE_BUDGET: usize = 2_000;
+/// Profundidad máxima de listas/estructuras anidadas al generar.
+const MAX_DEPTH: usize = 3;
+
+#[derive(Debug, Clone, Default)]
+pub struct FuzzConfig {
+    pub iterations: Option<u64>,
+    pub seconds: Option<u64>,
+    pub seed: Option<u64>,
+    pub out_dir: Option<PathBuf>,
+}
+
+/// Forma de un parámetro, resuelta desde su tipo declarado.
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    Int,
+    /// `numero`: acepta enteros y decimales.
+    Number,
+    Float,
+    Str,
+    Bool,
+    List(Box<Shape>),
+    Struct(String, Vec<(String, Shape)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Input {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    List(Vec<Input>),
+    Struct(String, Vec<(String, Input)>),
+}
+
+struct Target {
+    name: String,
+    params: Vec<Shape>,
+}
+
+/// Resultado de ejecutar una entrada.
+struct Outcome {
+    coverage: HashSet<(usize, bool)>,
+    /// Mensaje y función donde ocurrió, si la ejecución falló.
+    failure: Option<Failure>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Failure {
+    message: String,
+    function: String,
+}
+
+impl Failure {
+    /// Clave para deduplicar: el mismo error en la misma función, sin los
+    /// números concretos que dependen de la entrada.
+    fn key(&self) -> String {
+        let message: String = self
+            .message
+            .chars()
+            .map(|c| if c.is_ascii_digit() { '#' } else { c })
+            .collect();
+        format!("{}@{}", message, self.function)
+    }
+}
+
+/// xorshift64*: suficiente para fuzzing y reproducible con `--semilla`.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            0
+        } else {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn chance(&mut self, one_in: usize) -> bool {
+        self.below(one_in) == 0
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+const INTERESTING_INTS: &[i64] = &[
+    0,
+    1,
+    -1,
+    2,
+    10,
+    16,
+    100,
+    127,
+    -128,
+    255,
+    256,
+    1000,
+    65535,
+    i32::MAX as i64,
+    i32::MIN as i64,
+    i64::MAX,
+    i64::MIN,
+];
+
+const INTERESTING_FLOATS: &[f64] = &[0.0, 1.0, -1.0, 0.5, -0.5, 0.1, 1e9, -1e9, 1e-9];
+
+const INTERESTING_STRS: &[&str] = &[
+    "",
+    " ",
+    "0",
+    "-1",
+    "a",
+    "ñandú",
+    "😀",
+    "\n",
+    "\"",
+    "\\",
+    "%s",
+    "null",
+    "verdadero",
+    "{}",
+    "[]",
+];
+
+const INTERESTING_CHARS: &[char] = &[
+    'a', 'z', 'A', '0', '9', ' ', '-', '.', ',', ':', '"', '\\', '\n', '\t', 'ñ', 'é', '€', '😀',
+];
+
+pub fn run_fuzz(path: &str, lib_dirs: &[PathBuf], config: &FuzzConfig) {
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error al leer {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let mut loader = ModuleLoader::new(lib_dirs.to_vec());
+    let mut program = match loader.resolve_imports(&source, Path::new(path)) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Error imports en fuzzer: {:?}", e);
+            process::exit(1);
+        }
+    };
+    let errors = SemanticAnalyzer::new().analyze(&mut program);
+    if !errors.is_empty() {
+        super::show_sema_errors(&errors, &source, path);
+        process::exit(1);
+    }
+
+    let (targets, skipped) = find_targets(&program);
+    for (name, reason) in &skipped {
+        eprintln!("  ⚠ Se omite '{}': {}", name, reason);
+    }
+    if targets.is_empty() {
+        eprintln!(
+            "Error: {} no define funciones `fuzz_*` con parámetros soportados",
+            path
+        );
+        eprintln!(
+            "Tipos soportados: entero, numero, decimal, texto, booleano, listas y estructuras"
+        );
+        process::exit(1);
+    }
+
+    let ir = IRBuilder::new().build(&program);
+    let (bytecode, _) = Codegen::new().generate(&ir);
+    let branches = bytecode
+        .instructions
+        .iter()
+        .filter(|i| matches!(i, Instruction::WithIdx(Opcode::JmpIf, _)))
+        .count()
+        * 2;
+
+    let file = Path::new(path);
+    let out_dir = config
+        .out_dir
+        .clone()
+        .unwrap_or_else(|| default_out_dir(file));
+    let seed = config.seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(1)
+    });
+    let iterations = match (config.iterations, config.seconds) {
+        (None, None) => Some(DEFAULT_ITERATIONS),
+        (iterations, _) => iterations,
+    };
+    let deadline = config.seconds.map(Duration::from_secs);
+
+    println!();
+    println!("  🧪 FUZZING GUIADO POR COBERTURA: {}", path);
+    println!("  ═════════════════════════════════════════════════════════════");
+    println!(
+        "  • Semilla       : {} (repite con --semilla {})",
+        seed, seed
+    );
+    println!("  • Directorio    : {}", out_dir.display());
+    match (iterations, deadline) {
+        (Some(n), Some(d)) => println!(
+            "  • Presupuesto   : {} iteraciones o {}s por función",
+            n,
+            d.as_secs()
+        ),
+        (Some(n), None) => println!("  • Presupuesto   : {} iteraciones por función", n),
+        (None, Some(d)) => println!("  • Presupuesto   : {}s por función", d.as_secs()),
+        (None, None) => unreachable!(),
+    }
+    println!();
+
+    let dict = Dictionary::from_constants(&bytecode.ints, &bytecode.strings);
+    let mut vm = VM::new(bytecode);
+    vm.enable_coverage();
+    vm.set_limits(VmLimits {
+        max_instructions: Some(INSTRUCTION_BUDGET),
+        ..Default::default()
+    });
+
+    let mut rng = Rng::new(seed);
+    let mut total_crashes = 0usize;
+    for target in &targets {
+        let mut fuzzer = Fuzzer {
+            vm: &mut vm,
+            dict: &dict,
+            target,
+            corpus_dir: out_dir.join("corpus").join(&target.name),
+            crash_dir: out_dir.join("crashes"),
+            corpus: Vec::new(),
+            seen: HashSet::new(),
+            crashes: HashMap::new(),
+            executions: 0,
+        };
+        fuzzer.run(&mut rng, iterations, deadline, file, &out_dir);
+        println!(
+            "  ✓ {}: {} ejecuciones, {} de {} desenlaces de rama del programa, corpus {}, {} fallo(s)",
+            target.name,
+            fuzzer.executions,
+            fuzzer.seen.len(),
+            branches,
+            fuzzer.corpus.len(),
+            fuzzer.crashes.len()
+        );
+        total_crashes += fuzzer.crashes.len();
+    }
+    println!("  ═════════════════════════════════════════════════════════════");
+    if total_crashes > 0 {
+        println!(
+            "  ✗ {} fallo(s) guardados en {}",
+            total_crashes,
+            out_dir.join("crashes").display()
+        );
+        process::exit(1);
+    }
+    println!("  Sin fallos.\n");
+}
+
+fn default_out_dir(file: &Path) -> PathBuf {
+    let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("fuzz");
+    file.with_file_name(format!("{}.fuzz", stem))
+}
+
+/// Funciones `fuzz_*` del archivo y las omitidas por tener parámetros que no
+/// se saben generar.
+fn find_targets(program: &[DeclOrStmt]) -> (Vec<Target>, Vec<(String, String)>) {
+    let structs: HashMap<&str, Vec<(String, Type)>> = program
+        .iter()
+        .filter_map(|d| match d {
+            DeclOrStmt::Decl(Decl::Struct { name, fields, .. }) => Some((
+                name.as_str(),
+                fields
+                    .iter()
+                    .map(|f| (f.name.clone(), f.field_type.clone()))
+                    .collect(),
+            )),
+            _ => None,
+        })
+        .collect();
+    let mut targets = Vec::new();
+    let mut skipped = Vec::new();
+    for decl in program {
+        let DeclOrStmt::Decl(Decl::Function { name, params, .. }) = decl else {
+            continue;
+        };
+        if !name.starts_with("fuzz_") {
+            continue;
+        }
+        let shapes: Result<Vec<Shape>, String> = params
+            .iter()
+            .map(|p| shape_of(&p.param_type, &structs, &mut Vec::new()))
+            .collect();
+        match shapes {
+            Ok(params) => targets.push(Target {
+                name: name.clone(),
+                params,
+            }),
+            Err(reason) => skipped.push((name.clone(), reason)),
+        }
+    }
+    (targets, skipped)
+}
+
+fn shape_of(
+    ty: &Type,
+    structs: &HashMap<&str, Vec<(String, Type)>>,
+    visiting: &mut Vec<String>,
+) -> Result<Shape, String> {
+    Ok(match ty {
+        Type::Entero => Shape::Int,
+        Type::Numero => Shape::Number,
+        Type::Decimal => Shape::Float,
+        Type::Texto => Shape::Str,
+        Type::Booleano => Shape::Bool,
+        Type::Lista(inner) => Shape::List(Box::new(shape_of(inner, structs, visiting)?)),
+        Type::Prestado { inner, .. } | Type::Dueno(inner) => shape_of(inner, structs, visiting)?,
+        Type::Struct(name) => {
+            let fields = structs
+                .get(name.as_str())
+                .ok_or_else(|| format!("tipo '{}' desconocido", name))?;
+            if visiting.contains(name) {
+                return Err(format!("la estructura '{}' es recursiva", name));
+            }
+            visiting.push(name.clone());
+            let fields = fields
+                .iter()
+                .map(|(f, t)| Ok((f.clone(), shape_of(t, structs, visiting)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            visiting.pop();
+            Shape::Struct(name.clone(), fields)
+        }
+        other => return Err(format!("parámetro de tipo {:?} no soportado", other)),
+    })
+}
+
+/// Valores con los que generar y mutar: los casos límite de siempre más las
+/// constantes del programa (y sus vecinos), que suelen ser las que deciden
+/// las comparaciones.
+struct Dictionary {
+    ints: Vec<i64>,
+    strs: Vec<String>,
+}
+
+impl Dictionary {
+    fn from_constants(ints: &[i64], strs: &[String]) -> Self {
+        let mut dict = Dictionary {
+            ints: INTERESTING_INTS.to_vec(),
+            strs: INTERESTING_STRS.iter().map(|s| s.to_string()).collect(),
+        };
+        for &n in ints {
+            for k in [n.wrapping_sub(1), n, n.wrapping_add(1)] {
+                if !dict.ints.contains(&k) {
+                    dict.ints.push(k);
+                }
+            }
+        }
+        for s in strs {
+            if s.chars().count() <= 64 && !dict.strs.contains(s) {
+                dict.strs.push(s.clone());
+            }
+        }
+        dict
+    }
+}
+
+struct Fuzzer<'a> {
+    vm: &'a mut VM,
+    dict: &'a Dictionary,
+    target: &'a Target,
+    corpus_dir: PathBuf,
+    crash_dir: PathBuf,
+    corpus: Vec<Vec<Input>>,
+    /// Desenlaces de rama vistos por cualquier entrada.
+    seen: HashSet<(usize, bool)>,
+    /// Fallos únicos por clave, con su entrada minimizada.
+    crashes: HashMap<String, Vec<Input>>,
+    executions: u64,
+}
+
+impl Fuzzer<'_> {
+    fn run(
+        &mut self,
+        rng: &mut Rng,
+        iterations: Option<u64>,
+        deadline: Option<Duration>,
+        file: &Path,
+        out_dir: &Path,
+    ) {
+        let start = Instant::now();
+        let mut seeds = self.load_corpus();
+        seeds.push(self.target.params.iter().map(minimal).collect());
+        let loaded = seeds.len() - 1;
+        for _ in 0..8 {
+            seeds.push(self.generate_input(rng));
+        }
+        for (n, input) in seeds.into_iter().enumerate() {
+            // Lo leído del disco ya está guardado.
+            self.try_input(input, n >= loaded, file, out_dir);
+        }
+
+        let mut last_report = Instant::now();
+        let mut iteration = 0u64;
+        loop {
+            if iterations.is_some_and(|n| iteration >= n)
+                || deadline.is_some_and(|d| start.elapsed() >= d)
+            {
+                break;
+            }
+            iteration += 1;
+            let input = if self.corpus.is_empty() || rng.chance(16) {
+                self.generate_input(rng)
+            } else {
+                let mut input = rng.pick(&self.corpus).clone();
+                for _ in 0..=rng.below(3) {
+                    self.mutate_input(&mut input, rng);
+                }
+                input
+            };
+            self.try_input(input, true, file, out_dir);
+            if last_report.elapsed() >= Duration::from_secs(2) {
+                last_report = Instant::now();
+                let rate = self.executions as f64 / start.elapsed().as_secs_f64().max(1e-9);
+                println!(
+                    "    #{:<8} ramas: {:<5} corpus: {:<5} fallos: {:<3} ejec/s: {:.0}",
+                    self.executions,
+                    self.seen.len(),
+                    self.corpus.len(),
+                    self.crashes.len(),
+                    rate
+                );
+            }
+        }
+    }
+
+    fn generate_input(&self, rng: &mut Rng) -> Vec<Input> {
+        self.target
+            .params
+            .iter()
+            .map(|s| generate(s, self.dict, rng, 0))
+            .collect()
+    }
+
+    fn mutate_input(&self, input: &mut [Input], rng: &mut Rng) {
+        if input.is_empty() {
+            return;
+        }
+        let i = rng.below(input.len());
+        let shape = &self.target.params[i];
+        // Cruce: toma el argumento de otra entrada del corpus.
+        if self.corpus.len() > 1 && rng.chance(8) {
+            input[i] = rng.pick(&self.corpus)[i].clone();
+            return;
+        }
+        mutate(&mut input[i], shape, self.dict, rng);
+    }
+
+    /// Ejecuta la entrada; la guarda si aporta cobertura y registra el fallo
+    /// si lo provoca.
+    fn try_input(&mut self, input: Vec<Input>, save: bool, file: &Path, out_dir: &Path) {
+        let outcome = self.execute(&input);
+        if let Some(failure) = outcome.failure {
+            let key = failure.key();
+            if !self.crashes.contains_key(&key) {
+                let minimized = self.minimize(input, &key);
+                self.save_crash(&minimized, &failure, file, out_dir);
+                println!(
+                    "    ✗ fallo en {}: {}",
+                    self.target.name,
+                    call_source(&self.target.name, &minimized)
+                );
+                println!("      {}", failure.message);
+                self.crashes.insert(key, minimized);
+            }
+            return;
+        }
+        let new_branches = outcome.coverage.difference(&self.seen).count();
+        if new_branches > 0 || self.corpus.is_empty() {
+            self.seen.extend(outcome.coverage);
+            if save {
+                self.save_corpus_entry(&input);
+            }
+            self.corpus.push(input);
+        }
+    }
+
+    fn execute(&mut self, input: &[Input]) -> Outcome {
+        self.executions += 1;
+        let vm = &mut *self.vm;
+        vm.reset();
+        let mut result = vm.run().map(|_| Value::Void);
+        if result.is_ok() {
+            let args = input.iter().map(to_value).collect();
+            result = vm.run_function(&self.target.name, args);
+        }
+        let failure = result.err().map(|e| Failure {
+            message: if matches!(e, VmError::Sandbox(SandboxViolation::Instructions(_))) {
+                format!(
+                    "Posible bucle infinito: más de {} instrucciones",
+                    INSTRUCTION_BUDGET
+                )
+            } else {
+                e.to_string()
+            },
+            function: vm
+                .call_stack()
+                .last()
+                .map(|f| f.func_name.clone())
+                .unwrap_or_else(|| self.target.name.clone()),
+        });
+        Outcome {
+            coverage: vm.coverage().cloned().unwrap_or_default(),
+            failure,
+        }
+    }
+
+    /// Reduce la entrada mientras siga provocando el mismo fallo.
+    fn minimize(&mut self, mut input: Vec<Input>, key: &str) -> Vec<Input> {
+        let mut budget = MINIMIZE_BUDGET;
+        'outer: loop {
+            for i in 0..input.len() {
+                for candidate in shrink(&input[i]) {
+                    if budget == 0 {
+                        break 'outer;
+                    }
+                    budget -= 1;
+                    let mut attempt = input.clone();
+                    attempt[i] = candidate;
+                    let same = self
+                        .execute(&attempt)
+                        .failure
+                        .is_some_and(|f| f.key() == key);
+                    if same {
+                        input = attempt;
+                        continue 'outer;
+                    }
+                }
+            }
+            break;
+        }
+        input
+    }
+
+    fn load_corpus(&self) -> Vec<Vec<Input>> {
+        let Ok(entries) = fs::read_dir(&self.corpus_dir) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+        paths.sort();
+        paths
+            .iter()
+            .filter_map(|p| fs::read_to_string(p).ok())
+            .filter_map(|src| parse_call(&src, &self.target.name, &self.target.params))
+            .collect()
+    }
+
+    fn save_corpus_entry(&self, input: &[Input]) {
+        let call = call_source(&self.target.name, input);
+        let path = self
+            .corpus_dir
+            .join(format!("{:016x}.nv", fnv1a(call.as_bytes())));
+        if let Err(e) =
+            fs::create_dir_all(&self.corpus_dir).and_then(|_| fs::write(&path, call + "\n"))
+        {
+            eprintln!("  ⚠ No se pudo guardar {}: {}", path.display(), e);
+        }
+    }
+
+    fn save_crash(&self, input: &[Input], failure: &Failure, file: &Path, out_dir: &Path) {
+        let n = self.crashes.len() + 1;
+        let path = self
+            .crash_dir
+            .join(format!("{}_{}.nv", self.target.name, n));
+        let text = reproducer(file, out_dir, &self.target.name, input, &failure.message);
+        if let Err(e) = fs::create_dir_all(&self.crash_dir).and_then(|_| fs::write(&path, text)) {
+            eprintln!("  ⚠ No se pudo guardar {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// `.nv` que importa el archivo fuzzeado y repite la llamada que falló.
+/// Los módulos importados llevan el nombre del archivo como prefijo.
+fn reproducer(file: &Path, out_dir: &Path, target: &str, input: &[Input], message: &str) -> String {
+    let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("fuzz");
+    let file_name = file.file_name().and_then(|s| s.to_str()).unwrap_or("");
+    // Los fallos viven en <out_dir>/crashes/; si out_dir está junto al archivo
+    // la ruta relativa es corta, si no se usa la absoluta.
+    let import = if out_dir.parent() == file.parent() {
+        format!("../../{}", file_name)
+    } else {
+        fs::canonicalize(file)
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| file.display().to_string())
+    };
+    format!(
+        "// Fallo encontrado por `lumen fuzz` en {}\n// {}\nimportar \"{}\";\n\n{}\n",
+        target,
+        message.replace('\n', " "),
+        import,
+        call_source(&format!("{}_{}", stem, target), input)
+    )
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// ── Generación y mutación ─────────────────────────────
+
+fn minimal(shape: &Shape) -> Input {
+    match shape {
+        Shape::Int | Shape::Number => Input::Int(0),
+        Shape::Float => Input::Float(0.0),
+        Shape::Str => Input::Str(String::new()),
+        Shape::Bool => Input::Bool(false),
+        Shape::List(_) => Input::List(Vec::new()),
+        Shape::Struct(name, fields) => Input::Struct(
+            name.clone(),
+            fields
+                .iter()
+                .map(|(f, s)| (f.clone(), minimal(s)))
+                .collect(),
+        ),
+    }
+}
+
+fn generate(shape: &Shape, dict: &Dictionary, rng: &mut Rng, depth: usize) -> Input {
+    match shape {
+        Shape::Int => Input::Int(random_int(dict, rng)),
+        Shape::Number if rng.chance(2) => Input::Int(random_int(dict, rng)),
+        Shape::Number | Shape::Float => Input::Float(random_float(rng)),
+        Shape::Str => {
+            if rng.chance(2) {
+                Input::Str(rng.pick(&dict.strs).clone())
+            } else {
+                Input::Str((0..rng.below(12)).map(|_| random_char(rng)).collect())
+            }
+        }
+        Shape::Bool => Input::Bool(rng.chance(2)),
+        Shape::List(inner) => {
+            let len = if depth >= MAX_DEPTH { 0 } else { rng.below(6) };
+            Input::List(
+                (0..len)
+                    .map(|_| generate(inner, dict, rng, depth + 1))
+                    .collect(),
+            )
+        }
+        Shape::Struct(name, fields) => Input::Struct(
+            name.clone(),
+            fields
+                .iter()
+                .map(|(f, s)| (f.clone(), generate(s, dict, rng, depth + 1)))
+                .collect(),
+        ),
+    }
+}
+
+fn random_int(dict: &Dictionary, rng: &mut Rng) -> i64 {
+    match rng.below(3) {
+        0 => *rng.pick(&dict.ints),
+        1 => rng.below(201) as i64 - 100,
+        _ => rng.next() as i64,
+    }
+}
+
+fn random_float(rng: &mut Rng) -> f64 {
+    if rng.chance(2) {
+        *rng.pick(INTERESTING_FLOATS)
+    } else {
+        // Finito y sin exponente: el literal se puede volver a escribir en LÚMEN.
+        (rng.next() % 2_000_000) as f64 / 1000.0 - 1000.0
+    }
+}
+
+fn random_char(rng: &mut Rng) -> char {
+    if rng.chance(3) {
+        *rng.pick(INTERESTING_CHARS)
+    } else {
+        (b' ' + rng.below(95) as u8) as char
+    }
+}
+
+fn mutate(input: &mut Input, shape: &Shape, dict: &Dictionary, rng: &mut Rng) {
+    match input {
+        Input::Int(n) => {
+            *n = match rng.below(5) {
+                0 => n.wrapping_add(rng.below(16) as i64 + 1),
+                1 => n.wrapping_sub(rng.below(16) as i64 + 1),
+                2 => *n ^ (1i64 << rng.below(64)),
+                3 => n.wrapping_neg(),
+                _ => *rng.pick(&dict.ints),
+            }
+        }
+        Input::Float(x) => {
+            *x = match rng.below(4) {
+                0 => *x + (rng.below(200) as f64 - 100.0) / 10.0,
+                1 => *x * 2.0,
+                2 => -*x,
+                _ => *rng.pick(INTERESTING_FLOATS),
+            };
+            if !x.is_finite() || x.abs() > 1e15 {
+                *x = 0.0;
+            }
+        }
+        Input::Str(s) => {
+            let mut chars: Vec<char> = s.chars().collect();
+            match rng.below(6) {
+                0 => chars.insert(rng.below(chars.len() + 1), random_char(rng)),
+                1 if !chars.is_empty() => {
+                    chars.remove(rng.below(chars.len()));
+                }
+                2 if !chars.is_empty() => {
+                    let i = rng.below(chars.len());
+                    chars[i] = random_char(rng);
+                }
+                3 if !chars.is_empty() && chars.len() < 256 => {
+                    let from = rng.below(chars.len());
+                    let to = from + 1 + rng.below(chars.len() - from);
+                    let piece: Vec<char> = chars[from..to].to_vec();
+                    let at = rng.below(chars.len() + 1);
+                    chars.splice(at..at, piece);
+                }
+                4 => chars.truncate(rng.below(chars.len() + 1)),
+                _ => chars = rng.pick(&dict.strs).chars().collect(),
+            }
+            *s = chars.into_iter().collect();
+        }
+        Input::Bool(b) => *b = !*b,
+        Input::List(items) => {
+            let inner = match shape {
+                Shape::List(inner) => inner.as_ref(),
+                _ => return,
+            };
+            match rng.below(4) {
+                0 if items.len() < 64 => {
+                    items.insert(rng.below(items.len() + 1), generate(inner, dict, rng, 1))
+                }
+                1 if !items.is_empty() => {
+                    items.remove(rng.below(items.len()));
+                }
+                2 if !items.is_empty() && items.len() < 64 => {
+                    let item = items[rng.below(items.len())].clone();
+                    items.push(item);
+                }
+                _ if !items.is_empty() => {
+                    let i = rng.below(items.len());
+                    mutate(&mut items[i], inner, dict, rng);
+                }
+                _ => items.push(generate(inner, dict, rng, 1)),
+            }
+        }
+        Input::Struct(_, fields) => {
+            let Shape::Struct(_, shapes) = shape else {
+                return;
+            };
+            if !fields.is_empty() {
+                let i = rng.below(fields.len());
+                mutate(&mut fields[i].1, &shapes[i].1, dict, rng);
+            }
+        }
+    }
+}
+
+/// Versiones más simples de una entrada, de la más a la menos agresiva.
+fn shrink(input: &Input) -> Vec<Input> {
+    match input {
+        Input::Int(0) => Vec::new(),
+        Input::Int(n) => {
+            let mut out = vec![Input::Int(0), Input::Int(n / 2)];
+            out.push(Input::Int(n - n.signum()));
+            out
+        }
+        Input::Float(x) if *x == 0.0 => Vec::new(),
+        Input::Float(x) => vec![
+            Input::Float(0.0),
+            Input::Float(x.trunc()),
+            Input::Float(x / 2.0),
+        ]
+        .into_iter()
+        .filter(|c| c != input)
+        .collect(),
+        Input::Str(s) if s.is_empty() => Vec::new(),
+        Input::Str(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            let mut out = vec![Input::Str(String::new())];
+            let half = chars.len() / 2;
+            if half > 0 {
+                out.push(Input::Str(chars[..half].iter().collect()));
+                out.push(Input::Str(chars[half..].iter().collect()));
+            }
+            for i in 0..chars.len().min(32) {
+                let mut rest = chars.clone();
+                rest.remove(i);
+                out.push(Input::Str(rest.into_iter().collect()));
+            }
+            out
+        }
+        Input::Bool(true) => vec![Input::Bool(false)],
+        Input::Bool(false) => Vec::new(),
+        Input::List(items) if items.is_empty() => Vec::new(),
+        Input::List(items) => {
+            let mut out = vec![Input::List(Vec::new())];
+            let half = items.len() / 2;
+            if half > 0 {
+                out.push(Input::List(items[..half].to_vec()));
+                out.push(Input::List(items[half..].to_vec()));
+            }
+            for i in 0..items.len() {
+                let mut rest = items.clone();
+                rest.remove(i);
+                out.push(Input::List(rest));
+            }
+            for (i, item) in items.iter().enumerate() {
+                for smaller in shrink(item) {
+                    let mut copy = items.clone();
+                    copy[i] = smaller;
+                    out.push(Input::List(copy));
+                }
+            }
+            out
+        }
+        Input::Struct(name, fields) => {
+            let mut out = Vec::new();
+            for (i, (_, value)) in fields.iter().enumerate() {
+                for smaller in shrink(value) {
+                    let mut copy = fields.clone();
+                    copy[i].1 = smaller;
+                    out.push(Input::Struct(name.clone(), copy));
+                }
+            }
+            out
+        }
+    }
+}
+
+// ── Conversión a valores y a código LÚMEN ─────────────
+
+fn to_value(input: &Input) -> Value {
+    match input {
+        Input::Int(n) => Value::Int(*n),
+        Input::Float(x) => Value::Float(*x),
+        Input::Str(s) => Value::str(s.as_str()),
+        Input::Bool(b) => Value::Bool(*b),
+        Input::List(items) => Value::arr(items.iter().map(to_value).collect()),
+        Input::Struct(name, fields) => Value::Struct {
+            name: name.clone(),
+            fields: fields
+                .iter()
+                .map(|(f, v)| (f.clone(), to_value(v)))
+                .collect(),
+        },
+    }
+}
+
+fn literal(input: &Input) -> String {
+    match input {
+        // `-9223372036854775808` no cabe como literal positivo.
+        Input::Int(i64::MIN) => "(-9223372036854775807 - 1)".to_string(),
+        Input::Int(n) => n.to_string(),
+        Input::Float(x) => {
+            let s = x.to_string();
+            if s.contains('.') {
+                s
+            } else {
+                format!("{}.0", s)
+            }
+        }
+        Input::Str(s) => {
+            let mut out = String::from("\"");
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\t' => out.push_str("\\t"),
+                    '\r' => out.push_str("\\r"),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+        Input::Bool(true) => "verdadero".to_string(),
+        Input::Bool(false) => "falso".to_string(),
+        Input::List(items) => format!(
+            "[{}]",
+            items.iter().map(literal).collect::<Vec<_>>().join(", ")
+        ),
+        Input::Struct(name, fields) => format!(
+            "{} {{ {} }}",
+            name,
+            fields
+                .iter()
+                .map(|(f, v)| format!("{}: {}", f, literal(v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+fn call_source(name: &str, input: &[Input]) -> String {
+    format!(
+        "{}({});",
+        name,
+        input.iter().map(literal).collect::<Vec<_>>().join(", ")
+    )
+}
+
+/// Lee una entrada del corpus: una llamada a `target` con argumentos literales
+/// compatibles con sus parámetros.
+fn parse_call(source: &str, target: &str, params: &[Shape]) -> Option<Vec<Input>> {
+    let (tokens, lex_errors) = Lexer::new(source).tokenize();
+    if !lex_errors.is_empty() {
+        return None;
+    }
+    let (program, parse_errors) = Parser::new(tokens).parse();
+    if !parse_errors.is_empty() {
+        return None;
+    }
+    program.iter().find_map(|item| {
+        let DeclOrStmt::Stmt(Stmt::Expr { expr, .. }) = item else {
+            return None;
+        };
+        let Expr::Call { callee, args, .. } = expr.as_ref() else {
+            return None;
+        };
+        if !matches!(callee.as_ref(), Expr::Ident { name, .. } if name == target)
+            || args.len() != params.len()
+        {
+            return None;
+        }
+        args.iter()
+            .zip(params)
+            .map(|(arg, shape)| from_expr(arg, shape))
+            .collect()
+    })
+}
+
+fn from_expr(expr: &Expr, shape: &Shape) -> Option<Input> {
+    match (expr, shape) {
+        (Expr::Grouping { expr, .. }, _) => from_expr(expr, shape),
+        (Expr::Int { value, .. }, Shape::Int | Shape::Number) => Some(Input::Int(*value)),
+        (Expr::Float { value, .. }, Shape::Float | Shape::Number) => Some(Input::Float(*value)),
+        (Expr::Int { value, .. }, Shape::Float) => Some(Input::Float(*value as f64)),
+        (
+            Expr::Unary {
+                op: UnOp::Negate,
+                operand,
+                ..
+            },
+            _,
+        ) => match from_expr(operand, shape)? {
+            Input::Int(n) => Some(Input::Int(n.wrapping_neg())),
+            Input::Float(x) => Some(Input::Float(-x)),
+            _ => None,
+        },
+        (
+            Expr::Binary {
+                op: BinOp::Sub,
+                left,
+                right,
+                ..
+            },
+            Shape::Int | Shape::Number,
+        ) => match (from_expr(left, shape)?, from_expr(right, shape)?) {
+            (Input::Int(a), Input::Int(b)) => Some(Input::Int(a.wrapping_sub(b))),
+            _ => None,
+        },
+        (Expr::Str { value, .. }, Shape::Str) => Some(Input::Str(value.clone())),
+        (Expr::Bool { value, .. }, Shape::Bool) => Some(Input::Bool(*value)),
+        (Expr::List { items, .. }, Shape::List(inner)) => items
+            .iter()
+            .map(|i| from_expr(i, inner))
+            .collect::<Option<Vec<_>>>()
+            .map(Input::List),
+        (
+            Expr::StructInit {
+                struct_name,
+                fields,
+                ..
+            },
+            Shape::Struct(name, shapes),
+        ) if struct_name == name => shapes
+            .iter()
+            .map(|(f, s)| {
+                let (_, e) = fields.iter().find(|(n, _)| n == f)?;
+                Some((f.clone(), from_expr(e, s)?))
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|fields| Input::Struct(name.clone(), fields)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str) -> (Vec<DeclOrStmt>, lumen_codegen::Bytecode) {
+        let (tokens, _) = Lexer::new(source).tokenize();
+        let (mut program, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(SemanticAnalyzer::new().analyze(&mut program).is_empty());
+        let ir = IRBuilder::new().build(&program);
+        (program.clone(), Codegen::new().generate(&ir).0)
+    }
+
+    #[test]
+    fn test_literales_ida_y_vuelta() {
+        let params = vec![
+            Shape::Int,
+            Shape::Float,
+            Shape::Str,
+            Shape::List(Box::new(Shape::Bool)),
+            Shape::Struct(
+                "P".to_string(),
+                vec![("x".to_string(), Shape::Int), ("s".to_string(), Shape::Str)],
+            ),
+        ];
+        let input = vec![
+            Input::Int(i64::MIN),
+            Input::Float(-2.5),
+            Input::Str("a\"b\\c\nñ😀\t".to_string()),
+            Input::List(vec![Input::Bool(true), Input::Bool(false)]),
+            Input::Struct(
+                "P".to_string(),
+                vec![
+                    ("x".to_string(), Input::Int(-7)),
+                    ("s".to_string(), Input::Str(String::new())),
+                ],
+            ),
+        ];
+        let source = call_source("fuzz_f", &input);
+        assert_eq!(parse_call(&source, "fuzz_f", &params), Some(input));
+        assert_eq!(parse_call(&source, "fuzz_g", &params), None);
+    }
+
+    #[test]
+    fn test_encuentra_y_minimiza_fallo() {
+        let (program, bytecode) = compile(
+            "funcion entero fuzz_div(entero a, texto s) {
+                si (a > 500) {
+                    si (largo(s) > 2) {
+                        retornar a / 0;
+                    }
+                }
+                retornar a;
+            }",
+        );
+        let (targets, skipped) = find_targets(&program);
+        assert!(skipped.is_empty());
+        let dir = std::env::temp_dir().join(format!("lumen_fuzz_test_{}", process::id()));
+        let dict = Dictionary::from_constants(&bytecode.ints, &bytecode.strings);
+        let mut vm = VM::new(bytecode);
+        vm.enable_coverage();
+        vm.set_limits(VmLimits {
+            max_instructions: Some(INSTRUCTION_BUDGET),
+            ..Default::default()
+        });
+        let mut fuzzer = Fuzzer {
+            vm: &mut vm,
+            dict: &dict,
+            target: &targets[0],
+            corpus_dir: dir.join("corpus"),
+            crash_dir: dir.join("crashes"),
+            corpus: Vec::new(),
+            seen: HashSet::new(),
+            crashes: HashMap::new(),
+            executions: 0,
+        };
+        fuzzer.run(
+            &mut Rng::new(7),
+            Some(20_000),
+            None,
+            Path::new("div.nv"),
+            &dir,
+        );
+        let crashes: Vec<_> = fuzzer.crashes.values().cloned().collect();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(crashes.len(), 1);
+        // Minimizado: el menor entero que pasa la condición y tres caracteres.
+        match crashes[0].as_slice() {
+            [Input::Int(501), Input::Str(s)] => assert_eq!(s.chars().count(), 3),
+            other => panic!("entrada no minimizada: {:?}", other),
+        }
+    }
+}
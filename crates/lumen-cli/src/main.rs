@@ -142,83 +142,6 @@ fn run_test_ai_gen(path: &str, dest_path: &str, lib_dirs: &[PathBuf]) {
     run_source(&out_test_file, lib_dirs);
 }
 
-fn run_fuzz(path: &str, lib_dirs: &[PathBuf]) {
-    println!();
-    println!("  [1;35m╔══════════════════════════════════════════════════════════════════════════════════════╗[0m");
-    println!("  [1;35m║   🧪 LÚMEN COVERAGE-GUIDED FUZZER & CRASH DETECTOR (v2.4.6)                           ║[0m");
-    println!("  [1;35m║   Generación Automática de Mutaciones Límite, Casos Edge & Cobertura de Ramas         ║[0m");
-    println!("  [1;35m╚══════════════════════════════════════════════════════════════════════════════════════╝[0m");
-    println!();
-    println!("  • Archivo Objetivo : {}", path);
-    println!("  • Modo de Análisis : Coverage-Guided Mutation Testing (AST & Control Flow Graph)");
-    println!("  • Límites Probados : i64::MIN/MAX, Unicode Overflows, Null-Bytes, NaN, Recursión");
-    println!();
-
-    let source = match fs::read_to_string(path) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error al leer {}: {}", path, e);
-            process::exit(1);
-        }
-    };
-
-    let total_runs = 5000;
-    println!("  [1;33m[1/3] Compilando AST y grafo de ramas ejecutables...[0m");
-    let mut loader = ModuleLoader::new(lib_dirs.to_vec());
-    let program = match loader.resolve_imports(&source, Path::new(path)) {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("Error imports en fuzzer: {:?}", e);
-            process::exit(1);
-        }
-    };
-    let mut prog = program;
-    let errors = SemanticAnalyzer::new().analyze(&mut prog);
-    if !errors.is_empty() {
-        show_sema_errors(&errors, &source, path);
-        process::exit(1);
-    }
-
-    println!(
-        "  [1;36m[2/3] Ejecutando {} iteraciones de fuzzing con mutaciones en caliente...[0m",
-        total_runs
-    );
-    println!("  ┌──────────────────────────────────────────────────────────────────────┐");
-    println!(
-        "  │  [1;32m[████████████████████████████████████████████████████████████] 100%[0m  │"
-    );
-    println!("  └──────────────────────────────────────────────────────────────────────┘");
-
-    let unique_branches = 48;
-    let coverage_pct = 97.4;
-    let execs_per_sec = 285_000;
-
-    println!();
-    println!("  [1;32m[3/3] REPORTE FINAL DE COBERTURA Y ROBUSTEZ:[0m");
-    println!("  ══════════════════════════════════════════════════════════════════════");
-    println!("  • Iteraciones Ejecutadas : {} ejecuciones", total_runs);
-    println!(
-        "  • Velocidad de Fuzzing   : ~{} ejecuciones / segundo",
-        execs_per_sec
-    );
-    println!(
-        "  • Caminos de Control (Corpus) : {} ramas únicas descubiertas",
-        unique_branches
-    );
-    println!(
-        "  • Cobertura de Ramas     : [1;32m{:.1}%[0m",
-        coverage_pct
-    );
-    println!("  • Fallos de Tipo / Crashes: [1;32m0 detectados[0m (Código 100% Blindado)");
-    println!("  • Fugas de Memoria (Leaks) : 0 bytes");
-    println!();
-    println!(
-        "  ✨ ¡Análisis de Fuzzing completado con éxito! El archivo {} es 100% seguro.
-",
-        path
-    );
-}
-
 fn detect_user_environment() -> (String, String, usize, String) {
     let username = std::env::var("USERNAME")
         .or_else(|_| std::env::var("USER"))
@@ -268,6 +191,9 @@ fn detect_user_environment() -> (String, String, usize, String) {
     (username, os_name.to_string(), cores, comp_str)
 }
 
+mod dap;
+mod fuzz;
+
 use std::env;
 use std::fs;
 use std::io::Write;
@@ -278,11 +204,11 @@ use std::thread;
 
 use lumen_codegen::{disassemble, Bytecode, Codegen};
 use lumen_ir::IRBuilder;
-use lumen_lexer::token::Span;
+use lumen_lexer::Diagnostic;
 use lumen_parser::ast::DeclOrStmt;
 use lumen_project::ProjectManifest;
-use lumen_sema::{ModuleLoader, SemanticAnalyzer};
-use lumen_vm::VM;
+use lumen_sema::{loader::canonical_path, ModuleLoader, SemanticAnalyzer};
+use lumen_vm::{StepKind, VmLimits, VM};
 
 #[allow(dead_code)]
 struct Config {
@@ -305,6 +231,9 @@ struct Config {
     port: u16,
     sanitize: bool,
     ai_gen: bool,
+    fuzz: fuzz::FuzzConfig,
+    /// Formato de salida de `check`: "texto" (por defecto) o "json".
+    format: String,
 }
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -427,7 +356,7 @@ fn print_help() {
     println!();
     println!(
         "   test <archivo.nv>           Ejecutar suite de pruebas unitarias / Run tests
-   fuzz <archivo.nv>           Fuzzing guiado por cobertura y detección de edge cases"
+   fuzz <archivo.nv>           Fuzzing guiado por cobertura de las funciones fuzz_*"
     );
     println!(
         "   bench <archivo.nv>          Ejecutar benchmark de rendimiento / Benchmark performance"
@@ -457,6 +386,7 @@ fn print_help() {
     println!("   doctor / info               Diagnosticar entorno, compiladores y stdlib");
     println!("   serve [--port <num>]        Iniciar Playground Web local (WASM + API)");
     println!("   lsp                         Iniciar servidor Language Server Protocol");
+    println!("   dap                         Iniciar adaptador Debug Adapter Protocol (VS Code)");
     println!();
     println!("  ⚙️  OPCIONES & BANDERAS / OPTIONS & FLAGS:");
     println!();
@@ -477,6 +407,11 @@ fn print_help() {
     println!("   --target <triple>           Compilación cruzada: x86_64-linux-gnu, aarch64-apple-darwin, etc.");
     println!("   -O, --opt-level <0|1|2|3>   Nivel de optimización");
     println!("   --port <puerto>             Puerto para el servidor web (por defecto: 8080)");
+    println!("   --iteraciones <n>           Iteraciones de fuzz por función (por defecto: 10000)");
+    println!("   --tiempo <segundos>         Tiempo máximo de fuzz por función");
+    println!("   --semilla <n>               Semilla del fuzzer, para repetir una ejecución");
+    println!("   --corpus <dir>              Directorio de corpus y fallos (por defecto: <archivo>.fuzz)");
+    println!("   --format <texto|json>       Formato de los diagnósticos de check (json para CI)");
     println!("   -v, --version               Mostrar versión de LÚMEN");
     println!("   -h, --help                  Mostrar esta ayuda");
     println!();
@@ -734,6 +669,22 @@ fn print_learn() {
     println!("  🌐 Web: lumen serve");
 }
 
+/// Lee el número que sigue a `flag`, o termina con error si falta o no es válido.
+fn parse_count_flag(args: &[String], i: &mut usize, flag: &str) -> u64 {
+    *i += 1;
+    match args.get(*i).map(|a| a.parse::<u64>()) {
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            eprintln!("Error: valor inválido '{}' para '{}'", args[*i], flag);
+            process::exit(1);
+        }
+        None => {
+            eprintln!("Error: falta un número después de '{}'", flag);
+            process::exit(1);
+        }
+    }
+}
+
 fn parse_args(args: &[String]) -> Config {
     let mut i = 1;
     let mut command = String::new();
@@ -755,6 +706,8 @@ fn parse_args(args: &[String]) -> Config {
     let mut port: u16 = 8080;
     let mut sanitize = false;
     let mut ai_gen = false;
+    let mut fuzz = fuzz::FuzzConfig::default();
+    let mut format = "texto".to_string();
 
     while i < args.len() {
         match args[i].as_str() {
@@ -936,6 +889,39 @@ fn parse_args(args: &[String]) -> Config {
                     process::exit(1);
                 }
             }
+            "--iteraciones" | "--iterations" => {
+                fuzz.iterations = Some(parse_count_flag(args, &mut i, "--iteraciones"));
+            }
+            "--tiempo" | "--time" => {
+                fuzz.seconds = Some(parse_count_flag(args, &mut i, "--tiempo"));
+            }
+            "--semilla" | "--seed" => {
+                fuzz.seed = Some(parse_count_flag(args, &mut i, "--semilla"));
+            }
+            "--format" | "--formato" => {
+                i += 1;
+                match args.get(i).map(String::as_str) {
+                    Some("texto" | "text") => format = "texto".to_string(),
+                    Some("json") => format = "json".to_string(),
+                    Some(f) => {
+                        eprintln!("Error: formato desconocido '{}' (usa texto o json)", f);
+                        process::exit(1);
+                    }
+                    None => {
+                        eprintln!("Error: falta el formato después de '--format'");
+                        process::exit(1);
+                    }
+                }
+            }
+            "--corpus" => {
+                i += 1;
+                if i < args.len() {
+                    fuzz.out_dir = Some(PathBuf::from(&args[i]));
+                } else {
+                    eprintln!("Error: falta un directorio después de '--corpus'");
+                    process::exit(1);
+                }
+            }
             "--port-env" => {
                 // Deprecado: use LUMEN_PORT env var
                 i += 1;
@@ -1048,6 +1034,8 @@ fn parse_args(args: &[String]) -> Config {
         port,
         sanitize,
         ai_gen,
+        fuzz,
+        format,
     }
 }
 
@@ -1092,10 +1080,10 @@ fn real_main(args: Vec<String>) {
         "fuzz" | "fuzzing" => {
             if config.file.is_empty() {
                 eprintln!("Error: falta el archivo para fuzzing");
-                eprintln!("Uso: lumen fuzz <archivo.nv>");
+                eprintln!("Uso: lumen fuzz <archivo.nv> [--iteraciones N] [--tiempo S] [--semilla N] [--corpus DIR]");
                 process::exit(1);
             }
-            run_fuzz(&config.file, &config.lib_dirs);
+            fuzz::run_fuzz(&config.file, &config.lib_dirs, &config.fuzz);
         }
         "run" => {
             if config.file.is_empty() {
@@ -1153,7 +1141,9 @@ fn real_main(args: Vec<String>) {
             } else {
                 &config.file
             };
-            if Path::new(target).is_dir() || target == "." {
+            if config.format == "json" {
+                check_json(target, &config.lib_dirs);
+            } else if Path::new(target).is_dir() || target == "." {
                 check_project(target, &config.lib_dirs);
             } else {
                 check_source(target, &config.lib_dirs);
@@ -1529,8 +1519,11 @@ fn real_main(args: Vec<String>) {
             };
             run_registry(sub, config.port);
         }
+        "dap" => {
+            dap::run_dap(&config.lib_dirs);
+        }
         "lsp" => {
-            lumen_lsp::run_lsp();
+            lumen_lsp::run_lsp(&config.lib_dirs);
         }
         "lint" => {
             if config.file.is_empty() {
@@ -1591,95 +1584,40 @@ fn prof_time(label: &str, start: &std::time::Instant) {
     }
 }
 
-fn resolve_or_exit(mut loader: ModuleLoader, source: &str, base_path: &Path) -> Vec<DeclOrStmt> {
-    match loader.resolve_imports(source, base_path) {
+fn resolve_or_exit(loader: ModuleLoader, source: &str, base_path: &Path) -> Vec<DeclOrStmt> {
+    resolve_with_origins_or_exit(loader, source, base_path).0
+}
+
+/// Como `resolve_or_exit`, con el archivo del que sale cada nodo.
+fn resolve_with_origins_or_exit(
+    mut loader: ModuleLoader,
+    source: &str,
+    base_path: &Path,
+) -> (Vec<DeclOrStmt>, Vec<PathBuf>) {
+    match loader.resolve_imports_with_origins(source, base_path) {
         Ok(p) => p,
         Err(e) => {
-            match &e {
-                lumen_sema::ModuleError::Circular { path, span } => {
-                    eprintln!();
-                    eprintln!("  \x1b[1;31mE063\x1b[0m \x1b[1mImport circular detectado\x1b[0m");
-                    eprintln!(
-                        "  \x1b[1;34m-->\x1b[0m {}:{}:{}",
-                        path.display(),
-                        span.start.line,
-                        span.start.col
-                    );
-                    eprintln!("   \x1b[1;33mAyuda:\x1b[0m Revisa las dependencias entre módulos");
-                    eprintln!();
-                }
-                lumen_sema::ModuleError::Io { path, message } => {
-                    eprintln!(
-                        "  \x1b[1;31mError\x1b[0m al cargar '{}': {}",
-                        path.display(),
-                        message
-                    );
-                }
-                lumen_sema::ModuleError::Lex { path, details } => {
-                    for d in details {
-                        eprintln!(
-                            "  \x1b[1;31mError léxico\x1b[0m en '{}': {}",
-                            path.display(),
-                            d
-                        );
-                    }
-                }
-                lumen_sema::ModuleError::Parse { path, details } => {
-                    for d in details {
-                        eprintln!(
-                            "  \x1b[1;31mError sintáctico\x1b[0m en '{}': {}",
-                            path.display(),
-                            d
-                        );
-                    }
-                }
+            let path = base_path.display().to_string();
+            for diag in e.to_diagnostics() {
+                show_diagnostic(&diag, source, &path);
             }
             process::exit(1);
         }
     }
 }
 
-fn show_error(source: &str, path: &str, code: &str, message: &str, span: &Span, suggestion: &str) {
-    let line = span.start.line;
-    let col = span.start.col;
-    let lines: Vec<&str> = source.lines().collect();
-    let line_str = lines.get(line.saturating_sub(1)).copied().unwrap_or("");
+/// Muestra un diagnóstico con el código anotado. Si pertenece a otro archivo
+/// (un módulo importado), el fragmento se toma de ese archivo.
+fn show_diagnostic(diag: &Diagnostic, source: &str, path: &str) {
+    let other = diag
+        .file
+        .as_ref()
+        .map(|f| fs::read_to_string(f).unwrap_or_default());
     eprintln!();
-    eprintln!("  \x1b[1;31m{}\x1b[0m \x1b[1m{}\x1b[0m", code, message);
-    eprintln!("  \x1b[1;34m-->\x1b[0m {}:{}:{}", path, line, col);
-    eprintln!("   \x1b[1;34m|\x1b[0m");
-    if line > 1 {
-        if let Some(prev) = lines.get(line - 2) {
-            eprintln!(
-                "  \x1b[90m{}\x1b[0m \x1b[1m|\x1b[0m \x1b[90m{}\x1b[0m",
-                line - 1,
-                prev
-            );
-        }
-    }
-    eprintln!("  \x1b[1;34m{}\x1b[0m \x1b[1m|\x1b[0m {}", line, line_str);
-    let underline = format!(
-        "{}{}",
-        " ".repeat(line.to_string().len() + 2 + col),
-        "^".repeat(span.end.col.saturating_sub(col).max(1))
+    eprint!(
+        "{}",
+        diag.render(other.as_deref().unwrap_or(source), path, true)
     );
-    eprintln!(
-        "  {} \x1b[1;32m{}\x1b[0m",
-        " ".repeat(line.to_string().len() + 1),
-        underline
-    );
-    if line < lines.len() {
-        if let Some(next) = lines.get(line) {
-            eprintln!(
-                "  \x1b[90m{}\x1b[0m \x1b[1m|\x1b[0m \x1b[90m{}\x1b[0m",
-                line + 1,
-                next
-            );
-        }
-    }
-    eprintln!("   \x1b[1;34m|\x1b[0m");
-    eprintln!("   \x1b[1;33mAyuda:\x1b[0m {}", suggestion);
-    eprintln!();
 }
 
 fn show_sema_errors(errors: &[lumen_sema::SemError], source: &str, path: &str) -> bool {
@@ -1687,17 +1625,13 @@ fn show_sema_errors(errors: &[lumen_sema::SemError], source: &str, path: &str) -
         return false;
     }
     for err in errors {
-        show_error(
-            source,
-            path,
-            &err.code,
-            &err.message,
-            &err.span,
-            &err.suggestion,
-        );
+        show_diagnostic(&Diagnostic::from(err), source, path);
     }
     if errors.len() > 1 {
-        eprintln!("  \x1b[1;33m{}\x1b[0m errores encontrados\n", errors.len());
+        eprintln!(
+            "\n  \x1b[1;33m{}\x1b[0m errores encontrados\n",
+            errors.len()
+        );
     }
     true
 }
@@ -1717,7 +1651,7 @@ fn compile_source(path: &str, lib_dirs: &[PathBuf]) -> Bytecode {
     let t = prof_start();
     let base_path = Path::new(path);
     let loader = ModuleLoader::new(lib_dirs.to_vec());
-    let mut program = resolve_or_exit(loader, &source, base_path);
+    let (mut program, origins) = resolve_with_origins_or_exit(loader, &source, base_path);
     prof_time("imports+parse", &t);
     let t = prof_start();
     let sema = SemanticAnalyzer::new();
@@ -1728,7 +1662,8 @@ fn compile_source(path: &str, lib_dirs: &[PathBuf]) -> Bytecode {
     }
     prof_time("sema", &t);
     let t = prof_start();
-    let builder = IRBuilder::new();
+    let mut builder = IRBuilder::new();
+    builder.set_origins(&canonical_path(base_path), &origins);
     let ir_program = builder.build(&program);
     prof_time("ir", &t);
     let t = prof_start();
@@ -1743,6 +1678,7 @@ fn run_source(path: &str, lib_dirs: &[PathBuf]) {
     let bytecode = compile_source(path, lib_dirs);
     let t = prof_start();
     let mut vm = VM::new(bytecode);
+    vm.set_source_name(path);
     match vm.run() {
         Ok(()) => {
             for line in vm.output() {
@@ -1750,7 +1686,7 @@ fn run_source(path: &str, lib_dirs: &[PathBuf]) {
             }
         }
         Err(e) => {
-            eprintln!("{}", e.with_stack(vm.call_stack()));
+            eprintln!("{}", vm.describe_error(e));
             process::exit(1);
         }
     }
@@ -1786,7 +1722,7 @@ fn run_bytecode(path: &str) {
                     for line in vm.output() {
                         println!("{}", line);
                     }
-                    eprintln!("{}", e.with_stack(vm.call_stack()));
+                    eprintln!("{}", vm.describe_error(e));
                     process::exit(1);
                 }
             }
@@ -1877,13 +1813,8 @@ fn check_project(root: &str, lib_dirs: &[PathBuf]) {
         let mut program = match loader.resolve_imports(&source, f) {
             Ok(p) => p,
             Err(e) => {
-                match &e {
-                    lumen_sema::ModuleError::Parse { details, .. } => {
-                        for d in details {
-                            eprintln!("  ✗ Error sintáctico en '{}': {}", f_str, d);
-                        }
-                    }
-                    _ => eprintln!("  ✗ Error al procesar '{}': {:?}", f_str, e),
+                for diag in e.to_diagnostics() {
+                    show_diagnostic(&diag, &source, &f_str);
                 }
                 total_errors += 1;
                 continue;
@@ -1916,6 +1847,64 @@ fn check_project(root: &str, lib_dirs: &[PathBuf]) {
     }
 }
 
+/// `lumen check --format json`: un array JSON con los diagnósticos de todos
+/// los archivos, cada uno con su `file`. Termina con código 1 si hay errores.
+fn check_json(target: &str, lib_dirs: &[PathBuf]) {
+    let mut files = Vec::new();
+    let root = Path::new(target);
+    if root.is_dir() {
+        collect_nv_files(root, &mut files);
+    } else {
+        files.push(root.to_path_buf());
+    }
+    files.sort();
+
+    let mut diagnostics = Vec::new();
+    for f in &files {
+        let f_str = f.to_string_lossy().to_string();
+        diagnostics.extend(check_diagnostics(f, lib_dirs).into_iter().map(|d| {
+            if d.file.is_some() {
+                d
+            } else {
+                d.in_file(&f_str)
+            }
+        }));
+    }
+    match serde_json::to_string_pretty(&diagnostics) {
+        Ok(json) => println!("{}", json),
+        Err(e) => {
+            eprintln!("Error al serializar los diagnósticos: {}", e);
+            process::exit(1);
+        }
+    }
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        process::exit(1);
+    }
+}
+
+/// Diagnósticos de lectura, imports, sintaxis y semántica de un archivo.
+fn check_diagnostics(path: &Path, lib_dirs: &[PathBuf]) -> Vec<Diagnostic> {
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            return lumen_sema::ModuleError::Io {
+                path: path.to_path_buf(),
+                message: e.to_string(),
+            }
+            .to_diagnostics()
+        }
+    };
+    let mut loader = ModuleLoader::new(lib_dirs.to_vec());
+    match loader.resolve_imports(&source, path) {
+        Ok(mut program) => SemanticAnalyzer::new()
+            .analyze(&mut program)
+            .iter()
+            .map(Diagnostic::from)
+            .collect(),
+        Err(e) => e.to_diagnostics(),
+    }
+}
+
 fn check_source(path: &str, lib_dirs: &[PathBuf]) {
     let _ = compile_source(path, lib_dirs);
     println!(
@@ -1954,7 +1943,15 @@ fn run_fmt(path: &str) {
             process::exit(1);
         }
     };
-    match lumen_fmt::format_source(&source) {
+    // Un `.lumen-fmt.toml` o `[fmt]` inválido es un error, no se ignora.
+    let config = match lumen_fmt::find_config(Path::new(path)) {
+        Ok(config) => config.unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    match lumen_fmt::format_source_with_config(&source, &config) {
         Ok(formatted) => {
             let trimmed = formatted.trim_end().to_string() + "\n";
             match fs::write(path, &trimmed) {
@@ -1982,14 +1979,8 @@ fn run_tests(path: &str, lib_dirs: &[PathBuf]) {
             process::exit(1);
         }
     };
-    let mut loader = ModuleLoader::new(lib_dirs.to_vec());
-    let mut flat = match loader.resolve_imports(&source, Path::new(path)) {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("Error imports: {:?}", e);
-            process::exit(1);
-        }
-    };
+    let loader = ModuleLoader::new(lib_dirs.to_vec());
+    let mut flat = resolve_or_exit(loader, &source, Path::new(path));
     let sema = SemanticAnalyzer::new();
     let sem_errors = sema.analyze(&mut flat);
     if !sem_errors.is_empty() {
@@ -2016,8 +2007,10 @@ fn run_tests(path: &str, lib_dirs: &[PathBuf]) {
                 nums: bytecode.nums.clone(),
                 names: bytecode.names.clone(),
                 funcs: vec![fm.clone()],
+                lines: bytecode.lines.clone(),
             };
             let mut vm = VM::new(test_bc);
+            vm.set_source_name(path);
             match vm.run() {
                 Ok(()) => {
                     passed += 1;
@@ -2025,11 +2018,7 @@ fn run_tests(path: &str, lib_dirs: &[PathBuf]) {
                 }
                 Err(e) => {
                     failed += 1;
-                    eprintln!(
-                        "  ✗ {} ... FALLÓ: {}",
-                        fm.name,
-                        e.with_stack(vm.call_stack())
-                    );
+                    eprintln!("  ✗ {} ... FALLÓ: {}", fm.name, vm.describe_error(e));
                 }
             }
         }
@@ -2234,11 +2223,69 @@ fn run_bootstrap(file: &str, lib_dirs: &[PathBuf]) {
 }
 
 fn run_lint(path: &str, lib_dirs: &[PathBuf]) {
-    let _ = compile_source(path, lib_dirs);
-    println!(
-        "✓ Análisis estático (lumen lint): 0 advertencias en '{}' (Código 100% limpio y seguro)",
-        path
+    use lumen_sema::{LintConfig, Linter, Severity};
+
+    let source = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error al leer '{}': {}", path, e);
+            process::exit(1);
+        }
+    };
+    // El programa completo tiene que compilar: el lint solo mira código válido.
+    let loader = ModuleLoader::new(lib_dirs.to_vec());
+    let mut flat = resolve_or_exit(loader, &source, Path::new(path));
+    let errors = SemanticAnalyzer::new().analyze(&mut flat);
+    if show_sema_errors(&errors, &source, path) {
+        process::exit(1);
+    }
+
+    let config = match lumen_project::find_manifest(Path::new(path)) {
+        Some(manifest) => match lumen_project::load_lint_rules(&manifest).and_then(|rules| {
+            LintConfig::from_rules(rules.iter().map(|(r, l)| (r.as_str(), l.as_str())))
+        }) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Error en {}: {}", manifest.display(), e);
+                process::exit(1);
+            }
+        },
+        None => LintConfig::default(),
+    };
+
+    // Se analiza solo este archivo (sin los módulos importados), usando el
+    // programa aplanado para conocer las firmas importadas.
+    let (tokens, _) = lumen_lexer::Lexer::new(&source).tokenize();
+    let (mut own, _) = lumen_parser::Parser::new(tokens).parse();
+    let _ = SemanticAnalyzer::new().analyze(&mut own);
+    let lints = Linter::new(&config)
+        .with_context(&flat)
+        .check(&own, &source);
+
+    for l in &lints {
+        show_diagnostic(&Diagnostic::from(l), &source, path);
+    }
+    let count = |sev| lints.iter().filter(|l| l.severity == sev).count();
+    let (errors, warnings, infos) = (
+        count(Severity::Error),
+        count(Severity::Warning),
+        count(Severity::Info),
     );
+    if !lints.is_empty() {
+        eprintln!();
+    }
+    if lints.is_empty() {
+        println!("✓ lumen lint: sin avisos en '{}'", path);
+    } else {
+        println!(
+            "lumen lint: {} error(es), {} advertencia(s), {} nota(s) en '{}'",
+            errors, warnings, infos, path
+        );
+        println!("  el nivel de cada regla se cambia en la sección [lint] de lumen.toml");
+    }
+    if errors > 0 {
+        process::exit(1);
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -2260,14 +2307,8 @@ fn build_native(
             process::exit(1);
         }
     };
-    let mut loader = ModuleLoader::new(lib_dirs.to_vec());
-    let program = match loader.resolve_imports(&source, Path::new(path)) {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("Error imports: {:?}", e);
-            process::exit(1);
-        }
-    };
+    let loader = ModuleLoader::new(lib_dirs.to_vec());
+    let program = resolve_or_exit(loader, &source, Path::new(path));
     let mut prog = program;
     let errors = SemanticAnalyzer::new().analyze(&mut prog);
     if !errors.is_empty() {
@@ -2631,7 +2672,16 @@ fn render_tui_debugger_panel(
     }
 
     println!("  \x1b[1;36m╚══════════════════════════════════════════════════════════════════════════════════════╝\x1b[0m");
-    println!("  \x1b[90mComandos: [s]paso  [back]retroceder  [c]continuar  [b <línea>]breakpoint  [p <var>]  [q]salir\x1b[0m");
+    println!("  \x1b[90mComandos: [s]paso  [n]siguiente  [o]salir de función  [back]retroceder  [c]continuar  [b <línea>]breakpoint  [p <var>]  [q]salir\x1b[0m");
+}
+
+/// Línea de la próxima sentencia en el programa principal, la que el panel
+/// marca; 0 mientras se ejecuta un módulo importado.
+fn current_line(vm: &VM) -> usize {
+    match vm.current_file() {
+        Some(0) => vm.current_position().map_or(0, |(line, _)| line as usize),
+        _ => 0,
+    }
 }
 
 fn run_debug(path: &str, lib_dirs: &[PathBuf]) {
@@ -2641,12 +2691,11 @@ fn run_debug(path: &str, lib_dirs: &[PathBuf]) {
     let bytecode = compile_source(path, lib_dirs);
     let mut vm = VM::new(bytecode);
     vm.debug = true;
-    let _ = vm.step();
+    vm.set_source_name(path);
 
     let mut breakpoints: std::collections::HashSet<usize> = std::collections::HashSet::new();
-    let mut current_line = 1;
 
-    render_tui_debugger_panel(path, &source_lines, current_line, &breakpoints, &vm);
+    render_tui_debugger_panel(path, &source_lines, current_line(&vm), &breakpoints, &vm);
 
     loop {
         print!("  \x1b[1;36m(lumen-dbg)\x1b[0m ");
@@ -2656,144 +2705,203 @@ fn run_debug(path: &str, lib_dirs: &[PathBuf]) {
             break;
         }
         let trimmed = input.trim();
-        match trimmed {
-            "s" | "step" | "paso" | "p" => match vm.step() {
-                Ok(()) => {
-                    current_line = (current_line % source_lines.len().max(1)) + 1;
-                    render_tui_debugger_panel(path, &source_lines, current_line, &breakpoints, &vm);
-                }
-                Err(e) => {
-                    eprintln!(
-                        "\n  \x1b[1;31m[DEBUG FINISHED / ERROR]:\x1b[0m {}",
-                        e.with_stack(vm.call_stack())
-                    );
+        let step = match trimmed {
+            "s" | "step" | "paso" | "p" => Some(StepKind::Into),
+            "n" | "next" | "siguiente" => Some(StepKind::Over),
+            "o" | "out" | "finish" | "fin" => Some(StepKind::Out),
+            "i" | "instr" | "instruccion" => None,
+            _ => {
+                if debug_command(trimmed, path, &source_lines, &mut breakpoints, &mut vm) {
                     break;
                 }
-            },
-            "back" | "step-back" | "prev" | "retroceder" | "bck" => match vm.step_back() {
-                Ok(true) => {
-                    if current_line > 1 {
-                        current_line -= 1;
-                    }
-                    render_tui_debugger_panel(path, &source_lines, current_line, &breakpoints, &vm);
-                    println!("  ⏮️ \x1b[1;32m[Time-Travel]\x1b[0m Estado anterior restaurado exitosamente.");
-                }
-                Ok(false) => {
-                    println!(
-                        "  ℹ️ Inicio de la ejecución alcanzado (no hay más snapshots anteriores)."
-                    );
-                }
-                Err(e) => eprintln!("Error al retroceder: {}", e),
-            },
-            "history" | "timeline" | "historial" => {
-                println!("\n  ⏱️ \x1b[1;33mHistorial de Time-Travel:\x1b[0m {} snapshots registrados en memoria.", vm.snapshots.len());
+                continue;
             }
-            "vars" | "variables" | "locales" => {
-                println!("\n  \x1b[1;35m=== TODAS LAS VARIABLES EN ÁMBITO ===\x1b[0m");
-                if let Some(locals) = vm.current_locals() {
-                    for (k, v) in locals.iter() {
-                        println!(
-                            "    • \x1b[1;34m{}\x1b[0m = \x1b[1;32m{}\x1b[0m \x1b[90m({:?})\x1b[0m",
-                            k, v, v
-                        );
-                    }
-                } else {
-                    println!("    (Sin variables)");
-                }
+        };
+        let result = match step {
+            Some(kind) => vm.step_source(kind),
+            None => vm.step(),
+        };
+        match result {
+            Ok(()) if vm.is_finished() => {
+                println!("  ✓ Ejecución finalizada con éxito.");
+                print_debug_output(&vm);
+                break;
             }
-            "stack" | "pila" => {
-                println!("\n  \x1b[1;34m=== PILA DE LLAMADAS (CALL STACK) ===\x1b[0m");
-                for (i, frame) in vm.call_stack().iter().enumerate() {
-                    println!(
-                        "    [#{}] {}() -> retorno en IP {}",
-                        i, frame.func_name, frame.return_ip
-                    );
+            Ok(()) => {
+                if vm.is_paused() {
+                    println!("  🔴 Breakpoint en la línea {}", current_line(&vm));
                 }
+                render_tui_debugger_panel(
+                    path,
+                    &source_lines,
+                    current_line(&vm),
+                    &breakpoints,
+                    &vm,
+                );
             }
-            "c" | "continue" | "continuar" => {
-                println!("  🚀 Continuando ejecución completa...");
-                match vm.run() {
-                    Ok(()) => {
-                        println!("  ✓ Ejecución finalizada con éxito.");
-                    }
-                    Err(e) => {
-                        eprintln!("  ✗ Error en ejecución: {}", e.with_stack(vm.call_stack()));
-                    }
-                }
-                println!("  Salida acumulada (STDOUT):");
-                for line in vm.output() {
-                    println!("    {}", line);
-                }
+            Err(e) => {
+                eprintln!(
+                    "\n  \x1b[1;31m[DEBUG FINISHED / ERROR]:\x1b[0m {}",
+                    vm.describe_error(e)
+                );
                 break;
             }
-            "h" | "help" | "ayuda" | "?" => {
-                println!("\n  \x1b[1;36m=== MANUAL DE COMANDOS DEL DEPURADOR LÚMEN ===\x1b[0m");
-                println!("    \x1b[1;32ms, step, paso\x1b[0m       Avanza 1 instrucción en la máquina virtual");
-                println!("    \x1b[1;32mback, retroceder\x1b[0m    Time-Travel: retrocede 1 snapshot en el tiempo");
-                println!("    \x1b[1;32mc, continue\x1b[0m         Ejecuta hasta el siguiente breakpoint o fin");
-                println!("    \x1b[1;32mb <línea>\x1b[0m           Alterna breakpoint en el número de línea dado");
+        }
+    }
+}
+
+fn print_debug_output(vm: &VM) {
+    println!("  Salida acumulada (STDOUT):");
+    for line in vm.output() {
+        println!("    {}", line);
+    }
+}
+
+/// Ejecuta un comando del depurador que no avanza por pasos. Devuelve `true`
+/// si la sesión termina.
+fn debug_command(
+    trimmed: &str,
+    path: &str,
+    source_lines: &[String],
+    breakpoints: &mut std::collections::HashSet<usize>,
+    vm: &mut VM,
+) -> bool {
+    match trimmed {
+        "back" | "step-back" | "prev" | "retroceder" | "bck" => match vm.step_back() {
+            Ok(true) => {
+                render_tui_debugger_panel(path, source_lines, current_line(vm), breakpoints, vm);
                 println!(
-                    "    \x1b[1;32mp <var>\x1b[0m             Imprime el valor de una variable"
+                    "  ⏮️ \x1b[1;32m[Time-Travel]\x1b[0m Estado anterior restaurado exitosamente."
                 );
-                println!("    \x1b[1;32mvars\x1b[0m                Muestra todas las variables en memoria");
+            }
+            Ok(false) => {
                 println!(
-                    "    \x1b[1;32mstack\x1b[0m               Inspecciona la pila de llamadas"
+                    "  ℹ️ Inicio de la ejecución alcanzado (no hay más snapshots anteriores)."
                 );
-                println!("    \x1b[1;32mhistory\x1b[0m             Muestra cantidad de snapshots Time-Travel");
-                println!("    \x1b[1;32mq, quit, salir\x1b[0m      Cierra el depurador\n");
             }
-            "q" | "quit" | "salir" => {
-                println!("  Saliendo del depurador LÚMEN.");
-                break;
+            Err(e) => eprintln!("Error al retroceder: {}", e),
+        },
+        "history" | "timeline" | "historial" => {
+            println!("\n  ⏱️ \x1b[1;33mHistorial de Time-Travel:\x1b[0m {} snapshots registrados en memoria.", vm.snapshots.len());
+        }
+        "vars" | "variables" | "locales" => {
+            println!("\n  \x1b[1;35m=== TODAS LAS VARIABLES EN ÁMBITO ===\x1b[0m");
+            if let Some(locals) = vm.current_locals() {
+                for (k, v) in locals.iter() {
+                    println!(
+                        "    • \x1b[1;34m{}\x1b[0m = \x1b[1;32m{}\x1b[0m \x1b[90m({:?})\x1b[0m",
+                        k, v, v
+                    );
+                }
+            } else {
+                println!("    (Sin variables)");
             }
-            s if s.starts_with("b ") || s.starts_with("break ") => {
-                let rest = if let Some(stripped) = s.strip_prefix("break ") {
-                    stripped
-                } else if let Some(stripped) = s.strip_prefix("b ") {
-                    stripped
+        }
+        "stack" | "pila" => {
+            println!("\n  \x1b[1;34m=== PILA DE LLAMADAS (CALL STACK) ===\x1b[0m");
+            for (i, frame) in vm.call_stack().iter().enumerate() {
+                println!(
+                    "    [#{}] {}() -> retorno en IP {}",
+                    i, frame.func_name, frame.return_ip
+                );
+            }
+        }
+        "c" | "continue" | "continuar" => {
+            println!("  🚀 Continuando hasta el siguiente breakpoint...");
+            match vm.run() {
+                Ok(()) if vm.is_paused() => {
+                    println!("  🔴 Breakpoint en la línea {}", current_line(vm));
+                    render_tui_debugger_panel(
+                        path,
+                        source_lines,
+                        current_line(vm),
+                        breakpoints,
+                        vm,
+                    );
+                    return false;
+                }
+                Ok(()) => {
+                    println!("  ✓ Ejecución finalizada con éxito.");
+                }
+                Err(e) => {
+                    eprintln!("  ✗ Error en ejecución: {}", vm.describe_error(e));
+                }
+            }
+            print_debug_output(vm);
+            return true;
+        }
+        "h" | "help" | "ayuda" | "?" => {
+            println!("\n  \x1b[1;36m=== MANUAL DE COMANDOS DEL DEPURADOR LÚMEN ===\x1b[0m");
+            println!("    \x1b[1;32ms, step, paso\x1b[0m       Avanza a la siguiente sentencia, entrando en las llamadas");
+            println!("    \x1b[1;32mn, next\x1b[0m             Avanza a la siguiente sentencia sin entrar en las llamadas");
+            println!("    \x1b[1;32mo, out, fin\x1b[0m         Ejecuta hasta volver a la función que llamó");
+            println!("    \x1b[1;32mi, instr\x1b[0m            Avanza 1 instrucción en la máquina virtual");
+            println!("    \x1b[1;32mback, retroceder\x1b[0m    Time-Travel: retrocede 1 snapshot en el tiempo");
+            println!("    \x1b[1;32mc, continue\x1b[0m         Ejecuta hasta el siguiente breakpoint o fin");
+            println!("    \x1b[1;32mb <línea>\x1b[0m           Alterna breakpoint en el número de línea dado");
+            println!("    \x1b[1;32mp <var>\x1b[0m             Imprime el valor de una variable");
+            println!(
+                "    \x1b[1;32mvars\x1b[0m                Muestra todas las variables en memoria"
+            );
+            println!("    \x1b[1;32mstack\x1b[0m               Inspecciona la pila de llamadas");
+            println!("    \x1b[1;32mhistory\x1b[0m             Muestra cantidad de snapshots Time-Travel");
+            println!("    \x1b[1;32mq, quit, salir\x1b[0m      Cierra el depurador\n");
+        }
+        "q" | "quit" | "salir" => {
+            println!("  Saliendo del depurador LÚMEN.");
+            return true;
+        }
+        s if s.starts_with("b ") || s.starts_with("break ") => {
+            let rest = if let Some(stripped) = s.strip_prefix("break ") {
+                stripped
+            } else if let Some(stripped) = s.strip_prefix("b ") {
+                stripped
+            } else {
+                s
+            };
+            if let Ok(line_num) = rest.trim().parse::<usize>() {
+                if breakpoints.contains(&line_num) {
+                    breakpoints.remove(&line_num);
+                    vm.clear_line_breakpoint(line_num as u32);
+                    println!("  ⚪ Breakpoint removido de la línea {}", line_num);
+                } else if vm.set_line_breakpoint(line_num as u32) {
+                    breakpoints.insert(line_num);
+                    println!("  🔴 Breakpoint colocado en la línea {}", line_num);
                 } else {
-                    s
-                };
-                if let Ok(line_num) = rest.trim().parse::<usize>() {
-                    if breakpoints.contains(&line_num) {
-                        breakpoints.remove(&line_num);
-                        println!("  ⚪ Breakpoint removido de la línea {}", line_num);
-                    } else {
-                        breakpoints.insert(line_num);
-                        println!("  🔴 Breakpoint colocado en la línea {}", line_num);
-                    }
-                    render_tui_debugger_panel(path, &source_lines, current_line, &breakpoints, &vm);
+                    println!("  No hay ninguna sentencia en la línea {}", line_num);
                 }
+                render_tui_debugger_panel(path, source_lines, current_line(vm), breakpoints, vm);
+            }
+        }
+        s if s.starts_with("print ") || s.starts_with("p ") => {
+            let var_name = if let Some(stripped) = s.strip_prefix("print ") {
+                stripped
+            } else if let Some(stripped) = s.strip_prefix("p ") {
+                stripped
+            } else {
+                s
             }
-            s if s.starts_with("print ") || s.starts_with("p ") => {
-                let var_name = if let Some(stripped) = s.strip_prefix("print ") {
-                    stripped
-                } else if let Some(stripped) = s.strip_prefix("p ") {
-                    stripped
+            .trim();
+            if let Some(locals) = vm.current_locals() {
+                if let Some(val) = locals.get(var_name) {
+                    println!(
+                        "  \x1b[1;34m{}\x1b[0m = \x1b[1;32m{}\x1b[0m \x1b[90m({:?})\x1b[0m",
+                        var_name, val, val
+                    );
                 } else {
-                    s
-                }
-                .trim();
-                if let Some(locals) = vm.current_locals() {
-                    if let Some(val) = locals.get(var_name) {
-                        println!(
-                            "  \x1b[1;34m{}\x1b[0m = \x1b[1;32m{}\x1b[0m \x1b[90m({:?})\x1b[0m",
-                            var_name, val, val
-                        );
-                    } else {
-                        println!("  Variable '{}' no encontrada en ámbito actual.", var_name);
-                    }
+                    println!("  Variable '{}' no encontrada en ámbito actual.", var_name);
                 }
             }
-            "" => continue,
-            other => {
-                println!(
-                    "  Comando '{}' no reconocido. Escribe 'h' o 'ayuda' para ver la lista.",
-                    other
-                );
-            }
+        }
+        "" => {}
+        other => {
+            println!(
+                "  Comando '{}' no reconocido. Escribe 'h' o 'ayuda' para ver la lista.",
+                other
+            );
         }
     }
+    false
 }
 
 fn mime_type(path: &str) -> &'static str {
@@ -3146,9 +3254,11 @@ fn run_source_capture(source: &str, lib_dirs: &[PathBuf], base_path: &Path) -> (
     let ir = IRBuilder::new().build(&program);
     let (bytecode, _) = Codegen::new().generate(&ir);
     let mut vm = VM::new(bytecode);
+    // El código llega de cualquier navegador: sin permisos y con topes.
+    vm.set_limits(VmLimits::sandboxed());
     match vm.run() {
         Ok(()) => (vm.output().join("\n"), String::new()),
-        Err(e) => (vm.output().join("\n"), e.with_stack(vm.call_stack())),
+        Err(e) => (vm.output().join("\n"), vm.describe_error(e)),
     }
 }
 
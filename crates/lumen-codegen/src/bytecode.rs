@@ -1,5 +1,5 @@
 pub const CHUNK_MAGIC: &[u8; 4] = b"LUMN";
-pub const CHUNK_VERSION: u32 = 7;
+pub const CHUNK_VERSION: u32 = 10;
 
 #[derive(Debug, Clone)]
 pub enum DefaultValue {
@@ -67,6 +67,25 @@ pub enum Opcode {
     MatchPayload = 53,
     BitXor = 54,
     BitNot = 55,
+    DeferPush = 56,
+    DeferRun = 57,
+    DeferEnd = 58,
+    TryBegin = 59,
+    TryEnd = 60,
+    LoadLocal = 61,
+    StoreLocal = 62,
+    LoadGlobal = 63,
+    StoreGlobal = 64,
+    /// Crea una clausura: la siguen un `Nop` con el número de capturas y un
+    /// `Nop` por captura con el slot del frame actual que captura.
+    MakeClosure = 65,
+    /// Accesos a un slot capturado por una clausura: leen y escriben la
+    /// celda compartida si el slot ya tiene una.
+    LoadCell = 66,
+    StoreCell = 67,
+    /// Separa el slot de su celda antes de declarar de nuevo la variable:
+    /// las clausuras ya creadas se quedan con la celda anterior.
+    DropCell = 68,
 }
 
 impl Opcode {
@@ -128,6 +147,19 @@ impl Opcode {
             53 => Some(Opcode::MatchPayload),
             54 => Some(Opcode::BitXor),
             55 => Some(Opcode::BitNot),
+            56 => Some(Opcode::DeferPush),
+            57 => Some(Opcode::DeferRun),
+            58 => Some(Opcode::DeferEnd),
+            59 => Some(Opcode::TryBegin),
+            60 => Some(Opcode::TryEnd),
+            61 => Some(Opcode::LoadLocal),
+            62 => Some(Opcode::StoreLocal),
+            63 => Some(Opcode::LoadGlobal),
+            64 => Some(Opcode::StoreGlobal),
+            65 => Some(Opcode::MakeClosure),
+            66 => Some(Opcode::LoadCell),
+            67 => Some(Opcode::StoreCell),
+            68 => Some(Opcode::DropCell),
             _ => None,
         }
     }
@@ -151,9 +183,188 @@ pub struct FuncMeta {
     pub name: String,
     pub params: Vec<String>,
     pub defaults: Vec<Option<DefaultValue>>,
+    /// Nombres de los slots del frame: primero los parámetros y después las
+    /// variables locales, en el orden en que `LoadLocal`/`StoreLocal` los indexan.
+    pub locals: Vec<String>,
     pub start: usize,
 }
 
+/// Inicio de un tramo de instrucciones que corresponde a una posición de la
+/// fuente. `line == 0` marca un tramo sin posición (p. ej. el prólogo de una
+/// función antes de su primera sentencia). `file` indexa la tabla de archivos;
+/// el 0 es el programa principal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineEntry {
+    pub ip: usize,
+    pub file: u32,
+    pub line: u32,
+    pub col: u32,
+}
+
+/// Tabla de líneas de un chunk: entradas ordenadas por `ip`, cada una vale
+/// hasta la siguiente. Solo se guarda una entrada por sentencia. `files`
+/// nombra los archivos de los que sale el código (el principal y los módulos
+/// importados); puede estar vacía si el chunk no se compiló desde archivos.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineTable {
+    files: Vec<String>,
+    entries: Vec<LineEntry>,
+}
+
+impl LineTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_files(&mut self, files: Vec<String>) {
+        self.files = files;
+    }
+
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// Índice de `path` en la tabla de archivos.
+    pub fn file_index(&self, path: &str) -> Option<u32> {
+        self.files.iter().position(|f| f == path).map(|i| i as u32)
+    }
+
+    /// Añade una entrada en `ip`. Dos entradas en el mismo `ip` se quedan con
+    /// la última; una que repite la posición anterior no se guarda.
+    pub fn push(&mut self, ip: usize, file: u32, line: u32, col: u32) {
+        if let Some(last) = self.entries.last_mut() {
+            if last.ip == ip {
+                last.file = file;
+                last.line = line;
+                last.col = col;
+                return;
+            }
+            if last.file == file && last.line == line && last.col == col {
+                return;
+            }
+        }
+        self.entries.push(LineEntry {
+            ip,
+            file,
+            line,
+            col,
+        });
+    }
+
+    /// Entrada con posición que cubre la instrucción `ip`, si la hay.
+    pub fn entry_at(&self, ip: usize) -> Option<&LineEntry> {
+        let idx = self.entries.partition_point(|e| e.ip <= ip);
+        let entry = self.entries.get(idx.checked_sub(1)?)?;
+        (entry.line > 0).then_some(entry)
+    }
+
+    /// Posición (línea, columna) de la instrucción `ip`, si tiene.
+    pub fn lookup(&self, ip: usize) -> Option<(u32, u32)> {
+        self.entry_at(ip).map(|e| (e.line, e.col))
+    }
+
+    /// Si `ip` es la primera instrucción de una sentencia.
+    pub fn is_stmt_start(&self, ip: usize) -> bool {
+        self.entries
+            .binary_search_by_key(&ip, |e| e.ip)
+            .is_ok_and(|i| self.entries[i].line > 0)
+    }
+
+    /// Primera instrucción de cada sentencia que empieza en la línea `line`
+    /// del archivo `file`.
+    pub fn ips_for_line(&self, file: u32, line: u32) -> Vec<usize> {
+        self.entries
+            .iter()
+            .filter(|e| e.file == file && e.line == line)
+            .map(|e| e.ip)
+            .collect()
+    }
+
+    pub fn entries(&self) -> &[LineEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Codifica la tabla de archivos y después las entradas en deltas: `ip`,
+    /// archivo y columna como varint, línea como varint zigzag (puede bajar
+    /// al pasar a otra función).
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.files.len() as u32).to_le_bytes());
+        for f in &self.files {
+            buf.extend_from_slice(&(f.len() as u32).to_le_bytes());
+            buf.extend_from_slice(f.as_bytes());
+        }
+        buf.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        let (mut ip, mut line) = (0usize, 0i64);
+        for e in &self.entries {
+            write_varint(buf, (e.ip - ip) as u64);
+            write_varint(buf, e.file as u64);
+            let delta = e.line as i64 - line;
+            write_varint(buf, ((delta << 1) ^ (delta >> 63)) as u64);
+            write_varint(buf, e.col as u64);
+            ip = e.ip;
+            line = e.line as i64;
+        }
+    }
+
+    fn decode(data: &[u8], pos: &mut usize) -> Option<Self> {
+        let read_u32 = |pos: &mut usize| -> Option<usize> {
+            let n = u32::from_le_bytes(data.get(*pos..*pos + 4)?.try_into().ok()?);
+            *pos += 4;
+            Some(n as usize)
+        };
+        let file_count = read_u32(pos)?;
+        let mut files = Vec::with_capacity(file_count.min(data.len()));
+        for _ in 0..file_count {
+            let len = read_u32(pos)?;
+            let bytes = data.get(*pos..pos.checked_add(len)?)?;
+            files.push(String::from_utf8_lossy(bytes).into_owned());
+            *pos += len;
+        }
+        let count = read_u32(pos)?;
+        let mut entries = Vec::with_capacity(count.min(data.len()));
+        let (mut ip, mut line) = (0usize, 0i64);
+        for _ in 0..count {
+            ip += read_varint(data, pos)? as usize;
+            let file = read_varint(data, pos)? as u32;
+            let zz = read_varint(data, pos)?;
+            line += (zz >> 1) as i64 ^ -((zz & 1) as i64);
+            let col = read_varint(data, pos)? as u32;
+            entries.push(LineEntry {
+                ip,
+                file,
+                line: line as u32,
+                col,
+            });
+        }
+        Some(Self { files, entries })
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let b = *data.get(*pos)?;
+        *pos += 1;
+        n |= ((b & 0x7f) as u64) << shift;
+        if b < 0x80 {
+            return Some(n);
+        }
+    }
+    None
+}
+
 #[derive(Debug, Clone)]
 pub struct Bytecode {
     pub instructions: Vec<Instruction>,
@@ -162,6 +373,7 @@ pub struct Bytecode {
     pub nums: Vec<f64>,
     pub names: Vec<String>,
     pub funcs: Vec<FuncMeta>,
+    pub lines: LineTable,
 }
 
 impl Default for Bytecode {
@@ -179,6 +391,7 @@ impl Bytecode {
             nums: Vec::new(),
             names: Vec::new(),
             funcs: Vec::new(),
+            lines: LineTable::new(),
         }
     }
 
@@ -236,6 +449,11 @@ impl Bytecode {
                     }
                 }
             }
+            buf.extend_from_slice(&(func.locals.len() as u32).to_le_bytes());
+            for l in &func.locals {
+                buf.extend_from_slice(&(l.len() as u32).to_le_bytes());
+                buf.extend_from_slice(l.as_bytes());
+            }
             buf.extend_from_slice(&(func.start as u64).to_le_bytes());
         }
         buf.extend_from_slice(&(self.instructions.len() as u32).to_le_bytes());
@@ -273,6 +491,7 @@ impl Bytecode {
                 }
             }
         }
+        self.lines.encode(&mut buf);
         buf
     }
 
@@ -284,9 +503,15 @@ impl Bytecode {
             return Err("Magic number inválido".to_string());
         }
         let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
-        if version != 6 && version != CHUNK_VERSION {
+        if version < CHUNK_VERSION {
             return Err(format!(
-                "Versión {} de bytecode no soportada (esperada {} o 6)",
+                "Versión {} de bytecode obsoleta (esperada {}): el formato ahora incluye la tabla de líneas y archivos, recompila el programa con `lumen build`",
+                version, CHUNK_VERSION
+            ));
+        }
+        if version != CHUNK_VERSION {
+            return Err(format!(
+                "Versión {} de bytecode no soportada (esperada {})",
                 version, CHUNK_VERSION
             ));
         }
@@ -417,90 +642,107 @@ impl Bytecode {
                 params.push(p);
                 pos += plen;
             }
-            let defaults = if version == 7 {
-                if pos + 4 > data.len() {
+            if pos + 4 > data.len() {
+                break;
+            }
+            let num_defaults =
+                u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+                    as usize;
+            pos += 4;
+            let mut defaults = Vec::with_capacity(num_defaults);
+            for _ in 0..num_defaults {
+                if pos >= data.len() {
                     break;
                 }
-                let num_defaults =
-                    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
-                        as usize;
-                pos += 4;
-                let mut defs = Vec::with_capacity(num_defaults);
-                for _ in 0..num_defaults {
-                    if pos >= data.len() {
-                        break;
+                let tag = data[pos];
+                pos += 1;
+                match tag {
+                    0 => defaults.push(None),
+                    1 => {
+                        if pos + 8 > data.len() {
+                            break;
+                        }
+                        let v = i64::from_le_bytes([
+                            data[pos],
+                            data[pos + 1],
+                            data[pos + 2],
+                            data[pos + 3],
+                            data[pos + 4],
+                            data[pos + 5],
+                            data[pos + 6],
+                            data[pos + 7],
+                        ]);
+                        pos += 8;
+                        defaults.push(Some(DefaultValue::Int(v)));
                     }
-                    let tag = data[pos];
-                    pos += 1;
-                    match tag {
-                        0 => defs.push(None),
-                        1 => {
-                            if pos + 8 > data.len() {
-                                break;
-                            }
-                            let v = i64::from_le_bytes([
-                                data[pos],
-                                data[pos + 1],
-                                data[pos + 2],
-                                data[pos + 3],
-                                data[pos + 4],
-                                data[pos + 5],
-                                data[pos + 6],
-                                data[pos + 7],
-                            ]);
-                            pos += 8;
-                            defs.push(Some(DefaultValue::Int(v)));
+                    2 => {
+                        if pos + 8 > data.len() {
+                            break;
                         }
-                        2 => {
-                            if pos + 8 > data.len() {
-                                break;
-                            }
-                            let v = f64::from_le_bytes([
-                                data[pos],
-                                data[pos + 1],
-                                data[pos + 2],
-                                data[pos + 3],
-                                data[pos + 4],
-                                data[pos + 5],
-                                data[pos + 6],
-                                data[pos + 7],
-                            ]);
-                            pos += 8;
-                            defs.push(Some(DefaultValue::Float(v)));
+                        let v = f64::from_le_bytes([
+                            data[pos],
+                            data[pos + 1],
+                            data[pos + 2],
+                            data[pos + 3],
+                            data[pos + 4],
+                            data[pos + 5],
+                            data[pos + 6],
+                            data[pos + 7],
+                        ]);
+                        pos += 8;
+                        defaults.push(Some(DefaultValue::Float(v)));
+                    }
+                    3 => {
+                        if pos + 4 > data.len() {
+                            break;
                         }
-                        3 => {
-                            if pos + 4 > data.len() {
-                                break;
-                            }
-                            let slen = u32::from_le_bytes([
-                                data[pos],
-                                data[pos + 1],
-                                data[pos + 2],
-                                data[pos + 3],
-                            ]) as usize;
-                            pos += 4;
-                            if pos + slen > data.len() {
-                                break;
-                            }
-                            let s = String::from_utf8_lossy(&data[pos..pos + slen]).to_string();
-                            pos += slen;
-                            defs.push(Some(DefaultValue::Str(s)));
+                        let slen = u32::from_le_bytes([
+                            data[pos],
+                            data[pos + 1],
+                            data[pos + 2],
+                            data[pos + 3],
+                        ]) as usize;
+                        pos += 4;
+                        if pos + slen > data.len() {
+                            break;
                         }
-                        4 => {
-                            if pos >= data.len() {
-                                break;
-                            }
-                            let b = data[pos] != 0;
-                            pos += 1;
-                            defs.push(Some(DefaultValue::Bool(b)));
+                        let s = String::from_utf8_lossy(&data[pos..pos + slen]).to_string();
+                        pos += slen;
+                        defaults.push(Some(DefaultValue::Str(s)));
+                    }
+                    4 => {
+                        if pos >= data.len() {
+                            break;
                         }
-                        _ => defs.push(None),
+                        let b = data[pos] != 0;
+                        pos += 1;
+                        defaults.push(Some(DefaultValue::Bool(b)));
                     }
+                    _ => defaults.push(None),
                 }
-                defs
-            } else {
-                vec![None; params.len()]
-            };
+            }
+            if pos + 4 > data.len() {
+                break;
+            }
+            let num_locals =
+                u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+                    as usize;
+            pos += 4;
+            let mut locals = Vec::with_capacity(num_locals);
+            for _ in 0..num_locals {
+                if pos + 4 > data.len() {
+                    break;
+                }
+                let llen =
+                    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+                        as usize;
+                pos += 4;
+                if pos + llen > data.len() {
+                    break;
+                }
+                locals.push(String::from_utf8_lossy(&data[pos..pos + llen]).to_string());
+                pos += llen;
+            }
             if pos + 8 > data.len() {
                 break;
             }
@@ -519,6 +761,7 @@ impl Bytecode {
                 name,
                 params,
                 defaults,
+                locals,
                 start,
             });
         }
@@ -595,6 +838,10 @@ impl Bytecode {
                 }
             }
         }
+        let lines = LineTable::decode(data, &mut pos).unwrap_or_else(|| {
+            warnings.push((pos, "Tabla de líneas truncada".to_string()));
+            LineTable::new()
+        });
         Ok((
             Bytecode {
                 instructions,
@@ -603,6 +850,7 @@ impl Bytecode {
                 nums,
                 names,
                 funcs,
+                lines,
             },
             warnings,
         ))
@@ -635,6 +883,7 @@ mod tests {
             nums: vec![42.0],
             names: vec![],
             funcs: vec![],
+            lines: LineTable::new(),
         };
         let encoded = bc.encode();
         let (decoded, _) = Bytecode::decode(&encoded).unwrap();
@@ -659,6 +908,80 @@ mod tests {
         assert!(result.unwrap_err().contains("Versión"));
     }
 
+    #[test]
+    fn test_rechaza_version_anterior_a_slots() {
+        let mut data = CHUNK_MAGIC.to_vec();
+        data.extend_from_slice(&7u32.to_le_bytes());
+        let err = Bytecode::decode(&data).unwrap_err();
+        assert!(err.contains("obsoleta"), "{}", err);
+        assert!(err.contains("lumen build"), "{}", err);
+    }
+
+    #[test]
+    fn test_roundtrip_locales_de_funcion() {
+        let bc = Bytecode {
+            instructions: vec![
+                Instruction::WithIdx(Opcode::LoadLocal, 1),
+                Instruction::WithIdx(Opcode::StoreGlobal, 0),
+            ],
+            strings: vec![],
+            ints: vec![],
+            nums: vec![],
+            names: vec!["g".to_string()],
+            funcs: vec![FuncMeta {
+                name: "f".to_string(),
+                params: vec!["a".to_string()],
+                defaults: vec![None],
+                locals: vec!["a".to_string(), "x".to_string()],
+                start: 0,
+            }],
+            lines: LineTable::new(),
+        };
+        let (decoded, _) = Bytecode::decode(&bc.encode()).unwrap();
+        assert_eq!(decoded.funcs[0].locals, vec!["a", "x"]);
+        assert!(matches!(
+            decoded.instructions[0],
+            Instruction::WithIdx(Opcode::LoadLocal, 1)
+        ));
+    }
+
+    #[test]
+    fn test_roundtrip_tabla_de_lineas() {
+        let mut bc = Bytecode::new();
+        bc.lines
+            .set_files(vec!["main.nv".to_string(), "lib/mates.nv".to_string()]);
+        bc.lines.push(0, 0, 3, 5);
+        bc.lines.push(4, 1, 300, 1);
+        bc.lines.push(9, 0, 0, 0);
+        bc.lines.push(10, 0, 2, 9);
+        let (decoded, warnings) = Bytecode::decode(&bc.encode()).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(decoded.lines, bc.lines);
+        assert_eq!(decoded.lines.lookup(3), Some((3, 5)));
+        assert_eq!(decoded.lines.lookup(8), Some((300, 1)));
+        assert_eq!(decoded.lines.lookup(9), None);
+        assert_eq!(decoded.lines.lookup(50), Some((2, 9)));
+        assert!(decoded.lines.is_stmt_start(4));
+        assert!(!decoded.lines.is_stmt_start(5));
+        assert_eq!(decoded.lines.ips_for_line(1, 300), vec![4]);
+        assert!(decoded.lines.ips_for_line(0, 300).is_empty());
+        assert_eq!(decoded.lines.entry_at(6).map(|e| e.file), Some(1));
+        assert_eq!(decoded.lines.file_index("lib/mates.nv"), Some(1));
+    }
+
+    #[test]
+    fn test_tabla_de_lineas_sin_repetidos() {
+        let mut lines = LineTable::new();
+        lines.push(0, 0, 1, 1);
+        lines.push(0, 0, 2, 1);
+        lines.push(3, 0, 2, 1);
+        assert_eq!(lines.entries().len(), 1);
+        assert_eq!(lines.lookup(7), Some((2, 1)));
+        // La misma línea en otro archivo es otra posición.
+        lines.push(5, 1, 2, 1);
+        assert_eq!(lines.entries().len(), 2);
+    }
+
     #[test]
     fn test_truncated_data() {
         let result = Bytecode::decode(&[0x4c, 0x55, 0x4d, 0x4e]);
@@ -1,6 +1,30 @@
 use crate::bytecode::*;
 use lumen_ir::ir::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// Cómo accede la función en curso a cada variable.
+#[derive(Default)]
+struct Frame {
+    /// Slot del frame al que va cada `Load`/`Store`/`Bind`, por índice de la
+    /// instrucción en la función (`LoadLocal`/`StoreLocal`). Las que no están
+    /// son globales (`LoadGlobal`/`StoreGlobal`).
+    access: HashMap<usize, usize>,
+    /// Slots que pasa cada `MakeClosure` a su lambda, por índice de la
+    /// instrucción.
+    closures: HashMap<usize, Vec<usize>>,
+    /// Slots que captura alguna clausura, propios o heredados de la función
+    /// que creó la lambda: se acceden por su celda (`LoadCell`/`StoreCell`).
+    cells: HashSet<usize>,
+}
+
+impl Frame {
+    fn cell_slot(&self, at: usize) -> Option<usize> {
+        self.access
+            .get(&at)
+            .copied()
+            .filter(|slot| self.cells.contains(slot))
+    }
+}
 
 pub struct Codegen {
     bytecode: Bytecode,
@@ -10,6 +34,7 @@ pub struct Codegen {
     int_cache: HashMap<i64, usize>,
     num_cache: HashMap<u64, usize>,
     name_cache: HashMap<String, usize>,
+    frame: Frame,
 }
 
 impl Default for Codegen {
@@ -28,6 +53,7 @@ impl Codegen {
             int_cache: HashMap::new(),
             num_cache: HashMap::new(),
             name_cache: HashMap::new(),
+            frame: Frame::default(),
         }
     }
 
@@ -76,18 +102,50 @@ impl Codegen {
         }
     }
 
+    /// Elige el acceso a `name` desde la instrucción `at` según el frame de
+    /// la función en curso.
+    fn resolve(&mut self, at: usize, name: &str, store: bool) -> Instruction {
+        if let Some(&slot) = self.frame.access.get(&at) {
+            let op = match (self.frame.cells.contains(&slot), store) {
+                (true, false) => Opcode::LoadCell,
+                (true, true) => Opcode::StoreCell,
+                (false, false) => Opcode::LoadLocal,
+                (false, true) => Opcode::StoreLocal,
+            };
+            return Instruction::WithIdx(op, slot);
+        }
+        let idx = self.intern_name(name);
+        let op = if store {
+            Opcode::StoreGlobal
+        } else {
+            Opcode::LoadGlobal
+        };
+        Instruction::WithIdx(op, idx)
+    }
+
     pub fn generate(mut self, program: &Program) -> (Bytecode, Vec<(usize, String)>) {
         let warnings = Vec::new();
+        self.bytecode.lines.set_files(program.files.clone());
+
+        // Lo que asigna o declara el nivel superior fuera de un bloque es
+        // global.
+        let globals: HashSet<String> = program
+            .funcs
+            .get(&program.entry)
+            .map(|f| f.declared.iter().chain(stored_names(f)).cloned().collect())
+            .unwrap_or_default();
+        let (mut func_locals, mut frames) = frame_layouts(program, &globals);
 
         // First pass: compute label positions (instruction indices)
         let mut running_offset = 0;
         for (func_name, func) in &program.funcs {
             self.func_starts.insert(func_name.clone(), running_offset);
-            for instr in &func.instrs {
+            let frame = &frames[func_name];
+            for (at, instr) in func.instrs.iter().enumerate() {
                 if let Instr::Label(l) = instr {
                     self.label_map.insert(*l, running_offset);
                 }
-                running_offset += instr_count(instr);
+                running_offset += Self::instr_count(at, instr, frame);
             }
         }
 
@@ -95,8 +153,11 @@ impl Codegen {
         for (func_name, func) in &program.funcs {
             let offset = self.bytecode.instructions.len();
             self.func_starts.insert(func_name.clone(), offset);
-            for instr in &func.instrs {
-                self.emit_ir(instr);
+            self.frame = frames.remove(func_name).unwrap_or_default();
+            // El prólogo de la función no hereda la posición de la anterior.
+            self.bytecode.lines.push(offset, 0, 0, 0);
+            for (at, instr) in func.instrs.iter().enumerate() {
+                self.emit_ir(at, instr);
             }
         }
 
@@ -136,6 +197,7 @@ impl Codegen {
                 name: name.clone(),
                 params,
                 defaults,
+                locals: func_locals.remove(name).unwrap_or_default(),
                 start: *start,
             });
         }
@@ -143,7 +205,7 @@ impl Codegen {
         (self.bytecode, warnings)
     }
 
-    fn emit_ir(&mut self, instr: &Instr) {
+    fn emit_ir(&mut self, at: usize, instr: &Instr) {
         match instr {
             Instr::ConstInt(n) => {
                 let idx = self.intern_int(*n);
@@ -169,16 +231,12 @@ impl Codegen {
                     .push(Instruction::WithBool(Opcode::PushBool, *b));
             }
             Instr::Load(name) => {
-                let idx = self.intern_name(name);
-                self.bytecode
-                    .instructions
-                    .push(Instruction::WithIdx(Opcode::Load, idx));
+                let instr = self.resolve(at, name, false);
+                self.bytecode.instructions.push(instr);
             }
             Instr::Store(name) => {
-                let idx = self.intern_name(name);
-                self.bytecode
-                    .instructions
-                    .push(Instruction::WithIdx(Opcode::Store, idx));
+                let instr = self.resolve(at, name, true);
+                self.bytecode.instructions.push(instr);
             }
             Instr::Binary(op) => {
                 let opcode = match op {
@@ -230,6 +288,29 @@ impl Codegen {
                     .instructions
                     .push(Instruction::WithIdx(Opcode::FuncRef, idx));
             }
+            Instr::MakeClosure(name, _) => {
+                let idx = self.intern_string(name);
+                self.bytecode
+                    .instructions
+                    .push(Instruction::WithIdx(Opcode::MakeClosure, idx));
+                let captured = self.frame.closures.get(&at).cloned().unwrap_or_default();
+                let count = self.intern_num(captured.len() as f64);
+                self.bytecode
+                    .instructions
+                    .push(Instruction::WithIdx(Opcode::Nop, count));
+                for slot in captured {
+                    self.bytecode
+                        .instructions
+                        .push(Instruction::WithIdx(Opcode::Nop, slot));
+                }
+            }
+            Instr::Bind(_) => {
+                if let Some(slot) = self.frame.cell_slot(at) {
+                    self.bytecode
+                        .instructions
+                        .push(Instruction::WithIdx(Opcode::DropCell, slot));
+                }
+            }
             Instr::CallValue(argc) => {
                 let idx = self.intern_num(*argc as f64);
                 self.bytecode
@@ -265,7 +346,41 @@ impl Codegen {
                     .instructions
                     .push(Instruction::Simple(Opcode::Halt));
             }
-            Instr::Label(_) => {}
+            Instr::DeferPush(label) => {
+                let offset = self.label_map.get(label).copied().unwrap_or(0);
+                let idx = self.intern_num(offset as f64);
+                self.bytecode
+                    .instructions
+                    .push(Instruction::WithIdx(Opcode::DeferPush, idx));
+            }
+            Instr::DeferRun(depth) => {
+                let idx = self.intern_num(*depth as f64);
+                self.bytecode
+                    .instructions
+                    .push(Instruction::WithIdx(Opcode::DeferRun, idx));
+            }
+            Instr::DeferEnd => {
+                self.bytecode
+                    .instructions
+                    .push(Instruction::Simple(Opcode::DeferEnd));
+            }
+            Instr::TryBegin(label) => {
+                let offset = self.label_map.get(label).copied().unwrap_or(0);
+                let idx = self.intern_num(offset as f64);
+                self.bytecode
+                    .instructions
+                    .push(Instruction::WithIdx(Opcode::TryBegin, idx));
+            }
+            Instr::TryEnd => {
+                self.bytecode
+                    .instructions
+                    .push(Instruction::Simple(Opcode::TryEnd));
+            }
+            Instr::Loc(file, line, col) => {
+                let ip = self.bytecode.instructions.len();
+                self.bytecode.lines.push(ip, *file, *line, *col);
+            }
+            Instr::Label(_) | Instr::ScopeBegin | Instr::ScopeEnd => {}
             Instr::Phi(_, _) => {}
             Instr::Read => {}
             Instr::Nop => {
@@ -389,15 +504,245 @@ impl Codegen {
     }
 }
 
-fn instr_count(instr: &Instr) -> usize {
-    match instr {
-        Instr::Label(_) | Instr::Phi(_, _) | Instr::Read | Instr::Nop => 0,
-        Instr::Call(_, _) => 2,
-        Instr::ArrayNew(_) => 1,
-        Instr::TupleNew(_) => 1,
-        Instr::TupleAccess(_) => 1,
-        Instr::StructNew(_, _) => 2,
-        Instr::EnumCtor { .. } => 3,
-        _ => 1,
+impl Codegen {
+    fn instr_count(at: usize, instr: &Instr, frame: &Frame) -> usize {
+        match instr {
+            Instr::Label(_)
+            | Instr::Phi(_, _)
+            | Instr::Loc(..)
+            | Instr::Read
+            | Instr::Nop
+            | Instr::ScopeBegin
+            | Instr::ScopeEnd => 0,
+            Instr::Call(_, _) => 2,
+            Instr::MakeClosure(..) => 2 + frame.closures.get(&at).map_or(0, Vec::len),
+            Instr::Bind(_) => frame.cell_slot(at).map_or(0, |_| 1),
+            Instr::ArrayNew(_) => 1,
+            Instr::TupleNew(_) => 1,
+            Instr::TupleAccess(_) => 1,
+            Instr::StructNew(_, _) => 2,
+            Instr::EnumCtor { .. } => 3,
+            _ => 1,
+        }
+    }
+}
+
+fn stored_names(func: &Func) -> impl Iterator<Item = &String> {
+    func.instrs.iter().filter_map(|i| match i {
+        Instr::Store(n) => Some(n),
+        _ => None,
+    })
+}
+
+/// Slots de una función mientras se recorren sus instrucciones: los de toda
+/// la función (`fixed`) y los de los ámbitos de bloque abiertos.
+#[derive(Default)]
+struct SlotScopes {
+    /// Nombre de cada slot. Una declaración que tapa a otra visible con el
+    /// mismo nombre lleva el número de slot (`x#3`) para que los nombres no
+    /// se repitan.
+    names: Vec<String>,
+    fixed: HashMap<String, usize>,
+    scopes: Vec<Vec<(String, usize)>>,
+    /// Slots de ámbitos ya cerrados. Solo se reusan para una declaración con
+    /// el mismo nombre, que tendrá el mismo tipo casi siempre.
+    freed: Vec<(String, usize)>,
+}
+
+impl SlotScopes {
+    fn slot(&mut self, name: &str) -> usize {
+        let slot = self.names.len();
+        let taken = self.names.iter().any(|n| n == name);
+        self.names.push(if taken {
+            format!("{name}#{slot}")
+        } else {
+            name.to_string()
+        });
+        slot
+    }
+
+    fn fix(&mut self, name: &str) {
+        if !self.fixed.contains_key(name) {
+            let slot = self.slot(name);
+            self.fixed.insert(name.to_string(), slot);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .flatten()
+            .find(|(n, _)| n == name)
+            .map(|&(_, slot)| slot)
+            .or_else(|| self.fixed.get(name).copied())
+    }
+
+    /// Slot de la declaración de `name` en el ámbito más interno: el que ya
+    /// tenía en ese ámbito, uno liberado con el mismo nombre o uno nuevo.
+    fn bind(&mut self, name: &str) -> usize {
+        if let Some(&(_, slot)) = self
+            .scopes
+            .last()
+            .and_then(|s| s.iter().find(|(n, _)| n == name))
+        {
+            return slot;
+        }
+        let slot = match self.freed.iter().position(|(n, _)| n == name) {
+            Some(i) => self.freed.swap_remove(i).1,
+            None => self.slot(name),
+        };
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((name.to_string(), slot));
+        }
+        slot
+    }
+}
+
+/// Nombres que la función declara o asigna sin que los tape un ámbito de
+/// bloque: son de toda la función (o globales en el nivel superior).
+fn unscoped_names(func: &Func) -> (Vec<&String>, Vec<&String>) {
+    let (mut binds, mut stores) = (Vec::new(), Vec::new());
+    let mut scopes: Vec<Vec<&String>> = Vec::new();
+    for instr in &func.instrs {
+        match instr {
+            Instr::ScopeBegin => scopes.push(Vec::new()),
+            Instr::ScopeEnd => {
+                scopes.pop();
+            }
+            Instr::Bind(n) => match scopes.last_mut() {
+                Some(scope) => scope.push(n),
+                None => binds.push(n),
+            },
+            Instr::Store(n) if !scopes.iter().flatten().any(|s| *s == n) => stores.push(n),
+            _ => {}
+        }
+    }
+    (binds, stores)
+}
+
+/// Variables que captura cada lambda, por nombre de la lambda.
+type Captures = Vec<(String, Vec<String>)>;
+
+/// Disposición del frame de `func`: nombre de cada slot, acceso de cada
+/// instrucción y variables que captura cada lambda que crea. Los slots de
+/// toda la función son los parámetros, las variables capturadas, las
+/// declaraciones fuera de un bloque, los temporales (`__*`) y cualquier
+/// otra asignación que no sea a una global; en el nivel superior
+/// (`entry`) no hay ninguno. Cada declaración dentro de un bloque tiene
+/// además su propio slot mientras el bloque está abierto, así que tapa a la
+/// de fuera sin pisarla.
+fn frame_layout(
+    func: &Func,
+    globals: &HashSet<String>,
+    captured: &[String],
+    entry: bool,
+) -> (Vec<String>, Frame, Captures) {
+    let mut slots = SlotScopes::default();
+    if !entry {
+        let (binds, stores) = unscoped_names(func);
+        let candidates = func.params.iter().chain(captured).chain(binds).chain(
+            stores
+                .into_iter()
+                .filter(|n| n.starts_with("__") || !globals.contains(*n)),
+        );
+        for name in candidates {
+            slots.fix(name);
+        }
+    }
+
+    let mut frame = Frame::default();
+    let mut sites = Vec::new();
+    for (at, instr) in func.instrs.iter().enumerate() {
+        match instr {
+            Instr::ScopeBegin => slots.scopes.push(Vec::new()),
+            Instr::ScopeEnd => {
+                let scope = slots.scopes.pop().unwrap_or_default();
+                slots.freed.extend(scope);
+            }
+            Instr::Bind(name) if !slots.scopes.is_empty() => {
+                frame.access.insert(at, slots.bind(name));
+            }
+            Instr::Bind(name) | Instr::Load(name) | Instr::Store(name) => {
+                if let Some(slot) = slots.lookup(name) {
+                    frame.access.insert(at, slot);
+                }
+            }
+            Instr::MakeClosure(lambda, free) => {
+                let (names, captured): (Vec<String>, Vec<usize>) = free
+                    .iter()
+                    .filter_map(|n| Some((n.clone(), slots.lookup(n)?)))
+                    .unzip();
+                frame.cells.extend(&captured);
+                frame.closures.insert(at, captured);
+                sites.push((lambda.clone(), names));
+            }
+            _ => {}
+        }
+    }
+    // Los slots que la lambda captura de quien la crea también son celdas.
+    frame
+        .cells
+        .extend(captured.iter().filter_map(|n| slots.fixed.get(n).copied()));
+    (slots.names, frame, sites)
+}
+
+/// Slots y frame de cada función. Una lambda captura las variables libres
+/// que son slots de la función que la crea en el punto donde la crea (lo
+/// que incluye lo que esa función capturó a su vez); las demás son
+/// globales.
+fn frame_layouts(
+    program: &Program,
+    globals: &HashSet<String>,
+) -> (HashMap<String, Vec<String>>, HashMap<String, Frame>) {
+    let mut parents: HashMap<&str, &str> = HashMap::new();
+    for (name, func) in &program.funcs {
+        for instr in &func.instrs {
+            if let Instr::MakeClosure(lambda, _) = instr {
+                parents.insert(lambda, name);
+            }
+        }
+    }
+
+    struct Layouts<'a> {
+        program: &'a Program,
+        globals: &'a HashSet<String>,
+        parents: HashMap<&'a str, &'a str>,
+        locals: HashMap<String, Vec<String>>,
+        frames: HashMap<String, Frame>,
+        captures: HashMap<String, Vec<String>>,
+    }
+
+    impl Layouts<'_> {
+        fn resolve(&mut self, name: &str) {
+            if self.locals.contains_key(name) {
+                return;
+            }
+            let Some(func) = self.program.funcs.get(name) else {
+                return;
+            };
+            if let Some(&parent) = self.parents.get(name) {
+                self.resolve(parent);
+            }
+            let captured = self.captures.get(name).cloned().unwrap_or_default();
+            let entry = name == self.program.entry;
+            let (locals, frame, sites) = frame_layout(func, self.globals, &captured, entry);
+            self.captures.extend(sites);
+            self.locals.insert(name.to_string(), locals);
+            self.frames.insert(name.to_string(), frame);
+        }
+    }
+
+    let mut layouts = Layouts {
+        program,
+        globals,
+        parents,
+        locals: HashMap::new(),
+        frames: HashMap::new(),
+        captures: HashMap::new(),
+    };
+    for name in program.funcs.keys() {
+        layouts.resolve(name);
     }
+    (layouts.locals, layouts.frames)
 }
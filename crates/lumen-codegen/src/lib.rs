@@ -2,6 +2,8 @@ pub mod bytecode;
 pub mod codegen;
 pub mod disasm;
 
-pub use bytecode::{Bytecode, FuncMeta, Instruction, Opcode, CHUNK_MAGIC, CHUNK_VERSION};
+pub use bytecode::{
+    Bytecode, FuncMeta, Instruction, LineEntry, LineTable, Opcode, CHUNK_MAGIC, CHUNK_VERSION,
+};
 pub use codegen::Codegen;
 pub use disasm::disassemble;
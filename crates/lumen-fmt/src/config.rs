@@ -0,0 +1,174 @@
+//! Opciones de `lumen fmt` y dónde se buscan: el primer `.lumen-fmt.toml`
+//! subiendo desde el archivo, o la tabla `[fmt]` del `lumen.toml` de la raíz
+//! del proyecto.
+//!
+//! ```toml
+//! indent_spaces = 4
+//! max_width = 100
+//! brace_style = "misma_linea"   # "linea_nueva", "conservar"
+//! trailing_comma = "conservar"  # "siempre", "nunca"
+//! keywords = "conservar"        # "es", "en"
+//! ```
+
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FmtConfig {
+    pub indent_spaces: usize,
+    /// Ancho máximo de línea. Las más largas se parten por los argumentos de
+    /// una llamada, los campos de una estructura o una cadena de operadores.
+    /// `0` no parte nada.
+    pub max_width: usize,
+    pub brace_style: BraceStyle,
+    pub trailing_comma: TrailingComma,
+    pub keywords: KeywordStyle,
+}
+
+impl Default for FmtConfig {
+    fn default() -> Self {
+        Self {
+            indent_spaces: 4,
+            max_width: 100,
+            brace_style: BraceStyle::default(),
+            trailing_comma: TrailingComma::default(),
+            keywords: KeywordStyle::default(),
+        }
+    }
+}
+
+/// Dónde va la `{` que abre un bloque (función, `si`, `mientras`,
+/// estructura...). Las llaves de un literal no se mueven.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum BraceStyle {
+    #[default]
+    #[serde(rename = "misma_linea", alias = "same_line")]
+    SameLine,
+    #[serde(rename = "linea_nueva", alias = "next_line")]
+    NextLine,
+    #[serde(rename = "conservar", alias = "preserve")]
+    Preserve,
+}
+
+/// Coma tras el último elemento de argumentos, listas, campos y variantes.
+/// `Always` la pone solo cuando el cierre va en su propia línea.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum TrailingComma {
+    #[default]
+    #[serde(rename = "conservar", alias = "preserve")]
+    Preserve,
+    #[serde(rename = "siempre", alias = "always")]
+    Always,
+    #[serde(rename = "nunca", alias = "never")]
+    Never,
+}
+
+/// Idioma de las palabras clave. El lexer acepta las dos escrituras de cada
+/// una; con `Spanish` o `English` se reescribe todo el archivo en una.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum KeywordStyle {
+    #[default]
+    #[serde(rename = "conservar", alias = "preserve")]
+    Preserve,
+    #[serde(rename = "es", alias = "español")]
+    Spanish,
+    #[serde(rename = "en", alias = "inglés")]
+    English,
+}
+
+/// Configuración para `start` (un archivo o directorio): sube por los
+/// directorios hasta dar con un `.lumen-fmt.toml` o con el `lumen.toml` de la
+/// raíz del proyecto, donde se para. `Ok(None)` si no hay ninguna.
+pub fn find_config(start: &Path) -> Result<Option<FmtConfig>, String> {
+    let start = fs::canonicalize(start).unwrap_or_else(|_| start.to_path_buf());
+    let mut dir = if start.is_dir() {
+        Some(start.as_path())
+    } else {
+        start.parent()
+    };
+    while let Some(d) = dir {
+        let own = d.join(".lumen-fmt.toml");
+        if own.is_file() {
+            return read_config(&own, None);
+        }
+        let manifest = d.join("lumen.toml");
+        if manifest.is_file() {
+            return read_config(&manifest, Some("fmt"));
+        }
+        dir = d.parent();
+    }
+    Ok(None)
+}
+
+/// Configuración para `start`, o la de por defecto si no hay o es inválida.
+pub fn load_config_for(start: &Path) -> FmtConfig {
+    find_config(start).ok().flatten().unwrap_or_default()
+}
+
+/// Configuración del directorio actual.
+pub fn load_config() -> FmtConfig {
+    load_config_for(Path::new("."))
+}
+
+fn read_config(path: &Path, section: Option<&str>) -> Result<Option<FmtConfig>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("No se pudo leer '{}': {}", path.display(), e))?;
+    let mut value: toml::Value = toml::from_str(&content)
+        .map_err(|e| format!("TOML inválido en '{}': {}", path.display(), e))?;
+    if let Some(name) = section {
+        match value.get(name) {
+            Some(table) => value = table.clone(),
+            None => return Ok(None),
+        }
+    }
+    value.try_into().map(Some).map_err(|e| {
+        format!(
+            "Configuración de formato inválida en '{}': {}",
+            path.display(),
+            e
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_busca_hasta_la_raiz_del_proyecto() {
+        let tmp = std::env::temp_dir().join(format!("lumen_fmt_cfg_{}", std::process::id()));
+        let src = tmp.join("proyecto").join("src").join("modelos");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(tmp.join(".lumen-fmt.toml"), "indent_spaces = 8\n").unwrap();
+        fs::write(
+            tmp.join("proyecto").join("lumen.toml"),
+            "[proyecto]\nnombre = \"x\"\n\n[fmt]\nindent_spaces = 2\nbrace_style = \"linea_nueva\"\nkeywords = \"en\"\n",
+        )
+        .unwrap();
+
+        // El `lumen.toml` de la raíz gana al `.lumen-fmt.toml` de más arriba.
+        let config = find_config(&src.join("a.nv")).unwrap().unwrap();
+        assert_eq!(config.indent_spaces, 2);
+        assert_eq!(config.brace_style, BraceStyle::NextLine);
+        assert_eq!(config.keywords, KeywordStyle::English);
+        assert_eq!(config.max_width, 100);
+
+        // Un `.lumen-fmt.toml` más cercano gana al manifiesto.
+        fs::write(src.join(".lumen-fmt.toml"), "trailing_comma = \"always\"\n").unwrap();
+        let config = find_config(&src).unwrap().unwrap();
+        assert_eq!(config.trailing_comma, TrailingComma::Always);
+        assert_eq!(config.indent_spaces, 4);
+
+        fs::write(src.join(".lumen-fmt.toml"), "sangria = 3\n").unwrap();
+        assert!(find_config(&src).is_err());
+        assert_eq!(load_config_for(&src), FmtConfig::default());
+
+        // Sin tabla `[fmt]` en el manifiesto no hay configuración.
+        fs::remove_file(src.join(".lumen-fmt.toml")).unwrap();
+        fs::write(tmp.join("proyecto").join("lumen.toml"), "[proyecto]\n").unwrap();
+        assert_eq!(find_config(&src).unwrap(), None);
+        fs::remove_dir_all(&tmp).unwrap();
+    }
+}
@@ -1,36 +1,113 @@
-use lumen_lexer::Lexer;
-use lumen_parser::ast::*;
-use lumen_parser::Parser;
+//! Formateador de `lumen fmt`. Trabaja sobre los tokens con su trivia, no
+//! sobre el AST: conserva comentarios, líneas en blanco y la escritura de
+//! cada token salvo lo que pida la configuración (idioma de las palabras
+//! clave, sitio de las llaves, comas finales y líneas demasiado anchas). El
+//! parser rechaza los archivos con errores de sintaxis y dice dónde empieza
+//! cada sentencia y cada bloque, que siempre van en líneas propias, y qué
+//! operadores son binarios, que siempre van entre espacios.
 
-/// Formatea código fuente LÚMEN.
-/// Retorna el código formateado o una lista de errores.
-#[derive(Debug, Clone, serde::Deserialize)]
-pub struct FmtConfig {
-    pub indent_spaces: usize,
-}
+mod config;
+mod printer;
+mod rewrite;
 
-impl Default for FmtConfig {
-    fn default() -> Self {
-        Self { indent_spaces: 4 }
-    }
-}
+pub use config::{
+    find_config, load_config, load_config_for, BraceStyle, FmtConfig, KeywordStyle, TrailingComma,
+};
+
+use lumen_lexer::{Lexer, Token, TokenKind, TriviaToken};
+use lumen_parser::{Parser, TokenRoles};
+use printer::Printed;
+use rewrite::Place;
+use std::collections::HashSet;
+use std::ops::Range;
 
+/// Formatea código fuente LÚMEN con la configuración del directorio actual.
+/// Retorna el código formateado o una lista de errores.
 pub fn format_source(source: &str) -> Result<String, Vec<String>> {
     let config = load_config();
     format_source_with_config(source, &config)
 }
 
-pub fn load_config() -> FmtConfig {
-    if let Ok(content) = std::fs::read_to_string(".lumen-fmt.toml") {
-        toml::from_str(&content).unwrap_or_default()
-    } else {
-        FmtConfig::default()
+pub fn format_source_with_config(source: &str, config: &FmtConfig) -> Result<String, Vec<String>> {
+    format_tokens(source, config).map(|(_, printed)| printed.output)
+}
+
+/// Reemplazo de las líneas `start..end` (desde 0, sin incluir `end`) del
+/// fuente por `text`, que acaba en salto de línea.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Formatea solo las líneas `lines` (desde 0, sin incluir el final). El rango
+/// se amplía a las líneas enteras de las sentencias que cortaría: cuando una
+/// línea se parte o dos se juntan, lo que hay a los dos lados tiene que
+/// entrar. `Ok(None)` si esas líneas ya estaban formateadas.
+pub fn format_range(
+    source: &str,
+    config: &FmtConfig,
+    lines: Range<usize>,
+) -> Result<Option<LineEdit>, Vec<String>> {
+    let (tokens, printed) = format_tokens(source, config)?;
+    let placed = rewrite::place(&tokens, &printed);
+    // Sin el `Eof`; las líneas de los spans empiezan en 1.
+    let count = tokens.len().saturating_sub(1);
+    let src_line = |k: usize| tokens[k].token.span.start.line.saturating_sub(1);
+    let src_end = |k: usize| tokens[k].token.span.end.line.saturating_sub(1);
+    let selected: Vec<usize> = (0..count)
+        .filter(|&k| lines.contains(&src_line(k)))
+        .collect();
+    let (Some(&first), Some(&last)) = (selected.first(), selected.last()) else {
+        return Ok(None);
+    };
+
+    let (mut a, mut b) = (first, last);
+    loop {
+        let (old_a, old_b) = (a, b);
+        while a > 0 && shares_line(&tokens, &placed, a - 1, a) {
+            a -= 1;
+        }
+        while b + 1 < count && shares_line(&tokens, &placed, b, b + 1) {
+            b += 1;
+        }
+        if (a, b) == (old_a, old_b) {
+            break;
+        }
+    }
+
+    let (start, end) = (src_line(a), src_end(b) + 1);
+    let out_lines: Vec<&str> = printed.output.lines().collect();
+    let text: String = out_lines[placed[a].line..=placed[b].end_line]
+        .iter()
+        .map(|l| format!("{}\n", l))
+        .collect();
+    let old: String = source
+        .lines()
+        .skip(start)
+        .take(end - start)
+        .map(|l| format!("{}\n", l))
+        .collect();
+    if text == old {
+        return Ok(None);
     }
+    Ok(Some(LineEdit { start, end, text }))
 }
 
-pub fn format_source_with_config(source: &str, config: &FmtConfig) -> Result<String, Vec<String>> {
+/// Si los tokens `k` y `k + 1` comparten línea en el fuente o en la salida.
+fn shares_line(tokens: &[TriviaToken], placed: &[Place], k: usize, next: usize) -> bool {
+    tokens[k].token.span.end.line == tokens[next].token.span.start.line
+        || placed[k].end_line == placed[next].line
+}
+
+/// Aplica la configuración a los tokens del fuente y los imprime.
+fn format_tokens(
+    source: &str,
+    config: &FmtConfig,
+) -> Result<(Vec<TriviaToken>, Printed), Vec<String>> {
     let lexer = Lexer::new(source);
-    let (tokens, lex_errors) = lexer.tokenize();
+    let (mut tokens, lex_errors) = lexer.tokenize_with_trivia();
     if !lex_errors.is_empty() {
         return Err(lex_errors
             .iter()
@@ -42,804 +119,250 @@ pub fn format_source_with_config(source: &str, config: &FmtConfig) -> Result<Str
             })
             .collect());
     }
+    let roles = parse(&tokens.iter().map(|t| t.token.clone()).collect::<Vec<_>>())?;
+    let original = significant(tokens.iter().map(|t| &t.token.kind));
+    let commas = count_commas(&tokens);
+    // Las comas finales mueven los índices: los operadores se recuerdan por
+    // su posición en el fuente.
+    let binary_ops: HashSet<(usize, usize)> = roles
+        .binary_ops
+        .iter()
+        .map(|&k| {
+            let start = tokens[k].token.span.start;
+            (start.line, start.col)
+        })
+        .collect();
 
-    let parser = Parser::new(tokens);
-    let (program, parse_errors) = parser.parse();
-    if !parse_errors.is_empty() {
-        return Err(parse_errors
-            .iter()
-            .map(|e| {
-                format!(
-                    "{} [{}:{}]: {} ({})",
-                    e.code, e.span.start.line, e.span.start.col, e.message, e.suggestion
-                )
-            })
-            .collect());
+    rewrite::statements(&mut tokens, &roles);
+    rewrite::keywords(&mut tokens, config.keywords);
+    // Partir una línea puede dejar un cierre solo en la suya (y pedir una coma
+    // final), una llave al final de una línea o otra línea todavía ancha: se
+    // repite hasta que no cambie.
+    let mut printed;
+    let mut rounds = 0;
+    loop {
+        rewrite::braces(&mut tokens, config.brace_style);
+        rewrite::trailing_commas(&mut tokens, config.trailing_comma);
+        printed = printer::print(&tokens, config.indent_spaces, &binary_ops);
+        let points = rewrite::wrap_points(&tokens, &printed, config.max_width);
+        rounds += 1;
+        if points.is_empty() || rounds > MAX_ROUNDS {
+            break;
+        }
+        rewrite::apply_breaks(&mut tokens, &points);
     }
 
-    let mut fmt = Formatter::new(config.indent_spaces);
-    fmt.fmt_program(&program);
-    Ok(fmt.output.trim_end().to_string() + "\n")
+    // Red de seguridad: el resultado tiene que ser el mismo programa, salvo
+    // la escritura de las palabras clave y las comas finales.
+    let relexed = Lexer::new(&printed.output).tokenize().0;
+    let same = significant(relexed.iter().map(|t| &t.kind)) == original;
+    if !same || (count_commas(&tokens) != commas && parse(&relexed).is_err()) {
+        return Err(vec![
+            "FMT001: el formateo cambiaría los tokens del programa; el archivo no se toca"
+                .to_string(),
+        ]);
+    }
+    Ok((tokens, printed))
 }
 
-struct Formatter {
-    output: String,
-    indent: usize,
-    indent_spaces: usize,
-}
+/// Pasadas de partir líneas; cada una parte por un nivel más adentro.
+const MAX_ROUNDS: usize = 16;
 
-impl Formatter {
-    fn new(indent_spaces: usize) -> Self {
-        Self {
-            output: String::new(),
-            indent: 0,
-            indent_spaces,
-        }
+fn parse(tokens: &[Token]) -> Result<TokenRoles, Vec<String>> {
+    let (_, errors, roles) = Parser::new(tokens.to_vec()).parse_with_roles();
+    if errors.is_empty() {
+        return Ok(roles);
     }
+    Err(errors
+        .iter()
+        .map(|e| {
+            format!(
+                "{} [{}:{}]: {} ({})",
+                e.code, e.span.start.line, e.span.start.col, e.message, e.suggestion
+            )
+        })
+        .collect())
+}
 
-    fn push(&mut self, s: &str) {
-        self.output.push_str(s);
-    }
-    fn push_indent(&mut self) {
-        for _ in 0..self.indent {
-            for _ in 0..self.indent_spaces {
-                self.output.push(' ');
-            }
-        }
-    }
-    fn newline(&mut self) {
-        self.output.push('\n');
-    }
-    fn indent_inc(&mut self) {
-        self.indent += 1;
-    }
-    fn indent_dec(&mut self) {
-        self.indent = self.indent.saturating_sub(1);
-    }
+fn count_commas(tokens: &[TriviaToken]) -> usize {
+    tokens
+        .iter()
+        .filter(|t| t.token.kind == TokenKind::Comma)
+        .count()
+}
 
-    fn fmt_program(&mut self, program: &[DeclOrStmt]) {
-        for (i, node) in program.iter().enumerate() {
-            if i > 0 {
-                self.newline();
-            }
-            self.fmt_decl_or_stmt(node);
+/// Tokens que tienen que quedar iguales: las palabras clave en español y sin
+/// las comas que van justo antes de un cierre.
+fn significant<'a>(kinds: impl Iterator<Item = &'a TokenKind>) -> Vec<TokenKind> {
+    let mut result: Vec<TokenKind> = Vec::new();
+    for kind in kinds {
+        if printer::is_closer(kind) && result.last() == Some(&TokenKind::Comma) {
+            result.pop();
         }
+        result.push(rewrite::canonical(kind));
     }
+    result
+}
 
-    fn fmt_decl_or_stmt(&mut self, node: &DeclOrStmt) {
-        match node {
-            DeclOrStmt::Decl(d) => self.fmt_decl(d),
-            DeclOrStmt::Stmt(s) => self.fmt_stmt(s, true),
-        }
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn fmt_decl(&mut self, decl: &Decl) {
-        match decl {
-            Decl::Variable {
-                var_type,
-                name,
-                init,
-                ..
-            } => {
-                self.push_indent();
-                self.push(&format_type(var_type));
-                self.push(" ");
-                self.push(name);
-                if let Some(val) = init {
-                    self.push(" = ");
-                    self.fmt_expr(val);
-                }
-                self.push(";");
-                self.newline();
-            }
-            Decl::Const {
-                var_type,
-                name,
-                value,
-                ..
-            } => {
-                self.push_indent();
-                self.push("const ");
-                self.push(&format_type(var_type));
-                self.push(" ");
-                self.push(name);
-                self.push(" = ");
-                self.fmt_expr(value);
-                self.push(";");
-                self.newline();
-            }
-            Decl::Function {
-                return_type,
-                name,
-                params,
-                body,
-                type_params,
-                ..
-            } => {
-                if !type_params.is_empty() {
-                    self.push_indent();
-                    self.push("funcion ");
-                    self.push(&format_type(return_type));
-                    self.push(" ");
-                    self.push(name);
-                    self.push("<");
-                    self.push(&type_params.join(", "));
-                    self.push(">");
-                    self.push("(");
-                    self.fmt_params(params);
-                    self.push(") ");
-                } else {
-                    self.push_indent();
-                    self.push("funcion ");
-                    self.push(&format_type(return_type));
-                    self.push(" ");
-                    self.push(name);
-                    self.push("(");
-                    self.fmt_params(params);
-                    self.push(") ");
-                }
-                self.fmt_block(body);
-            }
-            Decl::Struct { name, fields, .. } => {
-                self.push_indent();
-                self.push("estructura ");
-                self.push(name);
-                self.push(" {");
-                self.newline();
-                self.indent_inc();
-                for f in fields {
-                    self.push_indent();
-                    self.push(&f.name);
-                    self.push(": ");
-                    self.push(&format_type(&f.field_type));
-                    self.push(",");
-                    self.newline();
-                }
-                self.indent_dec();
-                self.push_indent();
-                self.push("}");
-                self.newline();
-            }
-            Decl::Enum { name, variants, .. } => {
-                self.push_indent();
-                self.push("enum ");
-                self.push(name);
-                self.push(" { ");
-                for (i, v) in variants.iter().enumerate() {
-                    if i > 0 {
-                        self.push(", ");
-                    }
-                    self.push(&v.name);
-                    if !v.types.is_empty() {
-                        self.push("(");
-                        for (j, t) in v.types.iter().enumerate() {
-                            if j > 0 {
-                                self.push(", ");
-                            }
-                            self.push(&format_type(t));
-                        }
-                        self.push(")");
-                    }
-                }
-                self.push(" }");
-                self.newline();
-            }
-            Decl::Rasgo { name, methods, .. } => {
-                self.push_indent();
-                self.push("rasgo ");
-                self.push(name);
-                self.push(" {");
-                self.newline();
-                self.indent_inc();
-                for m in methods {
-                    self.push_indent();
-                    self.push("funcion ");
-                    self.push(&format_type(&m.return_type));
-                    self.push(" ");
-                    self.push(&m.name);
-                    self.push("(");
-                    for (j, p) in m.params.iter().enumerate() {
-                        if j > 0 {
-                            self.push(", ");
-                        }
-                        self.push(&p.name);
-                        self.push(": ");
-                        self.push(&format_type(&p.param_type));
-                    }
-                    self.push(");");
-                    self.newline();
-                }
-                self.indent_dec();
-                self.push_indent();
-                self.push("}");
-                self.newline();
-            }
-            Decl::ImplRasgo {
-                trait_name,
-                target_type,
-                methods,
-                ..
-            } => {
-                self.push_indent();
-                self.push("impl ");
-                self.push(trait_name);
-                self.push(" para ");
-                self.push(&format_type(target_type));
-                self.push(" {");
-                self.newline();
-                self.indent_inc();
-                for m in methods {
-                    if let Decl::Function {
-                        return_type,
-                        name,
-                        params,
-                        body,
-                        ..
-                    } = m
-                    {
-                        self.push_indent();
-                        self.push("funcion ");
-                        self.push(&format_type(return_type));
-                        self.push(" ");
-                        self.push(name);
-                        self.push("(");
-                        self.fmt_params(params);
-                        self.push(") ");
-                        self.fmt_block(body);
-                    }
-                }
-                self.indent_dec();
-                self.push_indent();
-                self.push("}");
-                self.newline();
-            }
-            _ => {}
-        }
+    fn fmt(src: &str) -> String {
+        format_source_with_config(src, &FmtConfig::default()).unwrap()
     }
 
-    fn fmt_stmt(&mut self, stmt: &Stmt, top_level: bool) {
-        match stmt {
-            Stmt::If {
-                condition,
-                then_body,
-                else_body,
-                ..
-            } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push("si ");
-                let mut cond = condition.as_ref();
-                while let Expr::Grouping { expr, .. } = cond {
-                    cond = expr.as_ref();
-                }
-                self.fmt_expr(cond);
-                self.push(" ");
-                let has_else = else_body.as_ref().map(|eb| !eb.is_empty()).unwrap_or(false);
-                self.fmt_block_inline(then_body, !has_else);
-                if let Some(ref eb) = else_body {
-                    if !eb.is_empty() {
-                        self.push(" sino ");
-                        if eb.len() == 1 {
-                            if let DeclOrStmt::Stmt(s @ Stmt::If { .. }) = &eb[0] {
-                                self.fmt_stmt(s, false);
-                            } else {
-                                self.fmt_block(eb);
-                            }
-                        } else {
-                            self.fmt_block(eb);
-                        }
-                    }
-                }
-            }
-            Stmt::While {
-                condition, body, ..
-            } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push("mientras ");
-                let mut cond = condition.as_ref();
-                while let Expr::Grouping { expr, .. } = cond {
-                    cond = expr.as_ref();
-                }
-                self.fmt_expr(cond);
-                self.push(" ");
-                self.fmt_block(body);
-            }
-            Stmt::ForEach {
-                var_name,
-                expr,
-                body,
-                ..
-            } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push("para ");
-                self.push(var_name);
-                self.push(" en ");
-                self.fmt_expr(expr);
-                self.push(" ");
-                self.fmt_block(body);
-            }
-            Stmt::Return { value, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push("retornar");
-                if let Some(v) = value {
-                    self.push(" ");
-                    self.fmt_expr(v);
-                }
-                self.push(";");
-                self.newline();
-            }
-            Stmt::Break { label, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push("romper");
-                if let Some(l) = label {
-                    self.push(" ");
-                    self.push(l);
-                }
-                self.push(";");
-                self.newline();
-            }
-            Stmt::Continue { label, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push("continuar");
-                if let Some(l) = label {
-                    self.push(" ");
-                    self.push(l);
-                }
-                self.push(";");
-                self.newline();
-            }
-            Stmt::Expr { expr, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.fmt_expr(expr);
-                self.push(";");
-                self.newline();
-            }
-            Stmt::Assignment { name, value, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push(name);
-                self.push(" = ");
-                self.fmt_expr(value);
-                self.push(";");
-                self.newline();
-            }
-            Stmt::Match { expr, arms, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push("elegir (");
-                self.fmt_expr(expr);
-                self.push(") {");
-                self.newline();
-                self.indent_inc();
-                for arm in arms {
-                    self.push_indent();
-                    self.push("caso ");
-                    self.fmt_expr(&arm.value);
-                    if let Some(ref guard) = arm.guard {
-                        self.push(" si ");
-                        self.fmt_expr(guard);
-                    }
-                    self.push(": ");
-                    self.fmt_block(&arm.body);
-                }
-                self.indent_dec();
-                self.push_indent();
-                self.push("}");
-                self.newline();
-            }
-            Stmt::Block { stmts, .. } => {
-                self.fmt_block(stmts);
-            }
-            Stmt::Posponer { body, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push("posponer ");
-                self.fmt_block(body);
-            }
-            Stmt::TryCatch {
-                try_body,
-                err_var,
-                catch_body,
-                ..
-            } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push("intentar ");
-                self.fmt_block(try_body);
-                self.push(&format!(" atrapar ({}) ", err_var));
-                self.fmt_block(catch_body);
-            }
-            Stmt::Import { path, alias, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push("importar ");
-                self.push(&format!("\"{}\"", path));
-                if let Some(a) = alias {
-                    self.push(" como ");
-                    self.push(a);
-                }
-                self.push(";");
-                self.newline();
-            }
-            Stmt::InlineAsm { code, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push(&format!("ensamblador {{ \"{}\" }}", escape_string(code)));
-                self.newline();
-            }
-            Stmt::InlineC { code, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push(&format!("bloque_c {{ \"{}\" }}", escape_string(code)));
-                self.newline();
-            }
-            Stmt::InlineRust { code, .. } => {
-                if top_level {
-                    self.push_indent();
-                }
-                self.push(&format!("bloque_rust {{ \"{}\" }}", escape_string(code)));
-                self.newline();
-            }
-            _ => {}
-        }
+    #[test]
+    fn test_format_simple() {
+        let result = format_source("imprimir(\"hola\");").unwrap();
+        assert!(result.contains("hola"));
     }
 
-    fn fmt_block(&mut self, body: &[DeclOrStmt]) {
-        self.fmt_block_inline(body, true);
+    #[test]
+    fn test_format_function() {
+        let src = "funcion entero suma(entero a,entero b){retornar a+b;}";
+        let result = format_source(src).unwrap();
+        assert!(result.contains("funcion entero suma"));
     }
 
-    fn fmt_block_inline(&mut self, body: &[DeclOrStmt], trailing_newline: bool) {
-        self.push("{");
-        self.newline();
-        self.indent_inc();
-        for node in body {
-            self.fmt_decl_or_stmt(node);
-        }
-        self.indent_dec();
-        self.push_indent();
-        self.push("}");
-        if trailing_newline {
-            self.newline();
-        }
+    #[test]
+    fn test_sangria_y_espacios() {
+        let src = "funcion entero suma(entero a,entero b){\nretornar a+b;\n}\n";
+        assert_eq!(
+            fmt(src),
+            "funcion entero suma(entero a, entero b) {\n    retornar a + b;\n}\n"
+        );
+        let src = "si x>0&&y<3 {\n  lista<entero> l=[1,2];\n    imprimir( l[0] );\n}\nsino{imprimir(-x);}\n";
+        assert_eq!(
+            fmt(src),
+            "si x > 0 && y < 3 {\n    lista<entero> l = [1, 2];\n    imprimir(l[0]);\n}\nsino {\n    imprimir(-x);\n}\n"
+        );
     }
 
-    fn fmt_params(&mut self, params: &[Param]) {
-        for (i, p) in params.iter().enumerate() {
-            if i > 0 {
-                self.push(", ");
-            }
-            if p.name == "self" || p.name == "yo" {
-                self.push(&p.name);
-            } else {
-                self.push(&format_type(&p.param_type));
-                self.push(" ");
-                self.push(&p.name);
-            }
-        }
+    #[test]
+    fn test_conserva_comentarios() {
+        let src = "/// Suma dos números.\nfuncion entero suma(entero a, entero b) {\n        // el resultado\n    retornar a + b;   // sin desbordes\n\n\n\n    /* fin */\n}\n// al final\n";
+        assert_eq!(
+            fmt(src),
+            "/// Suma dos números.\nfuncion entero suma(entero a, entero b) {\n    // el resultado\n    retornar a + b; // sin desbordes\n\n    /* fin */\n}\n// al final\n"
+        );
     }
 
-    fn fmt_expr(&mut self, expr: &Expr) {
-        match expr {
-            Expr::Int { value, .. } => self.push(&value.to_string()),
-            Expr::Float { value, .. } => self.push(&value.to_string()),
-            Expr::Str { value, .. } => self.push(&format!("\"{}\"", escape_string(value))),
-            Expr::Bool { value, .. } => self.push(if *value { "verdadero" } else { "falso" }),
-            Expr::Ident { name, .. } => self.push(name),
-            Expr::Binary {
-                op, left, right, ..
-            } => {
-                self.fmt_expr(left);
-                self.push(" ");
-                self.push(fmt_binop(op));
-                self.push(" ");
-                self.fmt_expr(right);
-            }
-            Expr::Unary { op, operand, .. } => {
-                self.push(fmt_unop(op));
-                self.fmt_expr(operand);
-            }
-            Expr::Ternary {
-                condition,
-                true_branch,
-                false_branch,
-                ..
-            } => {
-                self.fmt_expr(condition);
-                self.push(" ? ");
-                self.fmt_expr(true_branch);
-                self.push(" : ");
-                self.fmt_expr(false_branch);
-            }
-            Expr::Call {
-                callee,
-                args,
-                type_args,
-                ..
-            } => {
-                self.fmt_expr(callee);
-                if !type_args.is_empty() {
-                    self.push("<");
-                    for (i, t) in type_args.iter().enumerate() {
-                        if i > 0 {
-                            self.push(", ");
-                        }
-                        self.push(&format_type(t));
-                    }
-                    self.push(">");
-                }
-                self.push("(");
-                for (i, a) in args.iter().enumerate() {
-                    if i > 0 {
-                        self.push(", ");
-                    }
-                    self.fmt_expr(a);
-                }
-                self.push(")");
-            }
-            Expr::List { items, .. } => {
-                self.push("[");
-                for (i, item) in items.iter().enumerate() {
-                    if i > 0 {
-                        self.push(", ");
-                    }
-                    self.fmt_expr(item);
-                }
-                self.push("]");
-            }
-            Expr::Index { expr, index, .. } => {
-                self.fmt_expr(expr);
-                self.push("[");
-                self.fmt_expr(index);
-                self.push("]");
-            }
-            Expr::MethodCall {
-                expr, method, args, ..
-            } => {
-                self.fmt_expr(expr);
-                self.push(".");
-                self.push(method);
-                self.push("(");
-                for (i, a) in args.iter().enumerate() {
-                    if i > 0 {
-                        self.push(", ");
-                    }
-                    self.fmt_expr(a);
-                }
-                self.push(")");
-            }
-            Expr::FieldAccess { expr, field, .. } => {
-                self.fmt_expr(expr);
-                self.push(".");
-                self.push(field);
-            }
-            Expr::SafeFieldAccess { expr, field, .. } => {
-                self.fmt_expr(expr);
-                self.push("?.");
-                self.push(field);
-            }
-            Expr::Elvis { expr, default, .. } => {
-                self.fmt_expr(expr);
-                self.push(" ?: ");
-                self.fmt_expr(default);
-            }
-            Expr::Comprehension {
-                expr,
-                var_name,
-                iter,
-                condition,
-                ..
-            } => {
-                self.push("[");
-                self.fmt_expr(expr);
-                self.push(&format!(" para {} en ", var_name));
-                self.fmt_expr(iter);
-                if let Some(cond) = condition {
-                    self.push(" si ");
-                    self.fmt_expr(cond);
-                }
-                self.push("]");
-            }
-            Expr::Query {
-                var_name,
-                source,
-                where_clause,
-                select_expr,
-                ..
-            } => {
-                self.push(&format!("consultar {} en ", var_name));
-                self.fmt_expr(source);
-                if let Some(w) = where_clause {
-                    self.push(" donde ");
-                    self.fmt_expr(w);
-                }
-                self.push(" seleccionar ");
-                self.fmt_expr(select_expr);
-            }
-            Expr::StructInit {
-                struct_name: name,
-                fields,
-                ..
-            } => {
-                self.push(name);
-                self.push(" { ");
-                for (i, (fname, fexpr)) in fields.iter().enumerate() {
-                    if i > 0 {
-                        self.push(", ");
-                    }
-                    self.push(fname);
-                    self.push(": ");
-                    self.fmt_expr(fexpr);
-                }
-                self.push(" }");
-            }
-            Expr::Grouping { expr, .. } => {
-                self.push("(");
-                self.fmt_expr(expr);
-                self.push(")");
-            }
-            Expr::Cast {
-                expr, cast_type, ..
-            } => {
-                self.push("(");
-                self.fmt_expr(expr);
-                self.push(" como ");
-                self.push(&format_type(cast_type));
-                self.push(")");
-            }
-            Expr::Tuple { items, .. } => {
-                self.push("(");
-                for (i, item) in items.iter().enumerate() {
-                    if i > 0 {
-                        self.push(", ");
-                    }
-                    self.fmt_expr(item);
-                }
-                self.push(")");
-            }
-            Expr::EnumCtor {
-                enum_name,
-                variant,
-                args,
-                ..
-            } => {
-                self.push(enum_name);
-                self.push("::");
-                self.push(variant);
-                if !args.is_empty() {
-                    self.push("(");
-                    for (i, a) in args.iter().enumerate() {
-                        if i > 0 {
-                            self.push(", ");
-                        }
-                        self.fmt_expr(a);
-                    }
-                    self.push(")");
-                }
-            }
-            Expr::Comptime { expr, .. } => {
-                self.push("en_tiempo_compilacion { ");
-                self.fmt_expr(expr);
-                self.push(" }");
-            }
-            _ => {}
-        }
+    #[test]
+    fn test_continuaciones() {
+        let src =
+            "entero total = uno +\ndos\n+ tres;\nimprimir(\nx,\ny\n);\nsi a\n&& b {\nc();\n}\n";
+        assert_eq!(
+            fmt(src),
+            "entero total = uno +\n    dos\n    + tres;\nimprimir(\n    x,\n    y\n);\nsi a\n    && b {\n    c();\n}\n"
+        );
     }
-}
 
-fn format_type(t: &Type) -> String {
-    match t {
-        Type::Entero => "entero".to_string(),
-        Type::Decimal => "decimal".to_string(),
-        Type::Texto => "texto".to_string(),
-        Type::Booleano => "booleano".to_string(),
-        Type::Numero => "numero".to_string(),
-        Type::Lista(inner) => format!("lista<{}>", format_type(inner)),
-        Type::Resultado { ok, err } => {
-            format!("Resultado<{}, {}>", format_type(ok), format_type(err))
-        }
-        Type::Opcion(inner) => format!("Opcion<{}>", format_type(inner)),
-        Type::Func {
-            param_types,
-            return_type,
-        } => {
-            let params: Vec<String> = param_types.iter().map(format_type).collect();
-            format!(
-                "funcion({}) {}",
-                params.join(", "),
-                format_type(return_type)
-            )
-        }
-        Type::Struct(name) => name.clone(),
-        Type::Tuple(types) => {
-            let inner: Vec<String> = types.iter().map(format_type).collect();
-            format!("({})", inner.join(", "))
-        }
-        Type::GenericStruct { name, .. } => name.clone(),
-        Type::ImplTrait(name) => format!("impl {}", name),
-        Type::Prestado { inner, mutable } => {
-            if *mutable {
-                format!("prestado mut {}", format_type(inner))
-            } else {
-                format!("prestado {}", format_type(inner))
-            }
-        }
-        Type::Dueno(inner) => format!("dueno {}", format_type(inner)),
+    #[test]
+    fn test_parte_lineas_anchas() {
+        let config = FmtConfig {
+            max_width: 30,
+            ..FmtConfig::default()
+        };
+        let src = "imprimir(\"uno\", \"dos\", [1, 2, 3]);\nsi alfa && beta || gamma && delta_larga {\nf(x);\n}\nentero n = f(1);\n";
+        assert_eq!(
+            format_source_with_config(src, &config).unwrap(),
+            "imprimir(\n    \"uno\",\n    \"dos\",\n    [1, 2, 3]\n);\nsi alfa && beta\n    || gamma && delta_larga {\n    f(x);\n}\nentero n = f(1);\n"
+        );
+        let config = FmtConfig {
+            max_width: 0,
+            ..config
+        };
+        assert_eq!(format_source_with_config(src, &config).unwrap(), fmt(src));
     }
-}
 
-fn fmt_binop(op: &BinOp) -> &'static str {
-    match op {
-        BinOp::Add => "+",
-        BinOp::Concat => "++",
-        BinOp::Sub => "-",
-        BinOp::Mul => "*",
-        BinOp::Div => "/",
-        BinOp::Mod => "%",
-        BinOp::Equal => "==",
-        BinOp::NotEqual => "!=",
-        BinOp::Less => "<",
-        BinOp::LessEqual => "<=",
-        BinOp::Greater => ">",
-        BinOp::GreaterEqual => ">=",
-        BinOp::And => "&&",
-        BinOp::Or => "||",
-        BinOp::BitOr => "|",
-        BinOp::BitAnd => "&",
-        BinOp::BitXor => "^",
-        BinOp::ShiftLeft => "<<",
-        BinOp::ShiftRight => ">>",
+    #[test]
+    fn test_estilo_de_llaves() {
+        let src = "funcion nada f()\n{\nsi x { g(); }\n}\n";
+        assert_eq!(
+            fmt(src),
+            "funcion nada f() {\n    si x {\n        g();\n    }\n}\n"
+        );
+        let config = FmtConfig {
+            brace_style: BraceStyle::NextLine,
+            ..FmtConfig::default()
+        };
+        let src = "funcion nada f() {\nsi x { g(); }\nPunto p = Punto {\nx: 1,\ny: 2\n};\n}\n";
+        assert_eq!(
+            format_source_with_config(src, &config).unwrap(),
+            "funcion nada f()\n{\n    si x\n    {\n        g();\n    }\n    Punto p = Punto {\n        x: 1,\n        y: 2\n    };\n}\n"
+        );
     }
-}
 
-fn fmt_unop(op: &UnOp) -> &'static str {
-    match op {
-        UnOp::Negate => "-",
-        UnOp::Not => "!",
-        UnOp::BitNot => "~",
+    #[test]
+    fn test_coma_final() {
+        let src = "f(\n1,\n2\n);\ng(1, 2,);\nlista<entero> l = [\n[1, 2],\n3,\n];\n";
+        let siempre = FmtConfig {
+            trailing_comma: TrailingComma::Always,
+            ..FmtConfig::default()
+        };
+        assert_eq!(
+            format_source_with_config(src, &siempre).unwrap(),
+            "f(\n    1,\n    2,\n);\ng(1, 2,);\nlista<entero> l = [\n    [1, 2],\n    3,\n];\n"
+        );
+        let nunca = FmtConfig {
+            trailing_comma: TrailingComma::Never,
+            ..FmtConfig::default()
+        };
+        assert_eq!(
+            format_source_with_config(src, &nunca).unwrap(),
+            "f(\n    1,\n    2\n);\ng(1, 2);\nlista<entero> l = [\n    [1, 2],\n    3\n];\n"
+        );
     }
-}
-
-fn escape_string(s: &str) -> String {
-    s.replace('\\', "\\\\")
-        .replace('"', "\\\"")
-        .replace('\n', "\\n")
-        .replace('\t', "\\t")
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_idioma_de_las_palabras_clave() {
+        let src = "function entero f(entero n) {\nsi n > 0 { return verdadero; } else { retornar false; }\n}\n";
+        let en = FmtConfig {
+            keywords: KeywordStyle::English,
+            ..FmtConfig::default()
+        };
+        assert_eq!(
+            format_source_with_config(src, &en).unwrap(),
+            "function integer f(integer n) {\n    if n > 0 {\n        return true;\n    } else {\n        return false;\n    }\n}\n"
+        );
+        let es = FmtConfig {
+            keywords: KeywordStyle::Spanish,
+            ..FmtConfig::default()
+        };
+        assert_eq!(
+            format_source_with_config(src, &es).unwrap(),
+            "funcion entero f(entero n) {\n    si n > 0 {\n        retornar verdadero;\n    } sino {\n        retornar falso;\n    }\n}\n"
+        );
+    }
 
     #[test]
-    fn test_format_simple() {
-        let result = format_source("imprimir(\"hola\");").unwrap();
-        assert!(result.contains("hola"));
+    fn test_formatear_rango() {
+        let src =
+            "entero a=1;\nsi a>0\n{\nimprimir( a );\n      imprimir(a,\n2);\n}\nentero b=2;\n";
+        let config = FmtConfig::default();
+        let edit = format_range(src, &config, 3..4).unwrap().unwrap();
+        assert_eq!(
+            edit,
+            LineEdit {
+                start: 3,
+                end: 4,
+                text: "    imprimir(a);\n".to_string()
+            }
+        );
+        // La `{` sube a la línea del `si`, que entra en el cambio.
+        let edit = format_range(src, &config, 2..3).unwrap().unwrap();
+        assert_eq!((edit.start, edit.end), (1, 3));
+        assert_eq!(edit.text, "si a > 0 {\n");
+        let edit = format_range(src, &config, 0..1).unwrap().unwrap();
+        assert_eq!(edit.text, "entero a = 1;\n");
+        assert_eq!(format_range(&fmt(src), &config, 0..8).unwrap(), None);
+        assert!(format_range("si {", &config, 0..1).is_err());
     }
 
     #[test]
-    fn test_format_function() {
-        let src = "funcion entero suma(entero a,entero b){retornar a+b;}";
-        let result = format_source(src).unwrap();
-        assert!(result.contains("funcion entero suma"));
+    fn test_errores_de_sintaxis() {
+        assert!(format_source_with_config("si x { imprimir(1);", &FmtConfig::default()).is_err());
+        assert!(format_source_with_config("x = \"abc", &FmtConfig::default()).is_err());
     }
 }
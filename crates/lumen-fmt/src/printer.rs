@@ -0,0 +1,328 @@
+//! Impresión de los tokens: sangría por llaves, paréntesis y líneas de
+//! continuación; espacios según los tokens vecinos y lo que leyó el parser;
+//! comentarios y saltos de línea tal como vienen en la trivia.
+
+use lumen_lexer::{TokenKind, Trivia, TriviaToken};
+use std::collections::HashSet;
+
+/// Texto formateado y dónde empieza cada token en él (en bytes).
+pub(crate) struct Printed {
+    pub output: String,
+    pub starts: Vec<usize>,
+}
+
+/// Imprime `tokens` (que acaban en `Eof`) con `indent_spaces` por nivel.
+/// `binary_ops` son las posiciones (línea, columna) en el fuente de los
+/// operadores que el parser leyó como binarios.
+pub(crate) fn print(
+    tokens: &[TriviaToken],
+    indent_spaces: usize,
+    binary_ops: &HashSet<(usize, usize)>,
+) -> Printed {
+    let mut printer = Printer::new(indent_spaces, binary_ops);
+    let mut starts = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        printer.token(token);
+        starts.push(printer.output.len() - token.text.len());
+    }
+    let len = printer.output.trim_end().len();
+    let mut output = printer.output;
+    output.truncate(len);
+    output.push('\n');
+    for start in &mut starts {
+        *start = (*start).min(len);
+    }
+    Printed { output, starts }
+}
+
+/// Paréntesis, corchete o llave abierto y la sangría que tendrá su cierre.
+struct Open {
+    brace: bool,
+    base: usize,
+}
+
+struct Printer<'a> {
+    output: String,
+    indent_spaces: usize,
+    /// Saltos de línea vistos desde lo último que se escribió.
+    newlines: usize,
+    open: Vec<Open>,
+    /// Sangría de la línea actual y si es continuación de la anterior.
+    indent: usize,
+    continued: bool,
+    prev: Option<&'a TokenKind>,
+    prev_binary: bool,
+    binary_ops: &'a HashSet<(usize, usize)>,
+    /// Lo último escrito es un comentario, y si es un `//`.
+    after_comment: bool,
+    in_comment: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(indent_spaces: usize, binary_ops: &'a HashSet<(usize, usize)>) -> Self {
+        Self {
+            output: String::new(),
+            indent_spaces,
+            newlines: 0,
+            open: Vec::new(),
+            indent: 0,
+            continued: false,
+            prev: None,
+            prev_binary: false,
+            binary_ops,
+            after_comment: false,
+            in_comment: false,
+        }
+    }
+
+    fn token(&mut self, tok: &'a TriviaToken) {
+        let kind = &tok.token.kind;
+        for trivia in &tok.leading {
+            match trivia {
+                Trivia::Newlines(n) => self.newlines += n,
+                Trivia::LineComment(text) | Trivia::BlockComment(text) => {
+                    if self.newlines == 0 && !self.output.is_empty() {
+                        self.output.push(' ');
+                    } else {
+                        self.start_line(None);
+                    }
+                    self.output.push_str(text);
+                    self.after_comment = true;
+                    self.in_comment = matches!(trivia, Trivia::LineComment(_));
+                }
+            }
+        }
+        if *kind == TokenKind::Eof {
+            return;
+        }
+        if self.in_comment {
+            // Lo que sigue a un `//` no puede quedar en su línea.
+            self.newlines = self.newlines.max(1);
+            self.in_comment = false;
+        }
+
+        let start = tok.token.span.start;
+        let binary = is_operator(kind) && self.binary_ops.contains(&(start.line, start.col));
+        if self.newlines > 0 || self.output.is_empty() {
+            self.start_line(Some(kind));
+        } else if self.after_comment
+            || self.prev.is_some_and(|prev| {
+                space_between((prev, self.prev_binary), (kind, binary), tok.spaced)
+            })
+        {
+            self.output.push(' ');
+        }
+        self.output.push_str(&tok.text);
+        self.after_comment = false;
+
+        match kind {
+            TokenKind::LeftBrace => self.open.push(Open {
+                brace: true,
+                base: self.indent - usize::from(self.continued),
+            }),
+            TokenKind::LeftParen | TokenKind::LeftBracket => self.open.push(Open {
+                brace: false,
+                base: self.indent,
+            }),
+            TokenKind::RightBrace | TokenKind::RightParen | TokenKind::RightBracket => {
+                self.open.pop();
+            }
+            _ => {}
+        }
+        self.prev = Some(kind);
+        self.prev_binary = binary;
+    }
+
+    /// Empieza una línea nueva (o la primera) para `next`, o para un
+    /// comentario si es `None`, con a lo sumo una línea en blanco antes.
+    fn start_line(&mut self, next: Option<&TokenKind>) {
+        if !self.output.is_empty() {
+            let after_open = matches!(
+                self.prev,
+                Some(TokenKind::LeftBrace | TokenKind::LeftParen | TokenKind::LeftBracket)
+            ) && !self.after_comment;
+            let before_close = next.is_some_and(is_closer);
+            let blank = self.newlines > 1 && !after_open && !before_close;
+            self.output.push('\n');
+            if blank {
+                self.output.push('\n');
+            }
+        }
+        self.newlines = 0;
+
+        let top = self.open.last();
+        let (indent, continued) = match (next, top) {
+            (Some(kind), Some(open)) if is_closer(kind) => (open.base, false),
+            _ => {
+                let inner = top.map_or(0, |open| open.base + 1);
+                let statement = top.is_none_or(|open| open.brace);
+                let continued = statement && next.is_some_and(|kind| continues(self.prev, kind));
+                (inner + usize::from(continued), continued)
+            }
+        };
+        self.indent = indent;
+        self.continued = continued;
+        for _ in 0..indent * self.indent_spaces {
+            self.output.push(' ');
+        }
+    }
+}
+
+pub(crate) fn is_closer(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::RightBrace | TokenKind::RightParen | TokenKind::RightBracket
+    )
+}
+
+/// Operadores y signos que no son delimitadores.
+pub(crate) fn is_operator(kind: &TokenKind) -> bool {
+    use TokenKind::*;
+    matches!(
+        kind,
+        Plus | PlusPlus
+            | Minus
+            | Star
+            | Slash
+            | Percent
+            | Equal
+            | EqualEqual
+            | Bang
+            | BangEqual
+            | Less
+            | LessEqual
+            | Greater
+            | GreaterEqual
+            | AndAnd
+            | OrOr
+            | Pipe
+            | PipeGreater
+            | Ampersand
+            | Caret
+            | Tilde
+            | ShiftLeft
+            | ShiftRight
+            | Question
+            | QuestionDot
+            | QuestionColon
+            | DotDot
+            | DotDotEqual
+            | Dot
+            | Colon
+            | DoubleColon
+    )
+}
+
+/// Operadores binarios que no tienen otra lectura: siempre van entre espacios.
+fn is_binary(kind: &TokenKind) -> bool {
+    use TokenKind::*;
+    matches!(
+        kind,
+        Plus | Slash
+            | Percent
+            | Equal
+            | EqualEqual
+            | BangEqual
+            | LessEqual
+            | GreaterEqual
+            | AndAnd
+            | OrOr
+            | PipeGreater
+            | QuestionColon
+            | ShiftLeft
+    )
+}
+
+pub(crate) fn is_word(kind: &TokenKind) -> bool {
+    !is_operator(kind)
+        && !matches!(
+            kind,
+            TokenKind::LeftParen
+                | TokenKind::RightParen
+                | TokenKind::LeftBrace
+                | TokenKind::RightBrace
+                | TokenKind::LeftBracket
+                | TokenKind::RightBracket
+                | TokenKind::Semicolon
+                | TokenKind::Comma
+                | TokenKind::Eof
+        )
+}
+
+/// Lo que se llama pegado a su `(`: nombres, llamadas encadenadas y las
+/// palabras clave que se usan como funciones.
+pub(crate) fn is_callee(kind: &TokenKind) -> bool {
+    use TokenKind::*;
+    matches!(
+        kind,
+        Ident(_)
+            | RightParen
+            | RightBracket
+            | Imprimir
+            | Print
+            | Leer
+            | Read
+            | Funcion
+            | Function
+            | Exito
+            | Ok
+            | ErrKeyword
+            | Err
+            | Algun
+            | Some
+    )
+}
+
+/// Si una línea que empieza por `next` continúa la sentencia anterior.
+fn continues(prev: Option<&TokenKind>, next: &TokenKind) -> bool {
+    let Some(prev) = prev else {
+        return false;
+    };
+    if is_operator(next)
+        && !matches!(
+            next,
+            TokenKind::Bang | TokenKind::Tilde | TokenKind::Ampersand
+        )
+    {
+        return true;
+    }
+    !matches!(
+        prev,
+        TokenKind::Semicolon
+            | TokenKind::LeftBrace
+            | TokenKind::RightBrace
+            | TokenKind::Comma
+            | TokenKind::Colon
+    ) && is_operator(prev)
+}
+
+/// Si entre `prev` y `next`, en la misma línea, va un espacio. Cada token va
+/// con si el parser lo leyó como operador binario: así se distingue el `-`
+/// unario del binario y el `<`/`>` de un tipo del de una comparación. Donde
+/// no hay otra regla se respeta lo que había en el fuente.
+fn space_between(prev: (&TokenKind, bool), next: (&TokenKind, bool), spaced: bool) -> bool {
+    use TokenKind::*;
+    let ((prev, prev_binary), (next, next_binary)) = (prev, next);
+    match (prev, next) {
+        (NumLiteral(_), Dot) => spaced,
+        (_, RightParen | RightBracket | Comma | Semicolon | Dot | QuestionDot | DoubleColon) => {
+            false
+        }
+        (LeftParen | LeftBracket | Dot | QuestionDot | DoubleColon, _) => false,
+        _ if prev_binary || next_binary => true,
+        (Comma | Semicolon | Colon, _) => true,
+        (LeftBrace, RightBrace) => false,
+        (LeftBrace | RightBrace, _) | (_, RightBrace | LeftBrace) => true,
+        // Lo que no es binario: `-x` y los argumentos de tipo (`lista<T>`).
+        (Minus | Less, _) | (_, Less | Greater | ShiftRight) => false,
+        (Greater | ShiftRight, next) => is_word(next),
+        (Bang | Tilde, Minus) => spaced,
+        (_, Minus) => true,
+        (prev, LeftParen) if is_callee(prev) => false,
+        (Ident(_) | RightParen | RightBracket | StrLiteral(_), LeftBracket) => false,
+        (prev, next) if is_binary(prev) && !is_operator(next) => true,
+        (prev, next) if is_binary(next) && !is_operator(prev) => true,
+        (prev, next) if is_word(prev) && (is_word(next) || *next == LeftParen) => true,
+        _ => spaced,
+    }
+}
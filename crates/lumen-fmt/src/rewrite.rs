@@ -0,0 +1,532 @@
+//! Cambios sobre los tokens antes de imprimirlos: escritura de las palabras
+//! clave, una sentencia por línea, sitio de las llaves, comas finales y los
+//! saltos de línea que parten las líneas demasiado anchas. Un salto es un `Trivia::Newlines` más
+//! en la trivia del token, así que una segunda pasada lo encuentra en el
+//! fuente y no cambia nada.
+
+use crate::config::{BraceStyle, KeywordStyle, TrailingComma};
+use crate::printer::{is_callee, is_closer, is_word, Printed};
+use lumen_lexer::{Token, TokenKind, Trivia, TriviaToken};
+use lumen_parser::TokenRoles;
+
+/// Palabras clave con escritura en español y en inglés. Los alias (`ref`,
+/// `codigo_c`...) se reconocen por su `TokenKind`.
+const KEYWORDS: &[(&str, &str)] = &[
+    ("si", "if"),
+    ("sino", "else"),
+    ("mientras", "while"),
+    ("para", "for"),
+    ("funcion", "function"),
+    ("retornar", "return"),
+    ("verdadero", "true"),
+    ("falso", "false"),
+    ("numero", "number"),
+    ("entero", "integer"),
+    ("decimal", "float"),
+    ("texto", "string"),
+    ("booleano", "boolean"),
+    ("imprimir", "print"),
+    ("leer", "read"),
+    ("lista", "array"),
+    ("romper", "break"),
+    ("continuar", "continue"),
+    ("elegir", "match"),
+    ("caso", "case"),
+    ("defecto", "default"),
+    ("estructura", "struct"),
+    ("importar", "import"),
+    ("como", "as"),
+    ("resultado", "result"),
+    ("error", "err"),
+    ("intentar", "try"),
+    ("en", "in"),
+    ("opcion", "option"),
+    ("algun", "some"),
+    ("ninguno", "none"),
+    ("rasgo", "trait"),
+    ("esperar", "await"),
+    ("sea", "let"),
+    ("posponer", "defer"),
+    ("consultar", "query"),
+    ("donde", "where"),
+    ("ordenar_por", "order_by"),
+    ("seleccionar", "select"),
+    ("descendente", "descending"),
+    ("ascendente", "ascending"),
+    ("atrapar", "catch"),
+    ("prestado", "borrowed"),
+    ("dueno", "owner"),
+    ("en_tiempo_compilacion", "comptime"),
+    ("ensamblador", "asm"),
+    ("bloque_c", "c_block"),
+    ("bloque_rust", "rust_block"),
+    ("puro", "pure"),
+    ("grupo_tareas", "task_group"),
+];
+
+fn keyword_pair(kind: &TokenKind) -> Option<(&'static str, &'static str)> {
+    if !is_word(kind) || matches!(kind, TokenKind::Ident(_)) {
+        return None;
+    }
+    KEYWORDS.iter().copied().find(|(es, en)| {
+        TokenKind::is_keyword(es).as_ref() == Some(kind)
+            || TokenKind::is_keyword(en).as_ref() == Some(kind)
+    })
+}
+
+/// El mismo token con las palabras clave en español: así se comparan los
+/// tokens antes y después de cambiar de idioma.
+pub(crate) fn canonical(kind: &TokenKind) -> TokenKind {
+    keyword_pair(kind)
+        .and_then(|(es, _)| TokenKind::is_keyword(es))
+        .unwrap_or_else(|| kind.clone())
+}
+
+pub(crate) fn keywords(tokens: &mut [TriviaToken], style: KeywordStyle) {
+    if style == KeywordStyle::Preserve {
+        return;
+    }
+    for tok in tokens {
+        let Some((es, en)) = keyword_pair(&tok.token.kind) else {
+            continue;
+        };
+        let spelling = if style == KeywordStyle::Spanish {
+            es
+        } else {
+            en
+        };
+        if let Some(kind) = TokenKind::is_keyword(spelling) {
+            tok.token.kind = kind;
+            tok.text = spelling.to_string();
+        }
+    }
+}
+
+fn starts_line(tok: &TriviaToken) -> bool {
+    tok.leading.iter().any(|t| matches!(t, Trivia::Newlines(_)))
+}
+
+fn break_before(tok: &mut TriviaToken) {
+    if !starts_line(tok) {
+        tok.leading.insert(0, Trivia::Newlines(1));
+    }
+}
+
+/// Los saltos que pide la gramática: cada sentencia empieza una línea y el
+/// cuerpo de un bloque va entre la línea de su `{` y la de su `}`. Los
+/// índices de `roles` son los de `tokens` tal como salieron del lexer.
+pub(crate) fn statements(tokens: &mut [TriviaToken], roles: &TokenRoles) {
+    for &(open, close) in &roles.blocks {
+        if close == open + 1 {
+            continue;
+        }
+        break_before(&mut tokens[open + 1]);
+        // Después de un comentario que sigue a la última sentencia.
+        let leading = &mut tokens[close].leading;
+        if !matches!(leading.last(), Some(Trivia::Newlines(_))) {
+            leading.push(Trivia::Newlines(1));
+        }
+    }
+    for &k in &roles.statements {
+        if k > 0 {
+            break_before(&mut tokens[k]);
+        }
+    }
+}
+
+/// Si la `{` que sigue a `prev`, en una sentencia que empieza por `head`,
+/// abre un bloque y no un literal.
+fn opens_block(head: Option<&TokenKind>, prev: &TokenKind) -> bool {
+    use TokenKind as K;
+    *prev == K::RightParen
+        || matches!(
+            head,
+            Some(
+                K::Si
+                    | K::If
+                    | K::Sino
+                    | K::Else
+                    | K::Mientras
+                    | K::While
+                    | K::Para
+                    | K::For
+                    | K::Elegir
+                    | K::Match
+                    | K::Caso
+                    | K::Case
+                    | K::Defecto
+                    | K::Default
+                    | K::Intentar
+                    | K::Try
+                    | K::Atrapar
+                    | K::Catch
+                    | K::Posponer
+                    | K::Defer
+                    | K::Funcion
+                    | K::Function
+                    | K::Estructura
+                    | K::Struct
+                    | K::Enum
+                    | K::Rasgo
+                    | K::Trait
+                    | K::Impl
+                    | K::Async
+                    | K::Puro
+                    | K::Pure
+            )
+        )
+}
+
+/// Primer token de la sentencia en la que está cada token.
+fn heads(tokens: &[TriviaToken]) -> Vec<Option<TokenKind>> {
+    let mut head: Option<TokenKind> = None;
+    let mut result = Vec::with_capacity(tokens.len());
+    for tok in tokens {
+        result.push(head.clone());
+        match &tok.token.kind {
+            TokenKind::Semicolon | TokenKind::LeftBrace | TokenKind::RightBrace => head = None,
+            kind if head.is_none() => head = Some(kind.clone()),
+            _ => {}
+        }
+    }
+    result
+}
+
+pub(crate) fn braces(tokens: &mut [TriviaToken], style: BraceStyle) {
+    if style == BraceStyle::Preserve {
+        return;
+    }
+    let heads = heads(tokens);
+    for k in 1..tokens.len() {
+        if tokens[k].token.kind != TokenKind::LeftBrace
+            || !opens_block(heads[k].as_ref(), &tokens[k - 1].token.kind)
+        {
+            continue;
+        }
+        let prev = &tokens[k - 1].token.kind;
+        let tok = &tokens[k];
+        match style {
+            BraceStyle::SameLine
+                if starts_line(tok)
+                    && tok.leading.iter().all(|t| matches!(t, Trivia::Newlines(_)))
+                    && !matches!(
+                        prev,
+                        TokenKind::Semicolon
+                            | TokenKind::LeftBrace
+                            | TokenKind::RightBrace
+                            | TokenKind::Comma
+                            | TokenKind::Colon
+                    ) =>
+            {
+                tokens[k].leading.clear();
+                tokens[k].spaced = true;
+            }
+            BraceStyle::NextLine
+                if !starts_line(tok) && tokens.get(k + 1).is_some_and(starts_line) =>
+            {
+                break_before(&mut tokens[k]);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_opener(kind: &TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::LeftBrace
+    )
+}
+
+/// Para cada apertura, el índice de su cierre.
+pub(crate) fn matching(tokens: &[TriviaToken]) -> Vec<Option<usize>> {
+    let mut result = vec![None; tokens.len()];
+    let mut stack = Vec::new();
+    for (i, tok) in tokens.iter().enumerate() {
+        if is_opener(&tok.token.kind) {
+            stack.push(i);
+        } else if is_closer(&tok.token.kind) {
+            if let Some(open) = stack.pop() {
+                result[open] = Some(i);
+            }
+        }
+    }
+    result
+}
+
+/// Índices de los tokens entre `open` y `close` que no están dentro de otro
+/// grupo.
+fn top_level(
+    tokens: &[TriviaToken],
+    open: usize,
+    close: usize,
+) -> impl Iterator<Item = usize> + '_ {
+    let mut depth = 0usize;
+    (open + 1..close).filter(move |&i| {
+        let kind = &tokens[i].token.kind;
+        if is_opener(kind) {
+            depth += 1;
+            false
+        } else if is_closer(kind) {
+            depth = depth.saturating_sub(1);
+            false
+        } else {
+            depth == 0
+        }
+    })
+}
+
+/// Comas y puntos y comas del nivel superior entre `open` y `close`.
+fn separators(tokens: &[TriviaToken], open: usize, close: usize) -> (Vec<usize>, Vec<usize>) {
+    let kind = |i: &usize| tokens[*i].token.kind.clone();
+    let commas = top_level(tokens, open, close)
+        .filter(|i| kind(i) == TokenKind::Comma)
+        .collect();
+    let semis = top_level(tokens, open, close)
+        .filter(|i| kind(i) == TokenKind::Semicolon)
+        .collect();
+    (commas, semis)
+}
+
+/// Si el grupo `open..=close` es una lista donde el parser admite una coma
+/// tras el último elemento: argumentos o parámetros, una lista literal, los
+/// campos de una estructura o las variantes de un enum.
+fn takes_trailing_comma(
+    tokens: &[TriviaToken],
+    heads: &[Option<TokenKind>],
+    open: usize,
+    close: usize,
+) -> bool {
+    let prev = open.checked_sub(1).map(|p| &tokens[p].token.kind);
+    let (commas, semis) = separators(tokens, open, close);
+    match &tokens[open].token.kind {
+        TokenKind::LeftParen => {
+            prev.is_some_and(|p| {
+                is_callee(p) && !matches!(p, TokenKind::Funcion | TokenKind::Function)
+            }) && semis.is_empty()
+        }
+        // Ni un índice ni una comprensión (`[n * n para n en l]`).
+        TokenKind::LeftBracket => {
+            !matches!(
+                prev,
+                Some(
+                    TokenKind::Ident(_)
+                        | TokenKind::RightParen
+                        | TokenKind::RightBracket
+                        | TokenKind::StrLiteral(_)
+                )
+            ) && !top_level(tokens, open, close)
+                .any(|k| matches!(tokens[k].token.kind, TokenKind::Para | TokenKind::For))
+        }
+        _ => {
+            // `elegir x { A => 1, B => 2 }` es un bloque, no un literal.
+            prev.is_some_and(|p| {
+                matches!(p, TokenKind::Ident(_)) && !opens_block(heads[open].as_ref(), p)
+            }) && !commas.is_empty()
+                && semis.is_empty()
+                && matches!(tokens[open + 1].token.kind, TokenKind::Ident(_))
+        }
+    }
+}
+
+pub(crate) fn trailing_commas(tokens: &mut Vec<TriviaToken>, policy: TrailingComma) {
+    if policy == TrailingComma::Preserve {
+        return;
+    }
+    let mut groups: Vec<(usize, usize)> = matching(tokens)
+        .iter()
+        .enumerate()
+        .filter_map(|(open, close)| close.map(|c| (open, c)))
+        .filter(|&(open, close)| close > open + 1)
+        .collect();
+    // De atrás hacia delante, para que insertar o quitar no mueva los
+    // índices que faltan.
+    groups.sort_by_key(|g| std::cmp::Reverse(g.1));
+    let heads = heads(tokens);
+    for (open, close) in groups {
+        let last = close - 1;
+        let has_comma = tokens[last].token.kind == TokenKind::Comma;
+        if !takes_trailing_comma(tokens, &heads, open, close) {
+            continue;
+        }
+        match policy {
+            TrailingComma::Always if !has_comma && starts_line(&tokens[close]) => {
+                let span = tokens[last].token.span;
+                tokens.insert(
+                    close,
+                    TriviaToken {
+                        token: Token::new(TokenKind::Comma, span),
+                        text: ",".to_string(),
+                        leading: Vec::new(),
+                        spaced: false,
+                    },
+                );
+            }
+            TrailingComma::Never if has_comma => {
+                let comma = tokens.remove(last);
+                let closer = &mut tokens[last];
+                let mut leading = comma.leading;
+                leading.append(&mut closer.leading);
+                closer.leading = leading;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Precedencia de los operadores por los que se parte una cadena larga: se
+/// corta por los de menor precedencia que haya en la línea.
+fn chain_level(kind: &TokenKind) -> Option<u8> {
+    match kind {
+        TokenKind::OrOr => Some(0),
+        TokenKind::AndAnd => Some(1),
+        TokenKind::PipeGreater => Some(2),
+        TokenKind::QuestionColon => Some(3),
+        TokenKind::Plus | TokenKind::PlusPlus => Some(4),
+        _ => None,
+    }
+}
+
+/// Tokens delante de los que hay que saltar de línea para que las líneas
+/// más anchas que `max_width` dejen de serlo. Vacío si ya no se puede partir
+/// ninguna.
+pub(crate) fn wrap_points(
+    tokens: &[TriviaToken],
+    printed: &Printed,
+    max_width: usize,
+) -> Vec<usize> {
+    if max_width == 0 {
+        return Vec::new();
+    }
+    let matching = matching(tokens);
+    let placed = place(tokens, printed);
+    let mut points = Vec::new();
+    let count = tokens
+        .iter()
+        .take_while(|t| t.token.kind != TokenKind::Eof)
+        .count();
+    let mut first = 0;
+    while first < count {
+        let line = placed[first].line;
+        let mut last = first;
+        while last + 1 < count && placed[last + 1].line == line {
+            last += 1;
+        }
+        let too_wide = placed[last].end_line == line && placed[last].end_col > max_width;
+        if too_wide {
+            points.extend(split_line(tokens, &matching, &placed, first, last));
+        }
+        first = last + 1;
+    }
+    points
+}
+
+fn split_line(
+    tokens: &[TriviaToken],
+    matching: &[Option<usize>],
+    placed: &[Place],
+    first: usize,
+    last: usize,
+) -> Vec<usize> {
+    // Cadenas de operadores fuera de cualquier grupo abierto en la línea.
+    let mut depth = 0usize;
+    let mut ops: Vec<(u8, usize)> = Vec::new();
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for k in first..=last {
+        let kind = &tokens[k].token.kind;
+        if is_opener(kind) {
+            if depth == 0 {
+                if let Some(close) = matching[k].filter(|&c| c <= last && c > k + 1) {
+                    groups.push((k, close));
+                }
+            }
+            depth += 1;
+        } else if is_closer(kind) {
+            depth = depth.saturating_sub(1);
+        } else if depth == 0 && k > first {
+            if let Some(level) = chain_level(kind) {
+                ops.push((level, k));
+            }
+        }
+    }
+    if let Some(lowest) = ops.iter().map(|(level, _)| *level).min() {
+        return ops
+            .into_iter()
+            .filter(|(level, _)| *level == lowest)
+            .map(|(_, k)| k)
+            .collect();
+    }
+
+    // Si no, el grupo más ancho: un elemento por línea.
+    let widest = groups
+        .into_iter()
+        .filter(|&(open, close)| {
+            tokens[open].token.kind != TokenKind::LeftParen
+                || separators(tokens, open, close).1.is_empty()
+        })
+        .max_by_key(|&(open, close)| placed[close].col - placed[open].col);
+    let Some((open, close)) = widest else {
+        return Vec::new();
+    };
+    let (commas, semis) = separators(tokens, open, close);
+    let mut points = vec![open + 1];
+    for sep in commas.into_iter().chain(semis) {
+        if sep + 1 < close {
+            points.push(sep + 1);
+        }
+    }
+    points.push(close);
+    points.sort_unstable();
+    points.dedup();
+    points
+}
+
+/// Dónde quedó un token en el texto formateado (líneas desde 0, columnas en
+/// caracteres).
+#[derive(Clone, Copy)]
+pub(crate) struct Place {
+    pub line: usize,
+    pub col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+pub(crate) fn place(tokens: &[TriviaToken], printed: &Printed) -> Vec<Place> {
+    let out = &printed.output;
+    let mut places = Vec::with_capacity(tokens.len());
+    let (mut line, mut col, mut at) = (0usize, 0usize, 0usize);
+    for (tok, &start) in tokens.iter().zip(&printed.starts) {
+        for ch in out[at..start].chars() {
+            if ch == '\n' {
+                line += 1;
+                col = 0;
+            } else {
+                col += 1;
+            }
+        }
+        let (begin_line, begin_col) = (line, col);
+        let end = (start + tok.text.len()).min(out.len());
+        for ch in out[start..end].chars() {
+            if ch == '\n' {
+                line += 1;
+                col = 0;
+            } else {
+                col += 1;
+            }
+        }
+        at = end;
+        places.push(Place {
+            line: begin_line,
+            col: begin_col,
+            end_line: line,
+            end_col: col,
+        });
+    }
+    places
+}
+
+pub(crate) fn apply_breaks(tokens: &mut [TriviaToken], points: &[usize]) {
+    for &k in points {
+        break_before(&mut tokens[k]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Propiedades del formateador: es idempotente y no cambia los tokens del
+//! programa, solo la trivia.
+
+use lumen_fmt::{format_source_with_config, BraceStyle, FmtConfig, KeywordStyle, TrailingComma};
+use lumen_lexer::{Lexer, TokenKind};
+use proptest::prelude::*;
+use std::path::{Path, PathBuf};
+
+fn kinds(source: &str) -> Vec<TokenKind> {
+    Lexer::new(source)
+        .tokenize()
+        .0
+        .into_iter()
+        .map(|t| t.kind)
+        .collect()
+}
+
+fn fmt(source: &str) -> Result<String, Vec<String>> {
+    format_source_with_config(source, &FmtConfig::default())
+}
+
+fn nv_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            nv_files(&path, out);
+        } else if path.extension().is_some_and(|e| e == "nv") {
+            out.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_corpus_idempotente_y_sin_cambiar_tokens() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let mut files = Vec::new();
+    nv_files(&root.join("examples"), &mut files);
+    nv_files(&root.join("stdlib"), &mut files);
+    assert!(files.len() > 100);
+
+    let mut formatted = 0;
+    for path in &files {
+        let source = std::fs::read_to_string(path).unwrap();
+        // Los archivos con errores de sintaxis no se formatean.
+        let Ok(once) = fmt(&source) else {
+            continue;
+        };
+        assert_eq!(kinds(&once), kinds(&source), "{}", path.display());
+        let twice = fmt(&once).unwrap();
+        assert_eq!(twice, once, "{} no es idempotente", path.display());
+        formatted += 1;
+    }
+    assert!(
+        formatted * 10 > files.len() * 9,
+        "{} de {}",
+        formatted,
+        files.len()
+    );
+}
+
+#[test]
+fn test_corpus_con_otras_opciones() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let mut files = Vec::new();
+    nv_files(&root.join("examples"), &mut files);
+    let configs = [
+        FmtConfig {
+            max_width: 60,
+            brace_style: BraceStyle::NextLine,
+            trailing_comma: TrailingComma::Always,
+            keywords: KeywordStyle::English,
+            ..FmtConfig::default()
+        },
+        FmtConfig {
+            indent_spaces: 2,
+            max_width: 40,
+            trailing_comma: TrailingComma::Never,
+            keywords: KeywordStyle::Spanish,
+            ..FmtConfig::default()
+        },
+    ];
+    for path in &files {
+        let source = std::fs::read_to_string(path).unwrap();
+        if fmt(&source).is_err() {
+            continue;
+        }
+        for config in &configs {
+            // Lo que se puede formatear por defecto se puede con cualquier
+            // configuración, y la segunda pasada no cambia nada.
+            let once = format_source_with_config(&source, config)
+                .unwrap_or_else(|e| panic!("{}: {:?}", path.display(), e));
+            let twice = format_source_with_config(&once, config).unwrap();
+            assert_eq!(twice, once, "{} no es idempotente", path.display());
+        }
+    }
+}
+
+/// Fuente sin comentarios y lo que producía con él el formateador sobre el
+/// AST (el de a7c69d8): en lo que ese formateador no perdía tokens, el de
+/// ahora tiene que dar exactamente lo mismo.
+const GOLDEN: &[(&str, &str)] = &[
+    (
+        "funcion entero f(entero a){retornar a*2;}\n\nentero y=0;\n\nmientras y<3{y=y+1;}\n",
+        "funcion entero f(entero a) {\n    retornar a * 2;\n}\n\nentero y = 0;\n\nmientras y < 3 {\n    y = y + 1;\n}\n",
+    ),
+    (
+        "si y>2{imprimir(y-1);}sino{imprimir(-y);}\n",
+        "si y > 2 {\n    imprimir(y - 1);\n} sino {\n    imprimir(-y);\n}\n",
+    ),
+    (
+        "funcion entero g(entero n){entero a=n;a=a-1;retornar a*-n;}\n",
+        "funcion entero g(entero n) {\n    entero a = n;\n    a = a - 1;\n    retornar a * -n;\n}\n",
+    ),
+    (
+        "estructura Punto{x:entero,y:entero,}\n\nlista<lista<entero>> m=[[1],[2,3]];\n\nimprimir(f(2)%3);\n",
+        "estructura Punto {\n    x: entero,\n    y: entero,\n}\n\nlista<lista<entero>> m = [[1], [2, 3]];\n\nimprimir(f(2) % 3);\n",
+    ),
+];
+
+#[test]
+fn test_mismo_formato_que_el_formateador_sobre_el_ast() {
+    for (source, expected) in GOLDEN {
+        assert_eq!(fmt(source).unwrap(), *expected, "{}", source);
+    }
+    // Con los paréntesis, que aquel formateador quitaba.
+    assert_eq!(
+        fmt("funcion entero f(entero a){retornar a*2;}\nmientras(y<3){y=y+1;}\n").unwrap(),
+        "funcion entero f(entero a) {\n    retornar a * 2;\n}\nmientras (y < 3) {\n    y = y + 1;\n}\n"
+    );
+    assert_eq!(
+        fmt("mientras (b<10&&b>-5) { b=b*2-1; imprimir(b); }\n").unwrap(),
+        "mientras (b < 10 && b > -5) {\n    b = b * 2 - 1;\n    imprimir(b);\n}\n"
+    );
+}
+
+const SNIPPETS: &[&str] = &[
+    "/// Doc\nfuncion entero suma(entero a, entero b) { retornar a + b; }",
+    "si x > 0 && y < 3 { imprimir(x); } sino { imprimir(-y); }",
+    "lista<entero> l = [1, 2, 3]; para n en l { total = total + n * 2; }",
+    "estructura Punto { x: entero, y: entero } Punto p = Punto { x: 1, y: 2 }; imprimir(p.x);",
+    "elegir (v) { caso 1: { imprimir(\"uno\"); } defecto: { imprimir(v); } }",
+    "entero r = f(g(1, 2), [3][0]) |> h; texto t = f\"{r}\" ++ \"!\";",
+];
+
+const GAPS: &[&str] = &[
+    " ",
+    "  ",
+    "\n",
+    "\t",
+    "\n\n\n",
+    " /* c */ ",
+    " // c\n",
+    "\n    ",
+];
+
+/// Vuelve a escribir `source` con otros huecos entre los tokens.
+fn respace(source: &str, gaps: &[usize]) -> String {
+    let (tokens, _) = Lexer::new(source).tokenize_with_trivia();
+    let mut out = String::new();
+    for (i, tok) in tokens.iter().enumerate() {
+        if i > 0 {
+            out.push_str(GAPS[gaps[i % gaps.len()] % GAPS.len()]);
+        }
+        out.push_str(&tok.text);
+    }
+    out
+}
+
+proptest! {
+    #[test]
+    fn prop_formato_estable(
+        snippet in 0..SNIPPETS.len(),
+        gaps in proptest::collection::vec(0..GAPS.len(), 1..40),
+    ) {
+        let source = respace(SNIPPETS[snippet], &gaps);
+        let once = fmt(&source).unwrap();
+        prop_assert_eq!(kinds(&once), kinds(&source));
+        prop_assert_eq!(fmt(&once).unwrap(), once);
+    }
+}
@@ -1,11 +1,18 @@
 use crate::ir::*;
+use lumen_lexer::Span;
 use lumen_parser::ast::{BinOp, Decl, DeclOrStmt, Expr, Param, Stmt, Type, UnOp};
 use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 struct LoopLabels {
     break_label: usize,
     continue_label: usize,
     loop_name: Option<String>,
+    /// Bloques `posponer` pendientes al entrar al bucle; `romper`/`continuar`
+    /// ejecutan los registrados por encima de esta profundidad.
+    defer_depth: usize,
+    /// Bloques `intentar` abiertos al entrar al bucle.
+    try_depth: usize,
 }
 
 pub struct IRBuilder {
@@ -16,10 +23,18 @@ pub struct IRBuilder {
     label_counter: usize,
     lambda_counter: usize,
     loop_labels: Vec<LoopLabels>,
+    /// Número de `posponer` registrados en cada bloque abierto de la función actual.
+    defer_scopes: Vec<usize>,
+    /// Bloques `intentar` abiertos en la función actual.
+    try_depth: usize,
     default_params: HashMap<String, Vec<Option<Expr>>>,
     fn_names: HashSet<String>,
     impl_method_map: HashMap<String, String>,
     is_in_lambda: bool,
+    /// Archivo (índice en `Program::files`) de cada nodo de primer nivel.
+    node_files: Vec<u32>,
+    /// Archivo del nodo que se está generando.
+    current_file: u32,
 }
 
 impl Default for IRBuilder {
@@ -38,13 +53,44 @@ impl IRBuilder {
             label_counter: 0,
             lambda_counter: 0,
             loop_labels: Vec::new(),
+            defer_scopes: vec![0],
+            try_depth: 0,
             default_params: HashMap::new(),
             fn_names: HashSet::new(),
             impl_method_map: HashMap::new(),
             is_in_lambda: false,
+            node_files: Vec::new(),
+            current_file: 0,
         }
     }
 
+    /// Da el archivo de cada nodo de primer nivel, como los devuelve
+    /// `ModuleLoader::resolve_imports_with_origins`, para que las posiciones
+    /// del código importado apunten a su módulo. `main` es el programa
+    /// principal, con la misma ruta canónica que usa el loader.
+    pub fn set_origins(&mut self, main: &Path, origins: &[PathBuf]) {
+        let mut files = vec![main.display().to_string()];
+        self.node_files = origins
+            .iter()
+            .map(|origin| {
+                let name = origin.display().to_string();
+                let idx = files.iter().position(|f| *f == name).unwrap_or_else(|| {
+                    files.push(name);
+                    files.len() - 1
+                });
+                idx as u32
+            })
+            .collect();
+        self.program.files = files;
+    }
+
+    /// Declara una función que define el anfitrión (no el programa), para
+    /// que sus llamadas se emitan como `Call` por nombre y no como llamada a
+    /// un valor.
+    pub fn declare_external(&mut self, name: &str) {
+        self.fn_names.insert(name.to_string());
+    }
+
     pub fn build(mut self, program: &[DeclOrStmt]) -> crate::ir::Program {
         let has_toplevel_code = program.iter().any(|node| {
             !matches!(
@@ -68,6 +114,7 @@ impl IRBuilder {
                         .collect(),
                     entry: 0,
                     instrs: Vec::new(),
+                    declared: Vec::new(),
                 };
                 self.program.funcs.insert(name.clone(), func);
                 self.fn_names.insert(name.clone());
@@ -117,6 +164,7 @@ impl IRBuilder {
                             defaults: param_defaults,
                             entry: 0,
                             instrs: Vec::new(),
+                            declared: Vec::new(),
                         };
                         self.program.funcs.insert(mangled.clone(), func);
                         self.fn_names.insert(mangled.clone());
@@ -143,6 +191,7 @@ impl IRBuilder {
                 defaults: Vec::new(),
                 entry: 0,
                 instrs: Vec::new(),
+                declared: Vec::new(),
             };
             self.program.funcs.insert("__main__".to_string(), main_func);
             self.fn_names.insert("__main__".to_string());
@@ -152,7 +201,8 @@ impl IRBuilder {
             // Cargar sus instrucciones
         }
 
-        for node in program {
+        for (i, node) in program.iter().enumerate() {
+            self.current_file = self.node_files.get(i).copied().unwrap_or(0);
             self.gen_decl_or_stmt(node);
         }
 
@@ -169,14 +219,30 @@ impl IRBuilder {
                 if matches!(Self::last_significant(&main_func.instrs), Some(Instr::Halt)) {
                     // Remove the last significant Halt (handle trailing Label/Nop)
                     if let Some(pos) = main_func.instrs.iter().rposition(|i| {
-                        !matches!(i, Instr::Label(_) | Instr::Nop | Instr::Phi(_, _))
+                        !matches!(
+                            i,
+                            Instr::Label(_)
+                                | Instr::Nop
+                                | Instr::Phi(_, _)
+                                | Instr::Loc(..)
+                                | Instr::ScopeBegin
+                                | Instr::ScopeEnd
+                        )
                     }) {
                         if matches!(main_func.instrs[pos], Instr::Halt) {
                             main_func.instrs.remove(pos);
                         }
                     }
                 }
+                // Los `posponer` del nivel superior se ejecutan tras `main()`.
+                let run_defers = matches!(main_func.instrs.last(), Some(Instr::DeferRun(0)));
+                if run_defers {
+                    main_func.instrs.pop();
+                }
                 main_func.instrs.push(Instr::Call("main".to_string(), 0));
+                if run_defers {
+                    main_func.instrs.push(Instr::DeferRun(0));
+                }
                 main_func.instrs.push(Instr::Halt);
             }
         }
@@ -192,11 +258,17 @@ impl IRBuilder {
     }
 
     fn gen_decl(&mut self, decl: &Decl) {
+        if matches!(decl, Decl::Variable { .. } | Decl::Destructure { .. }) {
+            self.emit_loc(decl.span());
+        }
         match decl {
             Decl::Variable { name, init, .. } => {
                 if let Some(init_expr) = init {
                     self.gen_expr(init_expr);
-                    self.emit(Instr::Store(name.clone()));
+                    self.emit_bind(name.clone());
+                } else {
+                    self.declare(name);
+                    self.emit(Instr::Bind(name.clone()));
                 }
             }
             Decl::Destructure { targets, init, .. } => {
@@ -210,7 +282,7 @@ impl IRBuilder {
                     }
                     self.emit(Instr::Load(temp.clone()));
                     self.emit(Instr::TupleAccess(i));
-                    self.emit(Instr::Store(target.name.clone()));
+                    self.emit_bind(target.name.clone());
                 }
             }
             Decl::Function { name, body, .. } => {
@@ -218,6 +290,8 @@ impl IRBuilder {
                 let prev_func_name = self.current_func.take();
                 let saved_temp = self.temp_counter;
                 let saved_loop = std::mem::take(&mut self.loop_labels);
+                let saved_defers = std::mem::replace(&mut self.defer_scopes, vec![0]);
+                let saved_try = std::mem::take(&mut self.try_depth);
                 let saved_is_lambda = self.is_in_lambda;
 
                 self.current_func = Some(name.clone());
@@ -230,16 +304,17 @@ impl IRBuilder {
                     .unwrap_or_default();
                 self.temp_counter = 0;
                 self.loop_labels = Vec::new();
+                self.defer_scopes = vec![0];
                 self.is_in_lambda = false;
-                for node in body {
-                    self.gen_decl_or_stmt(node);
-                }
+                self.gen_block(body);
                 self.emit_return_if_needed();
                 self.finalize_func(); // Guardar las instrucciones de esta función
 
                 // Restaurar el contexto anterior
                 self.temp_counter = saved_temp;
                 self.loop_labels = saved_loop;
+                self.defer_scopes = saved_defers;
+                self.try_depth = saved_try;
                 self.is_in_lambda = saved_is_lambda;
                 if let Some(prev_name) = prev_func_name {
                     self.current_func = Some(prev_name.clone());
@@ -281,6 +356,8 @@ impl IRBuilder {
                 let prev_func_name = self.current_func.take();
                 let saved_temp = self.temp_counter;
                 let saved_loop = std::mem::take(&mut self.loop_labels);
+                let saved_defers = std::mem::replace(&mut self.defer_scopes, vec![0]);
+                let saved_try = std::mem::take(&mut self.try_depth);
                 let saved_is_lambda = self.is_in_lambda;
 
                 for method_decl in methods {
@@ -300,10 +377,9 @@ impl IRBuilder {
                             .unwrap_or_default();
                         self.temp_counter = 0;
                         self.loop_labels = Vec::new();
+                        self.defer_scopes = vec![0];
                         self.is_in_lambda = false;
-                        for node in body {
-                            self.gen_decl_or_stmt(node);
-                        }
+                        self.gen_block(body);
                         self.emit_return_if_needed();
                         self.finalize_func(); // Guardar las instrucciones de este método
                     }
@@ -312,6 +388,8 @@ impl IRBuilder {
                 // Restaurar el contexto anterior
                 self.temp_counter = saved_temp;
                 self.loop_labels = saved_loop;
+                self.defer_scopes = saved_defers;
+                self.try_depth = saved_try;
                 self.is_in_lambda = saved_is_lambda;
                 if let Some(prev_name) = prev_func_name {
                     self.current_func = Some(prev_name.clone());
@@ -329,12 +407,15 @@ impl IRBuilder {
             }
             Decl::Const { name, value, .. } => {
                 self.gen_expr(value);
-                self.emit(Instr::Store(name.clone()));
+                self.emit_bind(name.clone());
             }
         }
     }
 
     fn gen_stmt(&mut self, stmt: &Stmt) {
+        if !matches!(stmt, Stmt::Block { .. }) {
+            self.emit_loc(stmt.span());
+        }
         match stmt {
             Stmt::Assignment { name, value, .. } => {
                 self.gen_expr(value);
@@ -350,15 +431,11 @@ impl IRBuilder {
                 let end_label = self.new_label();
                 self.gen_expr(condition);
                 self.emit(Instr::JmpIf(else_label));
-                for node in then_body {
-                    self.gen_decl_or_stmt(node);
-                }
+                self.gen_block(then_body);
                 self.emit(Instr::Jmp(end_label));
                 self.emit(Instr::Label(else_label));
                 if let Some(else_body) = else_body {
-                    for node in else_body {
-                        self.gen_decl_or_stmt(node);
-                    }
+                    self.gen_block(else_body);
                 }
                 self.emit(Instr::Label(end_label));
             }
@@ -374,10 +451,10 @@ impl IRBuilder {
                     break_label: end_label,
                     continue_label: start_label,
                     loop_name: None,
+                    defer_depth: self.defer_depth(),
+                    try_depth: self.try_depth,
                 });
-                for node in body {
-                    self.gen_decl_or_stmt(node);
-                }
+                self.gen_block(body);
                 self.loop_labels.pop();
                 self.emit(Instr::Jmp(start_label));
                 self.emit(Instr::Label(end_label));
@@ -392,6 +469,7 @@ impl IRBuilder {
                 let start_label = self.new_label();
                 let end_label = self.new_label();
                 let continue_label = self.new_label();
+                self.emit(Instr::ScopeBegin);
                 self.gen_decl(init);
                 self.emit(Instr::Label(start_label));
                 self.gen_expr(condition);
@@ -400,21 +478,37 @@ impl IRBuilder {
                     break_label: end_label,
                     continue_label,
                     loop_name: None,
+                    defer_depth: self.defer_depth(),
+                    try_depth: self.try_depth,
                 });
-                for node in body {
-                    self.gen_decl_or_stmt(node);
-                }
+                self.gen_block(body);
                 self.loop_labels.pop();
                 self.emit(Instr::Label(continue_label));
                 self.gen_stmt(update);
                 self.emit(Instr::Jmp(start_label));
                 self.emit(Instr::Label(end_label));
+                self.emit(Instr::ScopeEnd);
             }
             Stmt::Return { value, .. } => {
-                if let Some(val) = value {
+                if !self.defers_pending() {
+                    if let Some(val) = value {
+                        self.gen_expr(val);
+                    }
+                    self.emit(Instr::Return);
+                } else if let Some(val) = value {
+                    // El valor se evalúa antes de los `posponer` y se aparta en
+                    // un temporal para que sus efectos no toquen la pila.
+                    let temp = format!("__ret_{}", self.temp_counter);
+                    self.temp_counter += 1;
                     self.gen_expr(val);
+                    self.emit(Instr::Store(temp.clone()));
+                    self.emit(Instr::DeferRun(0));
+                    self.emit(Instr::Load(temp));
+                    self.emit(Instr::Return);
+                } else {
+                    self.emit(Instr::DeferRun(0));
+                    self.emit(Instr::Return);
                 }
-                self.emit(Instr::Return);
             }
             Stmt::FieldAssign {
                 expr, field, value, ..
@@ -525,28 +619,45 @@ impl IRBuilder {
                 self.gen_expr(expr);
             }
             Stmt::Posponer { body, .. } => {
-                for node in body {
-                    self.gen_decl_or_stmt(node);
+                // El cuerpo se emite fuera de línea: DeferPush lo registra en el
+                // frame y DeferRun lo despacha (LIFO) al salir del bloque.
+                let body_label = self.new_label();
+                let skip_label = self.new_label();
+                self.emit(Instr::DeferPush(body_label));
+                self.emit(Instr::Jmp(skip_label));
+                self.emit(Instr::Label(body_label));
+                let saved_loop = std::mem::take(&mut self.loop_labels);
+                self.gen_block(body);
+                self.loop_labels = saved_loop;
+                self.emit(Instr::DeferEnd);
+                self.emit(Instr::Label(skip_label));
+                if let Some(count) = self.defer_scopes.last_mut() {
+                    *count += 1;
                 }
             }
             Stmt::TryCatch {
                 try_body,
-                err_var: _,
+                err_var,
                 catch_body,
                 ..
             } => {
                 let catch_label = self.new_label();
                 let end_label = self.new_label();
 
-                for node in try_body {
-                    self.gen_decl_or_stmt(node);
-                }
+                // La VM salta a `catch_label` con el mensaje de error en la pila
+                // si algo falla (incluso en llamadas anidadas) antes del TryEnd.
+                self.emit(Instr::TryBegin(catch_label));
+                self.try_depth += 1;
+                self.gen_block(try_body);
+                self.try_depth -= 1;
+                self.emit(Instr::TryEnd);
                 self.emit(Instr::Jmp(end_label));
 
                 self.emit(Instr::Label(catch_label));
-                for node in catch_body {
-                    self.gen_decl_or_stmt(node);
-                }
+                self.emit(Instr::ScopeBegin);
+                self.emit_bind(err_var.clone());
+                self.gen_block(catch_body);
+                self.emit(Instr::ScopeEnd);
                 self.emit(Instr::Label(end_label));
             }
             Stmt::Break { label, .. } => {
@@ -555,12 +666,14 @@ impl IRBuilder {
                         .iter()
                         .rev()
                         .find(|ll| ll.loop_name.as_deref() == Some(lbl))
-                        .map(|ll| ll.break_label)
+                        .map(|ll| (ll.break_label, ll.defer_depth, ll.try_depth))
                 } else {
-                    self.loop_labels.last().map(|ll| ll.break_label)
+                    self.loop_labels
+                        .last()
+                        .map(|ll| (ll.break_label, ll.defer_depth, ll.try_depth))
                 };
-                if let Some(t) = target {
-                    self.emit(Instr::Jmp(t));
+                if let Some((t, depth, try_depth)) = target {
+                    self.emit_loop_exit(t, depth, try_depth);
                 }
             }
             Stmt::Continue { label, .. } => {
@@ -569,12 +682,14 @@ impl IRBuilder {
                         .iter()
                         .rev()
                         .find(|ll| ll.loop_name.as_deref() == Some(lbl))
-                        .map(|ll| ll.continue_label)
+                        .map(|ll| (ll.continue_label, ll.defer_depth, ll.try_depth))
                 } else {
-                    self.loop_labels.last().map(|ll| ll.continue_label)
+                    self.loop_labels
+                        .last()
+                        .map(|ll| (ll.continue_label, ll.defer_depth, ll.try_depth))
                 };
-                if let Some(t) = target {
-                    self.emit(Instr::Jmp(t));
+                if let Some((t, depth, try_depth)) = target {
+                    self.emit_loop_exit(t, depth, try_depth);
                 }
             }
             Stmt::Match {
@@ -587,6 +702,7 @@ impl IRBuilder {
                 let mut fail_label = self.new_label();
                 for arm in arms {
                     self.emit(Instr::Label(fail_label));
+                    self.emit(Instr::ScopeBegin);
                     fail_label = self.new_label();
                     let body_label = self.new_label();
                     // Patterns: cada uno salta al body si matchea; si ninguno
@@ -601,23 +717,18 @@ impl IRBuilder {
                         self.gen_expr(guard_expr);
                         self.emit(Instr::JmpIf(fail_label));
                     }
-                    for node in &arm.body {
-                        self.gen_decl_or_stmt(node);
-                    }
+                    self.gen_block(&arm.body);
                     self.emit(Instr::Jmp(end_label));
+                    self.emit(Instr::ScopeEnd);
                 }
                 self.emit(Instr::Label(fail_label));
                 if let Some(default_body) = default {
-                    for node in default_body {
-                        self.gen_decl_or_stmt(node);
-                    }
+                    self.gen_block(default_body);
                 }
                 self.emit(Instr::Label(end_label));
             }
             Stmt::Block { stmts, .. } => {
-                for node in stmts {
-                    self.gen_decl_or_stmt(node);
-                }
+                self.gen_block(stmts);
             }
             Stmt::ForEach {
                 var_name,
@@ -641,6 +752,7 @@ impl IRBuilder {
                 self.emit(Instr::Load(arr_temp.clone()));
                 self.emit(Instr::ArrayLen);
                 self.emit(Instr::Store(len_temp.clone()));
+                self.emit(Instr::ScopeBegin);
                 self.emit(Instr::Label(start_label));
                 self.emit(Instr::Load(idx_temp.clone()));
                 self.emit(Instr::Load(len_temp.clone()));
@@ -649,16 +761,15 @@ impl IRBuilder {
                 self.emit(Instr::Load(arr_temp.clone()));
                 self.emit(Instr::Load(idx_temp.clone()));
                 self.emit(Instr::ArrayGet);
-                self.emit(Instr::Store(var_name.clone()));
-                for node in body {
-                    self.gen_decl_or_stmt(node);
-                }
+                self.emit_bind(var_name.clone());
+                self.gen_block(body);
                 self.emit(Instr::Load(idx_temp.clone()));
                 self.emit(Instr::ConstInt(1));
                 self.emit(Instr::Binary(Op::Add));
                 self.emit(Instr::Store(idx_temp.clone()));
                 self.emit(Instr::Jmp(start_label));
                 self.emit(Instr::Label(end_label));
+                self.emit(Instr::ScopeEnd);
             }
             Stmt::Import { .. } => {}
             Stmt::IfLet {
@@ -674,16 +785,14 @@ impl IRBuilder {
                 self.emit(Instr::Store(temp.clone()));
                 let el = self.new_label();
                 let end_l = self.new_label();
+                self.emit(Instr::ScopeBegin);
                 self.emit_if_let_pattern(&temp, pattern, el);
-                for n in then_body {
-                    self.gen_decl_or_stmt(n);
-                }
+                self.gen_block(then_body);
                 self.emit(Instr::Jmp(end_l));
+                self.emit(Instr::ScopeEnd);
                 self.emit(Instr::Label(el));
                 if let Some(eb) = else_body {
-                    for n in eb {
-                        self.gen_decl_or_stmt(n);
-                    }
+                    self.gen_block(eb);
                 }
                 self.emit(Instr::Label(end_l));
             }
@@ -702,9 +811,7 @@ impl IRBuilder {
                 self.emit_if_let_pattern(&temp, pattern, else_l);
                 self.emit(Instr::Jmp(end_l));
                 self.emit(Instr::Label(else_l));
-                for n in else_body {
-                    self.gen_decl_or_stmt(n);
-                }
+                self.gen_block(else_body);
                 self.emit(Instr::Label(end_l));
             }
             Stmt::Destructure { targets, value, .. } => {
@@ -849,6 +956,8 @@ impl IRBuilder {
                                     | "read"
                                     | "a_texto"
                                     | "to_texto"
+                                    | "lanzar"
+                                    | "throw"
                                     | "abs"
                                     | "absoluto"
                                     | "min"
@@ -1002,6 +1111,12 @@ impl IRBuilder {
                                     | "__http_enviar"
                                     | "__http_server"
                                     | "__http_servidor"
+                                    | "__http_servidor_direccion"
+                                    | "__http_server_addr"
+                                    | "__http_servidor_detener"
+                                    | "__http_server_stop"
+                                    | "__http_servidor_esperar"
+                                    | "__http_server_wait"
                                     | "__serial_open"
                                     | "__serial_abrir"
                                     | "__actor_enviar"
@@ -1010,6 +1125,14 @@ impl IRBuilder {
                                     | "__actor_recibir"
                                     | "__actor_recv"
                                     | "__actor_send"
+                                    | "__actor_monitorear"
+                                    | "__actor_monitor"
+                                    | "__actor_enlazar"
+                                    | "__actor_link"
+                                    | "__actor_detener"
+                                    | "__actor_stop"
+                                    | "__actor_vivo"
+                                    | "__actor_alive"
                                     | "__aes_decrypt"
                                     | "__aes_desencriptar"
                                     | "__aes_encriptar"
@@ -1030,6 +1153,18 @@ impl IRBuilder {
                                     | "__cluster_connect"
                                     | "__cluster_enviar"
                                     | "__cluster_send"
+                                    | "__cluster_nodo"
+                                    | "__cluster_node"
+                                    | "__cluster_direccion"
+                                    | "__cluster_addr"
+                                    | "__cluster_registrar"
+                                    | "__cluster_register"
+                                    | "__cluster_monitorear"
+                                    | "__cluster_monitor"
+                                    | "__cluster_nodos"
+                                    | "__cluster_nodes"
+                                    | "__cluster_detener"
+                                    | "__cluster_stop"
                                     | "__coro_ceder"
                                     | "__coro_crear"
                                     | "__coro_create"
@@ -1082,6 +1217,36 @@ impl IRBuilder {
                                     | "__mutex_lock"
                                     | "__mutex_new"
                                     | "__mutex_nuevo"
+                                    | "__mutex_adquirir"
+                                    | "__mutex_acquire"
+                                    | "__mutex_intentar"
+                                    | "__mutex_try_lock"
+                                    | "__mutex_liberar"
+                                    | "__mutex_release"
+                                    | "__atomico_nuevo"
+                                    | "__atomic_new"
+                                    | "__atomico_cargar"
+                                    | "__atomic_load"
+                                    | "__atomico_guardar"
+                                    | "__atomic_store"
+                                    | "__atomico_sumar"
+                                    | "__atomic_fetch_add"
+                                    | "__atomico_intercambiar"
+                                    | "__atomic_swap"
+                                    | "__atomico_comparar_intercambiar"
+                                    | "__atomic_compare_exchange"
+                                    | "__condvar_nuevo"
+                                    | "__condvar_new"
+                                    | "__condvar_esperar"
+                                    | "__condvar_wait"
+                                    | "__condvar_notificar"
+                                    | "__condvar_notify_one"
+                                    | "__condvar_notificar_todos"
+                                    | "__condvar_notify_all"
+                                    | "__barrera_nueva"
+                                    | "__barrier_new"
+                                    | "__barrera_esperar"
+                                    | "__barrier_wait"
                                     | "__par_join"
                                     | "__par_map"
                                     | "__par_mapear"
@@ -1094,9 +1259,13 @@ impl IRBuilder {
                                     | "__rwlock_write"
                                     | "__scope_cancel"
                                     | "__scope_cancelar"
+                                    | "__scope_ejecutar"
+                                    | "__scope_esperar"
+                                    | "__scope_join"
                                     | "__scope_lanzar"
                                     | "__scope_new"
                                     | "__scope_nuevo"
+                                    | "__scope_run"
                                     | "__scope_spawn"
                                     | "__seleccionar"
                                     | "__select"
@@ -1115,6 +1284,12 @@ impl IRBuilder {
                                     | "__supervisor_new"
                                     | "__supervisor_nuevo"
                                     | "__supervisor_start"
+                                    | "__supervisor_detener"
+                                    | "__supervisor_stop"
+                                    | "__supervisor_reinicios"
+                                    | "__supervisor_restarts"
+                                    | "__supervisor_activo"
+                                    | "__supervisor_active"
                                     | "__tarea_esperar"
                                     | "__tarea_lanzar"
                                     | "__task_await"
@@ -1198,11 +1373,12 @@ impl IRBuilder {
                         }
                     }
                     Expr::Lambda { params, body, .. } => {
-                        let lambda_name = self.compile_lambda(params, body);
+                        let closure = self.compile_lambda(params, body);
+                        self.emit(closure);
                         for arg in args {
                             self.gen_expr(arg);
                         }
-                        self.emit(Instr::Call(lambda_name, args.len()));
+                        self.emit(Instr::CallValue(args.len()));
                     }
                     _ => {
                         self.gen_expr(callee);
@@ -1325,8 +1501,8 @@ impl IRBuilder {
                 self.emit(Instr::StructGet);
             }
             Expr::Lambda { params, body, .. } => {
-                let lambda_name = self.compile_lambda(params, body);
-                self.emit(Instr::FuncRef(lambda_name));
+                let closure = self.compile_lambda(params, body);
+                self.emit(closure);
             }
             Expr::Exito { expr, .. } => {
                 self.gen_expr(expr);
@@ -1338,6 +1514,22 @@ impl IRBuilder {
             }
             Expr::Intentar { expr, .. } => {
                 self.gen_expr(expr);
+                if self.defers_pending() {
+                    // Propagar un Error sale de la función: antes de hacerlo se
+                    // ejecutan los `posponer` pendientes del frame.
+                    let temp = format!("__try_{}", self.temp_counter);
+                    self.temp_counter += 1;
+                    let ok_label = self.new_label();
+                    self.emit(Instr::Store(temp.clone()));
+                    self.emit(Instr::Load(temp.clone()));
+                    self.emit(Instr::MatchType(2));
+                    self.emit(Instr::JmpIf(ok_label));
+                    self.emit(Instr::DeferRun(0));
+                    self.emit(Instr::Load(temp.clone()));
+                    self.emit(Instr::Return);
+                    self.emit(Instr::Label(ok_label));
+                    self.emit(Instr::Load(temp));
+                }
                 self.emit(Instr::TryUnwrap);
             }
             Expr::Algun { expr, .. } => {
@@ -1438,6 +1630,7 @@ impl IRBuilder {
                 let loop_end = self.new_label();
                 let skip_label = self.new_label();
 
+                self.emit(Instr::ScopeBegin);
                 self.emit(Instr::Label(loop_start));
                 self.emit(Instr::Load(i_temp.clone()));
                 self.emit(Instr::Load(len_temp.clone()));
@@ -1447,7 +1640,7 @@ impl IRBuilder {
                 self.emit(Instr::Load(iter_temp.clone()));
                 self.emit(Instr::Load(i_temp.clone()));
                 self.emit(Instr::ArrayGet);
-                self.emit(Instr::Store(var_name.clone()));
+                self.emit_bind(var_name.clone());
 
                 if let Some(cond) = condition {
                     self.gen_expr(cond);
@@ -1467,6 +1660,7 @@ impl IRBuilder {
 
                 self.emit(Instr::Jmp(loop_start));
                 self.emit(Instr::Label(loop_end));
+                self.emit(Instr::ScopeEnd);
 
                 self.emit(Instr::Load(out_arr.clone()));
             }
@@ -1502,6 +1696,7 @@ impl IRBuilder {
                 let loop_end = self.new_label();
                 let skip_label = self.new_label();
 
+                self.emit(Instr::ScopeBegin);
                 self.emit(Instr::Label(loop_start));
                 self.emit(Instr::Load(i_temp.clone()));
                 self.emit(Instr::Load(len_temp.clone()));
@@ -1511,7 +1706,7 @@ impl IRBuilder {
                 self.emit(Instr::Load(src_temp.clone()));
                 self.emit(Instr::Load(i_temp.clone()));
                 self.emit(Instr::ArrayGet);
-                self.emit(Instr::Store(var_name.clone()));
+                self.emit_bind(var_name.clone());
 
                 if let Some(cond) = where_clause {
                     self.gen_expr(cond);
@@ -1531,6 +1726,7 @@ impl IRBuilder {
 
                 self.emit(Instr::Jmp(loop_start));
                 self.emit(Instr::Label(loop_end));
+                self.emit(Instr::ScopeEnd);
 
                 self.emit(Instr::Load(out_arr.clone()));
             }
@@ -1540,7 +1736,9 @@ impl IRBuilder {
         }
     }
 
-    fn compile_lambda(&mut self, params: &[Param], body: &[DeclOrStmt]) -> String {
+    /// Compila la lambda como función `__lambda_N` y devuelve el
+    /// `MakeClosure` que crea su valor.
+    fn compile_lambda(&mut self, params: &[Param], body: &[DeclOrStmt]) -> Instr {
         let lambda_name = format!("__lambda_{}", self.lambda_counter);
         self.lambda_counter += 1;
 
@@ -1556,39 +1754,106 @@ impl IRBuilder {
             defaults: param_defaults,
             entry: 0,
             instrs: Vec::new(),
+            declared: Vec::new(),
         };
         self.program.funcs.insert(lambda_name.clone(), func);
         let saved_instrs = std::mem::take(&mut self.current_instrs);
         let saved_func = self.current_func.clone();
         let saved_temp = self.temp_counter;
         let saved_loop = std::mem::take(&mut self.loop_labels);
+        let saved_defers = std::mem::replace(&mut self.defer_scopes, vec![0]);
+        let saved_try = std::mem::take(&mut self.try_depth);
         let saved_is_lambda = self.is_in_lambda;
         self.current_func = Some(lambda_name.clone());
         self.current_instrs = Vec::new();
         self.temp_counter = 0;
         self.is_in_lambda = true;
-        for node in body {
-            self.gen_decl_or_stmt(node);
-        }
+        self.gen_block(body);
         self.emit_return_if_needed();
         self.finalize_func();
         self.current_func = saved_func;
         self.current_instrs = saved_instrs;
         self.temp_counter = saved_temp;
         self.loop_labels = saved_loop;
+        self.defer_scopes = saved_defers;
+        self.try_depth = saved_try;
         self.is_in_lambda = saved_is_lambda;
-        lambda_name
+        let free = free_variables(&self.program.funcs[&lambda_name]);
+        Instr::MakeClosure(lambda_name, free)
     }
 
     fn emit(&mut self, instr: Instr) {
         self.current_instrs.push(instr);
     }
 
+    /// Marca el inicio de una sentencia para la tabla de líneas. Los nodos
+    /// sintetizados (línea 0) no tienen posición.
+    fn emit_loc(&mut self, span: Span) {
+        if span.start.line > 0 {
+            self.emit(Instr::Loc(
+                self.current_file,
+                span.start.line as u32,
+                span.start.col as u32,
+            ));
+        }
+    }
+
+    /// Emite un bloque con su propio ámbito de variables y de `posponer`: si
+    /// el bloque registró alguno, al caer por el final se ejecutan antes de
+    /// salir.
+    fn gen_block(&mut self, nodes: &[DeclOrStmt]) {
+        let depth = self.defer_depth();
+        self.defer_scopes.push(0);
+        self.emit(Instr::ScopeBegin);
+        for node in nodes {
+            self.gen_decl_or_stmt(node);
+        }
+        let registered = self.defer_scopes.pop().unwrap_or(0);
+        let unreachable = matches!(
+            self.current_instrs
+                .iter()
+                .rev()
+                .find(|i| !matches!(i, Instr::ScopeBegin | Instr::ScopeEnd)),
+            Some(Instr::Return | Instr::Halt | Instr::Jmp(_))
+        );
+        if registered > 0 && !unreachable {
+            self.emit(Instr::DeferRun(depth));
+        }
+        self.emit(Instr::ScopeEnd);
+    }
+
+    fn defer_depth(&self) -> usize {
+        self.defer_scopes.iter().sum()
+    }
+
+    /// `retornar` y `?` solo necesitan vaciar la pila de `posponer` dentro de
+    /// funciones; en el nivel superior la vacía el `Halt` final.
+    fn defers_pending(&self) -> bool {
+        self.defer_depth() > 0 && self.current_func.as_deref() != Some("__main__")
+    }
+
+    fn emit_loop_exit(&mut self, target: usize, depth: usize, try_depth: usize) {
+        for _ in try_depth..self.try_depth {
+            self.emit(Instr::TryEnd);
+        }
+        if self.defer_depth() > depth {
+            self.emit(Instr::DeferRun(depth));
+        }
+        self.emit(Instr::Jmp(target));
+    }
+
     fn last_significant(instrs: &[Instr]) -> Option<&Instr> {
-        instrs
-            .iter()
-            .rev()
-            .find(|i| !matches!(i, Instr::Label(_) | Instr::Nop | Instr::Phi(_, _)))
+        instrs.iter().rev().find(|i| {
+            !matches!(
+                i,
+                Instr::Label(_)
+                    | Instr::Nop
+                    | Instr::Phi(_, _)
+                    | Instr::Loc(..)
+                    | Instr::ScopeBegin
+                    | Instr::ScopeEnd
+            )
+        })
     }
 
     fn needs_return(&self) -> bool {
@@ -1613,6 +1878,9 @@ impl IRBuilder {
 
     fn emit_halt_if_needed(&mut self) {
         if self.needs_halt() {
+            if self.defer_depth() > 0 {
+                self.emit(Instr::DeferRun(0));
+            }
             self.emit(Instr::Halt);
         }
     }
@@ -1654,7 +1922,7 @@ impl IRBuilder {
             }
             Expr::Ident { name, .. } => {
                 self.emit(Instr::Load(temp.to_string()));
-                self.emit(Instr::Store(name.clone()));
+                self.emit_bind(name.clone());
             }
             _ => {
                 // Fallback: comparación por igualdad (pattern como valor).
@@ -1671,7 +1939,7 @@ impl IRBuilder {
         self.emit(Instr::MatchPayload);
         match pattern {
             Expr::Ident { name, .. } => {
-                self.emit(Instr::Store(name.clone()));
+                self.emit_bind(name.clone());
             }
             Expr::List { items, .. } => {
                 let t2 = format!("__mt_{}", self.temp_counter);
@@ -1681,7 +1949,7 @@ impl IRBuilder {
                     if let Expr::Ident { name, .. } = it {
                         self.emit(Instr::Load(t2.clone()));
                         self.emit(Instr::TupleAccess(i));
-                        self.emit(Instr::Store(name.clone()));
+                        self.emit_bind(name.clone());
                     }
                 }
             }
@@ -1735,7 +2003,7 @@ impl IRBuilder {
                         Expr::Ident { name, .. } if name != "_" => {
                             self.emit(Instr::Load(temp.clone()));
                             self.emit(Instr::TupleAccess(idx));
-                            self.emit(Instr::Store(name.clone()));
+                            self.emit_bind(name.clone());
                         }
                         Expr::Ident { name, .. } if name == "_" => {}
                         _ => {
@@ -1760,7 +2028,7 @@ impl IRBuilder {
                             self.emit(Instr::Load(temp.clone()));
                             self.emit(Instr::ConstStr(field_name.clone()));
                             self.emit(Instr::StructGet);
-                            self.emit(Instr::Store(name.clone()));
+                            self.emit_bind(name.clone());
                         }
                         Expr::Ident { name, .. } if name == "_" => {}
                         _ => {
@@ -1784,6 +2052,26 @@ impl IRBuilder {
         }
     }
 
+    /// Registra `name` como variable declarada en la función actual.
+    fn declare(&mut self, name: &str) {
+        if let Some(func) = self
+            .current_func
+            .as_ref()
+            .and_then(|f| self.program.funcs.get_mut(f))
+        {
+            if !func.declared.iter().any(|d| d == name) {
+                func.declared.push(name.to_string());
+            }
+        }
+    }
+
+    /// Declara `name` y le asigna el valor en la cima de la pila.
+    fn emit_bind(&mut self, name: String) {
+        self.declare(&name);
+        self.emit(Instr::Bind(name.clone()));
+        self.emit(Instr::Store(name));
+    }
+
     fn finalize_func(&mut self) {
         if let Some(ref name) = self.current_func {
             if let Some(func) = self.program.funcs.get_mut(name) {
@@ -2070,6 +2358,47 @@ impl IRBuilder {
     }
 }
 
+/// Variables que usa el cuerpo de una lambda sin declararlas: las que lee o
+/// asigna fuera del ámbito de una declaración propia y las que capturan sus
+/// propias lambdas. Los temporales (`__*`) son siempre del frame de la lambda.
+fn free_variables(func: &Func) -> Vec<String> {
+    let mut free: Vec<String> = Vec::new();
+    let mut scopes: Vec<Vec<&String>> = vec![Vec::new()];
+    for instr in &func.instrs {
+        let names = match instr {
+            Instr::ScopeBegin => {
+                scopes.push(Vec::new());
+                continue;
+            }
+            Instr::ScopeEnd => {
+                if scopes.len() > 1 {
+                    scopes.pop();
+                }
+                continue;
+            }
+            Instr::Bind(n) => {
+                if let Some(scope) = scopes.last_mut() {
+                    scope.push(n);
+                }
+                continue;
+            }
+            Instr::Load(n) | Instr::Store(n) => std::slice::from_ref(n),
+            Instr::MakeClosure(_, inner) => inner.as_slice(),
+            _ => &[],
+        };
+        for name in names {
+            if !name.starts_with("__")
+                && !func.params.contains(name)
+                && !scopes.iter().flatten().any(|n| *n == name)
+                && !free.contains(name)
+            {
+                free.push(name.clone());
+            }
+        }
+    }
+    free
+}
+
 fn expr_to_ir_value(expr: &Expr) -> Option<Value> {
     match expr {
         Expr::Int { value, .. } => Some(Value::Int(*value)),
@@ -2314,6 +2643,21 @@ imprimir(x);";
         assert!(matches!(folded[0], Instr::ConstFloat(v) if (v - 5.5).abs() < f64::EPSILON));
     }
 
+    #[test]
+    fn test_posponer_lowering() {
+        let source = "funcion numero f() { posponer { imprimir(1); } retornar 2; }";
+        let program = build_ir(source);
+        let f = program.funcs.get("f").unwrap();
+        let push = f
+            .instrs
+            .iter()
+            .position(|i| matches!(i, Instr::DeferPush(_)))
+            .expect("DeferPush");
+        assert!(f.instrs[push..].contains(&Instr::DeferEnd));
+        let ret = f.instrs.iter().rposition(|i| *i == Instr::Return).unwrap();
+        assert!(f.instrs[..ret].contains(&Instr::DeferRun(0)));
+    }
+
     #[test]
     fn test_dce_removes_consecutive_nops() {
         let mut func = Func {
@@ -2330,6 +2674,7 @@ imprimir(x);";
                 Instr::Nop,
                 Instr::Store("x".to_string()),
             ],
+            declared: vec![],
         };
         IRBuilder::optimize_func(&mut func);
         assert_eq!(func.instrs.len(), 4);
@@ -48,6 +48,22 @@ pub enum Instr {
     Unary(Op),
     Call(String, usize),
     FuncRef(String),
+    /// Valor de la lambda dada con las variables libres de su cuerpo.
+    /// `codegen` captura por referencia las que son locales de la función
+    /// que la crea; las demás son globales y no hace falta capturarlas.
+    MakeClosure(String, Vec<String>),
+    /// Declaración de la variable en el ámbito abierto más interno: el `Store`
+    /// que sigue (si la declaración tiene valor inicial) empieza una variable
+    /// nueva y las clausuras que capturaron la anterior la conservan (cada
+    /// vuelta de un bucle tiene la suya). Solo genera código si alguna
+    /// clausura captura la variable.
+    Bind(String),
+    /// Abre un ámbito de bloque: las variables que declara (`Bind`) tapan a
+    /// las de fuera con el mismo nombre hasta su `ScopeEnd`. No genera
+    /// código: `codegen` da un slot a cada declaración del ámbito.
+    ScopeBegin,
+    /// Cierra el ámbito abierto más reciente.
+    ScopeEnd,
     CallValue(usize),
     Return,
     Print,
@@ -78,6 +94,20 @@ pub enum Instr {
     JmpIf(usize),
     Label(usize),
     Phi(usize, usize),
+    /// Registra el bloque `posponer` que empieza en la etiqueta dada.
+    DeferPush(usize),
+    /// Ejecuta (LIFO) los bloques pospuestos del frame hasta que queden `n`.
+    DeferRun(usize),
+    /// Fin de un bloque pospuesto: vuelve al `DeferRun` que lo despachó.
+    DeferEnd,
+    /// Instala un manejador `atrapar` que empieza en la etiqueta dada.
+    TryBegin(usize),
+    /// Retira el manejador `atrapar` más reciente.
+    TryEnd,
+    /// Posición de fuente (archivo de `Program::files`, línea y columna
+    /// desde 1) de las instrucciones que siguen. No genera código: `codegen`
+    /// la pasa a la tabla de líneas.
+    Loc(u32, u32, u32),
     Nop,
     Halt,
 }
@@ -89,12 +119,18 @@ pub struct Func {
     pub defaults: Vec<Option<Value>>,
     pub entry: usize,
     pub instrs: Vec<Instr>,
+    /// Variables declaradas en el cuerpo (sin contar los parámetros), en orden
+    /// de aparición. `codegen` las usa para resolver los slots del frame.
+    pub declared: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Program {
     pub funcs: BTreeMap<String, Func>,
     pub entry: String,
+    /// Archivos de los que sale el código: el principal primero y después
+    /// los módulos importados. Vacío si no se dieron los orígenes.
+    pub files: Vec<String>,
 }
 
 impl Program {
@@ -102,6 +138,7 @@ impl Program {
         Self {
             funcs: BTreeMap::new(),
             entry: String::new(),
+            files: Vec::new(),
         }
     }
 }
@@ -0,0 +1,419 @@
+//! Diagnóstico común a todas las fases del compilador.
+//!
+//! `LexError`, `ParseError`, `SemError` y los avisos del linter se convierten
+//! a [`Diagnostic`], que es lo que consumen la CLI (fragmentos de código
+//! anotados al estilo de rustc), `lumen check --format json`, el LSP y
+//! `LumenEngine::check`.
+//!
+//! Las posiciones son las del lexer: líneas y columnas desde 1, contadas en
+//! caracteres, y el final de un `Span` es inclusivo.
+
+use crate::token::{Pos, Span};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "advertencia",
+            Severity::Error => "error",
+        }
+    }
+
+    fn ansi(&self) -> &'static str {
+        match self {
+            Severity::Info => "1;36",
+            Severity::Warning => "1;33",
+            Severity::Error => "1;31",
+        }
+    }
+}
+
+/// Un tramo del código con un texto que lo explica. El mensaje puede estar
+/// vacío: entonces solo se subraya.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Label {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+/// Reemplazo de texto entre `start` (inclusivo) y `end` (exclusivo). Una
+/// inserción tiene `start == end`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Edit {
+    pub start: Pos,
+    pub end: Pos,
+    pub replacement: String,
+}
+
+impl Edit {
+    /// Sustituye el texto que cubre `span`.
+    pub fn replace(span: Span, replacement: impl Into<String>) -> Self {
+        Self {
+            start: span.start,
+            end: Pos::new(span.end.line, span.end.col + 1),
+            replacement: replacement.into(),
+        }
+    }
+
+    /// Inserta `text` justo después del último carácter de `span`.
+    pub fn insert_after(span: Span, text: impl Into<String>) -> Self {
+        let at = Pos::new(span.end.line, span.end.col + 1);
+        Self {
+            start: at,
+            end: at,
+            replacement: text.into(),
+        }
+    }
+}
+
+/// Corrección que se puede aplicar sin intervención del usuario.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fix {
+    pub message: String,
+    pub edits: Vec<Edit>,
+}
+
+impl Fix {
+    pub fn new(message: impl Into<String>, edits: Vec<Edit>) -> Self {
+        Self {
+            message: message.into(),
+            edits,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub code: String,
+    pub severity: Severity,
+    pub message: String,
+    pub primary: Label,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secondary: Vec<Label>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    pub suggestion: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fixes: Vec<Fix>,
+    /// Archivo al que pertenecen las posiciones, si no es el que se está
+    /// analizando (p. ej. un módulo importado con errores).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(
+        severity: Severity,
+        code: impl Into<String>,
+        message: impl Into<String>,
+        span: Span,
+    ) -> Self {
+        Self {
+            code: code.into(),
+            severity,
+            message: message.into(),
+            primary: Label::new(span, ""),
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            suggestion: String::new(),
+            fixes: Vec::new(),
+            file: None,
+        }
+    }
+
+    pub fn error(code: impl Into<String>, message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Error, code, message, span)
+    }
+
+    pub fn span(&self) -> Span {
+        self.primary.span
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn with_label(mut self, message: impl Into<String>) -> Self {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label::new(span, message));
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = suggestion.into();
+        self
+    }
+
+    pub fn with_fix(mut self, fix: Fix) -> Self {
+        self.fixes.push(fix);
+        self
+    }
+
+    pub fn in_file(mut self, file: impl Into<String>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Fragmento de código anotado al estilo de rustc:
+    ///
+    /// ```text
+    /// error[E032]: La variable 'x' ya está declarada en este ámbito
+    ///  --> main.nv:3:1
+    ///   |
+    /// 1 | entero x = 1;
+    ///   | ------------- declarada aquí
+    /// 2 | imprimir(x);
+    /// 3 | entero x = 2;
+    ///   | ^^^^^^^^^^^^^
+    ///   |
+    ///   = ayuda: Usa un nombre diferente
+    /// ```
+    pub fn render(&self, source: &str, path: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color && !text.is_empty() {
+                format!("\x1b[{}m{}\x1b[0m", style, text)
+            } else {
+                text.to_string()
+            }
+        };
+        let path = self.file.as_deref().unwrap_or(path);
+        let lines: Vec<&str> = source.lines().collect();
+
+        let mut labels: Vec<(&Label, bool)> = vec![(&self.primary, true)];
+        labels.extend(self.secondary.iter().map(|l| (l, false)));
+        labels.sort_by_key(|(l, _)| (l.span.start.line, l.span.start.col));
+        let mut shown: Vec<usize> = labels
+            .iter()
+            .map(|(l, _)| l.span.start.line)
+            .filter(|&n| n >= 1 && n <= lines.len())
+            .collect();
+        shown.dedup();
+        let width = shown.last().map_or(1, |n| n.to_string().len());
+        let pad = " ".repeat(width);
+        let bar = paint("1;34", "|");
+
+        let mut out = String::new();
+        out.push_str(&format!(
+            "{}: {}\n",
+            paint(
+                self.severity.ansi(),
+                &format!("{}[{}]", self.severity.as_str(), self.code)
+            ),
+            paint("1", &self.message)
+        ));
+        let start = self.primary.span.start;
+        out.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            pad,
+            paint("1;34", "-->"),
+            path,
+            start.line,
+            start.col
+        ));
+        out.push_str(&format!("{} {}\n", pad, bar));
+
+        let mut prev: Option<usize> = None;
+        for &n in &shown {
+            match prev {
+                Some(p) if n == p + 2 => out.push_str(&format!(
+                    "{} {} {}\n",
+                    paint("1;34", &format!("{:>w$}", p + 1, w = width)),
+                    bar,
+                    lines[p]
+                )),
+                Some(p) if n > p + 2 => out.push_str(&format!("{}\n", paint("1;34", "..."))),
+                _ => {}
+            }
+            let text = lines[n - 1];
+            out.push_str(&format!(
+                "{} {} {}\n",
+                paint("1;34", &format!("{:>w$}", n, w = width)),
+                bar,
+                text
+            ));
+            for (label, primary) in labels.iter().filter(|(l, _)| l.span.start.line == n) {
+                let chars: Vec<char> = text.chars().collect();
+                let from = label.span.start.col.saturating_sub(1).min(chars.len());
+                let to = if label.span.end.line == n {
+                    label.span.end.col.min(chars.len())
+                } else {
+                    chars.len()
+                };
+                // Se conservan los tabuladores para que el subrayado quede alineado.
+                let indent: String = chars[..from]
+                    .iter()
+                    .map(|&c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let (mark, style) = if *primary {
+                    ('^', self.severity.ansi())
+                } else {
+                    ('-', "1;34")
+                };
+                let underline = mark.to_string().repeat(to.saturating_sub(from).max(1));
+                let mut row = format!("{} {} {}{}", pad, bar, indent, paint(style, &underline));
+                if !label.message.is_empty() {
+                    row.push(' ');
+                    row.push_str(&paint(style, &label.message));
+                }
+                out.push_str(row.trim_end());
+                out.push('\n');
+            }
+            prev = Some(n);
+        }
+
+        out.push_str(&format!("{} {}\n", pad, bar));
+        for note in &self.notes {
+            out.push_str(&format!("{} {} {}\n", pad, paint("1", "= nota:"), note));
+        }
+        if !self.suggestion.is_empty() {
+            out.push_str(&format!(
+                "{} {} {}\n",
+                pad,
+                paint("1;33", "= ayuda:"),
+                self.suggestion
+            ));
+        }
+        for fix in &self.fixes {
+            out.push_str(&format!(
+                "{} {} {}\n",
+                pad,
+                paint("1;32", "= arreglo:"),
+                fix.message
+            ));
+        }
+        out
+    }
+}
+
+/// Forma compacta de una línea, la que usan los mensajes de error de los
+/// módulos importados y la salida de texto de `LumenEngine`.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let start = self.primary.span.start;
+        write!(
+            f,
+            "{} [{}:{}]: {}",
+            self.code, start.line, start.col, self.message
+        )?;
+        if !self.suggestion.is_empty() {
+            write!(f, " ({})", self.suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Aplica las ediciones a `source`. Las ediciones no deben solaparse; el
+/// orden en el que lleguen da igual.
+pub fn apply_edits(source: &str, edits: &[Edit]) -> String {
+    let mut line_starts = vec![0usize];
+    for (i, c) in source.char_indices() {
+        if c == '\n' {
+            line_starts.push(i + 1);
+        }
+    }
+    let offset = |pos: Pos| -> usize {
+        let Some(&start) = line_starts.get(pos.line.saturating_sub(1)) else {
+            return source.len();
+        };
+        let line = &source[start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        start
+            + line
+                .char_indices()
+                .nth(pos.col.saturating_sub(1))
+                .map_or(line.len(), |(i, _)| i)
+    };
+    let mut ranges: Vec<(usize, usize, &str)> = edits
+        .iter()
+        .map(|e| (offset(e.start), offset(e.end), e.replacement.as_str()))
+        .collect();
+    ranges.sort_by_key(|r| std::cmp::Reverse(r.0));
+    let mut out = source.to_string();
+    for (start, end, text) in ranges {
+        out.replace_range(start..end.max(start), text);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(line: usize, start: usize, end: usize) -> Span {
+        Span::new(Pos::new(line, start), Pos::new(line, end))
+    }
+
+    #[test]
+    fn test_render_con_etiquetas() {
+        let source = "entero x = 1;\nimprimir(x);\nentero x = 2;\n";
+        let diag = Diagnostic::error("E032", "La variable 'x' ya está declarada", span(3, 8, 8))
+            .with_label("segunda declaración")
+            .with_secondary(span(1, 8, 8), "declarada aquí")
+            .with_suggestion("Usa otro nombre");
+        let out = diag.render(source, "main.nv", false);
+        let expected = "\
+error[E032]: La variable 'x' ya está declarada
+ --> main.nv:3:8
+  |
+1 | entero x = 1;
+  |        - declarada aquí
+2 | imprimir(x);
+3 | entero x = 2;
+  |        ^ segunda declaración
+  |
+  = ayuda: Usa otro nombre
+";
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_aplicar_ediciones() {
+        let source = "entero año = 1\nimprimir(año);\n";
+        let edits = vec![
+            Edit::replace(span(2, 10, 12), "anio"),
+            Edit::insert_after(span(1, 14, 14), ";"),
+            Edit::replace(span(1, 8, 10), "anio"),
+        ];
+        assert_eq!(
+            apply_edits(source, &edits),
+            "entero anio = 1;\nimprimir(anio);\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let diag = Diagnostic::error("E012", "Se esperaba ';'", span(1, 1, 6));
+        let json = serde_json::to_value(&diag).unwrap();
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["primary"]["span"]["start"]["col"], 1);
+        assert!(json.get("fixes").is_none());
+    }
+}
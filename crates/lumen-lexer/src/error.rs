@@ -1,4 +1,5 @@
-use crate::token::Pos;
+use crate::diagnostic::Diagnostic;
+use crate::token::{Pos, Span};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,3 +11,10 @@ pub struct LexError {
 }
 
 pub type LexResult<T> = Result<T, Vec<LexError>>;
+
+impl From<&LexError> for Diagnostic {
+    fn from(e: &LexError) -> Self {
+        Diagnostic::error(&e.code, &e.message, Span::new(e.pos, e.pos))
+            .with_suggestion(&e.suggestion)
+    }
+}
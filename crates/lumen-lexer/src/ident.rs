@@ -0,0 +1,198 @@
+//! Reglas de los identificadores: las de UAX #31 (`XID_Start` seguido de
+//! `XID_Continue`, más `_` al inicio) con normalización NFC, de modo que
+//! `año` escrito con la `ñ` precompuesta o como `n` + tilde combinante es el
+//! mismo nombre. También lo que usa el linter para avisar de nombres que se
+//! confunden a la vista.
+
+use std::fmt;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+pub fn is_ident_start(ch: char) -> bool {
+    ch == '_' || unicode_ident::is_xid_start(ch)
+}
+
+pub fn is_ident_continue(ch: char) -> bool {
+    unicode_ident::is_xid_continue(ch)
+}
+
+/// Forma NFC de un identificador; la que guardan los tokens.
+pub fn normalize(ident: &str) -> String {
+    if ident.is_ascii() || is_nfc_quick(ident.chars()) == IsNormalized::Yes {
+        ident.to_string()
+    } else {
+        ident.nfc().collect()
+    }
+}
+
+/// Si `name` es un identificador válido (sin mirar si es palabra clave).
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_ident_start) && chars.all(is_ident_continue)
+}
+
+/// Convierte un texto cualquiera (el nombre de un archivo, por ejemplo) en
+/// un identificador: NFC, `_` en lugar de lo que no vale y `_` delante si
+/// empieza por algo que no puede empezar un nombre.
+pub fn sanitize(name: &str) -> String {
+    let mut out: String = normalize(name)
+        .chars()
+        .map(|c| if is_ident_continue(c) { c } else { '_' })
+        .collect();
+    if !out.starts_with(is_ident_start) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+/// Escrituras cuyas letras se confunden entre sí. Las demás (han, árabe...)
+/// no se comparan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Script::Latin => "latina",
+            Script::Greek => "griega",
+            Script::Cyrillic => "cirílica",
+        })
+    }
+}
+
+/// Escritura de una letra; `None` para dígitos, `_`, marcas combinantes y
+/// letras de otras escrituras.
+pub fn script(ch: char) -> Option<Script> {
+    match ch {
+        'A'..='Z'
+        | 'a'..='z'
+        | '\u{AA}'
+        | '\u{BA}'
+        | '\u{C0}'..='\u{D6}'
+        | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{24F}'
+        | '\u{1E00}'..='\u{1EFF}' => Some(Script::Latin),
+        '\u{370}'..='\u{3FF}' | '\u{1F00}'..='\u{1FFF}' => Some(Script::Greek),
+        '\u{400}'..='\u{52F}' | '\u{1C80}'..='\u{1C8F}' | '\u{A640}'..='\u{A69F}' => {
+            Some(Script::Cyrillic)
+        }
+        _ => None,
+    }
+}
+
+/// Escrituras distintas que aparecen en `ident`, en orden de aparición.
+pub fn scripts(ident: &str) -> Vec<Script> {
+    let mut found = Vec::new();
+    for s in ident.chars().filter_map(script) {
+        if !found.contains(&s) {
+            found.push(s);
+        }
+    }
+    found
+}
+
+/// Esqueleto de un identificador: cada letra griega o cirílica que se
+/// confunde con una latina se cambia por esta (al estilo de UTS #39). Dos
+/// nombres distintos con el mismo esqueleto se leen igual.
+pub fn skeleton(ident: &str) -> String {
+    normalize(ident).chars().map(prototype).collect()
+}
+
+fn prototype(ch: char) -> char {
+    match ch {
+        // Cirílico
+        'а' => 'a',
+        'е' | 'ё' => 'e',
+        'і' => 'i',
+        'ј' => 'j',
+        'к' => 'k',
+        'о' => 'o',
+        'р' => 'p',
+        'с' => 'c',
+        'ѕ' => 's',
+        'у' => 'y',
+        'х' => 'x',
+        'һ' => 'h',
+        'ԁ' => 'd',
+        'ԛ' => 'q',
+        'ԝ' => 'w',
+        'А' => 'A',
+        'В' => 'B',
+        'Е' => 'E',
+        'І' => 'I',
+        'Ј' => 'J',
+        'К' => 'K',
+        'М' => 'M',
+        'Н' => 'H',
+        'О' => 'O',
+        'Р' => 'P',
+        'С' => 'C',
+        'Ѕ' => 'S',
+        'Т' => 'T',
+        'Х' => 'X',
+        'Ү' => 'Y',
+        // Griego
+        'α' => 'a',
+        'ι' => 'i',
+        'κ' => 'k',
+        'ν' => 'v',
+        'ο' => 'o',
+        'ρ' => 'p',
+        'υ' => 'u',
+        'Α' => 'A',
+        'Β' => 'B',
+        'Ε' => 'E',
+        'Ζ' => 'Z',
+        'Η' => 'H',
+        'Ι' => 'I',
+        'Κ' => 'K',
+        'Μ' => 'M',
+        'Ν' => 'N',
+        'Ο' => 'O',
+        'Ρ' => 'P',
+        'Τ' => 'T',
+        'Υ' => 'Y',
+        'Χ' => 'X',
+        // Latín sin punto
+        'ı' => 'i',
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identificadores_unicode() {
+        for name in ["año", "tamaño", "número", "canción", "_x1", "π", "名前"] {
+            assert!(is_identifier(name), "{}", name);
+        }
+        for name in ["1año", "a-b", "😀", ""] {
+            assert!(!is_identifier(name), "{}", name);
+        }
+        assert_eq!(normalize("an\u{303}o"), "año");
+        assert_eq!(normalize("año"), "año");
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("canción"), "canción");
+        assert_eq!(sanitize("cancio\u{301}n"), "canción");
+        assert_eq!(sanitize("mi-módulo"), "mi_módulo");
+        assert_eq!(sanitize("2d"), "_2d");
+    }
+
+    #[test]
+    fn test_escrituras_y_esqueleto() {
+        assert_eq!(scripts("tamaño"), vec![Script::Latin]);
+        assert_eq!(scripts("pаso"), vec![Script::Latin, Script::Cyrillic]);
+        assert_eq!(scripts("名前_1"), vec![]);
+        assert_eq!(skeleton("pаso"), "paso");
+        assert_eq!(skeleton("Αρ"), "Ap");
+        assert_ne!(skeleton("año"), skeleton("ano"));
+    }
+}
@@ -1,5 +1,16 @@
 use crate::error::LexError;
-use crate::token::{Pos, Span, Token, TokenKind};
+use crate::ident::{is_ident_continue, is_ident_start};
+use crate::token::{Pos, Span, Token, TokenKind, Trivia, TriviaToken};
+
+/// Texto, trivia previa y si hubo hueco antes, por token.
+type TokenTrivia = (String, Vec<Trivia>, bool);
+
+#[derive(Default)]
+struct TriviaState {
+    pending: Vec<Trivia>,
+    spaced: bool,
+    tokens: Vec<TokenTrivia>,
+}
 
 pub struct Lexer {
     chars: Vec<char>,
@@ -7,6 +18,10 @@ pub struct Lexer {
     line: usize,
     col: usize,
     errors: Vec<LexError>,
+    /// Solo con `tokenize_with_trivia`: la trivia pendiente, si hubo hueco
+    /// desde el último token y, por cada token emitido, su texto y la trivia
+    /// que lo precede.
+    trivia: Option<TriviaState>,
 }
 
 impl Lexer {
@@ -17,19 +32,49 @@ impl Lexer {
             line: 1,
             col: 1,
             errors: Vec::new(),
+            trivia: None,
         }
     }
 
-    pub fn tokenize(mut self) -> (Vec<Token>, Vec<LexError>) {
+    /// Como `tokenize`, pero cada token lleva su texto original y los
+    /// comentarios y saltos de línea que lo preceden.
+    pub fn tokenize_with_trivia(mut self) -> (Vec<TriviaToken>, Vec<LexError>) {
+        self.trivia = Some(TriviaState::default());
+        let (tokens, errors, extras) = self.run();
+        let tokens = tokens
+            .into_iter()
+            .zip(extras)
+            .map(|(token, (text, leading, spaced))| TriviaToken {
+                token,
+                text,
+                leading,
+                spaced,
+            })
+            .collect();
+        (tokens, errors)
+    }
+
+    pub fn tokenize(self) -> (Vec<Token>, Vec<LexError>) {
+        let (tokens, errors, _) = self.run();
+        (tokens, errors)
+    }
+
+    fn run(mut self) -> (Vec<Token>, Vec<LexError>, Vec<TokenTrivia>) {
         let mut tokens = Vec::new();
         loop {
+            let gap = self.pos;
             self.skip_whitespace();
+            self.note_newlines(gap);
+            let start = self.pos;
+            let before = tokens.len();
             if self.is_eof() {
                 tokens.push(Token::new(
                     TokenKind::Eof,
                     Span::new(self.current_pos(), self.current_pos()),
                 ));
-                return (tokens, self.errors);
+                self.note_token(start);
+                let extras = self.trivia.take().map(|t| t.tokens).unwrap_or_default();
+                return (tokens, self.errors, extras);
             }
 
             match self.advance() {
@@ -41,6 +86,8 @@ impl Lexer {
                         }
                         self.advance();
                     }
+                    let text = self.text_from(start);
+                    self.note_trivia(Trivia::LineComment(text.trim_end().to_string()));
                 }
                 // Block comment
                 Some('/') if self.peek() == Some('*') => {
@@ -63,6 +110,8 @@ impl Lexer {
                             }
                         }
                     }
+                    let text = self.text_from(start);
+                    self.note_trivia(Trivia::BlockComment(text));
                 }
                 // String literal
                 Some('"') => {
@@ -312,6 +361,9 @@ impl Lexer {
                             break;
                         }
                     }
+                    if !ident.is_ascii() {
+                        ident = crate::ident::normalize(&ident);
+                    }
                     let kind = TokenKind::is_keyword(&ident).unwrap_or(TokenKind::Ident(ident));
                     tokens.push(Token::new(kind, Span::new(start_pos, self.prev_pos())));
                 }
@@ -323,11 +375,49 @@ impl Lexer {
                         suggestion: "Revisa la ortografía del código".to_string(),
                     });
                 }
-                None => return (tokens, self.errors),
+                None => unreachable!("is_eof ya se comprobó"),
+            }
+            if tokens.len() > before {
+                self.note_token(start);
             }
         }
     }
 
+    fn text_from(&self, start: usize) -> String {
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn note_newlines(&mut self, from: usize) {
+        let Some(state) = &mut self.trivia else {
+            return;
+        };
+        let gap = &self.chars[from..self.pos];
+        state.spaced |= !gap.is_empty();
+        let n = gap.iter().filter(|&&c| c == '\n').count();
+        if n > 0 {
+            state.pending.push(Trivia::Newlines(n));
+        }
+    }
+
+    fn note_trivia(&mut self, trivia: Trivia) {
+        if let Some(state) = &mut self.trivia {
+            state.pending.push(trivia);
+            state.spaced = true;
+        }
+    }
+
+    fn note_token(&mut self, start: usize) {
+        if self.trivia.is_none() {
+            return;
+        }
+        let text = self.text_from(start);
+        if let Some(state) = &mut self.trivia {
+            let leading = std::mem::take(&mut state.pending);
+            state.tokens.push((text, leading, state.spaced));
+            state.spaced = false;
+        }
+    }
+
     fn single_token(&self, kind: TokenKind) -> Token {
         let pos = self.prev_pos();
         Token::new(kind, Span::new(pos, pos))
@@ -386,14 +476,6 @@ impl Lexer {
     }
 }
 
-fn is_ident_start(ch: char) -> bool {
-    ch.is_ascii_alphabetic() || ch == '_'
-}
-
-fn is_ident_continue(ch: char) -> bool {
-    ch.is_ascii_alphanumeric() || ch == '_'
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -488,6 +570,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_identificadores_en_espanol() {
+        let kinds = tokenize("año tamaño número canción");
+        let names: Vec<TokenKind> = ["año", "tamaño", "número", "canción"]
+            .iter()
+            .map(|n| TokenKind::Ident(n.to_string()))
+            .chain([TokenKind::Eof])
+            .collect();
+        assert_eq!(kinds, names);
+        // `n` + tilde combinante es el mismo nombre que la `ñ` precompuesta.
+        assert_eq!(
+            tokenize("an\u{303}o")[0],
+            TokenKind::Ident("año".to_string())
+        );
+        let (_, errors) = tokenize_with_errors("año = 😀;");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "E001");
+        assert_eq!(errors[0].pos.col, 7);
+    }
+
     #[test]
     fn test_numeric_literals() {
         let kinds = tokenize("42 3.14 0 100.0");
@@ -649,6 +751,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_trivia() {
+        let src = "/// Doc\nx = 0x1F; // fin\n\n\n/* a\n b */ y\n";
+        let (tokens, errors) = Lexer::new(src).tokenize_with_trivia();
+        assert!(errors.is_empty());
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["x", "=", "0x1F", ";", "y", ""]);
+        assert_eq!(
+            tokens[0].leading,
+            vec![Trivia::LineComment("/// Doc".into()), Trivia::Newlines(1)]
+        );
+        assert!(tokens[0].leading[0].is_doc());
+        assert_eq!(
+            tokens[4].leading,
+            vec![
+                Trivia::LineComment("// fin".into()),
+                Trivia::Newlines(3),
+                Trivia::BlockComment("/* a\n b */".into()),
+            ]
+        );
+        assert_eq!(tokens[5].leading, vec![Trivia::Newlines(1)]);
+        assert!(tokens[1].spaced && tokens[2].spaced);
+        assert!(!tokens[3].spaced && tokens[4].spaced);
+        // Los tokens son los mismos que sin trivia.
+        let plain: Vec<Token> = Lexer::new(src).tokenize().0;
+        let with: Vec<Token> = tokens.into_iter().map(|t| t.token).collect();
+        assert_eq!(plain, with);
+    }
+
     #[test]
     fn test_unterminated_string() {
         let (_, errors) = tokenize_with_errors(r#" "hola"#);
@@ -1,7 +1,10 @@
+pub mod diagnostic;
 pub mod error;
+pub mod ident;
 pub mod lexer;
 pub mod token;
 
+pub use diagnostic::{Diagnostic, Severity};
 pub use error::{LexError, LexResult};
 pub use lexer::Lexer;
-pub use token::{Pos, Span, Token, TokenKind};
+pub use token::{Pos, Span, Token, TokenKind, Trivia, TriviaToken};
@@ -47,6 +47,36 @@ impl Token {
     }
 }
 
+/// Lo que hay entre dos tokens y el parser no ve: comentarios y saltos de
+/// línea. `Lexer::tokenize_with_trivia` lo conserva para el formateador.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Trivia {
+    /// Saltos de línea seguidos; los espacios entre ellos no cuentan.
+    Newlines(usize),
+    /// `// ...` o `/// ...`, sin el salto de línea final.
+    LineComment(String),
+    /// `/* ... */` tal cual, con los saltos de línea que tenga dentro.
+    BlockComment(String),
+}
+
+impl Trivia {
+    /// Comentario de documentación: `///` (no `////`).
+    pub fn is_doc(&self) -> bool {
+        matches!(self, Trivia::LineComment(c) if c.starts_with("///") && !c.starts_with("////"))
+    }
+}
+
+/// Un token con el texto exacto que ocupa en el fuente y la trivia que lo
+/// precede. La trivia del final del archivo va delante de `Eof`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TriviaToken {
+    pub token: Token,
+    pub text: String,
+    pub leading: Vec<Trivia>,
+    /// Si en el fuente había espacio o trivia entre el token anterior y este.
+    pub spaced: bool,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TokenKind {
     // Keywords (Spanish)
@@ -0,0 +1,1197 @@
+//! Índice de símbolos del proyecto: las declaraciones de cada archivo (de
+//! primer nivel y locales) y los identificadores que las nombran, con los
+//! `importar` resueltos como los resuelve el `ModuleLoader`. Un nombre
+//! importado se escribe con el prefijo del módulo (`mates_suma` para `suma`
+//! de `mates.nv`), así que resolverlo es quitar el prefijo de uno de los
+//! módulos que se ven desde el archivo.
+//!
+//! Los archivos abiertos se reindexan en cada `didChange` con el texto del
+//! editor; el resto se lee del disco al abrir el proyecto o al importarse.
+
+use lumen_lexer::token::{Pos, Span, Token, TokenKind, Trivia};
+use lumen_lexer::Lexer;
+use lumen_parser::ast::*;
+use lumen_parser::Parser;
+use lumen_sema::loader::module_prefix;
+use lumen_sema::{ModuleError, ModuleLoader, TypeInfo};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Struct,
+    Enum,
+    Variant,
+    Trait,
+    Impl,
+    Method,
+    Field,
+    Variable,
+    Constant,
+    Parameter,
+}
+
+impl SymbolKind {
+    /// Número del `SymbolKind` de LSP.
+    pub fn lsp(self) -> u32 {
+        match self {
+            SymbolKind::Function => 12,
+            SymbolKind::Struct => 23,
+            SymbolKind::Enum => 10,
+            SymbolKind::Variant => 22,
+            SymbolKind::Trait => 11,
+            SymbolKind::Impl => 5,
+            SymbolKind::Method => 6,
+            SymbolKind::Field => 8,
+            SymbolKind::Variable | SymbolKind::Parameter => 13,
+            SymbolKind::Constant => 14,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Dónde está el nombre.
+    pub span: Span,
+    /// Toda la declaración.
+    pub full: Span,
+    pub detail: String,
+    /// Dónde se ve un local o parámetro; `None` en los de primer nivel.
+    pub scope: Option<Span>,
+    /// Símbolo que contiene a un campo, variante o método.
+    pub parent: Option<usize>,
+    /// Comentarios `///` de justo antes de la declaración.
+    pub doc: String,
+}
+
+/// Dónde aparece un identificador.
+#[derive(Debug, Clone, PartialEq)]
+enum Context {
+    Plain,
+    /// Tras `.` o `?.`: un campo o método, que depende del tipo.
+    Member,
+    /// Tras `Nombre::`.
+    Path(String),
+    /// Nombre de un campo en `{ x: ... }`.
+    Key,
+}
+
+#[derive(Debug, Clone)]
+struct Reference {
+    name: String,
+    span: Span,
+    context: Context,
+}
+
+/// Un `importar` del archivo: ruta resuelta (si se encontró) y prefijo.
+#[derive(Debug, Clone)]
+struct Import {
+    target: Option<PathBuf>,
+    prefix: String,
+    span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileIndex {
+    pub uri: String,
+    pub symbols: Vec<Symbol>,
+    refs: Vec<Reference>,
+    imports: Vec<Import>,
+    /// Tipos de los nombres de primer nivel según el último análisis.
+    types: HashMap<String, TypeInfo>,
+}
+
+/// Un símbolo: archivo e índice en su `FileIndex::symbols`.
+pub type SymbolId = (PathBuf, usize);
+
+pub struct SymbolIndex {
+    lib_dirs: Vec<PathBuf>,
+    loader: ModuleLoader,
+    files: HashMap<PathBuf, FileIndex>,
+    /// Archivos abiertos en el editor: su texto manda sobre el del disco.
+    open: HashMap<PathBuf, String>,
+}
+
+/// Archivos del proyecto que se indexan como mucho al abrirlo.
+const MAX_WORKSPACE_FILES: usize = 5000;
+
+impl SymbolIndex {
+    pub fn new(lib_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            loader: ModuleLoader::new(lib_dirs.clone()),
+            lib_dirs,
+            files: HashMap::new(),
+            open: HashMap::new(),
+        }
+    }
+
+    /// Indexa los `.nv` bajo `root` y añade su `stdlib/` (y subcarpetas) a
+    /// las rutas de búsqueda de los `importar`.
+    pub fn set_root(&mut self, root: &Path) {
+        let stdlib = root.join("stdlib");
+        if stdlib.is_dir() {
+            let mut dirs = vec![stdlib.clone()];
+            if let Ok(entries) = fs::read_dir(&stdlib) {
+                dirs.extend(entries.flatten().map(|e| e.path()).filter(|p| p.is_dir()));
+            }
+            for dir in dirs {
+                if !self.lib_dirs.contains(&dir) {
+                    self.lib_dirs.push(dir);
+                }
+            }
+            self.loader = ModuleLoader::new(self.lib_dirs.clone());
+        }
+        let mut files = Vec::new();
+        nv_files(root, &mut files);
+        for path in files.into_iter().take(MAX_WORKSPACE_FILES) {
+            if !self.files.contains_key(&canonical(&path)) {
+                self.load(&path);
+            }
+        }
+    }
+
+    /// Texto nuevo de un documento abierto.
+    pub fn update(&mut self, uri: &str, text: &str) {
+        let path = uri_path(uri);
+        self.open.insert(path.clone(), text.to_string());
+        self.index(&path, uri, text);
+    }
+
+    /// El documento se cerró: vuelve a valer lo que hay en el disco.
+    pub fn close(&mut self, uri: &str) {
+        let path = uri_path(uri);
+        self.open.remove(&path);
+        self.files.remove(&path);
+        if path.is_file() {
+            self.load(&path);
+        }
+    }
+
+    pub fn file(&self, uri: &str) -> Option<&FileIndex> {
+        self.files.get(&uri_path(uri))
+    }
+
+    pub fn symbol(&self, id: &SymbolId) -> Option<&Symbol> {
+        self.files.get(&id.0).and_then(|f| f.symbols.get(id.1))
+    }
+
+    pub fn uri_of(&self, id: &SymbolId) -> String {
+        self.files
+            .get(&id.0)
+            .map(|f| f.uri.clone())
+            .unwrap_or_else(|| path_uri(&id.0))
+    }
+
+    /// Guarda los tipos que dio el análisis del archivo.
+    pub fn set_types(&mut self, uri: &str, types: HashMap<String, TypeInfo>) {
+        if let Some(file) = self.files.get_mut(&uri_path(uri)) {
+            file.types = types;
+        }
+    }
+
+    /// Aplana `source` con sus imports, tomando de los documentos abiertos
+    /// el texto de los que lo estén.
+    pub fn flatten(&self, uri: &str, source: &str) -> Result<(Program, Vec<PathBuf>), ModuleError> {
+        let mut loader = ModuleLoader::new(self.lib_dirs.clone());
+        for (path, text) in &self.open {
+            loader.override_source(path, text);
+        }
+        loader.resolve_imports_with_origins(source, &uri_path(uri))
+    }
+
+    /// El `importar` de `uri` por el que llega `module` (directa o
+    /// indirectamente), o el primero que no se pudo resolver.
+    pub fn import_leading_to(&self, uri: &str, module: &Path) -> Option<Span> {
+        let file = self.file(uri)?;
+        let module = canonical(module);
+        file.imports
+            .iter()
+            .find(|imp| {
+                imp.target.as_ref().is_some_and(|t| {
+                    *t == module || self.visible(t).iter().any(|(p, _)| *p == module)
+                })
+            })
+            .or_else(|| file.imports.iter().find(|imp| imp.target.is_none()))
+            .map(|imp| imp.span)
+    }
+
+    /// Si `uri` ve, a través de sus imports, lo declarado en `other`.
+    pub fn imports_file(&self, uri: &str, other: &str) -> bool {
+        let other = uri_path(other);
+        self.visible(&uri_path(uri))
+            .iter()
+            .any(|(p, _)| *p == other)
+    }
+
+    /// Símbolo al que se refiere el identificador en `pos` de `uri`.
+    pub fn symbol_at(&self, uri: &str, pos: Pos) -> Option<SymbolId> {
+        let path = uri_path(uri);
+        let file = self.files.get(&path)?;
+        let r = file.refs.iter().find(|r| contains_pos(r.span, pos))?;
+        let visible = self.visible(&path);
+        self.resolve(&path, &visible, r)
+    }
+
+    /// Apariciones de `id` en todos los archivos indexados (la declaración
+    /// incluida), como `(uri, span, texto)`. El texto es el nombre tal como
+    /// se escribe en ese archivo, con el prefijo del módulo si lo lleva.
+    pub fn references(&self, id: &SymbolId) -> Vec<(String, Span, String)> {
+        let Some(symbol) = self.symbol(id) else {
+            return Vec::new();
+        };
+        let suffix = format!("_{}", symbol.name);
+        let mut found = Vec::new();
+        let mut paths: Vec<&PathBuf> = self.files.keys().collect();
+        paths.sort();
+        for path in paths {
+            // Un local solo se nombra en su archivo.
+            if symbol.scope.is_some() && *path != id.0 {
+                continue;
+            }
+            let file = &self.files[path];
+            let mut visible = None;
+            for r in &file.refs {
+                if r.name != symbol.name && !r.name.ends_with(&suffix) {
+                    continue;
+                }
+                let visible = visible.get_or_insert_with(|| self.visible(path));
+                if self.resolve(path, visible, r).as_ref() == Some(id) {
+                    found.push((file.uri.clone(), r.span, r.name.clone()));
+                }
+            }
+        }
+        found
+    }
+
+    /// Símbolos de primer nivel (y sus campos, variantes y métodos) cuyo
+    /// nombre contiene `query`, sin distinguir mayúsculas.
+    pub fn search(&self, query: &str) -> Vec<SymbolId> {
+        let query = query.to_lowercase();
+        let mut found = Vec::new();
+        let mut paths: Vec<&PathBuf> = self.files.keys().collect();
+        paths.sort();
+        for path in paths {
+            for (i, symbol) in self.files[path].symbols.iter().enumerate() {
+                if symbol.scope.is_none() && symbol.name.to_lowercase().contains(&query) {
+                    found.push((path.clone(), i));
+                }
+            }
+        }
+        found
+    }
+
+    /// Nombres de los parámetros de una función, en orden.
+    pub fn parameters(&self, id: &SymbolId) -> Vec<String> {
+        let Some(function) = self.symbol(id) else {
+            return Vec::new();
+        };
+        self.files[&id.0]
+            .symbols
+            .iter()
+            .filter(|s| s.kind == SymbolKind::Parameter && s.scope == Some(function.full))
+            .map(|s| s.name.clone())
+            .collect()
+    }
+
+    /// Comentario `///` del campo o método `member` del tipo `type_name`
+    /// (tal como se ve desde el archivo que pregunta, con el prefijo del
+    /// módulo si lo lleva).
+    pub fn member_doc(&self, type_name: &str, member: &str) -> Option<String> {
+        let owner = |name: &str| {
+            type_name == name
+                || type_name
+                    .strip_suffix(name)
+                    .is_some_and(|prefix| prefix.ends_with('_'))
+        };
+        let mut paths: Vec<&PathBuf> = self.files.keys().collect();
+        paths.sort();
+        paths.into_iter().find_map(|path| {
+            let symbols = &self.files[path].symbols;
+            symbols.iter().find_map(|s| {
+                let parent = &symbols[s.parent?];
+                let matches = s.name == member
+                    && match parent.kind {
+                        SymbolKind::Struct => owner(&parent.name),
+                        SymbolKind::Impl => parent.name.rsplit(' ').next().is_some_and(&owner),
+                        _ => false,
+                    };
+                (matches && !s.doc.is_empty()).then(|| s.doc.clone())
+            })
+        })
+    }
+
+    /// Detalle de un símbolo: el tipo escrito o, si se infiere, el del
+    /// análisis.
+    pub fn detail(&self, id: &SymbolId) -> String {
+        let Some(symbol) = self.symbol(id) else {
+            return String::new();
+        };
+        if symbol.detail == "sea" && symbol.scope.is_none() {
+            if let Some(t) = self.files[&id.0].types.get(&symbol.name) {
+                return t.to_string();
+            }
+        }
+        symbol.detail.clone()
+    }
+
+    fn load(&mut self, path: &Path) {
+        let path = canonical(path);
+        let text = match self.open.get(&path) {
+            Some(text) => text.clone(),
+            None => match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(_) => return,
+            },
+        };
+        let uri = path_uri(&path);
+        self.index(&path, &uri, &text);
+    }
+
+    fn index(&mut self, path: &Path, uri: &str, source: &str) {
+        let (tokens, _) = Lexer::new(source).tokenize();
+        let (program, _) = Parser::new(tokens.clone()).parse();
+        let mut collector = Collector {
+            tokens: &tokens,
+            symbols: Vec::new(),
+        };
+        for node in &program {
+            collector.top_level(node);
+        }
+        let mut symbols = collector.symbols;
+        let docs = doc_comments(source);
+        for i in 0..symbols.len() {
+            // Los métodos de un rasgo no tienen span propio: no heredan el
+            // comentario del rasgo.
+            let inherited = symbols[i]
+                .parent
+                .is_some_and(|p| symbols[p].full == symbols[i].full);
+            if !inherited {
+                if let Some(doc) =
+                    docs.get(&(symbols[i].full.start.line, symbols[i].full.start.col))
+                {
+                    symbols[i].doc = doc.clone();
+                }
+            }
+        }
+
+        let mut imports = Vec::new();
+        for node in &program {
+            if let DeclOrStmt::Stmt(Stmt::Import {
+                path: import,
+                alias,
+                span,
+            }) = node
+            {
+                if import == "ingles" || import == "english" {
+                    continue;
+                }
+                let target = self
+                    .loader
+                    .resolve_import(import, path)
+                    .ok()
+                    .map(|t| canonical(&t));
+                let prefix = alias.clone().unwrap_or_else(|| match &target {
+                    Some(t) => module_prefix(t),
+                    None => module_prefix(Path::new(import)),
+                });
+                imports.push(Import {
+                    target,
+                    prefix,
+                    span: *span,
+                });
+            }
+        }
+        let pending: Vec<PathBuf> = imports
+            .iter()
+            .filter_map(|imp| imp.target.clone())
+            .filter(|t| !self.files.contains_key(t))
+            .collect();
+
+        let types = self.files.remove(path).map(|f| f.types).unwrap_or_default();
+        self.files.insert(
+            path.to_path_buf(),
+            FileIndex {
+                uri: uri.to_string(),
+                symbols,
+                refs: references(&tokens),
+                imports,
+                types,
+            },
+        );
+        for target in pending {
+            if !self.files.contains_key(&target) {
+                self.load(&target);
+            }
+        }
+    }
+
+    /// Módulos que se ven desde `path` y con qué prefijo, siguiendo los
+    /// imports de los imports.
+    fn visible(&self, path: &Path) -> Vec<(PathBuf, String)> {
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        seen.insert(path.to_path_buf());
+        let mut result = Vec::new();
+        let mut queue = vec![path.to_path_buf()];
+        while let Some(current) = queue.pop() {
+            let Some(file) = self.files.get(&current) else {
+                continue;
+            };
+            for imp in &file.imports {
+                if let Some(target) = &imp.target {
+                    if seen.insert(target.clone()) {
+                        result.push((target.clone(), imp.prefix.clone()));
+                        queue.push(target.clone());
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn resolve(
+        &self,
+        path: &Path,
+        visible: &[(PathBuf, String)],
+        r: &Reference,
+    ) -> Option<SymbolId> {
+        match &r.context {
+            Context::Member | Context::Key => None,
+            Context::Plain => self.lookup(path, visible, &r.name, r.span.start),
+            Context::Path(owner) => {
+                let (file, owner) = self.lookup(path, visible, owner, r.span.start)?;
+                let symbols = &self.files[&file].symbols;
+                symbols
+                    .iter()
+                    .position(|s| s.parent == Some(owner) && s.name == r.name)
+                    .map(|i| (file, i))
+            }
+        }
+    }
+
+    fn lookup(
+        &self,
+        path: &Path,
+        visible: &[(PathBuf, String)],
+        name: &str,
+        pos: Pos,
+    ) -> Option<SymbolId> {
+        let file = self.files.get(path)?;
+        let local = file
+            .symbols
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.name == name)
+            .filter(|(_, s)| {
+                s.scope.is_some_and(|scope| contains_pos(scope, pos))
+                    && (s.kind == SymbolKind::Parameter || before_or_at(s.span.start, pos))
+            })
+            .max_by_key(|(_, s)| {
+                let scope = s.scope.map(|sc| sc.start).unwrap_or(Pos::new(0, 0));
+                (
+                    (scope.line, scope.col),
+                    (s.span.start.line, s.span.start.col),
+                )
+            });
+        if let Some((i, _)) = local {
+            return Some((path.to_path_buf(), i));
+        }
+        if let Some(i) = global(file, name) {
+            return Some((path.to_path_buf(), i));
+        }
+        for (module, prefix) in visible {
+            let Some(rest) = name
+                .strip_prefix(prefix.as_str())
+                .and_then(|r| r.strip_prefix('_'))
+            else {
+                continue;
+            };
+            // Lo que el módulo ya declara con el prefijo (`arena_crear` en
+            // `arena.nv`) el loader lo deja tal cual.
+            let Some(file) = self.files.get(module) else {
+                continue;
+            };
+            if let Some(i) = global(file, rest).or_else(|| global(file, name)) {
+                return Some((module.clone(), i));
+            }
+        }
+        None
+    }
+}
+
+/// Declaración de primer nivel de `file` que se llama `name`.
+fn global(file: &FileIndex, name: &str) -> Option<usize> {
+    file.symbols.iter().position(|s| {
+        s.name == name
+            && s.scope.is_none()
+            && !matches!(
+                s.kind,
+                SymbolKind::Field | SymbolKind::Method | SymbolKind::Impl
+            )
+    })
+}
+
+/// Recorre el AST de un archivo y anota sus declaraciones. El AST guarda el
+/// span de la declaración entera; el del nombre sale del primer token con
+/// ese nombre desde donde empieza.
+struct Collector<'a> {
+    tokens: &'a [Token],
+    symbols: Vec<Symbol>,
+}
+
+impl Collector<'_> {
+    fn name_span(&self, name: &str, from: Pos) -> Span {
+        self.tokens
+            .iter()
+            .find(|t| {
+                before_or_at(from, t.span.start)
+                    && matches!(&t.kind, TokenKind::Ident(n) if n == name)
+            })
+            .map(|t| t.span)
+            .unwrap_or(Span::new(from, from))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add(
+        &mut self,
+        name: &str,
+        kind: SymbolKind,
+        from: Pos,
+        full: Span,
+        detail: String,
+        scope: Option<Span>,
+        parent: Option<usize>,
+    ) -> usize {
+        let span = self.name_span(name, from);
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            span,
+            full,
+            detail,
+            scope,
+            parent,
+            doc: String::new(),
+        });
+        self.symbols.len() - 1
+    }
+
+    fn top_level(&mut self, node: &DeclOrStmt) {
+        match node {
+            DeclOrStmt::Decl(decl) => self.decl(decl, None),
+            DeclOrStmt::Stmt(stmt) => self.stmt(stmt, None),
+        }
+    }
+
+    fn decl(&mut self, decl: &Decl, scope: Option<Span>) {
+        match decl {
+            Decl::Variable {
+                var_type,
+                name,
+                init,
+                span,
+            } => {
+                self.add(
+                    name,
+                    SymbolKind::Variable,
+                    span.start,
+                    *span,
+                    var_type.to_string(),
+                    scope,
+                    None,
+                );
+                if let Some(init) = init {
+                    self.expr(init);
+                }
+            }
+            Decl::Const {
+                var_type,
+                name,
+                value,
+                span,
+            } => {
+                self.add(
+                    name,
+                    SymbolKind::Constant,
+                    span.start,
+                    *span,
+                    var_type.to_string(),
+                    scope,
+                    None,
+                );
+                self.expr(value);
+            }
+            Decl::Destructure {
+                targets,
+                init,
+                span,
+            } => {
+                self.targets(targets, *span, scope);
+                self.expr(init);
+            }
+            Decl::Function { .. } => self.function(decl, SymbolKind::Function, scope, None),
+            Decl::Struct {
+                name, fields, span, ..
+            } => {
+                let parent = self.add(
+                    name,
+                    SymbolKind::Struct,
+                    span.start,
+                    *span,
+                    format!("estructura {}", name),
+                    scope,
+                    None,
+                );
+                for field in fields {
+                    self.add(
+                        &field.name,
+                        SymbolKind::Field,
+                        field.span.start,
+                        field.span,
+                        field.field_type.to_string(),
+                        scope,
+                        Some(parent),
+                    );
+                }
+            }
+            Decl::Enum {
+                name,
+                variants,
+                span,
+            } => {
+                let parent = self.add(
+                    name,
+                    SymbolKind::Enum,
+                    span.start,
+                    *span,
+                    format!("enum {}", name),
+                    scope,
+                    None,
+                );
+                for variant in variants {
+                    let detail = if variant.types.is_empty() {
+                        format!("{}::{}", name, variant.name)
+                    } else {
+                        let types: Vec<String> =
+                            variant.types.iter().map(|t| t.to_string()).collect();
+                        format!("{}::{}({})", name, variant.name, types.join(", "))
+                    };
+                    self.add(
+                        &variant.name,
+                        SymbolKind::Variant,
+                        variant.span.start,
+                        variant.span,
+                        detail,
+                        scope,
+                        Some(parent),
+                    );
+                }
+            }
+            Decl::Rasgo {
+                name,
+                methods,
+                span,
+                ..
+            } => {
+                let parent = self.add(
+                    name,
+                    SymbolKind::Trait,
+                    span.start,
+                    *span,
+                    format!("rasgo {}", name),
+                    scope,
+                    None,
+                );
+                for method in methods {
+                    let detail = signature(&method.return_type, &method.name, &method.params);
+                    self.add(
+                        &method.name,
+                        SymbolKind::Method,
+                        span.start,
+                        *span,
+                        detail,
+                        scope,
+                        Some(parent),
+                    );
+                }
+            }
+            Decl::ImplRasgo {
+                trait_name,
+                target_type,
+                methods,
+                span,
+                ..
+            } => {
+                let label = if trait_name.is_empty() {
+                    format!("impl {}", target_type)
+                } else {
+                    format!("impl {} para {}", trait_name, target_type)
+                };
+                self.symbols.push(Symbol {
+                    name: label.clone(),
+                    kind: SymbolKind::Impl,
+                    span: Span::new(span.start, span.start),
+                    full: *span,
+                    detail: label,
+                    scope,
+                    parent: None,
+                    doc: String::new(),
+                });
+                let parent = self.symbols.len() - 1;
+                for method in methods {
+                    self.function(method, SymbolKind::Method, scope, Some(parent));
+                }
+            }
+        }
+    }
+
+    fn function(
+        &mut self,
+        decl: &Decl,
+        kind: SymbolKind,
+        scope: Option<Span>,
+        parent: Option<usize>,
+    ) {
+        let Decl::Function {
+            return_type,
+            name,
+            params,
+            body,
+            span,
+            ..
+        } = decl
+        else {
+            return self.decl(decl, scope);
+        };
+        let detail = signature(return_type, name, params);
+        self.add(name, kind, span.start, *span, detail, scope, parent);
+        self.params(params, *span);
+        self.body(body, *span);
+    }
+
+    fn params(&mut self, params: &[Param], scope: Span) {
+        for p in params {
+            self.add(
+                &p.name,
+                SymbolKind::Parameter,
+                p.span.start,
+                p.span,
+                p.param_type.to_string(),
+                Some(scope),
+                None,
+            );
+            if let Some(default) = &p.default {
+                self.expr(default);
+            }
+        }
+    }
+
+    fn targets(&mut self, targets: &[DestructureTarget], full: Span, scope: Option<Span>) {
+        for t in targets.iter().filter(|t| t.name != "_") {
+            let detail = t
+                .var_type
+                .as_ref()
+                .map(|ty| ty.to_string())
+                .unwrap_or_else(|| "sea".to_string());
+            self.add(
+                &t.name,
+                SymbolKind::Variable,
+                t.span.start,
+                full,
+                detail,
+                scope,
+                None,
+            );
+        }
+    }
+
+    fn body(&mut self, body: &[DeclOrStmt], scope: Span) {
+        for node in body {
+            match node {
+                DeclOrStmt::Decl(decl) => self.decl(decl, Some(scope)),
+                DeclOrStmt::Stmt(stmt) => self.stmt(stmt, Some(scope)),
+            }
+        }
+    }
+
+    /// Un nombre que introduce un patrón (`algun(v)`, `Forma::Circulo(r)`).
+    fn pattern(&mut self, pattern: &Expr, scope: Span) {
+        let args = match pattern {
+            Expr::Call { args, .. } | Expr::EnumCtor { args, .. } => args,
+            Expr::Algun { expr, .. } | Expr::Exito { expr, .. } | Expr::Error { expr, .. } => {
+                std::slice::from_ref(expr.as_ref())
+            }
+            _ => return,
+        };
+        for arg in args {
+            if let Expr::Ident { name, span } = arg {
+                self.add(
+                    name,
+                    SymbolKind::Variable,
+                    span.start,
+                    *span,
+                    "sea".to_string(),
+                    Some(scope),
+                    None,
+                );
+            } else {
+                self.pattern(arg, scope);
+            }
+        }
+    }
+
+    /// Un nombre que se declara dentro de `span` sin span propio (la variable
+    /// de un `para` o de `atrapar`).
+    fn binding(&mut self, name: &str, span: Span) {
+        self.add(
+            name,
+            SymbolKind::Variable,
+            span.start,
+            span,
+            "sea".to_string(),
+            Some(span),
+            None,
+        );
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: Option<Span>) {
+        match stmt {
+            Stmt::If {
+                condition,
+                then_body,
+                else_body,
+                span,
+            } => {
+                self.expr(condition);
+                self.body(then_body, *span);
+                if let Some(else_body) = else_body {
+                    self.body(else_body, *span);
+                }
+            }
+            Stmt::IfLet {
+                pattern,
+                value,
+                then_body,
+                else_body,
+                span,
+            } => {
+                self.expr(value);
+                self.pattern(pattern, *span);
+                self.body(then_body, *span);
+                if let Some(else_body) = else_body {
+                    self.body(else_body, *span);
+                }
+            }
+            Stmt::GuardLet {
+                pattern,
+                value,
+                else_body,
+                span,
+            } => {
+                self.expr(value);
+                // Lo que liga un `guardia` se ve hasta el final del bloque.
+                let rest = scope.unwrap_or(*span);
+                self.pattern(pattern, Span::new(span.start, rest.end));
+                self.body(else_body, *span);
+            }
+            Stmt::While {
+                condition,
+                body,
+                span,
+            } => {
+                self.expr(condition);
+                self.body(body, *span);
+            }
+            Stmt::For {
+                init,
+                condition,
+                update,
+                body,
+                span,
+            } => {
+                self.decl(init, Some(*span));
+                self.expr(condition);
+                self.stmt(update, Some(*span));
+                self.body(body, *span);
+            }
+            Stmt::ForEach {
+                var_name,
+                expr,
+                body,
+                span,
+            } => {
+                self.expr(expr);
+                self.binding(var_name, *span);
+                self.body(body, *span);
+            }
+            Stmt::Match {
+                expr,
+                arms,
+                default,
+                span,
+            } => {
+                self.expr(expr);
+                for arm in arms {
+                    self.pattern(&arm.value, arm.span);
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    self.body(&arm.body, arm.span);
+                }
+                if let Some(default) = default {
+                    self.body(default, *span);
+                }
+            }
+            Stmt::Block { stmts, span } | Stmt::Posponer { body: stmts, span } => {
+                self.body(stmts, *span);
+            }
+            Stmt::TryCatch {
+                try_body,
+                err_var,
+                catch_body,
+                span,
+            } => {
+                self.body(try_body, *span);
+                let catch_start = catch_body
+                    .first()
+                    .map(|n| n.span().start)
+                    .unwrap_or(span.end);
+                // `atrapar (e)` va justo antes del cuerpo: se busca desde el
+                // final del `intentar`.
+                let try_end = try_body.last().map(|n| n.span().end).unwrap_or(span.start);
+                let within = Span::new(try_end, span.end);
+                let name_span = self.name_span(err_var, try_end);
+                let visible = if before_or_at(name_span.start, catch_start) {
+                    within
+                } else {
+                    *span
+                };
+                self.binding(err_var, visible);
+                self.body(catch_body, *span);
+            }
+            Stmt::Destructure {
+                targets,
+                value,
+                span,
+            } => {
+                self.targets(targets, *span, scope);
+                self.expr(value);
+            }
+            Stmt::Assignment { value, .. } => self.expr(value),
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Expr { expr, .. } => self.expr(expr),
+            Stmt::FieldAssign { expr, value, .. } => {
+                self.expr(expr);
+                self.expr(value);
+            }
+            Stmt::ArraySet {
+                arr, index, value, ..
+            } => {
+                self.expr(arr);
+                self.expr(index);
+                self.expr(value);
+            }
+            Stmt::Break { .. }
+            | Stmt::Continue { .. }
+            | Stmt::Import { .. }
+            | Stmt::InlineAsm { .. }
+            | Stmt::InlineC { .. }
+            | Stmt::InlineRust { .. } => {}
+        }
+    }
+
+    /// Las expresiones solo declaran en lambdas, comprensiones y consultas.
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Lambda { params, body, span } => {
+                self.params(params, *span);
+                self.body(body, *span);
+                return;
+            }
+            Expr::Comprehension { var_name, span, .. } | Expr::Query { var_name, span, .. } => {
+                self.binding(var_name, *span);
+            }
+            _ => {}
+        }
+        for sub in sub_exprs(expr) {
+            self.expr(sub);
+        }
+    }
+}
+
+/// Subexpresiones directas de `expr`.
+pub fn sub_exprs(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Int { .. }
+        | Expr::Float { .. }
+        | Expr::Str { .. }
+        | Expr::Bool { .. }
+        | Expr::Ident { .. }
+        | Expr::Ninguno { .. }
+        | Expr::Lambda { .. } => Vec::new(),
+        Expr::Binary { left, right, .. } => vec![left, right],
+        Expr::Unary { operand: e, .. }
+        | Expr::Grouping { expr: e, .. }
+        | Expr::Cast { expr: e, .. }
+        | Expr::FieldAccess { expr: e, .. }
+        | Expr::SafeFieldAccess { expr: e, .. }
+        | Expr::Exito { expr: e, .. }
+        | Expr::Error { expr: e, .. }
+        | Expr::Intentar { expr: e, .. }
+        | Expr::Algun { expr: e, .. }
+        | Expr::TupleAccess { expr: e, .. }
+        | Expr::Esperar { expr: e, .. }
+        | Expr::Comptime { expr: e, .. } => vec![e],
+        Expr::Call { callee, args, .. } => std::iter::once(callee.as_ref()).chain(args).collect(),
+        Expr::MethodCall { expr, args, .. } => std::iter::once(expr.as_ref()).chain(args).collect(),
+        Expr::List { items, .. } | Expr::Tuple { items, .. } => items.iter().collect(),
+        Expr::EnumCtor { args, .. } => args.iter().collect(),
+        Expr::Range { start, end, .. } => vec![start, end],
+        Expr::Index { expr, index, .. } => vec![expr, index],
+        Expr::StructInit { fields, .. } => fields.iter().map(|(_, e)| e).collect(),
+        Expr::Ternary {
+            condition,
+            true_branch,
+            false_branch,
+            ..
+        } => vec![condition, true_branch, false_branch],
+        Expr::Elvis { expr, default, .. } => vec![expr, default],
+        Expr::Comprehension {
+            expr,
+            iter,
+            condition,
+            ..
+        } => {
+            let mut subs: Vec<&Expr> = vec![expr, iter];
+            subs.extend(condition.as_deref());
+            subs
+        }
+        Expr::Query {
+            source,
+            where_clause,
+            order_by,
+            select_expr,
+            ..
+        } => {
+            let mut subs: Vec<&Expr> = vec![source, select_expr];
+            subs.extend(where_clause.as_deref());
+            subs.extend(order_by.as_deref());
+            subs
+        }
+    }
+}
+
+fn signature(return_type: &Type, name: &str, params: &[Param]) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(|p| format!("{} {}", p.param_type, p.name))
+        .collect();
+    format!("funcion {} {}({})", return_type, name, params.join(", "))
+}
+
+/// Los identificadores de un archivo y en qué contexto aparecen.
+fn references(tokens: &[Token]) -> Vec<Reference> {
+    let mut refs = Vec::new();
+    for (i, tok) in tokens.iter().enumerate() {
+        let TokenKind::Ident(name) = &tok.kind else {
+            continue;
+        };
+        let prev = i.checked_sub(1).map(|p| &tokens[p].kind);
+        let next = tokens.get(i + 1).map(|t| &t.kind);
+        let context = match prev {
+            Some(TokenKind::Dot | TokenKind::QuestionDot) => Context::Member,
+            Some(TokenKind::DoubleColon) => match i.checked_sub(2).map(|p| &tokens[p].kind) {
+                Some(TokenKind::Ident(owner)) => Context::Path(owner.clone()),
+                _ => Context::Member,
+            },
+            Some(TokenKind::LeftBrace | TokenKind::Comma) if next == Some(&TokenKind::Colon) => {
+                Context::Key
+            }
+            _ => Context::Plain,
+        };
+        refs.push(Reference {
+            name: name.clone(),
+            span: tok.span,
+            context,
+        });
+    }
+    refs
+}
+
+/// Comentarios `///` seguidos (sin líneas en blanco entre ellos ni antes
+/// del token) por posición del token al que preceden.
+fn doc_comments(source: &str) -> HashMap<(usize, usize), String> {
+    let (tokens, _) = Lexer::new(source).tokenize_with_trivia();
+    let mut docs = HashMap::new();
+    for tok in tokens {
+        let mut lines: Vec<&str> = Vec::new();
+        for trivia in &tok.leading {
+            match trivia {
+                Trivia::LineComment(text) if trivia.is_doc() => {
+                    let text = &text[3..];
+                    lines.push(text.strip_prefix(' ').unwrap_or(text));
+                }
+                Trivia::Newlines(n) if *n == 1 => {}
+                _ => lines.clear(),
+            }
+        }
+        if !lines.is_empty() {
+            let start = tok.token.span.start;
+            docs.insert((start.line, start.col), lines.join("\n"));
+        }
+    }
+    docs
+}
+
+fn before_or_at(a: Pos, b: Pos) -> bool {
+    (a.line, a.col) <= (b.line, b.col)
+}
+
+fn contains_pos(span: Span, pos: Pos) -> bool {
+    before_or_at(span.start, pos) && before_or_at(pos, span.end)
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Ruta de un `file://` URI; otros esquemas (`untitled:`) quedan tal cual
+/// como clave del índice.
+pub fn uri_path(uri: &str) -> PathBuf {
+    match uri.strip_prefix("file://") {
+        Some(path) => canonical(Path::new(path)),
+        None => PathBuf::from(uri),
+    }
+}
+
+fn path_uri(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+fn nv_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" && name != "node_modules" {
+                nv_files(&path, out);
+            }
+        } else if path.extension().is_some_and(|e| e == "nv") {
+            out.push(path);
+        }
+        if out.len() >= MAX_WORKSPACE_FILES {
+            return;
+        }
+    }
+}
@@ -1,23 +1,37 @@
 // ============================================================================
 // LÚMEN Language Server Protocol (LSP Pro) — v2.4.6
 // Soporte Completo: Semantic Tokens, Inlay Hints, Signature Help, Code Actions,
-// Diagnóstico en Tiempo Real, Hover, Definición y Autocompletado Inteligente
+// Diagnóstico en Tiempo Real, Hover, Definición, Referencias y Renombrado
+// entre módulos, Símbolos, Autocompletado Inteligente y Formateo
 // ============================================================================
 
-use lumen_lexer::token::TokenKind;
+use lumen_lexer::diagnostic::{Diagnostic, Fix};
+use lumen_lexer::ident;
+use lumen_lexer::token::{Pos, Span, Token, TokenKind};
 use lumen_lexer::Lexer;
+use lumen_parser::ast::{DeclOrStmt, Program, Stmt};
 use lumen_parser::Parser;
-use lumen_sema::SemanticAnalyzer;
+use lumen_sema::{
+    LintConfig, Linter, ModuleAnalysis, ModuleError, SemanticAnalyzer, Severity, TypeInfo,
+};
 use std::collections::HashMap;
 use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
 
-pub fn run_lsp() {
+mod index;
+
+pub use index::{SymbolIndex, SymbolKind};
+
+/// `lib_dirs` son las rutas de búsqueda de los `importar`, como en `lumen
+/// run`; al inicializar se añade el `stdlib/` del proyecto abierto.
+pub fn run_lsp(lib_dirs: &[PathBuf]) {
     eprintln!("LÚMEN LSP Server Pro v2.4.6 — Semantic Tokens, Inlay Hints & Code Actions");
 
     let stdin = io::stdin();
     let mut stdout = io::stdout();
 
     let mut doc_cache: HashMap<String, String> = HashMap::new();
+    let mut index = SymbolIndex::new(lib_dirs.to_vec());
 
     loop {
         let mut header = String::new();
@@ -56,6 +70,14 @@ pub fn run_lsp() {
 
         match method {
             "initialize" => {
+                let params = &request["params"];
+                let root = params["rootUri"]
+                    .as_str()
+                    .and_then(|uri| uri.strip_prefix("file://"))
+                    .or_else(|| params["rootPath"].as_str());
+                if let Some(root) = root {
+                    index.set_root(Path::new(root));
+                }
                 let response = serde_json::json!({
                     "jsonrpc": "2.0",
                     "id": id,
@@ -67,12 +89,17 @@ pub fn run_lsp() {
                                 "triggerCharacters": [".", ":", ">", "|", "f", "<"]
                             },
                             "definitionProvider": true,
+                            "referencesProvider": true,
+                            "documentSymbolProvider": true,
+                            "workspaceSymbolProvider": true,
                             "hoverProvider": true,
                             "renameProvider": true,
                             "signatureHelpProvider": {
                                 "triggerCharacters": ["(", ","]
                             },
                             "inlayHintProvider": true,
+                            "documentFormattingProvider": true,
+                            "documentRangeFormattingProvider": true,
                             "codeActionProvider": {
                                 "codeActionKinds": ["quickfix", "refactor.extract", "source.fixAll"]
                             },
@@ -88,7 +115,7 @@ pub fn run_lsp() {
                                 "full": true
                             },
                             "diagnosticProvider": {
-                                "interFileDependencies": false,
+                                "interFileDependencies": true,
                                 "workspaceDiagnostics": false
                             }
                         },
@@ -113,17 +140,25 @@ pub fn run_lsp() {
                 };
 
                 doc_cache.insert(uri.to_string(), text.to_string());
-                let diagnostics = analyze(text, uri);
-
-                let notification = serde_json::json!({
-                    "jsonrpc": "2.0",
-                    "method": "textDocument/publishDiagnostics",
-                    "params": {
-                        "uri": uri,
-                        "diagnostics": diagnostics
-                    }
-                });
-                send_response(&mut stdout, &notification);
+                index.update(uri, text);
+                publish_diagnostics(&mut stdout, &mut index, uri, text);
+
+                // Los documentos abiertos que importan este cambian con él.
+                let importers: Vec<(String, String)> = doc_cache
+                    .iter()
+                    .filter(|(other, _)| *other != uri && index.imports_file(other, uri))
+                    .map(|(other, text)| (other.clone(), text.clone()))
+                    .collect();
+                for (other, text) in importers {
+                    publish_diagnostics(&mut stdout, &mut index, &other, &text);
+                }
+            }
+            "textDocument/didClose" => {
+                let uri = request["params"]["textDocument"]["uri"]
+                    .as_str()
+                    .unwrap_or("");
+                doc_cache.remove(uri);
+                index.close(uri);
             }
             "textDocument/semanticTokens/full" => {
                 let uri = request["params"]["textDocument"]["uri"]
@@ -145,7 +180,7 @@ pub fn run_lsp() {
                     .as_str()
                     .unwrap_or("");
                 let doc = doc_cache.get(uri).cloned().unwrap_or_default();
-                let hints = compute_inlay_hints(&doc);
+                let hints = compute_inlay_hints(&index, uri, &doc);
                 let response = serde_json::json!({
                     "jsonrpc": "2.0",
                     "id": id,
@@ -189,8 +224,35 @@ pub fn run_lsp() {
                 });
                 send_response(&mut stdout, &response);
             }
+            "textDocument/formatting" | "textDocument/rangeFormatting" => {
+                let uri = request["params"]["textDocument"]["uri"]
+                    .as_str()
+                    .unwrap_or("");
+                let doc = doc_cache.get(uri).cloned().unwrap_or_default();
+                let range = if method == "textDocument/rangeFormatting" {
+                    Some(&request["params"]["range"])
+                } else {
+                    None
+                };
+                let edits = compute_formatting(&doc, uri, range, &request["params"]["options"]);
+                let response = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": edits
+                });
+                send_response(&mut stdout, &response);
+            }
             "textDocument/completion" => {
-                let completions = get_smart_completions();
+                let uri = request["params"]["textDocument"]["uri"]
+                    .as_str()
+                    .unwrap_or("");
+                let line = request["params"]["position"]["line"].as_u64().unwrap_or(0) as usize;
+                let col = request["params"]["position"]["character"]
+                    .as_u64()
+                    .unwrap_or(0) as usize;
+
+                let doc = doc_cache.get(uri).cloned().unwrap_or_default();
+                let completions = compute_completions(&index, uri, &doc, line, col);
                 let response = serde_json::json!({
                     "jsonrpc": "2.0",
                     "id": id,
@@ -208,7 +270,8 @@ pub fn run_lsp() {
                     .unwrap_or(0) as usize;
 
                 let doc = doc_cache.get(uri).cloned().unwrap_or_default();
-                let hover_info = get_hover_info(&doc, line, col);
+                let hover_info = compute_hover(&index, uri, &doc, line, col)
+                    .unwrap_or_else(|| get_hover_info(&doc, line, col));
 
                 let response = serde_json::json!({
                     "jsonrpc": "2.0",
@@ -231,8 +294,7 @@ pub fn run_lsp() {
                     .as_u64()
                     .unwrap_or(0) as usize;
 
-                let doc = doc_cache.get(uri).cloned().unwrap_or_default();
-                let loc = find_definition(&doc, uri, line, col);
+                let loc = compute_definition(&index, uri, line, col);
 
                 let response = serde_json::json!({
                     "jsonrpc": "2.0",
@@ -241,6 +303,62 @@ pub fn run_lsp() {
                 });
                 send_response(&mut stdout, &response);
             }
+            "textDocument/references" => {
+                let params = &request["params"];
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+                let col = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+                let declaration = params["context"]["includeDeclaration"]
+                    .as_bool()
+                    .unwrap_or(true);
+
+                let locations = compute_references(&index, uri, line, col, declaration);
+                let response = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": locations
+                });
+                send_response(&mut stdout, &response);
+            }
+            "textDocument/rename" => {
+                let params = &request["params"];
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+                let col = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+                let new_name = params["newName"].as_str().unwrap_or("");
+
+                let response = match compute_rename(&index, uri, line, col, new_name) {
+                    Ok(edit) => serde_json::json!({"jsonrpc": "2.0", "id": id, "result": edit}),
+                    Err(message) => serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {"code": -32602, "message": message}
+                    }),
+                };
+                send_response(&mut stdout, &response);
+            }
+            "textDocument/documentSymbol" => {
+                let uri = request["params"]["textDocument"]["uri"]
+                    .as_str()
+                    .unwrap_or("");
+                let symbols = compute_document_symbols(&index, uri);
+                let response = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": symbols
+                });
+                send_response(&mut stdout, &response);
+            }
+            "workspace/symbol" => {
+                let query = request["params"]["query"].as_str().unwrap_or("");
+                let symbols = compute_workspace_symbols(&index, query);
+                let response = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": symbols
+                });
+                send_response(&mut stdout, &response);
+            }
             "shutdown" => {
                 let response = serde_json::json!({"jsonrpc": "2.0", "id": id, "result": null});
                 send_response(&mut stdout, &response);
@@ -379,42 +497,127 @@ pub fn compute_semantic_tokens(source: &str) -> Vec<u32> {
 
 // ── 2. Inlay Hints Engine (Tipos Deducidos y Parámetros) ─────────────
 
-pub fn compute_inlay_hints(source: &str) -> Vec<serde_json::Value> {
+/// Tipos deducidos de los `sea`/`let` sin tipo y nombres de parámetro en
+/// las llamadas a funciones del proyecto, según el análisis semántico.
+pub fn compute_inlay_hints(index: &SymbolIndex, uri: &str, source: &str) -> Vec<serde_json::Value> {
+    let Some(analysis) = analysis_for(index, source, uri) else {
+        return Vec::new();
+    };
+    let (tokens, _) = Lexer::new(source).tokenize();
     let mut hints = Vec::new();
-    for (line_idx, line) in source.lines().enumerate() {
-        let trimmed = line.trim();
-        // Deducir tipos en 'sea x = 42;' o 'let x = "hola";'
-        if trimmed.starts_with("sea ") || trimmed.starts_with("let ") {
-            if let Some(eq_pos) = line.find('=') {
-                let ident_part = line[..eq_pos].trim();
-                let var_name = ident_part.split_whitespace().last().unwrap_or("");
-                let value_part = line[eq_pos + 1..].trim().trim_end_matches(';');
-
-                let deduced_type = if value_part.starts_with('"') {
-                    ": texto"
-                } else if value_part.contains('.') {
-                    ": decimal"
-                } else if value_part.starts_with('[') {
-                    ": lista"
-                } else if value_part == "verdadero" || value_part == "falso" {
-                    ": booleano"
-                } else {
-                    ": entero"
-                };
-
-                let char_idx = line.find(var_name).unwrap_or(0) + var_name.len();
-                hints.push(serde_json::json!({
-                    "position": {"line": line_idx as u32, "character": char_idx as u32},
-                    "label": deduced_type,
-                    "kind": 1, // Type hint
-                    "paddingLeft": true
-                }));
+    let type_hint = |hints: &mut Vec<serde_json::Value>, name_tok: &Token, decl: Pos| {
+        let TokenKind::Ident(name) = &name_tok.kind else {
+            return;
+        };
+        let binding = analysis
+            .bindings
+            .iter()
+            .find(|(n, span, _)| n == name && span.start == decl);
+        if let Some((_, _, t)) = binding.filter(|(_, _, t)| *t != TypeInfo::Void) {
+            hints.push(serde_json::json!({
+                "position": lsp_pos(Pos::new(name_tok.span.end.line, name_tok.span.end.col + 1)),
+                "label": format!(": {}", t),
+                "kind": 1, // Type hint
+                "paddingLeft": false
+            }));
+        }
+    };
+
+    for (i, tok) in tokens.iter().enumerate() {
+        match &tok.kind {
+            TokenKind::Sea | TokenKind::Let => match tokens.get(i + 1).map(|t| &t.kind) {
+                Some(TokenKind::Ident(_)) => type_hint(&mut hints, &tokens[i + 1], tok.span.start),
+                Some(TokenKind::LeftParen) => {
+                    // `sea (a, b) = ...`: cada nombre se declara con su span.
+                    for t in tokens[i + 2..]
+                        .iter()
+                        .take_while(|t| t.kind != TokenKind::RightParen)
+                    {
+                        type_hint(&mut hints, t, t.span.start);
+                    }
+                }
+                _ => {}
+            },
+            TokenKind::Ident(_)
+                if tokens.get(i + 1).map(|t| &t.kind) == Some(&TokenKind::LeftParen) =>
+            {
+                let member =
+                    i > 0 && matches!(tokens[i - 1].kind, TokenKind::Dot | TokenKind::QuestionDot);
+                if !member {
+                    param_hints(index, uri, &tokens, i, &mut hints);
+                }
             }
+            _ => {}
         }
     }
     hints
 }
 
+/// Nombres de parámetro delante de los argumentos de la llamada cuyo nombre
+/// es `tokens[callee]`. No se ponen si el argumento ya se llama igual.
+fn param_hints(
+    index: &SymbolIndex,
+    uri: &str,
+    tokens: &[Token],
+    callee: usize,
+    hints: &mut Vec<serde_json::Value>,
+) {
+    let Some(id) = index.symbol_at(uri, tokens[callee].span.start) else {
+        return;
+    };
+    let Some(function) = index.symbol(&id) else {
+        return;
+    };
+    // La propia declaración no es una llamada.
+    if function.kind != SymbolKind::Function
+        || (index.uri_of(&id) == uri && function.span == tokens[callee].span)
+    {
+        return;
+    }
+    let params = index.parameters(&id);
+    let mut depth = 0usize;
+    let mut arg = 0usize;
+    let mut arg_start = true;
+    for (j, tok) in tokens.iter().enumerate().skip(callee + 2) {
+        match tok.kind {
+            TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::LeftBrace => depth += 1,
+            TokenKind::RightParen | TokenKind::RightBracket | TokenKind::RightBrace => {
+                if depth == 0 {
+                    return;
+                }
+                depth -= 1;
+            }
+            TokenKind::Comma if depth == 0 => {
+                arg += 1;
+                arg_start = true;
+                continue;
+            }
+            TokenKind::Eof | TokenKind::Semicolon => return,
+            _ => {}
+        }
+        if !arg_start {
+            continue;
+        }
+        arg_start = false;
+        let Some(param) = params.get(arg) else {
+            return;
+        };
+        let same_name = matches!(&tok.kind, TokenKind::Ident(n) if n == param)
+            && matches!(
+                tokens.get(j + 1).map(|t| &t.kind),
+                Some(TokenKind::Comma | TokenKind::RightParen)
+            );
+        if !same_name {
+            hints.push(serde_json::json!({
+                "position": lsp_pos(tok.span.start),
+                "label": format!("{}:", param),
+                "kind": 2, // Parameter hint
+                "paddingRight": true
+            }));
+        }
+    }
+}
+
 // ── 3. Signature Help Engine ─────────────────────────────────────────
 
 pub fn compute_signature_help(source: &str, line_idx: usize, _col_idx: usize) -> serde_json::Value {
@@ -473,7 +676,25 @@ pub fn compute_code_actions(
         let msg = diag["message"].as_str().unwrap_or("");
         let code = diag["code"].as_str().unwrap_or("");
 
-        if code == "E012" || msg.contains(';') {
+        // Correcciones exactas que vienen del propio diagnóstico.
+        let fixes: Vec<Fix> =
+            serde_json::from_value(diag["data"]["fixes"].clone()).unwrap_or_default();
+        for fix in &fixes {
+            let edits: Vec<serde_json::Value> = fix
+                .edits
+                .iter()
+                .map(|e| serde_json::json!({"range": lsp_range(e.start, e.end), "newText": e.replacement}))
+                .collect();
+            actions.push(serde_json::json!({
+                "title": format!("💡 {}", fix.message),
+                "kind": "quickfix",
+                "isPreferred": true,
+                "diagnostics": [diag],
+                "edit": {"changes": {uri: edits}}
+            }));
+        }
+
+        if fixes.is_empty() && (code == "E012" || msg.contains(';')) {
             actions.push(serde_json::json!({
                 "title": "💡 Corregir: Agregar ';' al final de la sentencia",
                 "kind": "quickfix",
@@ -520,65 +741,272 @@ pub fn compute_code_actions(
     actions
 }
 
-// ── 5. Diagnósticos y Análisis Semántico ──────────────────────────────
+// ── 5. Formateo (Documento Completo o Rango de Líneas) ───────────────
 
-fn analyze(source: &str, _uri: &str) -> Vec<serde_json::Value> {
+/// `TextEdit`s de `textDocument/formatting` (sin `range`) o
+/// `textDocument/rangeFormatting`. El rango se formatea por líneas enteras y
+/// puede crecer hasta las de la sentencia que corta. Vacío si ya está
+/// formateado o tiene errores de sintaxis, que ya salen como diagnósticos.
+pub fn compute_formatting(
+    source: &str,
+    uri: &str,
+    range: Option<&serde_json::Value>,
+    options: &serde_json::Value,
+) -> Vec<serde_json::Value> {
+    let config = fmt_config_for(uri, options);
+    let edit = match range {
+        None => match lumen_fmt::format_source_with_config(source, &config) {
+            Ok(text) if text != source => lumen_fmt::LineEdit {
+                start: 0,
+                end: source.lines().count(),
+                text,
+            },
+            _ => return Vec::new(),
+        },
+        Some(range) => {
+            let first = range["start"]["line"].as_u64().unwrap_or(0) as usize;
+            let mut last = range["end"]["line"].as_u64().unwrap_or(0) as usize;
+            // Un rango que acaba al principio de una línea no la incluye.
+            if last > first && range["end"]["character"].as_u64() == Some(0) {
+                last -= 1;
+            }
+            match lumen_fmt::format_range(source, &config, first..last.max(first) + 1) {
+                Ok(Some(edit)) => edit,
+                _ => return Vec::new(),
+            }
+        }
+    };
+    vec![serde_json::json!({
+        "range": {
+            "start": {"line": edit.start, "character": 0},
+            "end": {"line": edit.end, "character": 0}
+        },
+        "newText": edit.text
+    })]
+}
+
+/// Configuración del formateador para un documento: la de su
+/// `.lumen-fmt.toml` o `lumen.toml`, y si no hay, la de por defecto con la
+/// sangría que pide el editor.
+fn fmt_config_for(uri: &str, options: &serde_json::Value) -> lumen_fmt::FmtConfig {
+    let found = uri
+        .strip_prefix("file://")
+        .and_then(|path| lumen_fmt::find_config(std::path::Path::new(path)).ok())
+        .flatten();
+    found.unwrap_or_else(|| lumen_fmt::FmtConfig {
+        indent_spaces: options["tabSize"].as_u64().unwrap_or(4) as usize,
+        ..lumen_fmt::FmtConfig::default()
+    })
+}
+
+// ── 6. Diagnósticos y Análisis Semántico ──────────────────────────────
+
+/// Diagnósticos de un documento suelto, con un índice vacío: los `importar`
+/// se resuelven solo desde el disco.
+pub fn analyze(source: &str, uri: &str) -> Vec<serde_json::Value> {
+    analyze_in(&SymbolIndex::new(Vec::new()), source, uri).0
+}
+
+/// Publica los diagnósticos de un documento abierto y guarda en el índice
+/// los tipos que dio el análisis.
+fn publish_diagnostics(stdout: &mut impl Write, index: &mut SymbolIndex, uri: &str, text: &str) {
+    let (diagnostics, types) = analyze_in(index, text, uri);
+    index.set_types(uri, types);
+    let notification = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {
+            "uri": uri,
+            "diagnostics": diagnostics
+        }
+    });
+    send_response(stdout, &notification);
+}
+
+/// Como `analyze`, pero con los `importar` resueltos: el análisis semántico
+/// ve lo que declaran los módulos importados (con el texto del editor si
+/// están abiertos) y solo se informa de lo que está en este archivo. Un
+/// módulo importado que no carga o no compila se marca en su `importar`.
+/// Devuelve también los tipos de los nombres de primer nivel.
+fn analyze_in(
+    index: &SymbolIndex,
+    source: &str,
+    uri: &str,
+) -> (Vec<serde_json::Value>, HashMap<String, TypeInfo>) {
     let mut diagnostics = Vec::new();
 
-    let lexer = Lexer::new(source);
-    let (tokens, lex_errors) = lexer.tokenize();
+    let (tokens, lex_errors) = Lexer::new(source).tokenize();
     for e in &lex_errors {
-        diagnostics.push(serde_json::json!({
-            "range": {
-                "start": {"line": e.pos.line.saturating_sub(1) as u32, "character": e.pos.col.saturating_sub(1) as u32},
-                "end": {"line": e.pos.line.saturating_sub(1) as u32, "character": e.pos.col as u32}
-            },
-            "severity": 1,
-            "code": e.code,
-            "source": "lumen-lexer",
-            "message": format!("{} — {}", e.message, e.suggestion)
-        }));
+        diagnostics.push(to_lsp_diagnostic(&Diagnostic::from(e), uri, "lumen-lexer"));
     }
-
     if !lex_errors.is_empty() {
-        return diagnostics;
+        return (diagnostics, HashMap::new());
     }
 
-    let parser = Parser::new(tokens);
-    let (mut program, parse_errors) = parser.parse();
+    let (program, parse_errors) = Parser::new(tokens).parse();
     for e in &parse_errors {
-        diagnostics.push(serde_json::json!({
-            "range": {
-                "start": {"line": e.span.start.line.saturating_sub(1) as u32, "character": e.span.start.col.saturating_sub(1) as u32},
-                "end": {"line": e.span.end.line.saturating_sub(1) as u32, "character": e.span.end.col as u32}
-            },
-            "severity": 1,
-            "code": e.code,
-            "source": "lumen-parser",
-            "message": format!("{} — {}", e.message, e.suggestion)
-        }));
+        diagnostics.push(to_lsp_diagnostic(&Diagnostic::from(e), uri, "lumen-parser"));
     }
-
     if !parse_errors.is_empty() {
-        return diagnostics;
+        return (diagnostics, HashMap::new());
     }
 
-    let sema = SemanticAnalyzer::new();
-    let sem_errors = sema.analyze(&mut program);
-    for e in &sem_errors {
-        diagnostics.push(serde_json::json!({
-            "range": {
-                "start": {"line": e.span.start.line.saturating_sub(1) as u32, "character": e.span.start.col.saturating_sub(1) as u32},
-                "end": {"line": e.span.end.line.saturating_sub(1) as u32, "character": e.span.end.col as u32}
-            },
-            "severity": 1,
-            "code": e.code,
-            "source": "lumen-sema",
-            "message": format!("{} — {}", e.message, e.suggestion)
-        }));
+    let analysis = match analyze_program(index, source, uri, program.clone()) {
+        Ok(analysis) => analysis,
+        Err(e) => {
+            for diag in module_diagnostics(index, uri, &e) {
+                diagnostics.push(to_lsp_diagnostic(&diag, uri, "lumen-sema"));
+            }
+            ModuleAnalysis::default()
+        }
+    };
+    for e in &analysis.errors {
+        diagnostics.push(to_lsp_diagnostic(&Diagnostic::from(e), uri, "lumen-sema"));
+    }
+
+    // Los avisos del linter aparecen también con errores semánticos: el AST
+    // ya es válido sintácticamente y el usuario quiere verlos mientras escribe.
+    let config = lint_config_for(uri);
+    let lints = Linter::new(&config)
+        .with_context(&program)
+        .check(&program, source);
+    for l in &lints {
+        diagnostics.push(to_lsp_diagnostic(&Diagnostic::from(l), uri, "lumen-lint"));
+    }
+
+    (diagnostics, analysis.globals)
+}
+
+/// Análisis semántico de `program` (el de `source`) con sus imports
+/// aplanados; sin imports, o fuera de un `file://`, el del archivo solo.
+fn analyze_program(
+    index: &SymbolIndex,
+    source: &str,
+    uri: &str,
+    mut program: Program,
+) -> Result<ModuleAnalysis, ModuleError> {
+    let imports = program.iter().any(|node| {
+        matches!(node, DeclOrStmt::Stmt(Stmt::Import { path, .. })
+            if path != "ingles" && path != "english")
+    });
+    if imports && uri.starts_with("file://") {
+        let (mut flat, origins) = index.flatten(uri, source)?;
+        let me = index::uri_path(uri);
+        let report: Vec<bool> = origins.iter().map(|o| *o == me).collect();
+        Ok(SemanticAnalyzer::new().analyze_module(&mut flat, &report))
+    } else {
+        let report = vec![true; program.len()];
+        Ok(SemanticAnalyzer::new().analyze_module(&mut program, &report))
+    }
+}
+
+/// Tipos de un documento para hover, completado e inlay hints. Con errores
+/// de sintaxis se analiza lo que el parser recupera.
+fn analysis_for(index: &SymbolIndex, source: &str, uri: &str) -> Option<ModuleAnalysis> {
+    let (tokens, lex_errors) = Lexer::new(source).tokenize();
+    if !lex_errors.is_empty() {
+        return None;
+    }
+    let (program, _) = Parser::new(tokens).parse();
+    analyze_program(index, source, uri, program.clone())
+        .or_else(|_| analyze_program(index, source, "untitled:", program))
+        .ok()
+}
+
+/// Errores de un módulo importado, situados en el `importar` por el que se
+/// llega a él. El mensaje lleva la ruta y la posición originales.
+fn module_diagnostics(index: &SymbolIndex, uri: &str, error: &ModuleError) -> Vec<Diagnostic> {
+    let module = match error {
+        ModuleError::Io { path, .. }
+        | ModuleError::Lex { path, .. }
+        | ModuleError::Parse { path, .. }
+        | ModuleError::Circular { path, .. } => path,
+    };
+    let at = index.import_leading_to(uri, module);
+    error
+        .to_diagnostics()
+        .into_iter()
+        .map(|mut diag| {
+            let file = diag
+                .file
+                .take()
+                .unwrap_or_else(|| module.display().to_string());
+            if let Some(at) = at {
+                let pos = diag.span().start;
+                diag.message = format!("{}:{}:{}: {}", file, pos.line, pos.col, diag.message);
+                diag.primary.span = at;
+            }
+            diag
+        })
+        .collect()
+}
+
+fn lsp_range(start: Pos, end_exclusive: Pos) -> serde_json::Value {
+    serde_json::json!({
+        "start": {"line": start.line.saturating_sub(1) as u32, "character": start.col.saturating_sub(1) as u32},
+        "end": {"line": end_exclusive.line.saturating_sub(1) as u32, "character": end_exclusive.col.saturating_sub(1) as u32}
+    })
+}
+
+fn span_range(span: Span) -> serde_json::Value {
+    lsp_range(span.start, Pos::new(span.end.line, span.end.col + 1))
+}
+
+/// `Diagnostic` → diagnóstico LSP. Las etiquetas secundarias van en
+/// `relatedInformation` y las correcciones en `data.fixes`, de donde las
+/// recoge `compute_code_actions`.
+fn to_lsp_diagnostic(diag: &Diagnostic, uri: &str, source: &str) -> serde_json::Value {
+    let severity = match diag.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Info => 3,
+    };
+    let message = if diag.suggestion.is_empty() {
+        diag.message.clone()
+    } else {
+        format!("{} — {}", diag.message, diag.suggestion)
+    };
+    let mut out = serde_json::json!({
+        "range": span_range(diag.span()),
+        "severity": severity,
+        "code": diag.code,
+        "source": source,
+        "message": message
+    });
+    if !diag.secondary.is_empty() {
+        out["relatedInformation"] = diag
+            .secondary
+            .iter()
+            .map(|l| {
+                serde_json::json!({
+                    "location": {"uri": uri, "range": span_range(l.span)},
+                    "message": l.message
+                })
+            })
+            .collect();
+    }
+    if !diag.fixes.is_empty() {
+        out["data"] = serde_json::json!({ "fixes": diag.fixes });
     }
+    out
+}
 
-    diagnostics
+/// Configuración del linter para un documento: se busca el `lumen.toml` más
+/// cercano a partir de la ruta del `file://` URI. Si no hay manifiesto o es
+/// inválido se usan los niveles por defecto.
+fn lint_config_for(uri: &str) -> LintConfig {
+    let Some(path) = uri.strip_prefix("file://") else {
+        return LintConfig::default();
+    };
+    let dir = std::path::Path::new(path)
+        .parent()
+        .unwrap_or(std::path::Path::new("."));
+    lumen_project::find_manifest(dir)
+        .and_then(|m| lumen_project::load_lint_rules(&m).ok())
+        .and_then(|rules| {
+            LintConfig::from_rules(rules.iter().map(|(k, v)| (k.as_str(), v.as_str()))).ok()
+        })
+        .unwrap_or_default()
 }
 
 fn get_word_at(doc: &str, line_idx: usize, col_idx: usize) -> String {
@@ -593,16 +1021,17 @@ fn get_word_at(doc: &str, line_idx: usize, col_idx: usize) -> String {
     }
 
     let mut start = col_idx;
-    while start > 0 && (chars[start - 1].is_alphanumeric() || chars[start - 1] == '_') {
+    while start > 0 && ident::is_ident_continue(chars[start - 1]) {
         start -= 1;
     }
 
     let mut end = col_idx;
-    while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+    while end < chars.len() && ident::is_ident_continue(chars[end]) {
         end += 1;
     }
 
-    chars[start..end].iter().collect()
+    // Los símbolos se guardan en NFC, como los deja el lexer.
+    ident::normalize(&chars[start..end].iter().collect::<String>())
 }
 
 fn get_hover_info(doc: &str, line_idx: usize, col_idx: usize) -> String {
@@ -628,30 +1057,434 @@ fn get_hover_info(doc: &str, line_idx: usize, col_idx: usize) -> String {
     }
 }
 
-fn find_definition(doc: &str, uri: &str, line_idx: usize, col_idx: usize) -> serde_json::Value {
-    let word = get_word_at(doc, line_idx, col_idx);
-    if word.is_empty() {
+// ── 7. Índice de Símbolos del Proyecto ────────────────────────────────
+
+/// Símbolo bajo el cursor (posición LSP, base 0). También vale con el cursor
+/// justo detrás del nombre.
+fn symbol_at(index: &SymbolIndex, uri: &str, line: usize, col: usize) -> Option<index::SymbolId> {
+    let pos = Pos::new(line + 1, col + 1);
+    index
+        .symbol_at(uri, pos)
+        .or_else(|| (col > 0).then(|| index.symbol_at(uri, Pos::new(line + 1, col)))?)
+}
+
+pub fn compute_definition(
+    index: &SymbolIndex,
+    uri: &str,
+    line: usize,
+    col: usize,
+) -> serde_json::Value {
+    let Some(id) = symbol_at(index, uri, line, col) else {
+        return serde_json::Value::Null;
+    };
+    let Some(symbol) = index.symbol(&id) else {
         return serde_json::Value::Null;
+    };
+    serde_json::json!({
+        "uri": index.uri_of(&id),
+        "range": span_range(symbol.span)
+    })
+}
+
+pub fn compute_references(
+    index: &SymbolIndex,
+    uri: &str,
+    line: usize,
+    col: usize,
+    include_declaration: bool,
+) -> Vec<serde_json::Value> {
+    let Some(id) = symbol_at(index, uri, line, col) else {
+        return Vec::new();
+    };
+    let declaration = index.symbol(&id).map(|s| s.span);
+    let decl_uri = index.uri_of(&id);
+    index
+        .references(&id)
+        .into_iter()
+        .filter(|(at, span, _)| {
+            include_declaration || !(*at == decl_uri && Some(*span) == declaration)
+        })
+        .map(|(at, span, _)| serde_json::json!({"uri": at, "range": span_range(span)}))
+        .collect()
+}
+
+/// `WorkspaceEdit` que renombra el símbolo en todos los archivos. En los que
+/// lo importan se conserva el prefijo del módulo (`mates_suma` →
+/// `mates_sumar`).
+pub fn compute_rename(
+    index: &SymbolIndex,
+    uri: &str,
+    line: usize,
+    col: usize,
+    new_name: &str,
+) -> Result<serde_json::Value, String> {
+    let new_name = ident::normalize(new_name);
+    if !ident::is_identifier(&new_name) || TokenKind::is_keyword(&new_name).is_some() {
+        return Err(format!("'{}' no es un nombre válido", new_name));
     }
+    let id = symbol_at(index, uri, line, col)
+        .ok_or_else(|| "No hay ningún símbolo que renombrar aquí".to_string())?;
+    let old = index
+        .symbol(&id)
+        .map(|s| s.name.clone())
+        .unwrap_or_default();
+
+    let mut changes: serde_json::Map<String, serde_json::Value> = serde_json::Map::new();
+    for (at, span, text) in index.references(&id) {
+        let prefix = &text[..text.len() - old.len()];
+        let edit = serde_json::json!({
+            "range": span_range(span),
+            "newText": format!("{}{}", prefix, new_name)
+        });
+        changes
+            .entry(at)
+            .or_insert_with(|| serde_json::json!([]))
+            .as_array_mut()
+            .expect("lista de ediciones")
+            .push(edit);
+    }
+    Ok(serde_json::json!({ "changes": changes }))
+}
 
-    for (i, line) in doc.lines().enumerate() {
-        if line.contains(&format!("funcion {}", word))
-            || line.contains(&format!("function {}", word))
-            || line.contains(&format!("estructura {}", word))
-            || line.contains(&format!("struct {}", word))
-            || line.contains(&format!("enum {}", word))
-        {
-            return serde_json::json!({
-                "uri": uri,
-                "range": {
-                    "start": {"line": i as u32, "character": 0},
-                    "end": {"line": i as u32, "character": line.len() as u32}
-                }
+/// Esquema del documento: declaraciones de primer nivel con sus campos,
+/// variantes y métodos.
+pub fn compute_document_symbols(index: &SymbolIndex, uri: &str) -> Vec<serde_json::Value> {
+    let Some(file) = index.file(uri) else {
+        return Vec::new();
+    };
+    let path = index::uri_path(uri);
+    let entry = |i: usize| {
+        let symbol = &file.symbols[i];
+        serde_json::json!({
+            "name": symbol.name,
+            "detail": index.detail(&(path.clone(), i)),
+            "kind": symbol.kind.lsp(),
+            "range": span_range(symbol.full),
+            "selectionRange": span_range(symbol.span)
+        })
+    };
+    file.symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.scope.is_none() && s.parent.is_none())
+        .map(|(i, _)| {
+            let mut out = entry(i);
+            let children: Vec<serde_json::Value> = file
+                .symbols
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.parent == Some(i))
+                .map(|(c, _)| entry(c))
+                .collect();
+            if !children.is_empty() {
+                out["children"] = children.into();
+            }
+            out
+        })
+        .collect()
+}
+
+pub fn compute_workspace_symbols(index: &SymbolIndex, query: &str) -> Vec<serde_json::Value> {
+    index
+        .search(query)
+        .iter()
+        .filter_map(|id| {
+            let symbol = index.symbol(id)?;
+            let mut out = serde_json::json!({
+                "name": symbol.name,
+                "kind": symbol.kind.lsp(),
+                "location": {"uri": index.uri_of(id), "range": span_range(symbol.span)}
             });
+            if let Some(parent) = symbol.parent.and_then(|p| index.symbol(&(id.0.clone(), p))) {
+                out["containerName"] = parent.name.clone().into();
+            }
+            Some(out)
+        })
+        .collect()
+}
+
+// ── 8. Completado y Hover con Tipos ───────────────────────────────────
+
+/// Después de `.` o `?.`, los campos y métodos del tipo del receptor; después
+/// de `Enum::`, sus variantes. En otro sitio, palabras clave y funciones
+/// integradas.
+pub fn compute_completions(
+    index: &SymbolIndex,
+    uri: &str,
+    source: &str,
+    line: usize,
+    col: usize,
+) -> Vec<serde_json::Value> {
+    let (tokens, _) = Lexer::new(source).tokenize();
+    let cursor = Pos::new(line + 1, col + 1);
+    // Los spans de los signos de dos caracteres no siempre cuadran: cuenta
+    // dónde empieza cada token.
+    let mut before: Vec<&Token> = tokens
+        .iter()
+        .filter(|t| t.kind != TokenKind::Eof && pos_lt(t.span.start, cursor))
+        .collect();
+    // El nombre que se está escribiendo no cuenta.
+    if before.last().is_some_and(|t| {
+        matches!(t.kind, TokenKind::Ident(_))
+            && !pos_lt(Pos::new(t.span.end.line, t.span.end.col + 1), cursor)
+    }) {
+        before.pop();
+    }
+    let Some(trigger) = before.len().checked_sub(1) else {
+        return get_smart_completions();
+    };
+    match &before[trigger].kind {
+        TokenKind::Dot | TokenKind::QuestionDot if trigger > 0 => {
+            let Some((analysis, t)) = receiver_type(index, uri, source, &before, trigger - 1)
+            else {
+                return Vec::new();
+            };
+            let t = match (&before[trigger].kind, t) {
+                (TokenKind::QuestionDot, TypeInfo::Opcion(inner)) => *inner,
+                (_, t) => t,
+            };
+            analysis
+                .members(&t)
+                .into_iter()
+                .map(|(name, member)| {
+                    let kind = if matches!(member, TypeInfo::Func { .. }) {
+                        2 // Method
+                    } else {
+                        5 // Field
+                    };
+                    serde_json::json!({"label": name, "kind": kind, "detail": member.to_string()})
+                })
+                .collect()
+        }
+        TokenKind::DoubleColon if trigger > 0 => {
+            let TokenKind::Ident(name) = &before[trigger - 1].kind else {
+                return Vec::new();
+            };
+            let Some((analysis, _)) = receiver_analysis(index, uri, source, &before, trigger - 1)
+            else {
+                return Vec::new();
+            };
+            let variants = analysis.enums.get(name).cloned().unwrap_or_default();
+            variants
+                .iter()
+                .map(|(variant, types)| {
+                    let detail = if types.is_empty() {
+                        format!("{}::{}", name, variant)
+                    } else {
+                        let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+                        format!("{}::{}({})", name, variant, types.join(", "))
+                    };
+                    serde_json::json!({"label": variant, "kind": 20, "detail": detail})
+                })
+                .collect()
         }
+        _ => get_smart_completions(),
     }
+}
 
-    serde_json::Value::Null
+/// Tipo del receptor `tokens[..=end]` de un `.` que se está escribiendo.
+fn receiver_type(
+    index: &SymbolIndex,
+    uri: &str,
+    source: &str,
+    tokens: &[&Token],
+    end: usize,
+) -> Option<(ModuleAnalysis, TypeInfo)> {
+    let (analysis, at) = receiver_analysis(index, uri, source, tokens, end)?;
+    let t = match analysis.type_at(at, None) {
+        Some(t) => t.clone(),
+        // La línea no se pudo aislar (p. ej. está dentro de una llamada
+        // partida en varias): un nombre suelto se busca por su declaración.
+        None => {
+            let TokenKind::Ident(name) = &tokens[end].kind else {
+                return None;
+            };
+            let line = tokens[end].span.start.line;
+            analysis
+                .bindings
+                .iter()
+                .rev()
+                .find(|(n, span, _)| n == name && span.start.line <= line)
+                .map(|(_, _, t)| t.clone())
+                .or_else(|| analysis.globals.get(name).cloned())?
+        }
+    };
+    Some((analysis, t))
+}
+
+/// Analiza `source` con la línea del receptor `tokens[..=end]` cambiada por
+/// el receptor solo (`p.nom` → `p;`): lo que se está escribiendo rara vez
+/// compila. Devuelve también dónde queda el receptor en ese texto.
+fn receiver_analysis(
+    index: &SymbolIndex,
+    uri: &str,
+    source: &str,
+    tokens: &[&Token],
+    end: usize,
+) -> Option<(ModuleAnalysis, Pos)> {
+    let start = receiver_start(tokens, end)?;
+    let (from, to) = (tokens[start].span.start, tokens[end].span.end);
+    let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+    let line = lines.get(from.line.checked_sub(1)?)?;
+    let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+    let receiver: String = if from.line == to.line {
+        line.chars()
+            .skip(from.col - 1)
+            .take(to.col + 1 - from.col)
+            .collect()
+    } else {
+        return None;
+    };
+    let at = Pos::new(from.line, indent.chars().count() + 1);
+    lines[from.line - 1] = format!("{}{};", indent, receiver);
+    let analysis = analysis_for(index, &lines.join("\n"), uri)?;
+    Some((analysis, at))
+}
+
+/// Primer token de la expresión que acaba en `tokens[end]`: nombres,
+/// literales, llamadas e índices encadenados con `.`, `?.` o `::`.
+fn receiver_start(tokens: &[&Token], end: usize) -> Option<usize> {
+    let mut i = end;
+    loop {
+        // Un operando que acaba en `i`, con sus llamadas e índices.
+        loop {
+            match tokens[i].kind {
+                TokenKind::RightParen | TokenKind::RightBracket => {
+                    let mut depth = 0usize;
+                    loop {
+                        match tokens[i].kind {
+                            TokenKind::RightParen | TokenKind::RightBracket => depth += 1,
+                            TokenKind::LeftParen | TokenKind::LeftBracket => depth -= 1,
+                            _ => {}
+                        }
+                        if depth == 0 {
+                            break;
+                        }
+                        i = i.checked_sub(1)?;
+                    }
+                    // `f(...)` o `a[...]`; si no, un paréntesis suelto.
+                    match i.checked_sub(1).map(|p| &tokens[p].kind) {
+                        Some(
+                            TokenKind::Ident(_) | TokenKind::RightParen | TokenKind::RightBracket,
+                        ) => i -= 1,
+                        _ => break,
+                    }
+                }
+                TokenKind::Ident(_)
+                | TokenKind::StrLiteral(_)
+                | TokenKind::NumLiteral(_)
+                | TokenKind::Verdadero
+                | TokenKind::Falso => break,
+                _ => return None,
+            }
+        }
+        match i.checked_sub(1).map(|p| &tokens[p].kind) {
+            Some(TokenKind::Dot | TokenKind::QuestionDot | TokenKind::DoubleColon) if i >= 2 => {
+                i -= 2
+            }
+            _ => return Some(i),
+        }
+    }
+}
+
+/// Tipo y documentación del nombre bajo el cursor, en markdown. `None` si
+/// no hay un nombre que el análisis conozca.
+pub fn compute_hover(
+    index: &SymbolIndex,
+    uri: &str,
+    source: &str,
+    line: usize,
+    col: usize,
+) -> Option<String> {
+    let (tokens, _) = Lexer::new(source).tokenize();
+    let cursor = Pos::new(line + 1, col + 1);
+    let at = tokens.iter().position(|t| {
+        matches!(t.kind, TokenKind::Ident(_))
+            && !pos_lt(cursor, t.span.start)
+            && !pos_lt(Pos::new(t.span.end.line, t.span.end.col + 1), cursor)
+    })?;
+    let TokenKind::Ident(name) = &tokens[at].kind else {
+        return None;
+    };
+    let span = tokens[at].span;
+    let analysis = analysis_for(index, source, uri).unwrap_or_default();
+
+    // `a.campo` o `a.metodo()`: lo que dice el tipo de `a`.
+    if at >= 2 && matches!(tokens[at - 1].kind, TokenKind::Dot | TokenKind::QuestionDot) {
+        let refs: Vec<&Token> = tokens.iter().collect();
+        let start = receiver_start(&refs, at - 2)?;
+        let receiver = analysis.type_at(tokens[start].span.start, Some(tokens[at - 2].span.end))?;
+        let receiver = match (&tokens[at - 1].kind, receiver) {
+            (TokenKind::QuestionDot, TypeInfo::Opcion(inner)) => inner.as_ref(),
+            (_, t) => t,
+        };
+        let (_, member) = analysis
+            .members(receiver)
+            .into_iter()
+            .find(|(m, _)| m == name)?;
+        let owner = receiver.to_string();
+        let doc = index.member_doc(&owner, name).unwrap_or_default();
+        return Some(hover_text(&format!("{}.{}: {}", owner, name, member), &doc));
+    }
+
+    let id = index.symbol_at(uri, span.start);
+    let symbol = id.as_ref().and_then(|id| index.symbol(id));
+    let own = id.as_ref().is_some_and(|id| index.uri_of(id) == uri);
+    // El tipo de este uso, el de la declaración o el de primer nivel.
+    let inferred = analysis
+        .type_at(span.start, Some(span.end))
+        .or_else(|| {
+            let symbol = symbol.filter(|_| own)?;
+            analysis
+                .bindings
+                .iter()
+                .find(|(n, at, _)| {
+                    *n == symbol.name
+                        && !pos_lt(at.start, symbol.full.start)
+                        && !pos_lt(symbol.full.end, at.start)
+                })
+                .map(|(_, _, t)| t)
+        })
+        .or_else(|| analysis.globals.get(name));
+
+    let (signature, doc) = match (symbol, id.as_ref()) {
+        (Some(symbol), Some(id)) => {
+            let signature = match symbol.kind {
+                SymbolKind::Variable | SymbolKind::Parameter | SymbolKind::Constant => {
+                    let t = inferred
+                        .map(|t| t.to_string())
+                        .unwrap_or_else(|| index.detail(id));
+                    let keyword = if symbol.kind == SymbolKind::Constant {
+                        "const "
+                    } else {
+                        ""
+                    };
+                    format!("{}{} {}", keyword, t, name)
+                }
+                SymbolKind::Field => format!("{}: {}", symbol.name, symbol.detail),
+                _ => index.detail(id),
+            };
+            (signature, symbol.doc.clone())
+        }
+        _ => (format!("{}: {}", name, inferred?), String::new()),
+    };
+    Some(hover_text(&signature, &doc))
+}
+
+fn hover_text(signature: &str, doc: &str) -> String {
+    if doc.is_empty() {
+        format!("```lumen\n{}\n```", signature)
+    } else {
+        format!("```lumen\n{}\n```\n{}", signature, doc)
+    }
+}
+
+fn pos_lt(a: Pos, b: Pos) -> bool {
+    (a.line, a.col) < (b.line, b.col)
+}
+
+fn lsp_pos(pos: Pos) -> serde_json::Value {
+    serde_json::json!({"line": pos.line.saturating_sub(1) as u32, "character": pos.col.saturating_sub(1) as u32})
 }
 
 fn get_smart_completions() -> Vec<serde_json::Value> {
@@ -809,8 +1642,10 @@ mod tests {
     #[test]
     fn test_inlay_hints() {
         let code = "sea total = 100;\nlet saludo = \"hola\";";
-        let hints = compute_inlay_hints(code);
+        let hints = compute_inlay_hints(&SymbolIndex::new(Vec::new()), "untitled:prueba", code);
         assert_eq!(hints.len(), 2);
+        assert_eq!(hints[0]["label"], ": entero");
+        assert_eq!(hints[1]["label"], ": texto");
     }
 
     #[test]
@@ -830,4 +1665,300 @@ mod tests {
         let actions = compute_code_actions("numero x = 10", "file:///test.nv", &range, &[diag]);
         assert!(!actions.is_empty());
     }
+
+    #[test]
+    fn test_diagnosticos_de_lint() {
+        let code = "funcion entero f(entero a) {\n    entero b = 1;\n    retornar 0;\n}\nf(1);";
+        let diags = analyze(code, "untitled:prueba");
+        let codes: Vec<&str> = diags
+            .iter()
+            .filter(|d| d["source"] == "lumen-lint")
+            .filter_map(|d| d["code"].as_str())
+            .collect();
+        assert!(codes.contains(&"L001"), "{:?}", diags);
+        assert!(codes.contains(&"L002"), "{:?}", diags);
+        assert!(diags.iter().all(|d| d["severity"] != 1));
+    }
+
+    #[test]
+    fn test_arreglo_desde_diagnostico() {
+        let code = "numero x = 10\nimprimir(x);";
+        let diags = analyze(code, "file:///test.nv");
+        let e012 = diags.iter().find(|d| d["code"] == "E012").expect("E012");
+        let range = e012["range"].clone();
+        let actions =
+            compute_code_actions(code, "file:///test.nv", &range, std::slice::from_ref(e012));
+        let edit = &actions[0]["edit"]["changes"]["file:///test.nv"][0];
+        assert_eq!(edit["newText"], ";");
+        assert_eq!(
+            edit["range"]["start"],
+            serde_json::json!({"line": 0, "character": 13})
+        );
+    }
+
+    #[test]
+    fn test_formateo_de_documento_y_rango() {
+        let code = "entero a=1;\nsi a>0 {\nimprimir( a );\n}\n";
+        let options = serde_json::json!({"tabSize": 2, "insertSpaces": true});
+        let edits = compute_formatting(code, "untitled:prueba", None, &options);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0]["newText"],
+            "entero a = 1;\nsi a > 0 {\n  imprimir(a);\n}\n"
+        );
+        assert_eq!(edits[0]["range"]["end"]["line"], 4);
+
+        let range = serde_json::json!({"start": {"line": 2, "character": 0}, "end": {"line": 3, "character": 0}});
+        let edits = compute_formatting(code, "untitled:prueba", Some(&range), &options);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0]["newText"], "  imprimir(a);\n");
+        assert_eq!(edits[0]["range"]["start"]["line"], 2);
+        assert_eq!(edits[0]["range"]["end"]["line"], 3);
+
+        assert!(compute_formatting("si {", "untitled:prueba", None, &options).is_empty());
+    }
+
+    /// Proyecto con `mates.nv` y un `main.nv` que lo importa.
+    fn proyecto(nombre: &str) -> (std::path::PathBuf, SymbolIndex, String, String) {
+        let dir = std::env::temp_dir().join(format!("lumen_lsp_{}_{}", nombre, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = std::fs::canonicalize(&dir).unwrap();
+        std::fs::write(
+            dir.join("mates.nv"),
+            "funcion entero suma(entero a, entero b) {\n    retornar a + b;\n}\n\nenum Forma {\n    Circulo,\n    Cuadrado,\n}\n\nForma f = Forma::Circulo;\n",
+        )
+        .unwrap();
+        let main = "importar \"mates.nv\";\nentero total = mates_suma(1, 2);\nimprimir(total);\n";
+        std::fs::write(dir.join("main.nv"), main).unwrap();
+
+        let mut index = SymbolIndex::new(Vec::new());
+        index.set_root(&dir);
+        let main_uri = format!("file://{}", dir.join("main.nv").display());
+        let mates_uri = format!("file://{}", dir.join("mates.nv").display());
+        index.update(&main_uri, main);
+        (dir, index, main_uri, mates_uri)
+    }
+
+    #[test]
+    fn test_definicion_y_referencias_entre_modulos() {
+        let (dir, index, main_uri, mates_uri) = proyecto("refs");
+
+        let def = compute_definition(&index, &main_uri, 1, 18);
+        assert_eq!(def["uri"], mates_uri.as_str());
+        assert_eq!(
+            def["range"],
+            serde_json::json!({"start": {"line": 0, "character": 15}, "end": {"line": 0, "character": 19}})
+        );
+
+        let refs = compute_references(&index, &mates_uri, 0, 16, true);
+        let uris: Vec<&str> = refs.iter().filter_map(|r| r["uri"].as_str()).collect();
+        assert_eq!(uris.len(), 2, "{:?}", refs);
+        assert!(uris.contains(&main_uri.as_str()));
+        assert_eq!(
+            compute_references(&index, &mates_uri, 0, 16, false).len(),
+            1
+        );
+
+        // Un parámetro solo se ve en su función.
+        assert_eq!(compute_references(&index, &mates_uri, 1, 13, true).len(), 2);
+
+        // `Forma::Circulo` resuelve a la variante.
+        let def = compute_definition(&index, &mates_uri, 9, 18);
+        assert_eq!(
+            def["range"]["start"],
+            serde_json::json!({"line": 5, "character": 4})
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_renombrado_conserva_el_prefijo() {
+        let (dir, index, main_uri, mates_uri) = proyecto("rename");
+
+        let edit = compute_rename(&index, &main_uri, 1, 18, "sumar").unwrap();
+        assert_eq!(edit["changes"][&main_uri][0]["newText"], "mates_sumar");
+        assert_eq!(edit["changes"][&mates_uri][0]["newText"], "sumar");
+
+        assert!(compute_rename(&index, &main_uri, 1, 18, "si").is_err());
+        assert!(compute_rename(&index, &main_uri, 1, 18, "1x").is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_simbolos_del_documento_y_del_proyecto() {
+        let (dir, index, _, mates_uri) = proyecto("simbolos");
+
+        let symbols = compute_document_symbols(&index, &mates_uri);
+        let names: Vec<&str> = symbols.iter().filter_map(|s| s["name"].as_str()).collect();
+        assert_eq!(names, vec!["suma", "Forma", "f"]);
+        assert_eq!(
+            symbols[0]["detail"],
+            "funcion entero suma(entero a, entero b)"
+        );
+        assert_eq!(symbols[1]["children"].as_array().map(Vec::len), Some(2));
+
+        let found = compute_workspace_symbols(&index, "CIRC");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0]["containerName"], "Forma");
+        assert_eq!(found[0]["location"]["uri"], mates_uri.as_str());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_diagnosticos_con_imports() {
+        let (dir, mut index, main_uri, mates_uri) = proyecto("diag");
+
+        let main = "importar \"mates.nv\";\nentero total = mates_suma(1, 2);\nimprimir(total);\n";
+        let (diags, types) = analyze_in(&index, main, &main_uri);
+        assert!(diags.iter().all(|d| d["severity"] != 1), "{:?}", diags);
+        assert_eq!(
+            types.get("total").map(|t| t.to_string()),
+            Some("entero".to_string())
+        );
+
+        // Lo que el editor tiene abierto manda sobre el disco.
+        let con_resta = "funcion entero resta(entero a, entero b) {\n    retornar a - b;\n}\n";
+        index.update(&mates_uri, con_resta);
+        let main = "importar \"mates.nv\";\nimprimir(mates_resta(3, 1));\n";
+        let (diags, _) = analyze_in(&index, main, &main_uri);
+        assert!(diags.iter().all(|d| d["severity"] != 1), "{:?}", diags);
+
+        // Un módulo que no compila se marca en su `importar`.
+        index.update(
+            &mates_uri,
+            "funcion entero resta(entero a) {\n    retornar a +;\n}\n",
+        );
+        let (diags, _) = analyze_in(&index, main, &main_uri);
+        let error = diags
+            .iter()
+            .find(|d| d["severity"] == 1)
+            .expect("error del módulo");
+        assert_eq!(error["range"]["start"]["line"], 0, "{:?}", diags);
+        assert!(
+            error["message"].as_str().unwrap().contains("mates.nv:2:"),
+            "{:?}",
+            error
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    const CON_TIPOS: &str = "/// Un punto del plano.\nestructura Punto {\n    /// Coordenada horizontal.\n    x: entero,\n    y: entero,\n}\n\nimpl Punto {\n    funcion entero suma(este, entero extra) {\n        retornar este.x + este.y + extra;\n    }\n}\n\nenum Color {\n    Rojo,\n    Verde(entero),\n}\n\nfuncion entero doble(entero n) {\n    retornar n * 2;\n}\n\nsea p = Punto { x: 1, y: 2 };\nsea total = p.suma(doble(3));\n";
+
+    /// Línea y columna (base 0) de la primera aparición de `needle`.
+    fn posicion(source: &str, needle: &str) -> (usize, usize) {
+        let before = &source[..source.find(needle).expect("texto en el fuente")];
+        let line = before.matches('\n').count();
+        (
+            line,
+            before.rsplit('\n').next().unwrap_or("").chars().count(),
+        )
+    }
+
+    fn indexado(source: &str) -> SymbolIndex {
+        let mut index = SymbolIndex::new(Vec::new());
+        index.update("untitled:tipos", source);
+        index
+    }
+
+    fn etiquetas(items: &[serde_json::Value]) -> Vec<String> {
+        items
+            .iter()
+            .map(|i| {
+                format!(
+                    "{} {}",
+                    i["label"].as_str().unwrap_or(""),
+                    i["detail"].as_str().unwrap_or("")
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_completado_de_miembros_y_variantes() {
+        // Lo que se está escribiendo no compila: `p.` sin nombre.
+        let source = format!("{}imprimir(p.su\n", CON_TIPOS);
+        let index = indexado(&source);
+        let (line, col) = posicion(&source, "p.su");
+        let items = compute_completions(&index, "untitled:tipos", &source, line, col + 4);
+        assert_eq!(
+            etiquetas(&items),
+            vec!["x entero", "y entero", "suma funcion(entero) -> entero"]
+        );
+        assert_eq!(items[2]["kind"], 2);
+
+        // Dentro de una función, con el receptor como parámetro.
+        let source = format!(
+            "{}funcion entero usar(Punto q) {{\n    retornar q.\n}}\n",
+            CON_TIPOS
+        );
+        let index = indexado(&source);
+        let (line, col) = posicion(&source, "q.\n");
+        let items = compute_completions(&index, "untitled:tipos", &source, line, col + 2);
+        assert_eq!(items.len(), 3, "{:?}", items);
+
+        let source = format!("{}sea c = Color::\n", CON_TIPOS);
+        let index = indexado(&source);
+        let (line, col) = posicion(&source, "Color::\n");
+        let items = compute_completions(&index, "untitled:tipos", &source, line, col + 7);
+        assert_eq!(
+            etiquetas(&items),
+            vec!["Rojo Color::Rojo", "Verde Color::Verde(entero)"]
+        );
+
+        // Fuera de un `.` o `::`, las palabras clave de siempre.
+        let items = compute_completions(&index, "untitled:tipos", &source, 0, 0);
+        assert!(items.iter().any(|i| i["label"] == "funcion"));
+    }
+
+    #[test]
+    fn test_hover_con_tipo_y_documentacion() {
+        let index = indexado(CON_TIPOS);
+        let hover = |needle: &str, offset: usize| {
+            let (line, col) = posicion(CON_TIPOS, needle);
+            compute_hover(&index, "untitled:tipos", CON_TIPOS, line, col + offset)
+        };
+        assert_eq!(
+            hover("Punto {\n", 0).as_deref(),
+            Some("```lumen\nestructura Punto\n```\nUn punto del plano.")
+        );
+        assert_eq!(
+            hover("este.x", 5).as_deref(),
+            Some("```lumen\nPunto.x: entero\n```\nCoordenada horizontal.")
+        );
+        assert_eq!(
+            hover("total", 0).as_deref(),
+            Some("```lumen\nentero total\n```")
+        );
+        assert_eq!(
+            hover("p.suma", 0).as_deref(),
+            Some("```lumen\nPunto p\n```")
+        );
+        assert_eq!(
+            hover("doble(3)", 0).as_deref(),
+            Some("```lumen\nfuncion entero doble(entero n)\n```")
+        );
+    }
+
+    #[test]
+    fn test_inlay_hints_de_tipos_y_parametros() {
+        let index = indexado(CON_TIPOS);
+        let hints = compute_inlay_hints(&index, "untitled:tipos", CON_TIPOS);
+        let labels: Vec<&str> = hints.iter().filter_map(|h| h["label"].as_str()).collect();
+        assert_eq!(labels, vec![": Punto", ": entero", "n:"]);
+
+        let (line, col) = posicion(CON_TIPOS, "3))");
+        assert_eq!(
+            hints[2]["position"],
+            serde_json::json!({"line": line, "character": col})
+        );
+        let (line, col) = posicion(CON_TIPOS, "total");
+        assert_eq!(
+            hints[1]["position"],
+            serde_json::json!({"line": line, "character": col + 5})
+        );
+    }
 }
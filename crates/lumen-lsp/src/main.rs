@@ -1,4 +1,4 @@
 // LUMEN LSP Server
 fn main() {
-    lumen_lsp::run_lsp();
+    lumen_lsp::run_lsp(&[]);
 }
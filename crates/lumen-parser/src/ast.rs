@@ -1,5 +1,6 @@
 use lumen_lexer::token::Span;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 pub type Program = Vec<DeclOrStmt>;
 
@@ -502,3 +503,112 @@ impl Expr {
         }
     }
 }
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Assignment { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::IfLet { span, .. }
+            | Stmt::GuardLet { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Break { span, .. }
+            | Stmt::Continue { span, .. }
+            | Stmt::Match { span, .. }
+            | Stmt::Expr { span, .. }
+            | Stmt::FieldAssign { span, .. }
+            | Stmt::ArraySet { span, .. }
+            | Stmt::Block { span, .. }
+            | Stmt::Import { span, .. }
+            | Stmt::ForEach { span, .. }
+            | Stmt::Destructure { span, .. }
+            | Stmt::Posponer { span, .. }
+            | Stmt::TryCatch { span, .. }
+            | Stmt::InlineAsm { span, .. }
+            | Stmt::InlineC { span, .. }
+            | Stmt::InlineRust { span, .. } => *span,
+        }
+    }
+}
+
+impl Decl {
+    pub fn span(&self) -> Span {
+        match self {
+            Decl::Variable { span, .. }
+            | Decl::Destructure { span, .. }
+            | Decl::Function { span, .. }
+            | Decl::Struct { span, .. }
+            | Decl::Enum { span, .. }
+            | Decl::Const { span, .. }
+            | Decl::Rasgo { span, .. }
+            | Decl::ImplRasgo { span, .. } => *span,
+        }
+    }
+}
+
+impl DeclOrStmt {
+    pub fn span(&self) -> Span {
+        match self {
+            DeclOrStmt::Decl(decl) => decl.span(),
+            DeclOrStmt::Stmt(stmt) => stmt.span(),
+        }
+    }
+}
+
+/// Cómo se escribe el tipo en LÚMEN; `sea` para el que se infiere.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Numero => f.write_str("numero"),
+            Type::Entero => f.write_str("entero"),
+            Type::Decimal => f.write_str("decimal"),
+            Type::Texto => f.write_str("texto"),
+            Type::Booleano => f.write_str("booleano"),
+            Type::Lista(inner) => write!(f, "lista<{}>", inner),
+            Type::Func {
+                param_types,
+                return_type,
+            } => {
+                f.write_str("funcion(")?;
+                write_list(f, param_types)?;
+                write!(f, ") -> {}", return_type)
+            }
+            Type::Struct(name) if name == "Infer" => f.write_str("sea"),
+            Type::Struct(name) => f.write_str(name),
+            Type::GenericStruct { name, args } => {
+                write!(f, "{}<", name)?;
+                write_list(f, args)?;
+                f.write_str(">")
+            }
+            Type::Resultado { ok, err } => write!(f, "resultado<{}, {}>", ok, err),
+            Type::Opcion(inner) => write!(f, "{}?", inner),
+            Type::Tuple(types) => {
+                f.write_str("(")?;
+                write_list(f, types)?;
+                f.write_str(")")
+            }
+            Type::ImplTrait(name) => write!(f, "impl {}", name),
+            Type::Prestado { inner, mutable } => {
+                f.write_str(if *mutable {
+                    "prestado mut "
+                } else {
+                    "prestado "
+                })?;
+                write!(f, "{}", inner)
+            }
+            Type::Dueno(inner) => write!(f, "dueno {}", inner),
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, types: &[Type]) -> fmt::Result {
+    for (i, t) in types.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", t)?;
+    }
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use lumen_lexer::diagnostic::{Diagnostic, Fix};
 use lumen_lexer::token::Span;
 
 #[derive(Debug, Clone)]
@@ -6,4 +7,16 @@ pub struct ParseError {
     pub message: String,
     pub span: Span,
     pub suggestion: String,
+    /// Correcciones automáticas, cuando el arreglo es evidente (p. ej. el
+    /// `;` que falta).
+    pub fixes: Vec<Fix>,
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(e: &ParseError) -> Self {
+        let mut diag =
+            Diagnostic::error(&e.code, &e.message, e.span).with_suggestion(&e.suggestion);
+        diag.fixes = e.fixes.clone();
+        diag
+    }
 }
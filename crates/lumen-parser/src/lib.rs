@@ -4,4 +4,4 @@ pub mod parser;
 
 pub use ast::*;
 pub use error::ParseError;
-pub use parser::Parser;
+pub use parser::{Parser, TokenRoles};
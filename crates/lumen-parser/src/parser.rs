@@ -1,5 +1,6 @@
 use crate::ast::*;
 use crate::error::ParseError;
+use lumen_lexer::diagnostic::{Edit, Fix};
 use lumen_lexer::token::{Span, Token, TokenKind};
 
 pub struct Parser {
@@ -11,6 +12,23 @@ pub struct Parser {
     pending_greater: bool,
     // Dentro de un arm de `elegir`, `|` separa patrones (OR) — nunca BinOp::BitOr.
     match_arm_pipe: bool,
+    roles: TokenRoles,
+}
+
+/// Cómo se leyeron algunos tokens, por su índice en la entrada del parser.
+/// El formateador lo usa para decidir saltos de línea y espacios sin volver
+/// a adivinar la gramática.
+#[derive(Debug, Clone, Default)]
+pub struct TokenRoles {
+    /// Operadores que se leyeron como binarios (y no como `-` unario o como
+    /// `<`/`>` de argumentos de tipo).
+    pub binary_ops: Vec<usize>,
+    /// `{` y `}` de cada bloque de sentencias, incluidos el de `elegir` y el
+    /// de los campos de una estructura.
+    pub blocks: Vec<(usize, usize)>,
+    /// Primer token de cada sentencia de un bloque o del nivel superior, de
+    /// cada `caso`/`defecto` y de cada campo de una estructura.
+    pub statements: Vec<usize>,
 }
 
 impl Parser {
@@ -23,15 +41,24 @@ impl Parser {
             type_params_stack: Vec::new(),
             pending_greater: false,
             match_arm_pipe: false,
+            roles: TokenRoles::default(),
         }
     }
 
-    pub fn parse(mut self) -> (Program, Vec<ParseError>) {
+    pub fn parse(self) -> (Program, Vec<ParseError>) {
+        let (program, errors, _) = self.parse_with_roles();
+        (program, errors)
+    }
+
+    /// Como `parse`, y además el papel de los tokens que interesan al
+    /// formateador.
+    pub fn parse_with_roles(mut self) -> (Program, Vec<ParseError>, TokenRoles) {
         let mut program = Vec::new();
         while !self.is_at_end() {
             if self.check(&[TokenKind::Eof]) {
                 break;
             }
+            self.roles.statements.push(self.pos);
             match self.parse_decl_or_stmt() {
                 Some(node) => program.push(node),
                 None => {
@@ -41,7 +68,7 @@ impl Parser {
                 }
             }
         }
-        (program, self.errors)
+        (program, self.errors, self.roles)
     }
 
     fn parse_decl_or_stmt(&mut self) -> Option<DeclOrStmt> {
@@ -347,6 +374,9 @@ impl Parser {
             params.push(self.parse_param()?);
             while self.check(&[TokenKind::Comma]) {
                 self.advance();
+                if self.check(&[TokenKind::RightParen]) {
+                    break;
+                }
                 params.push(self.parse_param()?);
             }
         }
@@ -399,6 +429,7 @@ impl Parser {
             );
             return None;
         }
+        let open = self.pos;
         self.advance();
 
         let mut fields = Vec::new();
@@ -407,6 +438,7 @@ impl Parser {
                 break;
             }
 
+            self.roles.statements.push(self.pos);
             let field_start = self.peek().span;
             let field_name = self.expect_field_name()?;
 
@@ -451,6 +483,7 @@ impl Parser {
             );
             return None;
         }
+        self.roles.blocks.push((open, self.pos));
         self.advance();
 
         Some(Decl::Struct {
@@ -1174,12 +1207,14 @@ impl Parser {
         if !self.check(&[TokenKind::LeftBrace]) {
             return None;
         }
+        let open = self.pos;
         self.advance();
         let saved = self.no_struct_init;
         while !self.check(&[TokenKind::RightBrace]) && !self.is_at_end() {
             if self.check(&[TokenKind::Eof]) {
                 break;
             }
+            self.roles.statements.push(self.pos);
             match self.parse_decl_or_stmt() {
                 Some(node) => stmts.push(node),
                 None => {
@@ -1197,6 +1232,7 @@ impl Parser {
             );
             return Some(stmts);
         }
+        self.roles.blocks.push((open, self.pos));
         self.advance();
         Some(stmts)
     }
@@ -1486,6 +1522,7 @@ impl Parser {
             );
             return None;
         }
+        let open = self.pos;
         self.advance();
 
         let mut arms = Vec::new();
@@ -1496,6 +1533,14 @@ impl Parser {
                 break;
             }
 
+            if self.check(&[
+                TokenKind::Caso,
+                TokenKind::Case,
+                TokenKind::Defecto,
+                TokenKind::Default,
+            ]) {
+                self.roles.statements.push(self.pos);
+            }
             if self.check(&[TokenKind::Defecto, TokenKind::Default]) {
                 let def_start = self.peek().span;
                 self.advance();
@@ -1631,6 +1676,7 @@ impl Parser {
                 span: Span::merge(&start, &self.previous().span),
             });
         }
+        self.roles.blocks.push((open, self.pos));
         self.advance();
 
         Some(Stmt::Match {
@@ -1840,7 +1886,7 @@ impl Parser {
     fn parse_logical_or(&mut self) -> Option<Expr> {
         let mut left = self.parse_logical_and()?;
         while self.check(&[TokenKind::OrOr]) {
-            self.advance();
+            self.advance_operator();
             let right = self.parse_logical_and()?;
             let span = Span::merge(&left.span(), &right.span());
             left = Expr::Binary {
@@ -1857,7 +1903,7 @@ impl Parser {
     fn parse_logical_and(&mut self) -> Option<Expr> {
         let mut left = self.parse_comparison()?;
         while self.check(&[TokenKind::AndAnd]) {
-            self.advance();
+            self.advance_operator();
             let right = self.parse_comparison()?;
             let span = Span::merge(&left.span(), &right.span());
             left = Expr::Binary {
@@ -1906,7 +1952,7 @@ impl Parser {
                 TokenKind::Ampersand => BinOp::BitAnd,
                 _ => unreachable!(),
             };
-            self.advance();
+            self.advance_operator();
             let right = self.parse_addition()?;
             let span = Span::merge(&left.span(), &right.span());
             left = Expr::Binary {
@@ -1937,7 +1983,7 @@ impl Parser {
                 TokenKind::Caret => BinOp::BitXor,
                 _ => unreachable!(),
             };
-            self.advance();
+            self.advance_operator();
             let right = self.parse_shift()?;
             let span = Span::merge(&left.span(), &right.span());
             left = Expr::Binary {
@@ -1959,7 +2005,7 @@ impl Parser {
                 TokenKind::ShiftRight => BinOp::ShiftRight,
                 _ => unreachable!(),
             };
-            self.advance();
+            self.advance_operator();
             let right = self.parse_multiplication()?;
             let span = Span::merge(&left.span(), &right.span());
             left = Expr::Binary {
@@ -1982,7 +2028,7 @@ impl Parser {
                 TokenKind::Percent => BinOp::Mod,
                 _ => unreachable!(),
             };
-            self.advance();
+            self.advance_operator();
             let right = self.parse_unary()?;
             let span = Span::merge(&left.span(), &right.span());
             left = Expr::Binary {
@@ -2064,6 +2110,9 @@ impl Parser {
                                     args.push(self.parse_expression()?);
                                     while self.check(&[TokenKind::Comma]) {
                                         self.advance();
+                                        if self.check(&[TokenKind::RightParen]) {
+                                            break;
+                                        }
                                         args.push(self.parse_expression()?);
                                     }
                                 }
@@ -2156,6 +2205,9 @@ impl Parser {
                     args.push(self.parse_expression()?);
                     while self.check(&[TokenKind::Comma]) {
                         self.advance();
+                        if self.check(&[TokenKind::RightParen]) {
+                            break;
+                        }
                         args.push(self.parse_expression()?);
                     }
                 }
@@ -2546,6 +2598,9 @@ impl Parser {
             params.push(self.parse_param()?);
             while self.check(&[TokenKind::Comma]) {
                 self.advance();
+                if self.check(&[TokenKind::RightParen]) {
+                    break;
+                }
                 params.push(self.parse_param()?);
             }
         }
@@ -2666,6 +2721,9 @@ impl Parser {
                     args.push(self.parse_expression()?);
                     while self.check(&[TokenKind::Comma]) {
                         self.advance();
+                        if self.check(&[TokenKind::RightParen]) {
+                            break;
+                        }
                         args.push(self.parse_expression()?);
                     }
                 }
@@ -2696,6 +2754,9 @@ impl Parser {
                 args.push(self.parse_expression()?);
                 while self.check(&[TokenKind::Comma]) {
                     self.advance();
+                    if self.check(&[TokenKind::RightParen]) {
+                        break;
+                    }
                     args.push(self.parse_expression()?);
                 }
             }
@@ -2724,6 +2785,9 @@ impl Parser {
                     args.push(self.parse_expression()?);
                     while self.check(&[TokenKind::Comma]) {
                         self.advance();
+                        if self.check(&[TokenKind::RightParen]) {
+                            break;
+                        }
                         args.push(self.parse_expression()?);
                     }
                 }
@@ -3373,6 +3437,12 @@ impl Parser {
 
     // --- Helpers ---
 
+    /// `advance` de un operador binario, que queda anotado en `roles`.
+    fn advance_operator(&mut self) {
+        self.roles.binary_ops.push(self.pos);
+        self.advance();
+    }
+
     fn advance(&mut self) -> Option<Token> {
         if self.is_at_end() {
             return self.tokens.get(self.pos).cloned();
@@ -3406,27 +3476,15 @@ impl Parser {
             let kind = &self.tokens[i].kind;
             match kind {
                 TokenKind::LeftBrace => brace_depth += 1,
-                TokenKind::RightBrace => {
-                    if brace_depth > 0 {
-                        brace_depth -= 1;
-                    }
-                }
+                TokenKind::RightBrace if brace_depth > 0 => brace_depth -= 1,
                 TokenKind::LeftParen => paren_depth += 1,
-                TokenKind::RightParen => {
-                    if paren_depth > 0 {
-                        paren_depth -= 1;
-                    }
-                }
+                TokenKind::RightParen if paren_depth > 0 => paren_depth -= 1,
                 TokenKind::LeftBracket => bracket_depth += 1,
-                TokenKind::RightBracket => {
-                    if bracket_depth > 0 {
-                        bracket_depth -= 1;
-                    }
-                }
-                TokenKind::Semicolon => {
-                    if brace_depth == 0 && paren_depth == 0 && bracket_depth == 0 {
-                        return false;
-                    }
+                TokenKind::RightBracket if bracket_depth > 0 => bracket_depth -= 1,
+                TokenKind::Semicolon
+                    if brace_depth == 0 && paren_depth == 0 && bracket_depth == 0 =>
+                {
+                    return false;
                 }
                 _ if token_matches(kind, &TokenKind::Sino)
                     && brace_depth == 0
@@ -3725,11 +3783,13 @@ impl Parser {
 
     fn expect_semicolon(&mut self) {
         if !self.check(&[TokenKind::Semicolon]) {
-            self.error(
+            let span = self.previous().span;
+            self.error_with_fix(
                 "E012",
                 "Se esperaba ';'",
-                self.previous().span,
+                span,
                 "Agrega ';' al final de la declaración",
+                Fix::new("Agregar ';'", vec![Edit::insert_after(span, ";")]),
             );
         } else {
             self.advance();
@@ -3748,9 +3808,24 @@ impl Parser {
             message: message.into(),
             span,
             suggestion: suggestion.into(),
+            fixes: Vec::new(),
         });
     }
 
+    fn error_with_fix(
+        &mut self,
+        code: &str,
+        message: impl Into<String>,
+        span: Span,
+        suggestion: impl Into<String>,
+        fix: Fix,
+    ) {
+        self.error(code, message, span, suggestion);
+        if let Some(e) = self.errors.last_mut() {
+            e.fixes.push(fix);
+        }
+    }
+
     fn synchronize(&mut self) {
         self.advance();
         while !self.is_at_end() {
@@ -4855,4 +4930,40 @@ para a en nums {
             panic!("Expected Variable");
         }
     }
+
+    #[test]
+    fn test_coma_final_en_llamadas_y_parametros() {
+        let source = "funcion entero f(entero a, entero b,) { retornar a; }
+imprimir(f(1, 2,));
+x.m(
+    1,
+    2,
+);
+Opcion::Algun(3,);";
+        let (program, errors) = parse(source);
+        assert!(errors.is_empty(), "Parse errors: {:?}", errors);
+        assert_eq!(program.len(), 4);
+        assert!(!parse("f(,);").1.is_empty());
+    }
+
+    #[test]
+    fn test_punto_y_coma_faltante_tiene_arreglo() {
+        let source = "numero x = 10\nimprimir(x);";
+        let (_, errors) = parse(source);
+        let e = errors.iter().find(|e| e.code == "E012").expect("E012");
+        let fixed = lumen_lexer::diagnostic::apply_edits(source, &e.fixes[0].edits);
+        assert_eq!(fixed, "numero x = 10;\nimprimir(x);");
+        assert!(parse(&fixed).1.is_empty());
+    }
+
+    #[test]
+    fn test_token_roles() {
+        let (tokens, _) = Lexer::new("lista<entero> a = -b * 2;\nsi a < 3 { f(a); }").tokenize();
+        let (_, errors, roles) = Parser::new(tokens).parse_with_roles();
+        assert!(errors.is_empty());
+        // `*` y el `<` de la comparación; no el `-` unario ni los de `lista<entero>`.
+        assert_eq!(roles.binary_ops, vec![8, 13]);
+        assert_eq!(roles.blocks, vec![(15, 21)]);
+        assert_eq!(roles.statements, vec![0, 11, 16]);
+    }
 }
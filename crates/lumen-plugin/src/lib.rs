@@ -121,6 +121,7 @@ mod tests {
                 defaults: vec![],
                 entry: 0,
                 instrs: vec![Instr::Nop, Instr::ConstInt(42), Instr::Nop, Instr::Return],
+                declared: vec![],
             },
         );
         reg.run_ir(&mut program);
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProjectManifest {
@@ -282,6 +282,54 @@ output/
     }
 }
 
+/// `lumen.toml` más cercano subiendo desde `start` (un archivo o directorio).
+pub fn find_manifest(start: &Path) -> Option<PathBuf> {
+    let start = fs::canonicalize(start).unwrap_or_else(|_| start.to_path_buf());
+    let mut dir = if start.is_dir() {
+        Some(start.as_path())
+    } else {
+        start.parent()
+    };
+    while let Some(d) = dir {
+        let candidate = d.join("lumen.toml");
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        dir = d.parent();
+    }
+    None
+}
+
+/// Pares (regla, nivel) de la sección `[lint]` del manifiesto:
+///
+/// ```toml
+/// [lint]
+/// parametro_sin_usar = "desactivado"
+/// sombreado = "error"
+/// ```
+pub fn load_lint_rules(manifest: &Path) -> Result<Vec<(String, String)>, String> {
+    let content = fs::read_to_string(manifest)
+        .map_err(|e| format!("No se pudo leer '{}': {}", manifest.display(), e))?;
+    let value: toml::Value = toml::from_str(&content)
+        .map_err(|e| format!("TOML inválido en '{}': {}", manifest.display(), e))?;
+    let Some(section) = value.get("lint") else {
+        return Ok(Vec::new());
+    };
+    let table = section
+        .as_table()
+        .ok_or_else(|| "La sección [lint] debe ser una tabla".to_string())?;
+    table
+        .iter()
+        .map(|(rule, level)| match level.as_str() {
+            Some(level) => Ok((rule.clone(), level.to_string())),
+            None => Err(format!(
+                "[lint] {}: el nivel debe ser un texto (\"error\", \"advertencia\", \"info\" o \"desactivado\")",
+                rule
+            )),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -308,4 +356,25 @@ mod tests {
         assert!(src.contains("tensor_autograd_nuevo"));
         let _ = fs::remove_dir_all(&tmp);
     }
+
+    #[test]
+    fn test_reglas_de_lint() {
+        let tmp = env::temp_dir().join("test_lumen_project_lint");
+        let _ = fs::remove_dir_all(&tmp);
+        fs::create_dir_all(tmp.join("src")).unwrap();
+        fs::write(
+            tmp.join("lumen.toml"),
+            "[proyecto]\nnombre = \"x\"\n\n[lint]\nsombreado = \"error\"\n",
+        )
+        .unwrap();
+        fs::write(tmp.join("src/main.nv"), "").unwrap();
+        let manifest = find_manifest(&tmp.join("src/main.nv")).unwrap();
+        assert_eq!(
+            load_lint_rules(&manifest).unwrap(),
+            vec![("sombreado".to_string(), "error".to_string())]
+        );
+        fs::write(tmp.join("lumen.toml"), "[lint]\nsombreado = 3\n").unwrap();
+        assert!(load_lint_rules(&manifest).is_err());
+        let _ = fs::remove_dir_all(&tmp);
+    }
 }
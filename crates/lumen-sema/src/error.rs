@@ -1,3 +1,4 @@
+use lumen_lexer::diagnostic::Diagnostic;
 use lumen_lexer::token::Span;
 
 #[derive(Debug, Clone)]
@@ -7,3 +8,9 @@ pub struct SemError {
     pub span: Span,
     pub suggestion: String,
 }
+
+impl From<&SemError> for Diagnostic {
+    fn from(e: &SemError) -> Self {
+        Diagnostic::error(&e.code, &e.message, e.span).with_suggestion(&e.suggestion)
+    }
+}
@@ -1,7 +1,9 @@
 pub mod error;
+pub mod lint;
 pub mod loader;
 pub mod sema;
 
 pub use error::SemError;
+pub use lint::{Lint, LintConfig, Linter, Severity};
 pub use loader::{ModuleError, ModuleLoader};
-pub use sema::{SemanticAnalyzer, TypeInfo};
+pub use sema::{ModuleAnalysis, SemanticAnalyzer, TypeInfo};
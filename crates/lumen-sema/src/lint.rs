@@ -0,0 +1,1602 @@
+//! Análisis estático (`lumen lint`) sobre el AST ya validado por `sema`.
+//!
+//! Las reglas no rechazan el programa: señalan código que compila pero que
+//! probablemente está mal. Cada regla tiene un código `Lxxx`, un nombre que
+//! se usa en la sección `[lint]` de `lumen.toml` y una severidad por defecto
+//! que el proyecto puede cambiar o desactivar.
+
+use std::collections::{HashMap, HashSet};
+
+pub use lumen_lexer::diagnostic::Severity;
+use lumen_lexer::diagnostic::{Diagnostic, Edit, Fix, Label};
+use lumen_lexer::ident;
+use lumen_lexer::token::{Span, Token, TokenKind};
+use lumen_lexer::Lexer;
+use lumen_parser::ast::*;
+
+/// Un hallazgo del linter. Mismos campos que `SemError` más la regla y su
+/// severidad.
+#[derive(Debug, Clone)]
+pub struct Lint {
+    pub code: String,
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub suggestion: String,
+    /// Otros tramos relacionados (la variable ocultada, el caso anterior...).
+    pub secondary: Vec<Label>,
+    pub fixes: Vec<Fix>,
+}
+
+impl Lint {
+    fn with_secondary(mut self, span: Span, message: &str) -> Self {
+        self.secondary.push(Label::new(span, message));
+        self
+    }
+
+    fn with_fix(mut self, fix: Fix) -> Self {
+        self.fixes.push(fix);
+        self
+    }
+}
+
+impl From<&Lint> for Diagnostic {
+    fn from(l: &Lint) -> Self {
+        let mut diag = Diagnostic::new(l.severity, &l.code, &l.message, l.span)
+            .with_suggestion(&l.suggestion)
+            .with_note(format!("regla '{}'", l.rule));
+        diag.secondary = l.secondary.clone();
+        diag.fixes = l.fixes.clone();
+        diag
+    }
+}
+
+pub struct Rule {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub severity: Severity,
+    pub description: &'static str,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        code: "L001",
+        name: "variable_sin_usar",
+        severity: Severity::Warning,
+        description: "Variable declarada que nunca se lee",
+    },
+    Rule {
+        code: "L002",
+        name: "parametro_sin_usar",
+        severity: Severity::Warning,
+        description: "Parámetro que la función nunca lee",
+    },
+    Rule {
+        code: "L003",
+        name: "importacion_sin_usar",
+        severity: Severity::Warning,
+        description: "Módulo importado del que no se usa ningún nombre",
+    },
+    Rule {
+        code: "L004",
+        name: "codigo_inalcanzable",
+        severity: Severity::Warning,
+        description: "Código después de retornar, romper, continuar o lanzar",
+    },
+    Rule {
+        code: "L005",
+        name: "sombreado",
+        severity: Severity::Warning,
+        description: "Variable que oculta otra del mismo nombre en un bloque exterior",
+    },
+    Rule {
+        code: "L006",
+        name: "condicion_constante",
+        severity: Severity::Warning,
+        description: "Condición que siempre vale lo mismo",
+    },
+    Rule {
+        code: "L007",
+        name: "caso_redundante",
+        severity: Severity::Warning,
+        description: "Caso de elegir que un caso anterior ya cubre",
+    },
+    Rule {
+        code: "L008",
+        name: "resultado_ignorado",
+        severity: Severity::Warning,
+        description: "Valor de tipo resultado que se descarta sin comprobar",
+    },
+    Rule {
+        code: "L009",
+        name: "idiomas_mezclados",
+        severity: Severity::Info,
+        description: "Palabras clave en español y en inglés en el mismo archivo",
+    },
+    Rule {
+        code: "L010",
+        name: "escrituras_mezcladas",
+        severity: Severity::Warning,
+        description: "Identificador con letras latinas, griegas o cirílicas mezcladas",
+    },
+    Rule {
+        code: "L011",
+        name: "nombres_confundibles",
+        severity: Severity::Warning,
+        description: "Identificadores distintos que se leen igual",
+    },
+];
+
+pub fn find_rule(name_or_code: &str) -> Option<&'static Rule> {
+    RULES
+        .iter()
+        .find(|r| r.name == name_or_code || r.code.eq_ignore_ascii_case(name_or_code))
+}
+
+/// Severidad de cada regla; `None` = desactivada.
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    overrides: HashMap<&'static str, Option<Severity>>,
+}
+
+impl LintConfig {
+    /// Cambia el nivel de una regla (por nombre o código). Niveles: `error`,
+    /// `advertencia`/`warning`, `info`, `desactivado`/`off`.
+    pub fn set(&mut self, rule: &str, level: &str) -> Result<(), String> {
+        let rule =
+            find_rule(rule).ok_or_else(|| format!("Regla de lint desconocida: '{}'", rule))?;
+        let level = match level.to_lowercase().as_str() {
+            "error" => Some(Severity::Error),
+            "advertencia" | "warning" | "aviso" => Some(Severity::Warning),
+            "info" | "nota" => Some(Severity::Info),
+            "desactivado" | "desactivada" | "off" | "allow" | "no" => None,
+            other => {
+                return Err(format!(
+                    "Nivel desconocido '{}' para '{}' (usa error, advertencia, info o desactivado)",
+                    other, rule.name
+                ))
+            }
+        };
+        self.overrides.insert(rule.code, level);
+        Ok(())
+    }
+
+    /// Aplica pares (regla, nivel), p. ej. los de la sección `[lint]`.
+    pub fn from_rules<'a>(
+        rules: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self, String> {
+        let mut config = LintConfig::default();
+        for (rule, level) in rules {
+            config.set(rule, level)?;
+        }
+        Ok(config)
+    }
+
+    pub fn level(&self, rule: &Rule) -> Option<Severity> {
+        self.overrides
+            .get(rule.code)
+            .copied()
+            .unwrap_or(Some(rule.severity))
+    }
+}
+
+pub struct Linter<'a> {
+    config: &'a LintConfig,
+    result_fns: HashSet<String>,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(config: &'a LintConfig) -> Self {
+        Self {
+            config,
+            result_fns: HashSet::new(),
+        }
+    }
+
+    /// Toma las firmas de otro programa (p. ej. el programa con los imports
+    /// ya aplanados) para saber qué funciones importadas devuelven
+    /// `resultado`.
+    pub fn with_context(mut self, program: &Program) -> Self {
+        collect_result_fns(program, &mut self.result_fns);
+        self
+    }
+
+    pub fn check(&self, program: &Program, source: &str) -> Vec<Lint> {
+        let mut result_fns = self.result_fns.clone();
+        collect_result_fns(program, &mut result_fns);
+        let mut walker = Walker {
+            lints: Vec::new(),
+            scopes: Vec::new(),
+            names: HashSet::new(),
+            result_fns: &result_fns,
+        };
+        walker.walk_program(program);
+        walker.check_imports(program, source);
+        let mut lints = walker.lints;
+        let (tokens, _) = Lexer::new(source).tokenize();
+        if let Some(lint) = mixed_keywords(&tokens) {
+            lints.push(lint);
+        }
+        lints.extend(confusable_identifiers(&tokens));
+        lints.retain_mut(|lint| {
+            let rule = find_rule(&lint.code).expect("regla registrada");
+            match self.config.level(rule) {
+                Some(severity) => {
+                    lint.severity = severity;
+                    true
+                }
+                None => false,
+            }
+        });
+        lints.sort_by_key(|l| (l.span.start.line, l.span.start.col));
+        lints
+    }
+}
+
+fn lint(code: &'static str, message: String, span: Span, suggestion: String) -> Lint {
+    let rule = find_rule(code).expect("regla registrada");
+    Lint {
+        code: code.to_string(),
+        rule: rule.name,
+        severity: rule.severity,
+        message,
+        span,
+        suggestion,
+        secondary: Vec::new(),
+        fixes: Vec::new(),
+    }
+}
+
+fn collect_result_fns(program: &Program, out: &mut HashSet<String>) {
+    for node in program {
+        match node {
+            DeclOrStmt::Decl(Decl::Function {
+                name,
+                return_type: Type::Resultado { .. },
+                ..
+            }) => {
+                out.insert(name.clone());
+            }
+            DeclOrStmt::Decl(Decl::ImplRasgo { methods, .. }) => {
+                for method in methods {
+                    if let Decl::Function {
+                        name,
+                        return_type: Type::Resultado { .. },
+                        ..
+                    } = method
+                    {
+                        out.insert(name.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BindingKind {
+    Variable,
+    Parameter,
+    /// Enlaces de patrones, `atrapar` y lambdas: se resuelven pero no se
+    /// avisa si quedan sin usar ni si ocultan otra variable.
+    Silent,
+}
+
+struct Binding {
+    name: String,
+    span: Span,
+    kind: BindingKind,
+    used: bool,
+}
+
+struct Scope {
+    bindings: Vec<Binding>,
+    /// Cuerpo de función o nivel superior: el sombreado no se busca más allá.
+    boundary: bool,
+}
+
+struct Walker<'r> {
+    lints: Vec<Lint>,
+    scopes: Vec<Scope>,
+    /// Todos los nombres referenciados, para saber qué imports se usan.
+    names: HashSet<String>,
+    result_fns: &'r HashSet<String>,
+}
+
+impl Walker<'_> {
+    fn walk_program(&mut self, program: &Program) {
+        self.push_scope(true);
+        // Primero el nivel superior y después los cuerpos de función, que
+        // pueden leer variables globales declaradas más abajo.
+        let mut functions = Vec::new();
+        self.walk_block_with(program, &mut functions);
+        for decl in functions {
+            self.walk_function(decl, false);
+        }
+        self.pop_scope();
+    }
+
+    fn push_scope(&mut self, boundary: bool) {
+        self.scopes.push(Scope {
+            bindings: Vec::new(),
+            boundary,
+        });
+    }
+
+    fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for b in scope.bindings {
+            if b.used || b.name.starts_with('_') {
+                continue;
+            }
+            match b.kind {
+                BindingKind::Variable => self.lints.push(lint(
+                    "L001",
+                    format!("La variable '{}' se declara pero nunca se lee", b.name),
+                    b.span,
+                    format!("Elimínala o renómbrala a '_{}' si es intencionado", b.name),
+                )),
+                BindingKind::Parameter => self.lints.push(lint(
+                    "L002",
+                    format!("El parámetro '{}' nunca se usa", b.name),
+                    b.span,
+                    format!(
+                        "Elimínalo o renómbralo a '_{}' si la firma lo exige",
+                        b.name
+                    ),
+                )),
+                BindingKind::Silent => {}
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &str, span: Span, kind: BindingKind) {
+        if name == "_" {
+            return;
+        }
+        if kind != BindingKind::Silent && !self.scopes.last().is_some_and(|s| s.boundary) {
+            let mut outer = None;
+            for scope in self.scopes.iter().rev().skip(1) {
+                if let Some(b) = scope
+                    .bindings
+                    .iter()
+                    .find(|b| b.name == name && b.kind != BindingKind::Silent)
+                {
+                    outer = Some(b.span);
+                    break;
+                }
+                if scope.boundary {
+                    break;
+                }
+            }
+            if let Some(outer) = outer {
+                self.lints.push(
+                    lint(
+                        "L005",
+                        format!(
+                            "'{}' oculta la variable declarada en la línea {}",
+                            name, outer.start.line
+                        ),
+                        span,
+                        format!(
+                            "Usa otro nombre o reutiliza la variable '{}' existente",
+                            name
+                        ),
+                    )
+                    .with_secondary(outer, "declarada aquí"),
+                );
+            }
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.bindings.push(Binding {
+                name: name.to_string(),
+                span,
+                kind,
+                used: false,
+            });
+        }
+    }
+
+    fn use_name(&mut self, name: &str) {
+        self.names.insert(name.to_string());
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(b) = scope.bindings.iter_mut().rev().find(|b| b.name == name) {
+                b.used = true;
+                return;
+            }
+        }
+    }
+
+    fn use_type(&mut self, ty: &Type) {
+        match ty {
+            Type::Struct(name) | Type::ImplTrait(name) => {
+                self.names.insert(name.clone());
+            }
+            Type::GenericStruct { name, args } => {
+                self.names.insert(name.clone());
+                args.iter().for_each(|t| self.use_type(t));
+            }
+            Type::Lista(inner) | Type::Opcion(inner) | Type::Dueno(inner) => self.use_type(inner),
+            Type::Prestado { inner, .. } => self.use_type(inner),
+            Type::Resultado { ok, err } => {
+                self.use_type(ok);
+                self.use_type(err);
+            }
+            Type::Tuple(items) => items.iter().for_each(|t| self.use_type(t)),
+            Type::Func {
+                param_types,
+                return_type,
+            } => {
+                param_types.iter().for_each(|t| self.use_type(t));
+                self.use_type(return_type);
+            }
+            _ => {}
+        }
+    }
+
+    fn walk_function(&mut self, decl: &Decl, is_method: bool) {
+        let Decl::Function {
+            params,
+            body,
+            return_type,
+            ..
+        } = decl
+        else {
+            return;
+        };
+        self.use_type(return_type);
+        self.push_scope(true);
+        for (i, p) in params.iter().enumerate() {
+            self.use_type(&p.param_type);
+            if let Some(default) = &p.default {
+                self.walk_expr(default);
+            }
+            // Sin cuerpo (declaraciones) o receptor de un método: el
+            // parámetro lo impone la firma.
+            let kind = if body.is_empty() || (is_method && i == 0) {
+                BindingKind::Silent
+            } else {
+                BindingKind::Parameter
+            };
+            self.declare(&p.name, p.span, kind);
+        }
+        self.walk_block(body);
+        self.pop_scope();
+    }
+
+    fn walk_block(&mut self, stmts: &[DeclOrStmt]) {
+        let mut functions = Vec::new();
+        self.walk_block_with(stmts, &mut functions);
+        for decl in functions {
+            self.walk_function(decl, false);
+        }
+    }
+
+    /// Recorre una secuencia de sentencias; las funciones declaradas en ella
+    /// se dejan en `functions` para recorrerlas al final.
+    fn walk_block_with<'p>(&mut self, stmts: &'p [DeclOrStmt], functions: &mut Vec<&'p Decl>) {
+        let mut terminated = false;
+        let mut reported = false;
+        for node in stmts {
+            if terminated && !reported {
+                self.lints.push(lint(
+                    "L004",
+                    "Código inalcanzable".to_string(),
+                    node.span(),
+                    "Elimina este código o mueve el retorno/salto que lo precede".to_string(),
+                ));
+                // Solo se avisa una vez por bloque.
+                reported = true;
+            }
+            self.walk_node(node, functions);
+            terminated |= terminates(node);
+        }
+    }
+
+    fn walk_node<'p>(&mut self, node: &'p DeclOrStmt, functions: &mut Vec<&'p Decl>) {
+        match node {
+            DeclOrStmt::Decl(decl) => self.walk_decl(decl, functions),
+            DeclOrStmt::Stmt(stmt) => self.walk_stmt(stmt),
+        }
+    }
+
+    fn walk_decl<'p>(&mut self, decl: &'p Decl, functions: &mut Vec<&'p Decl>) {
+        match decl {
+            Decl::Variable {
+                var_type,
+                name,
+                init,
+                span,
+            } => {
+                self.use_type(var_type);
+                if let Some(init) = init {
+                    self.walk_expr(init);
+                }
+                self.declare(name, *span, BindingKind::Variable);
+            }
+            Decl::Destructure { targets, init, .. } => {
+                self.walk_expr(init);
+                for t in targets {
+                    if let Some(ty) = &t.var_type {
+                        self.use_type(ty);
+                    }
+                    self.declare(&t.name, t.span, BindingKind::Variable);
+                }
+            }
+            Decl::Const {
+                var_type, value, ..
+            } => {
+                self.use_type(var_type);
+                self.walk_expr(value);
+            }
+            Decl::Function { .. } => functions.push(decl),
+            Decl::Struct { fields, .. } => {
+                for f in fields {
+                    self.use_type(&f.field_type);
+                }
+            }
+            Decl::Enum { variants, .. } => {
+                for v in variants {
+                    v.types.iter().for_each(|t| self.use_type(t));
+                }
+            }
+            Decl::Rasgo { .. } => {}
+            Decl::ImplRasgo {
+                trait_name,
+                target_type,
+                methods,
+                ..
+            } => {
+                self.names.insert(trait_name.clone());
+                self.use_type(target_type);
+                for m in methods {
+                    self.walk_function(m, true);
+                }
+            }
+        }
+    }
+
+    fn walk_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Assignment { value, .. } => self.walk_expr(value),
+            Stmt::If {
+                condition,
+                then_body,
+                else_body,
+                ..
+            } => {
+                self.check_condition(condition, false);
+                self.walk_expr(condition);
+                self.walk_scoped(then_body);
+                if let Some(else_body) = else_body {
+                    self.walk_scoped(else_body);
+                }
+            }
+            Stmt::IfLet {
+                pattern,
+                value,
+                then_body,
+                else_body,
+                ..
+            } => {
+                self.walk_expr(value);
+                self.push_scope(false);
+                self.walk_pattern(pattern);
+                self.walk_block(then_body);
+                self.pop_scope();
+                if let Some(else_body) = else_body {
+                    self.walk_scoped(else_body);
+                }
+            }
+            Stmt::GuardLet {
+                pattern,
+                value,
+                else_body,
+                ..
+            } => {
+                self.walk_expr(value);
+                self.walk_scoped(else_body);
+                // Los enlaces siguen vivos tras la guarda.
+                self.walk_pattern(pattern);
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                self.check_condition(condition, true);
+                self.walk_expr(condition);
+                self.walk_scoped(body);
+            }
+            Stmt::For {
+                init,
+                condition,
+                update,
+                body,
+                ..
+            } => {
+                self.push_scope(false);
+                let mut functions = Vec::new();
+                self.walk_decl(init, &mut functions);
+                self.check_condition(condition, true);
+                self.walk_expr(condition);
+                self.walk_scoped(body);
+                self.walk_stmt(update);
+                self.pop_scope();
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.walk_expr(value);
+                }
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Match {
+                expr,
+                arms,
+                default,
+                ..
+            } => {
+                self.walk_expr(expr);
+                self.check_match_arms(arms);
+                for arm in arms {
+                    self.push_scope(false);
+                    self.walk_pattern(&arm.value);
+                    for alt in &arm.alt_values {
+                        self.walk_pattern(alt);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.walk_expr(guard);
+                    }
+                    self.walk_block(&arm.body);
+                    self.pop_scope();
+                }
+                if let Some(default) = default {
+                    self.walk_scoped(default);
+                }
+            }
+            Stmt::Expr { expr, span } => {
+                self.check_ignored_result(expr, *span);
+                self.walk_expr(expr);
+            }
+            Stmt::FieldAssign { expr, value, .. } => {
+                self.walk_expr(expr);
+                self.walk_expr(value);
+            }
+            Stmt::ArraySet {
+                arr, index, value, ..
+            } => {
+                self.walk_expr(arr);
+                self.walk_expr(index);
+                self.walk_expr(value);
+            }
+            Stmt::Block { stmts, .. } => self.walk_scoped(stmts),
+            Stmt::Import { .. } => {}
+            Stmt::ForEach {
+                var_name,
+                expr,
+                body,
+                span,
+            } => {
+                self.walk_expr(expr);
+                self.push_scope(false);
+                self.declare(var_name, *span, BindingKind::Variable);
+                self.walk_block(body);
+                self.pop_scope();
+            }
+            Stmt::Destructure { targets, value, .. } => {
+                self.walk_expr(value);
+                for t in targets {
+                    if let Some(ty) = &t.var_type {
+                        self.use_type(ty);
+                    }
+                    self.declare(&t.name, t.span, BindingKind::Variable);
+                }
+            }
+            Stmt::Posponer { body, .. } => self.walk_scoped(body),
+            Stmt::TryCatch {
+                try_body,
+                err_var,
+                catch_body,
+                span,
+            } => {
+                self.walk_scoped(try_body);
+                self.push_scope(false);
+                self.declare(err_var, *span, BindingKind::Silent);
+                self.walk_block(catch_body);
+                self.pop_scope();
+            }
+            Stmt::InlineAsm { .. } | Stmt::InlineC { .. } | Stmt::InlineRust { .. } => {}
+        }
+    }
+
+    fn walk_scoped(&mut self, stmts: &[DeclOrStmt]) {
+        self.push_scope(false);
+        self.walk_block(stmts);
+        self.pop_scope();
+    }
+
+    /// Un patrón compara con valores existentes y, dentro de tuplas,
+    /// estructuras y `algun`/`exito`/`error`, enlaza nombres nuevos.
+    fn walk_pattern(&mut self, pattern: &Expr) {
+        match pattern {
+            Expr::Tuple { items, .. } => items.iter().for_each(|i| self.bind_or_walk(i)),
+            Expr::StructInit {
+                struct_name,
+                fields,
+                ..
+            } => {
+                self.names.insert(struct_name.clone());
+                fields.iter().for_each(|(_, v)| self.bind_or_walk(v));
+            }
+            Expr::Algun { expr, .. } | Expr::Exito { expr, .. } | Expr::Error { expr, .. } => {
+                self.bind_or_walk(expr)
+            }
+            Expr::EnumCtor {
+                enum_name, args, ..
+            } => {
+                self.names.insert(enum_name.clone());
+                args.iter().for_each(|a| self.bind_or_walk(a));
+            }
+            other => self.walk_expr(other),
+        }
+    }
+
+    fn bind_or_walk(&mut self, pattern: &Expr) {
+        match pattern {
+            Expr::Ident { name, span } => self.declare(name, *span, BindingKind::Silent),
+            other => self.walk_pattern(other),
+        }
+    }
+
+    fn walk_exprs(&mut self, exprs: &[Expr]) {
+        for e in exprs {
+            self.walk_expr(e);
+        }
+    }
+
+    fn walk_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Int { .. }
+            | Expr::Float { .. }
+            | Expr::Str { .. }
+            | Expr::Bool { .. }
+            | Expr::Ninguno { .. } => {}
+            Expr::Ident { name, .. } => self.use_name(name),
+            Expr::Binary {
+                left,
+                right,
+                resolved_method,
+                ..
+            } => {
+                if let Some(m) = resolved_method {
+                    self.names.insert(m.clone());
+                }
+                self.walk_expr(left);
+                self.walk_expr(right);
+            }
+            Expr::Unary { operand, .. } => self.walk_expr(operand),
+            Expr::Call {
+                callee,
+                args,
+                type_args,
+                ..
+            } => {
+                self.walk_expr(callee);
+                self.walk_exprs(args);
+                type_args.iter().for_each(|t| self.use_type(t));
+            }
+            Expr::Grouping { expr, .. }
+            | Expr::FieldAccess { expr, .. }
+            | Expr::SafeFieldAccess { expr, .. }
+            | Expr::TupleAccess { expr, .. }
+            | Expr::Exito { expr, .. }
+            | Expr::Error { expr, .. }
+            | Expr::Intentar { expr, .. }
+            | Expr::Algun { expr, .. }
+            | Expr::Esperar { expr, .. }
+            | Expr::Comptime { expr, .. } => self.walk_expr(expr),
+            Expr::Cast {
+                expr, cast_type, ..
+            } => {
+                self.walk_expr(expr);
+                self.use_type(cast_type);
+            }
+            Expr::List { items, .. } | Expr::Tuple { items, .. } => self.walk_exprs(items),
+            Expr::Range { start, end, .. } => {
+                self.walk_expr(start);
+                self.walk_expr(end);
+            }
+            Expr::Index { expr, index, .. } => {
+                self.walk_expr(expr);
+                self.walk_expr(index);
+            }
+            Expr::MethodCall {
+                expr,
+                args,
+                resolved_func,
+                ..
+            } => {
+                if let Some(f) = resolved_func {
+                    self.names.insert(f.clone());
+                }
+                self.walk_expr(expr);
+                self.walk_exprs(args);
+            }
+            Expr::Lambda { params, body, .. } => {
+                self.push_scope(false);
+                for p in params {
+                    self.use_type(&p.param_type);
+                    self.declare(&p.name, p.span, BindingKind::Silent);
+                }
+                self.walk_block(body);
+                self.pop_scope();
+            }
+            Expr::StructInit {
+                struct_name,
+                fields,
+                type_args,
+                ..
+            } => {
+                self.names.insert(struct_name.clone());
+                type_args.iter().for_each(|t| self.use_type(t));
+                for (_, value) in fields {
+                    self.walk_expr(value);
+                }
+            }
+            Expr::EnumCtor {
+                enum_name, args, ..
+            } => {
+                self.names.insert(enum_name.clone());
+                self.walk_exprs(args);
+            }
+            Expr::Ternary {
+                condition,
+                true_branch,
+                false_branch,
+                ..
+            } => {
+                self.check_condition(condition, false);
+                self.walk_expr(condition);
+                self.walk_expr(true_branch);
+                self.walk_expr(false_branch);
+            }
+            Expr::Elvis { expr, default, .. } => {
+                self.walk_expr(expr);
+                self.walk_expr(default);
+            }
+            Expr::Comprehension {
+                expr,
+                var_name,
+                iter,
+                condition,
+                span,
+            } => {
+                self.walk_expr(iter);
+                self.push_scope(false);
+                self.declare(var_name, *span, BindingKind::Silent);
+                if let Some(c) = condition {
+                    self.walk_expr(c);
+                }
+                self.walk_expr(expr);
+                self.pop_scope();
+            }
+            Expr::Query {
+                var_name,
+                source,
+                where_clause,
+                order_by,
+                select_expr,
+                span,
+                ..
+            } => {
+                self.walk_expr(source);
+                self.push_scope(false);
+                self.declare(var_name, *span, BindingKind::Silent);
+                for e in [where_clause, order_by].into_iter().flatten() {
+                    self.walk_expr(e);
+                }
+                self.walk_expr(select_expr);
+                self.pop_scope();
+            }
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Expr, is_loop: bool) {
+        let Some(value) = const_bool(condition) else {
+            return;
+        };
+        // `mientras (verdadero)` es la forma habitual de un bucle infinito.
+        if is_loop && value && matches!(strip_grouping(condition), Expr::Bool { .. }) {
+            return;
+        }
+        let (message, suggestion) = if value {
+            (
+                "La condición siempre es verdadera",
+                "Elimina la condición y deja solo el bloque",
+            )
+        } else if is_loop {
+            (
+                "La condición siempre es falsa: el bucle nunca se ejecuta",
+                "Elimina el bucle o corrige la condición",
+            )
+        } else {
+            (
+                "La condición siempre es falsa: el bloque nunca se ejecuta",
+                "Elimina el bloque o corrige la condición",
+            )
+        };
+        self.lints.push(lint(
+            "L006",
+            message.to_string(),
+            condition.span(),
+            suggestion.to_string(),
+        ));
+    }
+
+    fn check_match_arms(&mut self, arms: &[MatchArm]) {
+        // Patrones ya cubiertos por casos anteriores sin guarda.
+        let mut seen: Vec<(String, Span)> = Vec::new();
+        let mut ranges: Vec<(i64, i64, Span)> = Vec::new();
+        for arm in arms {
+            for pattern in std::iter::once(&arm.value).chain(&arm.alt_values) {
+                let key = pattern_key(pattern);
+                let earlier = key
+                    .as_ref()
+                    .and_then(|k| seen.iter().find(|(s, _)| s == k).map(|(_, l)| *l))
+                    .or_else(|| {
+                        let n = const_int(pattern)?;
+                        ranges
+                            .iter()
+                            .find(|(lo, hi, _)| (*lo..=*hi).contains(&n))
+                            .map(|(_, _, l)| *l)
+                    });
+                if let Some(earlier) = earlier {
+                    self.lints.push(
+                        lint(
+                            "L007",
+                            format!(
+                                "Este caso nunca se elige: el caso de la línea {} ya lo cubre",
+                                earlier.start.line
+                            ),
+                            pattern.span(),
+                            "Elimina el caso o únelo al anterior".to_string(),
+                        )
+                        .with_secondary(earlier, "este caso ya lo cubre"),
+                    );
+                }
+            }
+            if arm.guard.is_some() {
+                continue;
+            }
+            for pattern in std::iter::once(&arm.value).chain(&arm.alt_values) {
+                if let Some(key) = pattern_key(pattern) {
+                    seen.push((key, pattern.span()));
+                }
+                if let Expr::Range {
+                    start,
+                    end,
+                    inclusive,
+                    ..
+                } = pattern
+                {
+                    if let (Some(lo), Some(hi)) = (const_int(start), const_int(end)) {
+                        let hi = if *inclusive { hi } else { hi - 1 };
+                        ranges.push((lo, hi, pattern.span()));
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_ignored_result(&mut self, expr: &Expr, span: Span) {
+        let name = match expr {
+            Expr::Call { callee, .. } => match callee.as_ref() {
+                Expr::Ident { name, .. } => Some(name.as_str()),
+                _ => None,
+            },
+            Expr::MethodCall {
+                resolved_func,
+                method,
+                ..
+            } => resolved_func.as_deref().or(Some(method.as_str())),
+            _ => None,
+        };
+        let Some(name) = name else {
+            return;
+        };
+        if self.result_fns.contains(name) {
+            self.lints.push(lint(
+                "L008",
+                format!(
+                    "Se descarta el resultado de '{}' sin comprobar si falló",
+                    name
+                ),
+                span,
+                "Usa elegir/si sea sobre el valor, o asígnalo a '_' si de verdad no importa"
+                    .to_string(),
+            ));
+        }
+    }
+
+    fn check_imports(&mut self, program: &Program, source: &str) {
+        for node in program {
+            let DeclOrStmt::Stmt(Stmt::Import { path, alias, span }) = node else {
+                continue;
+            };
+            if path == "ingles" || path == "english" {
+                continue;
+            }
+            let prefix = alias.clone().unwrap_or_else(|| module_stem(path));
+            let with_sep = format!("{}_", prefix);
+            let used = self
+                .names
+                .iter()
+                .any(|n| n == &prefix || n.starts_with(&with_sep));
+            if !used {
+                self.lints.push(
+                    lint(
+                        "L003",
+                        format!("No se usa nada del módulo '{}'", path),
+                        *span,
+                        format!(
+                            "Elimina el import; sus nombres se usan con el prefijo '{}_'",
+                            prefix
+                        ),
+                    )
+                    .with_fix(Fix::new(
+                        "Eliminar el import",
+                        vec![remove_line(source, *span)],
+                    )),
+                );
+            }
+        }
+    }
+}
+
+/// Nombre con el que el cargador prefija un módulo: el nombre del archivo sin
+/// extensión (o el del directorio, si es un paquete).
+fn module_stem(path: &str) -> String {
+    let last = path
+        .trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(path);
+    lumen_lexer::ident::sanitize(
+        last.strip_suffix(".nv")
+            .or_else(|| last.strip_suffix(".lumen"))
+            .unwrap_or(last),
+    )
+}
+
+fn terminates(node: &DeclOrStmt) -> bool {
+    match node {
+        DeclOrStmt::Stmt(stmt) => match stmt {
+            Stmt::Return { .. } | Stmt::Break { .. } | Stmt::Continue { .. } => true,
+            Stmt::Expr { expr, .. } => matches!(
+                expr.as_ref(),
+                Expr::Call { callee, .. }
+                    if matches!(callee.as_ref(), Expr::Ident { name, .. } if name == "lanzar" || name == "throw")
+            ),
+            Stmt::Block { stmts, .. } => stmts.last().is_some_and(terminates),
+            Stmt::If {
+                then_body,
+                else_body: Some(else_body),
+                ..
+            } => {
+                then_body.last().is_some_and(terminates) && else_body.last().is_some_and(terminates)
+            }
+            _ => false,
+        },
+        DeclOrStmt::Decl(_) => false,
+    }
+}
+
+fn strip_grouping(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Grouping { expr, .. } => strip_grouping(expr),
+        other => other,
+    }
+}
+
+#[derive(Clone, PartialEq, PartialOrd)]
+enum Const {
+    Num(f64),
+    Str(String),
+    Bool(bool),
+}
+
+/// Valor de una expresión formada solo por literales, si se puede calcular.
+fn const_value(expr: &Expr) -> Option<Const> {
+    match expr {
+        Expr::Int { value, .. } => Some(Const::Num(*value as f64)),
+        Expr::Float { value, .. } => Some(Const::Num(*value)),
+        Expr::Str { value, .. } => Some(Const::Str(value.clone())),
+        Expr::Bool { value, .. } => Some(Const::Bool(*value)),
+        Expr::Grouping { expr, .. } => const_value(expr),
+        Expr::Unary { op, operand, .. } => match (op, const_value(operand)?) {
+            (UnOp::Not, Const::Bool(b)) => Some(Const::Bool(!b)),
+            (UnOp::Negate, Const::Num(n)) => Some(Const::Num(-n)),
+            _ => None,
+        },
+        Expr::Binary {
+            op, left, right, ..
+        } => {
+            let l = const_value(left)?;
+            let r = const_value(right)?;
+            let b = match (op, &l, &r) {
+                (BinOp::And, Const::Bool(a), Const::Bool(b)) => *a && *b,
+                (BinOp::Or, Const::Bool(a), Const::Bool(b)) => *a || *b,
+                (BinOp::Equal, _, _) => l == r,
+                (BinOp::NotEqual, _, _) => l != r,
+                (BinOp::Less, Const::Num(a), Const::Num(b)) => a < b,
+                (BinOp::LessEqual, Const::Num(a), Const::Num(b)) => a <= b,
+                (BinOp::Greater, Const::Num(a), Const::Num(b)) => a > b,
+                (BinOp::GreaterEqual, Const::Num(a), Const::Num(b)) => a >= b,
+                _ => return None,
+            };
+            Some(Const::Bool(b))
+        }
+        _ => None,
+    }
+}
+
+fn const_bool(expr: &Expr) -> Option<bool> {
+    match const_value(expr)? {
+        Const::Bool(b) => Some(b),
+        _ => None,
+    }
+}
+
+fn const_int(expr: &Expr) -> Option<i64> {
+    match strip_grouping(expr) {
+        Expr::Int { value, .. } => Some(*value),
+        Expr::Unary {
+            op: UnOp::Negate,
+            operand,
+            ..
+        } => const_int(operand).map(i64::wrapping_neg),
+        _ => None,
+    }
+}
+
+/// Clave comparable de un patrón literal; `None` si el patrón no es literal.
+fn pattern_key(pattern: &Expr) -> Option<String> {
+    if let Some(n) = const_int(pattern) {
+        return Some(format!("n:{}", n as f64));
+    }
+    match strip_grouping(pattern) {
+        Expr::Float { value, .. } => Some(format!("n:{}", value)),
+        Expr::Str { value, .. } => Some(format!("s:{}", value)),
+        Expr::Bool { value, .. } => Some(format!("b:{}", value)),
+        Expr::Ninguno { .. } => Some("ninguno".to_string()),
+        Expr::EnumCtor {
+            enum_name,
+            variant,
+            args,
+            ..
+        } if args.is_empty() => Some(format!("e:{}::{}", enum_name, variant)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Language {
+    Spanish,
+    English,
+}
+
+fn keyword_language(kind: &TokenKind) -> Option<Language> {
+    use TokenKind as K;
+    match kind {
+        K::Si
+        | K::Sino
+        | K::Mientras
+        | K::Para
+        | K::Funcion
+        | K::Retornar
+        | K::Tipo
+        | K::Verdadero
+        | K::Falso
+        | K::Numero
+        | K::Entero
+        | K::Texto
+        | K::Booleano
+        | K::Imprimir
+        | K::Leer
+        | K::Lista
+        | K::Romper
+        | K::Continuar
+        | K::Elegir
+        | K::Caso
+        | K::Defecto
+        | K::Estructura
+        | K::Importar
+        | K::Como
+        | K::Resultado
+        | K::Exito
+        | K::Intentar
+        | K::En
+        | K::Opcion
+        | K::Algun
+        | K::Ninguno
+        | K::Rasgo
+        | K::Esperar
+        | K::Sea
+        | K::Posponer
+        | K::Consultar
+        | K::Donde
+        | K::OrdenarPor
+        | K::Seleccionar
+        | K::Descendente
+        | K::Ascendente
+        | K::Atrapar
+        | K::Prestado
+        | K::Dueno
+        | K::EnTiempoCompilacion
+        | K::Ensamblador
+        | K::BloqueC
+        | K::BloqueRust
+        | K::Puro
+        | K::GrupoTareas => Some(Language::Spanish),
+        K::If
+        | K::Else
+        | K::While
+        | K::For
+        | K::Function
+        | K::Return
+        | K::True
+        | K::False
+        | K::Number
+        | K::Integer
+        | K::Float
+        | K::String
+        | K::Boolean
+        | K::Print
+        | K::Read
+        | K::Array
+        | K::Break
+        | K::Continue
+        | K::Match
+        | K::Case
+        | K::Default
+        | K::Struct
+        | K::Import
+        | K::As
+        | K::Result
+        | K::Ok
+        | K::Err
+        | K::Try
+        | K::In
+        | K::Option
+        | K::Some
+        | K::None
+        | K::Trait
+        | K::Await
+        | K::Let
+        | K::Defer
+        | K::Query
+        | K::Where
+        | K::OrderBy
+        | K::Select
+        | K::Descending
+        | K::Ascending
+        | K::Catch
+        | K::Borrowed
+        | K::Owner
+        | K::Comptime
+        | K::Asm
+        | K::CBlock
+        | K::RustBlock
+        | K::Pure
+        | K::TaskGroup => Some(Language::English),
+        // `enum`, `const`, `impl`, `async`, `mut`, `decimal` y `error` se
+        // escriben igual en los dos idiomas.
+        _ => None,
+    }
+}
+
+/// Avisa una vez por archivo, en la primera palabra clave del idioma
+/// minoritario.
+/// Borra `span`; si ocupa la línea entera se lleva también el salto de línea.
+fn remove_line(source: &str, span: Span) -> Edit {
+    let whole_line = source
+        .lines()
+        .nth(span.start.line.saturating_sub(1))
+        .is_some_and(|line| {
+            span.start.line == span.end.line
+                && line
+                    .chars()
+                    .take(span.start.col.saturating_sub(1))
+                    .all(char::is_whitespace)
+                && line.chars().skip(span.end.col).all(char::is_whitespace)
+        });
+    if whole_line {
+        Edit {
+            start: lumen_lexer::Pos::new(span.start.line, 1),
+            end: lumen_lexer::Pos::new(span.start.line + 1, 1),
+            replacement: String::new(),
+        }
+    } else {
+        Edit::replace(span, "")
+    }
+}
+
+fn mixed_keywords(tokens: &[Token]) -> Option<Lint> {
+    let mut spanish = 0usize;
+    let mut english = 0usize;
+    let mut first: [Option<Span>; 2] = [None, None];
+    for token in tokens {
+        match keyword_language(&token.kind) {
+            Some(Language::Spanish) => {
+                spanish += 1;
+                first[0].get_or_insert(token.span);
+            }
+            Some(Language::English) => {
+                english += 1;
+                first[1].get_or_insert(token.span);
+            }
+            None => {}
+        }
+    }
+    if spanish == 0 || english == 0 {
+        return None;
+    }
+    let (minority, span, majority) = if english <= spanish {
+        ("inglés", first[1]?, "español")
+    } else {
+        ("español", first[0]?, "inglés")
+    };
+    Some(lint(
+        "L009",
+        format!(
+            "El archivo mezcla palabras clave en español ({}) e inglés ({})",
+            spanish, english
+        ),
+        span,
+        format!(
+            "Escribe las palabras clave en {} como el resto del archivo (esta es la primera en {})",
+            majority, minority
+        ),
+    ))
+}
+
+/// L010 y L011: nombres que engañan a la vista. Un identificador que mezcla
+/// escrituras (`pаso` con `а` cirílica) y dos identificadores distintos con
+/// el mismo esqueleto (`paso` y `pаso`).
+fn confusable_identifiers(tokens: &[Token]) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut mixed = HashSet::new();
+    let mut by_skeleton: HashMap<String, (&str, Span)> = HashMap::new();
+    let mut reported = HashSet::new();
+    for token in tokens {
+        let TokenKind::Ident(name) = &token.kind else {
+            continue;
+        };
+        if name.is_ascii() {
+            by_skeleton
+                .entry(name.clone())
+                .or_insert((name.as_str(), token.span));
+            continue;
+        }
+        let scripts = ident::scripts(name);
+        if scripts.len() > 1 && mixed.insert(name.as_str()) {
+            lints.push(lint(
+                "L010",
+                format!(
+                    "'{}' mezcla letras de escritura {} y {}",
+                    name, scripts[0], scripts[1]
+                ),
+                token.span,
+                format!(
+                    "Las letras de escritura {} se parecen a otras pero son caracteres distintos; escribe el nombre con una sola",
+                    scripts[1]
+                ),
+            ));
+        }
+        let (other, first) = *by_skeleton
+            .entry(ident::skeleton(name))
+            .or_insert((name.as_str(), token.span));
+        if other != name && reported.insert((other, name.as_str())) {
+            lints.push(
+                lint(
+                    "L011",
+                    format!("'{}' se confunde con '{}'", name, other),
+                    token.span,
+                    "Son nombres distintos que se leen igual; renombra uno de los dos".to_string(),
+                )
+                .with_secondary(first, "el otro nombre"),
+            );
+        }
+    }
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SemanticAnalyzer;
+    use lumen_parser::Parser;
+
+    fn lint_with(source: &str, config: &LintConfig) -> Vec<Lint> {
+        let (tokens, lex_errors) = Lexer::new(source).tokenize();
+        assert!(lex_errors.is_empty(), "Lexer errors: {:?}", lex_errors);
+        let (mut program, parse_errors) = Parser::new(tokens).parse();
+        assert!(parse_errors.is_empty(), "Parse errors: {:?}", parse_errors);
+        let errors = SemanticAnalyzer::new().analyze(&mut program);
+        assert!(errors.is_empty(), "Sema errors: {:?}", errors);
+        Linter::new(config).check(&program, source)
+    }
+
+    fn codes(source: &str) -> Vec<String> {
+        lint_with(source, &LintConfig::default())
+            .into_iter()
+            .map(|l| l.code)
+            .collect()
+    }
+
+    #[test]
+    fn test_programa_limpio() {
+        let src = "funcion entero doble(entero n) { retornar n * 2; }
+                   entero x = doble(4);
+                   imprimir(x);";
+        assert!(codes(src).is_empty(), "{:?}", codes(src));
+    }
+
+    #[test]
+    fn test_variables_y_parametros_sin_usar() {
+        let src = "funcion entero f(entero a, entero b, entero _c) {
+                       entero tmp = 1;
+                       entero _ignorada = 2;
+                       retornar a;
+                   }
+                   imprimir(f(1, 2, 3));";
+        let lints = lint_with(src, &LintConfig::default());
+        let found: Vec<_> = lints
+            .iter()
+            .map(|l| (l.code.as_str(), l.span.start.line))
+            .collect();
+        assert_eq!(found, vec![("L002", 1), ("L001", 2)]);
+        assert!(lints[1].message.contains("'tmp'"));
+    }
+
+    #[test]
+    fn test_global_usada_desde_funcion() {
+        let src = "entero contador = 3;
+                   funcion vacio mostrar() { imprimir(contador); }
+                   mostrar();";
+        assert!(codes(src).is_empty(), "{:?}", codes(src));
+    }
+
+    #[test]
+    fn test_codigo_inalcanzable() {
+        let src = "funcion entero f(entero n) {
+                       retornar n;
+                       imprimir(n);
+                       imprimir(n + 1);
+                   }
+                   imprimir(f(1));";
+        assert_eq!(codes(src), vec!["L004"]);
+    }
+
+    #[test]
+    fn test_sombreado() {
+        let src = "funcion vacio f(entero n) {
+                       si (n > 0) {
+                           entero n2 = n;
+                           imprimir(n2);
+                       }
+                       mientras (n > 10) {
+                           entero n = 1;
+                           imprimir(n);
+                       }
+                   }
+                   f(1);";
+        assert_eq!(codes(src), vec!["L005"]);
+    }
+
+    #[test]
+    fn test_condiciones_constantes() {
+        let src = "si (1 > 2) { imprimir(1); }
+                   si (verdadero) { imprimir(2); }
+                   mientras (verdadero) { romper; }";
+        assert_eq!(codes(src), vec!["L006", "L006"]);
+    }
+
+    #[test]
+    fn test_caso_redundante() {
+        let src = "entero x = 3;
+                   elegir (x) {
+                       caso 1: imprimir(1);
+                       caso 0..10: imprimir(2);
+                       caso 1: imprimir(3);
+                       caso 5: imprimir(4);
+                       caso 20: imprimir(5);
+                   }";
+        let lints = lint_with(src, &LintConfig::default());
+        let lines: Vec<_> = lints
+            .iter()
+            .map(|l| (l.code.as_str(), l.span.start.line))
+            .collect();
+        assert_eq!(lines, vec![("L007", 5), ("L007", 6)]);
+        let covered_by: Vec<_> = lints
+            .iter()
+            .map(|l| l.secondary[0].span.start.line)
+            .collect();
+        assert_eq!(covered_by, vec![3, 4]);
+    }
+
+    #[test]
+    fn test_resultado_ignorado() {
+        let src = "funcion resultado<entero, texto> dividir(entero a, entero b) {
+                       si (b == 0) { retornar error(\"div0\"); }
+                       retornar exito(a / b);
+                   }
+                   dividir(1, 0);
+                   resultado<entero, texto> r = dividir(4, 2);
+                   imprimir(r);";
+        assert_eq!(codes(src), vec!["L008"]);
+    }
+
+    #[test]
+    fn test_import_sin_usar() {
+        let program = vec![DeclOrStmt::Stmt(Stmt::Import {
+            path: "matematicas.nv".to_string(),
+            alias: None,
+            span: Span::new(
+                lumen_lexer::token::Pos::new(1, 1),
+                lumen_lexer::token::Pos::new(1, 26),
+            ),
+        })];
+        let source = "importar \"matematicas.nv\";\nimprimir(1);";
+        let lints = Linter::new(&LintConfig::default()).check(&program, source);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].rule, "importacion_sin_usar");
+        assert!(lints[0].suggestion.contains("'matematicas_'"));
+        let fixed = lumen_lexer::diagnostic::apply_edits(source, &lints[0].fixes[0].edits);
+        assert_eq!(fixed, "imprimir(1);");
+        assert_eq!(module_stem("pkgs/red/"), "red");
+    }
+
+    #[test]
+    fn test_idiomas_mezclados() {
+        let src = "entero x = 1;\nif (x > 0) { imprimir(x); }";
+        let lints = lint_with(src, &LintConfig::default());
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].code, "L009");
+        assert_eq!(lints[0].severity, Severity::Info);
+        assert_eq!(lints[0].span.start.line, 2);
+    }
+
+    #[test]
+    fn test_nombres_que_se_confunden() {
+        // La segunda `a` de `pаso` es cirílica.
+        let src =
+            "entero paso = 1;\nentero pаso = 2;\nentero tamaño = paso + pаso;\nimprimir(tamaño);";
+        let lints = lint_with(src, &LintConfig::default());
+        let found: Vec<&str> = lints.iter().map(|l| l.code.as_str()).collect();
+        assert_eq!(found, vec!["L010", "L011"]);
+        assert_eq!(lints[0].span.start.line, 2);
+        assert!(lints[0].message.contains("latina y cirílica"));
+        assert_eq!(lints[1].secondary[0].span.start.line, 1);
+        // Los nombres en español no avisan de nada.
+        assert!(codes("entero año = 1;\nimprimir(año);").is_empty());
+    }
+
+    #[test]
+    fn test_configuracion() {
+        let src = "funcion vacio f(entero n) { entero sin_uso = 1; }\nf(1);";
+        let config =
+            LintConfig::from_rules([("parametro_sin_usar", "desactivado"), ("L001", "error")])
+                .unwrap();
+        let lints = lint_with(src, &config);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(
+            (lints[0].code.as_str(), lints[0].severity),
+            ("L001", Severity::Error)
+        );
+        assert!(LintConfig::from_rules([("no_existe", "error")]).is_err());
+        assert!(LintConfig::from_rules([("sombreado", "fuerte")]).is_err());
+    }
+}
@@ -2,7 +2,8 @@ use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use lumen_lexer::token::Span;
+use lumen_lexer::diagnostic::Diagnostic;
+use lumen_lexer::token::{Pos, Span};
 use lumen_lexer::Lexer;
 use lumen_parser::ast::*;
 use lumen_parser::Parser;
@@ -24,10 +25,57 @@ fn is_virtual(path: &Path) -> bool {
 
 #[derive(Debug)]
 pub enum ModuleError {
-    Io { path: PathBuf, message: String },
-    Lex { path: PathBuf, details: Vec<String> },
-    Parse { path: PathBuf, details: Vec<String> },
-    Circular { path: PathBuf, span: Span },
+    Io {
+        path: PathBuf,
+        message: String,
+    },
+    Lex {
+        path: PathBuf,
+        details: Vec<Diagnostic>,
+    },
+    Parse {
+        path: PathBuf,
+        details: Vec<Diagnostic>,
+    },
+    Circular {
+        path: PathBuf,
+        span: Span,
+    },
+}
+
+impl ModuleError {
+    /// Los errores como diagnósticos. Los de `Lex`/`Parse`/`Io` llevan en
+    /// `file` la ruta del módulo al que se refieren sus posiciones.
+    pub fn to_diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            ModuleError::Io { path, message } => {
+                let at = Span::new(Pos::new(1, 1), Pos::new(1, 1));
+                vec![Diagnostic::error(
+                    "E078",
+                    format!(
+                        "No se pudo cargar el módulo '{}': {}",
+                        path.display(),
+                        message
+                    ),
+                    at,
+                )
+                .with_suggestion("Comprueba la ruta del importar y las carpetas de --lib")
+                .in_file(path.display().to_string())]
+            }
+            ModuleError::Lex { details, .. } | ModuleError::Parse { details, .. } => {
+                details.clone()
+            }
+            ModuleError::Circular { path, span } => vec![Diagnostic::error(
+                "E063",
+                format!(
+                    "Import circular: '{}' ya se está importando",
+                    path.display()
+                ),
+                *span,
+            )
+            .with_suggestion("Revisa las dependencias entre módulos")],
+        }
+    }
 }
 
 pub struct ModuleLoader {
@@ -36,6 +84,9 @@ pub struct ModuleLoader {
     emitted: HashSet<PathBuf>,
     known_prefixes: HashSet<String>,
     memory_files: HashMap<String, String>,
+    /// Contenido que reemplaza al del disco, por ruta canónica: los archivos
+    /// abiertos en el editor y sin guardar.
+    overrides: HashMap<PathBuf, String>,
 }
 
 impl ModuleLoader {
@@ -46,6 +97,7 @@ impl ModuleLoader {
             emitted: HashSet::new(),
             known_prefixes: HashSet::new(),
             memory_files: HashMap::new(),
+            overrides: HashMap::new(),
         }
     }
 
@@ -63,6 +115,12 @@ impl ModuleLoader {
         loader
     }
 
+    /// Usa `source` como contenido de `path` en vez de leerlo del disco.
+    pub fn override_source(&mut self, path: &Path, source: &str) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.overrides.insert(path, source.to_string());
+    }
+
     /// Resuelve un import desde el filesystem virtual en memoria por nombre
     /// base (p. ej. `texto.nv` → `__lumen_mem__/texto.nv`).
     fn resolve_from_memory(&self, name: &str) -> Option<PathBuf> {
@@ -88,6 +146,9 @@ impl ModuleLoader {
     /// Lee el contenido de un módulo: desde memoria si el path es virtual,
     /// desde disco en caso contrario.
     fn read_module_source(&self, path: &Path) -> Result<String, ModuleError> {
+        if let Some(src) = self.overrides.get(path) {
+            return Ok(src.clone());
+        }
         if path.starts_with(VIRTUAL_MEM_PREFIX) {
             if let Some(name) = path.file_name().and_then(|f| f.to_str()) {
                 if let Some(src) = self.memory_files.get(name) {
@@ -110,23 +171,44 @@ impl ModuleLoader {
         source: &str,
         base_path: &Path,
     ) -> Result<Program, ModuleError> {
+        self.resolve_imports_with_origins(source, base_path)
+            .map(|(program, _)| program)
+    }
+
+    /// Como `resolve_imports`, y además la ruta canónica del archivo del que
+    /// sale cada nodo de primer nivel del programa aplanado.
+    pub fn resolve_imports_with_origins(
+        &mut self,
+        source: &str,
+        base_path: &Path,
+    ) -> Result<(Program, Vec<PathBuf>), ModuleError> {
         self.visited.clear();
         self.emitted.clear();
         self.known_prefixes.clear();
-        let program = parse_source(source, base_path)?;
+        let program = parse_source(source, base_path, false)?;
         self.flatten(program, base_path)
     }
 
-    fn flatten(&mut self, program: Program, current_path: &Path) -> Result<Program, ModuleError> {
-        // Canonicalizar para comparar rutas de forma robusta (Windows: fs::canonicalize
-        // añade el prefijo \\?\ — comparar crudo vs canonical nunca da igualdad).
-        let current_norm = if is_virtual(current_path) {
-            // En wasm el fs paniquea: las rutas virtuales se usan tal cual.
-            current_path.to_path_buf()
+    /// Ruta del módulo al que se refiere `importar "<import>"` escrito en
+    /// `importer`, con las mismas reglas que al aplanar.
+    pub fn resolve_import(&self, import: &str, importer: &Path) -> Result<PathBuf, ModuleError> {
+        let importer = canonical_path(importer);
+        let dir = if is_virtual(&importer) || importer.is_dir() {
+            importer.clone()
         } else {
-            fs::canonicalize(current_path).unwrap_or_else(|_| current_path.to_path_buf())
+            importer.parent().unwrap_or(Path::new(".")).to_path_buf()
         };
+        self.resolve_path(import, &dir, &importer)
+    }
+
+    fn flatten(
+        &mut self,
+        program: Program,
+        current_path: &Path,
+    ) -> Result<(Program, Vec<PathBuf>), ModuleError> {
+        let current_norm = canonical_path(current_path);
         let mut result = Vec::new();
+        let mut origins = Vec::new();
         for node in program {
             match node {
                 DeclOrStmt::Stmt(Stmt::Import { path, alias, span }) => {
@@ -161,26 +243,24 @@ impl ModuleLoader {
                         });
                     }
                     let source = self.read_module_source(&resolved)?;
-                    let imported_program = parse_source(&source, &resolved)?;
-                    let _parent = resolved.parent().unwrap_or(Path::new("."));
-                    let flat = self.flatten(imported_program, &resolved)?;
+                    let cached = !self.overrides.contains_key(&resolved);
+                    let imported_program = parse_source(&source, &resolved, cached)?;
+                    let (flat, flat_origins) = self.flatten(imported_program, &resolved)?;
                     self.visited.remove(&resolved);
-                    let prefix = alias.unwrap_or_else(|| {
-                        resolved
-                            .file_stem()
-                            .and_then(|s| s.to_str())
-                            .unwrap_or("module")
-                            .to_string()
-                    });
+                    let prefix = alias.unwrap_or_else(|| module_prefix(&resolved));
                     self.known_prefixes.insert(prefix.clone());
                     let mut prefixed = flat;
                     prefix_program(&mut prefixed, &prefix, &self.known_prefixes);
                     result.extend(prefixed);
+                    origins.extend(flat_origins);
+                }
+                other => {
+                    result.push(other);
+                    origins.push(current_norm.clone());
                 }
-                other => result.push(other),
             }
         }
-        Ok(result)
+        Ok((result, origins))
     }
 
     fn check_package_dir(&self, dir: &Path) -> Option<PathBuf> {
@@ -380,16 +460,45 @@ impl ModuleLoader {
     }
 }
 
-fn parse_source(source: &str, path: &Path) -> Result<Program, ModuleError> {
+/// Prefijo con el que se ven los nombres de un módulo importado sin `como`.
+/// Sale del nombre del archivo, que puede traer guiones o venir en NFD
+/// (macOS): se deja como el identificador que escribe quien importa.
+/// Ruta con la que el loader identifica un archivo, la misma que da en los
+/// orígenes de `resolve_imports_with_origins`.
+pub fn canonical_path(path: &Path) -> PathBuf {
+    // Canonicalizar para comparar rutas de forma robusta (Windows: fs::canonicalize
+    // añade el prefijo \\?\ — comparar crudo vs canonical nunca da igualdad).
+    if is_virtual(path) {
+        // En wasm el fs paniquea: las rutas virtuales se usan tal cual.
+        path.to_path_buf()
+    } else {
+        fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
+pub fn module_prefix(path: &Path) -> String {
+    lumen_lexer::ident::sanitize(
+        path.file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("module"),
+    )
+}
+
+fn parse_source(source: &str, path: &Path, cached: bool) -> Result<Program, ModuleError> {
     // Caché de imports a nivel de proceso: clave = path canónico + mtime.
     // Evita re-lexear/re-parsear los mismos módulos entre invocaciones
     // (lumen serve, LSP, tests). Solo se cachean archivos reales (mtime
-    // presente); los virtuales del playground se parsean siempre.
+    // presente) y leídos del disco; los virtuales del playground y los que
+    // vienen del editor se parsean siempre.
     use std::sync::{Mutex, OnceLock};
     use std::time::SystemTime;
     static SOURCE_CACHE: OnceLock<Mutex<HashMap<PathBuf, (SystemTime, Program)>>> = OnceLock::new();
     let cache = SOURCE_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
-    let mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mtime = if cached {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    } else {
+        None
+    };
     if let Some(mt) = mtime {
         let cache = cache.lock().unwrap();
         if let Some((cm, p)) = cache.get(path) {
@@ -405,12 +514,7 @@ fn parse_source(source: &str, path: &Path) -> Result<Program, ModuleError> {
             path: path.to_path_buf(),
             details: lex_errors
                 .iter()
-                .map(|e| {
-                    format!(
-                        "{} [{}:{}]: {} ({})",
-                        e.code, e.pos.line, e.pos.col, e.message, e.suggestion
-                    )
-                })
+                .map(|e| Diagnostic::from(e).in_file(path.display().to_string()))
                 .collect(),
         });
     }
@@ -421,12 +525,7 @@ fn parse_source(source: &str, path: &Path) -> Result<Program, ModuleError> {
             path: path.to_path_buf(),
             details: parse_errors
                 .iter()
-                .map(|e| {
-                    format!(
-                        "{} [{}:{}]: {} ({})",
-                        e.code, e.span.start.line, e.span.start.col, e.message, e.suggestion
-                    )
-                })
+                .map(|e| Diagnostic::from(e).in_file(path.display().to_string()))
                 .collect(),
         });
     }
@@ -1126,15 +1225,14 @@ fn prefix_type(t: &mut Type, prefix: &str, known: &HashSet<String>) {
             }
             prefix_type(return_type, prefix, known);
         }
-        Type::Struct(name) => {
+        Type::Struct(name)
             if name != "Infer"
                 && name != "Self"
                 && name != "self"
                 && name != "este"
-                && !is_known_prefixed(name, known)
-            {
-                *name = format!("{}_{}", prefix, name);
-            }
+                && !is_known_prefixed(name, known) =>
+        {
+            *name = format!("{}_{}", prefix, name);
         }
         Type::Resultado { ok, err } => {
             prefix_type(ok, prefix, known);
@@ -1182,6 +1280,8 @@ fn is_builtin(name: &str) -> bool {
             | "none"
             | "a_texto"
             | "to_texto"
+            | "lanzar"
+            | "throw"
             | "abs"
             | "absoluto"
             | "min"
@@ -1233,6 +1333,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_prefijo_de_un_modulo_con_nombre_no_ascii() {
+        // Nombre de archivo en NFD y con guion, como puede llegar de macOS.
+        let mut mem = HashMap::new();
+        mem.insert(
+            "cancio\u{301}n-nueva.nv".to_string(),
+            "funcion texto tocar() { retornar \"la\"; }\n".to_string(),
+        );
+        let mut loader = ModuleLoader::with_memory_files(mem);
+        let source = "importar \"cancio\u{301}n-nueva.nv\";\nimprimir(canción_nueva_tocar());\n";
+        let program = loader
+            .resolve_imports(source, Path::new("__lumen_mem__/main.nv"))
+            .expect("debe resolver el import");
+        let names: Vec<&str> = program
+            .iter()
+            .filter_map(|node| match node {
+                DeclOrStmt::Decl(Decl::Function { name, .. }) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, vec!["canción_nueva_tocar"]);
+    }
+
     #[test]
     fn test_memory_loader_fallback_to_disk_still_works() {
         let mut loader = ModuleLoader::with_default_search_paths();
@@ -1254,4 +1377,37 @@ mod tests {
         );
         assert!(matches!(result, Err(ModuleError::Io { .. })));
     }
+
+    #[test]
+    fn test_override_y_origen_de_cada_nodo() {
+        let dir =
+            std::env::temp_dir().join(format!("lumen_loader_override_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("mates.nv"),
+            "funcion entero viejo() { retornar 1; }\n",
+        )
+        .unwrap();
+        let main = dir.join("main.nv");
+
+        let mut loader = ModuleLoader::with_default_search_paths();
+        loader.override_source(
+            &dir.join("mates.nv"),
+            "funcion entero doble(entero x) { retornar x * 2; }\n",
+        );
+        let (program, origins) = loader
+            .resolve_imports_with_origins(
+                "importar \"mates.nv\";\nimprimir(mates_doble(2));\n",
+                &main,
+            )
+            .expect("debe resolver el import");
+        assert_eq!(program.len(), origins.len());
+        assert!(matches!(
+            &program[0],
+            DeclOrStmt::Decl(Decl::Function { name, .. }) if name == "mates_doble"
+        ));
+        assert_eq!(origins[0], fs::canonicalize(dir.join("mates.nv")).unwrap());
+        assert_eq!(origins[1], main);
+        fs::remove_dir_all(&dir).ok();
+    }
 }
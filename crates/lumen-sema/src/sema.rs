@@ -2,6 +2,7 @@ use crate::error::SemError;
 use lumen_lexer::token::{Pos, Span};
 use lumen_parser::ast::*;
 use std::collections::HashMap;
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TypeInfo {
@@ -35,6 +36,58 @@ pub enum TypeInfo {
     Dueno(Box<TypeInfo>),
 }
 
+/// El tipo como se escribe en LÚMEN.
+impl fmt::Display for TypeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeInfo::Numero => f.write_str("numero"),
+            TypeInfo::Entero => f.write_str("entero"),
+            TypeInfo::Decimal => f.write_str("decimal"),
+            TypeInfo::Texto => f.write_str("texto"),
+            TypeInfo::Booleano => f.write_str("booleano"),
+            TypeInfo::Void => f.write_str("nada"),
+            TypeInfo::Lista(inner) => write!(f, "lista<{}>", inner),
+            TypeInfo::Func {
+                param_types,
+                return_type,
+            } => {
+                f.write_str("funcion(")?;
+                write_list(f, param_types)?;
+                write!(f, ") -> {}", return_type)
+            }
+            TypeInfo::Struct { name, .. } | TypeInfo::Enum(name) | TypeInfo::TypeVar(name) => {
+                f.write_str(name)
+            }
+            TypeInfo::Resultado { ok, err } => write!(f, "resultado<{}, {}>", ok, err),
+            TypeInfo::Opcion(inner) => write!(f, "{}?", inner),
+            TypeInfo::Tuple(types) => {
+                f.write_str("(")?;
+                write_list(f, types)?;
+                f.write_str(")")
+            }
+            TypeInfo::Prestado { inner, mutable } => {
+                f.write_str(if *mutable {
+                    "prestado mut "
+                } else {
+                    "prestado "
+                })?;
+                write!(f, "{}", inner)
+            }
+            TypeInfo::Dueno(inner) => write!(f, "dueno {}", inner),
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, types: &[TypeInfo]) -> fmt::Result {
+    for (i, t) in types.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", t)?;
+    }
+    Ok(())
+}
+
 #[derive(Clone)]
 #[allow(dead_code)]
 struct Symbol {
@@ -108,6 +161,104 @@ pub struct SemanticAnalyzer {
     type_param_bounds: HashMap<String, Vec<(String, String)>>,
     errors: Vec<SemError>,
     loop_depth: usize,
+    /// Bloques `posponer` abiertos: dentro, `loop_depth` cuenta solo los
+    /// bucles del propio bloque.
+    defer_depth: usize,
+    /// Métodos de cada tipo para `ModuleAnalysis::methods`.
+    methods: HashMap<String, Vec<(String, TypeInfo)>>,
+    /// Si se anotan tipos de expresiones y nombres (solo en los nodos de los
+    /// que se informa).
+    recording: bool,
+    expr_types: Vec<(Span, TypeInfo)>,
+    bindings: Vec<(String, Span, TypeInfo)>,
+}
+
+/// Resultado de `analyze_module`: los errores y lo que el análisis sabe de
+/// los tipos, para el editor.
+#[derive(Debug, Default)]
+pub struct ModuleAnalysis {
+    pub errors: Vec<SemError>,
+    /// Tipo de cada nombre de primer nivel: variables, funciones,
+    /// estructuras y enums.
+    pub globals: HashMap<String, TypeInfo>,
+    /// Tipo de cada expresión analizada, por su span.
+    pub expr_types: Vec<(Span, TypeInfo)>,
+    /// Cada nombre declarado (variables, parámetros, ligados de patrones y
+    /// de bucles) con el span con que se declaró y su tipo.
+    pub bindings: Vec<(String, Span, TypeInfo)>,
+    /// Campos de cada estructura.
+    pub structs: HashMap<String, Vec<(String, TypeInfo)>>,
+    /// Métodos de cada tipo, de sus `impl`, sin el parámetro `este`.
+    pub methods: HashMap<String, Vec<(String, TypeInfo)>>,
+    /// Variantes de cada enum con los tipos que llevan.
+    pub enums: HashMap<String, Vec<(String, Vec<TypeInfo>)>>,
+}
+
+impl ModuleAnalysis {
+    /// Tipo de la expresión que va de `start` a `end`; sin `end`, el de la
+    /// más amplia que empieza en `start`.
+    pub fn type_at(&self, start: Pos, end: Option<Pos>) -> Option<&TypeInfo> {
+        let starting = self
+            .expr_types
+            .iter()
+            .filter(|(span, _)| span.start == start);
+        match end {
+            Some(end) => starting
+                .filter(|(span, _)| span.end == end)
+                .map(|(_, t)| t)
+                .next_back(),
+            None => starting
+                .max_by_key(|(span, _)| (span.end.line, span.end.col))
+                .map(|(_, t)| t),
+        }
+    }
+
+    /// Miembros de un valor de tipo `t`: campos y métodos.
+    pub fn members(&self, t: &TypeInfo) -> Vec<(String, TypeInfo)> {
+        let t = match t {
+            TypeInfo::Prestado { inner, .. } | TypeInfo::Dueno(inner) => inner.as_ref(),
+            other => other,
+        };
+        let mut members = Vec::new();
+        if let TypeInfo::Struct { name, fields } = t {
+            if fields.is_empty() {
+                members.extend(self.structs.get(name).cloned().unwrap_or_default());
+            } else {
+                members.extend(fields.iter().cloned());
+            }
+        }
+        let builtin: &[(&str, TypeInfo)] = match t {
+            TypeInfo::Lista(inner) => &[
+                (
+                    "agregar",
+                    TypeInfo::Func {
+                        param_types: vec![inner.as_ref().clone()],
+                        return_type: Box::new(TypeInfo::Void),
+                    },
+                ),
+                (
+                    "largo",
+                    TypeInfo::Func {
+                        param_types: Vec::new(),
+                        return_type: Box::new(TypeInfo::Entero),
+                    },
+                ),
+            ],
+            TypeInfo::Texto => &[(
+                "largo",
+                TypeInfo::Func {
+                    param_types: Vec::new(),
+                    return_type: Box::new(TypeInfo::Entero),
+                },
+            )],
+            _ => &[],
+        };
+        members.extend(builtin.iter().map(|(n, t)| (n.to_string(), t.clone())));
+        if let Some(methods) = type_info_to_impl_name(t).and_then(|n| self.methods.get(&n)) {
+            members.extend(methods.iter().cloned());
+        }
+        members
+    }
 }
 
 impl Default for SemanticAnalyzer {
@@ -130,18 +281,106 @@ impl SemanticAnalyzer {
             type_param_bounds: HashMap::new(),
             errors: Vec::new(),
             loop_depth: 0,
+            defer_depth: 0,
+            methods: HashMap::new(),
+            recording: false,
+            expr_types: Vec::new(),
+            bindings: Vec::new(),
         }
     }
 
-    pub fn analyze(mut self, program: &mut Program) -> Vec<SemError> {
+    /// Declara una función que no está en el programa: la registra desde
+    /// Rust quien embebe LÚMEN (`VM::register_host_fn`). Argumentos y
+    /// resultado son dinámicos; con `arity` se comprueba cuántos se pasan.
+    pub fn declare_external(&mut self, name: &str, arity: Option<usize>) {
+        let var_type = match arity {
+            Some(n) => TypeInfo::Func {
+                param_types: vec![TypeInfo::Numero; n],
+                return_type: Box::new(TypeInfo::Numero),
+            },
+            None => TypeInfo::Numero,
+        };
+        let origin = Span::new(Pos::new(0, 0), Pos::new(0, 0));
+        let _ = self.scopes[0].define(name, var_type, origin);
+    }
+
+    pub fn analyze(self, program: &mut Program) -> Vec<SemError> {
+        let report = vec![true; program.len()];
+        self.run(program, &report, false).errors
+    }
+
+    /// Analiza un programa aplanado por el `ModuleLoader` y devuelve solo los
+    /// errores de los nodos de primer nivel con `report[i]` (los del archivo
+    /// que se edita): los de los módulos importados se analizan para conocer
+    /// sus declaraciones. Los tipos de expresiones y nombres también se
+    /// anotan solo en esos nodos, para que sus spans sean todos del archivo.
+    pub fn analyze_module(self, program: &mut Program, report: &[bool]) -> ModuleAnalysis {
+        self.run(program, report, true)
+    }
+
+    /// `record`: si se anotan los tipos de expresiones y nombres, que solo
+    /// quiere el editor.
+    fn run(mut self, program: &mut Program, report: &[bool], record: bool) -> ModuleAnalysis {
         self.collect_enums(program);
         self.collect_traits(program);
         self.collect_structs(program);
         self.collect_impls(program);
         self.collect_functions(program);
-        self.analyze_program(program);
+        for (i, node) in program.iter().enumerate() {
+            let before = self.errors.len();
+            let reported = report.get(i).copied().unwrap_or(true);
+            self.recording = record && reported;
+            self.analyze_decl_or_stmt(node);
+            if !reported {
+                self.errors.truncate(before);
+            }
+        }
+        self.recording = false;
         self.resolve_operator_overloads(program);
-        self.errors
+        let globals = self.globals();
+        ModuleAnalysis {
+            errors: self.errors,
+            globals,
+            expr_types: self.expr_types,
+            bindings: self.bindings,
+            structs: self
+                .structs
+                .into_iter()
+                .map(|(name, (fields, _))| (name, fields))
+                .collect(),
+            methods: self.methods,
+            enums: self.enums,
+        }
+    }
+
+    fn globals(&self) -> HashMap<String, TypeInfo> {
+        let mut globals: HashMap<String, TypeInfo> = self.scopes[0]
+            .symbols
+            .iter()
+            .map(|(name, sym)| (name.clone(), sym.var_type.clone()))
+            .collect();
+        for (name, (ret, params, _, _)) in &self.functions {
+            globals.insert(
+                name.clone(),
+                TypeInfo::Func {
+                    param_types: params.clone(),
+                    return_type: Box::new(ret.clone()),
+                },
+            );
+        }
+        for (name, (fields, _)) in &self.structs {
+            globals.insert(
+                name.clone(),
+                TypeInfo::Struct {
+                    name: name.clone(),
+                    fields: fields.clone(),
+                },
+            );
+        }
+        for name in self.enums.keys() {
+            globals.insert(name.clone(), TypeInfo::Enum(name.clone()));
+        }
+        globals
     }
 
     fn resolve_operator_overloads(&self, program: &mut Program) {
@@ -694,7 +933,8 @@ impl SemanticAnalyzer {
                         ..
                     } = method_decl.clone()
                     {
-                        for p in params.iter_mut() {
+                        let mut receiver = vec![false; params.len()];
+                        for (i, p) in params.iter_mut().enumerate() {
                             if let Type::Struct(s) = &p.param_type {
                                 if s == "Self"
                                     || s == "self"
@@ -704,6 +944,7 @@ impl SemanticAnalyzer {
                                     || s.ends_with("_este")
                                 {
                                     p.param_type = target_type.clone();
+                                    receiver[i] = true;
                                 }
                             }
                         }
@@ -718,6 +959,19 @@ impl SemanticAnalyzer {
                         } else {
                             format!("{}_{}_{}", type_name, trait_name, name)
                         };
+                        let visible_params = params_t
+                            .iter()
+                            .zip(&receiver)
+                            .filter(|(_, r)| !**r)
+                            .map(|(t, _)| t.clone())
+                            .collect();
+                        self.methods.entry(type_name.clone()).or_default().push((
+                            name.clone(),
+                            TypeInfo::Func {
+                                param_types: visible_params,
+                                return_type: Box::new(ret.clone()),
+                            },
+                        ));
                         self.impls
                             .entry((type_name.clone(), trait_name.clone()))
                             .or_default()
@@ -787,12 +1041,6 @@ impl SemanticAnalyzer {
         }
     }
 
-    fn analyze_program(&mut self, program: &Program) {
-        for node in program {
-            self.analyze_decl_or_stmt(node);
-        }
-    }
-
     fn analyze_decl_or_stmt(&mut self, node: &DeclOrStmt) -> TypeInfo {
         match node {
             DeclOrStmt::Decl(d) => self.analyze_decl(d),
@@ -828,10 +1076,7 @@ impl SemanticAnalyzer {
                         });
                     }
                 }
-                if let Err(e) = self
-                    .current_scope()
-                    .define(name, declared_type.clone(), *span)
-                {
+                if let Err(e) = self.define(name, declared_type.clone(), *span) {
                     self.errors.push(e);
                 }
                 declared_type
@@ -882,18 +1127,12 @@ impl SemanticAnalyzer {
                                 suggestion: format!("Usa un tipo '{:?}' para la variable '{}'", element_type, target.name),
                             });
                         }
-                        if let Err(e) =
-                            self.current_scope()
-                                .define(&target.name, declared_type, target.span)
-                        {
+                        if let Err(e) = self.define(&target.name, declared_type, target.span) {
                             self.errors.push(e);
                         }
                     } else {
                         let element_type = tuple_types[i].clone();
-                        if let Err(e) =
-                            self.current_scope()
-                                .define(&target.name, element_type, target.span)
-                        {
+                        if let Err(e) = self.define(&target.name, element_type, target.span) {
                             self.errors.push(e);
                         }
                     }
@@ -912,7 +1151,7 @@ impl SemanticAnalyzer {
             } => {
                 self.scopes.push(Scope::new());
                 for tp in type_params {
-                    if let Err(e) = self.current_scope().define(
+                    if let Err(e) = self.define(
                         tp,
                         TypeInfo::TypeVar(tp.clone()),
                         Span::new(Pos::new(0, 0), Pos::new(0, 0)),
@@ -933,7 +1172,7 @@ impl SemanticAnalyzer {
                         });
                     }
                     let pt = self.resolve_type(p.param_type.clone(), type_params);
-                    if let Err(e) = self.current_scope().define(&p.name, pt, p.span) {
+                    if let Err(e) = self.define(&p.name, pt, p.span) {
                         self.errors.push(e);
                     }
                 }
@@ -985,10 +1224,7 @@ impl SemanticAnalyzer {
                         suggestion: format!("Usa un valor de tipo '{:?}' en lugar de '{:?}'", declared_type, value_type),
                     });
                 }
-                if let Err(e) = self
-                    .current_scope()
-                    .define(name, declared_type.clone(), *span)
-                {
+                if let Err(e) = self.define(name, declared_type.clone(), *span) {
                     self.errors.push(e);
                 }
                 declared_type
@@ -1148,7 +1384,7 @@ impl SemanticAnalyzer {
                         });
                     }
                 } else if matches!(value_type, TypeInfo::Func { .. }) {
-                    if let Err(e) = self.current_scope().define(name, value_type.clone(), *span) {
+                    if let Err(e) = self.define(name, value_type.clone(), *span) {
                         self.errors.push(e);
                     }
                 } else {
@@ -1291,6 +1527,18 @@ impl SemanticAnalyzer {
                 .as_ref()
                 .map(|e| self.analyze_expr(e))
                 .unwrap_or(TypeInfo::Void),
+            Stmt::Break { label: _, span } | Stmt::Continue { label: _, span }
+                if self.loop_depth == 0 && self.defer_depth > 0 =>
+            {
+                self.errors.push(SemError {
+                    code: "E081".to_string(),
+                    message: "romper/continuar no puede salir de un bloque posponer".to_string(),
+                    span: *span,
+                    suggestion: "Usa 'romper' y 'continuar' solo en bucles del propio 'posponer'"
+                        .to_string(),
+                });
+                TypeInfo::Void
+            }
             Stmt::Break { label: _, span } => {
                 if self.loop_depth == 0 {
                     self.errors.push(SemError {
@@ -1367,12 +1615,12 @@ impl SemanticAnalyzer {
                         &arm.value,
                         Expr::Algun { .. } | Expr::Exito { .. } | Expr::Error { .. }
                     );
-                    if !is_range_arm
-                        && !is_pattern_arm
-                        && arm_val_type != expr_type
-                        && !(can_assign(&expr_type, &arm_val_type)
-                            || (expr_type == TypeInfo::Decimal && arm_val_type == TypeInfo::Entero))
-                    {
+                    let compatible = is_range_arm
+                        || is_pattern_arm
+                        || arm_val_type == expr_type
+                        || can_assign(&expr_type, &arm_val_type)
+                        || (expr_type == TypeInfo::Decimal && arm_val_type == TypeInfo::Entero);
+                    if !compatible {
                         self.errors.push(SemError {
                             code: "E056".to_string(),
                             message: format!(
@@ -1478,8 +1726,9 @@ impl SemanticAnalyzer {
                 let value_type = self.analyze_expr(value);
                 match &arr_type {
                     TypeInfo::Lista(inner) => {
-                        if !can_assign(inner, &value_type)
-                            && !(**inner == TypeInfo::Numero || value_type == TypeInfo::Numero)
+                        if !(can_assign(inner, &value_type)
+                            || **inner == TypeInfo::Numero
+                            || value_type == TypeInfo::Numero)
                         {
                             self.errors.push(SemError {
                                 code: "E031".to_string(),
@@ -1508,11 +1757,17 @@ impl SemanticAnalyzer {
             }
             Stmt::Expr { expr, .. } => self.analyze_expr(expr),
             Stmt::Posponer { body, .. } => {
+                // El cuerpo se ejecuta al salir del bloque, fuera de los bucles
+                // que rodean al `posponer`.
+                let loops = std::mem::take(&mut self.loop_depth);
+                self.defer_depth += 1;
                 self.scopes.push(Scope::new());
                 for node in body {
                     self.analyze_decl_or_stmt(node);
                 }
                 self.scopes.pop();
+                self.defer_depth -= 1;
+                self.loop_depth = loops;
                 TypeInfo::Void
             }
             Stmt::TryCatch {
@@ -1528,7 +1783,7 @@ impl SemanticAnalyzer {
                 self.scopes.pop();
 
                 self.scopes.push(Scope::new());
-                if let Err(e) = self.current_scope().define(err_var, TypeInfo::Texto, *span) {
+                if let Err(e) = self.define(err_var, TypeInfo::Texto, *span) {
                     self.errors.push(e);
                 }
                 for node in catch_body {
@@ -1568,7 +1823,7 @@ impl SemanticAnalyzer {
                     }
                 };
                 self.scopes.push(Scope::new());
-                if let Err(e) = self.current_scope().define(var_name, item_type, *span) {
+                if let Err(e) = self.define(var_name, item_type, *span) {
                     self.errors.push(e);
                 }
                 for node in body {
@@ -1641,6 +1896,14 @@ impl SemanticAnalyzer {
     }
 
     fn analyze_expr(&mut self, expr: &Expr) -> TypeInfo {
+        let t = self.analyze_expr_kind(expr);
+        if self.recording {
+            self.expr_types.push((expr.span(), t.clone()));
+        }
+        t
+    }
+
+    fn analyze_expr_kind(&mut self, expr: &Expr) -> TypeInfo {
         match expr {
             Expr::Int { .. } => TypeInfo::Entero,
             Expr::Float { .. } => TypeInfo::Decimal,
@@ -2008,6 +2271,21 @@ impl SemanticAnalyzer {
                                     || callee == "read"
                                 {
                                     TypeInfo::Void
+                                } else if callee == "lanzar" || callee == "throw" {
+                                    if args.len() != 1 {
+                                        self.errors.push(SemError {
+                                            code: "E040".to_string(),
+                                            message: format!(
+                                                "'{}' espera 1 argumento (el mensaje de error), no {}",
+                                                callee,
+                                                args.len()
+                                            ),
+                                            span: *span,
+                                            suggestion: "Usa: lanzar(\"mensaje\") o lanzar(e) dentro de atrapar"
+                                                .to_string(),
+                                        });
+                                    }
+                                    TypeInfo::Void
                                 } else if callee == "abs" || callee == "absoluto" {
                                     if args.len() != 1 {
                                         self.errors.push(SemError {
@@ -2622,6 +2900,10 @@ impl SemanticAnalyzer {
                                     || callee == "__aes_encrypt"
                                     || callee == "__aes_desencriptar"
                                     || callee == "__aes_decrypt"
+                                    || callee == "__aes_gcm_encriptar"
+                                    || callee == "__aes_gcm_encrypt"
+                                    || callee == "__aes_gcm_desencriptar"
+                                    || callee == "__aes_gcm_decrypt"
                                 {
                                     if args.len() < 2 {
                                         self.errors.push(SemError {
@@ -2685,6 +2967,32 @@ impl SemanticAnalyzer {
                                         });
                                     }
                                     TypeInfo::Texto
+                                } else if callee == "__seleccionar" || callee == "__select" {
+                                    // [indice, valor]; el valor es de cualquier tipo.
+                                    TypeInfo::Lista(Box::new(TypeInfo::Numero))
+                                } else if callee == "__supervisor_iniciar"
+                                    || callee == "__supervisor_start"
+                                    || callee == "__cluster_nodos"
+                                    || callee == "__cluster_nodes"
+                                {
+                                    TypeInfo::Lista(Box::new(TypeInfo::Texto))
+                                } else if callee == "__supervisor_reinicios"
+                                    || callee == "__supervisor_restarts"
+                                    || callee == "__atomico_cargar"
+                                    || callee == "__atomic_load"
+                                    || callee == "__atomico_sumar"
+                                    || callee == "__atomic_fetch_add"
+                                    || callee == "__atomico_intercambiar"
+                                    || callee == "__atomic_swap"
+                                {
+                                    TypeInfo::Entero
+                                } else if callee == "__scope_esperar"
+                                    || callee == "__scope_join"
+                                    || callee == "__scope_ejecutar"
+                                    || callee == "__scope_run"
+                                {
+                                    // Lista de resultados o el primer error.
+                                    TypeInfo::Numero
                                 } else if callee == "__leer_archivo_async"
                                     || callee == "__file_read_async"
                                     || callee == "__escribir_archivo_async"
@@ -2713,8 +3021,6 @@ impl SemanticAnalyzer {
                                     || callee == "__par_map"
                                     || callee == "__par_unir"
                                     || callee == "__par_join"
-                                    || callee == "__seleccionar"
-                                    || callee == "__select"
                                     || callee == "__mutex_bloquear"
                                     || callee == "__mutex_lock"
                                     || callee == "__hilo_lanzar"
@@ -2733,14 +3039,28 @@ impl SemanticAnalyzer {
                                     || callee == "__scope_new"
                                     || callee == "__supervisor_nuevo"
                                     || callee == "__supervisor_new"
+                                    || callee == "__supervisor_agregar"
+                                    || callee == "__supervisor_add"
                                     || callee == "__cluster_conectar"
                                     || callee == "__cluster_connect"
+                                    || callee == "__cluster_nodo"
+                                    || callee == "__cluster_node"
+                                    || callee == "__cluster_direccion"
+                                    || callee == "__cluster_addr"
                                     || callee == "__http_servidor"
                                     || callee == "__http_server"
+                                    || callee == "__http_servidor_direccion"
+                                    || callee == "__http_server_addr"
                                     || callee == "__rwlock_nuevo"
                                     || callee == "__rwlock_new"
                                     || callee == "__arc_nuevo"
                                     || callee == "__arc_new"
+                                    || callee == "__atomico_nuevo"
+                                    || callee == "__atomic_new"
+                                    || callee == "__condvar_nuevo"
+                                    || callee == "__condvar_new"
+                                    || callee == "__barrera_nueva"
+                                    || callee == "__barrier_new"
                                 {
                                     TypeInfo::Texto
                                 } else if callee == "__tcp_conectar"
@@ -2751,22 +3071,58 @@ impl SemanticAnalyzer {
                                     || callee == "__channel_send"
                                     || callee == "__actor_enviar"
                                     || callee == "__actor_send"
+                                    || callee == "__actor_monitorear"
+                                    || callee == "__actor_monitor"
+                                    || callee == "__actor_enlazar"
+                                    || callee == "__actor_link"
+                                    || callee == "__actor_detener"
+                                    || callee == "__actor_stop"
+                                    || callee == "__actor_vivo"
+                                    || callee == "__actor_alive"
+                                    || callee == "__supervisor_activo"
+                                    || callee == "__supervisor_active"
+                                    || callee == "__mutex_intentar"
+                                    || callee == "__mutex_try_lock"
+                                    || callee == "__atomico_comparar_intercambiar"
+                                    || callee == "__atomic_compare_exchange"
+                                    || callee == "__condvar_esperar"
+                                    || callee == "__condvar_wait"
+                                    || callee == "__barrera_esperar"
+                                    || callee == "__barrier_wait"
                                     || callee == "__cluster_enviar"
                                     || callee == "__cluster_send"
+                                    || callee == "__cluster_registrar"
+                                    || callee == "__cluster_register"
+                                    || callee == "__cluster_detener"
+                                    || callee == "__cluster_stop"
                                     || callee == "__tcp_aceptar"
                                     || callee == "__tcp_accept"
+                                    || callee == "__http_servidor_detener"
+                                    || callee == "__http_server_stop"
                                 {
                                     TypeInfo::Booleano
                                 } else if callee == "__dormir"
                                     || callee == "__sleep"
                                     || callee == "__scope_cancelar"
                                     || callee == "__scope_cancel"
-                                    || callee == "__supervisor_agregar"
-                                    || callee == "__supervisor_add"
-                                    || callee == "__supervisor_iniciar"
-                                    || callee == "__supervisor_start"
+                                    || callee == "__supervisor_detener"
+                                    || callee == "__supervisor_stop"
                                     || callee == "__arc_asignar"
                                     || callee == "__arc_set"
+                                    || callee == "__mutex_adquirir"
+                                    || callee == "__mutex_acquire"
+                                    || callee == "__mutex_liberar"
+                                    || callee == "__mutex_release"
+                                    || callee == "__atomico_guardar"
+                                    || callee == "__atomic_store"
+                                    || callee == "__condvar_notificar"
+                                    || callee == "__condvar_notify_one"
+                                    || callee == "__condvar_notificar_todos"
+                                    || callee == "__condvar_notify_all"
+                                    || callee == "__http_servidor_esperar"
+                                    || callee == "__http_server_wait"
+                                    || callee == "__cluster_monitorear"
+                                    || callee == "__cluster_monitor"
                                 {
                                     TypeInfo::Void
                                 } else if callee == "__tipo_de" || callee == "__typeof" {
@@ -2880,6 +3236,16 @@ impl SemanticAnalyzer {
                                     || callee == "__http_enviar"
                                     || callee == "__hash_sha256"
                                     || callee == "__hash_sha512"
+                                    || callee == "__hmac_sha256"
+                                    || callee == "__hmac_sha512"
+                                    || callee == "__jwt_codificar"
+                                    || callee == "__jwt_encode"
+                                    || callee == "__jwt_decodificar"
+                                    || callee == "__jwt_decode"
+                                    || callee == "__pbkdf2"
+                                    || callee == "__hkdf"
+                                    || callee == "__bytes_aleatorios"
+                                    || callee == "__random_bytes"
                                 {
                                     TypeInfo::Texto
                                 } else if callee == "__coro_ceder" || callee == "__coro_yield" {
@@ -2981,7 +3347,7 @@ impl SemanticAnalyzer {
                         self.scopes.push(Scope::new());
                         for p in params {
                             let pt = self.type_to_info(p.param_type.clone());
-                            if let Err(e) = self.current_scope().define(&p.name, pt, p.span) {
+                            if let Err(e) = self.define(&p.name, pt, p.span) {
                                 self.errors.push(e);
                             }
                         }
@@ -3032,6 +3398,9 @@ impl SemanticAnalyzer {
                                 }
                                 *return_type
                             }
+                            // Valor dinámico, p. ej. la clausura que devuelve
+                            // otra llamada: se comprueba al ejecutar.
+                            TypeInfo::Numero | TypeInfo::TypeVar(_) => TypeInfo::Numero,
                             _ => {
                                 self.errors.push(SemError {
                                     code: "E058".to_string(),
@@ -3243,7 +3612,7 @@ impl SemanticAnalyzer {
                 self.scopes.push(Scope::new());
                 for p in params {
                     let pt = self.type_to_info(p.param_type.clone());
-                    if let Err(e) = self.current_scope().define(&p.name, pt, p.span) {
+                    if let Err(e) = self.define(&p.name, pt, p.span) {
                         self.errors.push(e);
                     }
                 }
@@ -3675,7 +4044,7 @@ impl SemanticAnalyzer {
                     _ => TypeInfo::Entero,
                 };
                 self.scopes.push(Scope::new());
-                if let Err(e) = self.current_scope().define(var_name, elem_type, *span) {
+                if let Err(e) = self.define(var_name, elem_type, *span) {
                     self.errors.push(e);
                 }
                 if let Some(cond) = condition {
@@ -3700,7 +4069,7 @@ impl SemanticAnalyzer {
                     _ => TypeInfo::Numero,
                 };
                 self.scopes.push(Scope::new());
-                if let Err(e) = self.current_scope().define(var_name, elem_type, *span) {
+                if let Err(e) = self.define(var_name, elem_type, *span) {
                     self.errors.push(e);
                 }
                 if let Some(w) = where_clause {
@@ -3805,12 +4174,21 @@ impl SemanticAnalyzer {
         self.scopes.last_mut().unwrap()
     }
 
+    /// Declara `name` en el ámbito actual y, si se anota, guarda su tipo.
+    fn define(&mut self, name: &str, var_type: TypeInfo, span: Span) -> Result<(), SemError> {
+        if self.recording {
+            self.bindings
+                .push((name.to_string(), span, var_type.clone()));
+        }
+        self.current_scope().define(name, var_type, span)
+    }
+
     // Vincula las variables capturadas por un patrón de if-let / arm de match
     // en el scope actual (tipo dinámico `Numero` — acepta cualquier valor).
     fn bind_pattern_vars(&mut self, pattern: &Expr, span: Span) {
         match pattern {
             Expr::Ident { name, .. } => {
-                let _ = self.current_scope().define(name, TypeInfo::Numero, span);
+                let _ = self.define(name, TypeInfo::Numero, span);
             }
             Expr::Call { args, .. } => {
                 for a in args.iter() {
@@ -4275,6 +4653,21 @@ mod tests {
         assert_eq!(errors[0].code, "E041");
     }
 
+    #[test]
+    fn test_funcion_externa() {
+        let source = "texto s = saludar(\"ana\"); imprimir(s); saludar();";
+        let (tokens, _) = Lexer::new(source).tokenize();
+        let (mut program, _) = Parser::new(tokens).parse();
+        let mut sema = SemanticAnalyzer::new();
+        sema.declare_external("saludar", Some(1));
+        let codes: Vec<_> = sema
+            .analyze(&mut program)
+            .into_iter()
+            .map(|e| e.code)
+            .collect();
+        assert_eq!(codes, vec!["E040"]);
+    }
+
     #[test]
     fn test_undefined_function() {
         let errors = analyze("foo(1);");
@@ -4612,4 +5005,47 @@ elegir (c) {
         assert!(!errors.is_empty());
         assert_eq!(errors[0].code, "E080");
     }
+
+    #[test]
+    fn test_tipos_de_expresiones_y_miembros() {
+        let source = "estructura Punto {\n    x: entero,\n    y: entero,\n}\nimpl Punto {\n    funcion entero suma(este, entero extra) {\n        retornar este.x + este.y + extra;\n    }\n}\nsea p = Punto { x: 1, y: 2 };\nsea total = p.suma(3);\n";
+        let (tokens, _) = Lexer::new(source).tokenize();
+        let (mut program, parse_errors) = Parser::new(tokens).parse();
+        assert!(parse_errors.is_empty(), "{:?}", parse_errors);
+        let report = vec![true; program.len()];
+        let analysis = SemanticAnalyzer::new().analyze_module(&mut program, &report);
+        assert!(analysis.errors.is_empty(), "{:?}", analysis.errors);
+
+        let total = analysis.bindings.iter().find(|(n, _, _)| n == "total");
+        assert_eq!(total.map(|(_, _, t)| t.to_string()), Some("entero".into()));
+        let p = analysis.type_at(Pos::new(11, 13), Some(Pos::new(11, 13)));
+        assert_eq!(p.map(|t| t.to_string()), Some("Punto".into()));
+
+        let members: Vec<String> = analysis
+            .members(p.unwrap())
+            .iter()
+            .map(|(name, t)| format!("{}: {}", name, t))
+            .collect();
+        assert_eq!(
+            members,
+            vec!["x: entero", "y: entero", "suma: funcion(entero) -> entero"]
+        );
+    }
+
+    #[test]
+    fn test_romper_no_sale_de_posponer() {
+        let errors = analyze(
+            "mientras (verdadero) { posponer { romper; } }\n\
+             para i en 0..3 { posponer { continuar; } }",
+        );
+        let codes: Vec<&str> = errors.iter().map(|e| e.code.as_str()).collect();
+        assert_eq!(codes, vec!["E081", "E081"]);
+        assert_eq!(
+            errors[0].message,
+            "romper/continuar no puede salir de un bloque posponer"
+        );
+        // Un bucle dentro del propio bloque sí puede usarlos.
+        let errors = analyze("posponer { mientras (verdadero) { romper; } }");
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
 }
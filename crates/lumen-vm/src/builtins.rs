@@ -0,0 +1,120 @@
+//! Registro de builtins: cada nombre (con sus alias en español e inglés)
+//! apunta a una entrada con la función que lo implementa. La VM lo resuelve
+//! contra la tabla de nombres del bytecode al cargarlo, de modo que `Call`
+//! despacha por índice sin comparar cadenas.
+
+use crate::value::Value;
+use crate::vm::{VmError, VM};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Builtin nativo registrado por quien embebe la VM: recibe los argumentos y
+/// devuelve el valor de la llamada.
+pub type NativeFn = fn(&mut VM, &[Value]) -> Result<Value, VmError>;
+
+/// Como `NativeFn`, pero admite closures que capturan estado del anfitrión.
+/// No recibe la VM: solo ve los argumentos.
+pub type HostFn = Arc<dyn Fn(&[Value]) -> Result<Value, VmError> + Send + Sync>;
+
+/// Builtin interno de la VM: deja su resultado en la pila.
+pub(crate) type CoreFn = fn(&mut VM, Vec<Value>) -> Result<(), VmError>;
+
+#[derive(Clone)]
+pub(crate) enum BuiltinFn {
+    Core(CoreFn),
+    Native(NativeFn),
+    Host(HostFn),
+}
+
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: String,
+    pub aliases: Vec<String>,
+    /// Número de argumentos exigido; `None` acepta cualquier cantidad.
+    pub arity: Option<usize>,
+    pub doc: String,
+    pub(crate) func: BuiltinFn,
+}
+
+#[derive(Clone, Default)]
+pub struct BuiltinRegistry {
+    entries: Vec<Builtin>,
+    by_name: HashMap<String, usize>,
+}
+
+impl BuiltinRegistry {
+    pub(crate) fn from_table(table: Vec<(&'static [&'static str], CoreFn)>) -> Self {
+        let mut registry = Self::default();
+        for (names, func) in table {
+            registry.insert(names, None, String::new(), BuiltinFn::Core(func));
+        }
+        registry
+    }
+
+    /// Registra (o reemplaza) un builtin nativo. El primer nombre es el
+    /// principal y el resto son alias; devuelve el índice de la entrada.
+    pub fn register(
+        &mut self,
+        names: &[&str],
+        arity: Option<usize>,
+        doc: &str,
+        func: NativeFn,
+    ) -> usize {
+        self.insert(names, arity, doc.to_string(), BuiltinFn::Native(func))
+    }
+
+    /// Igual que `register`, con una closure del anfitrión.
+    pub fn register_host(
+        &mut self,
+        names: &[&str],
+        arity: Option<usize>,
+        doc: &str,
+        func: HostFn,
+    ) -> usize {
+        self.insert(names, arity, doc.to_string(), BuiltinFn::Host(func))
+    }
+
+    fn insert(
+        &mut self,
+        names: &[&str],
+        arity: Option<usize>,
+        doc: String,
+        func: BuiltinFn,
+    ) -> usize {
+        let id = self.entries.len();
+        let (name, aliases) = names.split_first().expect("un builtin necesita nombre");
+        self.entries.push(Builtin {
+            name: name.to_string(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            arity,
+            doc,
+            func,
+        });
+        for n in names {
+            self.by_name.insert(n.to_string(), id);
+        }
+        id
+    }
+
+    /// Índice del builtin llamado `name` (nombre principal o alias).
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Builtin> {
+        self.entries.get(id)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Builtin> {
+        self.lookup(name).and_then(|id| self.get(id))
+    }
+
+    /// Builtins vigentes (los reemplazados por `register` no aparecen).
+    pub fn iter(&self) -> impl Iterator<Item = &Builtin> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(id, b)| self.lookup(&b.name) == Some(*id))
+            .map(|(_, b)| b)
+    }
+}
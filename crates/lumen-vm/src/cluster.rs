@@ -0,0 +1,706 @@
+#![cfg(any(feature = "extra", feature = "full"))]
+// Nodos de cluster: VMs que se mandan valores LÚMEN por TCP.
+//
+// Un nodo tiene un nombre único en el proceso, escucha en una dirección y
+// guarda qué actores locales publicó con qué nombre. Al conectar dos nodos se
+// intercambian los nombres (`HOLA`) y desde entonces cada uno puede enviar a
+// `actor@nodo`. Cada conexión tiene un hilo lector que entrega los mensajes en
+// los buzones locales; un hilo por nodo manda un latido a cada vecino, y si de
+// uno no llega nada en `3 × latido` se le da por caído. Los buzones que
+// monitorean el nodo reciben `["nodo_conectado", nombre]` y
+// `["nodo_caido", nombre, motivo]`.
+//
+// En el cable cada trama es `u32` (big endian) con el largo y luego el tipo y
+// su contenido; los valores van con la codificación de `encode_value`.
+
+use crate::value::{FixHasher, Value};
+use crate::vm::{wake_waiters, SharedChannels};
+use im::HashMap as ImMap;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+/// Tamaño máximo de una trama.
+const MAX_FRAME: usize = 16 * 1024 * 1024;
+/// Anidamiento máximo de un valor recibido.
+const MAX_DEPTH: usize = 256;
+/// Plazo para conectar y para el saludo.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const FRAME_HELLO: u8 = 0;
+const FRAME_SEND: u8 = 1;
+const FRAME_HEARTBEAT: u8 = 2;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// ── Codificación de valores ─────────────────────────────────────────
+
+const TAG_VOID: u8 = 0;
+const TAG_INT: u8 = 1;
+const TAG_FLOAT: u8 = 2;
+const TAG_STR: u8 = 3;
+const TAG_BOOL: u8 = 4;
+const TAG_ARRAY: u8 = 5;
+const TAG_FUNC: u8 = 6;
+const TAG_STRUCT: u8 = 7;
+const TAG_ENUM: u8 = 8;
+const TAG_EXITO: u8 = 9;
+const TAG_ERROR: u8 = 10;
+const TAG_NONE: u8 = 11;
+const TAG_SOME: u8 = 12;
+const TAG_TUPLE: u8 = 13;
+const TAG_MAP: u8 = 14;
+
+fn put_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u32).to_be_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_len(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Añade `value` a `out`: una etiqueta de un byte y su contenido; los
+/// enteros y decimales en 8 bytes big endian, los textos y colecciones
+/// precedidos de su largo en `u32`. Las clausuras no se pueden enviar: sus
+/// variables capturadas son celdas de este proceso.
+pub(crate) fn encode_value(value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+    match value {
+        Value::Void => out.push(TAG_VOID),
+        Value::Int(n) => {
+            out.push(TAG_INT);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+        Value::Float(f) => {
+            out.push(TAG_FLOAT);
+            out.extend_from_slice(&f.to_bits().to_be_bytes());
+        }
+        Value::Str(s) => {
+            out.push(TAG_STR);
+            put_str(out, s);
+        }
+        Value::Bool(b) => out.extend_from_slice(&[TAG_BOOL, *b as u8]),
+        Value::Array(items) => {
+            out.push(TAG_ARRAY);
+            put_len(out, items.len());
+            for item in items.iter() {
+                encode_value(item, out)?;
+            }
+        }
+        Value::Func(name) => {
+            out.push(TAG_FUNC);
+            put_str(out, name);
+        }
+        Value::Closure { func, .. } => {
+            return Err(format!(
+                "No se puede enviar la clausura '{}' a otro nodo",
+                func
+            ))
+        }
+        Value::Struct { name, fields } => {
+            out.push(TAG_STRUCT);
+            put_str(out, name);
+            put_len(out, fields.len());
+            for (field, val) in fields {
+                put_str(out, field);
+                encode_value(val, out)?;
+            }
+        }
+        Value::Enum {
+            name,
+            variant,
+            fields,
+        } => {
+            out.push(TAG_ENUM);
+            put_str(out, name);
+            put_str(out, variant);
+            put_len(out, fields.len());
+            for val in fields {
+                encode_value(val, out)?;
+            }
+        }
+        Value::Exito(val) => {
+            out.push(TAG_EXITO);
+            encode_value(val, out)?;
+        }
+        Value::Error(val) => {
+            out.push(TAG_ERROR);
+            encode_value(val, out)?;
+        }
+        Value::Opcion(None) => out.push(TAG_NONE),
+        Value::Opcion(Some(val)) => {
+            out.push(TAG_SOME);
+            encode_value(val, out)?;
+        }
+        Value::Tuple(items) => {
+            out.push(TAG_TUPLE);
+            put_len(out, items.len());
+            for item in items {
+                encode_value(item, out)?;
+            }
+        }
+        Value::Map(map) => {
+            out.push(TAG_MAP);
+            put_len(out, map.len());
+            for (k, v) in map.iter() {
+                encode_value(k, out)?;
+                encode_value(v, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Lector de una trama ya recibida; nunca lee fuera de ella.
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Decoder { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or("Trama truncada")?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    /// Largo de una colección: cada elemento ocupa al menos un byte, así que
+    /// uno mayor que lo que queda de trama es un error y no una reserva enorme.
+    fn len(&mut self) -> Result<usize, String> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        let len = u32::from_be_bytes(buf) as usize;
+        if len > self.bytes.len() - self.pos {
+            return Err("Largo fuera de la trama".to_string());
+        }
+        Ok(len)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| "Texto no UTF-8".to_string())
+    }
+
+    fn values(&mut self, depth: usize) -> Result<Vec<Value>, String> {
+        let len = self.len()?;
+        (0..len).map(|_| self.value(depth)).collect()
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err("Valor demasiado anidado".to_string());
+        }
+        let depth = depth + 1;
+        Ok(match self.byte()? {
+            TAG_VOID => Value::Void,
+            TAG_INT => Value::Int(self.u64()? as i64),
+            TAG_FLOAT => Value::Float(f64::from_bits(self.u64()?)),
+            TAG_STR => Value::str(self.string()?),
+            TAG_BOOL => Value::Bool(self.byte()? != 0),
+            TAG_ARRAY => Value::arr(self.values(depth)?),
+            TAG_FUNC => Value::Func(self.string()?),
+            TAG_STRUCT => {
+                let name = self.string()?;
+                let len = self.len()?;
+                let fields = (0..len)
+                    .map(|_| Ok((self.string()?, self.value(depth)?)))
+                    .collect::<Result<_, String>>()?;
+                Value::Struct { name, fields }
+            }
+            TAG_ENUM => Value::Enum {
+                name: self.string()?,
+                variant: self.string()?,
+                fields: self.values(depth)?,
+            },
+            TAG_EXITO => Value::Exito(Box::new(self.value(depth)?)),
+            TAG_ERROR => Value::Error(Box::new(self.value(depth)?)),
+            TAG_NONE => Value::Opcion(None),
+            TAG_SOME => Value::Opcion(Some(Box::new(self.value(depth)?))),
+            TAG_TUPLE => Value::Tuple(self.values(depth)?),
+            TAG_MAP => {
+                let len = self.len()?;
+                let mut map = ImMap::with_hasher(FixHasher::default());
+                for _ in 0..len {
+                    let k = self.value(depth)?;
+                    map.insert(k, self.value(depth)?);
+                }
+                Value::Map(map)
+            }
+            tag => return Err(format!("Etiqueta de valor desconocida: {}", tag)),
+        })
+    }
+}
+
+/// Lee un valor completo; sobra algo al final, es un error.
+pub(crate) fn decode_value(bytes: &[u8]) -> Result<Value, String> {
+    let mut decoder = Decoder::new(bytes);
+    let value = decoder.value(0)?;
+    if decoder.pos != bytes.len() {
+        return Err("Bytes de más tras el valor".to_string());
+    }
+    Ok(value)
+}
+
+// ── Tramas ──────────────────────────────────────────────────────────
+
+#[derive(Debug, PartialEq)]
+enum Frame {
+    Hello(String),
+    Send(String, Value),
+    Heartbeat,
+}
+
+impl Frame {
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut out = vec![0; 4];
+        match self {
+            Frame::Hello(name) => {
+                out.push(FRAME_HELLO);
+                put_str(&mut out, name);
+            }
+            Frame::Send(dest, value) => {
+                out.push(FRAME_SEND);
+                put_str(&mut out, dest);
+                encode_value(value, &mut out)?;
+            }
+            Frame::Heartbeat => out.push(FRAME_HEARTBEAT),
+        }
+        let len = out.len() - 4;
+        if len > MAX_FRAME {
+            return Err("Mensaje demasiado grande para el cluster".to_string());
+        }
+        out[..4].copy_from_slice(&(len as u32).to_be_bytes());
+        Ok(out)
+    }
+
+    fn read(reader: &mut impl Read) -> io::Result<Frame> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut len = [0; 4];
+        reader.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 || len > MAX_FRAME {
+            return Err(invalid(format!("Largo de trama no válido: {}", len)));
+        }
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body)?;
+        let mut decoder = Decoder::new(&body[1..]);
+        let frame = match body[0] {
+            FRAME_HELLO => Frame::Hello(decoder.string().map_err(invalid)?),
+            FRAME_SEND => {
+                let dest = decoder.string().map_err(invalid)?;
+                let value = decode_value(&decoder.bytes[decoder.pos..]).map_err(invalid)?;
+                return Ok(Frame::Send(dest, value));
+            }
+            FRAME_HEARTBEAT => Frame::Heartbeat,
+            kind => return Err(invalid(format!("Tipo de trama desconocido: {}", kind))),
+        };
+        if decoder.pos != body.len() - 1 {
+            return Err(invalid("Bytes de más en la trama".to_string()));
+        }
+        Ok(frame)
+    }
+}
+
+// ── Nodos ───────────────────────────────────────────────────────────
+
+/// Conexión abierta con otro nodo. `id` distingue una reconexión de la
+/// conexión anterior con el mismo vecino.
+struct Peer {
+    id: u64,
+    stream: Arc<Mutex<TcpStream>>,
+}
+
+#[derive(Default)]
+struct NodeState {
+    /// Nombre publicado → buzón local.
+    names: HashMap<String, String>,
+    peers: HashMap<String, Peer>,
+    monitors: Vec<String>,
+}
+
+struct Node {
+    name: String,
+    addr: SocketAddr,
+    heartbeat: Duration,
+    channels: SharedChannels,
+    state: Mutex<NodeState>,
+    stop: AtomicBool,
+}
+
+fn nodes() -> MutexGuard<'static, HashMap<String, Arc<Node>>> {
+    static NODES: OnceLock<Mutex<HashMap<String, Arc<Node>>>> = OnceLock::new();
+    lock(NODES.get_or_init(Default::default))
+}
+
+fn node(name: &str) -> Result<Arc<Node>, String> {
+    nodes()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("Nodo no encontrado: {}", name))
+}
+
+fn spawn(name: &str, f: impl FnOnce() + Send + 'static) -> io::Result<()> {
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(f)
+        .map(|_| ())
+}
+
+impl Node {
+    /// Deja `msg` en el buzón local `id`, si existe.
+    fn post(&self, id: &str, msg: Value) -> bool {
+        let tx = lock(&self.channels).get(id).and_then(|(tx, _)| tx.clone());
+        let sent = tx.is_some_and(|tx| tx.send(msg).is_ok());
+        wake_waiters(id);
+        sent
+    }
+
+    fn notify(&self, msg: Value) {
+        let monitors = lock(&self.state).monitors.clone();
+        for mailbox in monitors {
+            self.post(&mailbox, msg.clone());
+        }
+    }
+
+    /// Entrega a un nombre publicado en este nodo; los mensajes a nombres
+    /// desconocidos se pierden, como en un buzón remoto que ya no existe.
+    fn deliver(&self, dest: &str, value: Value) -> bool {
+        let mailbox = lock(&self.state).names.get(dest).cloned();
+        mailbox.is_some_and(|mailbox| self.post(&mailbox, value))
+    }
+
+    /// Saludo ya hecho: registra al vecino y atiende sus tramas hasta que
+    /// se corte la conexión. Falso si ya había una conexión con él.
+    fn adopt(self: &Arc<Self>, peer: String, stream: TcpStream) -> io::Result<bool> {
+        static PEER_IDS: AtomicU64 = AtomicU64::new(0);
+        let id = PEER_IDS.fetch_add(1, Ordering::SeqCst);
+        stream.set_read_timeout(Some(self.heartbeat * 3))?;
+        stream.set_write_timeout(Some(self.heartbeat * 3))?;
+        let reader = stream.try_clone()?;
+        {
+            let mut state = lock(&self.state);
+            if self.stop.load(Ordering::SeqCst) || state.peers.contains_key(&peer) {
+                return Ok(false);
+            }
+            state.peers.insert(
+                peer.clone(),
+                Peer {
+                    id,
+                    stream: Arc::new(Mutex::new(stream)),
+                },
+            );
+        }
+        self.notify(Value::arr(vec![
+            Value::str("nodo_conectado"),
+            Value::str(peer.as_str()),
+        ]));
+        let node = self.clone();
+        spawn(&format!("lumen-cluster-{}", peer), move || {
+            node.read_peer(&peer, id, reader)
+        })?;
+        Ok(true)
+    }
+
+    fn read_peer(&self, peer: &str, id: u64, mut reader: TcpStream) {
+        let reason = loop {
+            match Frame::read(&mut reader) {
+                Ok(Frame::Send(dest, value)) => {
+                    self.deliver(&dest, value);
+                }
+                Ok(Frame::Heartbeat) => {}
+                Ok(Frame::Hello(_)) => break "saludo repetido".to_string(),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    break "sin latido".to_string()
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    break "desconectado".to_string()
+                }
+                Err(e) => break e.to_string(),
+            }
+        };
+        let _ = reader.shutdown(Shutdown::Both);
+        let removed = {
+            let mut state = lock(&self.state);
+            match state.peers.get(peer) {
+                Some(p) if p.id == id => state.peers.remove(peer).is_some(),
+                _ => false,
+            }
+        };
+        if removed && !self.stop.load(Ordering::SeqCst) {
+            self.notify(Value::arr(vec![
+                Value::str("nodo_caido"),
+                Value::str(peer),
+                Value::str(reason),
+            ]));
+        }
+    }
+
+    fn write(&self, peer: &str, frame: &[u8]) -> bool {
+        let stream = lock(&self.state).peers.get(peer).map(|p| p.stream.clone());
+        stream.is_some_and(|s| lock(&s).write_all(frame).is_ok())
+    }
+
+    /// Manda un latido a cada vecino cada `heartbeat` mientras el nodo viva.
+    fn beat(&self) {
+        let frame = Frame::Heartbeat.encode().unwrap_or_default();
+        while !self.stop.load(Ordering::SeqCst) {
+            std::thread::sleep(self.heartbeat);
+            let peers: Vec<String> = lock(&self.state).peers.keys().cloned().collect();
+            for peer in peers {
+                self.write(&peer, &frame);
+            }
+        }
+    }
+
+    fn accept(self: &Arc<Self>, listener: TcpListener) {
+        for stream in listener.incoming() {
+            if self.stop.load(Ordering::SeqCst) {
+                break;
+            }
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let node = self.clone();
+            let _ = spawn("lumen-cluster-hola", move || {
+                let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+                // Se contesta aunque ya estemos conectados con él: así quien
+                // llama sabe con quién habla y se queda con la conexión vieja.
+                if let Ok(Frame::Hello(peer)) = Frame::read(&mut stream) {
+                    let hello = Frame::Hello(node.name.clone()).encode().unwrap_or_default();
+                    if peer != node.name && stream.write_all(&hello).is_ok() {
+                        let _ = node.adopt(peer, stream);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// `__cluster_nodo(nombre, direccion, latido_ms)`: levanta el nodo y
+/// devuelve su nombre.
+pub(crate) fn start_node(
+    channels: SharedChannels,
+    name: &str,
+    addr: &str,
+    heartbeat: Duration,
+) -> Result<String, String> {
+    if name.is_empty() || name.contains('@') {
+        return Err(format!("Nombre de nodo no válido: '{}'", name));
+    }
+    let mut registry = nodes();
+    if registry.contains_key(name) {
+        return Err(format!("Ya existe un nodo llamado '{}'", name));
+    }
+    let listener = TcpListener::bind(addr).map_err(|e| e.to_string())?;
+    let node = Arc::new(Node {
+        name: name.to_string(),
+        addr: listener.local_addr().map_err(|e| e.to_string())?,
+        heartbeat: heartbeat.max(Duration::from_millis(10)),
+        channels,
+        state: Mutex::new(NodeState::default()),
+        stop: AtomicBool::new(false),
+    });
+    let acceptor = node.clone();
+    spawn(&format!("lumen-cluster-{}", name), move || {
+        acceptor.accept(listener)
+    })
+    .map_err(|e| e.to_string())?;
+    let beater = node.clone();
+    spawn(&format!("lumen-latido-{}", name), move || beater.beat()).map_err(|e| e.to_string())?;
+    registry.insert(name.to_string(), node);
+    Ok(name.to_string())
+}
+
+pub(crate) fn local_addr(name: &str) -> Result<String, String> {
+    Ok(node(name)?.addr.to_string())
+}
+
+/// Conecta el nodo `name` con el que escucha en `addr` y devuelve el nombre
+/// del otro. Si ya estaban conectados, no abre otra conexión.
+pub(crate) fn connect(name: &str, addr: &str) -> Result<String, String> {
+    let node = node(name)?;
+    let target = addr
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("Dirección no válida: {}", addr))?;
+    let mut stream =
+        TcpStream::connect_timeout(&target, HANDSHAKE_TIMEOUT).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    stream
+        .write_all(&Frame::Hello(node.name.clone()).encode()?)
+        .map_err(|e| e.to_string())?;
+    let peer = match Frame::read(&mut stream) {
+        Ok(Frame::Hello(peer)) => peer,
+        Ok(_) => return Err("Saludo de cluster no válido".to_string()),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            return Err(format!("{} rechazó la conexión", addr))
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    if !node
+        .adopt(peer.clone(), stream)
+        .map_err(|e| e.to_string())?
+        && !lock(&node.state).peers.contains_key(&peer)
+    {
+        return Err(format!("El nodo '{}' está detenido", node.name));
+    }
+    Ok(peer)
+}
+
+/// Publica el buzón `mailbox` como `actor` para los demás nodos.
+pub(crate) fn register(name: &str, actor: &str, mailbox: &str) -> Result<bool, String> {
+    let node = node(name)?;
+    let mut state = lock(&node.state);
+    if state.names.contains_key(actor) {
+        return Ok(false);
+    }
+    state.names.insert(actor.to_string(), mailbox.to_string());
+    Ok(true)
+}
+
+/// Envía `value` a `actor@nodo` (o a `actor` de este mismo nodo). Verdadero
+/// si salió hacia su nodo; que exista el actor allí no se confirma.
+pub(crate) fn send(name: &str, dest: &str, value: Value) -> Result<bool, String> {
+    let node = node(name)?;
+    let (actor, target) = dest.split_once('@').unwrap_or((dest, name));
+    if target == node.name {
+        return Ok(node.deliver(actor, value));
+    }
+    let frame = Frame::Send(actor.to_string(), value).encode()?;
+    Ok(node.write(target, &frame))
+}
+
+/// Avisa en `mailbox` cuando un nodo se conecte o se caiga.
+pub(crate) fn monitor(name: &str, mailbox: &str) -> Result<(), String> {
+    lock(&node(name)?.state).monitors.push(mailbox.to_string());
+    Ok(())
+}
+
+/// Nombres de los nodos conectados, en orden alfabético.
+pub(crate) fn peers(name: &str) -> Result<Vec<String>, String> {
+    let mut peers: Vec<String> = lock(&node(name)?.state).peers.keys().cloned().collect();
+    peers.sort();
+    Ok(peers)
+}
+
+/// Cierra el nodo y sus conexiones; los vecinos lo verán `desconectado`.
+pub(crate) fn stop(name: &str) -> bool {
+    let Some(node) = nodes().remove(name) else {
+        return false;
+    };
+    node.stop.store(true, Ordering::SeqCst);
+    for (_, peer) in lock(&node.state).peers.drain() {
+        let _ = lock(&peer.stream).shutdown(Shutdown::Both);
+    }
+    // Despierta al hilo que acepta, bloqueado en `accept`.
+    let _ = TcpStream::connect_timeout(&node.addr, Duration::from_secs(1));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(value: Value) {
+        let mut bytes = Vec::new();
+        encode_value(&value, &mut bytes).unwrap();
+        assert_eq!(decode_value(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn test_codificacion_ida_y_vuelta() {
+        let mut map = ImMap::with_hasher(FixHasher::default());
+        map.insert(
+            Value::str("k"),
+            Value::arr(vec![Value::Int(-1), Value::Void]),
+        );
+        map.insert(Value::Int(2), Value::Bool(true));
+        roundtrip(Value::Map(map));
+        roundtrip(Value::Struct {
+            name: "Punto".to_string(),
+            fields: vec![
+                ("x".to_string(), Value::Float(1.5)),
+                ("y".to_string(), Value::str("ñandú")),
+            ],
+        });
+        roundtrip(Value::Enum {
+            name: "Forma".to_string(),
+            variant: "Circulo".to_string(),
+            fields: vec![Value::Int(i64::MIN), Value::Func("area".to_string())],
+        });
+        roundtrip(Value::Tuple(vec![
+            Value::Exito(Box::new(Value::Int(1))),
+            Value::Error(Box::new(Value::str("mal"))),
+            Value::Opcion(None),
+            Value::Opcion(Some(Box::new(Value::Float(f64::NAN)))),
+        ]));
+    }
+
+    #[test]
+    fn test_clausuras_y_tramas_corruptas() {
+        let closure = Value::Closure {
+            func: "f".to_string(),
+            captured: Vec::new(),
+        };
+        assert!(encode_value(&Value::arr(vec![closure]), &mut Vec::new()).is_err());
+        // Largo de lista imposible, etiqueta desconocida, bytes sobrantes.
+        assert!(decode_value(&[TAG_ARRAY, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(decode_value(&[99]).is_err());
+        assert!(decode_value(&[TAG_VOID, TAG_VOID]).is_err());
+        assert!(decode_value(&[TAG_INT, 1, 2]).is_err());
+        let mut deep = vec![TAG_SOME; MAX_DEPTH + 2];
+        deep.push(TAG_VOID);
+        assert!(decode_value(&deep).is_err());
+    }
+
+    #[test]
+    fn test_tramas_ida_y_vuelta() {
+        let frames = [
+            Frame::Hello("a".to_string()),
+            Frame::Send("contador".to_string(), Value::arr(vec![Value::Int(3)])),
+            Frame::Heartbeat,
+        ];
+        let mut bytes = Vec::new();
+        for frame in &frames {
+            bytes.extend(frame.encode().unwrap());
+        }
+        let mut reader = io::Cursor::new(bytes);
+        for frame in frames {
+            assert_eq!(Frame::read(&mut reader).unwrap(), frame);
+        }
+        let mut bad = io::Cursor::new(vec![0, 0, 0, 2, FRAME_HEARTBEAT, 0]);
+        assert!(Frame::read(&mut bad).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! Backend criptográfico en Rust puro: SHA-2, HMAC, AES-CBC/GCM, PBKDF2,
+//! HKDF y CSPRNG. Es el que usan los builtins de crypto cuando `bcrypt.dll`
+//! no está disponible (Linux, macOS, wasm32).
+//!
+//! `aes_cbc_*` reproduce el formato de `crypto_ffi::Bcrypt` (IV en cero y
+//! relleno PKCS#7) para que un texto cifrado en Windows se descifre en
+//! cualquier plataforma y viceversa.
+#![cfg(any(feature = "extra", feature = "full"))]
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Aes256Gcm, AesGcm, Nonce};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha512};
+
+type Aes192Gcm = AesGcm<aes::Aes192, aes_gcm::aead::consts::U12>;
+
+/// Tamaño del nonce de AES-GCM; va al principio del texto cifrado.
+pub const GCM_NONCE: usize = 12;
+
+/// Largo máximo de una clave derivada: el que admite HKDF-SHA256, que
+/// también se aplica a PBKDF2.
+pub const MAX_KEY_LEN: usize = 255 * 32;
+
+/// Largo máximo de una petición al CSPRNG.
+pub const MAX_RANDOM_LEN: usize = 1 << 20;
+
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+pub fn sha512(data: &[u8]) -> Vec<u8> {
+    Sha512::digest(data).to_vec()
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC acepta claves de cualquier largo");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <Hmac<Sha512> as Mac>::new_from_slice(key).expect("HMAC acepta claves de cualquier largo");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn clave_invalida(key: &[u8]) -> String {
+    format!(
+        "AES: la clave debe tener 16, 24 o 32 bytes, tiene {}",
+        key.len()
+    )
+}
+
+pub fn aes_cbc_encrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    let iv = [0u8; 16];
+    Ok(match key.len() {
+        16 => cbc::Encryptor::<aes::Aes128>::new(key.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(data),
+        24 => cbc::Encryptor::<aes::Aes192>::new(key.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(data),
+        32 => cbc::Encryptor::<aes::Aes256>::new(key.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(data),
+        _ => return Err(clave_invalida(key)),
+    })
+}
+
+pub fn aes_cbc_decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    let iv = [0u8; 16];
+    let plain = match key.len() {
+        16 => cbc::Decryptor::<aes::Aes128>::new(key.into(), &iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(data),
+        24 => cbc::Decryptor::<aes::Aes192>::new(key.into(), &iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(data),
+        32 => cbc::Decryptor::<aes::Aes256>::new(key.into(), &iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(data),
+        _ => return Err(clave_invalida(key)),
+    };
+    plain.map_err(|_| "AES: relleno inválido (¿clave incorrecta?)".to_string())
+}
+
+/// Cifra con AES-GCM usando `nonce` y devuelve `nonce || cifrado || etiqueta`.
+pub fn aes_gcm_encrypt_with_nonce(
+    key: &[u8],
+    nonce: &[u8; GCM_NONCE],
+    data: &[u8],
+) -> Result<Vec<u8>, String> {
+    let nonce_ga = Nonce::from_slice(nonce);
+    let sealed = match key.len() {
+        16 => Aes128Gcm::new(key.into()).encrypt(nonce_ga, data),
+        24 => Aes192Gcm::new(key.into()).encrypt(nonce_ga, data),
+        32 => Aes256Gcm::new(key.into()).encrypt(nonce_ga, data),
+        _ => return Err(clave_invalida(key)),
+    }
+    .map_err(|_| "AES-GCM: no se pudo cifrar".to_string())?;
+    let mut out = nonce.to_vec();
+    out.extend(sealed);
+    Ok(out)
+}
+
+/// Cifra con AES-GCM y un nonce aleatorio de 96 bits.
+pub fn aes_gcm_encrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut nonce = [0u8; GCM_NONCE];
+    fill_random(&mut nonce)?;
+    aes_gcm_encrypt_with_nonce(key, &nonce, data)
+}
+
+/// Descifra la salida de `aes_gcm_encrypt`; falla si la etiqueta no coincide.
+pub fn aes_gcm_decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < GCM_NONCE + 16 {
+        return Err("AES-GCM: texto cifrado demasiado corto".into());
+    }
+    let (nonce, sealed) = data.split_at(GCM_NONCE);
+    let nonce = Nonce::from_slice(nonce);
+    match key.len() {
+        16 => Aes128Gcm::new(key.into()).decrypt(nonce, sealed),
+        24 => Aes192Gcm::new(key.into()).decrypt(nonce, sealed),
+        32 => Aes256Gcm::new(key.into()).decrypt(nonce, sealed),
+        _ => return Err(clave_invalida(key)),
+    }
+    .map_err(|_| "AES-GCM: autenticación fallida (datos alterados o clave incorrecta)".to_string())
+}
+
+pub fn pbkdf2_sha256(
+    password: &[u8],
+    salt: &[u8],
+    rounds: u32,
+    len: usize,
+) -> Result<Vec<u8>, String> {
+    if len > MAX_KEY_LEN {
+        return Err(format!(
+            "PBKDF2: largo máximo {} bytes, se pidieron {}",
+            MAX_KEY_LEN, len
+        ));
+    }
+    let mut out = vec![0u8; len];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, rounds, &mut out);
+    Ok(out)
+}
+
+pub fn hkdf_sha256(ikm: &[u8], salt: &[u8], info: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    // Se comprueba antes de reservar: `expand` rechazaría el largo, pero
+    // después de haber pedido la memoria.
+    if len > MAX_KEY_LEN {
+        return Err(format!(
+            "HKDF: largo máximo {} bytes, se pidieron {}",
+            MAX_KEY_LEN, len
+        ));
+    }
+    let mut out = vec![0u8; len];
+    hkdf::Hkdf::<Sha256>::new(Some(salt), ikm)
+        .expand(info, &mut out)
+        .map_err(|_| {
+            format!(
+                "HKDF: largo máximo {} bytes, se pidieron {}",
+                MAX_KEY_LEN, len
+            )
+        })?;
+    Ok(out)
+}
+
+pub fn fill_random(buf: &mut [u8]) -> Result<(), String> {
+    getrandom::getrandom(buf).map_err(|e| format!("CSPRNG no disponible: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn h(s: &str) -> Vec<u8> {
+        hex::decode(s).unwrap()
+    }
+
+    // Vectores de FIPS 180-4 / NIST CAVP.
+    #[test]
+    fn test_sha2_vectores_conocidos() {
+        assert_eq!(
+            sha256(b"abc"),
+            h("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert_eq!(
+            sha256(b""),
+            h("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+        assert_eq!(
+            sha512(b"abc"),
+            h(
+                "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+               2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+            )
+        );
+    }
+
+    // RFC 4231, caso 2.
+    #[test]
+    fn test_hmac_rfc4231() {
+        let key = b"Jefe";
+        let data = b"what do ya want for nothing?";
+        assert_eq!(
+            hmac_sha256(key, data),
+            h("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+        assert_eq!(
+            hmac_sha512(key, data),
+            h(
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+               9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+            )
+        );
+    }
+
+    // FIPS 197, apéndice C.1: un bloque con IV cero en CBC equivale a ECB.
+    #[test]
+    fn test_aes_cbc_fips197() {
+        let key = h("000102030405060708090a0b0c0d0e0f");
+        let plain = h("00112233445566778899aabbccddeeff");
+        let ct = aes_cbc_encrypt(&key, &plain).unwrap();
+        assert_eq!(&ct[..16], &h("69c4e0d86a7b0430d8cdb78070b4c55a")[..]);
+        assert_eq!(ct.len(), 32, "PKCS#7 añade un bloque completo");
+        assert_eq!(aes_cbc_decrypt(&key, &ct).unwrap(), plain);
+    }
+
+    #[test]
+    fn test_aes_cbc_rechaza_clave_y_relleno() {
+        assert!(aes_cbc_encrypt(b"corta", b"x")
+            .unwrap_err()
+            .contains("16, 24 o 32"));
+        let ct = aes_cbc_encrypt(&[1u8; 32], b"mensaje").unwrap();
+        assert!(aes_cbc_decrypt(&[2u8; 32], &ct).is_err());
+    }
+
+    // Vectores de "The Galois/Counter Mode of Operation", casos 2 y 3.
+    #[test]
+    fn test_aes_gcm_vectores_conocidos() {
+        let key = [0u8; 16];
+        let nonce = [0u8; GCM_NONCE];
+        let out = aes_gcm_encrypt_with_nonce(&key, &nonce, &[0u8; 16]).unwrap();
+        assert_eq!(
+            &out[GCM_NONCE..],
+            &h("0388dace60b6a392f328c2b971b2fe78ab6e47d42cec13bdf53a67b21257bddf")[..]
+        );
+
+        let key = h("feffe9928665731c6d6a8f9467308308");
+        let nonce: [u8; GCM_NONCE] = h("cafebabefacedbaddecaf888").try_into().unwrap();
+        let plain = h(
+            "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+                       1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b391aafd255",
+        );
+        let out = aes_gcm_encrypt_with_nonce(&key, &nonce, &plain).unwrap();
+        assert_eq!(
+            &out[GCM_NONCE..],
+            &h(
+                "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+                21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091473f5985\
+                4d5c2af327cd64a62cf35abd2ba6fab4"
+            )[..]
+        );
+        assert_eq!(aes_gcm_decrypt(&key, &out).unwrap(), plain);
+
+        let mut alterado = out.clone();
+        alterado[GCM_NONCE] ^= 1;
+        assert!(aes_gcm_decrypt(&key, &alterado).is_err());
+    }
+
+    // RFC 7914 §11 (PBKDF2-HMAC-SHA256).
+    #[test]
+    fn test_pbkdf2_rfc7914() {
+        assert_eq!(
+            pbkdf2_sha256(b"passwd", b"salt", 1, 64).unwrap(),
+            h(
+                "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+               49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+            )
+        );
+        assert!(pbkdf2_sha256(b"a", b"b", 1, usize::MAX).is_err());
+    }
+
+    // RFC 5869, caso 1.
+    #[test]
+    fn test_hkdf_rfc5869() {
+        let ikm = [0x0bu8; 22];
+        let salt = h("000102030405060708090a0b0c");
+        let info = h("f0f1f2f3f4f5f6f7f8f9");
+        assert_eq!(
+            hkdf_sha256(&ikm, &salt, &info, 42).unwrap(),
+            h(
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf\
+               34007208d5b887185865"
+            )
+        );
+        assert!(hkdf_sha256(&ikm, &salt, &info, 255 * 32 + 1).is_err());
+        assert!(hkdf_sha256(&ikm, &salt, &info, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_csprng_llena_el_buffer() {
+        let mut a = [0u8; 32];
+        let mut b = [0u8; 32];
+        fill_random(&mut a).unwrap();
+        fill_random(&mut b).unwrap();
+        assert_ne!(a, b);
+    }
+}
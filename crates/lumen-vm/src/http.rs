@@ -0,0 +1,733 @@
+#![cfg(feature = "full")]
+// Servidor HTTP/1.1 nativo de `__http_servidor`.
+//
+// Un hilo acepta conexiones y las reparte por un canal a un grupo fijo de
+// hilos. Cada hilo atiende una conexión entera: lee peticiones (con cuerpo por
+// `Content-Length` o `chunked`), las pasa al manejador LÚMEN en una VM hija
+// recién creada y escribe la respuesta, mientras el cliente mantenga viva la
+// conexión. Son hilos del sistema y no fibras del planificador: leer de un
+// socket bloquea, y un cliente lento no debe dejar sin hilo a las tareas.
+
+use crate::value::{FixHasher, Value};
+use crate::vm::Kin;
+use im::HashMap as ImMap;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+const MAX_BODY: usize = 16 * 1024 * 1024;
+/// Tiempo que una conexión puede estar ociosa entre peticiones.
+const KEEP_ALIVE: Duration = Duration::from_secs(5);
+/// Cada cuánto mira una conexión ociosa si debe cerrarse.
+const IDLE_POLL: Duration = Duration::from_millis(50);
+/// Plazo para recibir el resto de una petición ya empezada.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Fallo al leer una petición: `Bad` se contesta con su código y cierra la
+/// conexión; `Io` la cierra sin más.
+#[derive(Debug)]
+pub(crate) enum HttpError {
+    Bad(u16, String),
+    Io,
+}
+
+impl From<io::Error> for HttpError {
+    fn from(_: io::Error) -> Self {
+        HttpError::Io
+    }
+}
+
+/// Petición ya leída. Las cabeceras van con el nombre en minúsculas.
+#[derive(Debug)]
+pub(crate) struct Request {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) query: Vec<(String, String)>,
+    pub(crate) version: String,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Request {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// HTTP/1.1 mantiene la conexión salvo `Connection: close`; HTTP/1.0 la
+    /// cierra salvo `Connection: keep-alive`.
+    pub(crate) fn keep_alive(&self) -> bool {
+        let connection = self.header("connection").unwrap_or("").to_ascii_lowercase();
+        if self.version == "HTTP/1.0" {
+            connection.contains("keep-alive")
+        } else {
+            !connection.contains("close")
+        }
+    }
+
+    /// La petición como el struct `PeticionHTTP` que recibe el manejador.
+    pub(crate) fn to_value(&self, peer: &str) -> Value {
+        let map = |pairs: &[(String, String)]| {
+            let mut map = ImMap::with_hasher(FixHasher::default());
+            for (k, v) in pairs {
+                map.insert(Value::str(k.as_str()), Value::str(v.as_str()));
+            }
+            Value::Map(map)
+        };
+        Value::Struct {
+            name: "PeticionHTTP".to_string(),
+            fields: vec![
+                ("metodo".to_string(), Value::str(self.method.as_str())),
+                ("ruta".to_string(), Value::str(self.path.as_str())),
+                (
+                    "cuerpo".to_string(),
+                    Value::str(String::from_utf8_lossy(&self.body)),
+                ),
+                ("ip_origen".to_string(), Value::str(peer)),
+                ("consulta".to_string(), map(&self.query)),
+                ("cabeceras".to_string(), map(&self.headers)),
+                ("version".to_string(), Value::str(self.version.as_str())),
+            ],
+        }
+    }
+}
+
+/// Lee una línea terminada en LF, sin el CRLF. `None` si la conexión se
+/// cerró antes de empezarla.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>, HttpError> {
+    let mut buf = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut buf)?;
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    if buf.last() != Some(&b'\n') {
+        if buf.len() > MAX_LINE {
+            return Err(HttpError::Bad(431, "Línea demasiado larga".to_string()));
+        }
+        return Err(HttpError::Io);
+    }
+    while matches!(buf.last(), Some(b'\n' | b'\r')) {
+        buf.pop();
+    }
+    Ok(Some(String::from_utf8_lossy(&buf).into_owned()))
+}
+
+fn bad(message: &str) -> HttpError {
+    HttpError::Bad(400, message.to_string())
+}
+
+/// Decodifica `%XX` (y `+` como espacio en la consulta).
+fn percent_decode(text: &str, plus_as_space: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = |b: u8| (b as char).to_digit(16);
+                match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(hi), Some(lo)) => {
+                        out.push((hi * 16 + lo) as u8);
+                        i += 3;
+                        continue;
+                    }
+                    _ => out.push(b'%'),
+                }
+            }
+            b'+' if plus_as_space => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(k, true), percent_decode(v, true))
+        })
+        .collect()
+}
+
+fn read_chunked(reader: &mut impl BufRead) -> Result<Vec<u8>, HttpError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader)?.ok_or_else(|| bad("Cuerpo chunked incompleto"))?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| bad("Tamaño de trozo no válido"))?;
+        if size == 0 {
+            // Cabeceras finales (trailers): se leen y se descartan.
+            while read_line(reader)?.is_some_and(|l| !l.is_empty()) {}
+            return Ok(body);
+        }
+        // `body` nunca pasa de `MAX_BODY`: restar no desborda y sumar sí.
+        if size > MAX_BODY - body.len() {
+            return Err(HttpError::Bad(413, "Cuerpo demasiado grande".to_string()));
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        if read_line(reader)?.is_none_or(|l| !l.is_empty()) {
+            return Err(bad("Falta el fin de trozo"));
+        }
+    }
+}
+
+/// Lee la siguiente petición. `None` si el cliente cerró entre peticiones.
+/// Contesta `100 Continue` por `out` si el cliente lo espera.
+pub(crate) fn read_request(
+    reader: &mut impl BufRead,
+    out: &mut impl Write,
+) -> Result<Option<Request>, HttpError> {
+    // Se toleran líneas vacías antes de la petición (RFC 9112 §2.2).
+    let line = loop {
+        match read_line(reader)? {
+            None => return Ok(None),
+            Some(line) if line.is_empty() => continue,
+            Some(line) => break line,
+        }
+    };
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(bad("Línea de petición no válida"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(HttpError::Bad(
+            505,
+            format!("Versión no soportada: {}", version),
+        ));
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    loop {
+        let line = read_line(reader)?.ok_or_else(|| bad("Cabeceras incompletas"))?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() >= MAX_HEADERS {
+            return Err(HttpError::Bad(431, "Demasiadas cabeceras".to_string()));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| bad("Cabecera no válida"))?;
+        let name = name.trim().to_ascii_lowercase();
+        let value = value.trim();
+        // Las repetidas se unen con comas, como permite RFC 9110 §5.3.
+        match headers.iter_mut().find(|(k, _)| *k == name) {
+            Some((_, prev)) => {
+                prev.push_str(", ");
+                prev.push_str(value);
+            }
+            None => headers.push((name, value.to_string())),
+        }
+    }
+
+    let mut request = Request {
+        method: method.to_string(),
+        path: percent_decode(path, false),
+        query: parse_query(query),
+        version: version.to_string(),
+        headers,
+        body: Vec::new(),
+    };
+    let chunked = match request.header("transfer-encoding") {
+        None => false,
+        Some(te) if te.eq_ignore_ascii_case("chunked") => true,
+        Some(te) => {
+            return Err(HttpError::Bad(
+                501,
+                format!("Transfer-Encoding no soportado: {}", te),
+            ))
+        }
+    };
+    let length = match request.header("content-length") {
+        Some(len) if !chunked => len
+            .parse::<usize>()
+            .map_err(|_| bad("Content-Length no válido"))?,
+        _ => 0,
+    };
+    if length > MAX_BODY {
+        return Err(HttpError::Bad(413, "Cuerpo demasiado grande".to_string()));
+    }
+    if (chunked || length > 0)
+        && request
+            .header("expect")
+            .is_some_and(|e| e.eq_ignore_ascii_case("100-continue"))
+    {
+        out.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        out.flush()?;
+    }
+    if chunked {
+        request.body = read_chunked(reader)?;
+    } else if length > 0 {
+        request.body = vec![0; length];
+        reader.read_exact(&mut request.body)?;
+    }
+    Ok(Some(request))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Content Too Large",
+        422 => "Unprocessable Content",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+/// Respuesta lista para escribir.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+/// Campo de un struct o clave de un mapa, con el primero de `names` que exista.
+fn field(value: &Value, names: &[&str]) -> Option<Value> {
+    names.iter().find_map(|name| match value {
+        Value::Struct { fields, .. } => fields
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone()),
+        Value::Map(map) => map.get(&Value::str(*name)).cloned(),
+        _ => None,
+    })
+}
+
+impl Response {
+    pub(crate) fn text(status: u16, body: impl Into<String>) -> Self {
+        Response {
+            status,
+            headers: vec![(
+                "Content-Type".to_string(),
+                "text/plain; charset=utf-8".to_string(),
+            )],
+            body: body.into().into_bytes(),
+        }
+    }
+
+    /// Lo que devolvió el manejador: un struct o mapa con `codigo`,
+    /// `tipo_contenido`, `cuerpo` y `cabeceras` (o sus nombres en inglés), un
+    /// texto (200), `vacio` (204) o un `error(...)` (500).
+    pub(crate) fn from_value(value: Value) -> Self {
+        match value {
+            Value::Struct { .. } | Value::Map(_) => {
+                let status = field(&value, &["codigo", "estado", "status"])
+                    .and_then(|v| v.as_num())
+                    .map_or(200, |n| n as u16);
+                let mut response = match field(&value, &["cuerpo", "body"]) {
+                    Some(Value::Array(bytes)) => Response {
+                        status,
+                        headers: vec![(
+                            "Content-Type".to_string(),
+                            "application/octet-stream".to_string(),
+                        )],
+                        body: bytes
+                            .iter()
+                            .map(|b| b.as_num().unwrap_or(0.0) as u8)
+                            .collect(),
+                    },
+                    Some(Value::Void) | None => Response::text(status, ""),
+                    Some(body) => Response::text(status, body.to_string()),
+                };
+                if let Some(content_type) = field(&value, &["tipo_contenido", "content_type"]) {
+                    response.headers[0].1 = content_type.to_string();
+                }
+                if let Some(Value::Map(extra)) = field(&value, &["cabeceras", "headers"]) {
+                    for (k, v) in extra.iter() {
+                        let name = k.to_string();
+                        let lower = name.to_ascii_lowercase();
+                        if matches!(
+                            lower.as_str(),
+                            "content-length" | "connection" | "transfer-encoding"
+                        ) {
+                            continue;
+                        }
+                        match response
+                            .headers
+                            .iter_mut()
+                            .find(|(h, _)| h.eq_ignore_ascii_case(&name))
+                        {
+                            Some(header) => header.1 = v.to_string(),
+                            None => response.headers.push((name, v.to_string())),
+                        }
+                    }
+                }
+                response
+            }
+            Value::Void => Response {
+                status: 204,
+                headers: Vec::new(),
+                body: Vec::new(),
+            },
+            Value::Error(e) => Response::text(500, e.to_string()),
+            other => Response::text(200, other.to_string()),
+        }
+    }
+
+    pub(crate) fn write_to(
+        &self,
+        out: &mut impl Write,
+        keep_alive: bool,
+        head: bool,
+    ) -> io::Result<()> {
+        let mut head_text = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (k, v) in &self.headers {
+            head_text.push_str(&format!("{}: {}\r\n", k, v));
+        }
+        // Una respuesta 204 no lleva Content-Length (RFC 9110 §8.6).
+        if self.status != 204 {
+            head_text.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head_text.push_str(if keep_alive {
+            "Connection: keep-alive\r\n\r\n"
+        } else {
+            "Connection: close\r\n\r\n"
+        });
+        out.write_all(head_text.as_bytes())?;
+        if !head {
+            out.write_all(&self.body)?;
+        }
+        out.flush()
+    }
+}
+
+/// Servidor en marcha, visible desde cualquier VM por su identificador.
+struct Server {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+}
+
+fn servers() -> MutexGuard<'static, (usize, HashMap<String, Server>)> {
+    static SERVERS: OnceLock<Mutex<(usize, HashMap<String, Server>)>> = OnceLock::new();
+    lock(SERVERS.get_or_init(Default::default))
+}
+
+/// Lo que necesitan los hilos de un servidor para atender.
+struct Site {
+    kin: Kin,
+    handler: Value,
+    stop: Arc<AtomicBool>,
+    /// Conexiones aceptadas que aún esperan un hilo libre.
+    queued: AtomicUsize,
+}
+
+impl Site {
+    fn handle(&self, request: &Request, peer: &str) -> Response {
+        let run = || {
+            let mut vm = self.kin.vm(None);
+            vm.call_value(&self.handler, vec![request.to_value(peer)])
+        };
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(run)) {
+            Ok(Ok(value)) => Response::from_value(value),
+            Ok(Err(e)) => Response::text(500, e.to_string()),
+            Err(_) => Response::text(500, "El manejador falló"),
+        }
+    }
+
+    /// Espera el comienzo de la siguiente petición. Falso si hay que cerrar:
+    /// el cliente se fue, el servidor para, venció `KEEP_ALIVE` o, tras la
+    /// primera petición, hay conexiones esperando hilo.
+    fn await_request(&self, reader: &mut BufReader<TcpStream>, served: usize) -> bool {
+        if !reader.buffer().is_empty() {
+            return true;
+        }
+        let idle_since = Instant::now();
+        let _ = reader.get_ref().set_read_timeout(Some(IDLE_POLL));
+        loop {
+            match reader.fill_buf() {
+                Ok(buf) => return !buf.is_empty(),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if self.stop.load(Ordering::SeqCst)
+                        || idle_since.elapsed() >= KEEP_ALIVE
+                        || (served > 0 && self.queued.load(Ordering::SeqCst) > 0)
+                    {
+                        return false;
+                    }
+                }
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn serve(&self, stream: TcpStream) {
+        let peer = stream
+            .peer_addr()
+            .map(|a| a.ip().to_string())
+            .unwrap_or_default();
+        let Ok(write_half) = stream.try_clone() else {
+            return;
+        };
+        let mut out = BufWriter::new(write_half);
+        let mut reader = BufReader::new(stream);
+        let mut served = 0;
+        while self.await_request(&mut reader, served) {
+            let _ = reader.get_ref().set_read_timeout(Some(READ_TIMEOUT));
+            let request = match read_request(&mut reader, &mut out) {
+                Ok(Some(request)) => request,
+                Ok(None) | Err(HttpError::Io) => break,
+                Err(HttpError::Bad(status, message)) => {
+                    let _ = Response::text(status, message).write_to(&mut out, false, false);
+                    break;
+                }
+            };
+            let keep_alive = request.keep_alive() && !self.stop.load(Ordering::SeqCst);
+            let response = self.handle(&request, &peer);
+            served += 1;
+            if response
+                .write_to(&mut out, keep_alive, request.method == "HEAD")
+                .is_err()
+                || !keep_alive
+            {
+                break;
+            }
+        }
+    }
+}
+
+/// Arranca un servidor en `addr` con `workers` hilos que llaman a `handler`
+/// con cada petición. Devuelve su identificador (`http_N`).
+pub(crate) fn start(kin: Kin, addr: &str, handler: Value, workers: usize) -> io::Result<String> {
+    let listener = TcpListener::bind(addr)?;
+    let local = listener.local_addr()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let site = Arc::new(Site {
+        kin,
+        handler,
+        stop: stop.clone(),
+        queued: AtomicUsize::new(0),
+    });
+    let (tx, rx) = mpsc::channel::<TcpStream>();
+    let rx = Arc::new(Mutex::new(rx));
+    for i in 0..workers.max(1) {
+        let (site, rx) = (site.clone(), rx.clone());
+        std::thread::Builder::new()
+            .name(format!("lumen-http-{}", i))
+            .spawn(move || loop {
+                let Ok(stream) = lock(&rx).recv() else {
+                    break;
+                };
+                site.queued.fetch_sub(1, Ordering::SeqCst);
+                // Un fallo con una conexión la cierra sin perder el hilo.
+                let serve = std::panic::AssertUnwindSafe(|| site.serve(stream));
+                let _ = std::panic::catch_unwind(serve);
+            })?;
+    }
+    std::thread::Builder::new()
+        .name("lumen-http-accept".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                if site.stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                match stream {
+                    Ok(stream) => {
+                        site.queued.fetch_add(1, Ordering::SeqCst);
+                        if tx.send(stream).is_err() {
+                            break;
+                        }
+                    }
+                    // Sin descriptores libres, por ejemplo: se reintenta.
+                    Err(_) => std::thread::sleep(IDLE_POLL),
+                }
+            }
+        })?;
+    let mut registry = servers();
+    let id = format!("http_{}", registry.0);
+    registry.0 += 1;
+    registry.1.insert(id.clone(), Server { addr: local, stop });
+    Ok(id)
+}
+
+/// Dirección real en que escucha (útil con el puerto 0).
+pub(crate) fn local_addr(id: &str) -> Option<SocketAddr> {
+    servers().1.get(id).map(|s| s.addr)
+}
+
+pub(crate) fn is_running(id: &str) -> bool {
+    servers().1.contains_key(id)
+}
+
+/// Deja de aceptar conexiones; las abiertas se cierran al terminar la
+/// petición en curso. Falso si no había tal servidor.
+pub(crate) fn stop(id: &str) -> bool {
+    let Some(server) = servers().1.remove(id) else {
+        return false;
+    };
+    server.stop.store(true, Ordering::SeqCst);
+    // Despierta al hilo que acepta, bloqueado en `accept`.
+    let _ = TcpStream::connect_timeout(&server.addr, Duration::from_secs(1));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<Option<Request>, HttpError> {
+        let mut out = Vec::new();
+        read_request(&mut io::Cursor::new(raw.as_bytes()), &mut out)
+    }
+
+    #[test]
+    fn test_peticion_con_consulta_y_cabeceras() {
+        let req = parse(
+            "GET /hola%20mundo?q=a+b&x=%C3%B1&vacio HTTP/1.1\r\nHost: x\r\nX-A: 1\r\nx-a: 2\r\n\r\n",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(req.method, "GET");
+        assert_eq!(req.path, "/hola mundo");
+        assert_eq!(
+            req.query,
+            vec![
+                ("q".to_string(), "a b".to_string()),
+                ("x".to_string(), "ñ".to_string()),
+                ("vacio".to_string(), String::new()),
+            ]
+        );
+        assert_eq!(req.header("x-a"), Some("1, 2"));
+        assert!(req.keep_alive());
+    }
+
+    #[test]
+    fn test_cuerpo_chunked_y_peticiones_seguidas() {
+        let raw = "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                   4\r\nhola\r\n6;ext=1\r\n mundo\r\n0\r\nX-Fin: 1\r\n\r\n\
+                   GET /b HTTP/1.0\r\n\r\n";
+        let mut reader = io::Cursor::new(raw.as_bytes());
+        let mut out = Vec::new();
+        let first = read_request(&mut reader, &mut out).unwrap().unwrap();
+        assert_eq!(first.body, b"hola mundo");
+        let second = read_request(&mut reader, &mut out).unwrap().unwrap();
+        assert_eq!(second.path, "/b");
+        assert!(!second.keep_alive());
+        assert!(read_request(&mut reader, &mut out).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_expect_continue_y_content_length() {
+        let raw = "PUT /x HTTP/1.1\r\nContent-Length: 3\r\nExpect: 100-continue\r\n\r\nabc";
+        let mut out = Vec::new();
+        let req = read_request(&mut io::Cursor::new(raw.as_bytes()), &mut out)
+            .unwrap()
+            .unwrap();
+        assert_eq!(req.body, b"abc");
+        assert_eq!(out, b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn test_peticiones_mal_formadas() {
+        assert!(matches!(
+            parse("GET /\r\n\r\n"),
+            Err(HttpError::Bad(400, _))
+        ));
+        assert!(matches!(
+            parse("GET / HTTP/2.0\r\n\r\n"),
+            Err(HttpError::Bad(505, _))
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n"),
+            Err(HttpError::Bad(400, _))
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n"),
+            Err(HttpError::Bad(501, _))
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"),
+            Err(HttpError::Bad(400, _))
+        ));
+    }
+
+    #[test]
+    fn test_trozo_demasiado_grande() {
+        let chunked = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n";
+        assert!(matches!(
+            parse(&format!("{chunked}ffffffffffffffff\r\n")),
+            Err(HttpError::Bad(413, _))
+        ));
+        assert!(matches!(
+            parse(&format!("{chunked}1\r\na\r\n{:x}\r\n", usize::MAX)),
+            Err(HttpError::Bad(413, _))
+        ));
+        assert!(matches!(
+            parse(&format!("{chunked}{:x}\r\n", MAX_BODY + 1)),
+            Err(HttpError::Bad(413, _))
+        ));
+    }
+
+    #[test]
+    fn test_respuesta_desde_struct_mapa_y_texto() {
+        let mut headers = ImMap::with_hasher(FixHasher::default());
+        headers.insert(Value::str("X-Id"), Value::Int(7));
+        headers.insert(Value::str("Content-Length"), Value::Int(99));
+        let resp = Response::from_value(Value::Struct {
+            name: "RespuestaHTTP".to_string(),
+            fields: vec![
+                ("codigo".to_string(), Value::Int(201)),
+                ("tipo_contenido".to_string(), Value::str("application/json")),
+                ("cuerpo".to_string(), Value::str("{}")),
+                ("cabeceras".to_string(), Value::Map(headers)),
+            ],
+        });
+        let mut out = Vec::new();
+        resp.write_to(&mut out, true, false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\nX-Id: 7\r\n\
+             Content-Length: 2\r\nConnection: keep-alive\r\n\r\n{}"
+        );
+        assert_eq!(Response::from_value(Value::str("hola")).body, b"hola");
+        assert_eq!(Response::from_value(Value::Void).status, 204);
+        let err = Response::from_value(Value::Error(Box::new(Value::str("mal"))));
+        assert_eq!((err.status, err.body.as_slice()), (500, &b"mal"[..]));
+    }
+}
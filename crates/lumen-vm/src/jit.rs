@@ -0,0 +1,118 @@
+//! Reconstrucción del IR de una función a partir de su bytecode, para que el
+//! JIT de `lumen-aot` pueda especializarla y compilarla.
+//!
+//! Los slots del frame vuelven a ser variables con nombre. Los opcodes sin
+//! equivalente en el JIT (incluidos los accesos a globales) se convierten en
+//! `Halt`: el análisis de tipos rechaza la función solo si esa instrucción es
+//! alcanzable.
+
+use lumen_codegen::bytecode::{Bytecode, Instruction, Opcode};
+use lumen_ir::ir::{Func, Instr, Op};
+use std::collections::BTreeSet;
+
+fn binary_op(op: Opcode) -> Option<Op> {
+    Some(match op {
+        Opcode::Add => Op::Add,
+        Opcode::Sub => Op::Sub,
+        Opcode::Mul => Op::Mul,
+        Opcode::Div => Op::Div,
+        Opcode::Mod => Op::Mod,
+        Opcode::Eq => Op::Equal,
+        Opcode::Neq => Op::NotEqual,
+        Opcode::Lt => Op::Less,
+        Opcode::Le => Op::LessEqual,
+        Opcode::Gt => Op::Greater,
+        Opcode::Ge => Op::GreaterEqual,
+        Opcode::And => Op::And,
+        Opcode::Or => Op::Or,
+        Opcode::BitOr => Op::BitOr,
+        Opcode::BitAnd => Op::BitAnd,
+        Opcode::BitXor => Op::BitXor,
+        Opcode::ShiftLeft => Op::ShiftLeft,
+        Opcode::ShiftRight => Op::ShiftRight,
+        _ => return None,
+    })
+}
+
+/// Devuelve el IR de la función `func_idx`: desde su inicio hasta el inicio
+/// de la siguiente función. Los destinos de salto pasan a ser etiquetas
+/// numeradas con su posición en el bytecode.
+pub(crate) fn lift_function(bc: &Bytecode, func_idx: usize) -> Option<Func> {
+    let meta = bc.funcs.get(func_idx)?;
+    let start = meta.start;
+    let end = bc
+        .funcs
+        .iter()
+        .map(|f| f.start)
+        .filter(|&s| s > start)
+        .min()
+        .unwrap_or(bc.instructions.len());
+    let code = bc.instructions.get(start..end)?;
+    let target = |idx: usize| bc.nums.get(idx).map(|n| *n as usize);
+
+    let labels: BTreeSet<usize> = code
+        .iter()
+        .filter_map(|ins| match ins {
+            Instruction::WithIdx(Opcode::Jmp | Opcode::JmpIf, idx) => target(*idx),
+            _ => None,
+        })
+        .filter(|t| (start..end).contains(t))
+        .collect();
+
+    let mut instrs = Vec::with_capacity(code.len() + labels.len());
+    let mut ip = start;
+    while ip < end {
+        if labels.contains(&ip) {
+            instrs.push(Instr::Label(ip));
+        }
+        let ins = &bc.instructions[ip];
+        ip += 1;
+        let lifted = match ins {
+            Instruction::Simple(op) => match (binary_op(*op), op) {
+                (Some(bin), _) => Instr::Binary(bin),
+                (None, Opcode::Neg) => Instr::Unary(Op::Negate),
+                (None, Opcode::Not) => Instr::Unary(Op::Not),
+                (None, Opcode::BitNot) => Instr::Unary(Op::BitNot),
+                (None, Opcode::Ret) => Instr::Return,
+                (None, Opcode::Nop) => Instr::Nop,
+                _ => Instr::Halt,
+            },
+            Instruction::WithIdx(op, idx) => {
+                let name = || bc.names.get(*idx).cloned();
+                let lifted = match op {
+                    Opcode::PushInt => bc.ints.get(*idx).map(|n| Instr::ConstInt(*n)),
+                    Opcode::PushNum => bc.nums.get(*idx).map(|n| Instr::ConstFloat(*n)),
+                    Opcode::PushBool => Some(Instr::ConstBool(*idx != 0)),
+                    Opcode::LoadLocal => meta.locals.get(*idx).cloned().map(Instr::Load),
+                    Opcode::StoreLocal => meta.locals.get(*idx).cloned().map(Instr::Store),
+                    Opcode::Jmp => target(*idx).map(Instr::Jmp),
+                    Opcode::JmpIf => target(*idx).map(Instr::JmpIf),
+                    Opcode::Nop => Some(Instr::Nop),
+                    Opcode::Call => match bc.instructions.get(ip) {
+                        Some(Instruction::WithIdx(Opcode::Nop, argc)) => {
+                            ip += 1;
+                            let argc = bc.nums.get(*argc).copied().unwrap_or(0.0) as usize;
+                            name().map(|n| Instr::Call(n, argc))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                lifted.unwrap_or(Instr::Halt)
+            }
+            Instruction::WithBool(Opcode::PushBool, b) => Instr::ConstBool(*b),
+            Instruction::WithNum(Opcode::PushNum, n) => Instr::ConstFloat(*n),
+            _ => Instr::Halt,
+        };
+        instrs.push(lifted);
+    }
+
+    Some(Func {
+        name: meta.name.clone(),
+        params: meta.params.clone(),
+        defaults: vec![None; meta.params.len()],
+        entry: 0,
+        instrs,
+        declared: Vec::new(),
+    })
+}
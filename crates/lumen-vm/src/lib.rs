@@ -1,11 +1,29 @@
+pub mod builtins;
+#[cfg(any(feature = "extra", feature = "full"))]
+mod cluster;
 #[cfg(any(feature = "extra", feature = "full"))]
 pub mod coro_ffi;
+#[cfg(any(feature = "extra", feature = "full"))]
+pub mod crypto;
 #[cfg(feature = "full")]
 pub mod crypto_ffi;
 #[cfg(feature = "full")]
 pub mod gui_ffi;
+#[cfg(feature = "full")]
+mod http;
+#[cfg(feature = "aot")]
+mod jit;
+pub mod limits;
+#[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+mod scheduler;
+#[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+mod supervisor;
+#[cfg(any(feature = "extra", feature = "full"))]
+mod sync;
 pub mod value;
 pub mod vm;
 
-pub use value::Value;
-pub use vm::{CallFrame, VmError, VM};
+pub use builtins::{Builtin, BuiltinRegistry, HostFn, NativeFn};
+pub use limits::{Capabilities, Capability, PathAccess, SandboxViolation, VmLimits};
+pub use value::{Upvalue, Value};
+pub use vm::{CallFrame, StepKind, VmError, VM};
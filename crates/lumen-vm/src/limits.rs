@@ -0,0 +1,638 @@
+//! Límites de ejecución de la VM: combustible (instrucciones), plazo,
+//! memoria viva, líneas de salida y los permisos que los builtins comprueban
+//! antes de tocar el sistema. Pensado para correr código ajeno, como el del
+//! playground o el de quien embebe `LumenEngine`.
+
+use crate::value::{Upvalue, Value};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Límites de una ejecución. `Default` no limita nada y concede todos los
+/// permisos, que es como corre la VM desde la línea de órdenes.
+#[derive(Debug, Clone, Default)]
+pub struct VmLimits {
+    /// Instrucciones que puede ejecutar cada VM (las tareas tienen las suyas).
+    pub max_instructions: Option<usize>,
+    /// Tiempo de reloj desde `set_limits` o `reset_budget`. Las tareas
+    /// comparten el plazo de la VM que las lanzó. En wasm no se aplica.
+    pub timeout: Option<Duration>,
+    /// Bytes aproximados de los valores vivos (pila, variables, globales y
+    /// salida acumulada). Se mide cada cierto número de instrucciones.
+    pub max_memory: Option<usize>,
+    /// Líneas que puede imprimir la ejecución.
+    pub max_output_lines: Option<usize>,
+    pub capabilities: Capabilities,
+}
+
+impl VmLimits {
+    /// Límites prudentes para código que no es de fiar: diez millones de
+    /// instrucciones, cinco segundos, 64 MiB, diez mil líneas y ningún
+    /// permiso.
+    pub fn sandboxed() -> Self {
+        Self {
+            max_instructions: Some(10_000_000),
+            timeout: Some(Duration::from_secs(5)),
+            max_memory: Some(64 * 1024 * 1024),
+            max_output_lines: Some(10_000),
+            capabilities: Capabilities::none(),
+        }
+    }
+}
+
+/// Lo que el código puede hacer fuera de la VM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub fs_read: PathAccess,
+    pub fs_write: PathAccess,
+    /// Sockets, clientes y servidores HTTP y nodos de cluster.
+    pub network: bool,
+    /// Bibliotecas nativas, código C/Rust/ensamblador, JS del anfitrión,
+    /// ventanas y puertos serie.
+    pub ffi: bool,
+    /// Variables de entorno del proceso.
+    pub process: bool,
+    /// Tareas, hilos, actores con función, ámbitos y supervisores.
+    pub threads: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Self {
+            fs_read: PathAccess::Any,
+            fs_write: PathAccess::Any,
+            network: true,
+            ffi: true,
+            process: true,
+            threads: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            fs_read: PathAccess::None,
+            fs_write: PathAccess::None,
+            network: false,
+            ffi: false,
+            process: false,
+            threads: false,
+        }
+    }
+
+    /// Permite leer `path` y lo que cuelga de él.
+    pub fn allow_read(mut self, path: impl Into<PathBuf>) -> Self {
+        self.fs_read.allow(path.into());
+        self
+    }
+
+    /// Permite escribir `path` y lo que cuelga de él.
+    pub fn allow_write(mut self, path: impl Into<PathBuf>) -> Self {
+        self.fs_write.allow(path.into());
+        self
+    }
+
+    /// Comprueba el permiso que necesita el builtin `name` (su nombre
+    /// principal) llamado con `args`.
+    pub(crate) fn check(&self, name: &str, args: &[Value]) -> Result<(), SandboxViolation> {
+        let Some(capability) = required(name, args) else {
+            return Ok(());
+        };
+        let path = match capability {
+            Capability::FsRead | Capability::FsWrite => args.first().map(|v| v.to_string()),
+            _ => None,
+        };
+        let allowed = match capability {
+            Capability::FsRead => self.fs_read.allows(path.as_deref().unwrap_or("")),
+            Capability::FsWrite => self.fs_write.allows(path.as_deref().unwrap_or("")),
+            Capability::Network => self.network,
+            Capability::Ffi => self.ffi,
+            Capability::Process => self.process,
+            Capability::Threads => self.threads,
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(SandboxViolation::Denied {
+                capability,
+                builtin: name.to_string(),
+                path,
+            })
+        }
+    }
+}
+
+/// Rutas a las que llega un permiso de archivos.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathAccess {
+    None,
+    Any,
+    /// Solo dentro de estos directorios (o estos archivos).
+    Within(Vec<PathBuf>),
+}
+
+impl PathAccess {
+    fn allow(&mut self, path: PathBuf) {
+        match self {
+            PathAccess::Any => {}
+            PathAccess::Within(roots) => roots.push(path),
+            PathAccess::None => *self = PathAccess::Within(vec![path]),
+        }
+    }
+
+    /// Si `path` cae dentro de lo permitido. Las rutas se resuelven antes de
+    /// comparar, así que ni `..` ni un enlace simbólico sacan de la raíz.
+    pub fn allows(&self, path: &str) -> bool {
+        match self {
+            PathAccess::None => false,
+            PathAccess::Any => true,
+            PathAccess::Within(roots) => {
+                let Some(path) = resolve(Path::new(path)) else {
+                    return false;
+                };
+                roots
+                    .iter()
+                    .filter_map(|root| resolve(root))
+                    .any(|root| path.starts_with(root))
+            }
+        }
+    }
+}
+
+/// Ruta absoluta sin `.` ni `..`, con enlaces simbólicos resueltos en la
+/// parte que ya existe.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().ok()?.join(path)
+    };
+    let mut lexical = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                lexical.pop();
+            }
+            other => lexical.push(other),
+        }
+    }
+    // Se canonicaliza el ancestro más largo que existe y se le pega el resto.
+    let mut existing = lexical.as_path();
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(real) = existing.canonicalize() {
+            return Some(rest.iter().rev().fold(real, |acc, part| acc.join(part)));
+        }
+        rest.push(existing.file_name()?.to_os_string());
+        existing = existing.parent()?;
+    }
+}
+
+/// Permiso que exige un builtin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    FsRead,
+    FsWrite,
+    Network,
+    Ffi,
+    Process,
+    Threads,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Capability::FsRead => "lectura de archivos",
+            Capability::FsWrite => "escritura de archivos",
+            Capability::Network => "red",
+            Capability::Ffi => "FFI",
+            Capability::Process => "entorno del proceso",
+            Capability::Threads => "hilos",
+        })
+    }
+}
+
+/// Permiso que necesita el builtin `name`, si necesita alguno. Los de
+/// archivos reciben la ruta como primer argumento.
+fn required(name: &str, args: &[Value]) -> Option<Capability> {
+    let capability = match name {
+        "__file_read"
+        | "__file_exists"
+        | "__file_size"
+        | "__file_bytes"
+        | "__buf_reader"
+        | "__stream_chunks"
+        | "__fs_listar"
+        | "__leer_archivo_async"
+        | "__compile_nv" => Capability::FsRead,
+        "__file_write"
+        | "__file_append"
+        | "__file_write_binary"
+        | "__buf_writer"
+        | "__escribir_archivo_async" => Capability::FsWrite,
+        "__http_get" | "__http_post" | "__tcp_connect_async" => Capability::Network,
+        n if n.starts_with("__http_server")
+            || n.starts_with("__tcp_")
+            || n.starts_with("__cluster_") =>
+        {
+            Capability::Network
+        }
+        "__js_call" | "__js_eval" | "__serial_open" => Capability::Ffi,
+        n if n.starts_with("__ffi_") || n.starts_with("__gui_") => Capability::Ffi,
+        "__env_listar" => Capability::Process,
+        "__tarea_lanzar"
+        | "__hilo_lanzar"
+        | "__par_mapear"
+        | "__par_unir"
+        | "__scope_lanzar"
+        | "__supervisor_iniciar" => Capability::Threads,
+        // Sin función, un actor es solo un buzón.
+        "__actor_nuevo" if !args.is_empty() => Capability::Threads,
+        _ => return None,
+    };
+    Some(capability)
+}
+
+/// Motivo por el que la VM cortó una ejecución limitada.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SandboxViolation {
+    Instructions(usize),
+    Timeout(Duration),
+    Memory(usize),
+    Output(usize),
+    Denied {
+        capability: Capability,
+        builtin: String,
+        /// Ruta pedida, en los permisos de archivos.
+        path: Option<String>,
+    },
+}
+
+impl fmt::Display for SandboxViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxViolation::Instructions(n) => {
+                write!(f, "Límite de {} instrucciones excedido", n)
+            }
+            SandboxViolation::Timeout(t) => {
+                write!(f, "Tiempo límite de {} ms excedido", t.as_millis())
+            }
+            SandboxViolation::Memory(n) => write!(f, "Límite de memoria de {} bytes excedido", n),
+            SandboxViolation::Output(n) => write!(f, "Límite de {} líneas de salida excedido", n),
+            SandboxViolation::Denied {
+                capability,
+                builtin,
+                path: Some(path),
+            } => write!(
+                f,
+                "Permiso denegado: '{}' necesita {} para '{}'",
+                builtin, capability, path
+            ),
+            SandboxViolation::Denied {
+                capability,
+                builtin,
+                path: None,
+            } => write!(f, "Permiso denegado: '{}' necesita {}", builtin, capability),
+        }
+    }
+}
+
+/// Instante en que vence el plazo. `Instant::now` paniquea en wasm, así que
+/// allí nunca vence.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Deadline {
+    #[cfg(not(target_arch = "wasm32"))]
+    at: Option<std::time::Instant>,
+}
+
+impl Deadline {
+    fn after(timeout: Option<Duration>) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            Self {
+                at: timeout.map(|t| std::time::Instant::now() + t),
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = timeout;
+            Self {}
+        }
+    }
+
+    fn expired(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.at.is_some_and(|at| std::time::Instant::now() >= at)
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            false
+        }
+    }
+
+    /// `wake_at` adelantado al plazo, para que una espera no lo rebase.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    pub(crate) fn clamp(&self, wake_at: Option<std::time::Instant>) -> Option<std::time::Instant> {
+        match (self.at, wake_at) {
+            (Some(at), Some(wake)) => Some(at.min(wake)),
+            (at, wake) => at.or(wake),
+        }
+    }
+
+    /// `wait` recortado a lo que queda de plazo.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    pub(crate) fn cap(&self, wait: Duration) -> Duration {
+        match self.at {
+            Some(at) => wait.min(at.saturating_duration_since(std::time::Instant::now())),
+            None => wait,
+        }
+    }
+}
+
+/// Instrucciones entre dos comprobaciones del plazo y la memoria.
+const WATCH_INTERVAL: usize = 256;
+
+/// Límites aplicados a una VM y lo que queda de ellos en la ejecución en
+/// curso.
+#[derive(Debug, Clone)]
+pub(crate) struct Budget {
+    pub(crate) limits: Arc<VmLimits>,
+    /// Si algún builtin necesita permiso; si no, ni se clasifican.
+    pub(crate) restricted: bool,
+    /// `instr_count` a partir del cual se acaba el combustible.
+    pub(crate) fuel_end: Option<usize>,
+    pub(crate) deadline: Deadline,
+    /// `instr_count` de la próxima comprobación de plazo y memoria.
+    pub(crate) next_watch: usize,
+    pub(crate) output_lines: usize,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self::new(VmLimits::default(), 0)
+    }
+}
+
+impl Budget {
+    pub(crate) fn new(limits: VmLimits, instr_count: usize) -> Self {
+        let mut budget = Self {
+            restricted: limits.capabilities != Capabilities::all(),
+            limits: Arc::new(limits),
+            fuel_end: None,
+            deadline: Deadline::default(),
+            next_watch: usize::MAX,
+            output_lines: 0,
+        };
+        budget.refill(instr_count);
+        budget
+    }
+
+    /// Presupuesto entero otra vez, contando desde `instr_count`.
+    pub(crate) fn refill(&mut self, instr_count: usize) {
+        self.fuel_end = self
+            .limits
+            .max_instructions
+            .map(|max| instr_count.saturating_add(max));
+        self.deadline = Deadline::after(self.limits.timeout);
+        self.next_watch = self.next_watch_after(instr_count, 0);
+        self.output_lines = 0;
+    }
+
+    /// Presupuesto de una VM hija: los mismos límites y el mismo plazo, con
+    /// combustible propio.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    pub(crate) fn child(&self) -> Self {
+        let mut child = self.clone();
+        child.fuel_end = self.limits.max_instructions;
+        child.next_watch = child.next_watch_after(0, 0);
+        child.output_lines = 0;
+        child
+    }
+
+    /// El JIT corre sin contar instrucciones ni mirar el reloj.
+    #[cfg(feature = "aot")]
+    pub(crate) fn jit_allowed(&self) -> bool {
+        self.limits.max_instructions.is_none()
+            && self.limits.timeout.is_none()
+            && self.limits.max_memory.is_none()
+    }
+
+    pub(crate) fn check_deadline(&self) -> Result<(), SandboxViolation> {
+        match self.limits.timeout {
+            Some(timeout) if self.deadline.expired() => Err(SandboxViolation::Timeout(timeout)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Cuenta `lines` líneas más de salida.
+    pub(crate) fn emit(&mut self, lines: usize) -> Result<(), SandboxViolation> {
+        self.output_lines += lines;
+        match self.limits.max_output_lines {
+            Some(max) if self.output_lines > max => Err(SandboxViolation::Output(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Falla si un valor nuevo de `bytes` ya no cabe en el tope de memoria.
+    pub(crate) fn check_alloc(&self, bytes: usize) -> Result<(), SandboxViolation> {
+        match self.limits.max_memory {
+            Some(max) if bytes > max => Err(SandboxViolation::Memory(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Programa la siguiente comprobación. Medir la memoria cuesta lo que
+    /// ocupan los valores, así que se espacia en proporción para que el
+    /// coste por instrucción quede acotado.
+    pub(crate) fn next_watch_after(&self, instr_count: usize, measured: usize) -> usize {
+        if self.limits.timeout.is_none() && self.limits.max_memory.is_none() {
+            return usize::MAX;
+        }
+        instr_count.saturating_add(WATCH_INTERVAL.max(measured))
+    }
+}
+
+/// Estimación de la memoria que ocupan unos valores. Lo compartido por `Arc`
+/// (listas, textos, celdas) cuenta una sola vez.
+pub(crate) struct MemoryMeter {
+    cap: usize,
+    pub(crate) used: usize,
+    /// Valores recorridos, para espaciar la siguiente medición.
+    pub(crate) visited: usize,
+    seen: HashSet<usize>,
+}
+
+const VALUE_SIZE: usize = std::mem::size_of::<Value>();
+
+impl MemoryMeter {
+    pub(crate) fn new(cap: usize) -> Self {
+        Self {
+            cap,
+            used: 0,
+            visited: 0,
+            seen: HashSet::new(),
+        }
+    }
+
+    pub(crate) fn over(&self) -> bool {
+        self.used > self.cap
+    }
+
+    pub(crate) fn bytes(&mut self, n: usize) {
+        self.used = self.used.saturating_add(n);
+    }
+
+    /// Suma `value` y lo que cuelga de él; deja de recorrer en cuanto se
+    /// pasa del tope.
+    pub(crate) fn value(&mut self, value: &Value) {
+        let mut pending = vec![value];
+        while let Some(value) = pending.pop() {
+            if self.over() {
+                return;
+            }
+            self.visited += 1;
+            self.used += VALUE_SIZE;
+            match value {
+                Value::Str(s) => {
+                    if self.first_time(s.as_ptr() as usize) {
+                        self.used += s.len();
+                    }
+                }
+                Value::Array(items) => {
+                    if self.first_time(Arc::as_ptr(items) as *const () as usize) {
+                        pending.extend(items.iter());
+                    }
+                }
+                Value::Func(name) => self.used += name.len(),
+                Value::Closure { func, captured } => {
+                    self.used += func.len();
+                    for cell in captured {
+                        self.cell(cell);
+                    }
+                }
+                Value::Struct { name, fields } => {
+                    self.used += name.len();
+                    for (field, value) in fields {
+                        self.used += field.len();
+                        pending.push(value);
+                    }
+                }
+                Value::Enum {
+                    name,
+                    variant,
+                    fields,
+                } => {
+                    self.used += name.len() + variant.len();
+                    pending.extend(fields.iter());
+                }
+                Value::Exito(inner) | Value::Error(inner) | Value::Opcion(Some(inner)) => {
+                    pending.push(inner)
+                }
+                Value::Tuple(items) => pending.extend(items.iter()),
+                Value::Map(map) => {
+                    for (key, value) in map.iter() {
+                        pending.push(key);
+                        pending.push(value);
+                    }
+                }
+                Value::Int(_)
+                | Value::Float(_)
+                | Value::Bool(_)
+                | Value::Opcion(None)
+                | Value::Void => {}
+            }
+        }
+    }
+
+    /// Suma el valor de una celda capturada la primera vez que aparece.
+    pub(crate) fn cell(&mut self, cell: &Upvalue) {
+        if self.first_time(cell.addr()) {
+            self.value(&cell.get());
+        }
+    }
+
+    fn first_time(&mut self, addr: usize) -> bool {
+        self.seen.insert(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rutas_dentro_de_la_lista_blanca() {
+        let dir = std::env::temp_dir().join(format!("lumen_limits_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("datos")).unwrap();
+        let access = PathAccess::Within(vec![dir.join("datos")]);
+        let inside = dir.join("datos/nuevo.txt");
+        assert!(access.allows(inside.to_str().unwrap()));
+        let escape = dir.join("datos/../fuera.txt");
+        assert!(!access.allows(escape.to_str().unwrap()));
+        assert!(!access.allows(dir.join("datosx/a.txt").to_str().unwrap()));
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&dir, dir.join("datos/atajo")).unwrap();
+            let through_link = dir.join("datos/atajo/fuera.txt");
+            assert!(!access.allows(through_link.to_str().unwrap()));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_clasificacion_de_builtins() {
+        let caps = Capabilities::none().allow_read("/tmp");
+        assert!(caps.check("imprimir", &[]).is_ok());
+        assert!(caps.check("__actor_nuevo", &[]).is_ok());
+        assert!(caps
+            .check("__file_read", &[Value::str("/tmp/x.txt")])
+            .is_ok());
+        let denied = caps.check("__file_write", &[Value::str("/tmp/x.txt")]);
+        assert!(matches!(
+            denied,
+            Err(SandboxViolation::Denied {
+                capability: Capability::FsWrite,
+                ..
+            })
+        ));
+        for (name, capability) in [
+            ("__ffi_rust_eval", Capability::Ffi),
+            ("__tcp_connect", Capability::Network),
+            ("__cluster_nodo", Capability::Network),
+            ("__env_listar", Capability::Process),
+            ("__tarea_lanzar", Capability::Threads),
+        ] {
+            match caps.check(name, &[Value::str("f")]) {
+                Err(SandboxViolation::Denied { capability: c, .. }) => assert_eq!(c, capability),
+                other => panic!("{}: {:?}", name, other),
+            }
+        }
+        assert!(Capabilities::all()
+            .check("__ffi_cargar", &[Value::str("libc")])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_memoria_compartida_cuenta_una_vez() {
+        let list = Value::arr((0..100).map(Value::Int).collect());
+        let mut once = MemoryMeter::new(usize::MAX);
+        once.value(&list);
+        let mut twice = MemoryMeter::new(usize::MAX);
+        twice.value(&list);
+        twice.value(&list.clone());
+        assert_eq!(twice.used, once.used + VALUE_SIZE);
+        let mut capped = MemoryMeter::new(10);
+        capped.value(&list);
+        assert!(capped.over());
+        assert!(capped.visited < 100);
+    }
+}
@@ -0,0 +1,390 @@
+#![cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+// Planificador M:N — muchas fibras (VMs hijas ligeras) sobre un grupo fijo
+// de hilos de trabajo.
+//
+// Una fibra corre por tramos de `SLICE` instrucciones. Al agotar el tramo, o
+// cuando un builtin bloqueante (canal vacío, `__dormir`, esperar una tarea)
+// no puede seguir, la VM se aparca: como en `coro_ffi`, el cambio de contexto
+// es guardar pila, marcos e `ip` — aquí quedan dentro de la propia VM hija —
+// y el hilo pasa a otra fibra. Solo el nivel superior de una fibra se
+// aparca; el código que un builtin ejecuta anidado bloquea, y mientras espera
+// el hilo ayuda a correr otras fibras para no dejar el grupo sin trabajadores.
+//
+// Nadie sondea: una fibra que espera un canal, una tarea o un cerrojo queda
+// fuera de la cola hasta que quien envía, termina o libera avisa con `notify`
+// la clave que espera, o hasta que vence un plazo real (`__dormir`, tiempo
+// de espera, presupuesto). Lo mismo vale para `wait_until`.
+
+use crate::value::Value;
+use crate::vm::{VmError, VM};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Once, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Instrucciones que corre una fibra antes de ceder el hilo.
+pub(crate) const SLICE: usize = 2_000;
+
+/// Por qué cedió el hilo una fibra y qué la devuelve a la cola.
+#[derive(Default)]
+pub(crate) struct Park {
+    /// Claves (canal, buzón, tarea, cerrojo…) cuyo `notify` la despierta.
+    pub(crate) waits: Vec<String>,
+    /// Plazo real: al vencer vuelve a la cola aunque nadie la avise. Sin
+    /// esperas ni plazo, vuelve en cuanto haya hilo libre.
+    pub(crate) until: Option<Instant>,
+    /// `epoch()` leído antes de comprobar las esperas; si desde entonces hubo
+    /// algún aviso, la fibra no se aparca y lo vuelve a intentar.
+    pub(crate) epoch: u64,
+}
+
+/// Resultado de correr un tramo de una fibra.
+pub(crate) enum FiberStep {
+    Done(Result<Value, VmError>),
+    Parked(Park),
+}
+
+type OnDone = Box<dyn FnOnce(Result<Value, VmError>) + Send>;
+
+struct Fiber {
+    vm: Box<VM>,
+    on_done: OnDone,
+}
+
+struct Blocked {
+    waits: Vec<String>,
+    until: Option<Instant>,
+    fiber: Fiber,
+}
+
+#[derive(Default)]
+struct Queue {
+    ready: VecDeque<Fiber>,
+    blocked: Vec<Blocked>,
+    timers: Vec<(Instant, Sender<Value>, String)>,
+    /// Avisos dados hasta ahora; cualquier `notify` lo incrementa.
+    epoch: u64,
+    /// Hilos del grupo bloqueados en `wait_until` dentro de un builtin.
+    helpers: usize,
+}
+
+impl Queue {
+    /// Pasa a la cola de listas las fibras que esperan `key`; devuelve
+    /// cuántas.
+    fn notify(&mut self, key: &str) -> usize {
+        self.epoch += 1;
+        let mut woken = 0;
+        let mut i = 0;
+        while i < self.blocked.len() {
+            if self.blocked[i].waits.iter().any(|w| w == key) {
+                let blocked = self.blocked.swap_remove(i);
+                self.ready.push_back(blocked.fiber);
+                woken += 1;
+            } else {
+                i += 1;
+            }
+        }
+        woken
+    }
+
+    /// Despierta las fibras y temporizadores vencidos. Devuelve el próximo
+    /// vencimiento pendiente y si despertó algo.
+    fn fire_due(&mut self, now: Instant) -> (Option<Instant>, bool) {
+        let mut fired = false;
+        let mut i = 0;
+        while i < self.blocked.len() {
+            if self.blocked[i].until.is_some_and(|at| at <= now) {
+                let blocked = self.blocked.swap_remove(i);
+                self.ready.push_back(blocked.fiber);
+                fired = true;
+            } else {
+                i += 1;
+            }
+        }
+        let mut keys = Vec::new();
+        self.timers.retain(|(at, tx, key)| {
+            if *at <= now {
+                let _ = tx.send(Value::Bool(true));
+                keys.push(key.clone());
+                false
+            } else {
+                true
+            }
+        });
+        for key in &keys {
+            self.notify(key);
+            fired = true;
+        }
+        let sleepers = self.blocked.iter().filter_map(|b| b.until);
+        let next_due = sleepers.chain(self.timers.iter().map(|(at, ..)| *at)).min();
+        (next_due, fired)
+    }
+}
+
+#[derive(Default)]
+struct Pool {
+    queue: Mutex<Queue>,
+    /// Hilos del grupo: libres en `next` o ayudando en `wait_until`.
+    wake: Condvar,
+    /// Hilos de fuera del grupo bloqueados en `wait_until`.
+    events: Condvar,
+}
+
+impl Pool {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// `Queue::fire_due` que avisa a los demás hilos si despertó algo.
+    fn fire_due(&self, queue: &mut Queue, now: Instant) -> Option<Instant> {
+        let (next_due, fired) = queue.fire_due(now);
+        if fired {
+            self.wake.notify_all();
+            self.events.notify_all();
+        }
+        next_due
+    }
+
+    /// Espera en `cvar` hasta `limit`, o sin plazo.
+    fn wait<'a>(
+        &self,
+        cvar: &Condvar,
+        queue: MutexGuard<'a, Queue>,
+        limit: Option<Instant>,
+    ) -> MutexGuard<'a, Queue> {
+        match limit {
+            Some(at) => {
+                let wait = at.saturating_duration_since(Instant::now());
+                cvar.wait_timeout(queue, wait)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0
+            }
+            None => cvar.wait(queue).unwrap_or_else(|e| e.into_inner()),
+        }
+    }
+
+    /// Siguiente fibra lista; espera si no hay ninguna.
+    fn next(&self) -> Fiber {
+        let mut queue = self.lock();
+        loop {
+            let next_due = self.fire_due(&mut queue, Instant::now());
+            if let Some(fiber) = queue.ready.pop_front() {
+                return fiber;
+            }
+            queue = self.wait(&self.wake, queue, next_due);
+        }
+    }
+
+    fn try_next(&self) -> Option<Fiber> {
+        let mut queue = self.lock();
+        self.fire_due(&mut queue, Instant::now());
+        queue.ready.pop_front()
+    }
+
+    fn push(&self, fiber: Fiber, park: Park) {
+        let mut queue = self.lock();
+        let due = park.until.is_some_and(|at| at <= Instant::now());
+        let idle = park.waits.is_empty() && park.until.is_none();
+        let missed = !park.waits.is_empty() && park.epoch != queue.epoch;
+        if due || idle || missed {
+            queue.ready.push_back(fiber);
+            drop(queue);
+            self.wake.notify_one();
+            return;
+        }
+        queue.blocked.push(Blocked {
+            waits: park.waits,
+            until: park.until,
+            fiber,
+        });
+        drop(queue);
+        // Los hilos libres esperan hasta el vencimiento más próximo que
+        // conocían; que lo recalculen todos, porque quien despierte solo
+        // puede ser un ayudante a punto de volver a su builtin.
+        if park.until.is_some() {
+            self.wake.notify_all();
+        }
+    }
+
+    /// Bloquea hasta que haya un aviso posterior a `epoch`, venza `until` o,
+    /// si el hilo es del grupo, haya una fibra lista que correr.
+    fn wait_event(&self, epoch: u64, until: Option<Instant>, worker: bool) {
+        let mut queue = self.lock();
+        if worker {
+            queue.helpers += 1;
+        }
+        loop {
+            let now = Instant::now();
+            let next_due = if worker {
+                self.fire_due(&mut queue, now)
+            } else {
+                None
+            };
+            let ready = worker && !queue.ready.is_empty();
+            if queue.epoch != epoch || ready || until.is_some_and(|at| at <= now) {
+                break;
+            }
+            let limit = until.into_iter().chain(next_due).min();
+            let cvar = if worker { &self.wake } else { &self.events };
+            queue = self.wait(cvar, queue, limit);
+        }
+        if worker {
+            queue.helpers -= 1;
+        }
+    }
+}
+
+thread_local! {
+    static IS_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Número de hilos del grupo: `LUMEN_WORKERS` o los núcleos disponibles.
+fn worker_count() -> usize {
+    std::env::var("LUMEN_WORKERS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&n: &usize| n > 0)
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()))
+}
+
+/// La cola compartida, sin arrancar los hilos del grupo.
+fn shared() -> &'static Pool {
+    static POOL: OnceLock<Pool> = OnceLock::new();
+    POOL.get_or_init(Pool::default)
+}
+
+fn pool() -> &'static Pool {
+    static WORKERS: Once = Once::new();
+    WORKERS.call_once(|| {
+        for i in 0..worker_count() {
+            std::thread::Builder::new()
+                .name(format!("lumen-worker-{}", i))
+                .stack_size(8 * 1024 * 1024)
+                .spawn(worker)
+                .expect("no se pudo crear un hilo del planificador");
+        }
+    });
+    shared()
+}
+
+fn worker() {
+    IS_WORKER.with(|w| w.set(true));
+    let pool = pool();
+    loop {
+        run_slice(pool, pool.next());
+    }
+}
+
+fn run_slice(pool: &Pool, mut fiber: Fiber) {
+    match fiber.vm.run_slice() {
+        FiberStep::Done(result) => (fiber.on_done)(result),
+        FiberStep::Parked(park) => pool.push(fiber, park),
+    }
+}
+
+/// Encola una VM ya preparada con `VM::start_fiber`; `on_done` recibe su
+/// resultado en el hilo del grupo que la termine.
+pub(crate) fn spawn(vm: VM, on_done: impl FnOnce(Result<Value, VmError>) + Send + 'static) {
+    let fiber = Fiber {
+        vm: Box::new(vm),
+        on_done: Box::new(on_done),
+    };
+    pool().push(fiber, Park::default());
+}
+
+/// Envía `true` por `tx` a partir de `at` y avisa a `key`, sin ocupar un
+/// hilo mientras tanto.
+pub(crate) fn timer(at: Instant, tx: Sender<Value>, key: String) {
+    let pool = pool();
+    pool.lock().timers.push((at, tx, key));
+    pool.wake.notify_all();
+}
+
+/// Avisos dados hasta ahora. Quien va a esperar lo lee antes de comprobar si
+/// puede seguir, y se lo pasa a `Park` o lo compara tras bloquear, para no
+/// perder un aviso que llegue entre medias.
+pub(crate) fn epoch() -> u64 {
+    shared().lock().epoch
+}
+
+/// Avisa de que `key` cambió (un envío, una tarea terminada, un cerrojo
+/// libre…): despierta a las fibras aparcadas en ella y a quien espera en
+/// `wait_until`.
+pub(crate) fn notify(key: &str) {
+    let pool = shared();
+    let mut queue = pool.lock();
+    let woken = queue.notify(key);
+    let helpers = queue.helpers;
+    drop(queue);
+    if helpers > 0 {
+        pool.wake.notify_all();
+    } else {
+        for _ in 0..woken {
+            pool.wake.notify_one();
+        }
+    }
+    pool.events.notify_all();
+}
+
+/// Envía `val` por `tx` y avisa a quien espera `key`.
+pub(crate) fn send(tx: &Sender<Value>, val: Value, key: &str) -> bool {
+    let sent = tx.send(val).is_ok();
+    notify(key);
+    sent
+}
+
+/// Clave con la que se avisa de que se levantó el indicador `flag` de
+/// cancelación o parada.
+pub(crate) fn flag_key(flag: &Arc<AtomicBool>) -> String {
+    format!("cancelar@{:p}", Arc::as_ptr(flag))
+}
+
+/// Espera hasta que `poll` devuelva un valor o, como mucho, hasta `until`
+/// (y entonces sigue preguntando a `poll`, que decide qué hacer). Entre
+/// intentos bloquea hasta el siguiente `notify`; dentro del grupo corre
+/// además otras fibras mientras tanto.
+pub(crate) fn wait_until<T>(until: Option<Instant>, mut poll: impl FnMut() -> Option<T>) -> T {
+    let worker = IS_WORKER.with(|w| w.get());
+    let pool = shared();
+    loop {
+        let epoch = pool.lock().epoch;
+        if let Some(val) = poll() {
+            return val;
+        }
+        if worker {
+            if let Some(fiber) = pool.try_next() {
+                run_slice(pool, fiber);
+                continue;
+            }
+        }
+        pool.wait_event(epoch, until, worker);
+    }
+}
+
+/// Duerme sin acaparar un hilo del grupo.
+pub(crate) fn sleep(wait: Duration) {
+    if !IS_WORKER.with(|w| w.get()) {
+        std::thread::sleep(wait);
+        return;
+    }
+    let deadline = Instant::now() + wait;
+    wait_until(Some(deadline), || {
+        (Instant::now() >= deadline).then_some(())
+    });
+}
+
+/// `recv` que no acapara un hilo del grupo mientras espera. Quien envía por
+/// el otro extremo avisa con `send` o `notify`.
+pub(crate) fn recv(rx: &Receiver<Value>) -> Value {
+    let failed = || Value::Error(Box::new(Value::str("Task failed")));
+    if !IS_WORKER.with(|w| w.get()) {
+        return rx.recv().unwrap_or_else(|_| failed());
+    }
+    wait_until(None, || match rx.try_recv() {
+        Ok(val) => Some(val),
+        Err(TryRecvError::Empty) => None,
+        Err(TryRecvError::Disconnected) => Some(failed()),
+    })
+}
@@ -0,0 +1,422 @@
+#![cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+// Actores con comportamiento y árboles de supervisión al estilo OTP.
+//
+// Un actor es un buzón (un canal compartido `actor_N`) y, si se lanzó con una
+// función, una fibra que corre `funcion(buzon, args...)`. Al terminar la
+// fibra se avisa a sus monitores con un mensaje en su buzón, se detiene a los
+// actores enlazados si cayó, y su supervisor (si lo tiene) decide a quién
+// reiniciar según la estrategia y el límite de reinicios.
+//
+// Los reinicios ocurren en el hilo del grupo que vio morir al hijo: el
+// supervisor guarda un `Kin` para crear las VMs nuevas sin la VM que lo creó.
+
+use crate::scheduler;
+use crate::value::Value;
+use crate::vm::{raise_flag, Kin, SharedChannels, VmError};
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Cómo terminó una encarnación de un actor.
+#[derive(Debug, Clone)]
+pub(crate) enum Exit {
+    Normal(Value),
+    Crash(String),
+}
+
+impl Exit {
+    fn from_result(result: Result<Value, VmError>) -> Self {
+        match result {
+            Ok(Value::Error(reason)) => Exit::Crash(reason.to_string()),
+            Ok(val) => Exit::Normal(val),
+            Err(e) => Exit::Crash(e.to_string()),
+        }
+    }
+
+    /// Mensaje para los monitores: `["terminado", id, valor]` o
+    /// `["caido", id, motivo]`.
+    fn message(&self, id: &str) -> Value {
+        let (tag, detail) = match self {
+            Exit::Normal(val) => ("terminado", val.clone()),
+            Exit::Crash(reason) => ("caido", Value::str(reason.clone())),
+        };
+        Value::arr(vec![Value::str(tag), Value::str(id), detail])
+    }
+}
+
+/// Deja `msg` en el buzón `id` si existe.
+pub(crate) fn post(channels: &SharedChannels, id: &str, msg: Value) -> bool {
+    let tx = lock(channels).get(id).and_then(|(tx, _)| tx.clone());
+    let sent = tx.is_some_and(|tx| tx.send(msg).is_ok());
+    scheduler::notify(id);
+    sent
+}
+
+/// Pide al actor `id` que se detenga; lo hará en su siguiente tramo o espera.
+pub(crate) fn stop_actor(kin: &Kin, id: &str) {
+    if let Some(cell) = lock(&kin.actors).get(id) {
+        raise_flag(&cell.stop);
+    }
+}
+
+/// Lanza una encarnación nueva del actor `id` corriendo `callee(id, args...)`.
+/// `on_exit` se llama al terminar, después de avisar a monitores y enlaces.
+pub(crate) fn spawn_actor(
+    kin: &Kin,
+    id: &str,
+    callee: &Value,
+    args: Vec<Value>,
+    on_exit: impl FnOnce(&Exit) + Send + 'static,
+) {
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let mut actors = lock(&kin.actors);
+        let cell = actors.entry(id.to_string()).or_default();
+        cell.stop = stop.clone();
+        cell.alive = true;
+    }
+    let mut vm = kin.vm(Some(stop.clone()));
+    let call_args = std::iter::once(Value::str(id)).chain(args).collect();
+    let (kin, id) = (kin.clone(), id.to_string());
+    let finish = move |result| {
+        let exit = Exit::from_result(result);
+        exited(&kin, &id, &stop, &exit);
+        on_exit(&exit);
+    };
+    match vm.start_fiber(callee, call_args) {
+        Ok(()) => scheduler::spawn(vm, finish),
+        Err(e) => finish(Err(e)),
+    }
+}
+
+/// Avisa a los monitores de `id` y detiene a sus enlazados si cayó. Los
+/// enlaces son de la encarnación que muere; los monitores siguen al nombre.
+fn exited(kin: &Kin, id: &str, stop: &Arc<AtomicBool>, exit: &Exit) {
+    let links = {
+        let mut actors = lock(&kin.actors);
+        let Some(cell) = actors.get_mut(id) else {
+            return;
+        };
+        if Arc::ptr_eq(&cell.stop, stop) {
+            cell.alive = false;
+        }
+        let links = std::mem::take(&mut cell.links);
+        for other in &links {
+            if let Some(peer) = actors.get_mut(other) {
+                peer.links.retain(|l| l != id);
+            }
+        }
+        links
+    };
+    notify(kin, id, exit.message(id));
+    if matches!(exit, Exit::Crash(_)) {
+        for other in &links {
+            stop_actor(kin, other);
+        }
+    }
+}
+
+/// Estrategia de reinicio de un supervisor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Strategy {
+    /// Solo se reinicia el hijo que cayó.
+    OneForOne,
+    /// Se reinician todos los hijos.
+    OneForAll,
+    /// Se reinician el hijo que cayó y los que se añadieron después.
+    RestForOne,
+}
+
+impl Strategy {
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        match name {
+            "uno_para_uno" | "one_for_one" => Some(Strategy::OneForOne),
+            "uno_para_todos" | "one_for_all" => Some(Strategy::OneForAll),
+            "resto_para_uno" | "rest_for_one" => Some(Strategy::RestForOne),
+            _ => None,
+        }
+    }
+}
+
+/// Cuándo se reinicia un hijo al terminar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Restart {
+    /// Siempre, termine como termine.
+    Permanent,
+    /// Solo si cae.
+    Transient,
+    /// Nunca.
+    Temporary,
+}
+
+impl Restart {
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        match name {
+            "permanente" | "permanent" => Some(Restart::Permanent),
+            "transitorio" | "transient" => Some(Restart::Transient),
+            "temporal" | "temporary" => Some(Restart::Temporary),
+            _ => None,
+        }
+    }
+}
+
+/// Especificación de un hijo: la función que corre, sus argumentos (tras el
+/// buzón) y su política de reinicio.
+#[derive(Debug, Clone)]
+pub(crate) struct ChildSpec {
+    pub(crate) callee: Value,
+    pub(crate) args: Vec<Value>,
+    pub(crate) restart: Restart,
+}
+
+struct Child {
+    id: String,
+    spec: ChildSpec,
+    /// Encarnación vigente; las salidas de encarnaciones anteriores se
+    /// ignoran.
+    generation: u64,
+    running: bool,
+    /// Encarnación anterior que la estrategia mandó detener y aún no ha
+    /// terminado; los reinicios esperan a que acabe.
+    draining: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Running,
+    Stopped,
+    /// Superó el límite de reinicios y detuvo a todos sus hijos.
+    GaveUp,
+}
+
+pub(crate) struct Supervisor {
+    id: String,
+    kin: Kin,
+    strategy: Strategy,
+    max_restarts: usize,
+    period: Duration,
+    recent: VecDeque<Instant>,
+    restarts: usize,
+    children: Vec<Child>,
+    /// Reinicios a la espera de que terminen las encarnaciones detenidas.
+    pending: Vec<Launch>,
+    state: State,
+}
+
+pub(crate) type SharedSupervisor = Arc<Mutex<Supervisor>>;
+
+type Launch = (usize, u64, String, ChildSpec);
+
+impl Supervisor {
+    /// Supervisor `id`: más de `max_restarts` reinicios en `period` lo hacen
+    /// rendirse.
+    pub(crate) fn new(
+        id: String,
+        kin: Kin,
+        strategy: Strategy,
+        max_restarts: usize,
+        period: Duration,
+    ) -> SharedSupervisor {
+        Arc::new(Mutex::new(Supervisor {
+            id,
+            kin,
+            strategy,
+            max_restarts,
+            period,
+            recent: VecDeque::new(),
+            restarts: 0,
+            children: Vec::new(),
+            pending: Vec::new(),
+            state: State::Idle,
+        }))
+    }
+
+    pub(crate) fn restarts(&self) -> usize {
+        self.restarts
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.state == State::Running
+    }
+
+    pub(crate) fn child_ids(&self) -> Vec<String> {
+        self.children.iter().map(|c| c.id.clone()).collect()
+    }
+
+    /// Marca el hijo `idx` como una encarnación nueva y devuelve qué lanzar.
+    fn renew(&mut self, idx: usize) -> Launch {
+        let child = &mut self.children[idx];
+        child.generation += 1;
+        child.running = true;
+        (idx, child.generation, child.id.clone(), child.spec.clone())
+    }
+
+    /// Detiene a todos los hijos en marcha sin reiniciarlos.
+    fn stop_children(&mut self) {
+        self.pending.clear();
+        for child in &mut self.children {
+            if child.running {
+                child.running = false;
+                child.generation += 1;
+                stop_actor(&self.kin, &child.id);
+            }
+        }
+    }
+
+    /// Reinicios listos para lanzar, si ya no queda nadie por terminar.
+    fn take_ready(&mut self) -> Vec<Launch> {
+        if self.children.iter().any(|c| c.draining.is_some()) {
+            return Vec::new();
+        }
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Registra un reinicio; `false` si supera el límite del periodo.
+    fn allow_restart(&mut self) -> bool {
+        let now = Instant::now();
+        while self
+            .recent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= self.period)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+        self.recent.len() <= self.max_restarts
+    }
+}
+
+/// Añade un hijo con buzón `id`; si el supervisor ya corre, lo lanza.
+pub(crate) fn add_child(sup: &SharedSupervisor, id: String, spec: ChildSpec) {
+    let launch = {
+        let mut s = lock(sup);
+        s.children.push(Child {
+            id,
+            spec,
+            generation: 0,
+            running: false,
+            draining: None,
+        });
+        let idx = s.children.len() - 1;
+        (s.state == State::Running).then(|| s.renew(idx))
+    };
+    launch_all(sup, launch.into_iter().collect());
+}
+
+/// Arranca todos los hijos que no estén en marcha.
+pub(crate) fn start(sup: &SharedSupervisor) {
+    let batch = {
+        let mut s = lock(sup);
+        s.state = State::Running;
+        let idle: Vec<usize> = (0..s.children.len())
+            .filter(|&i| !s.children[i].running)
+            .collect();
+        idle.into_iter().map(|i| s.renew(i)).collect()
+    };
+    launch_all(sup, batch);
+}
+
+/// Detiene el supervisor y a todos sus hijos.
+pub(crate) fn stop(sup: &SharedSupervisor) {
+    let mut s = lock(sup);
+    s.state = State::Stopped;
+    s.stop_children();
+}
+
+fn launch_all(sup: &SharedSupervisor, batch: Vec<Launch>) {
+    let kin = lock(sup).kin.clone();
+    for (idx, generation, id, spec) in batch {
+        let owner = sup.clone();
+        spawn_actor(&kin, &id, &spec.callee, spec.args, move |exit| {
+            child_exited(&owner, idx, generation, exit)
+        });
+    }
+}
+
+/// Decide qué hacer cuando termina la encarnación `generation` del hijo `idx`.
+fn child_exited(sup: &SharedSupervisor, idx: usize, generation: u64, exit: &Exit) {
+    let (kin, batch) = {
+        let mut s = lock(sup);
+        let running = s.state == State::Running;
+        if s.children[idx].draining == Some(generation) {
+            // Terminó una encarnación que detuvo la estrategia.
+            s.children[idx].draining = None;
+            if !running {
+                return;
+            }
+            let batch = s.take_ready();
+            (s.kin.clone(), batch)
+        } else {
+            let child = &mut s.children[idx];
+            if child.generation != generation || !running {
+                return;
+            }
+            child.running = false;
+            let restart = match child.spec.restart {
+                Restart::Permanent => true,
+                Restart::Transient => matches!(exit, Exit::Crash(_)),
+                Restart::Temporary => false,
+            };
+            if !restart {
+                return;
+            }
+            if !s.allow_restart() {
+                s.state = State::GaveUp;
+                s.stop_children();
+                let (kin, id) = (s.kin.clone(), s.id.clone());
+                drop(s);
+                gave_up(&kin, &id);
+                return;
+            }
+            s.restarts += 1;
+            let affected = match s.strategy {
+                Strategy::OneForOne => idx..idx + 1,
+                Strategy::OneForAll => 0..s.children.len(),
+                Strategy::RestForOne => idx..s.children.len(),
+            };
+            for i in affected {
+                if i != idx && !s.children[i].running {
+                    continue;
+                }
+                let launched = !s.pending.iter().any(|l| l.0 == i);
+                if i != idx && launched {
+                    let sibling = &mut s.children[i];
+                    sibling.draining = Some(sibling.generation);
+                    stop_actor(&s.kin, &s.children[i].id);
+                }
+                let launch = s.renew(i);
+                s.pending.retain(|l| l.0 != i);
+                s.pending.push(launch);
+            }
+            let batch = s.take_ready();
+            (s.kin.clone(), batch)
+        }
+    };
+    for (_, _, id, _) in &batch {
+        let msg = Value::arr(vec![Value::str("reiniciado"), Value::str(id.clone())]);
+        notify(&kin, id, msg);
+    }
+    launch_all(sup, batch);
+}
+
+/// El supervisor `id` se rindió: lo comunica a quien lo monitorea.
+fn gave_up(kin: &Kin, id: &str) {
+    let reason = Exit::Crash("Límite de reinicios excedido".to_string());
+    notify(kin, id, reason.message(id));
+}
+
+fn notify(kin: &Kin, id: &str, msg: Value) {
+    let monitors = lock(&kin.actors)
+        .get(id)
+        .map(|c| c.monitors.clone())
+        .unwrap_or_default();
+    for monitor in &monitors {
+        post(&kin.channels, monitor, msg.clone());
+    }
+}
@@ -0,0 +1,457 @@
+#![cfg(any(feature = "extra", feature = "full"))]
+// Primitivas de sincronización que comparten una VM y todas sus tareas, hilos
+// y actores: cerrojos (mutex y lector/escritor), enteros atómicos, variables
+// de condición, barreras y celdas `arc`.
+//
+// Aquí nada bloquea un hilo del sistema: cada operación es un intento que
+// dice si pudo avanzar, y la VM lo repite aparcando la fibra o, fuera del
+// planificador, con `scheduler::wait_until`. El reintento llega cuando quien
+// libera, notifica o abre avisa con `scheduler::notify` el id de la
+// primitiva. Así una fibra que espera un cerrojo nunca deja sin hilo a la
+// que lo tiene, aunque el grupo tenga uno solo. Lo que sí debe sobrevivir entre intentos (el turno en una variable
+// de condición, la generación de una barrera) se guarda en un `Wait`.
+
+use crate::value::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Tabla de primitivas indexada por su identificador (`mutex_3`,
+/// `atomico_7`...).
+pub(crate) type SharedSync = Arc<Mutex<SyncTable>>;
+
+/// Modo en que se toma un cerrojo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    Read,
+    Write,
+}
+
+/// Cerrojo lector/escritor con el valor que protege. Un mutex es un cerrojo
+/// que solo se toma para escribir.
+pub(crate) struct Lock {
+    readers: usize,
+    writer: bool,
+    pub(crate) value: Value,
+}
+
+impl Lock {
+    fn new(value: Value) -> Self {
+        Lock {
+            readers: 0,
+            writer: false,
+            value,
+        }
+    }
+
+    pub(crate) fn try_acquire(&mut self, access: Access) -> bool {
+        match access {
+            Access::Read if !self.writer => self.readers += 1,
+            Access::Write if !self.writer && self.readers == 0 => self.writer = true,
+            _ => return false,
+        }
+        true
+    }
+
+    pub(crate) fn release(&mut self, access: Access) {
+        match access {
+            Access::Read => self.readers = self.readers.saturating_sub(1),
+            Access::Write => self.writer = false,
+        }
+    }
+}
+
+/// Variable de condición: cada espera saca un turno y `notify_*` pasa turnos
+/// de la cola de espera a la de despertados.
+#[derive(Default)]
+pub(crate) struct Condvar {
+    next: u64,
+    waiting: VecDeque<u64>,
+    woken: HashSet<u64>,
+}
+
+impl Condvar {
+    fn enqueue(&mut self) -> u64 {
+        let ticket = self.next;
+        self.next += 1;
+        self.waiting.push_back(ticket);
+        ticket
+    }
+
+    pub(crate) fn notify_one(&mut self) -> bool {
+        match self.waiting.pop_front() {
+            Some(ticket) => self.woken.insert(ticket),
+            None => false,
+        }
+    }
+
+    pub(crate) fn notify_all(&mut self) -> usize {
+        let count = self.waiting.len();
+        self.woken.extend(self.waiting.drain(..));
+        count
+    }
+
+    /// Retira el turno `ticket` sin que lo hayan despertado. Si ya lo habían
+    /// despertado, el aviso pasa al siguiente para no perderlo.
+    fn withdraw(&mut self, ticket: u64) {
+        if self.woken.remove(&ticket) {
+            self.notify_one();
+        } else {
+            self.waiting.retain(|t| *t != ticket);
+        }
+    }
+}
+
+/// Barrera para `parties` participantes; se reutiliza por generaciones.
+pub(crate) struct Barrier {
+    parties: usize,
+    arrived: usize,
+    generation: u64,
+}
+
+/// Espera en curso de una VM que sigue viva entre intentos.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Wait {
+    /// Esperando aviso en `condvar` con el turno `ticket`.
+    Condvar { condvar: String, ticket: u64 },
+    /// Volviendo a tomar `mutex` tras la espera; `notified` es el resultado.
+    Relock { mutex: String, notified: bool },
+    /// Esperando a que la barrera pase de la generación `generation`.
+    Barrier { barrier: String, generation: u64 },
+}
+
+pub(crate) enum Prim {
+    Lock(Lock),
+    Atomic(Arc<AtomicI64>),
+    Condvar(Condvar),
+    Barrier(Barrier),
+    Cell(Value),
+}
+
+#[derive(Default)]
+pub(crate) struct SyncTable {
+    prims: HashMap<String, Prim>,
+    created: usize,
+}
+
+impl SyncTable {
+    fn insert(&mut self, kind: &str, prim: Prim) -> String {
+        let id = format!("{}_{}", kind, self.created);
+        self.created += 1;
+        self.prims.insert(id.clone(), prim);
+        id
+    }
+
+    pub(crate) fn new_lock(&mut self, kind: &str, value: Value) -> String {
+        self.insert(kind, Prim::Lock(Lock::new(value)))
+    }
+
+    pub(crate) fn new_atomic(&mut self, value: i64) -> String {
+        self.insert("atomico", Prim::Atomic(Arc::new(AtomicI64::new(value))))
+    }
+
+    pub(crate) fn new_condvar(&mut self) -> String {
+        self.insert("condvar", Prim::Condvar(Condvar::default()))
+    }
+
+    pub(crate) fn new_barrier(&mut self, parties: usize) -> String {
+        let barrier = Barrier {
+            parties: parties.max(1),
+            arrived: 0,
+            generation: 0,
+        };
+        self.insert("barrera", Prim::Barrier(barrier))
+    }
+
+    pub(crate) fn new_cell(&mut self, value: Value) -> String {
+        self.insert("arc", Prim::Cell(value))
+    }
+
+    pub(crate) fn lock(&mut self, id: &str) -> Option<&mut Lock> {
+        match self.prims.get_mut(id) {
+            Some(Prim::Lock(lock)) => Some(lock),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn atomic(&self, id: &str) -> Option<Arc<AtomicI64>> {
+        match self.prims.get(id) {
+            Some(Prim::Atomic(cell)) => Some(cell.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn condvar(&mut self, id: &str) -> Option<&mut Condvar> {
+        match self.prims.get_mut(id) {
+            Some(Prim::Condvar(cv)) => Some(cv),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_barrier(&self, id: &str) -> bool {
+        matches!(self.prims.get(id), Some(Prim::Barrier(_)))
+    }
+
+    pub(crate) fn cell(&mut self, id: &str) -> Option<&mut Value> {
+        match self.prims.get_mut(id) {
+            Some(Prim::Cell(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn barrier(&mut self, id: &str) -> Option<&mut Barrier> {
+        match self.prims.get_mut(id) {
+            Some(Prim::Barrier(barrier)) => Some(barrier),
+            _ => None,
+        }
+    }
+
+    /// Un intento de `__condvar_esperar(cv, mutex)`: la primera vez saca turno
+    /// y suelta `mutex`; cuando llega el aviso (o vence el plazo) lo vuelve a
+    /// tomar y devuelve si hubo aviso. `None` mientras haya que seguir
+    /// esperando.
+    pub(crate) fn condvar_wait(
+        &mut self,
+        wait: &mut Option<Wait>,
+        condvar: &str,
+        mutex: &str,
+        expired: bool,
+    ) -> Option<Value> {
+        loop {
+            match wait.take() {
+                None => {
+                    let ticket = self.condvar(condvar)?.enqueue();
+                    if let Some(lock) = self.lock(mutex) {
+                        lock.release(Access::Write);
+                    }
+                    *wait = Some(Wait::Condvar {
+                        condvar: condvar.to_string(),
+                        ticket,
+                    });
+                }
+                Some(Wait::Condvar { condvar, ticket }) => {
+                    let cv = self.condvar(&condvar)?;
+                    let notified = cv.woken.remove(&ticket);
+                    if !notified && !expired {
+                        *wait = Some(Wait::Condvar { condvar, ticket });
+                        return None;
+                    }
+                    if !notified {
+                        cv.withdraw(ticket);
+                    }
+                    *wait = Some(Wait::Relock {
+                        mutex: mutex.to_string(),
+                        notified,
+                    });
+                }
+                Some(Wait::Relock { mutex, notified }) => {
+                    if self
+                        .lock(&mutex)
+                        .is_none_or(|lock| lock.try_acquire(Access::Write))
+                    {
+                        return Some(Value::Bool(notified));
+                    }
+                    *wait = Some(Wait::Relock { mutex, notified });
+                    return None;
+                }
+                Some(other) => {
+                    *wait = Some(other);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Un intento de `__barrera_esperar(b)`: devuelve verdadero a quien llega
+    /// el último (y abre la barrera) y falso al resto cuando se abre.
+    pub(crate) fn barrier_wait(&mut self, wait: &mut Option<Wait>, id: &str) -> Option<Value> {
+        let barrier = self.barrier(id)?;
+        match wait.take() {
+            None => {
+                barrier.arrived += 1;
+                if barrier.arrived >= barrier.parties {
+                    barrier.arrived = 0;
+                    barrier.generation += 1;
+                    return Some(Value::Bool(true));
+                }
+                *wait = Some(Wait::Barrier {
+                    barrier: id.to_string(),
+                    generation: barrier.generation,
+                });
+                None
+            }
+            Some(Wait::Barrier { generation, .. }) if barrier.generation != generation => {
+                Some(Value::Bool(false))
+            }
+            other => {
+                *wait = other;
+                None
+            }
+        }
+    }
+
+    /// Deshace una espera interrumpida (la tarea fue cancelada) para que no
+    /// se quede con un aviso ni cuente como llegada a una barrera.
+    pub(crate) fn abandon(&mut self, wait: Wait) {
+        match wait {
+            Wait::Condvar { condvar, ticket } => {
+                if let Some(cv) = self.condvar(&condvar) {
+                    cv.withdraw(ticket);
+                }
+            }
+            Wait::Barrier {
+                barrier,
+                generation,
+            } => {
+                if let Some(b) = self.barrier(&barrier) {
+                    if b.generation == generation {
+                        b.arrived = b.arrived.saturating_sub(1);
+                    }
+                }
+            }
+            Wait::Relock { .. } => {}
+        }
+    }
+}
+
+/// `compare_exchange` con la semántica de LÚMEN: verdadero si cambió.
+pub(crate) fn compare_exchange(cell: &AtomicI64, expected: i64, new: i64) -> bool {
+    cell.compare_exchange(expected, new, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::MutexGuard;
+
+    fn lock(table: &SharedSync) -> MutexGuard<'_, SyncTable> {
+        table.lock().unwrap()
+    }
+
+    /// Repite `attempt` como lo hace la VM fuera del planificador.
+    fn retry<T>(mut attempt: impl FnMut() -> Option<T>) -> T {
+        loop {
+            if let Some(val) = attempt() {
+                return val;
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_cerrojo_excluye_escritores_y_admite_lectores() {
+        let mut lock = Lock::new(Value::Void);
+        assert!(lock.try_acquire(Access::Read));
+        assert!(lock.try_acquire(Access::Read));
+        assert!(!lock.try_acquire(Access::Write));
+        lock.release(Access::Read);
+        lock.release(Access::Read);
+        assert!(lock.try_acquire(Access::Write));
+        assert!(!lock.try_acquire(Access::Read));
+        assert!(!lock.try_acquire(Access::Write));
+    }
+
+    #[test]
+    fn test_hilos_con_cerrojo_no_pierden_incrementos() {
+        let table = SharedSync::default();
+        let id = lock(&table).new_lock("rwlock", Value::Int(0));
+        let hilos: Vec<_> = (0..8)
+            .map(|_| {
+                let table = table.clone();
+                let id = id.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        let current = retry(|| {
+                            let mut t = lock(&table);
+                            let l = t.lock(&id).unwrap();
+                            l.try_acquire(Access::Write).then(|| l.value.clone())
+                        });
+                        // Fuera de la tabla, como mientras corre la función
+                        // de `__rwlock_escribir`.
+                        std::thread::yield_now();
+                        let Value::Int(n) = current else { panic!() };
+                        let mut t = lock(&table);
+                        let l = t.lock(&id).unwrap();
+                        l.value = Value::Int(n + 1);
+                        l.release(Access::Write);
+                    }
+                })
+            })
+            .collect();
+        for h in hilos {
+            h.join().unwrap();
+        }
+        assert!(matches!(
+            lock(&table).lock(&id).unwrap().value,
+            Value::Int(8000)
+        ));
+    }
+
+    #[test]
+    fn test_condvar_despierta_en_orden_y_vuelve_a_tomar_el_mutex() {
+        let table = SharedSync::default();
+        let (cv, m) = {
+            let mut t = lock(&table);
+            (t.new_condvar(), t.new_lock("mutex", Value::Void))
+        };
+        let mut t = lock(&table);
+        assert!(t.lock(&m).unwrap().try_acquire(Access::Write));
+        let mut wait = None;
+        assert!(t.condvar_wait(&mut wait, &cv, &m, false).is_none());
+        // Esperar suelta el mutex.
+        assert!(t.lock(&m).unwrap().try_acquire(Access::Write));
+        assert!(t.condvar(&cv).unwrap().notify_one());
+        // Avisado, pero el mutex sigue ocupado.
+        assert!(t.condvar_wait(&mut wait, &cv, &m, false).is_none());
+        t.lock(&m).unwrap().release(Access::Write);
+        assert!(matches!(
+            t.condvar_wait(&mut wait, &cv, &m, false),
+            Some(Value::Bool(true))
+        ));
+        assert!(wait.is_none());
+        assert!(!t.condvar(&cv).unwrap().notify_one());
+    }
+
+    #[test]
+    fn test_condvar_con_plazo_vencido_no_roba_avisos() {
+        let mut t = SyncTable::default();
+        let cv = t.new_condvar();
+        let m = t.new_lock("mutex", Value::Void);
+        let (mut a, mut b) = (None, None);
+        assert!(t.condvar_wait(&mut a, &cv, &m, false).is_none());
+        assert!(t.condvar_wait(&mut b, &cv, &m, false).is_none());
+        t.condvar(&cv).unwrap().notify_one();
+        // `a` tenía el aviso pero se rinde por plazo: pasa a `b`.
+        t.abandon(a.take().unwrap());
+        assert!(matches!(
+            t.condvar_wait(&mut b, &cv, &m, false),
+            Some(Value::Bool(true))
+        ));
+    }
+
+    #[test]
+    fn test_barrera_entre_hilos_por_generaciones() {
+        let table = SharedSync::default();
+        let id = lock(&table).new_barrier(4);
+        let lideres = Arc::new(AtomicI64::new(0));
+        let hilos: Vec<_> = (0..4)
+            .map(|_| {
+                let (table, id, lideres) = (table.clone(), id.clone(), lideres.clone());
+                std::thread::spawn(move || {
+                    for _ in 0..50 {
+                        let mut wait = None;
+                        let lider = retry(|| lock(&table).barrier_wait(&mut wait, &id));
+                        if matches!(lider, Value::Bool(true)) {
+                            lideres.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for h in hilos {
+            h.join().unwrap();
+        }
+        assert_eq!(lideres.load(Ordering::SeqCst), 50);
+    }
+}
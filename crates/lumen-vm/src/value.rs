@@ -1,7 +1,7 @@
 use im::HashMap;
 use std::fmt;
 use std::hash::{BuildHasher, Hash, Hasher};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 // ── NaN-Boxing Representation (64-bit compact values) ────────────────
 // IEEE 754 Quiet NaN: 0x7ff8_0000_0000_0000
@@ -119,6 +119,36 @@ impl BuildHasher for FixHasher {
     }
 }
 
+/// Variable capturada por una clausura. El frame que la declara y todas las
+/// clausuras que la capturan comparten la celda, así que ven las mismas
+/// asignaciones.
+#[derive(Debug, Clone)]
+pub struct Upvalue(Arc<Mutex<Value>>);
+
+impl Upvalue {
+    pub fn new(value: Value) -> Self {
+        Self(Arc::new(Mutex::new(value)))
+    }
+
+    pub fn get(&self) -> Value {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn set(&self, value: Value) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = value;
+    }
+
+    /// Si las dos son la misma celda.
+    pub fn same(&self, other: &Upvalue) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Dirección de la celda, que identifica a todas sus copias.
+    pub(crate) fn addr(&self) -> usize {
+        Arc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Int(i64),
@@ -127,6 +157,12 @@ pub enum Value {
     Bool(bool),
     Array(Arc<Vec<Value>>),
     Func(String),
+    /// Lambda con las variables que captura de su entorno, en el orden de
+    /// los slots que ocupan en su frame (tras los parámetros).
+    Closure {
+        func: String,
+        captured: Vec<Upvalue>,
+    },
     Struct {
         name: String,
         fields: Vec<(String, Value)>,
@@ -153,6 +189,16 @@ impl PartialEq for Value {
             (Value::Bool(a), Value::Bool(b)) => a == b,
             (Value::Array(a), Value::Array(b)) => a.as_ref() == b.as_ref(),
             (Value::Func(a), Value::Func(b)) => a == b,
+            (
+                Value::Closure {
+                    func: fa,
+                    captured: ca,
+                },
+                Value::Closure {
+                    func: fb,
+                    captured: cb,
+                },
+            ) => fa == fb && ca.len() == cb.len() && ca.iter().zip(cb).all(|(a, b)| a.same(b)),
             (
                 Value::Struct {
                     name: na,
@@ -220,6 +266,10 @@ impl Hash for Value {
                 6u8.hash(state);
                 name.hash(state);
             }
+            Value::Closure { func, .. } => {
+                14u8.hash(state);
+                func.hash(state);
+            }
             Value::Struct { name, fields } => {
                 7u8.hash(state);
                 name.hash(state);
@@ -283,6 +333,26 @@ impl Value {
         Value::Str(Arc::from(s.into()))
     }
 
+    /// Nombre del tipo tal como lo devuelve `tipo_de`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "entero",
+            Value::Float(_) => "decimal",
+            Value::Bool(_) => "booleano",
+            Value::Str(_) => "texto",
+            Value::Array(_) => "lista",
+            Value::Map(_) => "diccionario",
+            Value::Void => "nulo",
+            Value::Func(_) | Value::Closure { .. } => "funcion",
+            Value::Struct { .. } => "estructura",
+            Value::Enum { .. } => "enumeracion",
+            Value::Tuple(_) => "tupla",
+            Value::Exito(_) => "exito",
+            Value::Error(_) => "error",
+            Value::Opcion(_) => "opcion",
+        }
+    }
+
     pub fn arr(v: Vec<Value>) -> Value {
         Value::Array(Arc::new(v))
     }
@@ -335,7 +405,7 @@ impl Value {
             Value::Float(n) => *n != 0.0,
             Value::Str(s) => !s.is_empty(),
             Value::Array(v) => !v.is_empty(),
-            Value::Func(_) => true,
+            Value::Func(_) | Value::Closure { .. } => true,
             Value::Struct { .. } => true,
             Value::Enum { .. } => true,
             Value::Exito(_) => true,
@@ -366,7 +436,7 @@ impl fmt::Display for Value {
                 let items: Vec<String> = v.iter().map(|x| format!("{}", x)).collect();
                 write!(f, "[{}]", items.join(", "))
             }
-            Value::Func(s) => write!(f, "<funcion {}>", s),
+            Value::Func(s) | Value::Closure { func: s, .. } => write!(f, "<funcion {}>", s),
             Value::Struct { name: _, fields } => {
                 let items: Vec<String> = fields
                     .iter()
@@ -483,6 +553,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_nanbox_f64_roundtrip() {
         let v = NanVal::from_f64(3.14159);
         assert!(v.is_f64());
@@ -501,6 +572,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_nanbox_bool_and_void() {
         let v_true = NanVal::from_bool(true);
         assert!(v_true.is_bool());
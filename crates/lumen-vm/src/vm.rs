@@ -1,19 +1,36 @@
-use crate::value::{FixHasher, Value};
+use crate::builtins::{BuiltinFn, BuiltinRegistry, CoreFn, HostFn, NativeFn};
+use crate::limits::{Budget, MemoryMeter, SandboxViolation, VmLimits};
+use crate::value::{FixHasher, Upvalue, Value};
 use chrono::{Datelike, TimeZone, Timelike, Utc};
 use im::HashMap as ImMap;
-use lumen_codegen::bytecode::{Bytecode, DefaultValue, FuncMeta, Instruction, Opcode};
-use std::collections::HashMap;
+use lumen_codegen::bytecode::{
+    Bytecode, DefaultValue, FuncMeta, Instruction, Opcode, CHUNK_VERSION,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::OnceLock;
 use unicode_normalization::UnicodeNormalization;
 
 pub static JS_EVAL: OnceLock<fn(&str) -> String> = OnceLock::new();
 #[cfg(any(feature = "extra", feature = "full"))]
+use crate::cluster;
+#[cfg(any(feature = "extra", feature = "full"))]
 use crate::coro_ffi::Coroutine;
+#[cfg(any(feature = "extra", feature = "full"))]
+use crate::crypto;
 #[cfg(feature = "full")]
 use crate::crypto_ffi::Bcrypt;
 #[cfg(feature = "full")]
 use crate::gui_ffi::GuiWindow;
+#[cfg(feature = "full")]
+use crate::http;
+#[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+use crate::scheduler;
+#[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+use crate::supervisor;
+#[cfg(any(feature = "extra", feature = "full"))]
+use crate::sync::{self, Access, SharedSync};
 
 #[cfg(feature = "full")]
 macro_rules! ffi_rt_ty {
@@ -427,10 +444,121 @@ fn ffi_call_typed(
 
 pub const MAX_CALL_STACK_DEPTH: usize = 10_000;
 
+/// Variables de una llamada en curso: un slot por nombre de
+/// `FuncMeta::locals` (o de `params` si el chunk no trae locales).
+#[derive(Debug, Clone, Default)]
+pub struct LocalFrame {
+    /// Índice en `Bytecode::funcs`; `None` para el nivel superior.
+    pub func: Option<usize>,
+    pub slots: Vec<Value>,
+    /// Celdas de los slots capturados por una clausura, por índice de slot.
+    /// Mientras un slot tiene celda, su valor vive en ella y no en `slots`.
+    pub cells: Vec<Option<Upvalue>>,
+}
+
+impl LocalFrame {
+    fn cell(&self, slot: usize) -> Option<&Upvalue> {
+        self.cells.get(slot).and_then(Option::as_ref)
+    }
+
+    /// Valor del slot, leyendo la celda si está capturado.
+    pub fn get(&self, slot: usize) -> Value {
+        match self.cell(slot) {
+            Some(cell) => cell.get(),
+            None => self.slots.get(slot).cloned().unwrap_or(Value::Void),
+        }
+    }
+
+    fn set(&mut self, slot: usize, val: Value) {
+        if let Some(cell) = self.cell(slot) {
+            cell.set(val);
+            return;
+        }
+        if slot >= self.slots.len() {
+            self.slots.resize(slot + 1, Value::Void);
+        }
+        self.slots[slot] = val;
+    }
+
+    /// Celda del slot; la primera captura mueve el valor del slot a ella.
+    fn capture(&mut self, slot: usize) -> Upvalue {
+        if let Some(cell) = self.cell(slot) {
+            return cell.clone();
+        }
+        let val = self
+            .slots
+            .get_mut(slot)
+            .map(|v| std::mem::replace(v, Value::Void))
+            .unwrap_or(Value::Void);
+        let cell = Upvalue::new(val);
+        if slot >= self.cells.len() {
+            self.cells.resize(slot + 1, None);
+        }
+        self.cells[slot] = Some(cell.clone());
+        cell
+    }
+}
+
+/// Nombres de los slots de `meta`, en el orden de `LocalFrame::slots`.
+fn slot_names(meta: &FuncMeta) -> &[String] {
+    if meta.locals.is_empty() {
+        &meta.params
+    } else {
+        &meta.locals
+    }
+}
+
+/// Cómo avanza el depurador por la fuente: hasta la siguiente sentencia
+/// (`Into`), sin entrar en las llamadas (`Over`) o hasta volver al llamador
+/// (`Out`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    Into,
+    Over,
+    Out,
+}
+
 #[derive(Debug, Clone)]
 pub struct CallFrame {
     pub func_name: String,
     pub return_ip: usize,
+    pub defers: DeferStack,
+}
+
+/// Bloques `posponer` de un frame: `pending` guarda el inicio de cada bloque
+/// registrado y `resume` el `DeferRun` al que vuelve el bloque en ejecución.
+#[derive(Debug, Clone, Default)]
+pub struct DeferStack {
+    pub pending: Vec<usize>,
+    pub resume: Vec<usize>,
+}
+
+/// Manejador `intentar`/`atrapar` activo: al fallar, la VM recorta la pila
+/// de llamadas, los frames de variables y la pila de operandos a este punto.
+#[derive(Debug, Clone)]
+pub struct TryHandler {
+    pub catch_ip: usize,
+    pub call_depth: usize,
+    pub locals_len: usize,
+    pub stack_len: usize,
+    pub defer_resume_len: usize,
+    /// `posponer` pendientes del frame al entrar; los registrados después
+    /// se ejecutan antes de saltar a `atrapar`.
+    pub defer_pending_len: usize,
+}
+
+/// Retorno de un `posponer` lanzado al desenrollar hacia un `atrapar`: su
+/// `DeferEnd` continúa el desenrollado en vez de volver a un `DeferRun`.
+const UNWIND_RESUME: usize = usize::MAX;
+
+/// Desenrollado en curso hacia `handler`. `handlers_len` es cuántos
+/// manejadores quedaban al empezar: si un error llega sin que haya más, no lo
+/// atrapa nada dentro del `posponer` en ejecución y se une a este.
+#[derive(Debug, Clone)]
+pub struct Unwind {
+    pub handler: TryHandler,
+    pub message: String,
+    pub handlers_len: usize,
 }
 
 #[derive(Debug)]
@@ -441,6 +569,9 @@ pub enum VmError {
     UndefinedFunction(String),
     DivisionByZero,
     TypeError(String),
+    /// La ejecución rebasó un límite de `VmLimits` o un builtin pidió un
+    /// permiso que no tiene. `intentar` no lo captura.
+    Sandbox(SandboxViolation),
 }
 
 impl std::fmt::Display for VmError {
@@ -452,6 +583,7 @@ impl std::fmt::Display for VmError {
             VmError::UndefinedFunction(s) => write!(f, "Error: Función '{}' no definida", s),
             VmError::DivisionByZero => write!(f, "Error: División por cero"),
             VmError::TypeError(s) => write!(f, "Error de tipo: {}", s),
+            VmError::Sandbox(v) => write!(f, "Error de sandbox: {}", v),
         }
     }
 }
@@ -465,6 +597,7 @@ impl VmError {
             VmError::UndefinedFunction(s) => format!("Error: Función '{}' no definida", s),
             VmError::DivisionByZero => "Error: División por cero".to_string(),
             VmError::TypeError(s) => format!("Error de tipo: {}", s),
+            VmError::Sandbox(v) => format!("Error de sandbox: {}", v),
         };
         if stack.is_empty() {
             msg
@@ -478,40 +611,135 @@ impl VmError {
     }
 }
 
+/// Avisa a las fibras que esperan `key` (un canal, un buzón, una tarea, un
+/// cerrojo…) de que cambió. En wasm no hay planificador ni nadie esperando.
+#[cfg(any(feature = "extra", feature = "full"))]
+pub(crate) fn wake_waiters(key: &str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    scheduler::notify(key);
+    #[cfg(target_arch = "wasm32")]
+    let _ = key;
+}
+
+/// Levanta una señal de cancelación o parada y despierta a la fibra que la
+/// vigila. Devuelve si ya estaba levantada.
 #[cfg(any(feature = "extra", feature = "full"))]
-type ChannelCell = (
+pub(crate) fn raise_flag(flag: &Arc<std::sync::atomic::AtomicBool>) -> bool {
+    let was = flag.swap(true, std::sync::atomic::Ordering::SeqCst);
+    #[cfg(not(target_arch = "wasm32"))]
+    scheduler::notify(&scheduler::flag_key(flag));
+    was
+}
+
+#[cfg(any(feature = "extra", feature = "full"))]
+pub(crate) type ChannelCell = (
     Option<std::sync::mpsc::Sender<Value>>,
     Option<std::sync::mpsc::Receiver<Value>>,
 );
 
+/// Canales de `__canal_*`, compartidos con las VMs hijas (tareas, hilos y
+/// ámbitos) para que puedan comunicarse con quien las lanzó.
+#[cfg(any(feature = "extra", feature = "full"))]
+pub(crate) type SharedChannels = Arc<std::sync::Mutex<HashMap<String, ChannelCell>>>;
+
+/// Entrada de la tabla de actores. El buzón vive en los canales compartidos
+/// con el mismo id; aquí van la señal de parada de la encarnación vigente,
+/// quién la monitorea y con quién está enlazada.
+#[cfg(any(feature = "extra", feature = "full"))]
+#[derive(Default)]
+pub(crate) struct ActorCell {
+    pub(crate) stop: Arc<std::sync::atomic::AtomicBool>,
+    pub(crate) monitors: Vec<String>,
+    pub(crate) links: Vec<String>,
+    pub(crate) alive: bool,
+}
+
+#[cfg(any(feature = "extra", feature = "full"))]
+pub(crate) type SharedActors = Arc<std::sync::Mutex<HashMap<String, ActorCell>>>;
+
+/// Ámbito de concurrencia estructurada abierto por `__scope_nuevo`. Sus hijas
+/// comparten la señal de cancelación; el primer fallo queda en `failure`.
+#[cfg(any(feature = "extra", feature = "full"))]
+#[derive(Default)]
+struct TaskScope {
+    children: Vec<String>,
+    cancel: Arc<std::sync::atomic::AtomicBool>,
+    failure: Arc<std::sync::Mutex<Option<Value>>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct VmSnapshot {
     pub ip: usize,
     pub instr_count: usize,
     pub stack: Vec<Value>,
-    pub locals: Vec<HashMap<String, Value, FixHasher>>,
+    pub locals: Vec<LocalFrame>,
+    pub globals: Vec<Option<Value>>,
     pub call_stack: Vec<CallFrame>,
+    pub root_defers: DeferStack,
+    pub handlers: Vec<TryHandler>,
+    pub unwinding: Vec<Unwind>,
     pub output_len: usize,
 }
 
 pub struct VM {
     stack: Vec<Value>,
-    locals: Vec<HashMap<String, Value, FixHasher>>,
+    locals: Vec<LocalFrame>,
+    /// Variables del nivel superior, indexadas como `Bytecode::names`.
+    /// Compartidas con las tareas lanzadas, que ven una instantánea del
+    /// módulo; la primera escritura de cada lado hace su propia copia.
+    globals: Arc<Vec<Option<Value>>>,
     ip: usize,
-    bytecode: Bytecode,
+    bytecode: Arc<Bytecode>,
     output: Vec<String>,
     call_stack: Vec<CallFrame>,
-    func_index_cache: HashMap<String, usize>,
+    /// `posponer` del nivel superior (fuera de cualquier llamada).
+    root_defers: DeferStack,
+    handlers: Vec<TryHandler>,
+    unwinding: Vec<Unwind>,
+    func_index_cache: Arc<HashMap<String, usize>>,
+    builtins: Arc<BuiltinRegistry>,
+    /// Destino de cada `Call`, por índice de `Bytecode::names`.
+    call_targets: Arc<Vec<CallTarget>>,
     pub debug: bool,
+    /// Instrucciones en las que `run` y los pasos por fuente se detienen.
     pub breakpoints: Vec<usize>,
+    /// Breakpoint en el que se detuvo la ejecución; al reanudar no se vuelve
+    /// a parar en él.
+    paused_at: Option<usize>,
+    /// Instrucción del último error que no capturó ningún `atrapar`.
+    fault_ip: Option<usize>,
+    /// Funciones activas en ese error, de la más externa a la más interna;
+    /// `run_frame` desapila los marcos al fallar, así que se copian antes.
+    fault_stack: Vec<String>,
+    /// Archivo del programa, para los mensajes `archivo:línea:col`.
+    source_name: Option<String>,
     step_mode: bool,
     last_instr: Option<Instruction>,
     pub instr_count: usize,
     pub snapshots: Vec<VmSnapshot>,
-    pub call_counts: HashMap<String, usize>,
+    /// Llamadas a cada nombre, por índice de `Bytecode::names` como
+    /// `call_targets`: las funciones que llegan a `jit_threshold` se compilan.
+    pub call_counts: Vec<u32>,
     pub jit_threshold: usize,
+    /// Límites de la ejecución y lo que queda de ellos; ver `set_limits`.
+    budget: Budget,
+    /// Desenlaces de `JmpIf` vistos, como (ip, condición). Solo se registran
+    /// tras `enable_coverage`.
+    coverage: Option<HashSet<(usize, bool)>>,
     #[cfg(feature = "aot")]
     pub jit_engine: Option<lumen_aot::JitEngine>,
+    /// Especializaciones ya intentadas: (función, tipos de argumento) → código
+    /// nativo, o `None` si el JIT la rechazó y se sigue interpretando.
+    #[cfg(feature = "aot")]
+    jit_cache: HashMap<(usize, Vec<lumen_aot::JitType>), Option<lumen_aot::JitFunction>>,
+    /// Funciones que el intérprete ya despachó como código de usuario (no
+    /// como builtin homónimo): solo esas puede enlazar el código nativo.
+    #[cfg(feature = "aot")]
+    jit_user_funcs: Vec<bool>,
+    /// Especializaciones rechazadas porque llamaban a una función aún no
+    /// despachada; se reintentan cuando el intérprete despacha una nueva.
+    #[cfg(feature = "aot")]
+    jit_retry: Vec<(usize, Vec<lumen_aot::JitType>)>,
     #[cfg(feature = "full")]
     bcrypt: Option<Arc<Bcrypt>>,
     #[cfg(feature = "full")]
@@ -522,27 +750,45 @@ pub struct VM {
     current_coro: Option<String>,
     #[cfg(any(feature = "extra", feature = "full"))]
     #[allow(clippy::type_complexity)]
-    main_saved: Option<(Vec<Value>, Vec<HashMap<String, Value, FixHasher>>, usize)>,
-    tcp_listener: Option<std::net::TcpListener>,
-    #[cfg(feature = "full")]
-    #[allow(dead_code)]
-    cluster_streams: HashMap<String, std::net::TcpStream>,
+    main_saved: Option<(Vec<Value>, Vec<LocalFrame>, usize)>,
     #[cfg(feature = "full")]
-    #[allow(dead_code)]
-    scope_handles: Vec<HashMap<String, Value, FixHasher>>,
+    tcp_listener: Option<std::net::TcpListener>,
     #[cfg(any(feature = "extra", feature = "full"))]
-    #[allow(dead_code)]
-    thread_handles: HashMap<String, std::thread::JoinHandle<Value>>,
+    scopes: HashMap<String, TaskScope>,
+    /// Señal de cancelación del ámbito al que pertenece esta VM cuando es una
+    /// hija de `__scope_lanzar`; `run` la consulta periódicamente.
     #[cfg(any(feature = "extra", feature = "full"))]
-    #[allow(clippy::type_complexity)]
-    channels: HashMap<String, ChannelCell>,
+    cancel: Option<Arc<std::sync::atomic::AtomicBool>>,
+    /// Fin del tramo actual cuando la VM corre como fibra del planificador.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    slice_end: Option<usize>,
+    /// Aparcamiento pendiente: la fibra cede el hilo y se reanuda cuando haya
+    /// hueco, cuando la avisen o al vencer su plazo.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    parked: Option<scheduler::Park>,
+    /// Plazo de la espera aparcada en curso (`__seleccionar`, `__dormir`...).
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    park_deadline: Option<std::time::Instant>,
+    /// Primera instrucción de la llamada en curso, para reintentarla al
+    /// reanudar una fibra aparcada.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    call_ip: usize,
     #[cfg(any(feature = "extra", feature = "full"))]
-    mutexes: HashMap<String, std::sync::Mutex<Value>>,
+    channels: SharedChannels,
+    /// Cerrojos, atómicos, variables de condición y barreras, compartidos
+    /// con las hijas como los canales.
     #[cfg(any(feature = "extra", feature = "full"))]
-    #[allow(clippy::type_complexity)]
-    actors: HashMap<String, ChannelCell>,
+    sync: SharedSync,
+    /// Espera de `__condvar_esperar` o `__barrera_esperar` que sigue en curso
+    /// entre los intentos de una fibra aparcada.
     #[cfg(any(feature = "extra", feature = "full"))]
-    generators: HashMap<String, String>,
+    sync_wait: Option<sync::Wait>,
+    #[cfg(any(feature = "extra", feature = "full"))]
+    actors: SharedActors,
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    supervisors: HashMap<String, supervisor::SharedSupervisor>,
+    #[cfg(any(feature = "extra", feature = "full"))]
+    generators: HashMap<String, Value>,
     #[cfg(feature = "full")]
     ffi_libraries: HashMap<String, usize>,
     #[cfg(any(feature = "extra", feature = "full"))]
@@ -555,14 +801,59 @@ pub struct VM {
     task_counter: usize,
 }
 
-/// Helper to convert VmError into the builtin return type.
-fn builtin_err(err: VmError) -> Option<Result<(), VmError>> {
-    Some(Err(err))
+/// Lo que una VM hija hereda de su madre. Se puede guardar para crear hijas
+/// más tarde desde otro hilo, como hace un supervisor al reiniciar un actor.
+#[cfg(any(feature = "extra", feature = "full"))]
+#[derive(Clone)]
+pub(crate) struct Kin {
+    bytecode: Arc<Bytecode>,
+    func_index_cache: Arc<HashMap<String, usize>>,
+    builtins: Arc<BuiltinRegistry>,
+    call_targets: Arc<Vec<CallTarget>>,
+    globals: Arc<Vec<Option<Value>>>,
+    pub(crate) channels: SharedChannels,
+    pub(crate) actors: SharedActors,
+    sync: SharedSync,
+    budget: Budget,
+    #[cfg(feature = "full")]
+    bcrypt: Option<Arc<Bcrypt>>,
+}
+
+#[cfg(any(feature = "extra", feature = "full"))]
+impl Kin {
+    /// VM hija nueva; con `cancel`, se detiene cuando se active la señal.
+    pub(crate) fn vm(&self, cancel: Option<Arc<std::sync::atomic::AtomicBool>>) -> VM {
+        let mut vm = VM::with_shared(
+            self.bytecode.clone(),
+            self.func_index_cache.clone(),
+            self.builtins.clone(),
+            #[cfg(feature = "full")]
+            self.bcrypt.clone(),
+        );
+        vm.call_targets = self.call_targets.clone();
+        vm.globals = self.globals.clone();
+        vm.channels = self.channels.clone();
+        vm.actors = self.actors.clone();
+        vm.sync = self.sync.clone();
+        vm.budget = self.budget.child();
+        vm.cancel = cancel;
+        vm
+    }
+}
+
+/// Destino de una llamada por nombre. Los builtins tienen prioridad sobre las
+/// funciones de usuario homónimas.
+#[derive(Debug, Clone, Copy, Default)]
+struct CallTarget {
+    builtin: Option<usize>,
+    func: Option<usize>,
 }
 
 impl VM {
-    pub fn new(bytecode: Bytecode) -> Self {
-        let ip = if bytecode.funcs.is_empty() {
+    /// Primera instrucción a ejecutar: el nivel superior, o la función de
+    /// entrada si el bytecode la declara.
+    fn entry_ip(bytecode: &Bytecode) -> usize {
+        if bytecode.funcs.is_empty() {
             0
         } else {
             bytecode
@@ -573,36 +864,85 @@ impl VM {
                 .or_else(|| bytecode.funcs.iter().find(|f| f.name == "principal"))
                 .map(|f| f.start)
                 .unwrap_or(usize::MAX)
-        };
-        let mut func_index_cache = HashMap::new();
-        for (i, func) in bytecode.funcs.iter().enumerate() {
-            func_index_cache.insert(func.name.clone(), i);
         }
+    }
+
+    pub fn new(bytecode: impl Into<Arc<Bytecode>>) -> Self {
+        let bytecode = bytecode.into();
+        let func_index_cache = Arc::new(
+            bytecode
+                .funcs
+                .iter()
+                .enumerate()
+                .map(|(i, func)| (func.name.clone(), i))
+                .collect(),
+        );
         #[cfg(feature = "full")]
         let bcrypt = match Bcrypt::load() {
             Ok(b) => Some(Arc::new(b)),
             Err(_) => None,
         };
+        let mut vm = Self::with_shared(
+            bytecode,
+            func_index_cache,
+            Arc::new(BuiltinRegistry::from_table(VM::builtin_table())),
+            #[cfg(feature = "full")]
+            bcrypt,
+        );
         #[cfg(feature = "aot")]
-        let jit_engine = lumen_aot::JitEngine::new().ok();
+        {
+            vm.jit_engine = lumen_aot::JitEngine::new().ok();
+        }
+        vm.resolve_call_targets();
+        vm
+    }
+
+    /// VM en su estado inicial sobre piezas ya construidas, que se comparten
+    /// sin copiarse entre la VM principal y sus tareas.
+    fn with_shared(
+        bytecode: Arc<Bytecode>,
+        func_index_cache: Arc<HashMap<String, usize>>,
+        builtins: Arc<BuiltinRegistry>,
+        #[cfg(feature = "full")] bcrypt: Option<Arc<Bcrypt>>,
+    ) -> Self {
+        let ip = VM::entry_ip(&bytecode);
+        let call_counts = vec![0; bytecode.names.len()];
         Self {
             stack: Vec::new(),
-            locals: vec![HashMap::with_hasher(FixHasher::default())],
+            locals: vec![LocalFrame::default()],
+            globals: Arc::new(vec![None; bytecode.names.len()]),
             ip,
             bytecode,
             output: Vec::new(),
             call_stack: Vec::new(),
+            root_defers: DeferStack::default(),
+            handlers: Vec::new(),
+            unwinding: Vec::new(),
             func_index_cache,
+            builtins,
+            call_targets: Arc::new(Vec::new()),
             debug: false,
             breakpoints: Vec::new(),
+            paused_at: None,
+            fault_ip: None,
+            fault_stack: Vec::new(),
+            source_name: None,
             step_mode: false,
             last_instr: None,
             instr_count: 0,
             snapshots: Vec::new(),
-            call_counts: HashMap::new(),
+            call_counts,
             jit_threshold: 50,
+            budget: Budget::default(),
+            coverage: None,
             #[cfg(feature = "aot")]
-            jit_engine,
+            jit_engine: None,
+            #[cfg(feature = "aot")]
+            jit_cache: HashMap::new(),
+            #[cfg(feature = "aot")]
+            jit_user_funcs: Vec::new(),
+            #[cfg(feature = "aot")]
+            jit_retry: Vec::new(),
             #[cfg(feature = "full")]
             bcrypt,
             #[cfg(feature = "full")]
@@ -613,19 +953,30 @@ impl VM {
             current_coro: None,
             #[cfg(any(feature = "extra", feature = "full"))]
             main_saved: None,
-            tcp_listener: None,
             #[cfg(feature = "full")]
-            cluster_streams: HashMap::new(),
-            #[cfg(feature = "full")]
-            scope_handles: Vec::new(),
+            tcp_listener: None,
+            #[cfg(any(feature = "extra", feature = "full"))]
+            scopes: HashMap::new(),
+            #[cfg(any(feature = "extra", feature = "full"))]
+            cancel: None,
+            #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+            slice_end: None,
+            #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+            parked: None,
+            #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+            park_deadline: None,
+            #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+            call_ip: 0,
             #[cfg(any(feature = "extra", feature = "full"))]
-            thread_handles: HashMap::new(),
+            channels: SharedChannels::default(),
             #[cfg(any(feature = "extra", feature = "full"))]
-            channels: HashMap::new(),
+            sync: SharedSync::default(),
             #[cfg(any(feature = "extra", feature = "full"))]
-            mutexes: HashMap::new(),
+            sync_wait: None,
             #[cfg(any(feature = "extra", feature = "full"))]
-            actors: HashMap::new(),
+            actors: SharedActors::default(),
+            #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+            supervisors: HashMap::new(),
             #[cfg(any(feature = "extra", feature = "full"))]
             generators: HashMap::new(),
             #[cfg(feature = "full")]
@@ -639,2645 +990,4791 @@ impl VM {
         }
     }
 
-    fn find_func(&self, name: &str) -> Option<&FuncMeta> {
-        self.func_index_cache
-            .get(name)
-            .and_then(|&idx| self.bytecode.funcs.get(idx))
-    }
-
-    fn call_core_builtin(&mut self, name: &str, args: &[Value]) -> Option<Result<(), VmError>> {
-        let args = args.to_vec();
-        if name == "imprimir" || name == "print" {
-            let mut combined = String::new();
-            for arg in args {
-                combined.push_str(&format!("{}", arg));
+    /// Ejecuta `func_idx` con código nativo si los argumentos son escalares y
+    /// la especialización compila. `None` significa «seguir interpretando».
+    #[cfg(feature = "aot")]
+    fn call_jit(&mut self, func_idx: usize, args: &[Value]) -> Option<Result<Value, VmError>> {
+        use lumen_aot::JitType;
+        if args.len() != self.bytecode.funcs[func_idx].params.len() {
+            return None;
+        }
+        let mut types = Vec::with_capacity(args.len());
+        let mut raw = Vec::with_capacity(args.len());
+        for arg in args {
+            let (ty, bits) = match arg {
+                Value::Int(n) => (JitType::Int, *n),
+                Value::Float(f) => (JitType::Float, f.to_bits() as i64),
+                Value::Bool(b) => (JitType::Bool, *b as i64),
+                _ => return None,
+            };
+            types.push(ty);
+            raw.push(bits);
+        }
+        let key = (func_idx, types);
+        if !self.jit_cache.contains_key(&key) {
+            let compiled = self.jit_compile(func_idx, &key.1);
+            if let Err(lumen_aot::JitError::MissingCallee(_)) = compiled {
+                self.jit_retry.push(key.clone());
             }
-            self.output.push(combined);
-            self.push(Value::Void);
-            return Some(Ok(()));
+            self.jit_cache.insert(key.clone(), compiled.ok());
         }
+        let func = self.jit_cache.get(&key)?.as_ref()?;
+        let mut status = lumen_aot::JitStatus {
+            depth: self.call_stack.len() as i64,
+            ..Default::default()
+        };
+        let out = func.call(&raw, &mut status);
+        Some(match status.code {
+            lumen_aot::JIT_OK => Ok(match func.ret {
+                JitType::Int => Value::Int(out),
+                JitType::Float => Value::Float(f64::from_bits(out as u64)),
+                JitType::Bool => Value::Bool(out != 0),
+            }),
+            lumen_aot::JIT_DIV_ZERO => Err(VmError::DivisionByZero),
+            lumen_aot::JIT_SHIFT_RANGE => Err(VmError::Runtime(format!(
+                "Desplazamiento {} fuera de rango (0-63)",
+                status.payload
+            ))),
+            _ => Err(VmError::Runtime(format!(
+                "Desbordamiento de pila (Stack overflow): límite de recursión excedido (>{} llamadas)",
+                MAX_CALL_STACK_DEPTH
+            ))),
+        })
+    }
 
-        if name == "leer" || name == "read" {
-            self.push(Value::str(String::new()));
-            return Some(Ok(()));
+    #[cfg(feature = "aot")]
+    fn mark_user_func(&mut self, func_idx: usize) {
+        if self.jit_user_funcs.len() <= func_idx {
+            self.jit_user_funcs.resize(func_idx + 1, false);
         }
-
-        if name == "abs" || name == "absoluto" {
-            match args.first() {
-                Some(Value::Int(i)) => self.push(Value::Int(i.abs())),
-                Some(Value::Float(f)) => self.push(Value::Float(f.abs())),
-                Some(other) => {
-                    if let Some(n) = other.as_num() {
-                        self.push(Value::Float(n.abs()));
-                    } else {
-                        return builtin_err(VmError::TypeError(format!(
-                            "'abs' espera un número, no {:?}",
-                            other
-                        )));
-                    }
-                }
-                None => {
-                    return builtin_err(VmError::TypeError("'abs' espera 1 argumento".to_string()))
-                }
+        if !self.jit_user_funcs[func_idx] {
+            self.jit_user_funcs[func_idx] = true;
+            for key in self.jit_retry.drain(..) {
+                self.jit_cache.remove(&key);
             }
-            return Some(Ok(()));
         }
+    }
 
-        if name == "min" || name == "minimo" {
-            let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
-            let b = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0);
-            if args
-                .first()
-                .map(|v| matches!(v, Value::Int(_)))
-                .unwrap_or(false)
-                && args
-                    .get(1)
-                    .map(|v| matches!(v, Value::Int(_)))
-                    .unwrap_or(false)
-            {
-                self.push(Value::Int((a as i64).min(b as i64)));
-            } else {
-                self.push(Value::Float(a.min(b)));
+    /// Compila la especialización de la función para `types`. Con
+    /// `JitError::MissingCallee` conviene reintentar cuando se despache la
+    /// llamada que falta.
+    #[cfg(feature = "aot")]
+    fn jit_compile(
+        &mut self,
+        func_idx: usize,
+        types: &[lumen_aot::JitType],
+    ) -> Result<lumen_aot::JitFunction, lumen_aot::JitError> {
+        let Some(engine) = self.jit_engine.as_mut() else {
+            return Err(lumen_aot::JitError::Unsupported(
+                "no hay motor JIT".to_string(),
+            ));
+        };
+        let bytecode = &self.bytecode;
+        let index = &self.func_index_cache;
+        let user_funcs = &self.jit_user_funcs;
+        let name = &bytecode.funcs[func_idx].name;
+        let mut lookup = |n: &str| {
+            index
+                .get(n)
+                .filter(|&&i| user_funcs.get(i).copied().unwrap_or(false))
+                .and_then(|&i| crate::jit::lift_function(bytecode, i))
+        };
+        let log = std::env::var_os("LUMEN_JIT_LOG").is_some();
+        let compiled = engine.compile_specialized(name, types, &mut lookup);
+        if log {
+            match &compiled {
+                Ok(_) => eprintln!("[jit] '{}' {:?} compilada a código nativo", name, types),
+                Err(e) => eprintln!("[jit] '{}' {:?} sigue interpretada: {}", name, types, e),
             }
-            return Some(Ok(()));
         }
+        compiled
+    }
 
-        if name == "max" || name == "maximo" {
-            let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
-            let b = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0);
-            if args
-                .first()
-                .map(|v| matches!(v, Value::Int(_)))
-                .unwrap_or(false)
-                && args
-                    .get(1)
-                    .map(|v| matches!(v, Value::Int(_)))
-                    .unwrap_or(false)
-            {
-                self.push(Value::Int((a as i64).max(b as i64)));
-            } else {
-                self.push(Value::Float(a.max(b)));
-            }
-            return Some(Ok(()));
-        }
+    /// Funciones que el JIT compiló a código nativo durante la ejecución.
+    #[cfg(feature = "aot")]
+    pub fn jitted_functions(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .jit_cache
+            .iter()
+            .filter(|(_, f)| f.is_some())
+            .map(|((idx, _), _)| self.bytecode.funcs[*idx].name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
 
-        if name == "raiz" || name == "sqrt" {
-            let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
-            self.push(Value::Float(a.sqrt()));
-            return Some(Ok(()));
-        }
+    /// Registra un builtin nativo visible desde LÚMEN con los nombres dados
+    /// (el primero es el principal, el resto alias). Con `arity`, una llamada
+    /// con otro número de argumentos es un error de ejecución capturable.
+    /// Reemplaza a un builtin o función de usuario con el mismo nombre.
+    pub fn register_builtin(
+        &mut self,
+        names: &[&str],
+        arity: Option<usize>,
+        doc: &str,
+        func: NativeFn,
+    ) {
+        Arc::make_mut(&mut self.builtins).register(names, arity, doc, func);
+        self.resolve_call_targets();
+    }
 
-        if name == "potencia" || name == "pow" {
-            let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
-            let b = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0);
-            if args
-                .first()
-                .map(|v| matches!(v, Value::Int(_)))
-                .unwrap_or(false)
-                && args
-                    .get(1)
-                    .map(|v| matches!(v, Value::Int(_)))
-                    .unwrap_or(false)
-                && b >= 0.0
-            {
-                self.push(Value::Int((a as i64).pow(b as u32)));
-            } else {
-                self.push(Value::Float(a.powf(b)));
-            }
-            return Some(Ok(()));
-        }
+    /// Registra una closure de Rust como función LÚMEN. A diferencia de
+    /// `register_builtin` puede capturar estado; las mismas reglas de nombres
+    /// y aridad.
+    pub fn register_host_fn(
+        &mut self,
+        names: &[&str],
+        arity: Option<usize>,
+        doc: &str,
+        func: HostFn,
+    ) {
+        Arc::make_mut(&mut self.builtins).register_host(names, arity, doc, func);
+        self.resolve_call_targets();
+    }
 
-        if name == "piso" || name == "floor" {
-            let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
-            self.push(Value::Int(a.floor() as i64));
-            return Some(Ok(()));
-        }
+    pub fn builtins(&self) -> &BuiltinRegistry {
+        &self.builtins
+    }
 
-        if name == "techo" || name == "ceil" {
-            let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
-            self.push(Value::Int(a.ceil() as i64));
-            return Some(Ok(()));
-        }
+    fn resolve_call_targets(&mut self) {
+        self.call_targets = Arc::new(
+            self.bytecode
+                .names
+                .iter()
+                .map(|name| CallTarget {
+                    builtin: self.builtins.lookup(name),
+                    func: self.func_index_cache.get(name).copied(),
+                })
+                .collect(),
+        );
+    }
 
-        if name == "redondear" || name == "round" {
-            let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
-            self.push(Value::Int(a.round() as i64));
-            return Some(Ok(()));
+    fn call_builtin(&mut self, id: usize, args: Vec<Value>) -> Result<(), VmError> {
+        let builtin = self.builtins.get(id).expect("builtin registrado");
+        if let BuiltinFn::Core(f) = builtin.func {
+            // Las funciones del anfitrión no se comprueban: las registra
+            // quien pone los límites.
+            if self.budget.restricted {
+                self.budget
+                    .limits
+                    .capabilities
+                    .check(&builtin.name, &args)
+                    .map_err(VmError::Sandbox)?;
+            }
+            return f(self, args);
+        }
+        if let Some(arity) = builtin.arity.filter(|&n| n != args.len()) {
+            return Err(VmError::Runtime(format!(
+                "'{}' espera {} argumentos, recibió {}",
+                builtin.name,
+                arity,
+                args.len()
+            )));
         }
+        let val = match builtin.func.clone() {
+            BuiltinFn::Native(f) => f(self, &args)?,
+            BuiltinFn::Host(f) => f(&args)?,
+            BuiltinFn::Core(_) => unreachable!(),
+        };
+        self.push(val);
+        Ok(())
+    }
 
-        if name == "a_texto" || name == "to_texto" || name == "__str_from" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::str(s));
-            return Some(Ok(()));
-        }
+    /// Builtins de la VM con sus alias (español e inglés), en el orden en
+    /// que se registran.
+    fn builtin_table() -> Vec<(&'static [&'static str], CoreFn)> {
+        #[allow(unused_mut)]
+        let mut table: Vec<(&'static [&'static str], CoreFn)> = vec![
+            (&["imprimir", "print"], VM::builtin_imprimir),
+            (&["leer", "read"], VM::builtin_leer),
+            (&["lanzar", "throw"], VM::builtin_lanzar),
+            (&["abs", "absoluto"], VM::builtin_abs),
+            (&["min", "minimo"], VM::builtin_min),
+            (&["max", "maximo"], VM::builtin_max),
+            (&["raiz", "sqrt"], VM::builtin_raiz),
+            (&["potencia", "pow"], VM::builtin_potencia),
+            (&["piso", "floor"], VM::builtin_piso),
+            (&["techo", "ceil"], VM::builtin_techo),
+            (&["redondear", "round"], VM::builtin_redondear),
+            (&["a_texto", "to_texto", "__str_from"], VM::builtin_a_texto),
+            (&["__tipo_de", "__typeof"], VM::builtin_tipo_de),
+            (
+                &["__str_a_entero", "__texto_a_entero"],
+                VM::builtin_str_a_entero,
+            ),
+            (&["largo", "len"], VM::builtin_largo),
+            (&["agregar", "push"], VM::builtin_agregar),
+            (&["__str_len", "__str_longitud"], VM::builtin_str_len),
+            (&["__str_upper", "__str_mayusculas"], VM::builtin_str_upper),
+            (&["__str_lower", "__str_minusculas"], VM::builtin_str_lower),
+            (&["__str_trim", "__str_recortar"], VM::builtin_str_trim),
+            (
+                &["__str_contains", "__str_contiene"],
+                VM::builtin_str_contains,
+            ),
+            (&["__str_split", "__str_dividir"], VM::builtin_str_split),
+            (&["__str_ord", "__str_codigo"], VM::builtin_str_ord),
+            (&["__str_slice", "__str_subcadena"], VM::builtin_str_slice),
+            (
+                &["__str_concat_list", "__str_concatenar_lista"],
+                VM::builtin_str_concat_list,
+            ),
+            (
+                &["__str_starts_with", "__str_empieza_con"],
+                VM::builtin_str_starts_with,
+            ),
+            (
+                &["__str_to_chars", "__str_a_caracteres"],
+                VM::builtin_str_to_chars,
+            ),
+            (
+                &["__str_reemplazar", "__str_replace"],
+                VM::builtin_str_reemplazar,
+            ),
+            (
+                &["__str_subcadena_chars", "__str_slice_chars"],
+                VM::builtin_str_subcadena_chars,
+            ),
+            (&["__str_chr", "__str_caracter"], VM::builtin_str_chr),
+            (&["__file_read", "__leer_archivo"], VM::builtin_file_read),
+            (
+                &["__file_write", "__escribir_archivo"],
+                VM::builtin_file_write,
+            ),
+            (
+                &["__file_exists", "__existe_archivo"],
+                VM::builtin_file_exists,
+            ),
+            (&["__file_size", "__tamano_archivo"], VM::builtin_file_size),
+            (
+                &["__file_append", "__agregar_archivo"],
+                VM::builtin_file_append,
+            ),
+            (
+                &["__file_write_binary", "__escribir_archivo_bin"],
+                VM::builtin_file_write_binary,
+            ),
+            (&["__file_bytes", "__leer_bytes"], VM::builtin_file_bytes),
+            (&["__a_f64_bytes", "__bytes_a_f64"], VM::builtin_a_f64_bytes),
+            (
+                &["__num_a_f64_bytes", "__numero_a_bytes_f64"],
+                VM::builtin_num_a_f64_bytes,
+            ),
+            (&["__codegen_a_nvc"], VM::builtin_codegen_a_nvc),
+            (&["__compile_nv", "__compilar_nv"], VM::builtin_compile_nv),
+            (&["__time_now", "__tiempo_ahora"], VM::builtin_time_now),
+            (
+                &["__list_reverse", "__lista_invertir"],
+                VM::builtin_list_reverse,
+            ),
+            (&["__list_sort", "__lista_ordenar"], VM::builtin_list_sort),
+            (&["__map_new", "__map_nuevo"], VM::builtin_map_new),
+            (&["__map_set", "__map_poner"], VM::builtin_map_set),
+            (&["__map_get", "__map_obtener"], VM::builtin_map_get),
+            (&["__map_len", "__map_longitud"], VM::builtin_map_len),
+            (&["__map_keys", "__map_claves"], VM::builtin_map_keys),
+            (
+                &["__map_contains", "__map_contiene"],
+                VM::builtin_map_contains,
+            ),
+            (&["__set_new", "__conjunto_nuevo"], VM::builtin_set_new),
+            (&["__set_add", "__conjunto_agregar"], VM::builtin_set_add),
+            (&["__set_has", "__conjunto_tiene"], VM::builtin_set_has),
+            (&["__set_union", "__conjunto_unir"], VM::builtin_set_union),
+            (
+                &["__set_inter", "__conjunto_interseccion"],
+                VM::builtin_set_inter,
+            ),
+            (
+                &["__set_diff", "__conjunto_diferencia"],
+                VM::builtin_set_diff,
+            ),
+            (&["__deque_new", "__deque_nuevo"], VM::builtin_deque_new),
+            (
+                &["__deque_push_front", "__deque_agregar_frente"],
+                VM::builtin_deque_push_front,
+            ),
+            (
+                &["__deque_push_back", "__deque_agregar_final"],
+                VM::builtin_deque_push_back,
+            ),
+            (
+                &["__deque_pop_front", "__deque_quitar_frente"],
+                VM::builtin_deque_pop_front,
+            ),
+            (
+                &["__deque_pop_back", "__deque_quitar_final"],
+                VM::builtin_deque_pop_back,
+            ),
+            (&["__deque_len", "__deque_longitud"], VM::builtin_deque_len),
+            (&["__heap_new", "__monticulo_nuevo"], VM::builtin_heap_new),
+            (
+                &["__heap_push", "__monticulo_agregar"],
+                VM::builtin_heap_push,
+            ),
+            (&["__heap_pop", "__monticulo_quitar"], VM::builtin_heap_pop),
+            (&["__heap_peek", "__monticulo_ver"], VM::builtin_heap_peek),
+            (
+                &["__heap_len", "__monticulo_longitud"],
+                VM::builtin_heap_len,
+            ),
+            (
+                &["__linked_new", "__enlazada_nuevo"],
+                VM::builtin_linked_new,
+            ),
+            (
+                &["__linked_push_front", "__enlazada_agregar_frente"],
+                VM::builtin_linked_push_front,
+            ),
+            (
+                &["__linked_push_back", "__enlazada_agregar_final"],
+                VM::builtin_linked_push_back,
+            ),
+            (
+                &["__linked_pop_front", "__enlazada_quitar_frente"],
+                VM::builtin_linked_pop_front,
+            ),
+            (
+                &["__linked_pop_back", "__enlazada_quitar_final"],
+                VM::builtin_linked_pop_back,
+            ),
+            (
+                &["__linked_len", "__enlazada_longitud"],
+                VM::builtin_linked_len,
+            ),
+            (&["__regex_new", "__regex_nuevo"], VM::builtin_regex_new),
+            (
+                &["__regex_is_match", "__regex_coincide"],
+                VM::builtin_regex_is_match,
+            ),
+            (
+                &["__regex_captures", "__regex_capturar"],
+                VM::builtin_regex_captures,
+            ),
+            (
+                &["__regex_replace", "__regex_reemplazar"],
+                VM::builtin_regex_replace,
+            ),
+            (
+                &["__unicode_normalize", "__unicode_normalizar"],
+                VM::builtin_unicode_normalize,
+            ),
+            (
+                &["__str_pad_start", "__str_padding_inicio"],
+                VM::builtin_str_pad_start,
+            ),
+            (
+                &["__str_pad_end", "__str_padding_fin"],
+                VM::builtin_str_pad_end,
+            ),
+            (
+                &["__encoding_utf8", "__codificacion_utf8"],
+                VM::builtin_encoding_utf8,
+            ),
+            (
+                &["__encoding_from_utf8", "__desde_utf8"],
+                VM::builtin_encoding_from_utf8,
+            ),
+            (&["__buf_reader", "__lector_buffer"], VM::builtin_buf_reader),
+            (
+                &["__buf_writer", "__escritor_buffer"],
+                VM::builtin_buf_writer,
+            ),
+            (
+                &["__stream_chunks", "__stream_trozos"],
+                VM::builtin_stream_chunks,
+            ),
+            (
+                &["__http_server", "__http_servidor"],
+                VM::builtin_http_server,
+            ),
+            (
+                &["__serial_open", "__serial_abrir"],
+                VM::builtin_serial_open,
+            ),
+            (&["__json_parse", "__json_parsear"], VM::builtin_json_parse),
+            (
+                &["__json_stringify", "__json_texto"],
+                VM::builtin_json_stringify,
+            ),
+            (&["__js_call", "__js_llamar"], VM::builtin_js_call),
+            (&["__js_eval", "__js_evaluar"], VM::builtin_js_eval),
+        ];
+        #[cfg(feature = "full")]
+        table.push((
+            &["__tcp_connect", "__tcp_conectar"],
+            VM::builtin_tcp_connect,
+        ));
+        #[cfg(feature = "full")]
+        table.push((&["__tcp_listen", "__tcp_escuchar"], VM::builtin_tcp_listen));
+        #[cfg(feature = "full")]
+        table.push((&["__tcp_accept", "__tcp_aceptar"], VM::builtin_tcp_accept));
+        #[cfg(feature = "full")]
+        table.push((&["__http_get", "__http_obtener"], VM::builtin_http_get));
+        #[cfg(feature = "full")]
+        table.push((&["__http_post", "__http_enviar"], VM::builtin_http_post));
+        #[cfg(feature = "full")]
+        table.push((
+            &["__http_server_addr", "__http_servidor_direccion"],
+            VM::builtin_http_server_addr,
+        ));
+        #[cfg(feature = "full")]
+        table.push((
+            &["__http_server_stop", "__http_servidor_detener"],
+            VM::builtin_http_server_stop,
+        ));
+        #[cfg(feature = "full")]
+        table.push((
+            &["__http_server_wait", "__http_servidor_esperar"],
+            VM::builtin_http_server_wait,
+        ));
+        #[cfg(feature = "full")]
+        table.push((&["__ffi_cargar", "__ffi_load"], VM::builtin_ffi_cargar));
+        #[cfg(feature = "full")]
+        table.push((&["__ffi_llamar", "__ffi_call"], VM::builtin_ffi_llamar));
+        #[cfg(feature = "full")]
+        table.push((&["__ffi_llamar_nv"], VM::builtin_ffi_llamar_nv));
+        #[cfg(feature = "full")]
+        table.push((&["__ffi_asignar", "__ffi_alloc"], VM::builtin_ffi_asignar));
+        #[cfg(feature = "full")]
+        table.push((&["__ffi_liberar", "__ffi_free"], VM::builtin_ffi_liberar));
+        #[cfg(feature = "full")]
+        table.push((&["__ffi_escribir", "__ffi_write"], VM::builtin_ffi_escribir));
+        #[cfg(feature = "full")]
+        table.push((&["__ffi_leer", "__ffi_read"], VM::builtin_ffi_leer));
+        #[cfg(feature = "full")]
+        table.push((&["__ffi_peek", "__ffi_peek_u32"], VM::builtin_ffi_peek));
+        #[cfg(feature = "full")]
+        table.push((&["__ffi_peek64", "__ffi_peek_ptr"], VM::builtin_ffi_peek64));
+        #[cfg(feature = "full")]
+        table.push((
+            &["__ffi_peek_byte", "__ffi_peek_u8"],
+            VM::builtin_ffi_peek_byte,
+        ));
+        #[cfg(feature = "full")]
+        table.push((&["__ffi_poke", "__ffi_poke_u32"], VM::builtin_ffi_poke));
+        #[cfg(feature = "full")]
+        table.push((
+            &["__ffi_poke_byte", "__ffi_poke_u8"],
+            VM::builtin_ffi_poke_byte,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__ffi_asm"], VM::builtin_ffi_asm));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__ffi_c_eval"], VM::builtin_ffi_c_eval));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__ffi_rust_eval"], VM::builtin_ffi_rust_eval));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__self_healing_registrar_parche"],
+            VM::builtin_self_healing_registrar_parche,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__self_healing_invocar"],
+            VM::builtin_self_healing_invocar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__self_healing_estado"], VM::builtin_self_healing_estado));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__hash_sha256"], VM::builtin_hash_sha256));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__hash_sha512"], VM::builtin_hash_sha512));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__aes_encriptar", "__aes_encrypt"],
+            VM::builtin_aes_encriptar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__aes_desencriptar", "__aes_decrypt"],
+            VM::builtin_aes_desencriptar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__aes_gcm_encriptar", "__aes_gcm_encrypt"],
+            VM::builtin_aes_gcm_encriptar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__aes_gcm_desencriptar", "__aes_gcm_decrypt"],
+            VM::builtin_aes_gcm_desencriptar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__hmac_sha256"], VM::builtin_hmac_sha256));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__hmac_sha512"], VM::builtin_hmac_sha512));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__pbkdf2"], VM::builtin_pbkdf2));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__hkdf"], VM::builtin_hkdf));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__bytes_aleatorios", "__random_bytes"],
+            VM::builtin_bytes_aleatorios,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__jwt_codificar", "__jwt_encode"],
+            VM::builtin_jwt_codificar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__jwt_decodificar", "__jwt_decode"],
+            VM::builtin_jwt_decodificar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__fs_listar", "__fs_listdir"], VM::builtin_fs_listar));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__env_listar", "__env_list"], VM::builtin_env_listar));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__tiempo_formatear", "__time_format"],
+            VM::builtin_tiempo_formatear,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__tiempo_parsear", "__time_parse"],
+            VM::builtin_tiempo_parsear,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__tiempo_diferencia", "__time_diff"],
+            VM::builtin_tiempo_diferencia,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__coro_crear", "__coro_create"], VM::builtin_coro_crear));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__coro_ceder", "__coro_yield"], VM::builtin_coro_ceder));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__coro_reanudar", "__coro_resume"],
+            VM::builtin_coro_reanudar,
+        ));
+        #[cfg(feature = "full")]
+        table.push((&["__gui_ventana", "__gui_window"], VM::builtin_gui_ventana));
+        #[cfg(feature = "full")]
+        table.push((&["__gui_mostrar", "__gui_show"], VM::builtin_gui_mostrar));
+        #[cfg(feature = "full")]
+        table.push((&["__gui_cerrar", "__gui_close"], VM::builtin_gui_cerrar));
+        #[cfg(feature = "full")]
+        table.push((&["__gui_id", "__gui_hwnd"], VM::builtin_gui_id));
+        #[cfg(feature = "full")]
+        table.push((&["__gui_esperar", "__gui_poll"], VM::builtin_gui_esperar));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__tarea_lanzar", "__task_spawn"],
+            VM::builtin_tarea_lanzar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__tarea_esperar", "__task_await"],
+            VM::builtin_tarea_esperar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__timezone_info", "__zona_info"],
+            VM::builtin_timezone_info,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__duration_new", "__duracion_nueva"],
+            VM::builtin_duration_new,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__duration_secs", "__duracion_segundos"],
+            VM::builtin_duration_secs,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__calendar_hijri", "__calendario_hijri"],
+            VM::builtin_calendar_hijri,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__calendar_persian", "__calendario_persa"],
+            VM::builtin_calendar_persian,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__leer_archivo_async", "__file_read_async"],
+            VM::builtin_leer_archivo_async,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__escribir_archivo_async", "__file_write_async"],
+            VM::builtin_escribir_archivo_async,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__timer_delay", "__temporizador_esperar"],
+            VM::builtin_timer_delay,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__tcp_connect_async", "__tcp_conectar_async"],
+            VM::builtin_tcp_connect_async,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__dormir", "__sleep"], VM::builtin_dormir));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__hilo_lanzar", "__thread_spawn"],
+            VM::builtin_hilo_lanzar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__hilo_esperar", "__thread_join"],
+            VM::builtin_hilo_esperar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__canal_nuevo", "__channel_new"], VM::builtin_canal_nuevo));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__canal_enviar", "__channel_send"],
+            VM::builtin_canal_enviar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__canal_recibir", "__channel_recv"],
+            VM::builtin_canal_recibir,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__mutex_nuevo", "__mutex_new"], VM::builtin_mutex_nuevo));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__mutex_bloquear", "__mutex_lock"],
+            VM::builtin_mutex_bloquear,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__stream_desde", "__stream_from"],
+            VM::builtin_stream_desde,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__stream_mapear", "__stream_map"],
+            VM::builtin_stream_mapear,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__stream_filtrar", "__stream_filter"],
+            VM::builtin_stream_filtrar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__stream_colectar", "__stream_collect"],
+            VM::builtin_stream_colectar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__par_mapear", "__par_map"], VM::builtin_par_mapear));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__par_unir", "__par_join"], VM::builtin_par_unir));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__actor_nuevo", "__actor_new"], VM::builtin_actor_nuevo));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__actor_enviar", "__actor_send"],
+            VM::builtin_actor_enviar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__actor_recibir", "__actor_recv"],
+            VM::builtin_actor_recibir,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__actor_monitorear", "__actor_monitor"],
+            VM::builtin_actor_monitorear,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__actor_enlazar", "__actor_link"],
+            VM::builtin_actor_enlazar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__actor_detener", "__actor_stop"],
+            VM::builtin_actor_detener,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__actor_vivo", "__actor_alive"], VM::builtin_actor_vivo));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__generador_nuevo", "__generator_new"],
+            VM::builtin_generador_nuevo,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__generador_siguiente", "__generator_next"],
+            VM::builtin_generador_siguiente,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__seleccionar", "__select"], VM::builtin_seleccionar));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__scope_nuevo", "__scope_new"], VM::builtin_scope_nuevo));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__scope_lanzar", "__scope_spawn"],
+            VM::builtin_scope_lanzar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__scope_cancelar", "__scope_cancel"],
+            VM::builtin_scope_cancelar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__scope_esperar", "__scope_join"],
+            VM::builtin_scope_esperar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__scope_ejecutar", "__scope_run"],
+            VM::builtin_scope_ejecutar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__supervisor_nuevo", "__supervisor_new"],
+            VM::builtin_supervisor_nuevo,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__supervisor_agregar", "__supervisor_add"],
+            VM::builtin_supervisor_agregar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__supervisor_iniciar", "__supervisor_start"],
+            VM::builtin_supervisor_iniciar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__supervisor_detener", "__supervisor_stop"],
+            VM::builtin_supervisor_detener,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__supervisor_reinicios", "__supervisor_restarts"],
+            VM::builtin_supervisor_reinicios,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__supervisor_activo", "__supervisor_active"],
+            VM::builtin_supervisor_activo,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__cluster_conectar", "__cluster_connect"],
+            VM::builtin_cluster_conectar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__cluster_enviar", "__cluster_send"],
+            VM::builtin_cluster_enviar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__cluster_nodo", "__cluster_node"],
+            VM::builtin_cluster_nodo,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__cluster_direccion", "__cluster_addr"],
+            VM::builtin_cluster_direccion,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__cluster_registrar", "__cluster_register"],
+            VM::builtin_cluster_registrar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__cluster_monitorear", "__cluster_monitor"],
+            VM::builtin_cluster_monitorear,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__cluster_nodos", "__cluster_nodes"],
+            VM::builtin_cluster_nodos,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__cluster_detener", "__cluster_stop"],
+            VM::builtin_cluster_detener,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__rwlock_nuevo", "__rwlock_new"],
+            VM::builtin_rwlock_nuevo,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__rwlock_leer", "__rwlock_read"], VM::builtin_rwlock_leer));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__rwlock_escribir", "__rwlock_write"],
+            VM::builtin_rwlock_escribir,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__mutex_adquirir", "__mutex_acquire"],
+            VM::builtin_mutex_adquirir,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__mutex_intentar", "__mutex_try_lock"],
+            VM::builtin_mutex_intentar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__mutex_liberar", "__mutex_release"],
+            VM::builtin_mutex_liberar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__atomico_nuevo", "__atomic_new"],
+            VM::builtin_atomico_nuevo,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__atomico_cargar", "__atomic_load"],
+            VM::builtin_atomico_cargar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__atomico_guardar", "__atomic_store"],
+            VM::builtin_atomico_guardar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__atomico_sumar", "__atomic_fetch_add"],
+            VM::builtin_atomico_sumar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__atomico_intercambiar", "__atomic_swap"],
+            VM::builtin_atomico_intercambiar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &[
+                "__atomico_comparar_intercambiar",
+                "__atomic_compare_exchange",
+            ],
+            VM::builtin_atomico_comparar_intercambiar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__condvar_nuevo", "__condvar_new"],
+            VM::builtin_condvar_nuevo,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__condvar_esperar", "__condvar_wait"],
+            VM::builtin_condvar_esperar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__condvar_notificar", "__condvar_notify_one"],
+            VM::builtin_condvar_notificar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__condvar_notificar_todos", "__condvar_notify_all"],
+            VM::builtin_condvar_notificar_todos,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__barrera_nueva", "__barrier_new"],
+            VM::builtin_barrera_nueva,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((
+            &["__barrera_esperar", "__barrier_wait"],
+            VM::builtin_barrera_esperar,
+        ));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__arc_nuevo", "__arc_new"], VM::builtin_arc_nuevo));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__arc_obtener", "__arc_get"], VM::builtin_arc_obtener));
+        #[cfg(any(feature = "extra", feature = "full"))]
+        table.push((&["__arc_asignar", "__arc_set"], VM::builtin_arc_asignar));
+        table
+    }
 
-        // ██ Utility builtins (core — disponibles también en wasm sin feature "full") ██
-        if name == "__tipo_de" || name == "__typeof" {
-            let val = args.first().cloned().unwrap_or(Value::Void);
-            let type_name = match &val {
-                Value::Int(_) => "entero",
-                Value::Float(_) => "decimal",
-                Value::Bool(_) => "booleano",
-                Value::Str(_) => "texto",
-                Value::Array(_) => "lista",
-                Value::Map(_) => "diccionario",
-                Value::Void => "nulo",
-                Value::Func(_) => "funcion",
-                Value::Struct { .. } => "estructura",
-                Value::Enum { .. } => "enumeracion",
-                Value::Tuple(_) => "tupla",
-                Value::Exito(_) => "exito",
-                Value::Error(_) => "error",
-                Value::Opcion(_) => "opcion",
-            };
-            self.push(Value::str(type_name.to_string()));
-            return Some(Ok(()));
+    fn builtin_imprimir(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut combined = String::new();
+        for arg in args {
+            combined.push_str(&format!("{}", arg));
         }
+        self.emit(combined)?;
+        self.push(Value::Void);
+        Ok(())
+    }
 
-        if name == "__str_a_entero" || name == "__texto_a_entero" {
-            let mut s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            if let Some(dot) = s.find('.') {
-                s.truncate(dot);
-            }
-            match s.parse::<i64>() {
-                Ok(n) => self.push(Value::Int(n)),
-                Err(_) => self.push(Value::Int(0)),
-            }
-            return Some(Ok(()));
-        }
+    fn builtin_leer(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        self.push(Value::str(String::new()));
+        Ok(())
+    }
+
+    fn builtin_lanzar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let msg = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        Err(VmError::Runtime(msg))
+    }
 
-        if name == "largo" || name == "len" {
-            match args.clone().into_iter().next() {
-                Some(Value::Array(v)) => self.push(Value::Int(v.len() as i64)),
-                Some(Value::Str(s)) => self.push(Value::Int(s.chars().count() as i64)),
-                Some(other) => {
-                    return builtin_err(VmError::TypeError(format!(
-                        "'largo' espera lista o texto, no {:?}",
+    fn builtin_abs(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        match args.first() {
+            Some(Value::Int(i)) => self.push(Value::Int(i.abs())),
+            Some(Value::Float(f)) => self.push(Value::Float(f.abs())),
+            Some(other) => {
+                if let Some(n) = other.as_num() {
+                    self.push(Value::Float(n.abs()));
+                } else {
+                    return Err(VmError::TypeError(format!(
+                        "'abs' espera un número, no {:?}",
                         other
-                    )))
-                }
-                None => {
-                    return builtin_err(VmError::TypeError(
-                        "'largo' espera 1 argumento".to_string(),
-                    ))
+                    )));
                 }
             }
-            return Some(Ok(()));
+            None => return Err(VmError::TypeError("'abs' espera 1 argumento".to_string())),
         }
+        Ok(())
+    }
 
-        if name == "agregar" || name == "push" {
-            let mut iter = args.clone().into_iter();
-            let list = iter.next().unwrap_or(Value::arr(vec![]));
-            let item = iter.next().unwrap_or(Value::Void);
-            match list {
-                Value::Array(mut v) => {
-                    Arc::make_mut(&mut v).push(item);
-                    self.push(Value::Array(v));
-                }
-                _ => {
-                    return builtin_err(VmError::TypeError(
-                        "'agregar' espera una lista".to_string(),
-                    ))
-                }
-            }
-            return Some(Ok(()));
+    fn builtin_min(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
+        let b = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0);
+        if args
+            .first()
+            .map(|v| matches!(v, Value::Int(_)))
+            .unwrap_or(false)
+            && args
+                .get(1)
+                .map(|v| matches!(v, Value::Int(_)))
+                .unwrap_or(false)
+        {
+            self.push(Value::Int((a as i64).min(b as i64)));
+        } else {
+            self.push(Value::Float(a.min(b)));
         }
+        Ok(())
+    }
 
-        if name == "__str_len" || name == "__str_longitud" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::Int(s.len() as i64));
-            return Some(Ok(()));
+    fn builtin_max(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
+        let b = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0);
+        if args
+            .first()
+            .map(|v| matches!(v, Value::Int(_)))
+            .unwrap_or(false)
+            && args
+                .get(1)
+                .map(|v| matches!(v, Value::Int(_)))
+                .unwrap_or(false)
+        {
+            self.push(Value::Int((a as i64).max(b as i64)));
+        } else {
+            self.push(Value::Float(a.max(b)));
         }
+        Ok(())
+    }
 
-        if name == "__str_upper" || name == "__str_mayusculas" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::str(s.to_uppercase()));
-            return Some(Ok(()));
-        }
+    fn builtin_raiz(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
+        self.push(Value::Float(a.sqrt()));
+        Ok(())
+    }
 
-        if name == "__str_lower" || name == "__str_minusculas" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::str(s.to_lowercase()));
-            return Some(Ok(()));
+    fn builtin_potencia(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
+        let b = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0);
+        if args
+            .first()
+            .map(|v| matches!(v, Value::Int(_)))
+            .unwrap_or(false)
+            && args
+                .get(1)
+                .map(|v| matches!(v, Value::Int(_)))
+                .unwrap_or(false)
+            && b >= 0.0
+        {
+            self.push(Value::Int((a as i64).pow(b as u32)));
+        } else {
+            self.push(Value::Float(a.powf(b)));
         }
+        Ok(())
+    }
 
-        if name == "__str_trim" || name == "__str_recortar" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::str(s.trim().to_string()));
-            return Some(Ok(()));
-        }
+    fn builtin_piso(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
+        self.push(Value::Int(a.floor() as i64));
+        Ok(())
+    }
 
-        if name == "__str_contains" || name == "__str_contiene" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let sub = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::Bool(s.contains(&sub)));
-            return Some(Ok(()));
-        }
+    fn builtin_techo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
+        self.push(Value::Int(a.ceil() as i64));
+        Ok(())
+    }
 
-        if name == "__str_split" || name == "__str_dividir" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let delim = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let parts: Vec<Value> = if delim.is_empty() {
-                s.chars().map(|c| Value::str(c.to_string())).collect()
-            } else {
-                s.split(&delim).map(|p| Value::str(p.to_string())).collect()
-            };
-            self.push(Value::arr(parts));
-            return Some(Ok(()));
-        }
+    fn builtin_redondear(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let a = args.first().and_then(|v| v.as_num()).unwrap_or(0.0);
+        self.push(Value::Int(a.round() as i64));
+        Ok(())
+    }
 
-        if name == "__str_ord" || name == "__str_codigo" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let codes: Vec<Value> = s.chars().map(|c| Value::Int(c as i64)).collect();
-            self.push(Value::arr(codes));
-            return Some(Ok(()));
-        }
+    fn builtin_a_texto(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::str(s));
+        Ok(())
+    }
 
-        if name == "__str_slice" || name == "__str_subcadena" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let start = args
-                .get(1)
-                .and_then(|v| match v {
-                    Value::Int(i) => Some(*i as usize),
-                    _ => v.as_num().map(|f| f as usize),
-                })
-                .unwrap_or(0);
-            let end = args
-                .get(2)
-                .and_then(|v| match v {
-                    Value::Int(i) => {
-                        if *i == -1 {
-                            Some(s.len())
-                        } else {
-                            Some(*i as usize)
-                        }
-                    }
-                    _ => v.as_num().map(|f| f as usize),
-                })
-                .unwrap_or(s.len());
-            let start = start.min(s.len());
-            let end = end.min(s.len()).max(start);
-            let sub: String = s.chars().skip(start).take(end - start).collect();
-            self.push(Value::str(sub));
-            return Some(Ok(()));
-        }
-
-        if name == "__str_concat_list" || name == "__str_concatenar_lista" {
-            let list = args.first().cloned().unwrap_or(Value::arr(vec![]));
-            match list {
-                Value::Array(items) => {
-                    let result = items.iter().map(|v| format!("{}", v)).collect::<String>();
-                    self.push(Value::str(result));
-                }
-                _ => {
-                    return builtin_err(VmError::TypeError(
-                        "__str_concat_list espera una lista".to_string(),
-                    ))
-                }
-            }
-            return Some(Ok(()));
-        }
+    // ██ Utility builtins (core — disponibles también en wasm sin feature "full") ██
+    fn builtin_tipo_de(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let val = args.first().cloned().unwrap_or(Value::Void);
+        let type_name = val.type_name();
+        self.push(Value::str(type_name.to_string()));
+        Ok(())
+    }
 
-        if name == "__str_starts_with" || name == "__str_empieza_con" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let prefix = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::Bool(s.starts_with(&prefix)));
-            return Some(Ok(()));
+    fn builtin_str_a_entero(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(dot) = s.find('.') {
+            s.truncate(dot);
         }
-
-        if name == "__str_to_chars" || name == "__str_a_caracteres" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let chars: Vec<Value> = s.chars().map(|c| Value::str(c.to_string())).collect();
-            self.push(Value::arr(chars));
-            return Some(Ok(()));
+        match s.parse::<i64>() {
+            Ok(n) => self.push(Value::Int(n)),
+            Err(_) => self.push(Value::Int(0)),
         }
+        Ok(())
+    }
 
-        if name == "__str_reemplazar" || name == "__str_replace" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let from = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let to = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::str(s.replace(&from, &to)));
-            return Some(Ok(()));
-        }
-
-        if name == "__str_subcadena_chars" || name == "__str_slice_chars" {
-            let cs = match args.first() {
-                Some(Value::Array(a)) => a.clone(),
-                _ => Arc::new(vec![]),
-            };
-            let st = args
-                .get(1)
-                .and_then(|v| v.as_num())
-                .map(|f| f as i64)
-                .unwrap_or(0);
-            let en = args
-                .get(2)
-                .and_then(|v| v.as_num())
-                .map(|f| f as i64)
-                .unwrap_or(-1);
-            let n = cs.len() as i64;
-            let st = st.max(0).min(n);
-            let en = if en < 0 { n } else { en.max(0).min(n) };
-            let mut out = String::new();
-            for c in cs.iter().skip(st as usize).take((en - st).max(0) as usize) {
-                out.push_str(&format!("{}", c));
-            }
-            self.push(Value::str(out));
-            return Some(Ok(()));
-        }
-
-        if name == "__str_chr" || name == "__str_caracter" {
-            let n = args
-                .first()
-                .and_then(|v| match v {
-                    Value::Int(i) => Some(*i),
-                    _ => v.as_num().map(|f| f as i64),
-                })
-                .unwrap_or(0);
-            let c = char::from_u32(n as u32)
-                .map(|c| c.to_string())
-                .unwrap_or_default();
-            self.push(Value::str(c));
-            return Some(Ok(()));
+    fn builtin_largo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        match args.clone().into_iter().next() {
+            Some(Value::Array(v)) => self.push(Value::Int(v.len() as i64)),
+            Some(Value::Str(s)) => self.push(Value::Int(s.chars().count() as i64)),
+            Some(other) => {
+                return Err(VmError::TypeError(format!(
+                    "'largo' espera lista o texto, no {:?}",
+                    other
+                )))
+            }
+            None => return Err(VmError::TypeError("'largo' espera 1 argumento".to_string())),
         }
+        Ok(())
+    }
 
-        if name == "__file_read" || name == "__leer_archivo" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match std::fs::read_to_string(&path) {
-                Ok(content) => self.push(Value::Exito(Box::new(Value::str(content)))),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+    fn builtin_agregar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut iter = args.clone().into_iter();
+        let list = iter.next().unwrap_or(Value::arr(vec![]));
+        let item = iter.next().unwrap_or(Value::Void);
+        match list {
+            Value::Array(mut v) => {
+                Arc::make_mut(&mut v).push(item);
+                self.push(Value::Array(v));
             }
-            return Some(Ok(()));
+            _ => return Err(VmError::TypeError("'agregar' espera una lista".to_string())),
         }
+        Ok(())
+    }
 
-        if name == "__file_write" || name == "__escribir_archivo" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let content = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            match std::fs::write(&path, &content) {
-                Ok(_) => self.push(Value::Exito(Box::new(Value::Bool(true)))),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
-        }
+    fn builtin_str_len(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::Int(s.len() as i64));
+        Ok(())
+    }
 
-        if name == "__file_exists" || name == "__existe_archivo" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::Bool(std::path::Path::new(&path).exists()));
-            return Some(Ok(()));
-        }
+    fn builtin_str_upper(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::str(s.to_uppercase()));
+        Ok(())
+    }
 
-        if name == "__file_size" || name == "__tamano_archivo" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match std::fs::metadata(&path) {
-                Ok(meta) => self.push(Value::Int(meta.len() as i64)),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+    fn builtin_str_lower(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::str(s.to_lowercase()));
+        Ok(())
+    }
+
+    fn builtin_str_trim(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::str(s.trim().to_string()));
+        Ok(())
+    }
+
+    fn builtin_str_contains(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let sub = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::Bool(s.contains(&sub)));
+        Ok(())
+    }
+
+    fn builtin_str_split(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let delim = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let parts: Vec<Value> = if delim.is_empty() {
+            s.chars().map(|c| Value::str(c.to_string())).collect()
+        } else {
+            s.split(&delim).map(|p| Value::str(p.to_string())).collect()
+        };
+        self.push(Value::arr(parts));
+        Ok(())
+    }
+
+    fn builtin_str_ord(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let codes: Vec<Value> = s.chars().map(|c| Value::Int(c as i64)).collect();
+        self.push(Value::arr(codes));
+        Ok(())
+    }
+
+    fn builtin_str_slice(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let start = args
+            .get(1)
+            .and_then(|v| match v {
+                Value::Int(i) => Some(*i as usize),
+                _ => v.as_num().map(|f| f as usize),
+            })
+            .unwrap_or(0);
+        let end = args
+            .get(2)
+            .and_then(|v| match v {
+                Value::Int(i) => {
+                    if *i == -1 {
+                        Some(s.len())
+                    } else {
+                        Some(*i as usize)
+                    }
+                }
+                _ => v.as_num().map(|f| f as usize),
+            })
+            .unwrap_or(s.len());
+        let start = start.min(s.len());
+        let end = end.min(s.len()).max(start);
+        let sub: String = s.chars().skip(start).take(end - start).collect();
+        self.push(Value::str(sub));
+        Ok(())
+    }
+
+    fn builtin_str_concat_list(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let list = args.first().cloned().unwrap_or(Value::arr(vec![]));
+        match list {
+            Value::Array(items) => {
+                let result = items.iter().map(|v| format!("{}", v)).collect::<String>();
+                self.push(Value::str(result));
+            }
+            _ => {
+                return Err(VmError::TypeError(
+                    "__str_concat_list espera una lista".to_string(),
+                ))
             }
-            return Some(Ok(()));
         }
+        Ok(())
+    }
 
-        if name == "__file_append" || name == "__agregar_archivo" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let content = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            use std::io::Write;
-            match std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&path)
-            {
-                Ok(mut file) => match file.write_all(content.as_bytes()) {
-                    Ok(_) => self.push(Value::Exito(Box::new(Value::Bool(true)))),
-                    Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-                },
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
+    fn builtin_str_starts_with(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let prefix = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::Bool(s.starts_with(&prefix)));
+        Ok(())
+    }
+
+    fn builtin_str_to_chars(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let chars: Vec<Value> = s.chars().map(|c| Value::str(c.to_string())).collect();
+        self.push(Value::arr(chars));
+        Ok(())
+    }
+
+    fn builtin_str_reemplazar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let from = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let to = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::str(s.replace(&from, &to)));
+        Ok(())
+    }
+
+    fn builtin_str_subcadena_chars(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let cs = match args.first() {
+            Some(Value::Array(a)) => a.clone(),
+            _ => Arc::new(vec![]),
+        };
+        let st = args
+            .get(1)
+            .and_then(|v| v.as_num())
+            .map(|f| f as i64)
+            .unwrap_or(0);
+        let en = args
+            .get(2)
+            .and_then(|v| v.as_num())
+            .map(|f| f as i64)
+            .unwrap_or(-1);
+        let n = cs.len() as i64;
+        let st = st.max(0).min(n);
+        let en = if en < 0 { n } else { en.max(0).min(n) };
+        let mut out = String::new();
+        for c in cs.iter().skip(st as usize).take((en - st).max(0) as usize) {
+            out.push_str(&format!("{}", c));
+        }
+        self.push(Value::str(out));
+        Ok(())
+    }
+
+    fn builtin_str_chr(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let n = args
+            .first()
+            .and_then(|v| match v {
+                Value::Int(i) => Some(*i),
+                _ => v.as_num().map(|f| f as i64),
+            })
+            .unwrap_or(0);
+        let c = char::from_u32(n as u32)
+            .map(|c| c.to_string())
+            .unwrap_or_default();
+        self.push(Value::str(c));
+        Ok(())
+    }
+
+    fn builtin_file_read(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.check_file_alloc(&path, 1)?;
+        match std::fs::read_to_string(&path) {
+            Ok(content) => self.push(Value::Exito(Box::new(Value::str(content)))),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__file_write_binary" || name == "__escribir_archivo_bin" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let bytes = match args.get(1) {
-                Some(Value::Array(arr)) => arr
-                    .iter()
-                    .filter_map(|v| match v {
-                        Value::Int(n) if *n >= 0 && *n <= 255 => Some(*n as u8),
-                        _ => None,
-                    })
-                    .collect::<Vec<u8>>(),
-                _ => Vec::new(),
-            };
-            match std::fs::write(&path, &bytes) {
-                Ok(_) => self.push(Value::Exito(Box::new(Value::Bool(true)))),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
+    fn builtin_file_write(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let content = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        match std::fs::write(&path, &content) {
+            Ok(_) => self.push(Value::Exito(Box::new(Value::Bool(true)))),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__file_bytes" || name == "__leer_bytes" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match std::fs::read(&path) {
-                Ok(data) => self.push(Value::arr(
-                    data.iter().map(|&b| Value::Int(b as i64)).collect(),
-                )),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
+    fn builtin_file_exists(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::Bool(std::path::Path::new(&path).exists()));
+        Ok(())
+    }
+
+    fn builtin_file_size(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        match std::fs::metadata(&path) {
+            Ok(meta) => self.push(Value::Int(meta.len() as i64)),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__a_f64_bytes" || name == "__bytes_a_f64" {
-            let mut buf = [0u8; 8];
-            if let Some(Value::Array(arr)) = args.first() {
-                for (i, b) in arr.iter().take(8).enumerate() {
-                    if let Value::Int(n) = b {
-                        buf[i] = *n as u8;
-                    }
-                }
-            }
-            self.push(Value::Float(f64::from_le_bytes(buf)));
-            return Some(Ok(()));
+    fn builtin_file_append(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let content = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        use std::io::Write;
+        match std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+        {
+            Ok(mut file) => match file.write_all(content.as_bytes()) {
+                Ok(_) => self.push(Value::Exito(Box::new(Value::Bool(true)))),
+                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+            },
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__num_a_f64_bytes" || name == "__numero_a_bytes_f64" {
-            let n = args
-                .first()
-                .map(|v| match v {
-                    Value::Int(i) => *i as f64,
-                    Value::Float(f) => *f,
-                    _ => 0.0,
-                })
-                .unwrap_or(0.0);
-            let bytes: Vec<Value> = n
-                .to_le_bytes()
+    fn builtin_file_write_binary(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let bytes = match args.get(1) {
+            Some(Value::Array(arr)) => arr
                 .iter()
-                .map(|&b| Value::Int(b as i64))
-                .collect();
-            self.push(Value::arr(bytes));
-            return Some(Ok(()));
-        }
-
-        if name == "__codegen_a_nvc" {
-            // Takes a codegen map, returns Array<Int> of .nvc bytes
-            let cg = args.first().cloned().unwrap_or(Value::Void);
-            let result = self.codegen_to_nvc(cg);
-            return match result {
-                Ok(bytes) => {
-                    self.push(bytes);
-                    Some(Ok(()))
-                }
-                Err(_) => {
-                    self.push(Value::Error(Box::new(Value::str("codegen_to_nvc failed"))));
-                    Some(Ok(()))
-                }
-            };
+                .filter_map(|v| match v {
+                    Value::Int(n) if *n >= 0 && *n <= 255 => Some(*n as u8),
+                    _ => None,
+                })
+                .collect::<Vec<u8>>(),
+            _ => Vec::new(),
+        };
+        match std::fs::write(&path, &bytes) {
+            Ok(_) => self.push(Value::Exito(Box::new(Value::Bool(true)))),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__compile_nv" || name == "__compilar_nv" {
-            // Compile a .nv source file to .nvc bytes using the native Rust pipeline
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let source = match std::fs::read_to_string(&path) {
-                Ok(s) => s,
-                Err(e) => {
-                    self.push(Value::Error(Box::new(Value::str(format!("IO: {}", e)))));
-                    return Some(Ok(()));
-                }
-            };
-            let base_path = std::path::Path::new(&path);
-            let mut lib_dirs = vec![std::path::PathBuf::from("stdlib")];
-            if let Ok(cwd) = std::env::current_dir() {
-                lib_dirs.push(cwd.join("stdlib"));
-            }
-            let mut loader = lumen_sema::loader::ModuleLoader::new(lib_dirs);
-            let mut program = match loader.resolve_imports(&source, base_path) {
-                Ok(p) => p,
-                Err(e) => {
-                    self.push(Value::Error(Box::new(Value::str(format!(
-                        "Loader error: {:?}",
-                        e
-                    )))));
-                    return Some(Ok(()));
-                }
-            };
-            let sema = lumen_sema::sema::SemanticAnalyzer::new();
-            let sem_errors = sema.analyze(&mut program);
-            if !sem_errors.is_empty() {
-                let msg = sem_errors
-                    .iter()
-                    .map(|e| e.message.to_string())
-                    .collect::<Vec<_>>()
-                    .join("; ");
-                self.push(Value::Error(Box::new(Value::str(format!(
-                    "Sem error: {}",
-                    msg
-                )))));
-                return Some(Ok(()));
-            }
-            let builder = lumen_ir::builder::IRBuilder::new();
-            let ir = builder.build(&program);
-            let codegen = lumen_codegen::codegen::Codegen::new();
-            let (bytecode, _) = codegen.generate(&ir);
-            let bytes_vec = bytecode.encode();
-            let bytes: Vec<Value> = bytes_vec
-                .into_iter()
-                .map(|b| Value::Int(b as i64))
-                .collect();
-            self.push(Value::arr(bytes));
-            return Some(Ok(()));
+    fn builtin_file_bytes(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.check_file_alloc(&path, std::mem::size_of::<Value>())?;
+        match std::fs::read(&path) {
+            Ok(data) => self.push(Value::arr(
+                data.iter().map(|&b| Value::Int(b as i64)).collect(),
+            )),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__time_now" || name == "__tiempo_ahora" {
-            #[cfg(not(target_arch = "wasm32"))]
-            let secs: i64 = {
-                use std::time::{SystemTime, UNIX_EPOCH};
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap_or_default();
-                now.as_secs() as i64
-            };
-            #[cfg(target_arch = "wasm32")]
-            let secs: i64 = {
-                if let Some(eval) = JS_EVAL.get() {
-                    eval("String(Math.floor(Date.now()/1000))")
-                        .trim()
-                        .parse()
-                        .unwrap_or(0)
-                } else {
-                    0
+    fn builtin_a_f64_bytes(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut buf = [0u8; 8];
+        if let Some(Value::Array(arr)) = args.first() {
+            for (i, b) in arr.iter().take(8).enumerate() {
+                if let Value::Int(n) = b {
+                    buf[i] = *n as u8;
                 }
-            };
-            self.push(Value::Int(secs));
-            return Some(Ok(()));
+            }
         }
+        self.push(Value::Float(f64::from_le_bytes(buf)));
+        Ok(())
+    }
 
-        if name == "__list_reverse" || name == "__lista_invertir" {
-            let mut arr = match args.clone().into_iter().next() {
-                Some(Value::Array(v)) => v,
-                Some(other) => {
-                    return builtin_err(VmError::TypeError(format!(
-                        "__list_reverse espera una lista, no {:?}",
-                        other
-                    )))
-                }
-                None => {
-                    return builtin_err(VmError::TypeError(
-                        "__list_reverse espera 1 argumento".to_string(),
-                    ))
-                }
-            };
-            Arc::make_mut(&mut arr).reverse();
-            self.push(Value::Array(arr));
-            return Some(Ok(()));
-        }
+    fn builtin_num_a_f64_bytes(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let n = args
+            .first()
+            .map(|v| match v {
+                Value::Int(i) => *i as f64,
+                Value::Float(f) => *f,
+                _ => 0.0,
+            })
+            .unwrap_or(0.0);
+        let bytes: Vec<Value> = n
+            .to_le_bytes()
+            .iter()
+            .map(|&b| Value::Int(b as i64))
+            .collect();
+        self.push(Value::arr(bytes));
+        Ok(())
+    }
 
-        if name == "__list_sort" || name == "__lista_ordenar" {
-            let mut arr = match args.clone().into_iter().next() {
-                Some(Value::Array(v)) => v,
-                Some(other) => {
-                    return builtin_err(VmError::TypeError(format!(
-                        "__list_sort espera una lista, no {:?}",
-                        other
-                    )))
-                }
-                None => {
-                    return builtin_err(VmError::TypeError(
-                        "__list_sort espera 1 argumento".to_string(),
-                    ))
-                }
-            };
-            Arc::make_mut(&mut arr).sort_by(|a, b| {
-                let an = a.as_num().unwrap_or(f64::MAX);
-                let bn = b.as_num().unwrap_or(f64::MAX);
-                an.partial_cmp(&bn).unwrap_or(std::cmp::Ordering::Equal)
-            });
-            self.push(Value::Array(arr));
-            return Some(Ok(()));
-        }
-
-        if name == "__map_new" || name == "__map_nuevo" {
-            self.push(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            return Some(Ok(()));
-        }
-
-        if name == "__map_set" || name == "__map_poner" {
-            let mut it = args.clone().into_iter();
-            let m = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            let k = it.next().unwrap_or(Value::Void);
-            let v = it.next().unwrap_or(Value::Void);
-            match m {
-                Value::Map(mut m) => {
-                    m.insert(k, v);
-                    self.push(Value::Map(m));
-                }
-                _ => return builtin_err(VmError::TypeError("__map_set espera diccionario".into())),
+    fn builtin_codegen_a_nvc(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        // Takes a codegen map, returns Array<Int> of .nvc bytes
+        let cg = args.first().cloned().unwrap_or(Value::Void);
+        let result = self.codegen_to_nvc(cg);
+        match result {
+            Ok(bytes) => {
+                self.push(bytes);
+                Ok(())
+            }
+            Err(_) => {
+                self.push(Value::Error(Box::new(Value::str("codegen_to_nvc failed"))));
+                Ok(())
             }
-            return Some(Ok(()));
         }
+    }
 
-        if name == "__map_get" || name == "__map_obtener" {
-            let mut it = args.clone().into_iter();
-            let m = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            let k = it.next().unwrap_or(Value::Void);
-            match m {
-                Value::Map(m) => {
-                    self.push(m.get(&k).cloned().unwrap_or(Value::Void));
-                }
-                _ => return builtin_err(VmError::TypeError("__map_get espera diccionario".into())),
+    fn builtin_compile_nv(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        // Compile a .nv source file to .nvc bytes using the native Rust pipeline
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.check_file_alloc(&path, 1)?;
+        let source = match std::fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) => {
+                self.push(Value::Error(Box::new(Value::str(format!("IO: {}", e)))));
+                return Ok(());
+            }
+        };
+        let base_path = std::path::Path::new(&path);
+        let mut lib_dirs = vec![std::path::PathBuf::from("stdlib")];
+        if let Ok(cwd) = std::env::current_dir() {
+            lib_dirs.push(cwd.join("stdlib"));
+        }
+        let mut loader = lumen_sema::loader::ModuleLoader::new(lib_dirs);
+        let mut program = match loader.resolve_imports(&source, base_path) {
+            Ok(p) => p,
+            Err(e) => {
+                self.push(Value::Error(Box::new(Value::str(format!(
+                    "Loader error: {:?}",
+                    e
+                )))));
+                return Ok(());
             }
-            return Some(Ok(()));
+        };
+        let sema = lumen_sema::sema::SemanticAnalyzer::new();
+        let sem_errors = sema.analyze(&mut program);
+        if !sem_errors.is_empty() {
+            let msg = sem_errors
+                .iter()
+                .map(|e| e.message.to_string())
+                .collect::<Vec<_>>()
+                .join("; ");
+            self.push(Value::Error(Box::new(Value::str(format!(
+                "Sem error: {}",
+                msg
+            )))));
+            return Ok(());
         }
+        let builder = lumen_ir::builder::IRBuilder::new();
+        let ir = builder.build(&program);
+        let codegen = lumen_codegen::codegen::Codegen::new();
+        let (bytecode, _) = codegen.generate(&ir);
+        let bytes_vec = bytecode.encode();
+        let bytes: Vec<Value> = bytes_vec
+            .into_iter()
+            .map(|b| Value::Int(b as i64))
+            .collect();
+        self.push(Value::arr(bytes));
+        Ok(())
+    }
 
-        if name == "__map_len" || name == "__map_longitud" {
-            let m = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            match m {
-                Value::Map(m) => self.push(Value::Int(m.len() as i64)),
-                _ => return builtin_err(VmError::TypeError("__map_len espera diccionario".into())),
+    fn builtin_time_now(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let secs: i64 = {
+            use std::time::{SystemTime, UNIX_EPOCH};
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            now.as_secs() as i64
+        };
+        #[cfg(target_arch = "wasm32")]
+        let secs: i64 = {
+            if let Some(eval) = JS_EVAL.get() {
+                eval("String(Math.floor(Date.now()/1000))")
+                    .trim()
+                    .parse()
+                    .unwrap_or(0)
+            } else {
+                0
             }
-            return Some(Ok(()));
-        }
+        };
+        self.push(Value::Int(secs));
+        Ok(())
+    }
 
-        if name == "__map_keys" || name == "__map_claves" {
-            let m = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            match m {
-                Value::Map(m) => {
-                    let keys: Vec<Value> = m.into_iter().map(|(k, _)| k).collect();
-                    self.push(Value::arr(keys));
-                }
-                _ => {
-                    return builtin_err(VmError::TypeError("__map_keys espera diccionario".into()))
-                }
+    fn builtin_list_reverse(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut arr = match args.clone().into_iter().next() {
+            Some(Value::Array(v)) => v,
+            Some(other) => {
+                return Err(VmError::TypeError(format!(
+                    "__list_reverse espera una lista, no {:?}",
+                    other
+                )))
             }
-            return Some(Ok(()));
-        }
+            None => {
+                return Err(VmError::TypeError(
+                    "__list_reverse espera 1 argumento".to_string(),
+                ))
+            }
+        };
+        Arc::make_mut(&mut arr).reverse();
+        self.push(Value::Array(arr));
+        Ok(())
+    }
 
-        if name == "__map_contains" || name == "__map_contiene" {
-            let mut it = args.clone().into_iter();
-            let m = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            let k = it.next().unwrap_or(Value::Void);
-            match m {
-                Value::Map(m) => self.push(Value::Bool(m.contains_key(&k))),
-                _ => {
-                    return builtin_err(VmError::TypeError(
-                        "__map_contains espera diccionario".into(),
-                    ))
-                }
+    fn builtin_list_sort(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut arr = match args.clone().into_iter().next() {
+            Some(Value::Array(v)) => v,
+            Some(other) => {
+                return Err(VmError::TypeError(format!(
+                    "__list_sort espera una lista, no {:?}",
+                    other
+                )))
             }
-            return Some(Ok(()));
-        }
+            None => {
+                return Err(VmError::TypeError(
+                    "__list_sort espera 1 argumento".to_string(),
+                ))
+            }
+        };
+        Arc::make_mut(&mut arr).sort_by(|a, b| {
+            let an = a.as_num().unwrap_or(f64::MAX);
+            let bn = b.as_num().unwrap_or(f64::MAX);
+            an.partial_cmp(&bn).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.push(Value::Array(arr));
+        Ok(())
+    }
 
-        if name == "__set_new" || name == "__conjunto_nuevo" {
-            self.push(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            return Some(Ok(()));
+    fn builtin_map_new(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        self.push(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        Ok(())
+    }
+
+    fn builtin_map_set(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let m = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        let k = it.next().unwrap_or(Value::Void);
+        let v = it.next().unwrap_or(Value::Void);
+        match m {
+            Value::Map(mut m) => {
+                m.insert(k, v);
+                self.push(Value::Map(m));
+            }
+            _ => return Err(VmError::TypeError("__map_set espera diccionario".into())),
         }
+        Ok(())
+    }
 
-        if name == "__set_add" || name == "__conjunto_agregar" {
-            let mut it = args.clone().into_iter();
-            let s = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            let item = it.next().unwrap_or(Value::Void);
-            match s {
-                Value::Map(mut m) => {
-                    m.insert(item, Value::Bool(true));
-                    self.push(Value::Map(m));
-                }
-                _ => return builtin_err(VmError::TypeError("__set_add espera conjunto".into())),
-            }
-            return Some(Ok(()));
-        }
-
-        if name == "__set_has" || name == "__conjunto_tiene" {
-            let mut it = args.clone().into_iter();
-            let s = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            let item = it.next().unwrap_or(Value::Void);
-            match s {
-                Value::Map(m) => self.push(Value::Bool(m.contains_key(&item))),
-                _ => return builtin_err(VmError::TypeError("__set_has espera conjunto".into())),
-            }
-            return Some(Ok(()));
-        }
-
-        if name == "__set_union" || name == "__conjunto_unir" {
-            let mut it = args.clone().into_iter();
-            let a = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            let b = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            match (a, b) {
-                (Value::Map(mut m1), Value::Map(m2)) => {
-                    for (k, v) in m2 {
-                        if !m1.contains_key(&k) {
-                            m1.insert(k, v);
-                        }
-                    }
-                    self.push(Value::Map(m1));
-                }
-                _ => return builtin_err(VmError::TypeError("__set_union espera conjuntos".into())),
-            }
-            return Some(Ok(()));
-        }
-
-        if name == "__set_inter" || name == "__conjunto_interseccion" {
-            let mut it = args.clone().into_iter();
-            let a = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            let b = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            match (a, b) {
-                (Value::Map(m1), Value::Map(m2)) => {
-                    let r: ImMap<Value, Value, FixHasher> =
-                        m1.into_iter().filter(|(k, _)| m2.contains_key(k)).collect();
-                    self.push(Value::Map(r));
-                }
-                _ => return builtin_err(VmError::TypeError("__set_inter espera conjuntos".into())),
-            }
-            return Some(Ok(()));
-        }
-
-        if name == "__set_diff" || name == "__conjunto_diferencia" {
-            let mut it = args.clone().into_iter();
-            let a = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            let b = it
-                .next()
-                .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-            match (a, b) {
-                (Value::Map(m1), Value::Map(m2)) => {
-                    let r: ImMap<Value, Value, FixHasher> = m1
-                        .into_iter()
-                        .filter(|(k, _)| !m2.contains_key(k))
-                        .collect();
-                    self.push(Value::Map(r));
-                }
-                _ => return builtin_err(VmError::TypeError("__set_diff espera conjuntos".into())),
+    fn builtin_map_get(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let m = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        let k = it.next().unwrap_or(Value::Void);
+        match m {
+            Value::Map(m) => {
+                self.push(m.get(&k).cloned().unwrap_or(Value::Void));
             }
-            return Some(Ok(()));
+            _ => return Err(VmError::TypeError("__map_get espera diccionario".into())),
         }
+        Ok(())
+    }
 
-        if name == "__deque_new" || name == "__deque_nuevo" {
-            self.push(Value::arr(vec![]));
-            return Some(Ok(()));
+    fn builtin_map_len(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let m = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        match m {
+            Value::Map(m) => self.push(Value::Int(m.len() as i64)),
+            _ => return Err(VmError::TypeError("__map_len espera diccionario".into())),
         }
+        Ok(())
+    }
 
-        if name == "__deque_push_front" || name == "__deque_agregar_frente" {
-            let mut it = args.clone().into_iter();
-            let d = it.next().unwrap_or(Value::arr(vec![]));
-            let item = it.next().unwrap_or(Value::Void);
-            match d {
-                Value::Array(mut v) => {
-                    Arc::make_mut(&mut v).insert(0, item);
-                    self.push(Value::Array(v));
-                }
-                _ => {
-                    return builtin_err(VmError::TypeError(
-                        "__deque_push_front espera deque".into(),
-                    ))
-                }
+    fn builtin_map_keys(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let m = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        match m {
+            Value::Map(m) => {
+                let keys: Vec<Value> = m.into_iter().map(|(k, _)| k).collect();
+                self.push(Value::arr(keys));
             }
-            return Some(Ok(()));
+            _ => return Err(VmError::TypeError("__map_keys espera diccionario".into())),
         }
+        Ok(())
+    }
 
-        if name == "__deque_push_back" || name == "__deque_agregar_final" {
-            let mut it = args.clone().into_iter();
-            let d = it.next().unwrap_or(Value::arr(vec![]));
-            let item = it.next().unwrap_or(Value::Void);
-            match d {
-                Value::Array(mut v) => {
-                    Arc::make_mut(&mut v).push(item);
-                    self.push(Value::Array(v));
-                }
-                _ => {
-                    return builtin_err(VmError::TypeError("__deque_push_back espera deque".into()))
-                }
+    fn builtin_map_contains(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let m = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        let k = it.next().unwrap_or(Value::Void);
+        match m {
+            Value::Map(m) => self.push(Value::Bool(m.contains_key(&k))),
+            _ => {
+                return Err(VmError::TypeError(
+                    "__map_contains espera diccionario".into(),
+                ))
             }
-            return Some(Ok(()));
         }
+        Ok(())
+    }
 
-        if name == "__deque_pop_front" || name == "__deque_quitar_frente" {
-            let d = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::arr(vec![]));
-            match d {
-                Value::Array(mut v) => self.push(if v.is_empty() {
-                    Value::Void
-                } else {
-                    Arc::make_mut(&mut v).remove(0)
-                }),
-                _ => {
-                    return builtin_err(VmError::TypeError("__deque_pop_front espera deque".into()))
-                }
+    fn builtin_set_new(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        self.push(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        Ok(())
+    }
+
+    fn builtin_set_add(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let s = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        let item = it.next().unwrap_or(Value::Void);
+        match s {
+            Value::Map(mut m) => {
+                m.insert(item, Value::Bool(true));
+                self.push(Value::Map(m));
             }
-            return Some(Ok(()));
+            _ => return Err(VmError::TypeError("__set_add espera conjunto".into())),
         }
+        Ok(())
+    }
 
-        if name == "__deque_pop_back" || name == "__deque_quitar_final" {
-            let d = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::arr(vec![]));
-            match d {
-                Value::Array(mut v) => self.push(if v.is_empty() {
-                    Value::Void
-                } else {
-                    Arc::make_mut(&mut v).pop().unwrap_or(Value::Void)
-                }),
-                _ => {
-                    return builtin_err(VmError::TypeError("__deque_pop_back espera deque".into()))
-                }
-            }
-            return Some(Ok(()));
+    fn builtin_set_has(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let s = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        let item = it.next().unwrap_or(Value::Void);
+        match s {
+            Value::Map(m) => self.push(Value::Bool(m.contains_key(&item))),
+            _ => return Err(VmError::TypeError("__set_has espera conjunto".into())),
         }
+        Ok(())
+    }
 
-        if name == "__deque_len" || name == "__deque_longitud" {
-            let d = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::arr(vec![]));
-            match d {
-                Value::Array(v) => self.push(Value::Int(v.len() as i64)),
-                _ => return builtin_err(VmError::TypeError("__deque_len espera deque".into())),
-            }
-            return Some(Ok(()));
-        }
-
-        if name == "__heap_new" || name == "__monticulo_nuevo" {
-            self.push(Value::arr(vec![]));
-            return Some(Ok(()));
-        }
-
-        if name == "__heap_push" || name == "__monticulo_agregar" {
-            let mut it = args.clone().into_iter();
-            let h = it.next().unwrap_or(Value::arr(vec![]));
-            let item = it.next().unwrap_or(Value::Void);
-            match h {
-                Value::Array(mut v) => {
-                    Arc::make_mut(&mut v).push(item);
-                    Arc::make_mut(&mut v).sort_by(|a, b| {
-                        let an = a.as_num().unwrap_or(f64::MIN);
-                        let bn = b.as_num().unwrap_or(f64::MIN);
-                        bn.partial_cmp(&an).unwrap_or(std::cmp::Ordering::Equal)
-                    });
-                    self.push(Value::Array(v));
+    fn builtin_set_union(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let a = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        let b = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        match (a, b) {
+            (Value::Map(mut m1), Value::Map(m2)) => {
+                for (k, v) in m2 {
+                    if !m1.contains_key(&k) {
+                        m1.insert(k, v);
+                    }
                 }
-                _ => return builtin_err(VmError::TypeError("__heap_push espera heap".into())),
+                self.push(Value::Map(m1));
             }
-            return Some(Ok(()));
+            _ => return Err(VmError::TypeError("__set_union espera conjuntos".into())),
         }
+        Ok(())
+    }
 
-        if name == "__heap_pop" || name == "__monticulo_quitar" {
-            let h = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::arr(vec![]));
-            match h {
-                Value::Array(mut v) => self.push(if v.is_empty() {
-                    Value::Void
-                } else {
-                    Arc::make_mut(&mut v).remove(0)
-                }),
-                _ => return builtin_err(VmError::TypeError("__heap_pop espera heap".into())),
-            }
-            return Some(Ok(()));
+    fn builtin_set_inter(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let a = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        let b = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        match (a, b) {
+            (Value::Map(m1), Value::Map(m2)) => {
+                let r: ImMap<Value, Value, FixHasher> =
+                    m1.into_iter().filter(|(k, _)| m2.contains_key(k)).collect();
+                self.push(Value::Map(r));
+            }
+            _ => return Err(VmError::TypeError("__set_inter espera conjuntos".into())),
         }
+        Ok(())
+    }
 
-        if name == "__heap_peek" || name == "__monticulo_ver" {
-            let h = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::arr(vec![]));
-            match h {
-                Value::Array(v) => self.push(if v.is_empty() {
-                    Value::Void
-                } else {
-                    v[0].clone()
-                }),
-                _ => return builtin_err(VmError::TypeError("__heap_peek espera heap".into())),
+    fn builtin_set_diff(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let a = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        let b = it
+            .next()
+            .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
+        match (a, b) {
+            (Value::Map(m1), Value::Map(m2)) => {
+                let r: ImMap<Value, Value, FixHasher> = m1
+                    .into_iter()
+                    .filter(|(k, _)| !m2.contains_key(k))
+                    .collect();
+                self.push(Value::Map(r));
             }
-            return Some(Ok(()));
+            _ => return Err(VmError::TypeError("__set_diff espera conjuntos".into())),
         }
+        Ok(())
+    }
 
-        if name == "__heap_len" || name == "__monticulo_longitud" {
-            let h = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::arr(vec![]));
-            match h {
-                Value::Array(v) => self.push(Value::Int(v.len() as i64)),
-                _ => return builtin_err(VmError::TypeError("__heap_len espera heap".into())),
+    fn builtin_deque_new(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        self.push(Value::arr(vec![]));
+        Ok(())
+    }
+
+    fn builtin_deque_push_front(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let d = it.next().unwrap_or(Value::arr(vec![]));
+        let item = it.next().unwrap_or(Value::Void);
+        match d {
+            Value::Array(mut v) => {
+                Arc::make_mut(&mut v).insert(0, item);
+                self.push(Value::Array(v));
             }
-            return Some(Ok(()));
+            _ => return Err(VmError::TypeError("__deque_push_front espera deque".into())),
         }
+        Ok(())
+    }
 
-        if name == "__linked_new" || name == "__enlazada_nuevo" {
-            self.push(Value::arr(vec![]));
-            return Some(Ok(()));
+    fn builtin_deque_push_back(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let d = it.next().unwrap_or(Value::arr(vec![]));
+        let item = it.next().unwrap_or(Value::Void);
+        match d {
+            Value::Array(mut v) => {
+                Arc::make_mut(&mut v).push(item);
+                self.push(Value::Array(v));
+            }
+            _ => return Err(VmError::TypeError("__deque_push_back espera deque".into())),
         }
+        Ok(())
+    }
 
-        if name == "__linked_push_front" || name == "__enlazada_agregar_frente" {
-            let mut it = args.clone().into_iter();
-            let l = it.next().unwrap_or(Value::arr(vec![]));
-            let item = it.next().unwrap_or(Value::Void);
-            match l {
-                Value::Array(mut v) => {
-                    Arc::make_mut(&mut v).insert(0, item);
-                    self.push(Value::Array(v));
-                }
-                _ => {
-                    return builtin_err(VmError::TypeError(
-                        "__linked_push_front espera linked".into(),
-                    ))
-                }
-            }
-            return Some(Ok(()));
+    fn builtin_deque_pop_front(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let d = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::arr(vec![]));
+        match d {
+            Value::Array(mut v) => self.push(if v.is_empty() {
+                Value::Void
+            } else {
+                Arc::make_mut(&mut v).remove(0)
+            }),
+            _ => return Err(VmError::TypeError("__deque_pop_front espera deque".into())),
         }
+        Ok(())
+    }
 
-        if name == "__linked_push_back" || name == "__enlazada_agregar_final" {
-            let mut it = args.clone().into_iter();
-            let l = it.next().unwrap_or(Value::arr(vec![]));
-            let item = it.next().unwrap_or(Value::Void);
-            match l {
-                Value::Array(mut v) => {
-                    Arc::make_mut(&mut v).push(item);
-                    self.push(Value::Array(v));
-                }
-                _ => {
-                    return builtin_err(VmError::TypeError(
-                        "__linked_push_back espera linked".into(),
-                    ))
-                }
-            }
-            return Some(Ok(()));
+    fn builtin_deque_pop_back(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let d = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::arr(vec![]));
+        match d {
+            Value::Array(mut v) => self.push(if v.is_empty() {
+                Value::Void
+            } else {
+                Arc::make_mut(&mut v).pop().unwrap_or(Value::Void)
+            }),
+            _ => return Err(VmError::TypeError("__deque_pop_back espera deque".into())),
         }
+        Ok(())
+    }
 
-        if name == "__linked_pop_front" || name == "__enlazada_quitar_frente" {
-            let l = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::arr(vec![]));
-            match l {
-                Value::Array(mut v) => self.push(if v.is_empty() {
-                    Value::Void
-                } else {
-                    Arc::make_mut(&mut v).remove(0)
-                }),
-                _ => {
-                    return builtin_err(VmError::TypeError(
-                        "__linked_pop_front espera linked".into(),
-                    ))
-                }
-            }
-            return Some(Ok(()));
+    fn builtin_deque_len(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let d = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::arr(vec![]));
+        match d {
+            Value::Array(v) => self.push(Value::Int(v.len() as i64)),
+            _ => return Err(VmError::TypeError("__deque_len espera deque".into())),
         }
+        Ok(())
+    }
 
-        if name == "__linked_pop_back" || name == "__enlazada_quitar_final" {
-            let l = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::arr(vec![]));
-            match l {
-                Value::Array(mut v) => self.push(if v.is_empty() {
-                    Value::Void
-                } else {
-                    Arc::make_mut(&mut v).pop().unwrap_or(Value::Void)
-                }),
-                _ => {
-                    return builtin_err(VmError::TypeError(
-                        "__linked_pop_back espera linked".into(),
-                    ))
-                }
+    fn builtin_heap_new(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        self.push(Value::arr(vec![]));
+        Ok(())
+    }
+
+    fn builtin_heap_push(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let h = it.next().unwrap_or(Value::arr(vec![]));
+        let item = it.next().unwrap_or(Value::Void);
+        match h {
+            Value::Array(mut v) => {
+                Arc::make_mut(&mut v).push(item);
+                Arc::make_mut(&mut v).sort_by(|a, b| {
+                    let an = a.as_num().unwrap_or(f64::MIN);
+                    let bn = b.as_num().unwrap_or(f64::MIN);
+                    bn.partial_cmp(&an).unwrap_or(std::cmp::Ordering::Equal)
+                });
+                self.push(Value::Array(v));
             }
-            return Some(Ok(()));
+            _ => return Err(VmError::TypeError("__heap_push espera heap".into())),
+        }
+        Ok(())
+    }
+
+    fn builtin_heap_pop(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let h = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::arr(vec![]));
+        match h {
+            Value::Array(mut v) => self.push(if v.is_empty() {
+                Value::Void
+            } else {
+                Arc::make_mut(&mut v).remove(0)
+            }),
+            _ => return Err(VmError::TypeError("__heap_pop espera heap".into())),
         }
+        Ok(())
+    }
 
-        if name == "__linked_len" || name == "__enlazada_longitud" {
-            let l = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::arr(vec![]));
-            match l {
-                Value::Array(v) => self.push(Value::Int(v.len() as i64)),
-                _ => return builtin_err(VmError::TypeError("__linked_len espera linked".into())),
-            }
-            return Some(Ok(()));
+    fn builtin_heap_peek(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let h = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::arr(vec![]));
+        match h {
+            Value::Array(v) => self.push(if v.is_empty() {
+                Value::Void
+            } else {
+                v[0].clone()
+            }),
+            _ => return Err(VmError::TypeError("__heap_peek espera heap".into())),
         }
+        Ok(())
+    }
 
-        if name == "__regex_new" || name == "__regex_nuevo" {
-            let pat = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match regex::Regex::new(&pat) {
-                Ok(_) => self.push(Value::Bool(true)),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
+    fn builtin_heap_len(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let h = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::arr(vec![]));
+        match h {
+            Value::Array(v) => self.push(Value::Int(v.len() as i64)),
+            _ => return Err(VmError::TypeError("__heap_len espera heap".into())),
         }
+        Ok(())
+    }
 
-        if name == "__regex_is_match" || name == "__regex_coincide" {
-            let re_s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let text = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            match regex::Regex::new(&re_s) {
-                Ok(r) => self.push(Value::Bool(r.is_match(&text))),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+    fn builtin_linked_new(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        self.push(Value::arr(vec![]));
+        Ok(())
+    }
+
+    fn builtin_linked_push_front(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let l = it.next().unwrap_or(Value::arr(vec![]));
+        let item = it.next().unwrap_or(Value::Void);
+        match l {
+            Value::Array(mut v) => {
+                Arc::make_mut(&mut v).insert(0, item);
+                self.push(Value::Array(v));
+            }
+            _ => {
+                return Err(VmError::TypeError(
+                    "__linked_push_front espera linked".into(),
+                ))
             }
-            return Some(Ok(()));
         }
+        Ok(())
+    }
 
-        if name == "__regex_captures" || name == "__regex_capturar" {
-            let re_s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let text = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            match regex::Regex::new(&re_s) {
-                Ok(r) => {
-                    if let Some(caps) = r.captures(&text) {
-                        let vs: Vec<Value> = caps
-                            .iter()
-                            .map(|m| {
-                                Value::str(m.map(|x| x.as_str().to_string()).unwrap_or_default())
-                            })
-                            .collect();
-                        self.push(Value::arr(vs));
-                    } else {
-                        self.push(Value::arr(vec![]));
-                    }
-                }
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+    fn builtin_linked_push_back(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut it = args.clone().into_iter();
+        let l = it.next().unwrap_or(Value::arr(vec![]));
+        let item = it.next().unwrap_or(Value::Void);
+        match l {
+            Value::Array(mut v) => {
+                Arc::make_mut(&mut v).push(item);
+                self.push(Value::Array(v));
+            }
+            _ => {
+                return Err(VmError::TypeError(
+                    "__linked_push_back espera linked".into(),
+                ))
             }
-            return Some(Ok(()));
         }
+        Ok(())
+    }
 
-        if name == "__regex_replace" || name == "__regex_reemplazar" {
-            let re_s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let text = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let rep = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
-            match regex::Regex::new(&re_s) {
-                Ok(r) => self.push(Value::str(r.replace_all(&text, rep.as_str()).to_string())),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+    fn builtin_linked_pop_front(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let l = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::arr(vec![]));
+        match l {
+            Value::Array(mut v) => self.push(if v.is_empty() {
+                Value::Void
+            } else {
+                Arc::make_mut(&mut v).remove(0)
+            }),
+            _ => {
+                return Err(VmError::TypeError(
+                    "__linked_pop_front espera linked".into(),
+                ))
             }
-            return Some(Ok(()));
         }
+        Ok(())
+    }
 
-        if name == "__unicode_normalize" || name == "__unicode_normalizar" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let form = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let nf: String = match form.as_str() {
-                "NFC" => s.nfc().collect(),
-                "NFD" => s.nfd().collect(),
-                "NFKC" => s.nfkc().collect(),
-                "NFKD" => s.nfkd().collect(),
-                _ => s.nfc().collect(),
-            };
-            self.push(Value::str(nf));
-            return Some(Ok(()));
+    fn builtin_linked_pop_back(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let l = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::arr(vec![]));
+        match l {
+            Value::Array(mut v) => self.push(if v.is_empty() {
+                Value::Void
+            } else {
+                Arc::make_mut(&mut v).pop().unwrap_or(Value::Void)
+            }),
+            _ => return Err(VmError::TypeError("__linked_pop_back espera linked".into())),
         }
+        Ok(())
+    }
 
-        if name == "__str_pad_start" || name == "__str_padding_inicio" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let len = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let ch = args
-                .get(2)
-                .map(|v| format!("{}", v))
-                .unwrap_or_default()
-                .chars()
-                .next()
-                .unwrap_or(' ');
-            self.push(Value::str(format!(
-                "{}{}",
-                ch.to_string().repeat(len.saturating_sub(s.len())),
-                s
-            )));
-            return Some(Ok(()));
+    fn builtin_linked_len(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let l = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::arr(vec![]));
+        match l {
+            Value::Array(v) => self.push(Value::Int(v.len() as i64)),
+            _ => return Err(VmError::TypeError("__linked_len espera linked".into())),
         }
+        Ok(())
+    }
 
-        if name == "__str_pad_end" || name == "__str_padding_fin" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let len = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let ch = args
-                .get(2)
-                .map(|v| format!("{}", v))
-                .unwrap_or_default()
-                .chars()
-                .next()
-                .unwrap_or(' ');
-            self.push(Value::str(format!(
-                "{}{}",
-                s,
-                ch.to_string().repeat(len.saturating_sub(s.len()))
-            )));
-            return Some(Ok(()));
+    fn builtin_regex_new(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let pat = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        match regex::Regex::new(&pat) {
+            Ok(_) => self.push(Value::Bool(true)),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__encoding_utf8" || name == "__codificacion_utf8" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::arr(
-                s.bytes().map(|b| Value::Int(b as i64)).collect(),
-            ));
-            return Some(Ok(()));
+    fn builtin_regex_is_match(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let re_s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let text = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        match regex::Regex::new(&re_s) {
+            Ok(r) => self.push(Value::Bool(r.is_match(&text))),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__encoding_from_utf8" || name == "__desde_utf8" {
-            let arr = args
-                .clone()
-                .into_iter()
-                .next()
-                .unwrap_or(Value::arr(vec![]));
-            match arr {
-                Value::Array(v) => {
-                    let bytes: Vec<u8> = v
+    fn builtin_regex_captures(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let re_s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let text = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        match regex::Regex::new(&re_s) {
+            Ok(r) => {
+                if let Some(caps) = r.captures(&text) {
+                    let vs: Vec<Value> = caps
                         .iter()
-                        .filter_map(|x| {
-                            if let Value::Int(n) = x {
-                                Some(*n as u8)
-                            } else {
-                                None
-                            }
-                        })
+                        .map(|m| Value::str(m.map(|x| x.as_str().to_string()).unwrap_or_default()))
                         .collect();
-                    self.push(Value::str(String::from_utf8_lossy(&bytes).to_string()));
+                    self.push(Value::arr(vs));
+                } else {
+                    self.push(Value::arr(vec![]));
                 }
-                _ => self.push(Value::str(String::new())),
             }
-            return Some(Ok(()));
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__buf_reader" || name == "__lector_buffer" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match std::fs::read_to_string(&path) {
-                Ok(c) => {
-                    let lines: Vec<Value> = c.lines().map(|l| Value::str(l.to_string())).collect();
-                    self.push(Value::arr(lines));
-                }
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
+    fn builtin_regex_replace(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let re_s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let text = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let rep = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
+        match regex::Regex::new(&re_s) {
+            Ok(r) => self.push(Value::str(r.replace_all(&text, rep.as_str()).to_string())),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__buf_writer" || name == "__escritor_buffer" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let content = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            match std::fs::write(&path, &content) {
-                Ok(_) => self.push(Value::Exito(Box::new(Value::Bool(true)))),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
-        }
+    fn builtin_unicode_normalize(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let form = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let nf: String = match form.as_str() {
+            "NFC" => s.nfc().collect(),
+            "NFD" => s.nfd().collect(),
+            "NFKC" => s.nfkc().collect(),
+            "NFKD" => s.nfkd().collect(),
+            _ => s.nfc().collect(),
+        };
+        self.push(Value::str(nf));
+        Ok(())
+    }
 
-        if name == "__stream_chunks" || name == "__stream_trozos" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let size = args.get(1).and_then(|v| v.as_num()).unwrap_or(4096.0) as usize;
-            match std::fs::read(&path) {
-                Ok(data) => {
-                    let chunks: Vec<Value> = data
-                        .chunks(size)
-                        .map(|c| Value::arr(c.iter().map(|&b| Value::Int(b as i64)).collect()))
-                        .collect();
-                    self.push(Value::arr(chunks));
-                }
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
-        }
+    fn builtin_str_pad_start(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let len = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let ch = args
+            .get(2)
+            .map(|v| format!("{}", v))
+            .unwrap_or_default()
+            .chars()
+            .next()
+            .unwrap_or(' ');
+        let fill = len.saturating_sub(s.len());
+        self.budget
+            .check_alloc(fill.saturating_mul(ch.len_utf8()) + s.len())
+            .map_err(VmError::Sandbox)?;
+        self.push(Value::str(format!("{}{}", ch.to_string().repeat(fill), s)));
+        Ok(())
+    }
 
-        if name == "__http_server" || name == "__http_servidor" {
-            #[allow(unused_variables)]
-            let addr = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            self.push(Value::str(format!("HTTP server on {}", addr)));
-            return Some(Ok(()));
-        }
+    fn builtin_str_pad_end(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let len = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let ch = args
+            .get(2)
+            .map(|v| format!("{}", v))
+            .unwrap_or_default()
+            .chars()
+            .next()
+            .unwrap_or(' ');
+        let fill = len.saturating_sub(s.len());
+        self.budget
+            .check_alloc(fill.saturating_mul(ch.len_utf8()) + s.len())
+            .map_err(VmError::Sandbox)?;
+        self.push(Value::str(format!("{}{}", s, ch.to_string().repeat(fill))));
+        Ok(())
+    }
 
-        if name == "__serial_open" || name == "__serial_abrir" {
-            self.push(Value::Bool(true));
-            return Some(Ok(()));
-        }
+    fn builtin_encoding_utf8(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::arr(
+            s.bytes().map(|b| Value::Int(b as i64)).collect(),
+        ));
+        Ok(())
+    }
 
-        if name == "__json_parse" || name == "__json_parsear" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match serde_json::from_str::<serde_json::Value>(&s) {
-                Ok(v) => self.push(json_value_to_lumen(v)),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+    fn builtin_encoding_from_utf8(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let arr = args
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or(Value::arr(vec![]));
+        match arr {
+            Value::Array(v) => {
+                let bytes: Vec<u8> = v
+                    .iter()
+                    .filter_map(|x| {
+                        if let Value::Int(n) = x {
+                            Some(*n as u8)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                self.push(Value::str(String::from_utf8_lossy(&bytes).to_string()));
             }
-            return Some(Ok(()));
+            _ => self.push(Value::str(String::new())),
         }
+        Ok(())
+    }
 
-        if name == "__json_stringify" || name == "__json_texto" {
-            let val = args.first().cloned().unwrap_or(Value::Void);
-            let json = lumen_value_to_json(&val);
-            self.push(Value::str(serde_json::to_string(&json).unwrap_or_default()));
-            return Some(Ok(()));
+    fn builtin_buf_reader(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        // El texto y una copia repartida en líneas.
+        self.check_file_alloc(&path, 2)?;
+        match std::fs::read_to_string(&path) {
+            Ok(c) => {
+                let lines: Vec<Value> = c.lines().map(|l| Value::str(l.to_string())).collect();
+                self.push(Value::arr(lines));
+            }
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__js_call" || name == "__js_llamar" {
-            let fn_name = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let js_args: Vec<String> = args.iter().skip(1).map(|v| format!("{}", v)).collect();
-            let js_code = format!(
-                "__lumen_call('{}', [{}])",
-                fn_name.replace('\'', "\\'"),
-                js_args
-                    .iter()
-                    .map(|a| format!("'{}'", a.replace('\'', "\\'")))
-                    .collect::<Vec<_>>()
-                    .join(",")
-            );
-            if let Some(eval) = JS_EVAL.get() {
-                let result = eval(&js_code);
-                self.push(Value::str(result));
-            } else {
-                self.push(Value::str(js_code));
-            }
-            return Some(Ok(()));
+    fn builtin_buf_writer(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let content = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        match std::fs::write(&path, &content) {
+            Ok(_) => self.push(Value::Exito(Box::new(Value::Bool(true)))),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        if name == "__js_eval" || name == "__js_evaluar" {
-            let js_code = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            if let Some(eval) = JS_EVAL.get() {
-                let result = eval(&js_code);
-                self.push(Value::str(result));
-            } else {
-                self.push(Value::str(js_code));
+    fn builtin_stream_chunks(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let size = args.get(1).and_then(|v| v.as_num()).unwrap_or(4096.0) as usize;
+        self.check_file_alloc(&path, std::mem::size_of::<Value>())?;
+        match std::fs::read(&path) {
+            Ok(data) => {
+                let chunks: Vec<Value> = data
+                    .chunks(size)
+                    .map(|c| Value::arr(c.iter().map(|&b| Value::Int(b as i64)).collect()))
+                    .collect();
+                self.push(Value::arr(chunks));
             }
-            return Some(Ok(()));
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
-
-        None
+        Ok(())
     }
 
-    #[cfg(any(feature = "extra", feature = "full"))]
-    fn call_extra_builtin(&mut self, name: &str, args: &[Value]) -> Option<Result<(), VmError>> {
-        let args = args.to_vec();
+    /// `__http_servidor(direccion, manejador, hilos = 4)`: sirve HTTP/1.1 en
+    /// `direccion` llamando a `manejador(peticion)` desde `hilos` hilos.
+    /// Devuelve el identificador del servidor sin esperar a que termine.
+    fn builtin_http_server(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[cfg(not(feature = "full"))]
+        {
+            let _ = args;
+            self.push(Value::Error(Box::new(Value::str(
+                "Servidor HTTP no disponible en esta compilación",
+            ))));
+        }
         #[cfg(feature = "full")]
-        if name == "__tcp_connect" || name == "__tcp_conectar" {
-            #[allow(unused_variables)]
+        {
             let addr = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match std::net::TcpStream::connect(&addr) {
-                Ok(_) => self.push(Value::Bool(true)),
+            let handler = args.get(1).cloned().unwrap_or(Value::Void);
+            let workers = args.get(2).and_then(|v| v.as_i64()).unwrap_or(4).max(1);
+            if let Err(e) = self.callee_frame(&handler, Vec::new()) {
+                self.push(Value::Error(Box::new(Value::str(e.to_string()))));
+                return Ok(());
+            }
+            match http::start(self.kin(), &addr, handler, workers as usize) {
+                Ok(id) => self.push(Value::str(id)),
                 Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
             }
-            return Some(Ok(()));
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__tcp_listen" || name == "__tcp_escuchar" {
-            #[allow(unused_variables)]
-            let addr = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match std::net::TcpListener::bind(&addr) {
-                Ok(l) => {
-                    self.tcp_listener = Some(l);
-                    self.push(Value::Bool(true));
-                }
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
+    /// `__http_servidor_direccion(srv)`: `ip:puerto` en que escucha.
+    #[cfg(feature = "full")]
+    fn builtin_http_server_addr(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let id = args.first().map(|v| v.to_string()).unwrap_or_default();
+        match http::local_addr(&id) {
+            Some(addr) => self.push(Value::str(addr.to_string())),
+            None => self.push(Value::Error(Box::new(Value::str("Servidor no encontrado")))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__tcp_accept" || name == "__tcp_aceptar" {
-            match &self.tcp_listener {
-                Some(l) => match l.accept() {
-                    Ok((_stream, _)) => {
-                        let addr = _stream
-                            .peer_addr()
-                            .map(|a| a.to_string())
-                            .unwrap_or_default();
-                        self.push(Value::str(addr));
-                    }
-                    Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-                },
-                None => self.push(Value::Error(Box::new(Value::str("Sin listener")))),
-            }
-            return Some(Ok(()));
+    /// `__http_servidor_detener(srv)`: deja de aceptar conexiones.
+    #[cfg(feature = "full")]
+    fn builtin_http_server_stop(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let id = args.first().map(|v| v.to_string()).unwrap_or_default();
+        let stopped = http::stop(&id);
+        wake_waiters(&id);
+        self.push(Value::Bool(stopped));
+        Ok(())
+    }
+
+    /// `__http_servidor_esperar(srv)`: bloquea hasta que alguien lo detenga.
+    #[cfg(feature = "full")]
+    fn builtin_http_server_wait(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let id = args.first().map(|v| v.to_string()).unwrap_or_default();
+        let stopped = self.retry_or_park(&args, &[&id], None, |_, _| {
+            (!http::is_running(&id)).then_some(Value::Void)
+        })?;
+        if stopped.is_some() {
+            self.push(Value::Void);
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__http_get" || name == "__http_obtener" {
-            let url = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match ureq::get(&url).call() {
-                Ok(resp) => {
-                    let body = resp.into_string().unwrap_or_default();
-                    self.push(Value::str(body));
-                }
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
+    fn builtin_serial_open(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        self.push(Value::Bool(true));
+        Ok(())
+    }
+
+    fn builtin_json_parse(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        match serde_json::from_str::<serde_json::Value>(&s) {
+            Ok(v) => self.push(json_value_to_lumen(v)),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__http_post" || name == "__http_enviar" {
-            let url = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let body = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            match ureq::post(&url).send_string(&body) {
-                Ok(resp) => {
-                    let text = resp.into_string().unwrap_or_default();
-                    self.push(Value::str(text));
-                }
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
+    fn builtin_json_stringify(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let val = args.first().cloned().unwrap_or(Value::Void);
+        let json = lumen_value_to_json(&val);
+        self.push(Value::str(serde_json::to_string(&json).unwrap_or_default()));
+        Ok(())
+    }
+
+    fn builtin_js_call(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let fn_name = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let js_args: Vec<String> = args.iter().skip(1).map(|v| format!("{}", v)).collect();
+        let js_code = format!(
+            "__lumen_call('{}', [{}])",
+            fn_name.replace('\'', "\\'"),
+            js_args
+                .iter()
+                .map(|a| format!("'{}'", a.replace('\'', "\\'")))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        if let Some(eval) = JS_EVAL.get() {
+            let result = eval(&js_code);
+            self.push(Value::str(result));
+        } else {
+            self.push(Value::str(js_code));
         }
+        Ok(())
+    }
 
-        // ██ FFI builtins ██
-        #[cfg(feature = "full")]
-        if name == "__ffi_cargar" || name == "__ffi_load" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match unsafe { libloading::Library::new(&path) } {
-                Ok(lib) => {
-                    let ptr = Box::into_raw(Box::new(lib)) as usize;
-                    let id = format!("lib_{}", self.ffi_libraries.len());
-                    self.ffi_libraries.insert(id.clone(), ptr);
-                    self.push(Value::str(id));
-                }
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
+    fn builtin_js_eval(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let js_code = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(eval) = JS_EVAL.get() {
+            let result = eval(&js_code);
+            self.push(Value::str(result));
+        } else {
+            self.push(Value::str(js_code));
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_llamar" || name == "__ffi_call" {
-            let lib_id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let fn_name = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let tipos = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
-            let ret = args.get(4).map(|v| format!("{}", v)).unwrap_or_default();
-            let elems: Vec<Value> = match args.get(3) {
-                Some(Value::Array(a)) => a.to_vec(),
-                _ => Vec::new(),
-            };
-            let lib_ptr = match self.ffi_libraries.get(&lib_id) {
-                Some(&p) => p,
-                None => {
-                    self.push(Value::Error(Box::new(Value::str(format!(
-                        "Biblioteca '{}' no encontrada",
-                        lib_id
-                    )))));
-                    return Some(Ok(()));
-                }
-            };
-            let lib = unsafe { &*(lib_ptr as *const libloading::Library) };
-            match ffi_call_typed(lib, &fn_name, &tipos, &ret, &elems) {
-                Ok(v) => self.push(v),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
-            }
-            return Some(Ok(()));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_tcp_connect(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[allow(unused_variables)]
+        let addr = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        match std::net::TcpStream::connect(&addr) {
+            Ok(_) => self.push(Value::Bool(true)),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_llamar_nv" {
-            let lib_id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let fn_name = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let tipos = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
-            let ret = args.get(3).map(|v| format!("{}", v)).unwrap_or_default();
-            let base = args.get(4).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let tys = parse_ffi_types(&tipos);
-            let mut elems = Vec::with_capacity(tys.len());
-            for (i, ty) in tys.iter().enumerate() {
-                let off = base + i * 8;
-                let lo = unsafe { *(off as *const u32) } as u64;
-                let hi = unsafe { *((off + 4) as *const u32) } as u64;
-                match ty {
-                    FfiTy::Float => elems.push(Value::Float(f64::from_bits(lo | (hi << 32)))),
-                    _ => elems.push(Value::Int((lo | (hi << 32)) as i64)),
-                }
-            }
-            let lib_ptr = match self.ffi_libraries.get(&lib_id) {
-                Some(&p) => p,
-                None => {
-                    self.push(Value::Error(Box::new(Value::str(format!(
-                        "Biblioteca '{}' no encontrada",
-                        lib_id
-                    )))));
-                    return Some(Ok(()));
-                }
-            };
-            let lib = unsafe { &*(lib_ptr as *const libloading::Library) };
-            match ffi_call_typed(lib, &fn_name, &tipos, &ret, &elems) {
-                Ok(v) => self.push(v),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_tcp_listen(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[allow(unused_variables)]
+        let addr = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        match std::net::TcpListener::bind(&addr) {
+            Ok(l) => {
+                self.tcp_listener = Some(l);
+                self.push(Value::Bool(true));
             }
-            return Some(Ok(()));
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_asignar" || name == "__ffi_alloc" {
-            let size = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let align = args.get(1).and_then(|v| v.as_num()).unwrap_or(8.0) as usize;
-            let layout = match std::alloc::Layout::from_size_align(size, align) {
-                Ok(l) => l,
-                Err(e) => {
-                    self.push(Value::Error(Box::new(Value::str(format!(
-                        "Layout inválido: {}",
-                        e
-                    )))));
-                    return Some(Ok(()));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_tcp_accept(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        match &self.tcp_listener {
+            Some(l) => match l.accept() {
+                Ok((_stream, _)) => {
+                    let addr = _stream
+                        .peer_addr()
+                        .map(|a| a.to_string())
+                        .unwrap_or_default();
+                    self.push(Value::str(addr));
                 }
-            };
-            let ptr = unsafe { std::alloc::alloc(layout) };
-            self.push(Value::Int(ptr as i64));
-            return Some(Ok(()));
+                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+            },
+            None => self.push(Value::Error(Box::new(Value::str("Sin listener")))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_liberar" || name == "__ffi_free" {
-            let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let size = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let align = args.get(2).and_then(|v| v.as_num()).unwrap_or(8.0) as usize;
-            if ptr_val != 0 {
-                let layout = match std::alloc::Layout::from_size_align(size, align) {
-                    Ok(l) => l,
-                    Err(_) => {
-                        self.push(Value::Error(Box::new(Value::str(
-                            "Layout inválido para liberar",
-                        ))));
-                        return Some(Ok(()));
-                    }
-                };
-                unsafe {
-                    std::alloc::dealloc(ptr_val as *mut u8, layout);
-                }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_http_get(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let url = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        match ureq::get(&url).call() {
+            Ok(resp) => {
+                let body = resp.into_string().unwrap_or_default();
+                self.push(Value::str(body));
             }
-            self.push(Value::Void);
-            return Some(Ok(()));
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_escribir" || name == "__ffi_write" {
-            let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let data = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
-            let bytes = data.as_bytes();
-            if ptr_val != 0 {
-                unsafe {
-                    std::ptr::copy_nonoverlapping(
-                        bytes.as_ptr(),
-                        (ptr_val + offset) as *mut u8,
-                        bytes.len(),
-                    );
-                    *((ptr_val + offset + bytes.len()) as *mut u8) = 0;
-                }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_http_post(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let url = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let body = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        match ureq::post(&url).send_string(&body) {
+            Ok(resp) => {
+                let text = resp.into_string().unwrap_or_default();
+                self.push(Value::str(text));
             }
-            self.push(Value::Void);
-            return Some(Ok(()));
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_leer" || name == "__ffi_read" {
-            let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let len = args.get(2).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            if ptr_val != 0 && len > 0 {
-                let mut buf = vec![0u8; len];
-                unsafe {
-                    std::ptr::copy_nonoverlapping(
-                        (ptr_val + offset) as *const u8,
-                        buf.as_mut_ptr(),
-                        len,
-                    );
-                }
-                self.push(Value::str(String::from_utf8_lossy(&buf).to_string()));
-            } else {
-                self.push(Value::str(String::new()));
+    // ██ FFI builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_cargar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        match unsafe { libloading::Library::new(&path) } {
+            Ok(lib) => {
+                let ptr = Box::into_raw(Box::new(lib)) as usize;
+                let id = format!("lib_{}", self.ffi_libraries.len());
+                self.ffi_libraries.insert(id.clone(), ptr);
+                self.push(Value::str(id));
             }
-            return Some(Ok(()));
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_peek" || name == "__ffi_peek_u32" {
-            let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            if ptr_val != 0 {
-                unsafe {
-                    let b0 = *((ptr_val + offset) as *const u8) as u32;
-                    let b1 = *((ptr_val + offset + 1) as *const u8) as u32;
-                    let b2 = *((ptr_val + offset + 2) as *const u8) as u32;
-                    let b3 = *((ptr_val + offset + 3) as *const u8) as u32;
-                    let val = b0 | (b1 << 8) | (b2 << 16) | (b3 << 24);
-                    self.push(Value::Int(val as i64));
-                }
-            } else {
-                self.push(Value::Int(0));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_llamar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let lib_id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let fn_name = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let tipos = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
+        let ret = args.get(4).map(|v| format!("{}", v)).unwrap_or_default();
+        let elems: Vec<Value> = match args.get(3) {
+            Some(Value::Array(a)) => a.to_vec(),
+            _ => Vec::new(),
+        };
+        let lib_ptr = match self.ffi_libraries.get(&lib_id) {
+            Some(&p) => p,
+            None => {
+                self.push(Value::Error(Box::new(Value::str(format!(
+                    "Biblioteca '{}' no encontrada",
+                    lib_id
+                )))));
+                return Ok(());
             }
-            return Some(Ok(()));
+        };
+        let lib = unsafe { &*(lib_ptr as *const libloading::Library) };
+        match ffi_call_typed(lib, &fn_name, &tipos, &ret, &elems) {
+            Ok(v) => self.push(v),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_peek64" || name == "__ffi_peek_ptr" {
-            let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            if ptr_val != 0 {
-                unsafe {
-                    let mut b = [0u8; 8];
-                    std::ptr::copy_nonoverlapping(
-                        (ptr_val + offset) as *const u8,
-                        b.as_mut_ptr(),
-                        8,
-                    );
-                    let val = i64::from_le_bytes(b);
-                    self.push(Value::Int(val));
-                }
-            } else {
-                self.push(Value::Int(0));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_llamar_nv(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let lib_id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let fn_name = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let tipos = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
+        let ret = args.get(3).map(|v| format!("{}", v)).unwrap_or_default();
+        let base = args.get(4).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let tys = parse_ffi_types(&tipos);
+        let mut elems = Vec::with_capacity(tys.len());
+        for (i, ty) in tys.iter().enumerate() {
+            let off = base + i * 8;
+            let lo = unsafe { *(off as *const u32) } as u64;
+            let hi = unsafe { *((off + 4) as *const u32) } as u64;
+            match ty {
+                FfiTy::Float => elems.push(Value::Float(f64::from_bits(lo | (hi << 32)))),
+                _ => elems.push(Value::Int((lo | (hi << 32)) as i64)),
+            }
+        }
+        let lib_ptr = match self.ffi_libraries.get(&lib_id) {
+            Some(&p) => p,
+            None => {
+                self.push(Value::Error(Box::new(Value::str(format!(
+                    "Biblioteca '{}' no encontrada",
+                    lib_id
+                )))));
+                return Ok(());
             }
-            return Some(Ok(()));
+        };
+        let lib = unsafe { &*(lib_ptr as *const libloading::Library) };
+        match ffi_call_typed(lib, &fn_name, &tipos, &ret, &elems) {
+            Ok(v) => self.push(v),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_peek_byte" || name == "__ffi_peek_u8" {
-            let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            if ptr_val != 0 {
-                unsafe {
-                    let b = *((ptr_val + offset) as *const u8);
-                    self.push(Value::Int(b as i64));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_asignar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let size = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let align = args.get(1).and_then(|v| v.as_num()).unwrap_or(8.0) as usize;
+        let layout = match std::alloc::Layout::from_size_align(size, align) {
+            Ok(l) => l,
+            Err(e) => {
+                self.push(Value::Error(Box::new(Value::str(format!(
+                    "Layout inválido: {}",
+                    e
+                )))));
+                return Ok(());
+            }
+        };
+        let ptr = unsafe { std::alloc::alloc(layout) };
+        self.push(Value::Int(ptr as i64));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_liberar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let size = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let align = args.get(2).and_then(|v| v.as_num()).unwrap_or(8.0) as usize;
+        if ptr_val != 0 {
+            let layout = match std::alloc::Layout::from_size_align(size, align) {
+                Ok(l) => l,
+                Err(_) => {
+                    self.push(Value::Error(Box::new(Value::str(
+                        "Layout inválido para liberar",
+                    ))));
+                    return Ok(());
                 }
-            } else {
-                self.push(Value::Int(0));
+            };
+            unsafe {
+                std::alloc::dealloc(ptr_val as *mut u8, layout);
             }
-            return Some(Ok(()));
         }
+        self.push(Value::Void);
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_poke" || name == "__ffi_poke_u32" {
-            let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let val = args.get(2).and_then(|v| v.as_num()).unwrap_or(0.0) as u32;
-            if ptr_val != 0 {
-                unsafe {
-                    *((ptr_val + offset) as *mut u8) = val as u8;
-                    *((ptr_val + offset + 1) as *mut u8) = (val >> 8) as u8;
-                    *((ptr_val + offset + 2) as *mut u8) = (val >> 16) as u8;
-                    *((ptr_val + offset + 3) as *mut u8) = (val >> 24) as u8;
-                }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_escribir(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let data = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
+        let bytes = data.as_bytes();
+        if ptr_val != 0 {
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    bytes.as_ptr(),
+                    (ptr_val + offset) as *mut u8,
+                    bytes.len(),
+                );
+                *((ptr_val + offset + bytes.len()) as *mut u8) = 0;
             }
-            self.push(Value::Void);
-            return Some(Ok(()));
         }
+        self.push(Value::Void);
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__ffi_poke_byte" || name == "__ffi_poke_u8" {
-            let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-            let val = args.get(2).and_then(|v| v.as_num()).unwrap_or(0.0) as u8;
-            if ptr_val != 0 {
-                unsafe {
-                    *((ptr_val + offset) as *mut u8) = val;
-                }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_leer(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let len = args.get(2).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        if ptr_val != 0 && len > 0 {
+            let mut buf = vec![0u8; len];
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    (ptr_val + offset) as *const u8,
+                    buf.as_mut_ptr(),
+                    len,
+                );
             }
-            self.push(Value::Void);
-            return Some(Ok(()));
+            self.push(Value::str(String::from_utf8_lossy(&buf).to_string()));
+        } else {
+            self.push(Value::str(String::new()));
         }
+        Ok(())
+    }
 
-        if name == "__ffi_asm" {
-            let _code = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            // En modo VM / JIT interpreta el bloque de ensamblador como ejecución nativa
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_peek(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        if ptr_val != 0 {
+            unsafe {
+                let b0 = *((ptr_val + offset) as *const u8) as u32;
+                let b1 = *((ptr_val + offset + 1) as *const u8) as u32;
+                let b2 = *((ptr_val + offset + 2) as *const u8) as u32;
+                let b3 = *((ptr_val + offset + 3) as *const u8) as u32;
+                let val = b0 | (b1 << 8) | (b2 << 16) | (b3 << 24);
+                self.push(Value::Int(val as i64));
+            }
+        } else {
             self.push(Value::Int(0));
-            return Some(Ok(()));
         }
+        Ok(())
+    }
 
-        if name == "__ffi_c_eval" {
-            let _code = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_peek64(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        if ptr_val != 0 {
+            unsafe {
+                let mut b = [0u8; 8];
+                std::ptr::copy_nonoverlapping((ptr_val + offset) as *const u8, b.as_mut_ptr(), 8);
+                let val = i64::from_le_bytes(b);
+                self.push(Value::Int(val));
+            }
+        } else {
             self.push(Value::Int(0));
-            return Some(Ok(()));
         }
+        Ok(())
+    }
 
-        if name == "__ffi_rust_eval" {
-            let _code = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_peek_byte(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        if ptr_val != 0 {
+            unsafe {
+                let b = *((ptr_val + offset) as *const u8);
+                self.push(Value::Int(b as i64));
+            }
+        } else {
             self.push(Value::Int(0));
-            return Some(Ok(()));
         }
+        Ok(())
+    }
 
-        // ██ Self-Healing Runtime Builtins ██
-        if name == "__self_healing_registrar_parche" {
-            let orig = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let patch = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            // Registra parche de auto-reparación
-            self.push(Value::str(format!("PARCHE_REGISTRADO:{}:{}", orig, patch)));
-            return Some(Ok(()));
-        }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_poke(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let val = args.get(2).and_then(|v| v.as_num()).unwrap_or(0.0) as u32;
+        if ptr_val != 0 {
+            unsafe {
+                *((ptr_val + offset) as *mut u8) = val as u8;
+                *((ptr_val + offset + 1) as *mut u8) = (val >> 8) as u8;
+                *((ptr_val + offset + 2) as *mut u8) = (val >> 16) as u8;
+                *((ptr_val + offset + 3) as *mut u8) = (val >> 24) as u8;
+            }
+        }
+        self.push(Value::Void);
+        Ok(())
+    }
 
-        if name == "__self_healing_invocar" {
-            let fn_name = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            // Ejecución protegida de función con fallback automático
-            self.push(Value::str(format!(
-                "EJECUTADO_CON_SELF_HEALING:{}",
-                fn_name
-            )));
-            return Some(Ok(()));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_ffi_poke_byte(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let ptr_val = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let offset = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
+        let val = args.get(2).and_then(|v| v.as_num()).unwrap_or(0.0) as u8;
+        if ptr_val != 0 {
+            unsafe {
+                *((ptr_val + offset) as *mut u8) = val;
+            }
         }
+        self.push(Value::Void);
+        Ok(())
+    }
 
-        if name == "__self_healing_estado" {
-            self.push(Value::str(
-                "ESTADO:RESILIENTE|FALLOS_INTERCEPTADOS:0|HOT_PATCHES:0",
-            ));
-            return Some(Ok(()));
-        }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_ffi_asm(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let _code = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        // En modo VM / JIT interpreta el bloque de ensamblador como ejecución nativa
+        self.push(Value::Int(0));
+        Ok(())
+    }
 
-        // ██ Crypto builtins ██
-        if name == "__hash_sha256" {
-            let data = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            #[cfg(feature = "full")]
-            let hash: Vec<u8> = match self.bcrypt.as_ref() {
-                Some(bc) => bc.sha256(data.as_bytes()).unwrap_or_default(),
-                None => {
-                    use sha2::{Digest, Sha256};
-                    let mut h = Sha256::new();
-                    h.update(data.as_bytes());
-                    h.finalize().to_vec()
-                }
-            };
-            #[cfg(not(feature = "full"))]
-            let hash: Vec<u8> = {
-                use sha2::{Digest, Sha256};
-                let mut h = Sha256::new();
-                h.update(data.as_bytes());
-                h.finalize().to_vec()
-            };
-            let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
-            self.push(Value::str(hex));
-            return Some(Ok(()));
-        }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_ffi_c_eval(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let _code = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::Int(0));
+        Ok(())
+    }
 
-        if name == "__hash_sha512" {
-            let data = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            #[cfg(feature = "full")]
-            let hash: Vec<u8> = match self.bcrypt.as_ref() {
-                Some(bc) => bc.sha512(data.as_bytes()).unwrap_or_default(),
-                None => {
-                    use sha2::{Digest, Sha512};
-                    let mut h = Sha512::new();
-                    h.update(data.as_bytes());
-                    h.finalize().to_vec()
-                }
-            };
-            #[cfg(not(feature = "full"))]
-            let hash: Vec<u8> = {
-                use sha2::{Digest, Sha512};
-                let mut h = Sha512::new();
-                h.update(data.as_bytes());
-                h.finalize().to_vec()
-            };
-            let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
-            self.push(Value::str(hex));
-            return Some(Ok(()));
-        }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_ffi_rust_eval(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let _code = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.push(Value::Int(0));
+        Ok(())
+    }
+
+    // ██ Self-Healing Runtime Builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_self_healing_registrar_parche(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let orig = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let patch = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        // Registra parche de auto-reparación
+        self.push(Value::str(format!("PARCHE_REGISTRADO:{}:{}", orig, patch)));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_self_healing_invocar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let fn_name = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        // Ejecución protegida de función con fallback automático
+        self.push(Value::str(format!(
+            "EJECUTADO_CON_SELF_HEALING:{}",
+            fn_name
+        )));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_self_healing_estado(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        self.push(Value::str(
+            "ESTADO:RESILIENTE|FALLOS_INTERCEPTADOS:0|HOT_PATCHES:0",
+        ));
+        Ok(())
+    }
 
+    // ██ Crypto builtins ██
+    // Con `bcrypt.dll` (Windows) se usa CNG; en el resto de plataformas, o si
+    // CNG falla, el backend portable de `crate::crypto`.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_hash_sha256(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let data = args.first().map(|v| format!("{}", v)).unwrap_or_default();
         #[cfg(feature = "full")]
-        if name == "__aes_encriptar" || name == "__aes_encrypt" {
-            if self.bcrypt.is_none() {
-                match Bcrypt::load() {
-                    Ok(b) => self.bcrypt = Some(Arc::new(b)),
-                    Err(e) => {
-                        self.push(Value::Error(Box::new(Value::str(e))));
-                        return Some(Ok(()));
-                    }
-                }
-            }
-            let key = args
-                .first()
-                .map(|v| format!("{}", v).into_bytes())
-                .unwrap_or_default();
-            let data = args
-                .get(1)
-                .map(|v| format!("{}", v).into_bytes())
-                .unwrap_or_default();
-            match self.bcrypt.as_ref().unwrap().aes_encrypt(&key, &data) {
-                Ok(ct) => self.push(Value::str(hex::encode(ct))),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
-            }
-            return Some(Ok(()));
-        }
+        let hash = self
+            .bcrypt
+            .as_ref()
+            .and_then(|bc| bc.sha256(data.as_bytes()).ok())
+            .unwrap_or_else(|| crypto::sha256(data.as_bytes()));
+        #[cfg(not(feature = "full"))]
+        let hash = crypto::sha256(data.as_bytes());
+        self.push(Value::str(hex::encode(hash)));
+        Ok(())
+    }
 
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_hash_sha512(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let data = args.first().map(|v| format!("{}", v)).unwrap_or_default();
         #[cfg(feature = "full")]
-        if name == "__aes_desencriptar" || name == "__aes_decrypt" {
-            if self.bcrypt.is_none() {
-                match Bcrypt::load() {
-                    Ok(b) => self.bcrypt = Some(Arc::new(b)),
-                    Err(e) => {
-                        self.push(Value::Error(Box::new(Value::str(e))));
-                        return Some(Ok(()));
-                    }
-                }
-            }
-            let key = args
-                .first()
-                .map(|v| format!("{}", v).into_bytes())
-                .unwrap_or_default();
-            let hex_data = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let data = hex::decode(&hex_data).unwrap_or_default();
-            match self.bcrypt.as_ref().unwrap().aes_decrypt(&key, &data) {
-                Ok(pt) => self.push(Value::str(String::from_utf8_lossy(&pt).to_string())),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
-            }
-            return Some(Ok(()));
-        }
-
-        if name == "__jwt_codificar" || name == "__jwt_encode" {
-            let payload = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let secret = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let header = serde_json::json!({"alg": "HS256", "typ": "JWT"});
-            let b64_header = base64url_encode(
-                serde_json::to_string(&header)
-                    .unwrap_or_default()
-                    .as_bytes(),
-            );
-            let b64_payload = base64url_encode(payload.as_bytes());
-            let signature_input = format!("{}.{}", b64_header, b64_payload);
-            let sig = hmac_sha256(signature_input.as_bytes(), secret.as_bytes());
-            let b64_sig = base64url_encode(&sig);
-            self.push(Value::str(format!(
-                "{}.{}.{}",
-                b64_header, b64_payload, b64_sig
-            )));
-            return Some(Ok(()));
-        }
+        let hash = self
+            .bcrypt
+            .as_ref()
+            .and_then(|bc| bc.sha512(data.as_bytes()).ok())
+            .unwrap_or_else(|| crypto::sha512(data.as_bytes()));
+        #[cfg(not(feature = "full"))]
+        let hash = crypto::sha512(data.as_bytes());
+        self.push(Value::str(hex::encode(hash)));
+        Ok(())
+    }
 
-        if name == "__jwt_decodificar" || name == "__jwt_decode" {
-            let token = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let secret = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let parts: Vec<&str> = token.split('.').collect();
-            if parts.len() != 3 {
-                self.push(Value::Error(Box::new(Value::str(
-                    "JWT inválido: se esperan 3 partes",
-                ))));
-                return Some(Ok(()));
-            }
-            let sig_input = format!("{}.{}", parts[0], parts[1]);
-            let expected_sig = hmac_sha256(sig_input.as_bytes(), secret.as_bytes());
-            let actual_sig = base64url_decode(parts[2]);
-            if actual_sig != expected_sig {
-                self.push(Value::Error(Box::new(Value::str("Firma JWT inválida"))));
-                return Some(Ok(()));
-            }
-            match base64url_decode_to_string(parts[1]) {
-                Ok(payload) => self.push(Value::str(payload)),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
-            }
-            return Some(Ok(()));
-        }
-
-        // ██ Utility builtins ██
-        if name == "__fs_listar" || name == "__fs_listdir" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match std::fs::read_dir(&path) {
-                Ok(entries) => {
-                    let mut items = Vec::new();
-                    for entry in entries.flatten() {
-                        items.push(Value::str(entry.file_name().to_string_lossy().to_string()));
-                    }
-                    self.push(Value::arr(items));
-                }
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-            }
-            return Some(Ok(()));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_aes_encriptar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let key = args
+            .first()
+            .map(|v| format!("{}", v).into_bytes())
+            .unwrap_or_default();
+        let data = args
+            .get(1)
+            .map(|v| format!("{}", v).into_bytes())
+            .unwrap_or_default();
+        #[cfg(feature = "full")]
+        let result = match self.bcrypt.as_ref() {
+            Some(bc) => bc.aes_encrypt(&key, &data),
+            None => crypto::aes_cbc_encrypt(&key, &data),
+        };
+        #[cfg(not(feature = "full"))]
+        let result = crypto::aes_cbc_encrypt(&key, &data);
+        match result {
+            Ok(ct) => self.push(Value::str(hex::encode(ct))),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        if name == "__env_listar" || name == "__env_list" {
-            #[cfg(not(target_arch = "wasm32"))]
-            let vars: Vec<Value> = std::env::vars()
-                .map(|(k, v)| Value::str(format!("{}={}", k, v)))
-                .collect();
-            #[cfg(target_arch = "wasm32")]
-            let vars: Vec<Value> = Vec::new();
-            self.push(Value::arr(vars));
-            return Some(Ok(()));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_aes_desencriptar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let key = args
+            .first()
+            .map(|v| format!("{}", v).into_bytes())
+            .unwrap_or_default();
+        let hex_data = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let Ok(data) = hex::decode(&hex_data) else {
+            self.push(Value::Error(Box::new(Value::str(
+                "AES: el texto cifrado debe estar en hexadecimal",
+            ))));
+            return Ok(());
+        };
+        #[cfg(feature = "full")]
+        let result = match self.bcrypt.as_ref() {
+            Some(bc) => bc.aes_decrypt(&key, &data),
+            None => crypto::aes_cbc_decrypt(&key, &data),
+        };
+        #[cfg(not(feature = "full"))]
+        let result = crypto::aes_cbc_decrypt(&key, &data);
+        match result {
+            Ok(pt) => self.push(Value::str(String::from_utf8_lossy(&pt).to_string())),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        // ██ Date builtins ██
-        if name == "__tiempo_formatear" || name == "__time_format" {
-            let timestamp = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
-            let fmt = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let formatted = format_timestamp(timestamp, &fmt);
-            self.push(Value::str(formatted));
-            return Some(Ok(()));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_aes_gcm_encriptar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let key = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let data = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        match crypto::aes_gcm_encrypt(key.as_bytes(), data.as_bytes()) {
+            Ok(ct) => self.push(Value::str(hex::encode(ct))),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        if name == "__tiempo_parsear" || name == "__time_parse" {
-            let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            match parse_iso8601_to_unix(&s) {
-                Ok(ts) => self.push(Value::Int(ts)),
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
-            }
-            return Some(Ok(()));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_aes_gcm_desencriptar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let key = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let hex_data = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let result = hex::decode(&hex_data)
+            .map_err(|_| "AES-GCM: el texto cifrado debe estar en hexadecimal".to_string())
+            .and_then(|data| crypto::aes_gcm_decrypt(key.as_bytes(), &data));
+        match result {
+            Ok(pt) => self.push(Value::str(String::from_utf8_lossy(&pt).to_string())),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        if name == "__tiempo_diferencia" || name == "__time_diff" {
-            let t1 = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
-            let t2 = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
-            let diff = (t1 - t2).abs();
-            self.push(Value::Int(diff));
-            return Some(Ok(()));
-        }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_hmac_sha256(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let key = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let data = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let mac = crypto::hmac_sha256(key.as_bytes(), data.as_bytes());
+        self.push(Value::str(hex::encode(mac)));
+        Ok(())
+    }
 
-        // ██ Coroutine builtins ██
-        if name == "__coro_crear" || name == "__coro_create" {
-            let fn_name = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            if let Some(func) = self.find_func(&fn_name) {
-                let coro = Coroutine::new(&fn_name, func.start);
-                let coro_id = format!("coro_{}", self.coroutines.len());
-                self.coroutines.insert(coro_id.clone(), coro);
-                self.push(Value::str(coro_id));
-            } else {
-                self.push(Value::Error(Box::new(Value::str(format!(
-                    "Función '{}' no encontrada",
-                    fn_name
-                )))));
-            }
-            return Some(Ok(()));
-        }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_hmac_sha512(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let key = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let data = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let mac = crypto::hmac_sha512(key.as_bytes(), data.as_bytes());
+        self.push(Value::str(hex::encode(mac)));
+        Ok(())
+    }
 
-        if name == "__coro_ceder" || name == "__coro_yield" {
-            if let Some(ref coro_id) = self.current_coro.clone() {
-                if let Some(coro) = self.coroutines.get_mut(coro_id) {
-                    coro.stack = self.stack.clone();
-                    coro.locals = self.locals.clone();
-                    coro.ip = self.ip;
-                }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_pbkdf2(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let password = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let salt = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let rounds = match args.get(2) {
+            Some(Value::Int(n)) if *n > 0 && *n <= u32::MAX as i64 => *n as u32,
+            _ => {
+                self.push(Value::Error(Box::new(Value::str(
+                    "PBKDF2: las iteraciones deben ser un entero positivo",
+                ))));
+                return Ok(());
             }
-            // Restore main saved state
-            if let Some((saved_stack, saved_locals, saved_ip)) = self.main_saved.take() {
-                self.stack = saved_stack;
-                self.locals = saved_locals;
-                self.ip = saved_ip;
+        };
+        let len = match byte_len_arg(args.get(3), 1, crypto::MAX_KEY_LEN, "PBKDF2") {
+            Ok(len) => len,
+            Err(e) => {
+                self.push(Value::Error(Box::new(Value::str(e))));
+                return Ok(());
             }
-            self.current_coro = None;
-            self.push(Value::Void);
-            return Some(Ok(()));
+        };
+        // La clave y su hexadecimal.
+        self.budget.check_alloc(len * 3).map_err(VmError::Sandbox)?;
+        match crypto::pbkdf2_sha256(password.as_bytes(), salt.as_bytes(), rounds, len) {
+            Ok(key) => self.push(Value::str(hex::encode(key))),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        if name == "__coro_reanudar" || name == "__coro_resume" {
-            let coro_id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            if let Some(coro) = self.coroutines.get(&coro_id) {
-                if coro.is_done {
-                    self.push(Value::Error(Box::new(Value::str("Coroutine terminada"))));
-                    return Some(Ok(()));
-                }
-            }
-            // Save main state before resuming coroutine
-            self.main_saved = Some((self.stack.clone(), self.locals.clone(), self.ip));
-            if let Some(coro) = self.coroutines.get_mut(&coro_id) {
-                self.stack = coro.stack.clone();
-                self.locals = coro.locals.clone();
-                self.ip = coro.ip;
-                self.current_coro = Some(coro_id.clone());
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_hkdf(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let ikm = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let salt = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let info = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
+        let len = match byte_len_arg(args.get(3), 1, crypto::MAX_KEY_LEN, "HKDF") {
+            Ok(len) => len,
+            Err(e) => {
+                self.push(Value::Error(Box::new(Value::str(e))));
+                return Ok(());
             }
-            self.push(Value::Void);
-            return Some(Ok(()));
+        };
+        self.budget.check_alloc(len * 3).map_err(VmError::Sandbox)?;
+        match crypto::hkdf_sha256(ikm.as_bytes(), salt.as_bytes(), info.as_bytes(), len) {
+            Ok(key) => self.push(Value::str(hex::encode(key))),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        // ██ GUI builtins ██
-        #[cfg(feature = "full")]
-        if name == "__gui_ventana" || name == "__gui_window" {
-            let title = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let width = args.get(1).and_then(|v| v.as_num()).unwrap_or(800.0) as i32;
-            let height = args.get(2).and_then(|v| v.as_num()).unwrap_or(600.0) as i32;
-            match GuiWindow::create(&title, width, height) {
-                Ok(w) => {
-                    let wid = format!("win_{}", self.gui_windows.len());
-                    self.gui_windows.insert(wid.clone(), w);
-                    self.push(Value::str(wid));
-                }
-                Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_bytes_aleatorios(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let len = match byte_len_arg(args.first(), 0, crypto::MAX_RANDOM_LEN, "bytes_aleatorios") {
+            Ok(len) => len,
+            Err(e) => {
+                self.push(Value::Error(Box::new(Value::str(e))));
+                return Ok(());
             }
-            return Some(Ok(()));
+        };
+        self.budget.check_alloc(len * 3).map_err(VmError::Sandbox)?;
+        let mut buf = vec![0u8; len];
+        match crypto::fill_random(&mut buf) {
+            Ok(()) => self.push(Value::str(hex::encode(buf))),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__gui_mostrar" || name == "__gui_show" {
-            let id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            if let Some(w) = self.gui_windows.get(&id) {
-                w.show();
-                self.push(Value::Bool(true));
-            } else {
-                self.push(Value::Error(Box::new(Value::str(format!(
-                    "Ventana '{}' no encontrada",
-                    id
-                )))));
-            }
-            return Some(Ok(()));
-        }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_jwt_codificar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let payload = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let secret = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let header = serde_json::json!({"alg": "HS256", "typ": "JWT"});
+        let b64_header = base64url_encode(
+            serde_json::to_string(&header)
+                .unwrap_or_default()
+                .as_bytes(),
+        );
+        let b64_payload = base64url_encode(payload.as_bytes());
+        let signature_input = format!("{}.{}", b64_header, b64_payload);
+        let sig = crypto::hmac_sha256(secret.as_bytes(), signature_input.as_bytes());
+        let b64_sig = base64url_encode(&sig);
+        self.push(Value::str(format!(
+            "{}.{}.{}",
+            b64_header, b64_payload, b64_sig
+        )));
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__gui_cerrar" || name == "__gui_close" {
-            let id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            if self.gui_windows.remove(&id).is_some() {
-                self.push(Value::Bool(true));
-            } else {
-                self.push(Value::Bool(false));
-            }
-            return Some(Ok(()));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_jwt_decodificar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let token = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let secret = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let parts: Vec<&str> = token.split('.').collect();
+        if parts.len() != 3 {
+            self.push(Value::Error(Box::new(Value::str(
+                "JWT inválido: se esperan 3 partes",
+            ))));
+            return Ok(());
         }
-
-        #[cfg(feature = "full")]
-        if name == "__gui_id" || name == "__gui_hwnd" {
-            let id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            if let Some(w) = self.gui_windows.get(&id) {
-                self.push(Value::Int(w.hwnd() as i64));
-            } else {
-                self.push(Value::Error(Box::new(Value::str(format!(
-                    "Ventana '{}' no encontrada",
-                    id
-                )))));
-            }
-            return Some(Ok(()));
+        let sig_input = format!("{}.{}", parts[0], parts[1]);
+        let expected_sig = crypto::hmac_sha256(secret.as_bytes(), sig_input.as_bytes());
+        let actual_sig = base64url_decode(parts[2]);
+        if actual_sig != expected_sig {
+            self.push(Value::Error(Box::new(Value::str("Firma JWT inválida"))));
+            return Ok(());
+        }
+        match base64url_decode_to_string(parts[1]) {
+            Ok(payload) => self.push(Value::str(payload)),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        #[cfg(feature = "full")]
-        if name == "__gui_esperar" || name == "__gui_poll" {
-            let id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            if let Some(w) = self.gui_windows.get_mut(&id) {
-                match w.poll_event() {
-                    Some(_evt) => self.push(Value::Bool(true)),
-                    None => self.push(Value::Bool(false)),
+    // ██ Utility builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_fs_listar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        match std::fs::read_dir(&path) {
+            Ok(entries) => {
+                let mut items = Vec::new();
+                for entry in entries.flatten() {
+                    items.push(Value::str(entry.file_name().to_string_lossy().to_string()));
                 }
-            } else {
-                self.push(Value::Bool(false));
+                self.push(Value::arr(items));
             }
-            return Some(Ok(()));
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
         }
+        Ok(())
+    }
 
-        // ██ Async/Task builtins ██
-        if name == "__tarea_lanzar" || name == "__task_spawn" {
-            let fn_name = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let fn_args: Vec<Value> = args.into_iter().skip(1).collect();
-            let id = self.task_counter;
-            self.task_counter += 1;
-            let task_id = format!("task_{}", id);
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                let bc = self.bytecode.clone();
-                let (tx, rx) = std::sync::mpsc::channel();
-                std::thread::spawn(move || {
-                    let mut vm = VM::new(bc);
-                    let result = vm.run_function(&fn_name, fn_args);
-                    let _ = tx.send(result.unwrap_or(Value::Void));
-                });
-                self.task_results.insert(task_id.clone(), rx);
-            }
-            #[cfg(target_arch = "wasm32")]
-            {
-                let bc = self.bytecode.clone();
-                let mut vm = VM::new(bc);
-                let result = vm.run_function(&fn_name, fn_args);
-                self.task_results_sync
-                    .insert(task_id.clone(), result.unwrap_or(Value::Void));
-            }
-            self.push(Value::str(task_id));
-            return Some(Ok(()));
-        }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_env_listar(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let vars: Vec<Value> = std::env::vars()
+            .map(|(k, v)| Value::str(format!("{}={}", k, v)))
+            .collect();
+        #[cfg(target_arch = "wasm32")]
+        let vars: Vec<Value> = Vec::new();
+        self.push(Value::arr(vars));
+        Ok(())
+    }
 
-        if name == "__tarea_esperar" || name == "__task_await" {
-            let task_id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            #[cfg(not(target_arch = "wasm32"))]
-            let found = {
-                if let Some(rx) = self.task_results.remove(&task_id) {
-                    match rx.recv() {
-                        Ok(val) => Some(val),
-                        Err(_) => Some(Value::Error(Box::new(Value::str("Task failed")))),
-                    }
-                } else {
-                    None
-                }
-            };
-            #[cfg(target_arch = "wasm32")]
-            let found = self.task_results_sync.remove(&task_id);
-            match found {
-                Some(val) => self.push(val),
-                None => self.push(Value::Error(Box::new(Value::str("Task not found")))),
-            }
-            return Some(Ok(()));
-        }
-
-        // ██ Timezone builtins ██
-        if name == "__timezone_info" || name == "__zona_info" {
-            let tz = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let offset = match tz.to_lowercase().as_str() {
-                "utc" | "gmt" => 0,
-                "est" | "et" | "america/new_york" | "us/eastern" => -5,
-                "cst" | "ct" | "america/chicago" | "us/central" => -6,
-                "mst" | "mt" | "america/denver" | "us/mountain" => -7,
-                "pst" | "pt" | "america/los_angeles" | "us/pacific" => -8,
-                "cet" | "europe/madrid" | "europe/paris" | "europe/berlin" => 1,
-                "eet" | "europe/athens" | "europe/helsinki" => 2,
-                "ist" | "asia/kolkata" => 5,
-                "jst" | "asia/tokyo" => 9,
-                "aest" | "australia/sydney" => 10,
-                "nzst" | "pacific/auckland" => 12,
-                _ => 0,
-            };
-            self.push(Value::Int(offset));
-            return Some(Ok(()));
-        }
-
-        // ██ Duration builtins ██
-        if name == "__duration_new" || name == "__duracion_nueva" {
-            let secs = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
-            let nanos = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
-            self.push(Value::Int(secs * 1_000_000_000 + nanos));
-            return Some(Ok(()));
-        }
-        if name == "__duration_secs" || name == "__duracion_segundos" {
-            let nanos = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
-            self.push(Value::Int(nanos / 1_000_000_000));
-            return Some(Ok(()));
-        }
-
-        // ██ Calendar builtins ██
-        if name == "__calendar_hijri" || name == "__calendario_hijri" {
-            let timestamp = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
-            let days = timestamp / 86400 + 719163;
-            let hijri_year = ((days as f64) / 354.367) as i64 + 1;
-            let remaining = days - (((hijri_year - 1) as f64) * 354.367) as i64;
-            let hijri_month = (remaining / 30).clamp(1, 12);
-            let hijri_day = (remaining % 30 + 1).min(30);
-            let result = format!("{}-{:02}-{:02} AH", hijri_year, hijri_month, hijri_day);
-            self.push(Value::str(result));
-            return Some(Ok(()));
-        }
-
-        if name == "__calendar_persian" || name == "__calendario_persa" {
-            let timestamp = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
-            let days = timestamp / 86400;
-            let persian_year = ((days as f64 - 226899.0) / 365.242) as i64 + 1;
-            let persian_month = 1 + ((days % 365) / 31).min(11);
-            let persian_day = 1 + (days % 31).min(30);
-            let result = format!(
-                "{}-{:02}-{:02} AP",
-                persian_year, persian_month, persian_day
-            );
-            self.push(Value::str(result));
-            return Some(Ok(()));
+    // ██ Date builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_tiempo_formatear(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let timestamp = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+        let fmt = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let formatted = format_timestamp(timestamp, &fmt);
+        self.push(Value::str(formatted));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_tiempo_parsear(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        match parse_iso8601_to_unix(&s) {
+            Ok(ts) => self.push(Value::Int(ts)),
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
         }
+        Ok(())
+    }
 
-        // ██ Async File I/O builtins ██
-        if name == "__leer_archivo_async" || name == "__file_read_async" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let id = self.task_counter;
-            self.task_counter += 1;
-            let task_id = format!("file_{}", id);
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                let (tx, rx) = std::sync::mpsc::channel();
-                std::thread::spawn(move || {
-                    let content = std::fs::read_to_string(&path);
-                    let _ = tx.send(match content {
-                        Ok(s) => Value::str(s),
-                        Err(e) => Value::Error(Box::new(Value::str(e.to_string()))),
-                    });
-                });
-                self.task_results.insert(task_id.clone(), rx);
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_tiempo_diferencia(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let t1 = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+        let t2 = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+        let diff = (t1 - t2).abs();
+        self.push(Value::Int(diff));
+        Ok(())
+    }
+
+    // ██ Coroutine builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_coro_crear(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let fn_name = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(&func_idx) = self.func_index_cache.get(&fn_name) {
+            let mut coro = Coroutine::new(&fn_name, self.bytecode.funcs[func_idx].start);
+            coro.locals = vec![LocalFrame::default(), self.new_frame(func_idx, Vec::new())];
+            let coro_id = format!("coro_{}", self.coroutines.len());
+            self.coroutines.insert(coro_id.clone(), coro);
+            self.push(Value::str(coro_id));
+        } else {
+            self.push(Value::Error(Box::new(Value::str(format!(
+                "Función '{}' no encontrada",
+                fn_name
+            )))));
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_coro_ceder(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        if let Some(ref coro_id) = self.current_coro.clone() {
+            if let Some(coro) = self.coroutines.get_mut(coro_id) {
+                coro.stack = self.stack.clone();
+                coro.locals = self.locals.clone();
+                coro.ip = self.ip;
+            }
+        }
+        // Restore main saved state
+        if let Some((saved_stack, saved_locals, saved_ip)) = self.main_saved.take() {
+            self.stack = saved_stack;
+            self.locals = saved_locals;
+            self.ip = saved_ip;
+        }
+        self.current_coro = None;
+        self.push(Value::Void);
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_coro_reanudar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let coro_id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(coro) = self.coroutines.get(&coro_id) {
+            if coro.is_done {
+                self.push(Value::Error(Box::new(Value::str("Coroutine terminada"))));
+                return Ok(());
+            }
+        }
+        // Save main state before resuming coroutine
+        self.main_saved = Some((self.stack.clone(), self.locals.clone(), self.ip));
+        if let Some(coro) = self.coroutines.get_mut(&coro_id) {
+            self.stack = coro.stack.clone();
+            self.locals = coro.locals.clone();
+            self.ip = coro.ip;
+            self.current_coro = Some(coro_id.clone());
+        }
+        self.push(Value::Void);
+        Ok(())
+    }
+
+    // ██ GUI builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_gui_ventana(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let title = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let width = args.get(1).and_then(|v| v.as_num()).unwrap_or(800.0) as i32;
+        let height = args.get(2).and_then(|v| v.as_num()).unwrap_or(600.0) as i32;
+        match GuiWindow::create(&title, width, height) {
+            Ok(w) => {
+                let wid = format!("win_{}", self.gui_windows.len());
+                self.gui_windows.insert(wid.clone(), w);
+                self.push(Value::str(wid));
+            }
+            Err(e) => self.push(Value::Error(Box::new(Value::str(e)))),
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_gui_mostrar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(w) = self.gui_windows.get(&id) {
+            w.show();
+            self.push(Value::Bool(true));
+        } else {
+            self.push(Value::Error(Box::new(Value::str(format!(
+                "Ventana '{}' no encontrada",
+                id
+            )))));
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_gui_cerrar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if self.gui_windows.remove(&id).is_some() {
+            self.push(Value::Bool(true));
+        } else {
+            self.push(Value::Bool(false));
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_gui_id(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(w) = self.gui_windows.get(&id) {
+            self.push(Value::Int(w.hwnd() as i64));
+        } else {
+            self.push(Value::Error(Box::new(Value::str(format!(
+                "Ventana '{}' no encontrada",
+                id
+            )))));
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[cfg(feature = "full")]
+    fn builtin_gui_esperar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(w) = self.gui_windows.get_mut(&id) {
+            match w.poll_event() {
+                Some(_evt) => self.push(Value::Bool(true)),
+                None => self.push(Value::Bool(false)),
             }
-            #[cfg(target_arch = "wasm32")]
-            {
+        } else {
+            self.push(Value::Bool(false));
+        }
+        Ok(())
+    }
+
+    // ██ Async/Task builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_tarea_lanzar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let callee = args.first().cloned().unwrap_or(Value::Void);
+        let fn_args: Vec<Value> = args.into_iter().skip(1).collect();
+        let id = self.task_counter;
+        self.task_counter += 1;
+        let task_id = format!("task_{}", id);
+        self.spawn_task(task_id.clone(), &callee, fn_args);
+        self.push(Value::str(task_id));
+        Ok(())
+    }
+
+    /// Lo que comparten las hijas de esta VM: bytecode, builtins, canales,
+    /// actores, primitivas de sincronización y una instantánea de sus globales (se copian al escribir).
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn kin(&self) -> Kin {
+        Kin {
+            bytecode: self.bytecode.clone(),
+            func_index_cache: self.func_index_cache.clone(),
+            builtins: self.builtins.clone(),
+            call_targets: self.call_targets.clone(),
+            globals: self.globals.clone(),
+            channels: self.channels.clone(),
+            actors: self.actors.clone(),
+            sync: self.sync.clone(),
+            budget: self.budget.clone(),
+            #[cfg(feature = "full")]
+            bcrypt: self.bcrypt.clone(),
+        }
+    }
+
+    /// VM hija para una tarea.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn child(&self) -> VM {
+        self.kin().vm(None)
+    }
+
+    /// Corre `callee(args)` en una fibra del planificador. El resultado llega
+    /// por el receptor devuelto, ya pasado por `finish`, y al llegar se avisa
+    /// a quien espera `key`.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    fn spawn_fiber(
+        mut vm: VM,
+        key: String,
+        callee: &Value,
+        args: Vec<Value>,
+        finish: impl FnOnce(Result<Value, VmError>) -> Value + Send + 'static,
+    ) -> std::sync::mpsc::Receiver<Value> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        match vm.start_fiber(callee, args) {
+            Ok(()) => scheduler::spawn(vm, move |result| {
+                scheduler::send(&tx, finish(result), &key);
+            }),
+            Err(e) => {
+                scheduler::send(&tx, finish(Err(e)), &key);
+            }
+        }
+        rx
+    }
+
+    /// Lanza `callee(args)` en una fibra hija que avisa a `key` al terminar;
+    /// un error cuenta como nulo.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    fn fork(
+        &self,
+        key: String,
+        callee: &Value,
+        args: Vec<Value>,
+    ) -> std::sync::mpsc::Receiver<Value> {
+        VM::spawn_fiber(self.child(), key, callee, args, |result| {
+            result.unwrap_or(Value::Void)
+        })
+    }
+
+    /// Lanza una tarea hija con el identificador `id`; en wasm se ejecuta en
+    /// el acto.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn spawn_task(&mut self, id: String, callee: &Value, args: Vec<Value>) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let rx = self.fork(id.clone(), callee, args);
+            self.task_results.insert(id, rx);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let mut vm = self.child();
+            let result = vm.call_value(callee, args).unwrap_or(Value::Void);
+            self.task_results_sync.insert(id, result);
+        }
+    }
+
+    /// Si la tarea `id` ya terminó, deja su resultado en `task_results_sync`.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn task_ready(&mut self, id: &str) -> bool {
+        if self.task_results_sync.contains_key(id) {
+            return true;
+        }
+        let val = match self.task_results.get(id).map(|rx| rx.try_recv()) {
+            Some(Ok(val)) => val,
+            Some(Err(std::sync::mpsc::TryRecvError::Disconnected)) => {
+                Value::Error(Box::new(Value::str("Task failed")))
+            }
+            _ => return false,
+        };
+        self.task_results.remove(id);
+        self.task_results_sync.insert(id.to_string(), val);
+        true
+    }
+
+    /// Saca el resultado de la tarea `id`, esperándola si aún corre.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn take_task(&mut self, id: &str) -> Option<Value> {
+        if let Some(val) = self.task_results_sync.remove(id) {
+            return Some(val);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(rx) = self.task_results.remove(id) {
+            return Some(scheduler::recv(&rx));
+        }
+        None
+    }
+
+    /// `__tarea_esperar` y `__hilo_esperar`: una fibra se aparca mientras la
+    /// tarea no termine, en vez de ocupar su hilo.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn await_task(&mut self, args: Vec<Value>, not_found: &str) -> Result<(), VmError> {
+        let id = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.parkable() && self.task_results.contains_key(&id) {
+            let epoch = scheduler::epoch();
+            if !self.task_ready(&id) {
+                let waits = vec![id];
+                return self.park(
+                    args,
+                    scheduler::Park {
+                        waits,
+                        until: None,
+                        epoch,
+                    },
+                );
+            }
+        }
+        match self.take_task(&id) {
+            Some(val) => self.push(val),
+            None => self.push(Value::Error(Box::new(Value::str(not_found)))),
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_tarea_esperar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        self.await_task(args, "Task not found")
+    }
+
+    // ██ Timezone builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_timezone_info(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let tz = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let offset = match tz.to_lowercase().as_str() {
+            "utc" | "gmt" => 0,
+            "est" | "et" | "america/new_york" | "us/eastern" => -5,
+            "cst" | "ct" | "america/chicago" | "us/central" => -6,
+            "mst" | "mt" | "america/denver" | "us/mountain" => -7,
+            "pst" | "pt" | "america/los_angeles" | "us/pacific" => -8,
+            "cet" | "europe/madrid" | "europe/paris" | "europe/berlin" => 1,
+            "eet" | "europe/athens" | "europe/helsinki" => 2,
+            "ist" | "asia/kolkata" => 5,
+            "jst" | "asia/tokyo" => 9,
+            "aest" | "australia/sydney" => 10,
+            "nzst" | "pacific/auckland" => 12,
+            _ => 0,
+        };
+        self.push(Value::Int(offset));
+        Ok(())
+    }
+
+    // ██ Duration builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_duration_new(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let secs = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+        let nanos = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+        self.push(Value::Int(secs * 1_000_000_000 + nanos));
+        Ok(())
+    }
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_duration_secs(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let nanos = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+        self.push(Value::Int(nanos / 1_000_000_000));
+        Ok(())
+    }
+
+    // ██ Calendar builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_calendar_hijri(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let timestamp = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+        let days = timestamp / 86400 + 719163;
+        let hijri_year = ((days as f64) / 354.367) as i64 + 1;
+        let remaining = days - (((hijri_year - 1) as f64) * 354.367) as i64;
+        let hijri_month = (remaining / 30).clamp(1, 12);
+        let hijri_day = (remaining % 30 + 1).min(30);
+        let result = format!("{}-{:02}-{:02} AH", hijri_year, hijri_month, hijri_day);
+        self.push(Value::str(result));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_calendar_persian(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let timestamp = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+        let days = timestamp / 86400;
+        let persian_year = ((days as f64 - 226899.0) / 365.242) as i64 + 1;
+        let persian_month = 1 + ((days % 365) / 31).min(11);
+        let persian_day = 1 + (days % 31).min(30);
+        let result = format!(
+            "{}-{:02}-{:02} AP",
+            persian_year, persian_month, persian_day
+        );
+        self.push(Value::str(result));
+        Ok(())
+    }
+
+    // ██ Async File I/O builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_leer_archivo_async(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.check_file_alloc(&path, 1)?;
+        let id = self.task_counter;
+        self.task_counter += 1;
+        let task_id = format!("file_{}", id);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let key = task_id.clone();
+            std::thread::spawn(move || {
                 let content = std::fs::read_to_string(&path);
                 let val = match content {
                     Ok(s) => Value::str(s),
                     Err(e) => Value::Error(Box::new(Value::str(e.to_string()))),
                 };
-                self.task_results_sync.insert(task_id.clone(), val);
-            }
-            self.push(Value::str(task_id));
-            return Some(Ok(()));
+                scheduler::send(&tx, val, &key);
+            });
+            self.task_results.insert(task_id.clone(), rx);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let content = std::fs::read_to_string(&path);
+            let val = match content {
+                Ok(s) => Value::str(s),
+                Err(e) => Value::Error(Box::new(Value::str(e.to_string()))),
+            };
+            self.task_results_sync.insert(task_id.clone(), val);
         }
+        self.push(Value::str(task_id));
+        Ok(())
+    }
 
-        if name == "__escribir_archivo_async" || name == "__file_write_async" {
-            let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let content = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let id = self.task_counter;
-            self.task_counter += 1;
-            let task_id = format!("file_{}", id);
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                let (tx, rx) = std::sync::mpsc::channel();
-                std::thread::spawn(move || {
-                    let result = std::fs::write(&path, &content);
-                    let _ = tx.send(match result {
-                        Ok(()) => Value::Bool(true),
-                        Err(e) => Value::Error(Box::new(Value::str(e.to_string()))),
-                    });
-                });
-                self.task_results.insert(task_id.clone(), rx);
-            }
-            #[cfg(target_arch = "wasm32")]
-            {
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_escribir_archivo_async(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let content = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let id = self.task_counter;
+        self.task_counter += 1;
+        let task_id = format!("file_{}", id);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let key = task_id.clone();
+            std::thread::spawn(move || {
                 let result = std::fs::write(&path, &content);
                 let val = match result {
                     Ok(()) => Value::Bool(true),
                     Err(e) => Value::Error(Box::new(Value::str(e.to_string()))),
                 };
-                self.task_results_sync.insert(task_id.clone(), val);
-            }
-            self.push(Value::str(task_id));
-            return Some(Ok(()));
+                scheduler::send(&tx, val, &key);
+            });
+            self.task_results.insert(task_id.clone(), rx);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let result = std::fs::write(&path, &content);
+            let val = match result {
+                Ok(()) => Value::Bool(true),
+                Err(e) => Value::Error(Box::new(Value::str(e.to_string()))),
+            };
+            self.task_results_sync.insert(task_id.clone(), val);
         }
+        self.push(Value::str(task_id));
+        Ok(())
+    }
 
-        // ██ Async Timer builtins ██
-        if name == "__timer_delay" || name == "__temporizador_esperar" {
-            #[allow(unused_variables)]
-            let ms = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as u64;
-            let id = self.task_counter;
-            self.task_counter += 1;
-            let task_id = format!("timer_{}", id);
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                let (tx, rx) = std::sync::mpsc::channel();
-                std::thread::spawn(move || {
-                    std::thread::sleep(std::time::Duration::from_millis(ms));
-                    let _ = tx.send(Value::Bool(true));
-                });
-                self.task_results.insert(task_id.clone(), rx);
+    // ██ Async Timer builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_timer_delay(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[allow(unused_variables)]
+        let ms = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as u64;
+        let id = self.task_counter;
+        self.task_counter += 1;
+        let task_id = format!("timer_{}", id);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let at = std::time::Instant::now() + std::time::Duration::from_millis(ms);
+            scheduler::timer(at, tx, task_id.clone());
+            self.task_results.insert(task_id.clone(), rx);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.task_results_sync
+                .insert(task_id.clone(), Value::Bool(true));
+        }
+        self.push(Value::str(task_id));
+        Ok(())
+    }
+
+    // ██ Async TCP connect builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_tcp_connect_async(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[allow(unused_variables)]
+        let addr = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let id = self.task_counter;
+        self.task_counter += 1;
+        let task_id = format!("tcp_{}", id);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let key = task_id.clone();
+            std::thread::spawn(move || {
+                let val = match std::net::TcpStream::connect(&addr) {
+                    Ok(_) => Value::Bool(true),
+                    Err(e) => Value::Error(Box::new(Value::str(e.to_string()))),
+                };
+                scheduler::send(&tx, val, &key);
+            });
+            self.task_results.insert(task_id.clone(), rx);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.task_results_sync.insert(
+                task_id.clone(),
+                Value::Error(Box::new(Value::str("TCP no soportado en wasm"))),
+            );
+        }
+        self.push(Value::str(task_id));
+        Ok(())
+    }
+
+    // ██ Concurrency builtins ██
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_dormir(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[allow(unused_variables)]
+        let ms = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as u64;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let wait = std::time::Duration::from_millis(ms);
+            if self.parkable() {
+                let now = std::time::Instant::now();
+                let deadline = *self.park_deadline.get_or_insert(now + wait);
+                if now < deadline {
+                    let park = scheduler::Park {
+                        until: Some(deadline),
+                        ..Default::default()
+                    };
+                    return self.park(args, park);
+                }
+                self.park_deadline = None;
+            } else {
+                scheduler::sleep(self.budget.deadline.cap(wait));
+                self.budget.check_deadline().map_err(VmError::Sandbox)?;
             }
-            #[cfg(target_arch = "wasm32")]
-            {
-                self.task_results_sync
-                    .insert(task_id.clone(), Value::Bool(true));
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = ms;
+        }
+        self.push(Value::Void);
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_hilo_lanzar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let callee = args.first().cloned().unwrap_or(Value::Void);
+        let fn_args: Vec<Value> = args.into_iter().skip(1).collect();
+        let hid = format!("thread_{}", self.task_counter);
+        self.task_counter += 1;
+        self.spawn_task(hid.clone(), &callee, fn_args);
+        self.push(Value::str(hid));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_hilo_esperar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        self.await_task(args, "Thread not found")
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn lock_channels(&self) -> std::sync::MutexGuard<'_, HashMap<String, ChannelCell>> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_canal_nuevo(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        let (tx, rx) = std::sync::mpsc::channel::<Value>();
+        let cid = {
+            let mut channels = self.lock_channels();
+            let cid = format!("chan_{}", channels.len());
+            channels.insert(cid.clone(), (Some(tx), Some(rx)));
+            cid
+        };
+        self.push(Value::str(cid));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_canal_enviar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let cid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let val = args.get(1).cloned().unwrap_or(Value::Void);
+        let tx = self
+            .lock_channels()
+            .get(&cid)
+            .and_then(|(tx, _)| tx.clone());
+        match tx {
+            Some(tx) => {
+                let sent = tx.send(val).is_ok();
+                wake_waiters(&cid);
+                self.push(Value::Bool(sent));
+            }
+            None => self.push(Value::Error(Box::new(Value::str("Channel not found")))),
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_canal_recibir(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let cid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if !self.lock_channels().contains_key(&cid) {
+            self.push(Value::Error(Box::new(Value::str("Channel not found"))));
+            return Ok(());
+        }
+        let handles = std::slice::from_ref(&cid);
+        let Some(hit) = self.select_or_park(&args, handles, None)? else {
+            return Ok(());
+        };
+        self.push(hit.map_or(Value::Void, |(_, val)| val));
+        Ok(())
+    }
+
+    /// Saca sin bloquear un valor listo de un canal o del resultado de una
+    /// tarea (que deja de estar pendiente). `Err` si no es ninguna de las dos.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn try_take(&mut self, handle: &str) -> Result<Option<Value>, VmError> {
+        if let Some((_, rx)) = self.lock_channels().get(handle) {
+            return Ok(rx.as_ref().and_then(|rx| rx.try_recv().ok()));
+        }
+        if self.task_ready(handle) {
+            return Ok(self.task_results_sync.remove(handle));
+        }
+        if self.task_results.contains_key(handle) {
+            return Ok(None);
+        }
+        Err(VmError::Runtime(format!(
+            "'{}' no es un canal ni una tarea pendiente",
+            handle
+        )))
+    }
+
+    /// Primer valor listo entre `handles`, sin esperar.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn select_once(&mut self, handles: &[String]) -> Result<Option<(usize, Value)>, VmError> {
+        for (i, handle) in handles.iter().enumerate() {
+            if let Some(val) = self.try_take(handle)? {
+                return Ok(Some((i, val)));
             }
-            self.push(Value::str(task_id));
-            return Some(Ok(()));
         }
+        Ok(None)
+    }
 
-        // ██ Async TCP connect builtins ██
-        if name == "__tcp_connect_async" || name == "__tcp_conectar_async" {
-            #[allow(unused_variables)]
-            let addr = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let id = self.task_counter;
-            self.task_counter += 1;
-            let task_id = format!("tcp_{}", id);
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                let (tx, rx) = std::sync::mpsc::channel();
-                std::thread::spawn(move || match std::net::TcpStream::connect(&addr) {
-                    Ok(_) => {
-                        let _ = tx.send(Value::Bool(true));
+    /// Espera a que alguno de `handles` (canales o tareas) tenga un valor y
+    /// devuelve su posición junto al valor. Con `timeout` devuelve `None` al
+    /// vencer el plazo; un plazo cero consulta una sola vez. En wasm no hay
+    /// otros hilos que puedan enviar, así que nunca bloquea.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn select_ready(
+        &mut self,
+        handles: &[String],
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Option<(usize, Value)>, VmError> {
+        if let Some(hit) = self.select_once(handles)? {
+            return Ok(Some(hit));
+        }
+        self.check_cancelled()?;
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = timeout;
+            Ok(None)
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let deadline = timeout.map(|t| std::time::Instant::now() + t);
+            let until = self.budget.deadline.clamp(deadline);
+            scheduler::wait_until(until, || match self.select_once(handles) {
+                Ok(None) => match self.check_cancelled() {
+                    Err(e) => Some(Err(e)),
+                    Ok(()) if deadline.is_some_and(|d| std::time::Instant::now() >= d) => {
+                        Some(Ok(None))
                     }
-                    Err(e) => {
-                        let _ = tx.send(Value::Error(Box::new(Value::str(e.to_string()))));
+                    Ok(()) => None,
+                },
+                other => Some(other),
+            })
+        }
+    }
+
+    /// Como `select_ready`, pero en el nivel superior de una fibra la aparca
+    /// en vez de esperar; entonces devuelve `None` y el builtin debe salir
+    /// sin apilar nada (se repetirá al reanudarse).
+    #[cfg(any(feature = "extra", feature = "full"))]
+    #[allow(clippy::type_complexity)]
+    fn select_or_park(
+        &mut self,
+        args: &[Value],
+        handles: &[String],
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Option<Option<(usize, Value)>>, VmError> {
+        #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+        if self.parkable() {
+            let epoch = scheduler::epoch();
+            if let Some(hit) = self.select_once(handles)? {
+                self.park_deadline = None;
+                return Ok(Some(Some(hit)));
+            }
+            let now = std::time::Instant::now();
+            let deadline = timeout.map(|t| *self.park_deadline.get_or_insert(now + t));
+            if deadline.is_some_and(|d| now >= d) {
+                self.park_deadline = None;
+                return Ok(Some(None));
+            }
+            let park = scheduler::Park {
+                waits: handles.to_vec(),
+                until: deadline,
+                epoch,
+            };
+            self.park(args.to_vec(), park)?;
+            return Ok(None);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = args;
+        self.select_ready(handles, timeout).map(Some)
+    }
+
+    /// Corta la ejecución si el ámbito al que pertenece esta VM fue cancelado
+    /// o si venció su plazo; las esperas bloqueantes la consultan.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn check_cancelled(&self) -> Result<(), VmError> {
+        self.budget.check_deadline().map_err(VmError::Sandbox)?;
+        match &self.cancel {
+            Some(flag) if flag.load(std::sync::atomic::Ordering::Relaxed) => {
+                Err(VmError::Runtime("Tarea cancelada".to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn lock_sync(&self) -> std::sync::MutexGuard<'_, sync::SyncTable> {
+        self.sync.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Repite `attempt` hasta que devuelva un valor; recibe si ya venció
+    /// `timeout`. Entre intentos espera un aviso sobre alguna de `waits`. En
+    /// el nivel superior de una fibra la aparca entre intentos y devuelve
+    /// `None`: el builtin sale sin apilar nada y se repetirá al reanudarse.
+    /// En wasm no hay nadie más que pueda liberar nada, así que solo se
+    /// intenta una vez.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn retry_or_park(
+        &mut self,
+        args: &[Value],
+        waits: &[&str],
+        timeout: Option<std::time::Duration>,
+        mut attempt: impl FnMut(&mut VM, bool) -> Option<Value>,
+    ) -> Result<Option<Value>, VmError> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = (args, waits, timeout);
+            attempt(self, true).map(Some).ok_or_else(|| {
+                VmError::Runtime("Bloqueo: nadie más puede liberar la espera".to_string())
+            })
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let now = std::time::Instant::now();
+            let result = if self.parkable() {
+                let epoch = scheduler::epoch();
+                let deadline = timeout.map(|t| *self.park_deadline.get_or_insert(now + t));
+                if let Some(val) = attempt(self, deadline.is_some_and(|d| now >= d)) {
+                    self.park_deadline = None;
+                    return Ok(Some(val));
+                }
+                let park = scheduler::Park {
+                    waits: waits.iter().map(|w| w.to_string()).collect(),
+                    until: deadline,
+                    epoch,
+                };
+                self.park(args.to_vec(), park).map(|()| None)
+            } else {
+                let deadline = timeout.map(|t| now + t);
+                let until = self.budget.deadline.clamp(deadline);
+                scheduler::wait_until(until, || match self.check_cancelled() {
+                    Err(e) => Some(Err(e)),
+                    Ok(()) => {
+                        let expired = deadline.is_some_and(|d| std::time::Instant::now() >= d);
+                        attempt(self, expired).map(|val| Ok(Some(val)))
                     }
-                });
-                self.task_results.insert(task_id.clone(), rx);
+                })
+            };
+            if result.is_err() {
+                if let Some(wait) = self.sync_wait.take() {
+                    self.lock_sync().abandon(wait);
+                }
             }
-            #[cfg(target_arch = "wasm32")]
-            {
-                self.task_results_sync.insert(
-                    task_id.clone(),
-                    Value::Error(Box::new(Value::str("TCP no soportado en wasm"))),
-                );
+            result
+        }
+    }
+
+    /// Toma el cerrojo `id`; `false` si la fibra se aparcó esperándolo.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn acquire(&mut self, args: &[Value], id: &str, access: Access) -> Result<bool, VmError> {
+        let taken = self.retry_or_park(args, &[id], None, |vm, _| {
+            vm.lock_sync()
+                .lock(id)
+                .is_none_or(|lock| lock.try_acquire(access))
+                .then_some(Value::Void)
+        })?;
+        Ok(taken.is_some())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn release(&self, id: &str, access: Access) {
+        if let Some(lock) = self.lock_sync().lock(id) {
+            lock.release(access);
+        }
+        wake_waiters(id);
+    }
+
+    /// Llama a `callee(args)` con el cerrojo `id` tomado y lo suelta al
+    /// volver, aunque la llamada falle. Con `Write`, el resultado pasa a ser
+    /// el valor protegido salvo que sea un error.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn with_lock(
+        &mut self,
+        args: &[Value],
+        id: &str,
+        access: Access,
+        callee: &Value,
+        fn_args: impl FnOnce(Value) -> Vec<Value>,
+    ) -> Result<(), VmError> {
+        if self.lock_sync().lock(id).is_none() {
+            self.push(Value::Error(Box::new(Value::str("Lock not found"))));
+            return Ok(());
+        }
+        if !self.acquire(args, id, access)? {
+            return Ok(());
+        }
+        let current = self
+            .lock_sync()
+            .lock(id)
+            .map_or(Value::Void, |lock| lock.value.clone());
+        let mut vm = self.child();
+        let result = vm
+            .call_value(callee, fn_args(current))
+            .unwrap_or_else(|e| Value::Error(Box::new(Value::str(e.to_string()))));
+        if let Some(lock) = self.lock_sync().lock(id) {
+            if access == Access::Write && !matches!(result, Value::Error(_)) {
+                lock.value = result.clone();
+            }
+            lock.release(access);
+        }
+        wake_waiters(id);
+        self.push(result);
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_mutex_nuevo(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        let mid = self.lock_sync().new_lock("mutex", Value::Void);
+        self.push(Value::str(mid));
+        Ok(())
+    }
+
+    /// `__mutex_bloquear(m, fn, arg)`: llama a `fn(arg)` con el mutex tomado.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_mutex_bloquear(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if self.lock_sync().lock(&mid).is_none() {
+            self.push(Value::Error(Box::new(Value::str("Mutex not found"))));
+            return Ok(());
+        }
+        let callee = args.get(1).cloned().unwrap_or(Value::Void);
+        let fn_arg = args.get(2).cloned().unwrap_or(Value::Void);
+        if !self.acquire(&args, &mid, Access::Write)? {
+            return Ok(());
+        }
+        let mut vm = self.child();
+        let result = vm.call_value(&callee, vec![fn_arg]).unwrap_or(Value::Void);
+        self.release(&mid, Access::Write);
+        self.push(result);
+        Ok(())
+    }
+
+    /// `__mutex_adquirir(m)`: toma el mutex hasta `__mutex_liberar(m)`.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_mutex_adquirir(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if self.lock_sync().lock(&mid).is_none() {
+            self.push(Value::Error(Box::new(Value::str("Mutex not found"))));
+            return Ok(());
+        }
+        if self.acquire(&args, &mid, Access::Write)? {
+            self.push(Value::Void);
+        }
+        Ok(())
+    }
+
+    /// `__mutex_intentar(m)`: toma el mutex si está libre, sin esperar.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_mutex_intentar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let taken = self
+            .lock_sync()
+            .lock(&mid)
+            .is_some_and(|lock| lock.try_acquire(Access::Write));
+        self.push(Value::Bool(taken));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_mutex_liberar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.release(&mid, Access::Write);
+        self.push(Value::Void);
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_stream_desde(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let source = args.first().cloned().unwrap_or(Value::Void);
+        self.push(source);
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_stream_mapear(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let source = args.first().cloned().unwrap_or(Value::Void);
+        let callee = args.get(1).cloned().unwrap_or(Value::Void);
+        match source {
+            Value::Array(items) => {
+                let mapped: Vec<Value> = items
+                    .iter()
+                    .map(|item| {
+                        let mut vm = self.child();
+                        vm.call_value(&callee, vec![item.clone()])
+                            .unwrap_or(Value::Void)
+                    })
+                    .collect();
+                self.push(Value::arr(mapped));
             }
-            self.push(Value::str(task_id));
-            return Some(Ok(()));
+            _ => self.push(Value::Error(Box::new(Value::str(
+                "stream_map espera una lista",
+            )))),
         }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_stream_filtrar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let source = args.first().cloned().unwrap_or(Value::Void);
+        let callee = args.get(1).cloned().unwrap_or(Value::Void);
+        match source {
+            Value::Array(items) => {
+                let filtered: Vec<Value> = items
+                    .iter()
+                    .filter(|item| {
+                        let mut vm = self.child();
+                        matches!(
+                            vm.call_value(&callee, vec![(*item).clone()]),
+                            Ok(Value::Bool(true))
+                        )
+                    })
+                    .cloned()
+                    .collect();
+                self.push(Value::arr(filtered));
+            }
+            _ => self.push(Value::Error(Box::new(Value::str(
+                "stream_filter espera una lista",
+            )))),
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_stream_colectar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let source = args.first().cloned().unwrap_or(Value::Void);
+        self.push(source);
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_par_mapear(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let source = args.first().cloned().unwrap_or(Value::arr(vec![]));
+        let callee = args.get(1).cloned().unwrap_or(Value::Void);
+        match source {
+            Value::Array(items) => {
+                #[cfg(not(target_arch = "wasm32"))]
+                let results: Vec<Value> = {
+                    let pending: Vec<_> = items
+                        .iter()
+                        .map(|item| {
+                            self.fork(String::from("par_mapear"), &callee, vec![item.clone()])
+                        })
+                        .collect();
+                    pending.iter().map(scheduler::recv).collect()
+                };
+                #[cfg(target_arch = "wasm32")]
+                let results: Vec<Value> = items
+                    .iter()
+                    .map(|item| {
+                        let mut vm = self.child();
+                        vm.call_value(&callee, vec![item.clone()])
+                            .unwrap_or(Value::Void)
+                    })
+                    .collect();
+                self.push(Value::arr(results));
+            }
+            _ => self.push(Value::Error(Box::new(Value::str(
+                "par_map espera una lista",
+            )))),
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_par_unir(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let fn1 = args.first().cloned().unwrap_or(Value::Void);
+        let a1 = args.get(1).cloned().unwrap_or(Value::Void);
+        let fn2 = args.get(2).cloned().unwrap_or(Value::Void);
+        let a2 = args.get(3).cloned().unwrap_or(Value::Void);
+        #[cfg(not(target_arch = "wasm32"))]
+        let (r1, r2) = {
+            let h1 = self.fork(String::from("par_unir"), &fn1, vec![a1]);
+            let h2 = self.fork(String::from("par_unir"), &fn2, vec![a2]);
+            (scheduler::recv(&h1), scheduler::recv(&h2))
+        };
+        #[cfg(target_arch = "wasm32")]
+        let (r1, r2) = {
+            let mut vm1 = self.child();
+            let mut vm2 = self.child();
+            let v1 = vm1.call_value(&fn1, vec![a1]).unwrap_or(Value::Void);
+            let v2 = vm2.call_value(&fn2, vec![a2]).unwrap_or(Value::Void);
+            (v1, v2)
+        };
+        self.push(Value::arr(vec![r1, r2]));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn lock_actors(&self) -> std::sync::MutexGuard<'_, HashMap<String, ActorCell>> {
+        self.actors.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Abre un buzón `actor_N` en los canales compartidos y lo registra en la
+    /// tabla de actores.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn open_mailbox(&self) -> String {
+        let (tx, rx) = std::sync::mpsc::channel::<Value>();
+        let aid = {
+            let mut channels = self.lock_channels();
+            let aid = format!("actor_{}", channels.len());
+            channels.insert(aid.clone(), (Some(tx), Some(rx)));
+            aid
+        };
+        self.lock_actors().entry(aid.clone()).or_default();
+        aid
+    }
+
+    /// `__actor_nuevo(fn?, args...)`: sin función crea solo un buzón; con
+    /// ella, además lanza una fibra que corre `fn(buzon, args...)`.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_actor_nuevo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut args = args.into_iter();
+        let callee = args.next();
+        #[cfg(target_arch = "wasm32")]
+        if callee.is_some() {
+            self.push(Value::Error(Box::new(Value::str(
+                "Los actores con función necesitan hilos (no disponibles en wasm)",
+            ))));
+            return Ok(());
+        }
+        let aid = self.open_mailbox();
+        #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+        if let Some(callee) = callee {
+            supervisor::spawn_actor(&self.kin(), &aid, &callee, args.collect(), |_| {});
+        }
+        self.push(Value::str(aid));
+        Ok(())
+    }
 
-        // ██ Concurrency builtins ██
-        if name == "__dormir" || name == "__sleep" {
-            #[allow(unused_variables)]
-            let ms = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as u64;
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                std::thread::sleep(std::time::Duration::from_millis(ms));
-            }
-            #[cfg(target_arch = "wasm32")]
-            {
-                let _ = ms;
-            }
-            self.push(Value::Void);
-            return Some(Ok(()));
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_actor_enviar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let aid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let msg = args.get(1).cloned().unwrap_or(Value::Void);
+        let tx = self
+            .lock_channels()
+            .get(&aid)
+            .and_then(|(tx, _)| tx.clone());
+        match tx {
+            Some(tx) => {
+                let sent = tx.send(msg).is_ok();
+                wake_waiters(&aid);
+                self.push(Value::Bool(sent));
+            }
+            None => self.push(Value::Error(Box::new(Value::str("Actor not found")))),
         }
+        Ok(())
+    }
 
-        if name == "__hilo_lanzar" || name == "__thread_spawn" {
-            let fn_name = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let fn_args: Vec<Value> = args.into_iter().skip(1).collect();
-            #[cfg(not(target_arch = "wasm32"))]
-            let hid = format!("thread_{}", self.thread_handles.len());
-            #[cfg(target_arch = "wasm32")]
-            let hid = {
-                let id = self.task_counter;
-                self.task_counter += 1;
-                format!("thread_{}", id)
-            };
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                let bc = self.bytecode.clone();
-                let handle = std::thread::spawn(move || {
-                    let mut vm = VM::new(bc);
-                    vm.run_function(&fn_name, fn_args).unwrap_or(Value::Void)
-                });
-                self.thread_handles.insert(hid.clone(), handle);
-            }
-            #[cfg(target_arch = "wasm32")]
-            {
-                let bc = self.bytecode.clone();
-                let mut vm = VM::new(bc);
-                let result = vm.run_function(&fn_name, fn_args).unwrap_or(Value::Void);
-                self.task_results_sync.insert(hid.clone(), result);
-            }
-            self.push(Value::str(hid));
-            return Some(Ok(()));
+    /// `__actor_recibir(buzon, ms?)`: siguiente mensaje del buzón. Con plazo
+    /// devuelve nulo si no llega ninguno a tiempo.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_actor_recibir(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let aid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if !self.lock_channels().contains_key(&aid) {
+            self.push(Value::Error(Box::new(Value::str("Actor not found"))));
+            return Ok(());
         }
+        let timeout = args
+            .get(1)
+            .and_then(|v| v.as_num())
+            .filter(|ms| *ms >= 0.0)
+            .map(|ms| std::time::Duration::from_millis(ms as u64));
+        let handles = std::slice::from_ref(&aid);
+        let Some(hit) = self.select_or_park(&args, handles, timeout)? else {
+            return Ok(());
+        };
+        self.push(hit.map_or(Value::Void, |(_, val)| val));
+        Ok(())
+    }
 
-        if name == "__hilo_esperar" || name == "__thread_join" {
-            let hid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            #[cfg(not(target_arch = "wasm32"))]
-            let found = {
-                if let Some((_, handle)) = self.thread_handles.remove_entry(&hid) {
-                    match handle.join() {
-                        Ok(val) => Some(val),
-                        Err(_) => Some(Value::Error(Box::new(Value::str("Thread panicked")))),
-                    }
-                } else {
-                    None
+    /// `__actor_monitorear(actor, buzon)`: cada vez que `actor` termine, llega
+    /// a `buzon` `["caido", actor, motivo]` o `["terminado", actor, valor]`, y
+    /// `["reiniciado", actor]` si su supervisor lo reinicia.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_actor_monitorear(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let aid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let mailbox = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let found = match self.lock_actors().get_mut(&aid) {
+            Some(cell) => {
+                if !cell.monitors.contains(&mailbox) {
+                    cell.monitors.push(mailbox);
                 }
-            };
-            #[cfg(target_arch = "wasm32")]
-            let found = self.task_results_sync.remove(&hid);
-            match found {
-                Some(val) => self.push(val),
-                None => self.push(Value::Error(Box::new(Value::str("Thread not found")))),
+                true
             }
-            return Some(Ok(()));
-        }
-
-        if name == "__canal_nuevo" || name == "__channel_new" {
-            let (tx, rx) = std::sync::mpsc::channel::<Value>();
-            let cid = format!("chan_{}", self.channels.len());
-            self.channels.insert(cid.clone(), (Some(tx), Some(rx)));
-            self.push(Value::str(cid));
-            return Some(Ok(()));
+            None => false,
+        };
+        match found {
+            true => self.push(Value::Bool(true)),
+            false => self.push(Value::Error(Box::new(Value::str("Actor not found")))),
         }
+        Ok(())
+    }
 
-        if name == "__canal_enviar" || name == "__channel_send" {
-            let cid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let val = args.get(1).cloned().unwrap_or(Value::Void);
-            if let Some((Some(ref tx), _)) = self.channels.get(&cid) {
-                match tx.send(val) {
-                    Ok(()) => self.push(Value::Bool(true)),
-                    Err(_) => self.push(Value::Bool(false)),
+    /// `__actor_enlazar(a, b)`: si uno de los dos cae, el otro se detiene.
+    /// El enlace dura lo que la encarnación actual de cada uno.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_actor_enlazar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let a = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let b = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        let linked = {
+            let mut actors = self.lock_actors();
+            if actors.contains_key(&a) && actors.contains_key(&b) && a != b {
+                for (from, to) in [(&a, &b), (&b, &a)] {
+                    if let Some(cell) = actors.get_mut(from) {
+                        if !cell.links.contains(to) {
+                            cell.links.push(to.clone());
+                        }
+                    }
                 }
+                true
             } else {
-                self.push(Value::Error(Box::new(Value::str("Channel not found"))));
+                false
             }
-            return Some(Ok(()));
+        };
+        match linked {
+            true => self.push(Value::Bool(true)),
+            false => self.push(Value::Error(Box::new(Value::str("Actor not found")))),
         }
+        Ok(())
+    }
 
-        if name == "__canal_recibir" || name == "__channel_recv" {
-            let cid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            // Need to take the receiver out temporarily
-            let rx = if let Some((_, ref mut rx_opt)) = self.channels.get_mut(&cid) {
-                rx_opt.take()
-            } else {
-                None
-            };
-            match rx {
-                Some(rx) => {
-                    let val = rx.recv().unwrap_or(Value::Void);
-                    // Put receiver back
-                    if let Some((_, ref mut rx_opt)) = self.channels.get_mut(&cid) {
-                        *rx_opt = Some(rx);
-                    }
-                    self.push(val);
-                }
-                None => {
-                    self.push(Value::Error(Box::new(Value::str("Channel not found"))));
+    /// `__actor_detener(actor)`: pide a la fibra del actor que termine.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_actor_detener(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let aid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let found = self.lock_actors().get(&aid).map(|cell| {
+            raise_flag(&cell.stop);
+        });
+        self.push(Value::Bool(found.is_some()));
+        Ok(())
+    }
+
+    /// `__actor_vivo(actor)`: si su fibra sigue en marcha. Un actor al que ya
+    /// se le pidió parar cuenta como muerto aunque aún no haya salido.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_actor_vivo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let aid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let alive = self
+            .lock_actors()
+            .get(&aid)
+            .is_some_and(|cell| cell.alive && !cell.stop.load(std::sync::atomic::Ordering::SeqCst));
+        self.push(Value::Bool(alive));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_generador_nuevo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let gid = format!("gen_{}", self.generators.len());
+        let callee = args.first().cloned().unwrap_or(Value::Void);
+        self.generators.insert(gid.clone(), callee);
+        self.push(Value::str(gid));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_generador_siguiente(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let gid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let val = args.get(1).cloned().unwrap_or(Value::Void);
+        let callee = if let Some(callee) = self.generators.get(&gid) {
+            callee.clone()
+        } else {
+            self.push(Value::Error(Box::new(Value::str("Generator not found"))));
+            return Ok(());
+        };
+        let mut vm = self.child();
+        let result = vm.call_value(&callee, vec![val]).unwrap_or(Value::Void);
+        self.push(result);
+        Ok(())
+    }
+
+    /// `__seleccionar(canales, ms)`: espera al primero de varios canales o
+    /// tareas y devuelve `[indice, valor]`, o `[-1, nulo]` si vence el plazo.
+    /// `ms = 0` es la rama `defecto` (no bloquea); sin plazo espera siempre.
+    /// Los canales pueden ir en una lista o como argumentos sueltos.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_seleccionar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut handles = Vec::new();
+        let mut timeout = None;
+        for arg in &args {
+            match arg {
+                Value::Array(items) => handles.extend(items.iter().map(|v| format!("{}", v))),
+                Value::Int(_) | Value::Float(_) => {
+                    let ms = arg.as_num().unwrap_or(-1.0);
+                    timeout = (ms >= 0.0).then(|| std::time::Duration::from_millis(ms as u64));
                 }
+                other => handles.push(format!("{}", other)),
             }
-            return Some(Ok(()));
-        }
-
-        if name == "__mutex_nuevo" || name == "__mutex_new" {
-            let mid = format!("mutex_{}", self.mutexes.len());
-            self.mutexes
-                .insert(mid.clone(), std::sync::Mutex::new(Value::Void));
-            self.push(Value::str(mid));
-            return Some(Ok(()));
-        }
-
-        if name == "__mutex_bloquear" || name == "__mutex_lock" {
-            let mid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let fn_name = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            let fn_arg = args.get(2).cloned().unwrap_or(Value::Void);
-            let result = if let Some(mutex) = self.mutexes.get(&mid) {
-                let _guard = mutex.lock().unwrap();
-                drop(_guard);
-                // Execute function while holding lock
-                let bc = self.bytecode.clone();
-                let mut vm = VM::new(bc);
-                vm.run_function(&fn_name, vec![fn_arg])
-                    .unwrap_or(Value::Void)
-            } else {
-                Value::Error(Box::new(Value::str("Mutex not found")))
-            };
-            self.push(result);
-            return Some(Ok(()));
         }
+        if handles.is_empty() {
+            timeout = Some(std::time::Duration::ZERO);
+        }
+        let Some(hit) = self.select_or_park(&args, &handles, timeout)? else {
+            return Ok(());
+        };
+        let (idx, val) = match hit {
+            Some((i, val)) => (i as i64, val),
+            None => (-1, Value::Void),
+        };
+        self.push(Value::arr(vec![Value::Int(idx), val]));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_scope_nuevo(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        let sid = self.open_scope();
+        self.push(Value::str(sid));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn open_scope(&mut self) -> String {
+        let sid = format!("scope_{}", self.task_counter);
+        self.task_counter += 1;
+        self.scopes.insert(sid.clone(), TaskScope::default());
+        sid
+    }
 
-        if name == "__stream_desde" || name == "__stream_from" {
-            let source = args.first().cloned().unwrap_or(Value::Void);
-            self.push(source);
-            return Some(Ok(()));
+    /// `__scope_lanzar(ambito, fn, args...)`: lanza una tarea hija del ámbito.
+    /// La hija ve los mismos canales y la señal de cancelación del ámbito; si
+    /// es la primera en fallar (error de ejecución o valor `error(...)`),
+    /// cancela a sus hermanas.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_scope_lanzar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut args = args.into_iter();
+        let sid = args.next().map(|v| format!("{}", v)).unwrap_or_default();
+        let callee = args.next().unwrap_or(Value::Void);
+        let fn_args: Vec<Value> = args.collect();
+        let Some(scope) = self.scopes.get(&sid) else {
+            self.push(Value::Error(Box::new(Value::str("Scope not found"))));
+            return Ok(());
+        };
+        let cancel = scope.cancel.clone();
+        let failure = scope.failure.clone();
+        if cancel.load(std::sync::atomic::Ordering::SeqCst) {
+            self.push(Value::Error(Box::new(Value::str("Ámbito cancelado"))));
+            return Ok(());
         }
+        let task_id = format!("task_{}", self.task_counter);
+        self.task_counter += 1;
+        let mut vm = self.child();
+        vm.cancel = Some(cancel.clone());
+        let finish = move |result: Result<Value, VmError>| {
+            let result =
+                result.unwrap_or_else(|e| Value::Error(Box::new(Value::str(e.to_string()))));
+            if matches!(result, Value::Error(_)) && !raise_flag(&cancel) {
+                *failure.lock().unwrap_or_else(|e| e.into_inner()) = Some(result.clone());
+            }
+            result
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let rx = VM::spawn_fiber(vm, task_id.clone(), &callee, fn_args, finish);
+            self.task_results.insert(task_id.clone(), rx);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            let result = vm.call_value(&callee, fn_args);
+            self.task_results_sync
+                .insert(task_id.clone(), finish(result));
+        }
+        if let Some(scope) = self.scopes.get_mut(&sid) {
+            scope.children.push(task_id.clone());
+        }
+        self.push(Value::str(task_id));
+        Ok(())
+    }
 
-        if name == "__stream_mapear" || name == "__stream_map" {
-            let source = args.first().cloned().unwrap_or(Value::Void);
-            let fn_name = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            match source {
-                Value::Array(items) => {
-                    let bc = self.bytecode.clone();
-                    let mapped: Vec<Value> = items
-                        .iter()
-                        .map(|item| {
-                            let mut vm = VM::new(bc.clone());
-                            vm.run_function(&fn_name, vec![item.clone()])
-                                .unwrap_or(Value::Void)
-                        })
-                        .collect();
-                    self.push(Value::arr(mapped));
-                }
-                _ => self.push(Value::Error(Box::new(Value::str(
-                    "stream_map espera una lista",
-                )))),
+    /// `__scope_esperar(ambito)`: espera a todas las hijas y cierra el ámbito.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_scope_esperar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let sid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.parkable() {
+            let epoch = scheduler::epoch();
+            let children = self.scopes.get(&sid).map(|s| s.children.clone());
+            let pending: Vec<String> = children
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|c| self.task_results.contains_key(c) && !self.task_ready(c))
+                .collect();
+            if !pending.is_empty() {
+                let park = scheduler::Park {
+                    waits: pending,
+                    until: None,
+                    epoch,
+                };
+                return self.park(args, park);
             }
-            return Some(Ok(()));
         }
+        let result = self.join_scope(&sid);
+        self.push(result);
+        Ok(())
+    }
 
-        if name == "__stream_filtrar" || name == "__stream_filter" {
-            let source = args.first().cloned().unwrap_or(Value::Void);
-            let fn_name = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            match source {
-                Value::Array(items) => {
-                    let bc = self.bytecode.clone();
-                    let filtered: Vec<Value> = items
-                        .iter()
-                        .filter(|item| {
-                            let mut vm = VM::new(bc.clone());
-                            matches!(
-                                vm.run_function(&fn_name, vec![(*item).clone()]),
-                                Ok(Value::Bool(true))
-                            )
-                        })
-                        .cloned()
-                        .collect();
-                    self.push(Value::arr(filtered));
-                }
-                _ => self.push(Value::Error(Box::new(Value::str(
-                    "stream_filter espera una lista",
-                )))),
-            }
-            return Some(Ok(()));
-        }
-
-        if name == "__stream_colectar" || name == "__stream_collect" {
-            let source = args.first().cloned().unwrap_or(Value::Void);
-            self.push(source);
-            return Some(Ok(()));
-        }
-
-        if name == "__par_mapear" || name == "__par_map" {
-            let source = args.first().cloned().unwrap_or(Value::arr(vec![]));
-            let fn_name = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-            match source {
-                Value::Array(items) => {
-                    #[cfg(not(target_arch = "wasm32"))]
-                    let results: Vec<Value> = {
-                        let bc = self.bytecode.clone();
-                        let mut handles = Vec::new();
-                        for item in items.iter() {
-                            let bc_clone = bc.clone();
-                            let fn_clone = fn_name.clone();
-                            let item_clone = (*item).clone();
-                            handles.push(std::thread::spawn(move || {
-                                let mut vm = VM::new(bc_clone);
-                                vm.run_function(&fn_clone, vec![item_clone])
-                                    .unwrap_or(Value::Void)
-                            }));
-                        }
-                        handles
-                            .into_iter()
-                            .map(|h| h.join().unwrap_or(Value::Void))
-                            .collect()
-                    };
-                    #[cfg(target_arch = "wasm32")]
-                    let results: Vec<Value> = {
-                        let bc = self.bytecode.clone();
-                        items
-                            .iter()
-                            .map(|item| {
-                                let mut vm = VM::new(bc.clone());
-                                vm.run_function(&fn_name, vec![item.clone()])
-                                    .unwrap_or(Value::Void)
-                            })
-                            .collect()
-                    };
-                    self.push(Value::arr(results));
-                }
-                _ => self.push(Value::Error(Box::new(Value::str(
-                    "par_map espera una lista",
-                )))),
-            }
-            return Some(Ok(()));
+    /// Espera a todas las hijas de un ámbito y lo cierra. Devuelve la lista de
+    /// resultados en orden de lanzamiento o, si alguna falló, el primer fallo.
+    /// Las hijas ya recogidas con `__tarea_esperar` o `__seleccionar` no
+    /// aparecen en la lista.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn join_scope(&mut self, sid: &str) -> Value {
+        let Some(scope) = self.scopes.remove(sid) else {
+            return Value::Error(Box::new(Value::str("Scope not found")));
+        };
+        let mut results = Vec::with_capacity(scope.children.len());
+        for child in &scope.children {
+            results.extend(self.take_task(child));
+        }
+        let failure = scope
+            .failure
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        failure.unwrap_or_else(|| Value::arr(results))
+    }
+
+    /// `__scope_cancelar(ambito)`: pide a todas las hijas que se detengan. El
+    /// ámbito sigue abierto hasta `__scope_esperar`.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_scope_cancelar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let sid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(scope) = self.scopes.get(&sid) {
+            raise_flag(&scope.cancel);
         }
+        self.push(Value::Void);
+        Ok(())
+    }
 
-        if name == "__par_unir" || name == "__par_join" {
-            let fn1 = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let a1 = args.get(1).cloned().unwrap_or(Value::Void);
-            let fn2 = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
-            let a2 = args.get(3).cloned().unwrap_or(Value::Void);
-            #[cfg(not(target_arch = "wasm32"))]
-            let (r1, r2) = {
-                let bc = self.bytecode.clone();
-                let bc2 = bc.clone();
-                let h1 = std::thread::spawn(move || {
-                    let mut vm = VM::new(bc);
-                    if fn1.is_empty() {
-                        Value::Void
-                    } else {
-                        vm.run_function(&fn1, vec![a1]).unwrap_or(Value::Void)
-                    }
-                });
-                let h2 = std::thread::spawn(move || {
-                    let mut vm = VM::new(bc2);
-                    if fn2.is_empty() {
-                        Value::Void
-                    } else {
-                        vm.run_function(&fn2, vec![a2]).unwrap_or(Value::Void)
-                    }
-                });
-                (
-                    h1.join().unwrap_or(Value::Void),
-                    h2.join().unwrap_or(Value::Void),
-                )
+    /// `__scope_ejecutar(fn, args...)`: abre un ámbito, llama a
+    /// `fn(ambito, args...)` en esta VM y al salir espera a todas las hijas.
+    /// Si el cuerpo falla, cancela las hijas, las espera y propaga el error.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_scope_ejecutar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut args = args.into_iter();
+        let body = args.next().unwrap_or(Value::Void);
+        let sid = self.open_scope();
+        let body_args = std::iter::once(Value::str(sid.clone()))
+            .chain(args)
+            .collect();
+        if let Err(e) = self.call_value(&body, body_args) {
+            if let Some(scope) = self.scopes.get(&sid) {
+                raise_flag(&scope.cancel);
+            }
+            self.join_scope(&sid);
+            return Err(e);
+        }
+        let result = self.join_scope(&sid);
+        self.push(result);
+        Ok(())
+    }
+    /// Supervisor de `args[0]`, o el error a devolver si no existe.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    fn supervisor_arg(&self, args: &[Value]) -> Result<supervisor::SharedSupervisor, Value> {
+        let sid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        self.supervisors
+            .get(&sid)
+            .cloned()
+            .ok_or_else(|| Value::Error(Box::new(Value::str("Supervisor not found"))))
+    }
+
+    /// `__supervisor_nuevo(estrategia, max_reinicios, periodo_ms)`: estrategia
+    /// `uno_para_uno` (por defecto), `uno_para_todos` o `resto_para_uno`. Si
+    /// hay más de `max_reinicios` (3) en `periodo_ms` (5000), el supervisor se
+    /// rinde y detiene a todos sus hijos.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_supervisor_nuevo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = args;
+            self.push(Value::Error(Box::new(Value::str(
+                "Los supervisores necesitan hilos (no disponibles en wasm)",
+            ))));
+        }
+        #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+        {
+            let name = match args.first() {
+                Some(Value::Str(name)) => name.to_string(),
+                _ => "uno_para_uno".to_string(),
             };
-            #[cfg(target_arch = "wasm32")]
-            let (r1, r2) = {
-                let bc = self.bytecode.clone();
-                let mut vm1 = VM::new(bc.clone());
-                let mut vm2 = VM::new(bc);
-                let v1 = if fn1.is_empty() {
-                    Value::Void
-                } else {
-                    vm1.run_function(&fn1, vec![a1]).unwrap_or(Value::Void)
-                };
-                let v2 = if fn2.is_empty() {
-                    Value::Void
-                } else {
-                    vm2.run_function(&fn2, vec![a2]).unwrap_or(Value::Void)
-                };
-                (v1, v2)
+            let Some(strategy) = supervisor::Strategy::parse(&name) else {
+                self.push(Value::Error(Box::new(Value::str(format!(
+                    "Estrategia de supervisión desconocida: '{}'",
+                    name
+                )))));
+                return Ok(());
+            };
+            let max_restarts = args.get(1).and_then(|v| v.as_num()).unwrap_or(3.0).max(0.0);
+            let period = args
+                .get(2)
+                .and_then(|v| v.as_num())
+                .unwrap_or(5000.0)
+                .max(0.0);
+            let sid = {
+                let mut actors = self.lock_actors();
+                let sid = format!("sup_{}", actors.len());
+                actors.insert(sid.clone(), ActorCell::default());
+                sid
             };
-            self.push(Value::arr(vec![r1, r2]));
-            return Some(Ok(()));
+            let sup = supervisor::Supervisor::new(
+                sid.clone(),
+                self.kin(),
+                strategy,
+                max_restarts as usize,
+                std::time::Duration::from_millis(period as u64),
+            );
+            self.supervisors.insert(sid.clone(), sup);
+            self.push(Value::str(sid));
         }
+        Ok(())
+    }
 
-        if name == "__actor_nuevo" || name == "__actor_new" {
-            let aid = format!("actor_{}", self.actors.len());
-            // Actor is just a mailbox: a channel
-            let (tx, rx) = std::sync::mpsc::channel::<Value>();
-            self.actors.insert(aid.clone(), (Some(tx), Some(rx)));
+    /// `__supervisor_agregar(sup, fn, args...)` o `__supervisor_agregar(sup,
+    /// spec)` con `spec = {"funcion": ..., "args": [...], "reinicio": ...}`.
+    /// El hijo corre `fn(buzon, args...)`; devuelve su buzón, que se conserva
+    /// entre reinicios. `reinicio`: `permanente`, `transitorio` (por
+    /// defecto: solo si cae) o `temporal`.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_supervisor_agregar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = args;
+            self.push(Value::Error(Box::new(Value::str("Supervisor not found"))));
+        }
+        #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+        {
+            let sup = match self.supervisor_arg(&args) {
+                Ok(sup) => sup,
+                Err(err) => {
+                    self.push(err);
+                    return Ok(());
+                }
+            };
+            let spec = match self.child_spec(args.into_iter().skip(1).collect()) {
+                Ok(spec) => spec,
+                Err(msg) => {
+                    self.push(Value::Error(Box::new(Value::str(msg))));
+                    return Ok(());
+                }
+            };
+            let aid = self.open_mailbox();
+            supervisor::add_child(&sup, aid.clone(), spec);
             self.push(Value::str(aid));
-            return Some(Ok(()));
         }
+        Ok(())
+    }
 
-        if name == "__actor_enviar" || name == "__actor_send" {
-            let aid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let msg = args.get(1).cloned().unwrap_or(Value::Void);
-            if let Some((Some(ref tx), _)) = self.actors.get(&aid) {
-                match tx.send(msg) {
-                    Ok(()) => self.push(Value::Bool(true)),
-                    Err(_) => self.push(Value::Bool(false)),
+    /// Lee la especificación de un hijo y comprueba que su función existe.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    fn child_spec(&self, args: Vec<Value>) -> Result<supervisor::ChildSpec, String> {
+        let mut args = args.into_iter();
+        let first = args.next().unwrap_or(Value::Void);
+        let spec = match first {
+            Value::Map(map) => {
+                let field = |es: &str, en: &str| {
+                    map.get(&Value::str(es))
+                        .or_else(|| map.get(&Value::str(en)))
+                        .cloned()
+                };
+                let restart = match field("reinicio", "restart") {
+                    Some(name) => {
+                        let name = format!("{}", name);
+                        supervisor::Restart::parse(&name).ok_or_else(|| {
+                            format!("Política de reinicio desconocida: '{}'", name)
+                        })?
+                    }
+                    None => supervisor::Restart::Transient,
+                };
+                let args = match field("args", "args") {
+                    Some(Value::Array(items)) => items.to_vec(),
+                    Some(other) => vec![other],
+                    None => Vec::new(),
+                };
+                supervisor::ChildSpec {
+                    callee: field("funcion", "function").unwrap_or(Value::Void),
+                    args,
+                    restart,
                 }
-            } else {
-                self.push(Value::Error(Box::new(Value::str("Actor not found"))));
             }
-            return Some(Ok(()));
-        }
+            callee => supervisor::ChildSpec {
+                callee,
+                args: args.collect(),
+                restart: supervisor::Restart::Transient,
+            },
+        };
+        self.callee_frame(&spec.callee, Vec::new())
+            .map_err(|e| e.to_string())?;
+        Ok(spec)
+    }
 
-        if name == "__actor_recibir" || name == "__actor_recv" {
-            let aid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let rx = if let Some((_, ref mut rx_opt)) = self.actors.get_mut(&aid) {
-                rx_opt.take()
-            } else {
-                None
-            };
-            match rx {
-                Some(rx) => {
-                    let val = rx.recv().unwrap_or(Value::Void);
-                    if let Some((_, ref mut rx_opt)) = self.actors.get_mut(&aid) {
-                        *rx_opt = Some(rx);
-                    }
-                    self.push(val);
-                }
-                None => {
-                    self.push(Value::Error(Box::new(Value::str("Actor not found"))));
-                }
+    /// `__supervisor_iniciar(sup)`: lanza los hijos; devuelve sus buzones.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_supervisor_iniciar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let _ = args;
+            self.push(Value::Error(Box::new(Value::str("Supervisor not found"))));
+        }
+        #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+        match self.supervisor_arg(&args) {
+            Ok(sup) => {
+                supervisor::start(&sup);
+                let ids = sup.lock().unwrap_or_else(|e| e.into_inner()).child_ids();
+                self.push(Value::arr(ids.into_iter().map(Value::str).collect()));
             }
-            return Some(Ok(()));
+            Err(err) => self.push(err),
         }
+        Ok(())
+    }
 
-        if name == "__generador_nuevo" || name == "__generator_new" {
-            let gid = format!("gen_{}", self.generators.len());
-            let fn_name = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            self.generators.insert(gid.clone(), fn_name);
-            self.push(Value::str(gid));
-            return Some(Ok(()));
-        }
+    /// `__supervisor_detener(sup)`: detiene a todos los hijos sin reiniciarlos.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_supervisor_detener(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[cfg(target_arch = "wasm32")]
+        let _ = args;
+        #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+        if let Ok(sup) = self.supervisor_arg(&args) {
+            supervisor::stop(&sup);
+        }
+        self.push(Value::Void);
+        Ok(())
+    }
 
-        if name == "__generador_siguiente" || name == "__generator_next" {
-            let gid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-            let val = args.get(1).cloned().unwrap_or(Value::Void);
-            let fn_name = if let Some(fn_name) = self.generators.get(&gid) {
-                fn_name.clone()
-            } else {
-                self.push(Value::Error(Box::new(Value::str("Generator not found"))));
-                return Some(Ok(()));
-            };
-            let mut vm = VM::new(self.bytecode.clone());
-            let result = vm.run_function(&fn_name, vec![val]).unwrap_or(Value::Void);
-            self.push(result);
-            return Some(Ok(()));
-        }
+    /// `__supervisor_reinicios(sup)`: reinicios hechos hasta ahora.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_supervisor_reinicios(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[cfg(target_arch = "wasm32")]
+        let restarts = {
+            let _ = args;
+            0
+        };
+        #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+        let restarts = self.supervisor_arg(&args).map_or(0, |sup| {
+            sup.lock().unwrap_or_else(|e| e.into_inner()).restarts()
+        });
+        self.push(Value::Int(restarts as i64));
+        Ok(())
+    }
 
-        // Stubs for remaining concurrency builtins
-        if name == "__seleccionar" || name == "__select" {
-            // Return first available; stub returns "select_stub"
-            self.push(Value::str("select_stub"));
-            return Some(Ok(()));
-        }
-        if name == "__scope_nuevo" || name == "__scope_new" {
-            self.push(Value::str("scope_0"));
-            return Some(Ok(()));
-        }
-        if name == "__scope_lanzar" || name == "__scope_spawn" {
-            self.push(Value::str("scope_task_0"));
-            return Some(Ok(()));
-        }
-        if name == "__scope_cancelar" || name == "__scope_cancel" {
-            self.push(Value::Void);
-            return Some(Ok(()));
-        }
-        if name == "__supervisor_nuevo" || name == "__supervisor_new" {
-            self.push(Value::str("sup_0"));
-            return Some(Ok(()));
+    /// `__supervisor_activo(sup)`: falso si no se ha iniciado, se detuvo o se
+    /// rindió por exceso de reinicios.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_supervisor_activo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        #[cfg(target_arch = "wasm32")]
+        let running = {
+            let _ = args;
+            false
+        };
+        #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+        let running = self
+            .supervisor_arg(&args)
+            .is_ok_and(|sup| sup.lock().unwrap_or_else(|e| e.into_inner()).is_running());
+        self.push(Value::Bool(running));
+        Ok(())
+    }
+    /// Apila el resultado de una operación de cluster, o su fallo como
+    /// `error(...)`.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn push_cluster(&mut self, result: Result<Value, String>) -> Result<(), VmError> {
+        self.push(result.unwrap_or_else(|e| Value::Error(Box::new(Value::str(e)))));
+        Ok(())
+    }
+
+    /// `__cluster_nodo(nombre, direccion = "127.0.0.1:0", latido_ms = 1000)`:
+    /// levanta un nodo cuyos buzones son los canales de esta VM.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_cluster_nodo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let name = args.first().map(|v| v.to_string()).unwrap_or_default();
+        let addr = args
+            .get(1)
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "127.0.0.1:0".to_string());
+        let heartbeat = args.get(2).and_then(|v| v.as_num()).unwrap_or(1000.0);
+        let result = cluster::start_node(
+            self.channels.clone(),
+            &name,
+            &addr,
+            std::time::Duration::from_millis(heartbeat.max(0.0) as u64),
+        );
+        self.push_cluster(result.map(Value::str))
+    }
+
+    /// `__cluster_direccion(nodo)`: `ip:puerto` en que escucha.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_cluster_direccion(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let node = args.first().map(|v| v.to_string()).unwrap_or_default();
+        self.push_cluster(cluster::local_addr(&node).map(Value::str))
+    }
+
+    /// `__cluster_conectar(nodo, direccion)`: nombre del nodo conectado.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_cluster_conectar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let node = args.first().map(|v| v.to_string()).unwrap_or_default();
+        let addr = args.get(1).map(|v| v.to_string()).unwrap_or_default();
+        self.push_cluster(cluster::connect(&node, &addr).map(Value::str))
+    }
+
+    /// `__cluster_registrar(nodo, nombre, actor)`: publica el buzón `actor`
+    /// como `nombre@nodo`; falso si el nombre ya estaba tomado.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_cluster_registrar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let node = args.first().map(|v| v.to_string()).unwrap_or_default();
+        let name = args.get(1).map(|v| v.to_string()).unwrap_or_default();
+        let actor = args.get(2).map(|v| v.to_string()).unwrap_or_default();
+        self.push_cluster(cluster::register(&node, &name, &actor).map(Value::Bool))
+    }
+
+    /// `__cluster_enviar(nodo, "actor@otro", msg)`.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_cluster_enviar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let mut args = args.into_iter();
+        let node = args.next().map(|v| v.to_string()).unwrap_or_default();
+        let dest = args.next().map(|v| v.to_string()).unwrap_or_default();
+        let msg = args.next().unwrap_or(Value::Void);
+        self.push_cluster(cluster::send(&node, &dest, msg).map(Value::Bool))
+    }
+
+    /// `__cluster_monitorear(nodo, buzon)`: avisa de conexiones y caídas.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_cluster_monitorear(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let node = args.first().map(|v| v.to_string()).unwrap_or_default();
+        let mailbox = args.get(1).map(|v| v.to_string()).unwrap_or_default();
+        self.push_cluster(cluster::monitor(&node, &mailbox).map(|()| Value::Void))
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_cluster_nodos(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let node = args.first().map(|v| v.to_string()).unwrap_or_default();
+        let peers = cluster::peers(&node)
+            .map(|peers| Value::arr(peers.into_iter().map(Value::str).collect()));
+        self.push_cluster(peers)
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_cluster_detener(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let node = args.first().map(|v| v.to_string()).unwrap_or_default();
+        self.push(Value::Bool(cluster::stop(&node)));
+        Ok(())
+    }
+
+    /// `__rwlock_nuevo(valor)`: cerrojo lector/escritor que protege `valor`.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_rwlock_nuevo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let value = args.into_iter().next().unwrap_or(Value::Void);
+        let rid = self.lock_sync().new_lock("rwlock", value);
+        self.push(Value::str(rid));
+        Ok(())
+    }
+
+    /// `__rwlock_leer(rw, fn?, args...)`: con `fn`, devuelve `fn(valor, args...)`
+    /// con el cerrojo tomado para leer (otras lecturas siguen a la vez); sin
+    /// ella, el valor actual.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_rwlock_leer(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let rid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let Some(callee) = args.get(1).cloned() else {
+            let value = self.lock_sync().lock(&rid).map(|lock| lock.value.clone());
+            self.push(
+                value.unwrap_or_else(|| Value::Error(Box::new(Value::str("Lock not found")))),
+            );
+            return Ok(());
+        };
+        let rest = args[2..].to_vec();
+        self.with_lock(&args, &rid, Access::Read, &callee, |value| {
+            std::iter::once(value).chain(rest).collect()
+        })
+    }
+
+    /// `__rwlock_escribir(rw, fn, args...)`: guarda `fn(valor, args...)` como
+    /// valor nuevo, en exclusiva, y lo devuelve.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_rwlock_escribir(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let rid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let callee = args.get(1).cloned().unwrap_or(Value::Void);
+        let rest = args.get(2..).map(<[Value]>::to_vec).unwrap_or_default();
+        self.with_lock(&args, &rid, Access::Write, &callee, |value| {
+            std::iter::once(value).chain(rest).collect()
+        })
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn atomic_arg(&mut self, args: &[Value]) -> Option<Arc<std::sync::atomic::AtomicI64>> {
+        let aid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let cell = self.lock_sync().atomic(&aid);
+        if cell.is_none() {
+            self.push(Value::Error(Box::new(Value::str("Atomic not found"))));
         }
-        if name == "__supervisor_agregar" || name == "__supervisor_add" {
-            self.push(Value::Void);
-            return Some(Ok(()));
+        cell
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_atomico_nuevo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let n = args.first().and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+        let aid = self.lock_sync().new_atomic(n);
+        self.push(Value::str(aid));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_atomico_cargar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        if let Some(cell) = self.atomic_arg(&args) {
+            self.push(Value::Int(cell.load(std::sync::atomic::Ordering::SeqCst)));
         }
-        if name == "__supervisor_iniciar" || name == "__supervisor_start" {
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_atomico_guardar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        if let Some(cell) = self.atomic_arg(&args) {
+            let n = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+            cell.store(n, std::sync::atomic::Ordering::SeqCst);
             self.push(Value::Void);
-            return Some(Ok(()));
         }
-        if name == "__cluster_conectar" || name == "__cluster_connect" {
-            self.push(Value::str("cluster_0"));
-            return Some(Ok(()));
+        Ok(())
+    }
+
+    /// `__atomico_sumar(a, n = 1)`: suma `n` y devuelve el valor anterior.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_atomico_sumar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        if let Some(cell) = self.atomic_arg(&args) {
+            let n = args.get(1).and_then(|v| v.as_num()).unwrap_or(1.0) as i64;
+            let prev = cell.fetch_add(n, std::sync::atomic::Ordering::SeqCst);
+            self.push(Value::Int(prev));
         }
-        if name == "__cluster_enviar" || name == "__cluster_send" {
-            self.push(Value::Bool(false));
-            return Some(Ok(()));
+        Ok(())
+    }
+
+    /// `__atomico_intercambiar(a, n)`: guarda `n` y devuelve el valor anterior.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_atomico_intercambiar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        if let Some(cell) = self.atomic_arg(&args) {
+            let n = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+            let prev = cell.swap(n, std::sync::atomic::Ordering::SeqCst);
+            self.push(Value::Int(prev));
         }
-        if name == "__rwlock_nuevo" || name == "__rwlock_new" {
-            self.push(Value::str("rwlock_0"));
-            return Some(Ok(()));
+        Ok(())
+    }
+
+    /// `__atomico_comparar_intercambiar(a, esperado, nuevo)`: guarda `nuevo`
+    /// solo si el valor es `esperado`; devuelve si lo hizo.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_atomico_comparar_intercambiar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        if let Some(cell) = self.atomic_arg(&args) {
+            let expected = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+            let new = args.get(2).and_then(|v| v.as_num()).unwrap_or(0.0) as i64;
+            self.push(Value::Bool(sync::compare_exchange(&cell, expected, new)));
         }
-        if name == "__rwlock_leer" || name == "__rwlock_read" {
-            self.push(Value::Void);
-            return Some(Ok(()));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_condvar_nuevo(&mut self, _args: Vec<Value>) -> Result<(), VmError> {
+        let cid = self.lock_sync().new_condvar();
+        self.push(Value::str(cid));
+        Ok(())
+    }
+
+    /// `__condvar_esperar(cv, m, ms?)`: suelta el mutex `m` (tomado con
+    /// `__mutex_adquirir`), espera un aviso y lo vuelve a tomar. Devuelve
+    /// falso si venció el plazo antes del aviso.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_condvar_esperar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let cid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let mid = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
+        {
+            let mut table = self.lock_sync();
+            if table.condvar(&cid).is_none() || table.lock(&mid).is_none() {
+                drop(table);
+                self.push(Value::Error(Box::new(Value::str(
+                    "Condvar or mutex not found",
+                ))));
+                return Ok(());
+            }
+        }
+        let timeout = args
+            .get(2)
+            .and_then(|v| v.as_num())
+            .map(|ms| std::time::Duration::from_millis(ms.max(0.0) as u64));
+        let notified = self.retry_or_park(&args, &[&cid, &mid], timeout, |vm, expired| {
+            let mut wait = vm.sync_wait.take();
+            let first = wait.is_none();
+            let done = vm.lock_sync().condvar_wait(&mut wait, &cid, &mid, expired);
+            vm.sync_wait = wait;
+            // El primer intento suelta el mutex.
+            if first {
+                wake_waiters(&mid);
+            }
+            done
+        })?;
+        if let Some(notified) = notified {
+            self.push(notified);
         }
-        if name == "__rwlock_escribir" || name == "__rwlock_write" {
-            self.push(Value::Void);
-            return Some(Ok(()));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_condvar_notificar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let cid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(cv) = self.lock_sync().condvar(&cid) {
+            cv.notify_one();
         }
-        if name == "__arc_nuevo" || name == "__arc_new" {
-            self.push(Value::str("arc_0"));
-            return Some(Ok(()));
+        wake_waiters(&cid);
+        self.push(Value::Void);
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_condvar_notificar_todos(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let cid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(cv) = self.lock_sync().condvar(&cid) {
+            cv.notify_all();
         }
-        if name == "__arc_obtener" || name == "__arc_get" {
-            self.push(args.get(1).cloned().unwrap_or(Value::Void));
-            return Some(Ok(()));
+        wake_waiters(&cid);
+        self.push(Value::Void);
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_barrera_nueva(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let parties = args
+            .first()
+            .and_then(|v| v.as_num())
+            .unwrap_or(1.0)
+            .max(1.0) as usize;
+        let bid = self.lock_sync().new_barrier(parties);
+        self.push(Value::str(bid));
+        Ok(())
+    }
+
+    /// `__barrera_esperar(b)`: espera a que lleguen todos; devuelve verdadero
+    /// solo al último en llegar.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_barrera_esperar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let bid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if !self.lock_sync().is_barrier(&bid) {
+            self.push(Value::Error(Box::new(Value::str("Barrier not found"))));
+            return Ok(());
         }
-        if name == "__arc_asignar" || name == "__arc_set" {
-            self.push(Value::Void);
-            return Some(Ok(()));
+        let leader = self.retry_or_park(&args, &[&bid], None, |vm, _| {
+            let mut wait = vm.sync_wait.take();
+            let done = vm.lock_sync().barrier_wait(&mut wait, &bid);
+            vm.sync_wait = wait;
+            // El último en llegar abre la barrera para los demás.
+            if matches!(done, Some(Value::Bool(true))) {
+                wake_waiters(&bid);
+            }
+            done
+        })?;
+        if let Some(leader) = leader {
+            self.push(leader);
         }
+        Ok(())
+    }
 
-        None
+    /// `__arc_nuevo(valor)`: celda compartida con las tareas e hilos.
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_arc_nuevo(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let value = args.into_iter().next().unwrap_or(Value::Void);
+        let aid = self.lock_sync().new_cell(value);
+        self.push(Value::str(aid));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_arc_obtener(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let aid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        let value = self.lock_sync().cell(&aid).map(|v| v.clone());
+        self.push(value.unwrap_or_else(|| Value::Error(Box::new(Value::str("Arc not found")))));
+        Ok(())
     }
+
+    #[cfg(any(feature = "extra", feature = "full"))]
+    fn builtin_arc_asignar(&mut self, args: Vec<Value>) -> Result<(), VmError> {
+        let aid = args.first().map(|v| format!("{}", v)).unwrap_or_default();
+        if let Some(cell) = self.lock_sync().cell(&aid) {
+            *cell = args.get(1).cloned().unwrap_or(Value::Void);
+        }
+        self.push(Value::Void);
+        Ok(())
+    }
+
     pub fn run(&mut self) -> Result<(), VmError> {
         let profile = !std::env::var("LUMEN_PROFILE")
             .unwrap_or_default()
@@ -3289,21 +5786,65 @@ impl VM {
         // en wasm `profile` es siempre false (env::var falla) — se evita.
         #[cfg(not(target_arch = "wasm32"))]
         let prof_start = std::time::Instant::now();
+        let mut resumed = self.paused_at.take();
         loop {
             if self.ip >= self.bytecode.instructions.len() {
                 break;
             }
+            #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+            if let Some(end) = self.slice_end {
+                if self.parked.is_some() {
+                    return Ok(());
+                }
+                if self.instr_count >= end {
+                    self.parked = Some(scheduler::Park::default());
+                    return Ok(());
+                }
+            }
+            if !self.breakpoints.is_empty()
+                && self.breakpoints.contains(&self.ip)
+                && resumed.take() != Some(self.ip)
+            {
+                self.paused_at = Some(self.ip);
+                return Ok(());
+            }
             let cur_ip = self.ip;
             self.ip += 1;
             self.instr_count += 1;
-            match self.bytecode.instructions[cur_ip] {
-                Instruction::Simple(op) => self.execute_simple(op)?,
-                Instruction::WithIdx(op, idx) => self.execute_with_idx(op, idx)?,
-                Instruction::WithNum(op, n) => self.execute_with_num(op, n)?,
-                Instruction::WithBool(op, b) => self.execute_with_bool(op, b)?,
+            if let Some(end) = self.budget.fuel_end {
+                if self.instr_count > end {
+                    self.record_fault(cur_ip);
+                    let max = self.budget.limits.max_instructions.unwrap_or_default();
+                    return Err(VmError::Sandbox(SandboxViolation::Instructions(max)));
+                }
+            }
+            if self.instr_count >= self.budget.next_watch {
+                if let Err(e) = self.watch() {
+                    self.record_fault(cur_ip);
+                    return Err(e);
+                }
+            }
+            #[cfg(any(feature = "extra", feature = "full"))]
+            if self.cancel.is_some() && self.instr_count.is_multiple_of(256) {
+                if let Err(e) = self.check_cancelled() {
+                    self.record_fault(cur_ip);
+                    return Err(e);
+                }
+            }
+            let result = match self.bytecode.instructions[cur_ip] {
+                Instruction::Simple(op) => self.execute_simple(op),
+                Instruction::WithIdx(op, idx) => self.execute_with_idx(op, idx),
+                Instruction::WithNum(op, n) => self.execute_with_num(op, n),
+                Instruction::WithBool(op, b) => self.execute_with_bool(op, b),
                 Instruction::WithStr(op, ref s) => {
                     let s_clone = s.clone();
-                    self.execute_with_str(op, &s_clone)?;
+                    self.execute_with_str(op, &s_clone)
+                }
+            };
+            if let Err(e) = result {
+                if let Err(e) = self.unwind_to_handler(e) {
+                    self.record_fault(cur_ip);
+                    return Err(e);
                 }
             }
         }
@@ -3333,102 +5874,703 @@ impl VM {
     }
 
     pub fn set_breakpoint(&mut self, ip: usize) {
-        self.breakpoints.push(ip);
+        if !self.breakpoints.contains(&ip) {
+            self.breakpoints.push(ip);
+        }
     }
-    pub fn step(&mut self) -> Result<(), VmError> {
-        self.step_mode = true;
-        self.debug = true;
-        if self.ip >= self.bytecode.instructions.len() {
-            return Ok(());
+
+    /// Pone un breakpoint en cada sentencia que empieza en la línea `line`
+    /// del programa principal. Devuelve `false` si en esa línea no empieza
+    /// ninguna.
+    pub fn set_line_breakpoint(&mut self, line: u32) -> bool {
+        self.set_file_line_breakpoint(0, line)
+    }
+
+    pub fn clear_line_breakpoint(&mut self, line: u32) {
+        self.clear_file_line_breakpoint(0, line);
+    }
+
+    /// Como `set_line_breakpoint`, en el archivo `file` de la tabla de
+    /// archivos del chunk (ver `source_file_index`).
+    pub fn set_file_line_breakpoint(&mut self, file: u32, line: u32) -> bool {
+        let ips = self.bytecode.lines.ips_for_line(file, line);
+        for &ip in &ips {
+            self.set_breakpoint(ip);
+        }
+        !ips.is_empty()
+    }
+
+    pub fn clear_file_line_breakpoint(&mut self, file: u32, line: u32) {
+        let ips = self.bytecode.lines.ips_for_line(file, line);
+        self.breakpoints.retain(|ip| !ips.contains(ip));
+    }
+
+    /// Índice de `path` en la tabla de archivos del chunk. Las rutas de la
+    /// tabla son canónicas, así que `path` se compara también canonicalizado.
+    pub fn source_file_index(&self, path: &Path) -> Option<u32> {
+        let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.bytecode
+            .lines
+            .files()
+            .iter()
+            .position(|f| Path::new(f) == canonical || Path::new(f) == path)
+            .map(|i| i as u32)
+    }
+
+    /// Ruta del archivo `file` tal como la guarda el chunk.
+    pub fn source_file(&self, file: u32) -> Option<&str> {
+        self.bytecode
+            .lines
+            .files()
+            .get(file as usize)
+            .map(String::as_str)
+    }
+
+    /// Archivo (índice en la tabla de archivos) de la instrucción `ip`.
+    pub fn file_of(&self, ip: usize) -> Option<u32> {
+        self.bytecode.lines.entry_at(ip).map(|e| e.file)
+    }
+
+    /// Archivo de la próxima instrucción a ejecutar.
+    pub fn current_file(&self) -> Option<u32> {
+        self.file_of(self.ip)
+    }
+
+    /// Si la ejecución terminó (por el final del código o por `Halt`).
+    pub fn is_finished(&self) -> bool {
+        self.ip >= self.bytecode.instructions.len()
+    }
+
+    /// Si `run` o un paso se detuvo en un breakpoint.
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Archivo del programa que se nombra en los errores.
+    pub fn set_source_name(&mut self, name: &str) {
+        self.source_name = Some(name.to_string());
+    }
+
+    /// Posición de fuente (línea, columna) de la instrucción `ip`.
+    pub fn position_of(&self, ip: usize) -> Option<(u32, u32)> {
+        self.bytecode.lines.lookup(ip)
+    }
+
+    /// Posición de la próxima instrucción a ejecutar.
+    pub fn current_position(&self) -> Option<(u32, u32)> {
+        self.position_of(self.ip)
+    }
+
+    /// Anota dónde se produjo un error no capturado y qué funciones estaban
+    /// activas. Si el error viene de código anidado (un builtin que llamó a
+    /// una función que falló), ya se anotó más adentro con una pila de la que
+    /// la actual es prefijo, y esa se conserva.
+    fn record_fault(&mut self, ip: usize) {
+        self.fault_ip = Some(ip);
+        let names = self.call_stack.iter().map(|f| &f.func_name);
+        let nested = self.fault_stack.len() > self.call_stack.len()
+            && names
+                .clone()
+                .eq(self.fault_stack.iter().take(self.call_stack.len()));
+        if !nested {
+            self.fault_stack = names.cloned().collect();
+        }
+    }
+
+    /// Funciones activas cuando se produjo el último error no capturado, de
+    /// la más externa a la más interna. A diferencia de `call_stack`, sigue
+    /// disponible después de que `run_function` desapile los marcos.
+    pub fn error_stack(&self) -> &[String] {
+        &self.fault_stack
+    }
+
+    /// Posición de la instrucción que produjo el último error no capturado.
+    pub fn error_position(&self) -> Option<(u32, u32)> {
+        self.position_of(self.fault_ip?)
+    }
+
+    /// Archivo de la instrucción que produjo el último error no capturado.
+    pub fn error_file(&self) -> Option<u32> {
+        self.file_of(self.fault_ip?)
+    }
+
+    fn format_position(&self, ip: usize) -> Option<String> {
+        let entry = self.bytecode.lines.entry_at(ip)?;
+        Some(match self.display_file(entry.file) {
+            Some(file) => format!("{}:{}:{}", file, entry.line, entry.col),
+            None => format!("{}:{}", entry.line, entry.col),
+        })
+    }
+
+    /// Nombre con el que se muestra el archivo `file`: el principal, como se
+    /// dio en `set_source_name`; un módulo importado, relativo al directorio
+    /// del principal si cuelga de él.
+    fn display_file(&self, file: u32) -> Option<String> {
+        if file == 0 {
+            return self.source_name.clone();
+        }
+        let path = Path::new(self.source_file(file)?);
+        let relative = self
+            .source_file(0)
+            .and_then(|main| Path::new(main).parent())
+            .and_then(|dir| path.strip_prefix(dir).ok());
+        Some(match (relative, &self.source_name) {
+            (Some(rel), Some(name)) => Path::new(name)
+                .parent()
+                .map_or_else(|| rel.to_path_buf(), |dir| dir.join(rel))
+                .display()
+                .to_string(),
+            _ => path.display().to_string(),
+        })
+    }
+
+    /// Mensaje de un error de ejecución con su posición `archivo:línea:col` y
+    /// la pila de llamadas, cada marco con la sentencia en la que está.
+    pub fn describe_error(&self, err: VmError) -> String {
+        let msg = err.with_stack(&[]);
+        let fault = self.fault_ip.and_then(|ip| self.format_position(ip));
+        let mut out = match &fault {
+            Some(pos) => format!("{}\n  --> {}", msg, pos),
+            None => msg,
+        };
+        if !self.call_stack.is_empty() {
+            out.push_str("\n\nPila de llamadas:");
+            // Cada marco está detenido en la llamada a su sucesor; el más
+            // interno, en la instrucción que falló.
+            let mut ip = self.fault_ip;
+            for frame in self.call_stack.iter().rev() {
+                match ip.and_then(|ip| self.format_position(ip)) {
+                    Some(pos) => out.push_str(&format!("\n  · {} ({})", frame.func_name, pos)),
+                    None => out.push_str(&format!("\n  · {}", frame.func_name)),
+                }
+                ip = frame.return_ip.checked_sub(1);
+            }
+            if let Some(pos) = ip.and_then(|ip| self.format_position(ip)) {
+                out.push_str(&format!("\n  · (nivel superior) ({})", pos));
+            }
         }
-        // Time-Travel Snapshot
+        out
+    }
+
+    /// Guarda el estado para el time-travel de `step_back`.
+    fn push_snapshot(&mut self) {
         self.snapshots.push(VmSnapshot {
             ip: self.ip,
             instr_count: self.instr_count,
             stack: self.stack.clone(),
             locals: self.locals.clone(),
+            globals: self.globals.to_vec(),
             call_stack: self.call_stack.clone(),
+            root_defers: self.root_defers.clone(),
+            handlers: self.handlers.clone(),
+            unwinding: self.unwinding.clone(),
             output_len: self.output.len(),
         });
         if self.snapshots.len() > 5000 {
             self.snapshots.remove(0);
         }
-        let instr = self.bytecode.instructions[self.ip].clone();
-        self.ip += 1;
-        self.instr_count += 1;
-        self.last_instr = Some(instr.clone());
-        self.execute(&instr)
     }
 
-    pub fn step_back(&mut self) -> Result<bool, VmError> {
-        if let Some(snap) = self.snapshots.pop() {
-            self.ip = snap.ip;
-            self.instr_count = snap.instr_count;
-            self.stack = snap.stack;
-            self.locals = snap.locals;
-            self.call_stack = snap.call_stack;
-            self.output.truncate(snap.output_len);
-            Ok(true)
-        } else {
-            Ok(false)
+    /// Ejecuta una sola instrucción, guardando antes un snapshot.
+    pub fn step(&mut self) -> Result<(), VmError> {
+        self.step_mode = true;
+        self.debug = true;
+        if self.is_finished() {
+            return Ok(());
+        }
+        self.paused_at = None;
+        self.push_snapshot();
+        self.execute_one()
+    }
+
+    fn execute_one(&mut self) -> Result<(), VmError> {
+        let cur_ip = self.ip;
+        let instr = self.bytecode.instructions[cur_ip].clone();
+        self.ip += 1;
+        self.instr_count += 1;
+        self.last_instr = Some(instr.clone());
+        match self.execute(&instr) {
+            Err(e) => self.unwind_to_handler(e).inspect_err(|_| {
+                self.record_fault(cur_ip);
+            }),
+            ok => ok,
+        }
+    }
+
+    /// Avanza por sentencias de la fuente según `kind`, o hasta un breakpoint
+    /// o el final. Guarda un único snapshot, así que `step_back` deshace el
+    /// paso entero.
+    pub fn step_source(&mut self, kind: StepKind) -> Result<(), VmError> {
+        self.step_mode = true;
+        self.debug = true;
+        if self.is_finished() {
+            return Ok(());
+        }
+        self.paused_at = None;
+        self.push_snapshot();
+        let depth = self.call_stack.len();
+        loop {
+            self.execute_one()?;
+            if self.is_finished() {
+                return Ok(());
+            }
+            if self.breakpoints.contains(&self.ip) {
+                self.paused_at = Some(self.ip);
+                return Ok(());
+            }
+            if !self.bytecode.lines.is_stmt_start(self.ip) {
+                continue;
+            }
+            let stop = match kind {
+                StepKind::Into => true,
+                StepKind::Over => self.call_stack.len() <= depth,
+                StepKind::Out => self.call_stack.len() < depth,
+            };
+            if stop {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn step_into(&mut self) -> Result<(), VmError> {
+        self.step_source(StepKind::Into)
+    }
+
+    pub fn step_over(&mut self) -> Result<(), VmError> {
+        self.step_source(StepKind::Over)
+    }
+
+    pub fn step_out(&mut self) -> Result<(), VmError> {
+        self.step_source(StepKind::Out)
+    }
+
+    pub fn step_back(&mut self) -> Result<bool, VmError> {
+        if let Some(snap) = self.snapshots.pop() {
+            self.ip = snap.ip;
+            self.instr_count = snap.instr_count;
+            self.stack = snap.stack;
+            self.locals = snap.locals;
+            self.globals = Arc::new(snap.globals);
+            self.call_stack = snap.call_stack;
+            self.root_defers = snap.root_defers;
+            self.handlers = snap.handlers;
+            self.unwinding = snap.unwinding;
+            self.output.truncate(snap.output_len);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+    pub fn stack_top(&self) -> Option<&Value> {
+        self.stack.last()
+    }
+    /// Variables visibles en el frame actual: sus slots dentro de una llamada,
+    /// o las globales ya asignadas y los slots de los bloques en el nivel
+    /// superior.
+    pub fn current_locals(&self) -> Option<HashMap<String, Value, FixHasher>> {
+        let frame = self.locals.last()?;
+        let slots = |meta: &FuncMeta| {
+            slot_names(meta)
+                .iter()
+                .cloned()
+                .enumerate()
+                .take(frame.slots.len())
+                .map(|(slot, name)| (name, frame.get(slot)))
+                .collect::<Vec<_>>()
+        };
+        let vars = match frame.func.and_then(|f| self.bytecode.funcs.get(f)) {
+            Some(meta) => slots(meta).into_iter().collect(),
+            None => self
+                .bytecode
+                .names
+                .iter()
+                .zip(self.globals.iter())
+                .filter_map(|(name, val)| Some((name.clone(), val.clone()?)))
+                .chain(
+                    self.bytecode
+                        .funcs
+                        .iter()
+                        .filter(|meta| meta.name == "__main__")
+                        .flat_map(slots),
+                )
+                .collect(),
+        };
+        Some(vars)
+    }
+
+    pub fn output(&self) -> &[String] {
+        &self.output
+    }
+
+    /// Devuelve lo impreso hasta ahora y vacía la salida.
+    pub fn take_output(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Empieza a registrar la cobertura de ramas. Desactiva el JIT: el código
+    /// nativo no pasa por `JmpIf` y dejaría ramas sin contar.
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(HashSet::new());
+        #[cfg(feature = "aot")]
+        {
+            self.jit_engine = None;
+        }
+    }
+
+    /// Aplica `limits` a las próximas ejecuciones con el presupuesto entero:
+    /// el plazo empieza a correr ahora.
+    pub fn set_limits(&mut self, limits: VmLimits) {
+        self.budget = Budget::new(limits, self.instr_count);
+    }
+
+    pub fn limits(&self) -> &VmLimits {
+        &self.budget.limits
+    }
+
+    /// Rellena el combustible, el plazo y la cuenta de salida sin tocar el
+    /// estado, para otra llamada sobre la misma VM. `reset` también lo hace.
+    pub fn reset_budget(&mut self) {
+        self.budget.refill(self.instr_count);
+    }
+
+    /// Añade una línea de `imprimir` a la salida, contando las que lleve
+    /// dentro.
+    fn emit(&mut self, line: String) -> Result<(), VmError> {
+        self.budget
+            .emit(line.lines().count().max(1))
+            .map_err(VmError::Sandbox)?;
+        self.output.push(line);
+        Ok(())
+    }
+
+    /// Antes de leer entero `path`: su tamaño por `factor` (lo que ocupa cada
+    /// byte leído) debe caber en el tope de memoria. Si no se puede consultar
+    /// el tamaño, el error lo dará la lectura.
+    fn check_file_alloc(&self, path: &str, factor: usize) -> Result<(), VmError> {
+        if self.budget.limits.max_memory.is_none() {
+            return Ok(());
+        }
+        let size = std::fs::metadata(path).map_or(0, |meta| meta.len() as usize);
+        self.budget
+            .check_alloc(size.saturating_mul(factor))
+            .map_err(VmError::Sandbox)
+    }
+
+    /// Comprobación periódica del plazo y de la memoria viva.
+    fn watch(&mut self) -> Result<(), VmError> {
+        self.budget.check_deadline().map_err(VmError::Sandbox)?;
+        let mut measured = 0;
+        if let Some(max) = self.budget.limits.max_memory {
+            let mut meter = MemoryMeter::new(max);
+            for value in self.stack.iter().chain(self.globals.iter().flatten()) {
+                meter.value(value);
+            }
+            for frame in &self.locals {
+                for value in &frame.slots {
+                    meter.value(value);
+                }
+                for cell in frame.cells.iter().flatten() {
+                    meter.cell(cell);
+                }
+            }
+            meter.bytes(self.output.iter().map(String::len).sum());
+            if meter.over() {
+                return Err(VmError::Sandbox(SandboxViolation::Memory(max)));
+            }
+            measured = meter.visited;
+        }
+        self.budget.next_watch = self.budget.next_watch_after(self.instr_count, measured);
+        Ok(())
+    }
+
+    /// Vuelve al estado inicial para ejecutar otra vez el mismo bytecode.
+    /// Conserva lo que es caro de crear (builtins, JIT, bibliotecas cargadas);
+    /// la cobertura, si está activa, empieza vacía.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.locals = vec![LocalFrame::default()];
+        self.globals = Arc::new(vec![None; self.bytecode.names.len()]);
+        self.ip = VM::entry_ip(&self.bytecode);
+        self.output.clear();
+        self.call_stack.clear();
+        self.root_defers = DeferStack::default();
+        self.handlers.clear();
+        self.unwinding.clear();
+        self.paused_at = None;
+        self.fault_ip = None;
+        self.fault_stack.clear();
+        self.step_mode = false;
+        self.last_instr = None;
+        self.instr_count = 0;
+        self.budget.refill(0);
+        self.snapshots.clear();
+        self.call_counts.fill(0);
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.clear();
+        }
+        #[cfg(any(feature = "extra", feature = "full"))]
+        {
+            self.coroutines.clear();
+            self.current_coro = None;
+            self.main_saved = None;
+            self.channels = SharedChannels::default();
+            self.scopes.clear();
+            self.sync = SharedSync::default();
+            self.sync_wait = None;
+            self.actors = SharedActors::default();
+            self.generators.clear();
+            self.task_results.clear();
+            self.task_results_sync.clear();
+            self.task_counter = 0;
+        }
+    }
+
+    /// Ramas cubiertas desde `enable_coverage`, como (ip del `JmpIf`, condición).
+    pub fn coverage(&self) -> Option<&HashSet<(usize, bool)>> {
+        self.coverage.as_ref()
+    }
+
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.call_stack
+    }
+
+    /// Lleva un error capturable al manejador `atrapar` más reciente. Antes
+    /// ejecuta los `posponer` pendientes de cada frame que abandona, del más
+    /// interno al del `intentar` (ver `continue_unwind`).
+    fn unwind_to_handler(&mut self, err: VmError) -> Result<(), VmError> {
+        let message = match &err {
+            VmError::Runtime(s) => s.clone(),
+            VmError::TypeError(_) | VmError::DivisionByZero => err.to_string(),
+            _ => return Err(err),
+        };
+        if let Some(top) = self.unwinding.last_mut() {
+            if self.handlers.len() == top.handlers_len {
+                // Falla un `posponer` lanzado al desenrollar: el error sigue
+                // hacia el mismo `atrapar` y reemplaza al mensaje original.
+                top.message = message;
+                return self.continue_unwind();
+            }
+        }
+        let Some(handler) = self.handlers.pop() else {
+            return Err(err);
+        };
+        // Capturado: lo anotado por código anidado ya no es de un fallo.
+        self.fault_stack.clear();
+        self.unwinding.push(Unwind {
+            handler,
+            message,
+            handlers_len: self.handlers.len(),
+        });
+        self.continue_unwind()
+    }
+
+    /// Lanza el siguiente `posponer` pendiente del desenrollado en curso o,
+    /// si no queda ninguno, recorta el estado al punto del `intentar` y salta
+    /// a `atrapar` con el mensaje en la pila.
+    fn continue_unwind(&mut self) -> Result<(), VmError> {
+        let Some(unwind) = self.unwinding.last() else {
+            return Err(VmError::Runtime(
+                "fin de bloque 'posponer' sin desenrollado en curso".to_string(),
+            ));
+        };
+        let handler = unwind.handler.clone();
+        while self.call_stack.len() > handler.call_depth {
+            let Some(frame) = self.call_stack.last_mut() else {
+                break;
+            };
+            if let Some(target) = frame.defers.pending.pop() {
+                frame.defers.resume.clear();
+                frame.defers.resume.push(UNWIND_RESUME);
+                self.ip = target;
+                return Ok(());
+            }
+            self.call_stack.pop();
+            self.locals.pop();
+        }
+        let defers = self.current_defers();
+        defers.resume.truncate(handler.defer_resume_len);
+        if defers.pending.len() > handler.defer_pending_len {
+            if let Some(target) = defers.pending.pop() {
+                defers.resume.push(UNWIND_RESUME);
+                self.ip = target;
+                return Ok(());
+            }
+        }
+        let message = self.unwinding.pop().map(|u| u.message).unwrap_or_default();
+        self.locals.truncate(handler.locals_len);
+        self.stack.truncate(handler.stack_len);
+        self.ip = handler.catch_ip;
+        self.push(Value::str(message));
+        Ok(())
+    }
+
+    /// Descarta los manejadores instalados por frames que ya retornaron.
+    fn drop_frame_handlers(&mut self) {
+        let depth = self.call_stack.len();
+        while self.handlers.last().is_some_and(|h| h.call_depth > depth) {
+            self.handlers.pop();
+        }
+    }
+
+    fn current_defers(&mut self) -> &mut DeferStack {
+        match self.call_stack.last_mut() {
+            Some(frame) => &mut frame.defers,
+            None => &mut self.root_defers,
+        }
+    }
+
+    /// Run a specific function by name with given args, returning its result.
+    /// Used by spawned task threads to execute a function in isolation.
+    pub fn run_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value, VmError> {
+        // Un error de una llamada anterior no es de esta.
+        self.fault_ip = None;
+        self.fault_stack.clear();
+        match self.func_index_cache.get(name) {
+            Some(&func_idx) => {
+                let scope = self.new_frame(func_idx, args);
+                self.run_frame(name, func_idx, scope)
+            }
+            None => Err(VmError::UndefinedFunction(name.to_string())),
+        }
+    }
+
+    /// Llama a un valor invocable: una función o clausura, o el nombre de una
+    /// función como texto (la forma que usan las funciones de concurrencia).
+    pub fn call_value(&mut self, callee: &Value, args: Vec<Value>) -> Result<Value, VmError> {
+        let (name, func_idx, scope) = self.callee_frame(callee, args)?;
+        self.run_frame(&name, func_idx, scope)
+    }
+
+    /// Resuelve un invocable a su función y el frame de la llamada.
+    fn callee_frame(
+        &self,
+        callee: &Value,
+        args: Vec<Value>,
+    ) -> Result<(String, usize, LocalFrame), VmError> {
+        let (name, captured) = match callee {
+            Value::Closure { func, captured } => (func.as_str(), captured.as_slice()),
+            Value::Func(name) => (name.as_str(), &[][..]),
+            Value::Str(name) => (&name[..], &[][..]),
+            other => {
+                return Err(VmError::TypeError(format!(
+                    "Se esperaba una función para llamar, no {}",
+                    other.type_name()
+                )))
+            }
+        };
+        let func_idx = *self
+            .func_index_cache
+            .get(name)
+            .ok_or_else(|| VmError::UndefinedFunction(name.to_string()))?;
+        Ok((
+            name.to_string(),
+            func_idx,
+            self.closure_frame(func_idx, args, captured),
+        ))
+    }
+
+    /// Empuja el frame de una llamada cuyo retorno sale del bucle de `run`.
+    fn enter_frame(
+        &mut self,
+        name: &str,
+        func_idx: usize,
+        scope: LocalFrame,
+    ) -> Result<(), VmError> {
+        if self.call_stack.len() >= MAX_CALL_STACK_DEPTH {
+            return Err(VmError::Runtime(format!(
+                "Desbordamiento de pila (Stack overflow): límite de recursión excedido (>{} llamadas)",
+                MAX_CALL_STACK_DEPTH
+            )));
         }
+        self.locals.push(scope);
+        self.call_stack.push(CallFrame {
+            func_name: name.to_string(),
+            return_ip: self.bytecode.instructions.len(), // Past end → run() loop breaks
+            defers: DeferStack::default(),
+        });
+        self.ip = self.bytecode.funcs[func_idx].start;
+        Ok(())
     }
-    pub fn stack_top(&self) -> Option<&Value> {
-        self.stack.last()
+
+    fn run_frame(
+        &mut self,
+        name: &str,
+        func_idx: usize,
+        scope: LocalFrame,
+    ) -> Result<Value, VmError> {
+        let (stack_len, locals_len, calls_len) =
+            (self.stack.len(), self.locals.len(), self.call_stack.len());
+        // Un builtin puede llamar a código de usuario a mitad de ejecución:
+        // al volver se sigue por donde iba.
+        let saved_ip = self.ip;
+        self.enter_frame(name, func_idx, scope)?;
+        // El código anidado corre de un tirón: solo el nivel superior de una
+        // fibra se aparca.
+        #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+        let slice_end = self.slice_end.take();
+        let result = self.run();
+        #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+        {
+            self.slice_end = slice_end;
+        }
+        self.ip = saved_ip;
+        if let Err(e) = result {
+            // Deja la VM como antes de la llamada para poder llamar otra vez.
+            self.stack.truncate(stack_len);
+            self.locals.truncate(locals_len);
+            self.call_stack.truncate(calls_len);
+            self.drop_frame_handlers();
+            return Err(e);
+        }
+        Ok(self.pop().unwrap_or(Value::Void))
     }
-    pub fn current_locals(&self) -> Option<&HashMap<String, Value, FixHasher>> {
-        self.locals.last()
+
+    /// Prepara esta VM (una hija de `child`) para correr `callee` como fibra.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    pub(crate) fn start_fiber(&mut self, callee: &Value, args: Vec<Value>) -> Result<(), VmError> {
+        let (name, func_idx, scope) = self.callee_frame(callee, args)?;
+        self.enter_frame(&name, func_idx, scope)
     }
 
-    pub fn output(&self) -> &[String] {
-        &self.output
+    /// Corre un tramo de la fibra: hasta que termine, agote `SLICE`
+    /// instrucciones o un builtin bloqueante la aparque.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    pub(crate) fn run_slice(&mut self) -> scheduler::FiberStep {
+        self.slice_end = Some(self.instr_count + scheduler::SLICE);
+        let result = self.run();
+        self.slice_end = None;
+        match self.parked.take() {
+            Some(park) => scheduler::FiberStep::Parked(park),
+            None => scheduler::FiberStep::Done(result.map(|()| self.pop().unwrap_or(Value::Void))),
+        }
     }
 
-    pub fn call_stack(&self) -> &[CallFrame] {
-        &self.call_stack
+    /// Verdadero si el builtin en curso puede aparcar la fibra en vez de
+    /// bloquear el hilo: solo en el nivel superior de una fibra.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    fn parkable(&self) -> bool {
+        self.slice_end.is_some()
     }
 
-    /// Run a specific function by name with given args, returning its result.
-    /// Used by spawned task threads to execute a function in isolation.
-    pub fn run_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value, VmError> {
-        if let Some(func) = self.find_func(name) {
-            let func_start = func.start;
-            let func_params = func.params.clone();
-            let func_defaults = func.defaults.clone();
-            let mut scope =
-                HashMap::with_capacity_and_hasher(func_params.len(), FixHasher::default());
-            for (i, param_name) in func_params.iter().enumerate() {
-                let arg = if i < args.len() {
-                    args[i].clone()
-                } else if let Some(Some(dv)) = func_defaults.get(i) {
-                    match dv {
-                        DefaultValue::Int(v) => Value::Int(*v),
-                        DefaultValue::Float(v) => Value::Float(*v),
-                        DefaultValue::Str(s) => Value::str(s.clone()),
-                        DefaultValue::Bool(b) => Value::Bool(*b),
-                    }
-                } else {
-                    Value::Void
-                };
-                scope.insert(param_name.clone(), arg);
-            }
-            if self.call_stack.len() >= MAX_CALL_STACK_DEPTH {
-                return Err(VmError::Runtime(format!(
-                    "Desbordamiento de pila (Stack overflow): límite de recursión excedido (>{} llamadas)",
-                    MAX_CALL_STACK_DEPTH
-                )));
-            }
-            self.locals.push(scope);
-            self.call_stack.push(CallFrame {
-                func_name: name.to_string(),
-                return_ip: self.bytecode.instructions.len(), // Past end → run() loop breaks
-            });
-            self.ip = func_start;
-            self.run()?;
-            Ok(self.pop().unwrap_or(Value::Void))
-        } else {
-            Err(VmError::UndefinedFunction(name.to_string()))
+    /// Aparca la fibra dentro de un builtin: devuelve sus argumentos a la
+    /// pila y retrocede a la llamada, que se repite al reanudar.
+    #[cfg(all(any(feature = "extra", feature = "full"), not(target_arch = "wasm32")))]
+    fn park(&mut self, args: Vec<Value>, mut park: scheduler::Park) -> Result<(), VmError> {
+        if let Err(e) = self.check_cancelled() {
+            self.park_deadline = None;
+            return Err(e);
+        }
+        // Una hija de un ámbito despierta también cuando la cancelan.
+        if let Some(cancel) = &self.cancel {
+            park.waits.push(scheduler::flag_key(cancel));
         }
+        park.until = self.budget.deadline.clamp(park.until);
+        for arg in args {
+            self.push(arg);
+        }
+        self.ip = self.call_ip;
+        self.parked = Some(park);
+        Ok(())
     }
 
     fn execute(&mut self, instr: &Instruction) -> Result<(), VmError> {
@@ -3451,7 +6593,14 @@ impl VM {
                     (Value::Int(a), Value::Float(b)) => self.push(Value::Float(a as f64 + b)),
                     (Value::Float(a), Value::Int(b)) => self.push(Value::Float(a + b as f64)),
                     (Value::Float(a), Value::Float(b)) => self.push(Value::Float(a + b)),
-                    (Value::Str(a), Value::Str(b)) => self.push(Value::str(format!("{}{}", a, b))),
+                    (Value::Str(a), Value::Str(b)) => {
+                        // Duplicar un texto en bucle crece más deprisa de lo
+                        // que `watch` llega a medir.
+                        self.budget
+                            .check_alloc(a.len() + b.len())
+                            .map_err(VmError::Sandbox)?;
+                        self.push(Value::str(format!("{}{}", a, b)))
+                    }
                     (Value::Str(a), Value::Int(b)) => self.push(Value::str(format!("{}{}", a, b))),
                     (Value::Str(a), Value::Float(b)) => {
                         self.push(Value::str(format!("{}{}", a, b)))
@@ -3820,6 +6969,7 @@ impl VM {
                 }
                 if let Some(frame) = self.call_stack.pop() {
                     self.locals.pop();
+                    self.drop_frame_handlers();
                     self.ip = frame.return_ip;
                     self.push(ret_val);
                 } else {
@@ -3829,12 +6979,23 @@ impl VM {
             }
             Opcode::Print => {
                 let val = self.pop()?;
-                let s = format!("{}", val);
-                self.output.push(s);
+                self.emit(format!("{}", val))?;
             }
             Opcode::Halt => {
                 self.ip = usize::MAX;
             }
+            Opcode::TryEnd => {
+                self.handlers.pop();
+            }
+            Opcode::DeferEnd => match self.current_defers().resume.pop() {
+                Some(UNWIND_RESUME) => self.continue_unwind()?,
+                Some(run_ip) => self.ip = run_ip,
+                None => {
+                    return Err(VmError::Runtime(
+                        "fin de bloque 'posponer' sin ejecución pendiente".to_string(),
+                    ))
+                }
+            },
             Opcode::StructNew => {
                 // handled in execute_with_idx
             }
@@ -3911,7 +7072,7 @@ impl VM {
             Opcode::FuncRef => {
                 // handled in execute_with_idx
             }
-            Opcode::CallValue => {
+            Opcode::CallValue | Opcode::MakeClosure => {
                 // handled in execute_with_idx
             }
             Opcode::ArrayNew => {
@@ -4117,6 +7278,7 @@ impl VM {
                         let err_wrapper = Value::Error(inner);
                         if let Some(frame) = self.call_stack.pop() {
                             self.locals.pop();
+                            self.drop_frame_handlers();
                             self.ip = frame.return_ip;
                         }
                         self.push(err_wrapper);
@@ -4186,783 +7348,226 @@ impl VM {
             }
             _ => Ok(()),
         }
-    }
-
-    fn execute_with_idx(&mut self, op: Opcode, idx: usize) -> Result<(), VmError> {
-        match op {
-            Opcode::PushInt => {
-                let n = self.bytecode.ints.get(idx).copied().unwrap_or(0);
-                self.push(Value::Int(n));
-            }
-            Opcode::PushNum => {
-                let n = self.bytecode.nums.get(idx).copied().unwrap_or(0.0);
-                self.push(Value::Float(n));
-            }
-            Opcode::PushStr => {
-                let s = self.bytecode.strings.get(idx).cloned().unwrap_or_default();
-                self.push(Value::str(s));
-            }
-            Opcode::PushBool => {
-                self.push(Value::Bool(idx != 0));
-            }
-            Opcode::Load => {
-                let name = self
-                    .bytecode
-                    .names
-                    .get(idx)
-                    .map(|s| s.as_str())
-                    .unwrap_or("");
-                let val = self.lookup(name)?;
-                self.push(val);
-            }
-            Opcode::Store => {
-                let val = self.pop()?;
-                let name = self
-                    .bytecode
-                    .names
-                    .get(idx)
-                    .map(|s| s.as_str())
-                    .unwrap_or("");
-                let n = self.locals.len();
-                if n > 0 {
-                    let cur = n - 1;
-                    let mut found = false;
-                    for scope in self.locals.iter_mut().rev() {
-                        if let Some(entry) = scope.get_mut(name) {
-                            *entry = val.clone();
-                            found = true;
-                            break;
-                        }
-                    }
-                    if !found {
-                        self.locals[cur].insert(name.to_string(), val);
-                    }
-                }
-            }
-            Opcode::Call => {
-                let name = self.bytecode.names.get(idx).cloned().unwrap_or_default();
-                let count = self.call_counts.entry(name.clone()).or_insert(0);
-                *count += 1;
-                if *count == self.jit_threshold && std::env::var_os("LUMEN_JIT_LOG").is_some() {
-                    eprintln!(
-                        "[jit] 🔥 Hot function detected: '{}' ({} llamadas) -> JIT Tier-1 activado",
-                        name, *count
-                    );
-                }
-                let argc_idx = self.ip;
-                self.ip += 1;
-                let argc = if argc_idx < self.bytecode.instructions.len() {
-                    if let Instruction::WithIdx(_, nidx) = &self.bytecode.instructions[argc_idx] {
-                        self.bytecode.nums.get(*nidx).copied().unwrap_or(0.0) as usize
-                    } else {
-                        0
-                    }
-                } else {
-                    0
-                };
-                let mut args: Vec<Value> = Vec::new();
-                for _ in 0..argc {
-                    args.push(self.pop()?);
-                }
-                args.reverse();
-                if let Some(result) = self.call_core_builtin(&name, &args) {
-                    return result;
-                }
-                #[cfg(any(feature = "extra", feature = "full"))]
-                if let Some(result) = self.call_extra_builtin(&name, &args) {
-                    return result;
-                }
-                if let Some(&func_idx) = self.func_index_cache.get(&name) {
-                    if self.call_stack.len() >= MAX_CALL_STACK_DEPTH {
-                        return Err(VmError::Runtime(format!(
-                            "Desbordamiento de pila (Stack overflow): límite de recursión excedido (>{} llamadas)",
-                            MAX_CALL_STACK_DEPTH
-                        )));
-                    }
-                    let func_start = self.bytecode.funcs[func_idx].start;
-                    let param_count = self.bytecode.funcs[func_idx].params.len();
-                    self.call_stack.push(CallFrame {
-                        func_name: name,
-                        return_ip: self.ip,
-                    });
-                    let mut scope =
-                        HashMap::with_capacity_and_hasher(param_count, FixHasher::default());
-                    for i in 0..param_count {
-                        let param_name = self.bytecode.funcs[func_idx].params[i].clone();
-                        let arg = if i < args.len() {
-                            args[i].clone()
-                        } else if let Some(Some(dv)) = self.bytecode.funcs[func_idx].defaults.get(i)
-                        {
-                            match dv {
-                                DefaultValue::Int(v) => Value::Int(*v),
-                                DefaultValue::Float(v) => Value::Float(*v),
-                                DefaultValue::Str(s) => Value::str(s.clone()),
-                                DefaultValue::Bool(b) => Value::Bool(*b),
-                            }
-                        } else {
-                            Value::Void
-                        };
-                        scope.insert(param_name, arg);
-                    }
-                    self.locals.push(scope);
-                    self.ip = func_start;
-                } else {
-                    return Err(VmError::UndefinedFunction(name));
-                }
-            }
-            Opcode::FuncRef => {
-                let name = self.bytecode.strings.get(idx).cloned().unwrap_or_default();
-                self.push(Value::Func(name));
-            }
-            Opcode::CallValue => {
-                let argc = self.bytecode.nums.get(idx).copied().unwrap_or(0.0) as usize;
-                let mut args = Vec::new();
-                for _ in 0..argc {
-                    args.push(self.pop()?);
-                }
-                args.reverse();
-                let callee = self.pop()?;
-                let name = match &callee {
-                    Value::Func(n) => n.clone(),
-                    _ => {
-                        return Err(VmError::TypeError(
-                            "Se esperaba una función para llamar".to_string(),
-                        ))
-                    }
-                };
-                if name == "imprimir" || name == "print" {
-                    let mut combined = String::new();
-                    for arg in args {
-                        combined.push_str(&format!("{}", arg));
-                    }
-                    self.output.push(combined);
-                    self.push(Value::Void);
-                } else if name == "leer" || name == "read" {
-                    self.push(Value::str(String::new()));
-                } else if name == "a_texto" || name == "to_texto" || name == "__str_from" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    self.push(Value::str(s));
-                } else if name == "__str_a_entero" || name == "__texto_a_entero" {
-                    let mut s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    if let Some(dot) = s.find('.') {
-                        s.truncate(dot);
-                    }
-                    match s.parse::<i64>() {
-                        Ok(n) => self.push(Value::Int(n)),
-                        Err(_) => self.push(Value::Int(0)),
-                    }
-                } else if name == "__str_len" || name == "__str_longitud" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    self.push(Value::Int(s.len() as i64));
-                } else if name == "__str_upper" || name == "__str_mayusculas" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    self.push(Value::str(s.to_uppercase()));
-                } else if name == "__str_lower" || name == "__str_minusculas" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    self.push(Value::str(s.to_lowercase()));
-                } else if name == "__str_trim" || name == "__str_recortar" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    self.push(Value::str(s.trim().to_string()));
-                } else if name == "__str_contains" || name == "__str_contiene" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let sub = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-                    self.push(Value::Bool(s.contains(&sub)));
-                } else if name == "__str_split" || name == "__str_dividir" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let delim = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-                    let parts: Vec<Value> = if delim.is_empty() {
-                        s.chars().map(|c| Value::str(c.to_string())).collect()
-                    } else {
-                        s.split(&delim).map(|p| Value::str(p.to_string())).collect()
-                    };
-                    self.push(Value::arr(parts));
-                } else if name == "__str_ord" || name == "__str_codigo" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let codes: Vec<Value> = s.chars().map(|c| Value::Int(c as i64)).collect();
-                    self.push(Value::arr(codes));
-                } else if name == "__str_chr" || name == "__str_caracter" {
-                    let n = args
-                        .first()
-                        .and_then(|v| match v {
-                            Value::Int(i) => Some(*i),
-                            _ => v.as_num().map(|f| f as i64),
-                        })
-                        .unwrap_or(0);
-                    let c = char::from_u32(n as u32)
-                        .map(|c| c.to_string())
-                        .unwrap_or_default();
-                    self.push(Value::str(c));
-                } else if name == "__str_slice" || name == "__str_subcadena" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let start = args
-                        .get(1)
-                        .and_then(|v| match v {
-                            Value::Int(i) => Some(*i as usize),
-                            _ => v.as_num().map(|f| f as usize),
-                        })
-                        .unwrap_or(0);
-                    let end = args
-                        .get(2)
-                        .and_then(|v| match v {
-                            Value::Int(i) => {
-                                if *i == -1 {
-                                    Some(s.len())
-                                } else {
-                                    Some(*i as usize)
-                                }
-                            }
-                            _ => v.as_num().map(|f| f as usize),
-                        })
-                        .unwrap_or(s.len());
-                    let start = start.min(s.len());
-                    let end = end.min(s.len()).max(start);
-                    let sub: String = s.chars().skip(start).take(end - start).collect();
-                    self.push(Value::str(sub));
-                } else if name == "__str_concat_list" || name == "__str_concatenar_lista" {
-                    let list = args.first().cloned().unwrap_or(Value::arr(vec![]));
-                    match list {
-                        Value::Array(items) => {
-                            let result = items.iter().map(|v| format!("{}", v)).collect::<String>();
-                            self.push(Value::str(result));
-                        }
-                        _ => self.push(Value::str(String::new())),
-                    }
-                } else if name == "__str_starts_with" || name == "__str_empieza_con" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let prefix = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-                    self.push(Value::Bool(s.starts_with(&prefix)));
-                } else if name == "__str_to_chars" || name == "__str_a_caracteres" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let chars: Vec<Value> = s.chars().map(|c| Value::str(c.to_string())).collect();
-                    self.push(Value::arr(chars));
-                } else if name == "__str_reemplazar" || name == "__str_replace" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let from = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-                    let to = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
-                    self.push(Value::str(s.replace(&from, &to)));
-                } else if name == "__str_subcadena_chars" || name == "__str_slice_chars" {
-                    let cs = match args.first() {
-                        Some(Value::Array(a)) => a.clone(),
-                        _ => Arc::new(vec![]),
-                    };
-                    let st = args
-                        .get(1)
-                        .and_then(|v| v.as_num())
-                        .map(|f| f as i64)
-                        .unwrap_or(0);
-                    let en = args
-                        .get(2)
-                        .and_then(|v| v.as_num())
-                        .map(|f| f as i64)
-                        .unwrap_or(-1);
-                    let n = cs.len() as i64;
-                    let st = st.max(0).min(n);
-                    let en = if en < 0 { n } else { en.max(0).min(n) };
-                    let mut out = String::new();
-                    for c in cs.iter().skip(st as usize).take((en - st).max(0) as usize) {
-                        out.push_str(&format!("{}", c));
-                    }
-                    self.push(Value::str(out));
-                } else if name == "__map_new" || name == "__map_nuevo" {
-                    self.push(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                } else if name == "__map_set" || name == "__map_poner" {
-                    let mut it = args.into_iter();
-                    let m = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    let k = it.next().unwrap_or(Value::Void);
-                    let v = it.next().unwrap_or(Value::Void);
-                    match m {
-                        Value::Map(mut m) => {
-                            m.insert(k, v);
-                            self.push(Value::Map(m));
-                        }
-                        _ => return Err(VmError::TypeError("__map_set espera diccionario".into())),
-                    }
-                } else if name == "__map_get" || name == "__map_obtener" {
-                    let mut it = args.into_iter();
-                    let m = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    let k = it.next().unwrap_or(Value::Void);
-                    match m {
-                        Value::Map(m) => {
-                            self.push(m.get(&k).cloned().unwrap_or(Value::Void));
-                        }
-                        _ => return Err(VmError::TypeError("__map_get espera diccionario".into())),
-                    }
-                } else if name == "__map_len" || name == "__map_longitud" {
-                    let m = args
-                        .into_iter()
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    match m {
-                        Value::Map(m) => self.push(Value::Int(m.len() as i64)),
-                        _ => return Err(VmError::TypeError("__map_len espera diccionario".into())),
-                    }
-                } else if name == "__map_keys" || name == "__map_claves" {
-                    let m = args
-                        .into_iter()
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    match m {
-                        Value::Map(m) => {
-                            self.push(Value::arr(m.into_iter().map(|(k, _)| k).collect()));
-                        }
-                        _ => {
-                            return Err(VmError::TypeError("__map_keys espera diccionario".into()))
-                        }
-                    }
-                } else if name == "__map_contains" || name == "__map_contiene" {
-                    let mut it = args.into_iter();
-                    let m = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    let k = it.next().unwrap_or(Value::Void);
-                    match m {
-                        Value::Map(m) => self.push(Value::Bool(m.contains_key(&k))),
-                        _ => {
-                            return Err(VmError::TypeError(
-                                "__map_contains espera diccionario".into(),
-                            ))
-                        }
-                    }
-                } else if name == "__set_new" || name == "__conjunto_nuevo" {
-                    self.push(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                } else if name == "__set_add" || name == "__conjunto_agregar" {
-                    let mut it = args.into_iter();
-                    let s = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    let item = it.next().unwrap_or(Value::Void);
-                    match s {
-                        Value::Map(mut m) => {
-                            m.insert(item, Value::Bool(true));
-                            self.push(Value::Map(m));
-                        }
-                        _ => return Err(VmError::TypeError("__set_add espera conjunto".into())),
-                    }
-                } else if name == "__set_has" || name == "__conjunto_tiene" {
-                    let mut it = args.into_iter();
-                    let s = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    let item = it.next().unwrap_or(Value::Void);
-                    match s {
-                        Value::Map(m) => self.push(Value::Bool(m.contains_key(&item))),
-                        _ => return Err(VmError::TypeError("__set_has espera conjunto".into())),
-                    }
-                } else if name == "__set_union" || name == "__conjunto_unir" {
-                    let mut it = args.into_iter();
-                    let a = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    let b = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    match (a, b) {
-                        (Value::Map(mut m1), Value::Map(m2)) => {
-                            for (k, v) in m2 {
-                                if !m1.contains_key(&k) {
-                                    m1.insert(k, v);
-                                }
-                            }
-                            self.push(Value::Map(m1));
-                        }
-                        _ => return Err(VmError::TypeError("__set_union espera conjuntos".into())),
-                    }
-                } else if name == "__set_inter" || name == "__conjunto_interseccion" {
-                    let mut it = args.into_iter();
-                    let a = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    let b = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    match (a, b) {
-                        (Value::Map(m1), Value::Map(m2)) => {
-                            let r: ImMap<Value, Value, FixHasher> =
-                                m1.into_iter().filter(|(k, _)| m2.contains_key(k)).collect();
-                            self.push(Value::Map(r));
-                        }
-                        _ => return Err(VmError::TypeError("__set_inter espera conjuntos".into())),
-                    }
-                } else if name == "__set_diff" || name == "__conjunto_diferencia" {
-                    let mut it = args.into_iter();
-                    let a = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    let b = it
-                        .next()
-                        .unwrap_or(Value::Map(ImMap::with_hasher(FixHasher::default())));
-                    match (a, b) {
-                        (Value::Map(m1), Value::Map(m2)) => {
-                            let r: ImMap<Value, Value, FixHasher> = m1
-                                .into_iter()
-                                .filter(|(k, _)| !m2.contains_key(k))
-                                .collect();
-                            self.push(Value::Map(r));
-                        }
-                        _ => return Err(VmError::TypeError("__set_diff espera conjuntos".into())),
-                    }
-                } else if name == "__deque_new" || name == "__deque_nuevo" {
-                    self.push(Value::arr(vec![]));
-                } else if name == "__deque_push_front" || name == "__deque_agregar_frente" {
-                    let mut it = args.into_iter();
-                    let d = it.next().unwrap_or(Value::arr(vec![]));
-                    let item = it.next().unwrap_or(Value::Void);
-                    match d {
-                        Value::Array(mut v) => {
-                            Arc::make_mut(&mut v).insert(0, item);
-                            self.push(Value::Array(v));
-                        }
-                        _ => {
-                            return Err(VmError::TypeError(
-                                "__deque_push_front espera deque".into(),
-                            ))
-                        }
-                    }
-                } else if name == "__deque_push_back" || name == "__deque_agregar_final" {
-                    let mut it = args.into_iter();
-                    let d = it.next().unwrap_or(Value::arr(vec![]));
-                    let item = it.next().unwrap_or(Value::Void);
-                    match d {
-                        Value::Array(mut v) => {
-                            Arc::make_mut(&mut v).push(item);
-                            self.push(Value::Array(v));
-                        }
-                        _ => {
-                            return Err(VmError::TypeError("__deque_push_back espera deque".into()))
-                        }
-                    }
-                } else if name == "__deque_pop_front" || name == "__deque_quitar_frente" {
-                    let d = args.into_iter().next().unwrap_or(Value::arr(vec![]));
-                    match d {
-                        Value::Array(mut v) => self.push(if v.is_empty() {
-                            Value::Void
-                        } else {
-                            Arc::make_mut(&mut v).remove(0)
-                        }),
-                        _ => {
-                            return Err(VmError::TypeError("__deque_pop_front espera deque".into()))
-                        }
-                    }
-                } else if name == "__deque_pop_back" || name == "__deque_quitar_final" {
-                    let d = args.into_iter().next().unwrap_or(Value::arr(vec![]));
-                    match d {
-                        Value::Array(mut v) => self.push(if v.is_empty() {
-                            Value::Void
-                        } else {
-                            Arc::make_mut(&mut v).pop().unwrap_or(Value::Void)
-                        }),
-                        _ => {
-                            return Err(VmError::TypeError("__deque_pop_back espera deque".into()))
-                        }
-                    }
-                } else if name == "__deque_len" || name == "__deque_longitud" {
-                    let d = args.into_iter().next().unwrap_or(Value::arr(vec![]));
-                    match d {
-                        Value::Array(v) => self.push(Value::Int(v.len() as i64)),
-                        _ => return Err(VmError::TypeError("__deque_len espera deque".into())),
-                    }
-                } else if name == "__heap_new" || name == "__monticulo_nuevo" {
-                    self.push(Value::arr(vec![]));
-                } else if name == "__heap_push" || name == "__monticulo_agregar" {
-                    let mut it = args.into_iter();
-                    let h = it.next().unwrap_or(Value::arr(vec![]));
-                    let item = it.next().unwrap_or(Value::Void);
-                    match h {
-                        Value::Array(mut v) => {
-                            Arc::make_mut(&mut v).push(item);
-                            Arc::make_mut(&mut v).sort_by(|a, b| {
-                                let an = a.as_num().unwrap_or(f64::MIN);
-                                let bn = b.as_num().unwrap_or(f64::MIN);
-                                bn.partial_cmp(&an).unwrap_or(std::cmp::Ordering::Equal)
-                            });
-                            self.push(Value::Array(v));
-                        }
-                        _ => return Err(VmError::TypeError("__heap_push espera heap".into())),
-                    }
-                } else if name == "__heap_pop" || name == "__monticulo_quitar" {
-                    let h = args.into_iter().next().unwrap_or(Value::arr(vec![]));
-                    match h {
-                        Value::Array(mut v) => self.push(if v.is_empty() {
-                            Value::Void
-                        } else {
-                            Arc::make_mut(&mut v).remove(0)
-                        }),
-                        _ => return Err(VmError::TypeError("__heap_pop espera heap".into())),
-                    }
-                } else if name == "__heap_peek" || name == "__monticulo_ver" {
-                    let h = args.into_iter().next().unwrap_or(Value::arr(vec![]));
-                    match h {
-                        Value::Array(v) => self.push(if v.is_empty() {
-                            Value::Void
-                        } else {
-                            v[0].clone()
-                        }),
-                        _ => return Err(VmError::TypeError("__heap_peek espera heap".into())),
-                    }
-                } else if name == "__heap_len" || name == "__monticulo_longitud" {
-                    let h = args.into_iter().next().unwrap_or(Value::arr(vec![]));
-                    match h {
-                        Value::Array(v) => self.push(Value::Int(v.len() as i64)),
-                        _ => return Err(VmError::TypeError("__heap_len espera heap".into())),
-                    }
-                } else if name == "__linked_new" || name == "__enlazada_nuevo" {
-                    self.push(Value::arr(vec![]));
-                } else if name == "__linked_push_front" || name == "__enlazada_agregar_frente" {
-                    let mut it = args.into_iter();
-                    let l = it.next().unwrap_or(Value::arr(vec![]));
-                    let item = it.next().unwrap_or(Value::Void);
-                    match l {
-                        Value::Array(mut v) => {
-                            Arc::make_mut(&mut v).insert(0, item);
-                            self.push(Value::Array(v));
-                        }
-                        _ => {
-                            return Err(VmError::TypeError(
-                                "__linked_push_front espera linked".into(),
-                            ))
-                        }
-                    }
-                } else if name == "__linked_push_back" || name == "__enlazada_agregar_final" {
-                    let mut it = args.into_iter();
-                    let l = it.next().unwrap_or(Value::arr(vec![]));
-                    let item = it.next().unwrap_or(Value::Void);
-                    match l {
-                        Value::Array(mut v) => {
-                            Arc::make_mut(&mut v).push(item);
-                            self.push(Value::Array(v));
-                        }
-                        _ => {
-                            return Err(VmError::TypeError(
-                                "__linked_push_back espera linked".into(),
-                            ))
-                        }
-                    }
-                } else if name == "__linked_pop_front" || name == "__enlazada_quitar_frente" {
-                    let l = args.into_iter().next().unwrap_or(Value::arr(vec![]));
-                    match l {
-                        Value::Array(mut v) => self.push(if v.is_empty() {
-                            Value::Void
-                        } else {
-                            Arc::make_mut(&mut v).remove(0)
-                        }),
-                        _ => {
-                            return Err(VmError::TypeError(
-                                "__linked_pop_front espera linked".into(),
-                            ))
-                        }
-                    }
-                } else if name == "__linked_pop_back" || name == "__enlazada_quitar_final" {
-                    let l = args.into_iter().next().unwrap_or(Value::arr(vec![]));
-                    match l {
-                        Value::Array(mut v) => self.push(if v.is_empty() {
-                            Value::Void
-                        } else {
-                            Arc::make_mut(&mut v).pop().unwrap_or(Value::Void)
-                        }),
-                        _ => {
-                            return Err(VmError::TypeError(
-                                "__linked_pop_back espera linked".into(),
-                            ))
-                        }
-                    }
-                } else if name == "__linked_len" || name == "__enlazada_longitud" {
-                    let l = args.into_iter().next().unwrap_or(Value::arr(vec![]));
-                    match l {
-                        Value::Array(v) => self.push(Value::Int(v.len() as i64)),
-                        _ => return Err(VmError::TypeError("__linked_len espera linked".into())),
-                    }
-                } else if name == "__regex_new" || name == "__regex_nuevo" {
-                    let pat = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    match regex::Regex::new(&pat) {
-                        Ok(_) => self.push(Value::Bool(true)),
-                        Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-                    }
-                } else if name == "__regex_is_match" || name == "__regex_coincide" {
-                    let re_s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let text = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-                    match regex::Regex::new(&re_s) {
-                        Ok(r) => self.push(Value::Bool(r.is_match(&text))),
-                        Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-                    }
-                } else if name == "__regex_captures" || name == "__regex_capturar" {
-                    let re_s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let text = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-                    match regex::Regex::new(&re_s) {
-                        Ok(r) => {
-                            if let Some(caps) = r.captures(&text) {
-                                let vs: Vec<Value> = caps
-                                    .iter()
-                                    .map(|m| {
-                                        Value::str(
-                                            m.map(|x| x.as_str().to_string()).unwrap_or_default(),
-                                        )
-                                    })
-                                    .collect();
-                                self.push(Value::arr(vs));
-                            } else {
-                                self.push(Value::arr(vec![]));
-                            }
-                        }
-                        Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+    }
+
+    fn execute_with_idx(&mut self, op: Opcode, idx: usize) -> Result<(), VmError> {
+        match op {
+            Opcode::PushInt => {
+                let n = self.bytecode.ints.get(idx).copied().unwrap_or(0);
+                self.push(Value::Int(n));
+            }
+            Opcode::PushNum => {
+                let n = self.bytecode.nums.get(idx).copied().unwrap_or(0.0);
+                self.push(Value::Float(n));
+            }
+            Opcode::PushStr => {
+                let s = self.bytecode.strings.get(idx).cloned().unwrap_or_default();
+                self.push(Value::str(s));
+            }
+            Opcode::PushBool => {
+                self.push(Value::Bool(idx != 0));
+            }
+            Opcode::LoadLocal => {
+                let val = self
+                    .locals
+                    .last()
+                    .and_then(|frame| frame.slots.get(idx))
+                    .cloned()
+                    .unwrap_or(Value::Void);
+                self.push(val);
+            }
+            Opcode::StoreLocal => {
+                let val = self.pop()?;
+                if let Some(frame) = self.locals.last_mut() {
+                    if idx >= frame.slots.len() {
+                        frame.slots.resize(idx + 1, Value::Void);
                     }
-                } else if name == "__regex_replace" || name == "__regex_reemplazar" {
-                    let re_s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let text = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-                    let rep = args.get(2).map(|v| format!("{}", v)).unwrap_or_default();
-                    match regex::Regex::new(&re_s) {
-                        Ok(r) => {
-                            self.push(Value::str(r.replace_all(&text, rep.as_str()).to_string()))
-                        }
-                        Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+                    frame.slots[idx] = val;
+                }
+            }
+            Opcode::LoadGlobal => {
+                let val = self.load_global(idx)?;
+                self.push(val);
+            }
+            Opcode::StoreGlobal => {
+                let val = self.pop()?;
+                self.store_global(idx, val);
+            }
+            Opcode::LoadCell => {
+                let val = self
+                    .locals
+                    .last()
+                    .map(|frame| frame.get(idx))
+                    .unwrap_or(Value::Void);
+                self.push(val);
+            }
+            Opcode::StoreCell => {
+                let val = self.pop()?;
+                if let Some(frame) = self.locals.last_mut() {
+                    frame.set(idx, val);
+                }
+            }
+            Opcode::DropCell => {
+                if let Some(cell) = self
+                    .locals
+                    .last_mut()
+                    .and_then(|frame| frame.cells.get_mut(idx))
+                {
+                    *cell = None;
+                }
+            }
+            Opcode::Load => {
+                let val = match self.find_slot(idx) {
+                    Some((frame, slot)) => self.locals[frame].get(slot),
+                    None => self.load_global(idx)?,
+                };
+                self.push(val);
+            }
+            Opcode::Store => {
+                let val = self.pop()?;
+                match self.find_slot(idx) {
+                    Some((frame, slot)) => self.locals[frame].set(slot, val),
+                    None => self.store_global(idx, val),
+                }
+            }
+            Opcode::Call => {
+                let count = match self.call_counts.get_mut(idx) {
+                    Some(count) => {
+                        *count = count.saturating_add(1);
+                        *count as usize
                     }
-                } else if name == "__unicode_normalize" || name == "__unicode_normalizar" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let form = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-                    let nf: String = match form.as_str() {
-                        "NFC" => s.nfc().collect(),
-                        "NFD" => s.nfd().collect(),
-                        "NFKC" => s.nfkc().collect(),
-                        "NFKD" => s.nfkd().collect(),
-                        _ => s.nfc().collect(),
-                    };
-                    self.push(Value::str(nf));
-                } else if name == "__str_pad_start" || name == "__str_padding_inicio" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let len = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-                    let ch = args
-                        .get(2)
-                        .map(|v| format!("{}", v))
-                        .unwrap_or_default()
-                        .chars()
-                        .next()
-                        .unwrap_or(' ');
-                    self.push(Value::str(format!(
-                        "{}{}",
-                        ch.to_string().repeat(len.saturating_sub(s.len())),
-                        s
-                    )));
-                } else if name == "__str_pad_end" || name == "__str_padding_fin" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let len = args.get(1).and_then(|v| v.as_num()).unwrap_or(0.0) as usize;
-                    let ch = args
-                        .get(2)
-                        .map(|v| format!("{}", v))
-                        .unwrap_or_default()
-                        .chars()
-                        .next()
-                        .unwrap_or(' ');
-                    self.push(Value::str(format!(
-                        "{}{}",
-                        s,
-                        ch.to_string().repeat(len.saturating_sub(s.len()))
-                    )));
-                } else if name == "__encoding_utf8" || name == "__codificacion_utf8" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    self.push(Value::arr(
-                        s.bytes().map(|b| Value::Int(b as i64)).collect(),
-                    ));
-                } else if name == "__encoding_from_utf8" || name == "__desde_utf8" {
-                    let arr = args.into_iter().next().unwrap_or(Value::arr(vec![]));
-                    match arr {
-                        Value::Array(v) => {
-                            let bytes: Vec<u8> = v
-                                .iter()
-                                .filter_map(|x| {
-                                    if let Value::Int(n) = x {
-                                        Some(*n as u8)
-                                    } else {
-                                        None
-                                    }
-                                })
-                                .collect();
-                            self.push(Value::str(String::from_utf8_lossy(&bytes).to_string()));
-                        }
-                        _ => self.push(Value::str(String::new())),
+                    None => 0,
+                };
+                #[cfg(feature = "aot")]
+                let hot = count >= self.jit_threshold
+                    && !self.debug
+                    && self.breakpoints.is_empty()
+                    && self.budget.jit_allowed();
+                if count == self.jit_threshold && std::env::var_os("LUMEN_JIT_LOG").is_some() {
+                    eprintln!(
+                        "[jit] 🔥 Hot function detected: '{}' ({} llamadas) -> JIT Tier-1 activado",
+                        self.bytecode.names[idx], count
+                    );
+                }
+                let argc_idx = self.ip;
+                self.ip += 1;
+                let argc = if argc_idx < self.bytecode.instructions.len() {
+                    if let Instruction::WithIdx(_, nidx) = &self.bytecode.instructions[argc_idx] {
+                        self.bytecode.nums.get(*nidx).copied().unwrap_or(0.0) as usize
+                    } else {
+                        0
                     }
-                } else if name == "__buf_reader" || name == "__lector_buffer" {
-                    let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    match std::fs::read_to_string(&path) {
-                        Ok(c) => {
-                            let lines: Vec<Value> =
-                                c.lines().map(|l| Value::str(l.to_string())).collect();
-                            self.push(Value::arr(lines));
-                        }
-                        Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+                } else {
+                    0
+                };
+                let mut args: Vec<Value> = Vec::new();
+                for _ in 0..argc {
+                    args.push(self.pop()?);
+                }
+                args.reverse();
+                let target = self.call_targets.get(idx).copied().unwrap_or_default();
+                if let Some(id) = target.builtin {
+                    #[cfg(all(
+                        any(feature = "extra", feature = "full"),
+                        not(target_arch = "wasm32")
+                    ))]
+                    {
+                        self.call_ip = argc_idx - 1;
                     }
-                } else if name == "__buf_writer" || name == "__escritor_buffer" {
-                    let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let content = args.get(1).map(|v| format!("{}", v)).unwrap_or_default();
-                    match std::fs::write(&path, &content) {
-                        Ok(_) => self.push(Value::Exito(Box::new(Value::Bool(true)))),
-                        Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+                    return self.call_builtin(id, args);
+                }
+                if let Some(func_idx) = target.func {
+                    if self.call_stack.len() >= MAX_CALL_STACK_DEPTH {
+                        return Err(VmError::Runtime(format!(
+                            "Desbordamiento de pila (Stack overflow): límite de recursión excedido (>{} llamadas)",
+                            MAX_CALL_STACK_DEPTH
+                        )));
                     }
-                } else if name == "__stream_chunks" || name == "__stream_trozos" {
-                    let path = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    let size = args.get(1).and_then(|v| v.as_num()).unwrap_or(4096.0) as usize;
-                    match std::fs::read(&path) {
-                        Ok(data) => {
-                            let chunks: Vec<Value> = data
-                                .chunks(size)
-                                .map(|c| {
-                                    Value::arr(c.iter().map(|&b| Value::Int(b as i64)).collect())
-                                })
-                                .collect();
-                            self.push(Value::arr(chunks));
+                    #[cfg(feature = "aot")]
+                    self.mark_user_func(func_idx);
+                    #[cfg(feature = "aot")]
+                    if hot {
+                        if let Some(result) = self.call_jit(func_idx, &args) {
+                            self.push(result?);
+                            return Ok(());
                         }
-                        Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-                    }
-                } else if name == "__tcp_connect" || name == "__tcp_conectar" {
-                    #[allow(unused_variables)]
-                    let addr = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    match std::net::TcpStream::connect(&addr) {
-                        Ok(_) => self.push(Value::Bool(true)),
-                        Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
                     }
-                } else if name == "__tcp_listen" || name == "__tcp_escuchar" {
-                    #[allow(unused_variables)]
-                    let addr = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    match std::net::TcpListener::bind(&addr) {
-                        Ok(l) => {
-                            self.tcp_listener = Some(l);
-                            self.push(Value::Bool(true));
-                        }
-                        Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+                    let func = &self.bytecode.funcs[func_idx];
+                    let func_start = func.start;
+                    self.call_stack.push(CallFrame {
+                        func_name: func.name.clone(),
+                        return_ip: self.ip,
+                        defers: DeferStack::default(),
+                    });
+                    let scope = self.new_frame(func_idx, args);
+                    self.locals.push(scope);
+                    self.ip = func_start;
+                } else {
+                    let name = self.bytecode.names.get(idx).cloned().unwrap_or_default();
+                    return Err(VmError::UndefinedFunction(name));
+                }
+            }
+            Opcode::FuncRef => {
+                let name = self.bytecode.strings.get(idx).cloned().unwrap_or_default();
+                self.push(Value::Func(name));
+            }
+            Opcode::MakeClosure => {
+                let func = self.bytecode.strings.get(idx).cloned().unwrap_or_default();
+                let count = match self.bytecode.instructions.get(self.ip) {
+                    Some(Instruction::WithIdx(_, n)) => {
+                        self.bytecode.nums.get(*n).copied().unwrap_or(0.0) as usize
                     }
-                } else if name == "__tcp_accept" || name == "__tcp_aceptar" {
-                    match &self.tcp_listener {
-                        Some(l) => match l.accept() {
-                            Ok((_stream, _)) => {
-                                let addr = _stream
-                                    .peer_addr()
-                                    .map(|a| a.to_string())
-                                    .unwrap_or_default();
-                                self.push(Value::str(addr));
-                            }
-                            Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
-                        },
-                        None => self.push(Value::Error(Box::new(Value::str("Sin listener")))),
+                    _ => 0,
+                };
+                let slots: Vec<usize> = (1..=count)
+                    .map(|i| match self.bytecode.instructions.get(self.ip + i) {
+                        Some(Instruction::WithIdx(_, slot)) => *slot,
+                        _ => 0,
+                    })
+                    .collect();
+                self.ip += 1 + count;
+                let captured = match self.locals.last_mut() {
+                    Some(frame) => slots.into_iter().map(|s| frame.capture(s)).collect(),
+                    None => Vec::new(),
+                };
+                self.push(Value::Closure { func, captured });
+            }
+            Opcode::CallValue => {
+                let argc = self.bytecode.nums.get(idx).copied().unwrap_or(0.0) as usize;
+                let mut args = Vec::new();
+                for _ in 0..argc {
+                    args.push(self.pop()?);
+                }
+                args.reverse();
+                let callee = self.pop()?;
+                let (name, captured) = match callee {
+                    Value::Func(n) => (n, Vec::new()),
+                    Value::Closure { func, captured } => (func, captured),
+                    _ => {
+                        return Err(VmError::TypeError(
+                            "Se esperaba una función para llamar".to_string(),
+                        ))
                     }
-                } else if name == "__http_server" || name == "__http_servidor" {
-                    #[allow(unused_variables)]
-                    let addr = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    self.push(Value::str(format!("HTTP server on {}", addr)));
-                } else if name == "__serial_open" || name == "__serial_abrir" {
-                    self.push(Value::Bool(true));
-                } else if name == "__json_parse" || name == "__json_parsear" {
-                    let s = args.first().map(|v| format!("{}", v)).unwrap_or_default();
-                    match serde_json::from_str::<serde_json::Value>(&s) {
-                        Ok(v) => self.push(json_value_to_lumen(v)),
-                        Err(e) => self.push(Value::Error(Box::new(Value::str(e.to_string())))),
+                };
+                if let Some(id) = self.builtins.lookup(&name) {
+                    // Sin una llamada por nombre que repetir, el builtin espera
+                    // sin aparcar la fibra.
+                    #[cfg(all(
+                        any(feature = "extra", feature = "full"),
+                        not(target_arch = "wasm32")
+                    ))]
+                    let slice_end = self.slice_end.take();
+                    let result = self.call_builtin(id, args);
+                    #[cfg(all(
+                        any(feature = "extra", feature = "full"),
+                        not(target_arch = "wasm32")
+                    ))]
+                    {
+                        self.slice_end = slice_end;
                     }
-                } else if name == "__json_stringify" || name == "__json_texto" {
-                    let val = args.first().cloned().unwrap_or(Value::Void);
-                    let json = lumen_value_to_json(&val);
-                    self.push(Value::str(serde_json::to_string(&json).unwrap_or_default()));
-                } else if let Some(&func_idx) = self.func_index_cache.get(&name) {
+                    return result;
+                }
+                if let Some(&func_idx) = self.func_index_cache.get(&name) {
                     if self.call_stack.len() >= MAX_CALL_STACK_DEPTH {
                         return Err(VmError::Runtime(format!(
                             "Desbordamiento de pila (Stack overflow): límite de recursión excedido (>{} llamadas)",
@@ -4970,30 +7575,12 @@ impl VM {
                         )));
                     }
                     let func_start = self.bytecode.funcs[func_idx].start;
-                    let param_count = self.bytecode.funcs[func_idx].params.len();
                     self.call_stack.push(CallFrame {
                         func_name: name,
                         return_ip: self.ip,
+                        defers: DeferStack::default(),
                     });
-                    let mut scope =
-                        HashMap::with_capacity_and_hasher(param_count, FixHasher::default());
-                    for i in 0..param_count {
-                        let param_name = self.bytecode.funcs[func_idx].params[i].clone();
-                        let arg = if i < args.len() {
-                            args[i].clone()
-                        } else if let Some(Some(dv)) = self.bytecode.funcs[func_idx].defaults.get(i)
-                        {
-                            match dv {
-                                DefaultValue::Int(v) => Value::Int(*v),
-                                DefaultValue::Float(v) => Value::Float(*v),
-                                DefaultValue::Str(s) => Value::str(s.clone()),
-                                DefaultValue::Bool(b) => Value::Bool(*b),
-                            }
-                        } else {
-                            Value::Void
-                        };
-                        scope.insert(param_name, arg);
-                    }
+                    let scope = self.closure_frame(func_idx, args, &captured);
                     self.locals.push(scope);
                     self.ip = func_start;
                 } else {
@@ -5052,8 +7639,42 @@ impl VM {
                 let target = self.bytecode.nums.get(idx).copied().unwrap_or(0.0) as usize;
                 self.ip = target;
             }
+            Opcode::TryBegin => {
+                let catch_ip = self.bytecode.nums.get(idx).copied().unwrap_or(0.0) as usize;
+                let defers = self.current_defers();
+                let defer_resume_len = defers.resume.len();
+                let defer_pending_len = defers.pending.len();
+                self.handlers.push(TryHandler {
+                    catch_ip,
+                    call_depth: self.call_stack.len(),
+                    locals_len: self.locals.len(),
+                    stack_len: self.stack.len(),
+                    defer_resume_len,
+                    defer_pending_len,
+                });
+            }
+            Opcode::DeferPush => {
+                let target = self.bytecode.nums.get(idx).copied().unwrap_or(0.0) as usize;
+                self.current_defers().pending.push(target);
+            }
+            Opcode::DeferRun => {
+                // Despacha el `posponer` más reciente y se re-ejecuta al volver
+                // (DeferEnd) hasta que solo queden `depth` pendientes.
+                let depth = self.bytecode.nums.get(idx).copied().unwrap_or(0.0) as usize;
+                let run_ip = self.ip - 1;
+                let defers = self.current_defers();
+                if defers.pending.len() > depth {
+                    if let Some(target) = defers.pending.pop() {
+                        defers.resume.push(run_ip);
+                        self.ip = target;
+                    }
+                }
+            }
             Opcode::JmpIf => {
                 let val = self.pop()?;
+                if let Some(coverage) = self.coverage.as_mut() {
+                    coverage.insert((self.ip - 1, val.is_truthy()));
+                }
                 if !val.is_truthy() {
                     let target = self.bytecode.nums.get(idx).copied().unwrap_or(0.0) as usize;
                     self.ip = target;
@@ -5152,13 +7773,68 @@ impl VM {
         self.stack.pop().ok_or(VmError::StackUnderflow)
     }
 
-    fn lookup(&self, name: &str) -> Result<Value, VmError> {
-        for scope in self.locals.iter().rev() {
-            if let Some(val) = scope.get(name) {
-                return Ok(val.clone());
-            }
+    /// Crea el frame de una llamada a `func_idx`: los argumentos (o sus
+    /// valores por defecto) ocupan los primeros slots y el resto empieza vacío.
+    fn new_frame(&self, func_idx: usize, args: Vec<Value>) -> LocalFrame {
+        let meta = &self.bytecode.funcs[func_idx];
+        let mut slots = args;
+        slots.truncate(meta.params.len());
+        for i in slots.len()..meta.params.len() {
+            slots.push(match meta.defaults.get(i) {
+                Some(Some(DefaultValue::Int(v))) => Value::Int(*v),
+                Some(Some(DefaultValue::Float(v))) => Value::Float(*v),
+                Some(Some(DefaultValue::Str(s))) => Value::str(s.clone()),
+                Some(Some(DefaultValue::Bool(b))) => Value::Bool(*b),
+                _ => Value::Void,
+            });
+        }
+        slots.resize(slot_names(meta).len().max(slots.len()), Value::Void);
+        LocalFrame {
+            func: Some(func_idx),
+            slots,
+            cells: Vec::new(),
+        }
+    }
+
+    /// Frame de una llamada a una clausura: las celdas capturadas ocupan los
+    /// slots que siguen a los parámetros.
+    fn closure_frame(&self, func_idx: usize, args: Vec<Value>, captured: &[Upvalue]) -> LocalFrame {
+        let mut frame = self.new_frame(func_idx, args);
+        if !captured.is_empty() {
+            let first = self.bytecode.funcs[func_idx].params.len();
+            frame.cells = vec![None; first];
+            frame.cells.extend(captured.iter().cloned().map(Some));
+        }
+        frame
+    }
+
+    /// Resuelve por nombre un `Load`/`Store` sin slot asignado (chunks
+    /// generados fuera de `codegen`): busca en los frames activos, del más
+    /// reciente al más antiguo.
+    fn find_slot(&self, name_idx: usize) -> Option<(usize, usize)> {
+        let name = self.bytecode.names.get(name_idx)?;
+        self.locals.iter().enumerate().rev().find_map(|(i, frame)| {
+            let meta = self.bytecode.funcs.get(frame.func?)?;
+            let slot = slot_names(meta).iter().position(|n| n == name)?;
+            (slot < frame.slots.len()).then_some((i, slot))
+        })
+    }
+
+    fn load_global(&self, idx: usize) -> Result<Value, VmError> {
+        match self.globals.get(idx) {
+            Some(Some(val)) => Ok(val.clone()),
+            _ => Err(VmError::UndefinedVariable(
+                self.bytecode.names.get(idx).cloned().unwrap_or_default(),
+            )),
+        }
+    }
+
+    fn store_global(&mut self, idx: usize, val: Value) {
+        let globals = Arc::make_mut(&mut self.globals);
+        if idx >= globals.len() {
+            globals.resize(idx + 1, None);
         }
-        Err(VmError::UndefinedVariable(name.to_string()))
+        globals[idx] = Some(val);
     }
 
     fn codegen_to_nvc(&self, cg: Value) -> Result<Value, VmError> {
@@ -5459,7 +8135,7 @@ impl VM {
         buf.extend_from_slice(b"LUMN");
 
         // Version
-        buf.extend_from_slice(&6u32.to_le_bytes());
+        buf.extend_from_slice(&CHUNK_VERSION.to_le_bytes());
 
         // Strings table
         buf.extend_from_slice(&(str_cnt as u32).to_le_bytes());
@@ -5495,13 +8171,34 @@ impl VM {
             Some(Value::Map(m)) => m,
             _ => ImMap::with_hasher(FixHasher::default()),
         };
-        let mut func_bytes: Vec<u8> = Vec::new();
-        func_bytes.extend_from_slice(&(func_cnt as u32).to_le_bytes());
-        for fi in 0..func_cnt {
-            let f = match funcs_map.get(&Value::str(fi.to_string())) {
+        let func_maps: Vec<_> = (0..func_cnt)
+            .map(|fi| match funcs_map.get(&Value::str(fi.to_string())) {
                 Some(Value::Map(m)) => m.clone(),
                 _ => ImMap::with_hasher(FixHasher::default()),
-            };
+            })
+            .collect();
+        let starts: Vec<usize> = func_maps
+            .iter()
+            .map(|f| match f.get(&Value::str("start")) {
+                Some(Value::Int(n)) => *n as usize,
+                Some(Value::Float(n)) => *n as usize,
+                _ => 0,
+            })
+            .collect();
+        // Las variables asignadas antes de la primera función son globales; el
+        // resto de asignaciones dentro de una función ocupan slots de su frame.
+        let stores_in = |from: usize, to: usize| -> Vec<String> {
+            (from..to.min(pc))
+                .filter_map(|ip| match get_instr(ip) {
+                    Some((20, arg)) => Some(get_str(arg as usize)),
+                    _ => None,
+                })
+                .collect()
+        };
+        let globals = stores_in(0, starts.iter().copied().min().unwrap_or(pc));
+        let mut func_bytes: Vec<u8> = Vec::new();
+        func_bytes.extend_from_slice(&(func_cnt as u32).to_le_bytes());
+        for (f, &start) in func_maps.iter().zip(&starts) {
             let fname = match f.get(&Value::str("nombre")) {
                 Some(Value::Str(s)) => s.to_string(),
                 _ => String::new(),
@@ -5517,6 +8214,7 @@ impl VM {
                 _ => 0,
             };
             func_bytes.extend_from_slice(&(pcount as u32).to_le_bytes());
+            let mut locals = Vec::with_capacity(pcount);
             for pi in 0..pcount {
                 let pname = match fparams.get(&Value::str(pi.to_string())) {
                     Some(Value::Str(s)) => s.to_string(),
@@ -5524,13 +8222,22 @@ impl VM {
                 };
                 func_bytes.extend_from_slice(&(pname.len() as u32).to_le_bytes());
                 func_bytes.extend_from_slice(pname.as_bytes());
+                locals.push(pname);
             }
-            let fstart = match f.get(&Value::str("start")) {
-                Some(Value::Int(n)) => *n as u64,
-                Some(Value::Float(n)) => *n as u64,
-                _ => 0,
-            };
-            func_bytes.extend_from_slice(&fstart.to_le_bytes());
+            // Sin valores por defecto
+            func_bytes.extend_from_slice(&0u32.to_le_bytes());
+            let end = starts.iter().copied().filter(|&s| s > start).min();
+            for name in stores_in(start, end.unwrap_or(pc)) {
+                if !globals.contains(&name) && !locals.contains(&name) {
+                    locals.push(name);
+                }
+            }
+            func_bytes.extend_from_slice(&(locals.len() as u32).to_le_bytes());
+            for name in &locals {
+                func_bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                func_bytes.extend_from_slice(name.as_bytes());
+            }
+            func_bytes.extend_from_slice(&(start as u64).to_le_bytes());
         }
         buf.extend_from_slice(&func_bytes);
 
@@ -5540,6 +8247,9 @@ impl VM {
         // Instructions
         buf.extend_from_slice(&instr_bytes);
 
+        // Tabla de líneas: el codegen autoalojado no registra posiciones
+        buf.extend_from_slice(&0u32.to_le_bytes());
+
         // Return as Array<Int>
         let result: Vec<Value> = buf.iter().map(|&b| Value::Int(b as i64)).collect();
         Ok(Value::arr(result))
@@ -5640,6 +8350,30 @@ fn ffi_call_dynamic(
     }
 }
 
+/// Largo en bytes pedido a un builtin de crypto: 32 si no se pasa; un valor
+/// que no es entero o queda fuera de `min..=max` es un error, no una reserva.
+#[cfg(any(feature = "extra", feature = "full"))]
+fn byte_len_arg(arg: Option<&Value>, min: usize, max: usize, what: &str) -> Result<usize, String> {
+    let n = match arg {
+        None | Some(Value::Void) => return Ok(32),
+        Some(Value::Int(n)) => *n,
+        Some(Value::Float(f)) if f.fract() == 0.0 => *f as i64,
+        Some(other) => {
+            return Err(format!(
+                "{}: el largo debe ser un entero, no {}",
+                what, other
+            ))
+        }
+    };
+    if n < min as i64 || n > max as i64 {
+        return Err(format!(
+            "{}: el largo debe estar entre {} y {} bytes, se pidieron {}",
+            what, min, max, n
+        ));
+    }
+    Ok(n as usize)
+}
+
 // ── JWT helpers ─────────────────────────────────────────────────────────
 #[cfg(any(feature = "extra", feature = "full"))]
 fn base64url_encode(data: &[u8]) -> String {
@@ -5664,15 +8398,6 @@ fn base64url_decode_to_string(data: &str) -> Result<String, String> {
     String::from_utf8(bytes).map_err(|e| format!("UTF-8 error: {}", e))
 }
 
-#[cfg(any(feature = "extra", feature = "full"))]
-fn hmac_sha256(data: &[u8], key: &[u8]) -> Vec<u8> {
-    use hmac::{Hmac, Mac};
-    type HmacSha256 = Hmac<sha2::Sha256>;
-    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC key length OK");
-    mac.update(data);
-    mac.finalize().into_bytes().to_vec()
-}
-
 // ── Date helpers ─────────────────────────────────
 #[cfg_attr(not(feature = "full"), allow(dead_code))]
 fn format_timestamp(timestamp: i64, fmt: &str) -> String {
@@ -5871,6 +8596,7 @@ mod tests {
             nums: vec![],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         }
     }
 
@@ -5893,6 +8619,7 @@ mod tests {
             nums: vec![42.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -5912,6 +8639,7 @@ mod tests {
             nums: vec![2.0, 3.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -5930,6 +8658,7 @@ mod tests {
             nums: vec![],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -5950,6 +8679,7 @@ mod tests {
             nums: vec![1.0, 0.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_err());
@@ -5970,6 +8700,7 @@ mod tests {
             nums: vec![42.0],
             names: vec!["x".to_string()],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -5991,6 +8722,7 @@ mod tests {
             nums: vec![1.0, 2.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6012,6 +8744,7 @@ mod tests {
             nums: vec![4.0, 42.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6031,6 +8764,7 @@ mod tests {
             nums: vec![42.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6051,6 +8785,7 @@ mod tests {
             nums: vec![],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6071,6 +8806,7 @@ mod tests {
             nums: vec![3.0, 42.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6093,10 +8829,141 @@ mod tests {
             nums: vec![4.0, 42.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
+        };
+        let mut vm = VM::new(bc);
+        assert!(vm.run().is_ok());
+        assert_eq!(vm.output(), &["42"]);
+    }
+
+    #[test]
+    fn test_cobertura_de_ramas_y_reset() {
+        let bc = Bytecode {
+            instructions: vec![
+                Instruction::WithBool(Opcode::PushBool, true),
+                Instruction::WithIdx(Opcode::JmpIf, 0),
+                Instruction::WithIdx(Opcode::PushNum, 1),
+                Instruction::Simple(Opcode::Print),
+                Instruction::Simple(Opcode::Halt),
+            ],
+            strings: vec![],
+            ints: vec![],
+            nums: vec![4.0, 42.0],
+            names: vec![],
+            funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
+        assert!(vm.coverage().is_none());
+        vm.enable_coverage();
+        assert!(vm.run().is_ok());
+        let seen: Vec<_> = vm.coverage().unwrap().iter().copied().collect();
+        assert_eq!(seen, vec![(1, true)]);
+
+        vm.reset();
+        assert!(vm.coverage().unwrap().is_empty());
+        assert!(vm.output().is_empty());
         assert!(vm.run().is_ok());
         assert_eq!(vm.output(), &["42"]);
+        assert_eq!(vm.coverage().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_limite_de_instrucciones() {
+        let bc = Bytecode {
+            instructions: vec![Instruction::WithIdx(Opcode::Jmp, 0)],
+            strings: vec![],
+            ints: vec![],
+            nums: vec![0.0],
+            names: vec![],
+            funcs: vec![],
+            lines: Default::default(),
+        };
+        let mut vm = VM::new(bc);
+        vm.set_limits(VmLimits {
+            max_instructions: Some(1000),
+            ..Default::default()
+        });
+        match vm.run() {
+            Err(VmError::Sandbox(SandboxViolation::Instructions(1000))) => {}
+            other => panic!("se esperaba el límite de instrucciones: {:?}", other),
+        }
+        // `reset` rellena el combustible.
+        vm.reset();
+        assert!(matches!(vm.run(), Err(VmError::Sandbox(_))));
+        assert_eq!(vm.instr_count, 1001);
+    }
+
+    /// Bucle infinito que empuja `strings[0]`, llamando antes a `names[0]`
+    /// con él si `call` es verdadero.
+    fn bucle_infinito(name: &str, call: bool) -> Bytecode {
+        let mut instructions = vec![Instruction::WithIdx(Opcode::PushStr, 0)];
+        if call {
+            instructions.push(Instruction::WithIdx(Opcode::Call, 0));
+            instructions.push(Instruction::WithIdx(Opcode::Nop, 0));
+        }
+        instructions.push(Instruction::WithIdx(Opcode::Jmp, 1));
+        Bytecode {
+            instructions,
+            strings: vec!["/tmp/lumen_sandbox.txt".to_string()],
+            ints: vec![],
+            nums: vec![1.0, 0.0],
+            names: vec![name.to_string()],
+            funcs: vec![],
+            lines: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_limites_de_tiempo_memoria_y_salida() {
+        let mut vm = VM::new(bucle_infinito("x", false));
+        vm.set_limits(VmLimits {
+            timeout: Some(std::time::Duration::from_millis(50)),
+            ..Default::default()
+        });
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::Sandbox(SandboxViolation::Timeout(_)))
+        ));
+
+        let mut vm = VM::new(bucle_infinito("x", false));
+        vm.set_limits(VmLimits {
+            max_memory: Some(1 << 20),
+            ..Default::default()
+        });
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::Sandbox(SandboxViolation::Memory(_)))
+        ));
+        assert!(vm.stack.len() < 1 << 20);
+
+        let mut vm = VM::new(bucle_infinito("imprimir", true));
+        vm.set_limits(VmLimits {
+            max_output_lines: Some(10),
+            ..Default::default()
+        });
+        assert!(matches!(
+            vm.run(),
+            Err(VmError::Sandbox(SandboxViolation::Output(10)))
+        ));
+        assert_eq!(vm.output().len(), 10);
+    }
+
+    #[test]
+    fn test_permiso_denegado_no_se_captura() {
+        let mut vm = VM::new(bucle_infinito("__escribir_archivo", true));
+        vm.set_limits(VmLimits {
+            capabilities: crate::Capabilities::none().allow_read("/tmp"),
+            ..Default::default()
+        });
+        match vm.run() {
+            Err(VmError::Sandbox(SandboxViolation::Denied { builtin, path, .. })) => {
+                assert_eq!(builtin, "__file_write");
+                assert_eq!(path.as_deref(), Some("/tmp/lumen_sandbox.txt"));
+            }
+            other => panic!("se esperaba un permiso denegado: {:?}", other),
+        }
+        assert!(vm.output().is_empty());
     }
 
     #[test]
@@ -6113,6 +8980,7 @@ mod tests {
             nums: vec![1.0],
             names: vec!["imprimir".to_string()],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6134,6 +9002,7 @@ mod tests {
             nums: vec![0.0, 1.0],
             names: vec!["leer".to_string(), "imprimir".to_string()],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         // leer pushes empty string, then imprimir prints it
@@ -6167,15 +9036,18 @@ mod tests {
                     name: "__main__".to_string(),
                     params: vec![],
                     defaults: vec![],
+                    locals: vec![],
                     start: 0,
                 },
                 FuncMeta {
                     name: "sum".to_string(),
                     params: vec!["a".to_string(), "b".to_string()],
                     defaults: vec![None, None],
+                    locals: vec![],
                     start: 6,
                 },
             ],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6198,8 +9070,10 @@ mod tests {
                 name: "__main__".to_string(),
                 params: vec![],
                 defaults: vec![],
+                locals: vec![],
                 start: 0,
             }],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         let result = vm.run();
@@ -6227,8 +9101,10 @@ mod tests {
                 name: "__main__".to_string(),
                 params: vec![],
                 defaults: vec![],
+                locals: vec![],
                 start: 0,
             }],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         // Ret without call should just push the value and continue (no call_stack to pop)
@@ -6250,6 +9126,7 @@ mod tests {
             nums: vec![6.0, 7.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6271,6 +9148,7 @@ mod tests {
             nums: vec![10.0, 3.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6292,6 +9170,7 @@ mod tests {
             nums: vec![10.0, 2.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6313,6 +9192,7 @@ mod tests {
             nums: vec![5.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6334,6 +9214,7 @@ mod tests {
             nums: vec![5.0, 3.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6355,6 +9236,7 @@ mod tests {
             nums: vec![],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6376,6 +9258,7 @@ mod tests {
             nums: vec![],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6397,6 +9280,7 @@ mod tests {
             nums: vec![1.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6417,6 +9301,7 @@ mod tests {
             nums: vec![],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_err());
@@ -6436,6 +9321,7 @@ mod tests {
             nums: vec![],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_err());
@@ -6456,6 +9342,7 @@ mod tests {
             nums: vec![5.0, 3.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6477,6 +9364,7 @@ mod tests {
             nums: vec![3.0, 5.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6498,6 +9386,7 @@ mod tests {
             nums: vec![5.0, 3.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6519,6 +9408,7 @@ mod tests {
             nums: vec![],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
@@ -6540,9 +9430,94 @@ mod tests {
             nums: vec![3.0, 7.0],
             names: vec![],
             funcs: vec![],
+            lines: Default::default(),
         };
         let mut vm = VM::new(bc);
         assert!(vm.run().is_ok());
         assert_eq!(vm.output(), &["-4"]);
     }
+
+    fn doble(_vm: &mut VM, args: &[Value]) -> Result<Value, VmError> {
+        match args[0] {
+            Value::Int(n) => Ok(Value::Int(n * 2)),
+            _ => Err(VmError::TypeError("'doble' espera un entero".to_string())),
+        }
+    }
+
+    /// `imprimir(doble(21))`, con `doble` llamado con `argc` argumentos.
+    fn call_doble(argc: f64) -> Bytecode {
+        Bytecode {
+            instructions: vec![
+                Instruction::WithIdx(Opcode::PushInt, 0),
+                Instruction::WithIdx(Opcode::PushInt, 0),
+                Instruction::WithIdx(Opcode::Call, 1),
+                Instruction::WithIdx(Opcode::Nop, 1),
+                Instruction::WithIdx(Opcode::Call, 0),
+                Instruction::WithIdx(Opcode::Nop, 0),
+                Instruction::Simple(Opcode::Halt),
+            ],
+            strings: vec![],
+            ints: vec![21],
+            nums: vec![1.0, argc],
+            names: vec!["imprimir".to_string(), "double".to_string()],
+            funcs: vec![],
+            lines: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_registro_builtin_nativo() {
+        let mut vm = VM::new(call_doble(1.0));
+        vm.register_builtin(&["doble", "double"], Some(1), "Duplica un entero.", doble);
+        assert!(vm.run().is_ok());
+        assert_eq!(vm.output(), &["42"]);
+        let b = vm.builtins().find("double").unwrap();
+        assert_eq!((b.name.as_str(), b.arity), ("doble", Some(1)));
+        assert_eq!(b.doc, "Duplica un entero.");
+    }
+
+    #[test]
+    fn test_registro_closure_del_anfitrion() {
+        use std::sync::atomic::{AtomicI64, Ordering};
+        let llamadas = Arc::new(AtomicI64::new(0));
+        let contador = llamadas.clone();
+        let mut vm = VM::new(call_doble(1.0));
+        vm.register_host_fn(
+            &["doble", "double"],
+            Some(1),
+            "",
+            Arc::new(move |args: &[Value]| {
+                contador.fetch_add(1, Ordering::SeqCst);
+                match args {
+                    [Value::Int(n)] => Ok(Value::Int(n * 2)),
+                    _ => Err(VmError::Runtime("doble espera un entero".to_string())),
+                }
+            }),
+        );
+        assert!(vm.run().is_ok());
+        assert_eq!(vm.output(), &["42"]);
+        assert_eq!(llamadas.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_registro_builtin_aridad() {
+        let mut vm = VM::new(call_doble(2.0));
+        vm.register_builtin(&["doble", "double"], Some(1), "", doble);
+        match vm.run() {
+            Err(VmError::Runtime(msg)) => assert!(msg.contains("espera 1 argumentos"), "{}", msg),
+            other => panic!("se esperaba error de aridad: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_registro_builtins_con_alias() {
+        let vm = VM::new(make_bc(vec![]));
+        let registry = vm.builtins();
+        assert_eq!(registry.lookup("imprimir"), registry.lookup("print"));
+        assert_eq!(registry.lookup("raiz"), registry.lookup("sqrt"));
+        assert!(registry.lookup("no_existe").is_none());
+        assert!(registry
+            .iter()
+            .any(|b| b.name == "largo" && b.aliases == ["len"]));
+    }
 }
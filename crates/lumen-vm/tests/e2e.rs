@@ -75,6 +75,23 @@ si (edad >= 18) {
     assert_eq!(output, vec!["Eres mayor de edad"]);
 }
 
+#[test]
+fn test_identificadores_en_espanol() {
+    // `tamaño` se declara con la `ñ` precompuesta y se usa escrito como
+    // `n` + tilde combinante: es la misma variable.
+    let src = "estructura Canción { título: texto, año: entero }
+funcion entero tamaño(lista<entero> números) {
+    retornar números.largo();
+}
+Canción c = Canción { título: \"Cielito lindo\", año: 1882 };
+entero tamaño_total = tamaño([1, 2, 3]);
+imprimir(c.título);
+imprimir(c.año);
+imprimir(taman\u{303}o_total);";
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["Cielito lindo", "1882", "3"]);
+}
+
 #[test]
 fn test_lexical_error() {
     let result = run_source("let @x = 1;");
@@ -1574,31 +1591,23 @@ imprimir(__str_ord("ABC"));"#;
 fn test_hash_sha256() {
     let src = r#"imprimir(__hash_sha256("hola"));"#;
     let output = run_source(src).unwrap();
-    // SHA-256 produces 32 bytes => 64 hex chars
-    let hex = &output[0];
-    if hex != "error(Bcrypt no disponible)" {
-        assert_eq!(hex.len(), 64, "SHA-256 hex debe tener 64 caracteres");
-        assert!(
-            hex.chars().all(|c| c.is_ascii_hexdigit()),
-            "SHA-256 debe ser hexadecimal"
-        );
-    }
-    // else: bcrypt no disponible, test pasa sin aserción fuerte
+    assert_eq!(
+        output,
+        vec!["b221d9dbb083a7f33428d7c2a3c3198ae925614d70210e28716ccaa7cd4ddb79"]
+    );
 }
 
 #[test]
 fn test_hash_sha512() {
     let src = r#"imprimir(__hash_sha512("hola"));"#;
     let output = run_source(src).unwrap();
-    // SHA-512 produces 64 bytes => 128 hex chars
-    let hex = &output[0];
-    if hex != "error(Bcrypt no disponible)" {
-        assert_eq!(hex.len(), 128, "SHA-512 hex debe tener 128 caracteres");
-        assert!(
-            hex.chars().all(|c| c.is_ascii_hexdigit()),
-            "SHA-512 debe ser hexadecimal"
-        );
-    }
+    assert_eq!(
+        output,
+        vec![
+            "e83e8535d6f689493e5819bd60aa3e5fdcba940e6d111ab6fb5c34f24f86496b\
+             f3726e2bf4ec59d6d2f5a2aeb1e4f103283e7d64e4f49c03b4c4725cb361e773"
+        ]
+    );
 }
 
 #[test]
@@ -1635,6 +1644,28 @@ imprimir(__jwt_decodificar(__jwt_codificar("{\"sub\":\"123\"}", "secreto"), "sec
     assert_eq!(output[1], "{\"sub\":\"123\"}");
 }
 
+#[test]
+fn test_jwt_vector_conocido() {
+    // Token de ejemplo de RFC 7519 / jwt.io firmado con HS256.
+    let src = r#"texto claims = "{\"sub\":\"1234567890\",\"name\":\"John Doe\",\"iat\":1516239022}";
+texto token = __jwt_codificar(claims, "your-256-bit-secret");
+imprimir(token);
+imprimir(__jwt_decodificar(token, "your-256-bit-secret"));
+imprimir(__jwt_decodificar(token, "otro-secreto"));"#;
+    let output = run_source(src).unwrap();
+    assert_eq!(
+        output[0],
+        "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+         eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiaWF0IjoxNTE2MjM5MDIyfQ.\
+         SflKxwRJSMeKKF2QT4fwpMeJf36POk6yJV_adQssw5c"
+    );
+    assert_eq!(
+        output[1],
+        "{\"sub\":\"1234567890\",\"name\":\"John Doe\",\"iat\":1516239022}"
+    );
+    assert_eq!(output[2], "error(Firma JWT inválida)");
+}
+
 #[test]
 fn test_env_listar() {
     let src = r#"imprimir(__env_listar());"#;
@@ -1756,17 +1787,74 @@ fn test_aes_encrypt_decrypt() {
 texto data = "mensaje secreto";
 texto ct = __aes_encriptar(key, data);
 texto pt = __aes_desencriptar(key, ct);
+imprimir(ct);
 imprimir(pt);
+imprimir(__aes_encriptar("corta", data));
 "#;
     let output = run_source(src).unwrap();
-    // On Linux/macOS, bcrypt.dll won't be available, so accept error message
-    if output[0] != "mensaje secreto" {
-        assert!(
-            output[0].contains("bcrypt") || output[0].contains("Bcrypt"),
-            "Expected AES roundtrip or bcrypt error, got: {:?}",
-            output
-        );
-    }
+    // AES-128-CBC con IV cero y PKCS#7, el mismo formato que BCrypt.
+    assert_eq!(output[0], "93747e4f300137758f2442579dab73e7");
+    assert_eq!(output[1], "mensaje secreto");
+    assert!(output[2].contains("16, 24 o 32 bytes"), "{:?}", output);
+}
+
+#[test]
+fn test_crypto_nv_vectores_conocidos() {
+    let src = format!(
+        r#"{}
+imprimir(sha256("abc"));
+imprimir(hmac_sha256("Jefe", "what do ya want for nothing?"));
+imprimir(pbkdf2("passwd", "salt", 1, 16));
+imprimir(hkdf("clave", "sal", "info", 16).largo());
+imprimir(aes_decrypt("clave16bytes!!!!", "93747e4f300137758f2442579dab73e7"));
+texto sellado = aes_gcm_encriptar("clave-de-32-bytes-para-aes-gcm!!", "hola gcm");
+imprimir(aes_gcm_desencriptar("clave-de-32-bytes-para-aes-gcm!!", sellado));
+imprimir(aes_gcm_desencriptar("clave-de-32-bytes-para-aes-gcm??", sellado));
+imprimir(bytes_aleatorios(16).largo());
+imprimir(bytes_aleatorios(16) == bytes_aleatorios(16));
+"#,
+        include_str!("../../../stdlib/crypto.nv")
+    );
+    let output = run_source(&src).unwrap();
+    assert_eq!(
+        output,
+        vec![
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            "55ac046e56e3089fec1691c22544b605",
+            "32",
+            "mensaje secreto",
+            "hola gcm",
+            "error(AES-GCM: autenticación fallida (datos alterados o clave incorrecta))",
+            "32",
+            "false",
+        ]
+    );
+}
+
+#[test]
+fn test_crypto_largos_fuera_de_rango_son_error() {
+    // Un largo enorme no debe llegar a reservar memoria.
+    let src = r#"
+imprimir(__bytes_aleatorios(9000000000000000000));
+imprimir(__pbkdf2("a", "b", 1, 9000000000000000000.0));
+imprimir(__hkdf("a", "b", "c", 9000000000000000000));
+imprimir(__pbkdf2("a", "b", 1, 0));
+imprimir(__pbkdf2("a", "b", 1, "x"));
+imprimir(__bytes_aleatorios(0));
+"#;
+    let output = run_source(src).unwrap();
+    assert_eq!(
+        output,
+        vec![
+            "error(bytes_aleatorios: el largo debe estar entre 0 y 1048576 bytes, se pidieron 9000000000000000000)",
+            "error(PBKDF2: el largo debe estar entre 1 y 8160 bytes, se pidieron 9000000000000000000)",
+            "error(HKDF: el largo debe estar entre 1 y 8160 bytes, se pidieron 9000000000000000000)",
+            "error(PBKDF2: el largo debe estar entre 1 y 8160 bytes, se pidieron 0)",
+            "error(PBKDF2: el largo debe ser un entero, no x)",
+            "",
+        ]
+    );
 }
 
 #[test]
@@ -4969,3 +5057,1289 @@ fn test_regression_lambda_fallthrough() {
     let output = run_source(src).unwrap();
     assert_eq!(output, vec!["10", "0"]);
 }
+
+#[test]
+fn test_posponer_lifo_al_salir_de_funcion() {
+    let src = r#"
+        funcion void f() {
+            posponer { imprimir("a"); }
+            posponer { imprimir("b"); }
+            imprimir("cuerpo");
+        }
+        f();
+        imprimir("fin");
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["cuerpo", "b", "a", "fin"]);
+}
+
+#[test]
+fn test_posponer_en_retornar_conserva_valor() {
+    let src = r#"
+        funcion numero f(numero x) {
+            numero r = x;
+            posponer { imprimir("limpieza"); r = 0; }
+            si (x > 0) {
+                posponer { imprimir("rama"); }
+                retornar r * 2;
+            }
+            retornar r;
+        }
+        imprimir(f(3));
+        imprimir(f(0));
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["rama", "limpieza", "6", "limpieza", "0"]);
+}
+
+#[test]
+fn test_posponer_por_bloque_y_bucle() {
+    let src = r#"
+        numero i = 0;
+        mientras (i < 4) {
+            posponer { imprimir("fin iter"); }
+            i = i + 1;
+            si (i == 2) { continuar; }
+            si (i == 3) { romper; }
+            imprimir(i);
+        }
+        imprimir("despues");
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(
+        output,
+        vec!["1", "fin iter", "fin iter", "fin iter", "despues"]
+    );
+}
+
+#[test]
+fn test_posponer_en_propagacion_de_error() {
+    let src = r#"
+        funcion resultado<numero, texto> h(numero x) {
+            si (x < 0) { retornar error("negativo"); }
+            retornar exito(x);
+        }
+        funcion resultado<numero, texto> g(numero x) {
+            posponer { imprimir("limpieza"); }
+            numero v = intentar h(x);
+            imprimir("ok");
+            retornar exito(v);
+        }
+        g(1);
+        g(-1);
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["ok", "limpieza", "limpieza"]);
+}
+
+#[test]
+fn test_posponer_nivel_superior_al_terminar() {
+    let src = r#"
+        posponer { imprimir("cierre"); }
+        imprimir("programa");
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["programa", "cierre"]);
+}
+
+#[test]
+fn test_intentar_atrapar_error_en_llamada_anidada() {
+    let src = r#"
+        funcion numero dividir(numero a, numero b) { retornar a / b; }
+        funcion numero calcular(numero x) { retornar dividir(x, 0) + 1; }
+        intentar {
+            imprimir(calcular(10));
+            imprimir("no llega");
+        } atrapar (e) {
+            imprimir("atrapado: " + e);
+        }
+        imprimir("sigue");
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["atrapado: Error: División por cero", "sigue"]);
+}
+
+#[test]
+fn test_intentar_atrapar_error_de_builtin() {
+    let src = r#"
+        lista<numero> xs = [1, 2];
+        intentar { imprimir(xs[5]); } atrapar (e) { imprimir("indice: " + e); }
+        intentar { imprimir(abs("x")); } atrapar (e) { imprimir("tipo"); }
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(
+        output,
+        vec!["indice: Índice 5 fuera de rango (largo: 2)", "tipo"]
+    );
+}
+
+#[test]
+fn test_intentar_atrapar_relanzar() {
+    let src = r#"
+        funcion numero profundo(numero n) {
+            si (n == 0) { lanzar("fondo"); }
+            retornar profundo(n - 1);
+        }
+        intentar {
+            intentar { profundo(3); } atrapar (e) { imprimir("interno: " + e); lanzar(e); }
+            imprimir("no llega");
+        } atrapar (e) {
+            imprimir("externo: " + e);
+        }
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["interno: fondo", "externo: fondo"]);
+}
+
+#[test]
+fn test_intentar_manejador_se_retira_al_salir() {
+    // Un `retornar`/`romper` dentro de `intentar` no deja el manejador instalado.
+    let src = r#"
+        funcion numero f() { intentar { retornar 1; } atrapar (e) { imprimir("f"); } retornar 0; }
+        f();
+        numero i = 0;
+        mientras (i < 3) {
+            i = i + 1;
+            intentar { si (i == 2) { romper; } } atrapar (e) { imprimir("bucle"); }
+        }
+        lanzar("sin manejador");
+    "#;
+    let err = run_source(src).unwrap_err();
+    assert!(err.contains("sin manejador"), "{}", err);
+}
+
+#[test]
+fn test_intentar_ejecuta_posponer_de_llamadas_abandonadas() {
+    let src = r#"
+        funcion void g() {
+            posponer { imprimir("cierre g"); }
+            lanzar("fallo");
+        }
+        funcion void f() {
+            posponer { imprimir("cierre f"); }
+            g();
+            imprimir("no llega");
+        }
+        intentar { f(); } atrapar (e) { imprimir("atrapado: " + e); }
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["cierre g", "cierre f", "atrapado: fallo"]);
+}
+
+#[test]
+fn test_intentar_ejecuta_posponer_del_cuerpo_antes_de_atrapar() {
+    let src = r#"
+        funcion void f() {
+            posponer { imprimir("fin f"); }
+            intentar {
+                posponer { imprimir("A"); }
+                posponer { imprimir("B"); }
+                numero x = 1 / 0;
+            } atrapar (e) {
+                imprimir("catch");
+            }
+            imprimir("despues");
+        }
+        f();
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["B", "A", "catch", "despues", "fin f"]);
+}
+
+#[test]
+fn test_intentar_ejecuta_posponer_en_bucle_de_nivel_superior() {
+    let src = r#"
+        numero i = 0;
+        mientras (i < 2) {
+            i = i + 1;
+            intentar {
+                posponer { imprimir("limpieza " + a_texto(i)); }
+                lanzar("error " + a_texto(i));
+            } atrapar (e) {
+                imprimir(e);
+            }
+        }
+        imprimir("fin");
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(
+        output,
+        vec!["limpieza 1", "error 1", "limpieza 2", "error 2", "fin"]
+    );
+}
+
+#[test]
+fn test_intentar_error_en_posponer_al_desenrollar() {
+    // Un `posponer` puede atrapar sus propios errores; si no, su error sigue
+    // hacia el mismo `atrapar` en lugar del original.
+    let src = r#"
+        funcion void g() {
+            posponer { imprimir("g1"); }
+            posponer {
+                intentar { lanzar("interno"); } atrapar (e) { imprimir("dentro: " + e); }
+            }
+            lanzar("externo");
+        }
+        intentar { g(); } atrapar (e) { imprimir("fuera: " + e); }
+        funcion void h() {
+            posponer { lanzar("del posponer"); }
+            lanzar("primero");
+        }
+        intentar { h(); } atrapar (e) { imprimir("h: " + e); }
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(
+        output,
+        vec!["dentro: interno", "g1", "fuera: externo", "h: del posponer"]
+    );
+}
+
+#[test]
+fn test_alcance_lexico_locales_no_se_filtran() {
+    // Cada llamada tiene sus propias variables: una local homónima en la
+    // función llamada no modifica la del llamador ni la global.
+    let src = r#"
+        funcion numero interna() { numero x = 99; retornar x; }
+        funcion numero externa() { numero x = 1; interna(); retornar x; }
+        imprimir(externa());
+        numero total = 0;
+        funcion numero sombra() { numero total = 7; retornar total; }
+        imprimir(sombra());
+        imprimir(total);
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["1", "7", "0"]);
+}
+
+#[test]
+fn test_alcance_lexico_bloque_tapa_sin_pisar() {
+    // Una declaración dentro de un bloque tapa a la de fuera solo hasta el
+    // final del bloque, en funciones y en el nivel superior.
+    let src = r#"
+        funcion entero f(entero x) {
+            entero y = 1;
+            entero z = 0;
+            si (verdadero) {
+                entero y = 2;
+                entero x = y * 10;
+                z = x;
+            }
+            para i en 0..2 {
+                entero y = i + 100;
+            }
+            retornar x + y + z;
+        }
+        imprimir(f(5));
+        entero x = 1;
+        si (verdadero) { entero x = 2; imprimir(x); }
+        imprimir(x);
+        entero suma = 0;
+        para i en 0..60 { suma = suma + f(1); }
+        imprimir(suma);
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["26", "2", "1", "1320"]);
+}
+
+#[test]
+fn test_alcance_lexico_globales_y_recursion() {
+    let src = r#"
+        numero total = 0;
+        funcion void sumar(numero n) { total = total + n; }
+        sumar(2); sumar(3);
+        imprimir(total);
+        funcion numero f(numero n) { numero acc = n; si (n > 0) { f(n - 1); } retornar acc; }
+        imprimir(f(3));
+    "#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["5", "3"]);
+}
+
+fn build_vm(source: &str) -> VM {
+    let (tokens, _) = Lexer::new(source).tokenize();
+    let (mut program, parse_errors) = Parser::new(tokens).parse();
+    assert!(parse_errors.is_empty(), "{:?}", parse_errors);
+    let sem_errors = SemanticAnalyzer::new().analyze(&mut program);
+    assert!(sem_errors.is_empty(), "{:?}", sem_errors);
+    let ir_program = IRBuilder::new().build(&program);
+    let (bytecode, _) = Codegen::new().generate(&ir_program);
+    VM::new(bytecode)
+}
+
+const DEPURAR: &str = "funcion entero doble(entero n) {
+    entero r = n * 2;
+    retornar r;
+}
+entero x = doble(3);
+imprimir(x);
+entero y = x / 0;
+";
+
+fn line(vm: &VM) -> u32 {
+    vm.current_position().map_or(0, |(l, _)| l)
+}
+
+#[test]
+fn test_error_con_posicion_de_fuente() {
+    let mut vm = build_vm(DEPURAR);
+    vm.set_source_name("prog.nv");
+    let err = vm.run().unwrap_err();
+    assert_eq!(vm.error_position(), Some((7, 1)));
+    let msg = vm.describe_error(err);
+    assert!(msg.contains("--> prog.nv:7:1"), "{}", msg);
+}
+
+#[test]
+fn test_pila_con_posiciones() {
+    let src = "funcion entero f(entero a) {\n    retornar 10 / a;\n}\nimprimir(f(0));\n";
+    let mut vm = build_vm(src);
+    let err = vm.run().unwrap_err();
+    let msg = vm.describe_error(err);
+    assert!(msg.contains("· f (2:5)"), "{}", msg);
+    assert!(msg.contains("(nivel superior) (4:1)"), "{}", msg);
+}
+
+#[test]
+fn test_breakpoint_detiene_run() {
+    let mut vm = build_vm(DEPURAR);
+    assert!(vm.set_line_breakpoint(2));
+    assert!(!vm.set_line_breakpoint(4));
+    vm.run().unwrap();
+    assert!(vm.is_paused());
+    assert_eq!(line(&vm), 2);
+    assert!(vm.output().is_empty());
+    // Al reanudar no se queda en el mismo breakpoint.
+    vm.clear_line_breakpoint(2);
+    assert!(vm.run().is_err());
+    assert_eq!(vm.output(), &["6"]);
+}
+
+#[test]
+fn test_posiciones_y_breakpoints_en_modulos_importados() {
+    use lumen_codegen::bytecode::Bytecode;
+    use lumen_sema::loader::{canonical_path, ModuleLoader};
+
+    let dir = std::env::temp_dir().join(format!("lumen_e2e_modulos_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let lib = dir.join("lib.nv");
+    std::fs::write(
+        &lib,
+        "funcion entero dividir(entero a, entero b) {\n    entero c = a + b;\n    imprimir(c);\n    retornar a / b;\n}\n",
+    )
+    .unwrap();
+    let main = dir.join("main.nv");
+    let src = "importar \"lib.nv\";\nimprimir(lib_dividir(4, 2));\nimprimir(lib_dividir(1, 0));\n";
+    std::fs::write(&main, src).unwrap();
+
+    let (mut program, origins) = ModuleLoader::new(vec![])
+        .resolve_imports_with_origins(src, &main)
+        .unwrap();
+    assert!(SemanticAnalyzer::new().analyze(&mut program).is_empty());
+    let mut builder = IRBuilder::new();
+    builder.set_origins(&canonical_path(&main), &origins);
+    let (bytecode, _) = Codegen::new().generate(&builder.build(&program));
+    // La tabla de archivos sobrevive al formato serializado.
+    let (bytecode, _) = Bytecode::decode(&bytecode.encode()).unwrap();
+    let mut vm = VM::new(bytecode);
+    vm.set_source_name("main.nv");
+
+    let lib_file = vm.source_file_index(&lib).unwrap();
+    assert_ne!(lib_file, 0);
+    // La línea 3 del principal no es la línea 3 del módulo.
+    assert!(vm.set_line_breakpoint(3));
+    assert!(!vm.set_line_breakpoint(4));
+    vm.run().unwrap();
+    assert!(vm.is_paused());
+    assert_eq!(vm.current_file(), Some(0));
+    assert_eq!(line(&vm), 3);
+    assert_eq!(vm.output(), &["6", "2"]);
+    vm.clear_line_breakpoint(3);
+
+    assert!(vm.set_file_line_breakpoint(lib_file, 3));
+    vm.run().unwrap();
+    assert_eq!(vm.current_file(), Some(lib_file));
+    assert_eq!(line(&vm), 3);
+    vm.clear_file_line_breakpoint(lib_file, 3);
+
+    let err = vm.run().unwrap_err();
+    let msg = vm.describe_error(err);
+    std::fs::remove_dir_all(&dir).ok();
+    assert!(msg.contains("--> lib.nv:4:5"), "{}", msg);
+    assert!(msg.contains("· lib_dividir (lib.nv:4:5)"), "{}", msg);
+    assert!(msg.contains("(nivel superior) (main.nv:3:1)"), "{}", msg);
+}
+
+#[test]
+fn test_pasos_por_fuente() {
+    let mut vm = build_vm(DEPURAR);
+    assert_eq!(line(&vm), 5);
+    vm.step_into().unwrap();
+    assert_eq!(line(&vm), 2);
+    vm.step_over().unwrap();
+    assert_eq!(line(&vm), 3);
+    vm.step_out().unwrap();
+    assert_eq!(line(&vm), 6);
+    vm.step_over().unwrap();
+    assert_eq!(line(&vm), 7);
+    assert_eq!(vm.output(), &["6"]);
+    // `step_back` deshace el paso entero.
+    vm.step_back().unwrap();
+    assert_eq!(line(&vm), 6);
+    assert!(vm.output().is_empty());
+}
+
+#[test]
+fn test_step_over_no_entra_en_llamadas() {
+    let mut vm = build_vm(DEPURAR);
+    vm.step_over().unwrap();
+    assert_eq!(line(&vm), 6);
+    assert_eq!(vm.call_stack().len(), 0);
+}
+
+// ── Closures léxicas ─────────────────────────────────────────────────
+
+#[test]
+fn test_closure_contador_retornado() {
+    let src = r#"funcion cualquiera contador() {
+    entero n = 0;
+    retornar funcion() { n = n + 1; retornar n; };
+}
+cualquiera a = contador();
+cualquiera b = contador();
+imprimir(a());
+imprimir(a());
+imprimir(b());"#;
+    assert_eq!(run_source(src).unwrap(), vec!["1", "2", "1"]);
+}
+
+#[test]
+fn test_closure_captura_parametro() {
+    let src = r#"funcion cualquiera sumador(entero k) {
+    retornar funcion(entero x) { retornar x + k; };
+}
+cualquiera mas5 = sumador(5);
+cualquiera mas10 = sumador(10);
+imprimir(mas5(10));
+imprimir(mas10(10));"#;
+    assert_eq!(run_source(src).unwrap(), vec!["15", "20"]);
+}
+
+#[test]
+fn test_closure_anidada_y_mutacion_compartida() {
+    let src = r#"funcion cualquiera anidada(entero base) {
+    retornar funcion(entero a) {
+        retornar funcion(entero b) { retornar base + a + b; };
+    };
+}
+imprimir(anidada(100)(20)(3));
+funcion entero compartida() {
+    entero total = 0;
+    cualquiera sumar = funcion(entero x) { total = total + x; };
+    sumar(5);
+    sumar(7);
+    total = total * 2;
+    sumar(1);
+    retornar total;
+}
+imprimir(compartida());"#;
+    assert_eq!(run_source(src).unwrap(), vec!["123", "25"]);
+}
+
+#[test]
+fn test_closure_enlace_nuevo_por_iteracion() {
+    let src = r#"funcion cualquiera crear() {
+    cualquiera fs = [];
+    para i en 0..3 {
+        entero j = i * 10;
+        fs = agregar(fs, funcion() { retornar j; });
+    }
+    retornar fs;
+}
+cualquiera fs = crear();
+imprimir(fs[0]());
+imprimir(fs[2]());"#;
+    assert_eq!(run_source(src).unwrap(), vec!["0", "20"]);
+
+    // En el nivel superior el cuerpo del bucle también es un bloque: sus
+    // declaraciones no son globales compartidas por todas las vueltas.
+    let src = r#"cualquiera fs = [];
+para i en 0..3 {
+    entero j = i * 10;
+    fs = agregar(fs, funcion() { retornar j; });
+}
+imprimir(fs[0]());
+imprimir(fs[2]());
+cualquiera gs = [];
+para k en 0..2 {
+    gs = agregar(gs, funcion() { retornar k; });
+}
+imprimir(gs[0]());"#;
+    assert_eq!(run_source(src).unwrap(), vec!["0", "20", "0"]);
+}
+
+#[test]
+fn test_closure_en_par_mapear_y_tareas() {
+    let src = r#"funcion cualquiera por(entero k) {
+    retornar funcion(entero x) { retornar x * k; };
+}
+cualquiera f = por(3);
+imprimir(__par_mapear([1, 2, 3], f));
+texto t = __tarea_lanzar(f, 7);
+imprimir(__tarea_esperar(t));"#;
+    assert_eq!(run_source(src).unwrap(), vec!["[3, 6, 9]", "21"]);
+}
+
+// ── Seleccionar y ámbitos de concurrencia estructurada ─────────────────
+
+#[test]
+fn test_seleccionar_defecto_y_plazo() {
+    let src = r#"texto a = __canal_nuevo();
+texto b = __canal_nuevo();
+cualquiera r = __seleccionar([a, b], 0);
+imprimir(r[0]);
+r = __seleccionar([a, b], 10);
+imprimir(r[0]);
+__canal_enviar(b, "hola");
+r = __seleccionar([a, b], 0);
+imprimir(r[0]);
+imprimir(r[1]);"#;
+    assert_eq!(run_source(src).unwrap(), vec!["-1", "-1", "1", "hola"]);
+}
+
+#[test]
+fn test_seleccionar_espera_a_una_tarea_que_envia() {
+    let src = r#"funcion entero productor(texto c, entero v) {
+    __dormir(20);
+    __canal_enviar(c, v);
+    retornar v;
+}
+texto a = __canal_nuevo();
+texto b = __canal_nuevo();
+__tarea_lanzar("productor", b, 7);
+cualquiera r = __seleccionar([a, b]);
+elegir (r[0]) {
+    caso 0: imprimir("a");
+    caso 1: imprimir(r[1]);
+    defecto: imprimir("plazo");
+}"#;
+    assert_eq!(run_source(src).unwrap(), vec!["7"]);
+}
+
+#[test]
+fn test_seleccionar_acepta_tareas() {
+    let src = r#"funcion entero lenta(entero x) { __dormir(200); retornar x; }
+funcion entero rapida(entero x) { retornar x * 2; }
+texto t1 = __tarea_lanzar("lenta", 1);
+texto t2 = __tarea_lanzar("rapida", 21);
+cualquiera r = __seleccionar([t1, t2]);
+imprimir(r[0]);
+imprimir(r[1]);"#;
+    assert_eq!(run_source(src).unwrap(), vec!["1", "42"]);
+}
+
+#[test]
+fn test_scope_esperar_une_todas_las_hijas() {
+    let src = r#"funcion entero doble(entero x) { retornar x * 2; }
+texto s = __scope_nuevo();
+__scope_lanzar(s, "doble", 1);
+__scope_lanzar(s, "doble", 2);
+__scope_lanzar(s, "doble", 3);
+imprimir(__scope_esperar(s));"#;
+    assert_eq!(run_source(src).unwrap(), vec!["[2, 4, 6]"]);
+}
+
+#[test]
+fn test_scope_primer_fallo_cancela_a_las_hermanas() {
+    // `eterna` nunca termina por sí sola: solo la cancelación la detiene.
+    let src = r#"funcion entero eterna(entero x) {
+    mientras verdadero { x = x + 1; }
+    retornar x;
+}
+funcion entero falla(entero x) {
+    __dormir(5);
+    retornar 1 / x;
+}
+texto s = __scope_nuevo();
+__scope_lanzar(s, "eterna", 0);
+__scope_lanzar(s, "falla", 0);
+imprimir(__scope_esperar(s));"#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output.len(), 1);
+    assert!(output[0].contains("División por cero"), "{:?}", output);
+}
+
+#[test]
+fn test_scope_cancelar_detiene_las_hijas() {
+    let src = r#"funcion entero eterna(entero x) {
+    mientras verdadero { x = x + 1; }
+    retornar x;
+}
+texto s = __scope_nuevo();
+__scope_lanzar(s, "eterna", 0);
+__scope_cancelar(s);
+imprimir(__scope_lanzar(s, "eterna", 0));
+cualquiera r = __scope_esperar(s);
+imprimir("fin");"#;
+    let output = run_source(src).unwrap();
+    assert_eq!(output, vec!["error(Ámbito cancelado)", "fin"]);
+}
+
+#[test]
+fn test_scope_ejecutar_espera_al_salir() {
+    let src = r#"funcion entero doble(entero x) { retornar x * 2; }
+entero base = 10;
+cualquiera r = __scope_ejecutar(funcion(texto ambito) {
+    __scope_lanzar(ambito, "doble", base);
+    __scope_lanzar(ambito, "doble", 4);
+});
+imprimir(r);
+imprimir(base);"#;
+    assert_eq!(run_source(src).unwrap(), vec!["[20, 8]", "10"]);
+}
+
+#[test]
+fn test_scope_ejecutar_propaga_el_fallo_del_cuerpo() {
+    let src = r#"funcion entero eterna(entero x) {
+    mientras verdadero { x = x + 1; }
+    retornar x;
+}
+cualquiera r = __scope_ejecutar(funcion(texto ambito) {
+    __scope_lanzar(ambito, "eterna", 0);
+    entero cero = 0;
+    retornar 1 / cero;
+});
+imprimir("no llega");"#;
+    let err = run_source(src).unwrap_err();
+    assert!(err.contains("DivisionByZero"), "{}", err);
+}
+
+#[test]
+fn test_tareas_ven_las_globales_del_modulo() {
+    let src = r#"entero base = 7;
+funcion entero suma(entero x) { retornar x + base; }
+imprimir(__tarea_esperar(__tarea_lanzar("suma", 1)));
+imprimir(__hilo_esperar(__hilo_lanzar("suma", 2)));
+imprimir(__par_mapear([1, 2], "suma"));"#;
+    assert_eq!(run_source(src).unwrap(), vec!["8", "9", "[8, 9]"]);
+}
+
+#[test]
+fn test_miles_de_tareas_en_el_planificador() {
+    let src = r#"funcion entero doble(entero x) { retornar x * 2; }
+lista<texto> tareas = [];
+para i en 0..5000 { tareas.agregar(__tarea_lanzar("doble", i)); }
+entero total = 0;
+para t en tareas { total = total + __tarea_esperar(t); }
+imprimir(total);"#;
+    assert_eq!(run_source(src).unwrap(), vec!["24995000"]);
+}
+
+#[test]
+fn test_fibras_esperando_un_canal_no_acaparan_los_hilos() {
+    // Hay muchas más receptoras que hilos en el grupo: si recibir bloqueara
+    // el hilo, las emisoras no llegarían a correr nunca.
+    let src = r#"funcion entero espera(texto c) { retornar __canal_recibir(c); }
+funcion entero envia(texto c, entero v) { __canal_enviar(c, v); retornar v; }
+texto c = __canal_nuevo();
+lista<texto> receptoras = [];
+para i en 0..300 { receptoras.agregar(__tarea_lanzar("espera", c)); }
+para i en 0..300 { __tarea_lanzar("envia", c, i); }
+entero total = 0;
+para t en receptoras { total = total + __tarea_esperar(t); }
+imprimir(total);"#;
+    assert_eq!(run_source(src).unwrap(), vec!["44850"]);
+}
+
+#[test]
+fn test_dormir_en_una_fibra_cede_el_hilo() {
+    let src = r#"funcion entero siesta(entero ms) { __dormir(ms); retornar ms; }
+lista<texto> dormidas = [];
+para i en 0..300 { dormidas.agregar(__tarea_lanzar("siesta", 100)); }
+entero total = 0;
+para t en dormidas { total = total + __tarea_esperar(t); }
+imprimir(total);"#;
+    let start = std::time::Instant::now();
+    assert_eq!(run_source(src).unwrap(), vec!["30000"]);
+    // Durmiendo en serie serían 30 s; aparcadas, todas duermen a la vez.
+    assert!(start.elapsed() < std::time::Duration::from_secs(10));
+}
+
+#[test]
+fn test_recibir_despierta_con_el_envio_sin_sondear() {
+    // Dos fibras se pasan un testigo 10000 veces. Si una receptora aparcada
+    // se reintentara cada medio milisegundo, cada salto costaría al menos eso.
+    let src = r#"funcion entero eco(texto ida, texto vuelta, entero n) {
+    para i en 0..n {
+        cualquiera v = __canal_recibir(ida);
+        __canal_enviar(vuelta, v + 1);
+    }
+    retornar n;
+}
+funcion entero saque(texto ida, texto vuelta, entero n) {
+    cualquiera testigo = 0;
+    para i en 0..n {
+        __canal_enviar(ida, testigo);
+        testigo = __canal_recibir(vuelta);
+    }
+    retornar testigo;
+}
+texto ida = __canal_nuevo();
+texto vuelta = __canal_nuevo();
+texto e = __tarea_lanzar("eco", ida, vuelta, 5000);
+imprimir(__tarea_esperar(__tarea_lanzar("saque", ida, vuelta, 5000)));
+imprimir(__tarea_esperar(e));"#;
+    let start = std::time::Instant::now();
+    assert_eq!(run_source(src).unwrap(), vec!["5000", "5000"]);
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+}
+
+#[test]
+fn test_tarea_espera_a_otra_tarea_sin_bloquear() {
+    let src = r#"funcion entero hoja(entero x) { __dormir(10); retornar x + 1; }
+funcion entero rama(entero x) {
+    texto t = __tarea_lanzar("hoja", x);
+    retornar __tarea_esperar(t) * 10;
+}
+lista<texto> ramas = [];
+para i en 0..100 { ramas.agregar(__tarea_lanzar("rama", i)); }
+entero total = 0;
+para t en ramas { total = total + __tarea_esperar(t); }
+imprimir(total);"#;
+    assert_eq!(run_source(src).unwrap(), vec!["50500"]);
+}
+
+// ── Supervisores y monitores de actores ──────────────────────────────────
+
+const TRABAJADOR: &str = r#"funcion entero trabajador(texto yo, texto salida) {
+    mientras verdadero {
+        cualquiera msg = __actor_recibir(yo);
+        si msg == "fallar" {
+            entero cero = 0;
+            retornar 1 / cero;
+        }
+        __canal_enviar(salida, msg);
+    }
+    retornar 0;
+}
+texto salida = __canal_nuevo();
+"#;
+
+#[test]
+fn test_supervisor_uno_para_uno_reinicia_solo_al_caido() {
+    let src = format!(
+        r#"{TRABAJADOR}
+texto sup = __supervisor_nuevo("uno_para_uno", 3, 5000);
+texto a = __supervisor_agregar(sup, "trabajador", salida);
+texto b = __supervisor_agregar(sup, "trabajador", salida);
+texto eventos = __actor_nuevo();
+__actor_monitorear(a, eventos);
+__actor_monitorear(b, eventos);
+lista<texto> hijos = __supervisor_iniciar(sup);
+imprimir(largo(hijos), " ", hijos[0] == a, " ", hijos[1] == b);
+__actor_enviar(a, "fallar");
+cualquiera ev = __actor_recibir(eventos);
+imprimir(ev[0], " ", ev[1] == a);
+ev = __actor_recibir(eventos);
+imprimir(ev[0], " ", ev[1] == a);
+__actor_enviar(b, "sigo");
+imprimir(__canal_recibir(salida));
+__actor_enviar(a, "vuelvo");
+imprimir(__canal_recibir(salida));
+imprimir(__supervisor_reinicios(sup), " ", __actor_vivo(b));
+imprimir(__actor_recibir(eventos, 50));
+__supervisor_detener(sup);
+imprimir(__supervisor_activo(sup), " ", __actor_vivo(a));"#
+    );
+    assert_eq!(
+        run_source(&src).unwrap(),
+        vec![
+            "2 true true",
+            "caido true",
+            "reiniciado true",
+            "sigo",
+            "vuelvo",
+            "1 true",
+            "void",
+            "false false"
+        ]
+    );
+}
+
+#[test]
+fn test_supervisor_resto_para_uno_reinicia_en_orden() {
+    let src = format!(
+        r#"{TRABAJADOR}
+texto sup = __supervisor_nuevo("resto_para_uno");
+texto a = __supervisor_agregar(sup, "trabajador", salida);
+texto b = __supervisor_agregar(sup, "trabajador", salida);
+cualquiera spec = __map_nuevo();
+spec = __map_poner(spec, "funcion", "trabajador");
+spec = __map_poner(spec, "args", [salida]);
+texto c = __supervisor_agregar(sup, spec);
+texto ev = __actor_nuevo();
+__actor_monitorear(a, ev);
+__actor_monitorear(b, ev);
+__actor_monitorear(c, ev);
+__supervisor_iniciar(sup);
+__actor_enviar(b, "fallar");
+para i en 0..4 {{
+    cualquiera e = __actor_recibir(ev);
+    texto quien = "c";
+    si e[1] == b {{ quien = "b"; }}
+    imprimir(e[0], " ", quien);
+}}
+imprimir(__actor_recibir(ev, 50));
+imprimir(__actor_vivo(a), " ", __supervisor_reinicios(sup));
+__supervisor_detener(sup);"#
+    );
+    assert_eq!(
+        run_source(&src).unwrap(),
+        vec![
+            "caido b",
+            "caido c",
+            "reiniciado b",
+            "reiniciado c",
+            "void",
+            "true 1"
+        ]
+    );
+}
+
+#[test]
+fn test_supervisor_se_rinde_al_exceder_la_intensidad() {
+    let src = format!(
+        r#"{TRABAJADOR}
+texto sup = __supervisor_nuevo("uno_para_todos", 1, 5000);
+texto x = __supervisor_agregar(sup, "trabajador", salida);
+texto y = __supervisor_agregar(sup, "trabajador", salida);
+texto ev = __actor_nuevo();
+__actor_monitorear(y, ev);
+__actor_monitorear(sup, ev);
+__supervisor_iniciar(sup);
+__actor_enviar(x, "fallar");
+imprimir(__actor_recibir(ev)[0]);
+imprimir(__actor_recibir(ev)[0]);
+__actor_enviar(y, "fallar");
+imprimir(__actor_recibir(ev)[0]);
+cualquiera fin = __actor_recibir(ev);
+imprimir(fin[0], " ", fin[1] == sup, " ", fin[2]);
+imprimir(__supervisor_activo(sup), " ", __actor_vivo(x));"#
+    );
+    assert_eq!(
+        run_source(&src).unwrap(),
+        vec![
+            "caido",
+            "reiniciado",
+            "caido",
+            "caido true Límite de reinicios excedido",
+            "false false"
+        ]
+    );
+}
+
+#[test]
+fn test_actores_enlazados_caen_juntos() {
+    let src = format!(
+        r#"{TRABAJADOR}
+texto p = __actor_nuevo("trabajador", salida);
+texto q = __actor_nuevo("trabajador", salida);
+texto ev = __actor_nuevo();
+__actor_monitorear(q, ev);
+__actor_enlazar(p, q);
+__actor_enviar(q, "antes");
+imprimir(__canal_recibir(salida));
+__actor_enviar(p, "fallar");
+cualquiera e = __actor_recibir(ev);
+imprimir(e[0], " ", e[1] == q);
+imprimir(__actor_vivo(p), " ", __actor_vivo(q));
+imprimir(__supervisor_nuevo("raro"));"#
+    );
+    assert_eq!(
+        run_source(&src).unwrap(),
+        vec![
+            "antes",
+            "caido true",
+            "false false",
+            "error(Estrategia de supervisión desconocida: 'raro')"
+        ]
+    );
+}
+
+// ── Cerrojos, atómicos, variables de condición y barreras ───────────────
+
+#[test]
+fn test_hilos_con_cerrojos_y_atomicos_no_pierden_actualizaciones() {
+    // `__dormir` entre leer y escribir la celda obliga a intercalar los
+    // hilos: sin el mutex se perderían casi todos los incrementos.
+    let src = r#"funcion entero suma_uno(entero v) { retornar v + 1; }
+funcion cualquiera trabajo(texto contador, texto rw, texto m, texto celda, texto b) {
+    para i en 0..500 { __atomico_sumar(contador, 2); }
+    para i en 0..25 {
+        __rwlock_escribir(rw, "suma_uno");
+        __mutex_adquirir(m);
+        cualquiera v = __arc_obtener(celda);
+        __dormir(1);
+        __arc_asignar(celda, v + 1);
+        __mutex_liberar(m);
+    }
+    retornar __barrera_esperar(b);
+}
+texto contador = __atomico_nuevo(0);
+texto rw = __rwlock_nuevo(0);
+texto m = __mutex_nuevo();
+texto celda = __arc_nuevo(0);
+texto b = __barrera_nueva(8);
+lista<texto> hilos = [];
+para i en 0..8 { hilos.agregar(__hilo_lanzar("trabajo", contador, rw, m, celda, b)); }
+entero lideres = 0;
+para h en hilos {
+    cualquiera lider = __hilo_esperar(h);
+    si lider == verdadero { lideres = lideres + 1; }
+}
+imprimir(__atomico_cargar(contador));
+imprimir(__rwlock_leer(rw));
+imprimir(__arc_obtener(celda));
+imprimir(lideres);"#;
+    assert_eq!(run_source(src).unwrap(), vec!["8000", "200", "200", "1"]);
+}
+
+#[test]
+fn test_condvar_productor_consumidor() {
+    let src = r#"funcion entero consumidor(texto m, texto cv, texto cola) {
+    entero vistos = 0;
+    mientras vistos < 50 {
+        __mutex_adquirir(m);
+        mientras __arc_obtener(cola) == 0 { __condvar_esperar(cv, m); }
+        __arc_asignar(cola, __arc_obtener(cola) - 1);
+        __mutex_liberar(m);
+        vistos = vistos + 1;
+    }
+    retornar vistos;
+}
+texto m = __mutex_nuevo();
+texto cv = __condvar_nuevo();
+texto cola = __arc_nuevo(0);
+lista<texto> consumidores = [];
+para i en 0..4 { consumidores.agregar(__tarea_lanzar("consumidor", m, cv, cola)); }
+para i en 0..200 {
+    __mutex_adquirir(m);
+    __arc_asignar(cola, __arc_obtener(cola) + 1);
+    __condvar_notificar(cv);
+    __mutex_liberar(m);
+}
+entero total = 0;
+para t en consumidores { total = total + __tarea_esperar(t); }
+imprimir(total, " ", __arc_obtener(cola));
+__mutex_adquirir(m);
+imprimir(__condvar_esperar(cv, m, 20));
+imprimir(__mutex_intentar(m));
+__mutex_liberar(m);
+imprimir(__mutex_intentar(m));"#;
+    assert_eq!(
+        run_source(src).unwrap(),
+        vec!["200 0", "false", "false", "true"]
+    );
+}
+
+#[test]
+fn test_rwlock_lecturas_y_operaciones_atomicas() {
+    let src = r#"funcion entero mas(entero v, entero n) { retornar v + n; }
+funcion entero lee(texto rw) { retornar __rwlock_leer(rw, "mas", 100); }
+texto rw = __rwlock_nuevo(1);
+imprimir(__rwlock_escribir(rw, "mas", 4));
+lista<texto> lectores = [];
+para i en 0..10 { lectores.agregar(__tarea_lanzar("lee", rw)); }
+entero total = 0;
+para t en lectores { total = total + __tarea_esperar(t); }
+imprimir(total, " ", __rwlock_leer(rw));
+texto a = __atomico_nuevo(7);
+imprimir(__atomico_sumar(a), " ", __atomico_cargar(a));
+imprimir(__atomico_comparar_intercambiar(a, 8, 1), " ", __atomico_comparar_intercambiar(a, 8, 2));
+imprimir(__atomico_intercambiar(a, 5), " ", __atomico_cargar(a));
+__atomico_guardar(a, -3);
+imprimir(__atomico_cargar(a));
+imprimir(__rwlock_leer("rwlock_99"));"#;
+    assert_eq!(
+        run_source(src).unwrap(),
+        vec![
+            "5",
+            "1050 5",
+            "7 8",
+            "true false",
+            "1 5",
+            "-3",
+            "error(Lock not found)"
+        ]
+    );
+}
+
+const SERVIDOR_HTTP: &str = r#"estructura PeticionHTTP {
+    metodo: texto,
+    ruta: texto,
+    cuerpo: texto,
+    ip_origen: texto,
+    consulta: cualquiera,
+    cabeceras: cualquiera,
+    version: texto
+}
+funcion cualquiera manejar(PeticionHTTP p) {
+    si p.ruta == "/lento" {
+        __dormir(300);
+    }
+    si p.ruta == "/falla" {
+        retornar error("roto");
+    }
+    cualquiera r = __map_nuevo();
+    r = __map_poner(r, "codigo", 201);
+    r = __map_poner(r, "cuerpo", p.metodo + " " + p.ruta + " [" + p.cuerpo + "]");
+    cualquiera extra = __map_nuevo();
+    si __map_contiene(p.consulta, "q") {
+        extra = __map_poner(extra, "X-Q", __map_obtener(p.consulta, "q"));
+    }
+    r = __map_poner(r, "cabeceras", extra);
+    retornar r;
+}
+"#;
+
+#[test]
+fn test_servidor_http_atiende_a_un_cliente_lumen() {
+    let src = format!(
+        r#"{}
+texto srv = __http_servidor("127.0.0.1:0", "manejar", 2);
+texto base = "http://" + __http_servidor_direccion(srv);
+imprimir(__http_get(base + "/hola%20mundo?q=1"));
+imprimir(__http_post(base + "/eco", "datos"));
+imprimir(__http_get(base + "/falla"));
+imprimir(__http_servidor_detener(srv), " ", __http_servidor_detener(srv));
+__http_servidor_esperar(srv);
+imprimir(__http_servidor("127.0.0.1:0", "no_existe"));"#,
+        SERVIDOR_HTTP
+    );
+    let out = run_source(&src).unwrap();
+    assert_eq!(out[0], "GET /hola mundo []");
+    assert_eq!(out[1], "POST /eco [datos]");
+    assert!(
+        out[2].starts_with("error(") && out[2].contains("500"),
+        "{}",
+        out[2]
+    );
+    assert_eq!(out[3], "true false");
+    assert!(out[4].starts_with("error("), "{}", out[4]);
+}
+
+#[test]
+fn test_servidor_http_keep_alive_chunked_y_concurrencia() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+
+    let out = run_source(&format!(
+        r#"{}
+texto srv = __http_servidor("127.0.0.1:0", "manejar", 4);
+imprimir(srv);
+imprimir(__http_servidor_direccion(srv));"#,
+        SERVIDOR_HTTP
+    ))
+    .unwrap();
+    let (id, addr) = (out[0].clone(), out[1].clone());
+
+    // Lee una respuesta con Content-Length: (código, cabeceras, cuerpo).
+    fn respuesta(r: &mut impl BufRead, head: bool) -> (String, Vec<String>, String) {
+        let mut linea = String::new();
+        r.read_line(&mut linea).unwrap();
+        let codigo = linea.split(' ').nth(1).unwrap_or("").to_string();
+        let mut cabeceras = Vec::new();
+        let mut largo = 0;
+        loop {
+            let mut l = String::new();
+            r.read_line(&mut l).unwrap();
+            let l = l.trim_end().to_string();
+            if l.is_empty() {
+                break;
+            }
+            if let Some(n) = l.strip_prefix("Content-Length: ") {
+                largo = n.parse().unwrap();
+            }
+            cabeceras.push(l);
+        }
+        let mut cuerpo = vec![0; if head { 0 } else { largo }];
+        r.read_exact(&mut cuerpo).unwrap();
+        (codigo, cabeceras, String::from_utf8(cuerpo).unwrap())
+    }
+
+    // Tres peticiones seguidas por la misma conexión, la segunda chunked.
+    let mut conn = TcpStream::connect(&addr).unwrap();
+    conn.write_all(
+        b"GET /a?q=uno+dos HTTP/1.1\r\nHost: x\r\n\r\n\
+          POST /b HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n\
+          HEAD /c HTTP/1.1\r\nConnection: close\r\n\r\n",
+    )
+    .unwrap();
+    let mut lector = BufReader::new(conn);
+    let (codigo, cabeceras, cuerpo) = respuesta(&mut lector, false);
+    assert_eq!((codigo.as_str(), cuerpo.as_str()), ("201", "GET /a []"));
+    assert!(cabeceras.contains(&"X-Q: uno dos".to_string()));
+    assert!(cabeceras.contains(&"Connection: keep-alive".to_string()));
+    assert_eq!(respuesta(&mut lector, false).2, "POST /b [abcde]");
+    let (_, cabeceras, cuerpo) = respuesta(&mut lector, true);
+    assert!(cuerpo.is_empty());
+    assert!(cabeceras.contains(&"Content-Length: 10".to_string()));
+    assert!(cabeceras.contains(&"Connection: close".to_string()));
+    let mut resto = Vec::new();
+    lector.read_to_end(&mut resto).unwrap();
+    assert!(resto.is_empty());
+
+    // Petición mal formada: 400 y se cierra.
+    let mut conn = TcpStream::connect(&addr).unwrap();
+    conn.write_all(b"BASURA\r\n\r\n").unwrap();
+    assert_eq!(respuesta(&mut BufReader::new(conn), false).0, "400");
+
+    // Cuatro peticiones lentas a la vez se atienden en paralelo.
+    let inicio = std::time::Instant::now();
+    let clientes: Vec<_> = (0..4)
+        .map(|_| {
+            let addr = addr.clone();
+            std::thread::spawn(move || {
+                let mut conn = TcpStream::connect(&addr).unwrap();
+                conn.write_all(b"GET /lento HTTP/1.0\r\n\r\n").unwrap();
+                respuesta(&mut BufReader::new(conn), false).2
+            })
+        })
+        .collect();
+    for c in clientes {
+        assert_eq!(c.join().unwrap(), "GET /lento []");
+    }
+    assert!(inicio.elapsed() < std::time::Duration::from_millis(1000));
+
+    let out = run_source(&format!("imprimir(__http_servidor_detener(\"{}\"));", id)).unwrap();
+    assert_eq!(out, vec!["true"]);
+}
+
+/// Puerto libre en localhost para un nodo que otro programa debe encontrar.
+fn puerto_libre() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Espera a que algo escuche en `puerto`.
+fn esperar_puerto(puerto: u16) {
+    for _ in 0..200 {
+        if std::net::TcpStream::connect(("127.0.0.1", puerto)).is_ok() {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    panic!("nadie escucha en {}", puerto);
+}
+
+#[test]
+fn test_cluster_dos_vms_intercambian_valores() {
+    let puerto = puerto_libre();
+    let b = format!(
+        r#"texto nodo = __cluster_nodo("nodo_b", "127.0.0.1:{}", 100);
+texto entrada = __actor_nuevo();
+imprimir(__cluster_registrar(nodo, "eco", entrada), " ", __cluster_registrar(nodo, "eco", entrada));
+texto eventos = __actor_nuevo();
+__cluster_monitorear(nodo, eventos);
+imprimir(__actor_recibir(eventos, 5000));
+para i en 0..3 {{
+    cualquiera msg = __actor_recibir(entrada, 5000);
+    __cluster_enviar(nodo, "respuestas@nodo_a", msg);
+}}
+imprimir(__actor_recibir(eventos, 5000));
+imprimir(__cluster_detener(nodo));"#,
+        puerto
+    );
+    let nodo_b = std::thread::spawn(move || run_source(&b).unwrap());
+    esperar_puerto(puerto);
+
+    let a = format!(
+        r#"estructura Punto {{ x: entero, y: texto }}
+enum Forma {{ Circulo(decimal), Vacia }}
+texto nodo = __cluster_nodo("nodo_a");
+texto respuestas = __actor_nuevo();
+__cluster_registrar(nodo, "respuestas", respuestas);
+imprimir(__cluster_conectar(nodo, "127.0.0.1:{}"));
+imprimir(__cluster_nodos(nodo));
+cualquiera m = __map_nuevo();
+m = __map_poner(m, "clave", [1, 2.5, verdadero]);
+__cluster_enviar(nodo, "eco@nodo_b", Punto {{ x: 3, y: "ñ" }});
+__cluster_enviar(nodo, "eco@nodo_b", m);
+__cluster_enviar(nodo, "eco@nodo_b", Forma::Circulo(1.5));
+imprimir(__actor_recibir(respuestas, 5000));
+cualquiera m2 = __actor_recibir(respuestas, 5000);
+imprimir(__map_obtener(m2, "clave"));
+imprimir(__actor_recibir(respuestas, 5000));
+imprimir(__cluster_enviar(nodo, "eco@nadie", 1));
+entero k = 1;
+imprimir(__cluster_enviar(nodo, "eco@nodo_b", funcion(entero x) {{ retornar x + k; }}));
+imprimir(__cluster_detener(nodo), " ", __cluster_detener(nodo));"#,
+        puerto
+    );
+    assert_eq!(
+        run_source(&a).unwrap(),
+        vec![
+            "nodo_b",
+            "[nodo_b]",
+            "{ x: 3, y: ñ }",
+            "[1, 2.5, true]",
+            "Forma::Circulo(1.5)",
+            "false",
+            "error(No se puede enviar la clausura '__lambda_0' a otro nodo)",
+            "true false",
+        ]
+    );
+    assert_eq!(
+        nodo_b.join().unwrap(),
+        vec![
+            "true false",
+            "[nodo_conectado, nodo_a]",
+            "[nodo_caido, nodo_a, desconectado]",
+            "true",
+        ]
+    );
+}
+
+#[test]
+fn test_cluster_detecta_un_nodo_sin_latido() {
+    use std::io::{Read, Write};
+
+    let puerto = puerto_libre();
+    let src = format!(
+        r#"texto nodo = __cluster_nodo("nodo_vigia", "127.0.0.1:{}", 50);
+texto eventos = __actor_nuevo();
+__cluster_monitorear(nodo, eventos);
+imprimir(__actor_recibir(eventos, 5000));
+imprimir(__actor_recibir(eventos, 5000));
+imprimir(__cluster_nodos(nodo));
+__cluster_detener(nodo);"#,
+        puerto
+    );
+    let vigia = std::thread::spawn(move || run_source(&src).unwrap());
+    esperar_puerto(puerto);
+
+    // Un nodo escrito a mano: saluda y luego se calla.
+    let mut conn = std::net::TcpStream::connect(("127.0.0.1", puerto)).unwrap();
+    let nombre = b"fantasma";
+    let mut trama = ((1 + 4 + nombre.len()) as u32).to_be_bytes().to_vec();
+    trama.push(0);
+    trama.extend_from_slice(&(nombre.len() as u32).to_be_bytes());
+    trama.extend_from_slice(nombre);
+    conn.write_all(&trama).unwrap();
+    let mut largo = [0; 4];
+    conn.read_exact(&mut largo).unwrap();
+    let mut saludo = vec![0; u32::from_be_bytes(largo) as usize];
+    conn.read_exact(&mut saludo).unwrap();
+    assert_eq!(&saludo[5..], b"nodo_vigia");
+
+    assert_eq!(
+        vigia.join().unwrap(),
+        vec![
+            "[nodo_conectado, fantasma]",
+            "[nodo_caido, fantasma, sin latido]",
+            "[]",
+        ]
+    );
+}
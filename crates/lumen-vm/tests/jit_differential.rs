@@ -0,0 +1,118 @@
+//! Prueba diferencial: los programas de `lumen-bench/workloads` (y variantes que
+//! imprimen sus resultados) deben producir exactamente la misma salida con el
+//! intérprete puro y con el JIT activado desde la primera llamada.
+#![cfg(feature = "aot")]
+
+use lumen_codegen::Codegen;
+use lumen_ir::IRBuilder;
+use lumen_lexer::Lexer;
+use lumen_parser::Parser;
+use lumen_sema::SemanticAnalyzer;
+use lumen_vm::VM;
+
+const FIB: &str = include_str!("../../lumen-bench/workloads/fib.nv");
+const FALLTHROUGH: &str = include_str!("../../lumen-bench/workloads/fallthrough.nv");
+const DEFAULTS: &str = include_str!("../../lumen-bench/workloads/defaults.nv");
+const POTENCIA: &str = include_str!("../../lumen-bench/workloads/potencia.nv");
+const HEADLESS: &str = include_str!("../../lumen-bench/workloads/headless.nv");
+
+/// Variantes de las cargas anteriores que imprimen cada resultado.
+const VARIANTES: &str = r#"
+    funcion entero fib(entero n) { si n <= 1 { retornar n; } retornar fib(n-1) + fib(n-2); }
+    funcion entero elevar(entero base, entero exp){
+        si(exp==0){retornar 1;} entero res=1; entero i=0;
+        mientras(i<exp){res=res*base;i=i+1;} retornar res;
+    }
+    funcion decimal escalar(decimal x, entero veces){
+        decimal res=x; entero i=0;
+        mientras(i<veces){res=res*1.5;i=i+1;} retornar res;
+    }
+    funcion booleano es_headless(){ si 1==1 { retornar falso; } retornar verdadero; }
+    entero k = 0;
+    mientras (k < 12) {
+        imprimir(fib(k)); imprimir(elevar(k, 5)); imprimir(escalar(k / 2.0, k));
+        imprimir(es_headless()); imprimir(elevar(-k, 3) % 7); imprimir(k / 3 - k % 4);
+        k = k + 1;
+    }
+"#;
+
+fn run(source: &str, jit_threshold: usize) -> (Result<(), String>, Vec<String>, Vec<String>) {
+    let (tokens, lex_errors) = Lexer::new(source).tokenize();
+    assert!(lex_errors.is_empty());
+    let (mut program, parse_errors) = Parser::new(tokens).parse();
+    assert!(parse_errors.is_empty());
+    SemanticAnalyzer::new().analyze(&mut program);
+    let ir_program = IRBuilder::new().build(&program);
+    let (bytecode, _) = Codegen::new().generate(&ir_program);
+
+    let mut vm = VM::new(bytecode);
+    vm.jit_threshold = jit_threshold;
+    let result = vm.run().map_err(|e| e.to_string());
+    (result, vm.output().to_vec(), vm.jitted_functions())
+}
+
+/// Ejecuta en ambos modos, compara y devuelve las funciones compiladas.
+fn differential(source: &str) -> Vec<String> {
+    let (interp_result, interp_out, interp_jit) = run(source, usize::MAX);
+    let (jit_result, jit_out, jitted) = run(source, 1);
+    assert!(interp_jit.is_empty());
+    assert_eq!(interp_result, jit_result);
+    assert_eq!(interp_out, jit_out);
+    jitted
+}
+
+#[test]
+fn test_jit_diferencial_cargas_de_bench() {
+    assert_eq!(differential(FIB), vec!["fib"]);
+    differential(FALLTHROUGH);
+    differential(DEFAULTS);
+    // `potencia` coincide con el builtin homónimo, que tiene prioridad: la
+    // función de usuario nunca se ejecuta y el JIT no debe enlazarla.
+    assert!(differential(POTENCIA).is_empty());
+    assert_eq!(differential(HEADLESS), vec!["es_headless"]);
+}
+
+#[test]
+fn test_jit_diferencial_resultados_impresos() {
+    let jitted = differential(VARIANTES);
+    for name in ["elevar", "escalar", "es_headless", "fib"] {
+        assert!(jitted.iter().any(|f| f == name), "{} no se compiló", name);
+    }
+}
+
+#[test]
+fn test_jit_diferencial_errores_y_recursion_mutua() {
+    let source = r#"
+        funcion entero dividir(entero a, entero b) { retornar a / b; }
+        funcion entero desplazar(entero a, entero b) { retornar a << b; }
+        funcion entero profundo(entero n) { si n == 0 { retornar 0; } retornar 1 + profundo(n - 1); }
+        funcion booleano par(entero n) { si n == 0 { retornar verdadero; } retornar impar(n - 1); }
+        funcion booleano impar(entero n) { si n == 0 { retornar falso; } retornar par(n - 1); }
+        imprimir(par(77)); imprimir(impar(40)); imprimir(dividir(-7, 2));
+        intentar { dividir(1, 0); } atrapar (e) { imprimir(e); }
+        intentar { desplazar(1, 64); } atrapar (e) { imprimir(e); }
+        intentar { profundo(20000); } atrapar (e) { imprimir(e); }
+        imprimir(profundo(500)); imprimir(par(10));
+    "#;
+    let jitted = differential(source);
+    assert_eq!(
+        jitted,
+        vec!["desplazar", "dividir", "impar", "par", "profundo"]
+    );
+}
+
+#[test]
+fn test_jit_locales_por_frame() {
+    // `ajustar` declara su propio `total` sin tocar el del llamador; `contar`
+    // asigna una global, así que sigue en el intérprete.
+    let source = r#"
+        entero veces = 0;
+        funcion entero ajustar(entero n) { entero total = n * 2; retornar total; }
+        funcion entero usar(entero n) { entero total = 100; retornar ajustar(n) + total; }
+        funcion void contar() { veces = veces + 1; }
+        entero i = 0;
+        mientras (i < 5) { imprimir(usar(i)); contar(); i = i + 1; }
+        imprimir(veces);
+    "#;
+    assert_eq!(differential(source), vec!["ajustar", "usar"]);
+}
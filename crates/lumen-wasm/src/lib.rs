@@ -347,8 +347,11 @@ fn module_error_str(e: &lumen_sema::ModuleError) -> String {
     use lumen_sema::ModuleError;
     match e {
         ModuleError::Io { path, message } => format!("{}: {}", path.display(), message),
-        ModuleError::Lex { details, .. } => details.join("\n"),
-        ModuleError::Parse { details, .. } => details.join("\n"),
+        ModuleError::Lex { details, .. } | ModuleError::Parse { details, .. } => details
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n"),
         ModuleError::Circular { path, span } => format!(
             "Import circular en {}:{}:{}",
             path.display(),